@@ -0,0 +1,152 @@
+/*
+ * Compile-time checks that the generated chd.h is ABI-compatible with libchdr.
+ *
+ * The libchdr declarations below are transcribed from chd.h of libchdr at
+ * https://github.com/rtissera/libchdr/blob/cdcb714235b9ff7d207b703260706a364282b063/include/libchdr/chd.h
+ * with their names prefixed with `libchdr_` so that they can be compared against the
+ * declarations of chd-rs. This file only needs to compile; it is compiled by build.rs
+ * every time chd.h is regenerated, and any mismatch fails the build.
+ */
+#include <stddef.h>
+#include <stdint.h>
+
+#include "chd.h"
+
+/* C89-compatible static assertion, so that this compiles with any C compiler. */
+#define ABI_ASSERT(name, cond) typedef char abi_assert_##name[(cond) ? 1 : -1]
+
+enum libchdr_chd_error
+{
+	libchdr_CHDERR_NONE,
+	libchdr_CHDERR_NO_INTERFACE,
+	libchdr_CHDERR_OUT_OF_MEMORY,
+	libchdr_CHDERR_INVALID_FILE,
+	libchdr_CHDERR_INVALID_PARAMETER,
+	libchdr_CHDERR_INVALID_DATA,
+	libchdr_CHDERR_FILE_NOT_FOUND,
+	libchdr_CHDERR_REQUIRES_PARENT,
+	libchdr_CHDERR_FILE_NOT_WRITEABLE,
+	libchdr_CHDERR_READ_ERROR,
+	libchdr_CHDERR_WRITE_ERROR,
+	libchdr_CHDERR_CODEC_ERROR,
+	libchdr_CHDERR_INVALID_PARENT,
+	libchdr_CHDERR_HUNK_OUT_OF_RANGE,
+	libchdr_CHDERR_DECOMPRESSION_ERROR,
+	libchdr_CHDERR_COMPRESSION_ERROR,
+	libchdr_CHDERR_CANT_CREATE_FILE,
+	libchdr_CHDERR_CANT_VERIFY,
+	libchdr_CHDERR_NOT_SUPPORTED,
+	libchdr_CHDERR_METADATA_NOT_FOUND,
+	libchdr_CHDERR_INVALID_METADATA_SIZE,
+	libchdr_CHDERR_UNSUPPORTED_VERSION,
+	libchdr_CHDERR_VERIFY_INCOMPLETE,
+	libchdr_CHDERR_INVALID_METADATA,
+	libchdr_CHDERR_INVALID_STATE,
+	libchdr_CHDERR_OPERATION_PENDING,
+	libchdr_CHDERR_NO_ASYNC_OPERATION,
+	libchdr_CHDERR_UNSUPPORTED_FORMAT
+};
+
+struct libchdr_chd_header
+{
+	uint32_t length;
+	uint32_t version;
+	uint32_t flags;
+	uint32_t compression[4];
+	uint32_t hunkbytes;
+	uint32_t totalhunks;
+	uint64_t logicalbytes;
+	uint64_t metaoffset;
+	uint64_t mapoffset;
+	uint8_t md5[16];
+	uint8_t parentmd5[16];
+	uint8_t sha1[20];
+	uint8_t rawsha1[20];
+	uint8_t parentsha1[20];
+	uint32_t unitbytes;
+	uint64_t unitcount;
+	uint32_t hunkcount;
+	uint32_t mapentrybytes;
+	uint8_t *rawmap;
+	uint32_t obsolete_cylinders;
+	uint32_t obsolete_sectors;
+	uint32_t obsolete_heads;
+	uint32_t obsolete_hunksize;
+};
+
+ABI_ASSERT(md5_bytes, CHD_MD5_BYTES == 16);
+ABI_ASSERT(sha1_bytes, CHD_SHA1_BYTES == 20);
+ABI_ASSERT(mdflags_checksum, CHD_MDFLAGS_CHECKSUM == 0x01);
+
+/* chd_error */
+#define CHECK_ERROR(name) ABI_ASSERT(error_##name, (int)CHDERR_##name == (int)libchdr_CHDERR_##name)
+
+ABI_ASSERT(error_size, sizeof(chd_error) == sizeof(enum libchdr_chd_error));
+CHECK_ERROR(NONE);
+CHECK_ERROR(NO_INTERFACE);
+CHECK_ERROR(OUT_OF_MEMORY);
+CHECK_ERROR(INVALID_FILE);
+CHECK_ERROR(INVALID_PARAMETER);
+CHECK_ERROR(INVALID_DATA);
+CHECK_ERROR(FILE_NOT_FOUND);
+CHECK_ERROR(REQUIRES_PARENT);
+CHECK_ERROR(FILE_NOT_WRITEABLE);
+CHECK_ERROR(READ_ERROR);
+CHECK_ERROR(WRITE_ERROR);
+CHECK_ERROR(CODEC_ERROR);
+CHECK_ERROR(INVALID_PARENT);
+CHECK_ERROR(HUNK_OUT_OF_RANGE);
+CHECK_ERROR(DECOMPRESSION_ERROR);
+CHECK_ERROR(COMPRESSION_ERROR);
+CHECK_ERROR(CANT_CREATE_FILE);
+CHECK_ERROR(CANT_VERIFY);
+CHECK_ERROR(NOT_SUPPORTED);
+CHECK_ERROR(METADATA_NOT_FOUND);
+CHECK_ERROR(INVALID_METADATA_SIZE);
+CHECK_ERROR(UNSUPPORTED_VERSION);
+CHECK_ERROR(VERIFY_INCOMPLETE);
+CHECK_ERROR(INVALID_METADATA);
+CHECK_ERROR(INVALID_STATE);
+CHECK_ERROR(OPERATION_PENDING);
+CHECK_ERROR(NO_ASYNC_OPERATION);
+CHECK_ERROR(UNSUPPORTED_FORMAT);
+
+/* errors specific to chd-rs must come after every libchdr error. */
+ABI_ASSERT(error_unknown, (int)CHDERR_UNKNOWN > (int)libchdr_CHDERR_UNSUPPORTED_FORMAT);
+ABI_ASSERT(error_truncated, (int)CHDERR_TRUNCATED > (int)libchdr_CHDERR_UNSUPPORTED_FORMAT);
+ABI_ASSERT(error_invalid_map, (int)CHDERR_INVALID_MAP > (int)libchdr_CHDERR_UNSUPPORTED_FORMAT);
+ABI_ASSERT(error_hunk_count_mismatch,
+	(int)CHDERR_HUNK_COUNT_MISMATCH > (int)libchdr_CHDERR_UNSUPPORTED_FORMAT);
+/* unsupported codecs are reported as CHDERR_UNSUPPORTED_FORMAT, which is checked above. */
+
+/* chd_header */
+#define CHECK_FIELD(field)                                                               \
+	ABI_ASSERT(header_##field##_offset,                                                  \
+		offsetof(chd_header, field) == offsetof(struct libchdr_chd_header, field));      \
+	ABI_ASSERT(header_##field##_size,                                                    \
+		sizeof(((chd_header *)0)->field) == sizeof(((struct libchdr_chd_header *)0)->field))
+
+ABI_ASSERT(header_size, sizeof(chd_header) == sizeof(struct libchdr_chd_header));
+CHECK_FIELD(length);
+CHECK_FIELD(version);
+CHECK_FIELD(flags);
+CHECK_FIELD(compression);
+CHECK_FIELD(hunkbytes);
+CHECK_FIELD(totalhunks);
+CHECK_FIELD(logicalbytes);
+CHECK_FIELD(metaoffset);
+CHECK_FIELD(mapoffset);
+CHECK_FIELD(md5);
+CHECK_FIELD(parentmd5);
+CHECK_FIELD(sha1);
+CHECK_FIELD(rawsha1);
+CHECK_FIELD(parentsha1);
+CHECK_FIELD(unitbytes);
+CHECK_FIELD(unitcount);
+CHECK_FIELD(hunkcount);
+CHECK_FIELD(mapentrybytes);
+CHECK_FIELD(rawmap);
+CHECK_FIELD(obsolete_cylinders);
+CHECK_FIELD(obsolete_sectors);
+CHECK_FIELD(obsolete_heads);
+CHECK_FIELD(obsolete_hunksize);
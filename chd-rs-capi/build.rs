@@ -49,6 +49,16 @@ fn build_chdcorefile() {
         .expect("Unable to write bindings to libchdcorefile.");
 }
 
+/// Checks that the generated chd.h is ABI-compatible with libchdr at compile time.
+fn check_abi() {
+    println!("cargo:rerun-if-changed=abi/abi_check.c");
+    cc::Build::new()
+        .file("abi/abi_check.c")
+        .include(".")
+        .cargo_metadata(false)
+        .compile_intermediates();
+}
+
 fn main() {
     // Do not update files on docsrs
     if env::var("DOCS_RS").is_ok() {
@@ -72,5 +82,7 @@ fn main() {
     File::create("chd.h")
         .expect("Unable to open file")
         .write_all(string.as_bytes())
-        .expect("Unable to write bindings.")
+        .expect("Unable to write bindings.");
+
+    check_abi();
 }
@@ -15,11 +15,47 @@
 #define CHD_OPEN_READ 1
 
 /**
- * Open a CHD for reading and writing. This mode is not supported and will always return an error
- * when passed into a constructor function such as [`chd_open`](crate::chd_open).
+ * Open a CHD for reading and writing. This mode is not supported, since chd-rs only reads CHD files,
+ * and constructor functions such as [`chd_open`](crate::chd_open) will always return
+ * `CHDERR_FILE_NOT_WRITEABLE` when it is passed in.
  */
 #define CHD_OPEN_READWRITE 2
 
+/**
+ * The metadata entry is included in the SHA1 hash of the CHD file.
+ */
+#define CHD_MDFLAGS_CHECKSUM 1
+
+/**
+ * Zstandard compressed CHD files can be read.
+ */
+#define CHD_CAPABILITY_ZSTD (1 << 0)
+
+/**
+ * The `chd_precache` and `chd_precache_progress` functions are available.
+ */
+#define CHD_CAPABILITY_PRECACHE (1 << 1)
+
+/**
+ * The `chd_threadsafe_file*` handle and its functions are available.
+ */
+#define CHD_CAPABILITY_THREADSAFE (1 << 2)
+
+/**
+ * The `chd_open_file` and `chd_core_file` functions are available.
+ */
+#define CHD_CAPABILITY_CORE_FILE (1 << 3)
+
+/**
+ * The `chd_open_core_file` function is available.
+ */
+#define CHD_CAPABILITY_VIRTIO (1 << 4)
+
+/**
+ * Decompressed hunks are verified with their internal hash.
+ */
+#define CHD_CAPABILITY_VERIFY_BLOCK_CRC (1 << 5)
+
 /**
  * The chunk size to read when pre-caching the underlying file stream into memory.
  */
@@ -29,11 +65,186 @@
 
 #define CHD_SHA1_BYTES 20
 
+/**
+ * Error codes returned by the C API.
+ *
+ * This type is ABI-compatible with [libchdr](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L258),
+ * given sane defaults in the C compiler. See [repr(C) in the Rustonomicon](https://doc.rust-lang.org/nomicon/other-reprs.html#reprc) for more details.
+ * The discriminant of each error is its [`Error::code`](chd::Error::code).
+ */
+typedef enum chd_error {
+  /**
+   * No error.
+   * This is only used by the C API bindings.
+   */
+  CHDERR_NONE,
+  /**
+   * No drive interface.
+   * This is only for C-compatibility purposes and is otherwise unused.
+   */
+  CHDERR_NO_INTERFACE,
+  /**
+   * Unable to allocate the required size of buffer.
+   */
+  CHDERR_OUT_OF_MEMORY,
+  /**
+   * The file is not a valid CHD file.
+   */
+  CHDERR_INVALID_FILE,
+  /**
+   * An invalid parameter was provided.
+   */
+  CHDERR_INVALID_PARAMETER,
+  /**
+   * The data is invalid.
+   */
+  CHDERR_INVALID_DATA,
+  /**
+   * The file was not found.
+   */
+  CHDERR_FILE_NOT_FOUND,
+  /**
+   * This CHD requires a parent CHD that was not provided.
+   */
+  CHDERR_REQUIRES_PARENT,
+  /**
+   * The provided file is not writable.
+   * chd-rs only opens CHD files for reading, so this is returned when a CHD file is opened for writing.
+   */
+  CHDERR_FILE_NOT_WRITEABLE,
+  /**
+   * An error occurred when reading this CHD file.
+   */
+  CHDERR_READ_ERROR,
+  /**
+   * An error occurred when writing this CHD file.
+   * Since chd-rs does not implement CHD creation, this is unused.
+   */
+  CHDERR_WRITE_ERROR,
+  /**
+   * An error occurred when initializing a codec.
+   */
+  CHDERR_CODEC_ERROR,
+  /**
+   * The provided parent CHD is invalid.
+   */
+  CHDERR_INVALID_PARENT,
+  /**
+   * The request hunk is out of range for this CHD file.
+   */
+  CHDERR_HUNK_OUT_OF_RANGE,
+  /**
+   * An error occurred when decompressing a hunk.
+   */
+  CHDERR_DECOMPRESSION_ERROR,
+  /**
+   * An error occurred when compressing a hunk.
+   * Since chd-rs does not implement CHD creation, this is unused.
+   */
+  CHDERR_COMPRESSION_ERROR,
+  /**
+   * Could not create the file.
+   * Since chd-rs does not implement CHD creation, this is unused.
+   */
+  CHDERR_CANT_CREATE_FILE,
+  /**
+   * Could not verify the CHD.
+   * This is only for C-compatibility purposes and is otherwise unused.
+   */
+  CHDERR_CANT_VERIFY,
+  /**
+   * The requested operation is not supported.
+   * This is only for C-compatibility purposes and is otherwise unused.
+   */
+  CHDERR_NOT_SUPPORTED,
+  /**
+   * The requested metadata was not found.
+   */
+  CHDERR_METADATA_NOT_FOUND,
+  /**
+   * The metadata has an invalid size.
+   *
+   * The contents of a metadata entry may extend past the end of the file, or be too long
+   * for the 24-bit length field of a metadata entry.
+   */
+  CHDERR_INVALID_METADATA_SIZE,
+  /**
+   * The CHD version of the provided file is not supported by this library.
+   */
+  CHDERR_UNSUPPORTED_VERSION,
+  /**
+   * Unable to verify the CHD completely.
+   * This is only for C-compatibility purposes and is otherwise unused.
+   */
+  CHDERR_VERIFY_INCOMPLETE,
+  /**
+   * The requested metadata is invalid.
+   */
+  CHDERR_INVALID_METADATA,
+  /**
+   * The internal state of the decoder/encoder is invalid.
+   * This is only for C-compatibility purposes and is otherwise unused.
+   */
+  CHDERR_INVALID_STATE,
+  /**
+   * An operation is already pending.
+   * This is only for C-compatibility purposes and is otherwise unused.
+   */
+  CHDERR_OPERATION_PENDING,
+  /**
+   * No async operations are allowed.
+   * This is only for C-compatibility purposes and is otherwise unused.
+   */
+  CHDERR_NO_ASYNC_OPERATION,
+  /**
+   * Decompressing the CHD requires a codec that is not supported.
+   *
+   * This is also returned for [`Error::UnsupportedCodec`](chd::Error::UnsupportedCodec), since libchdr
+   * has no error that carries the unsupported codec.
+   */
+  CHDERR_UNSUPPORTED_FORMAT,
+  /**
+   * Unknown error.
+   */
+  CHDERR_UNKNOWN,
+  /**
+   * The CHD file is shorter than the length required by its header and hunk map,
+   * usually because the file was not completely copied or downloaded.
+   *
+   * The length required by the hunk map can be found with
+   * [`Map::required_length`](chd::map::Map::required_length).
+   */
+  CHDERR_TRUNCATED,
+  /**
+   * The compressed hunk map of the CHD file is corrupted.
+   *
+   * The parameters of the map may be out of range, the map may end before every entry is decoded,
+   * or the decoded map may not match its checksum.
+   */
+  CHDERR_INVALID_MAP,
+  /**
+   * The hunk count in the header of the CHD file is too small for its logical size, so the end
+   * of the logical data is not stored in any hunk.
+   *
+   * Such CHD files can be opened with [`ChdOpenOptions::lenient`](chd::ChdOpenOptions::lenient),
+   * which truncates the logical data to the end of the last hunk.
+   */
+  CHDERR_HUNK_COUNT_MISMATCH,
+} chd_error;
+
 /**
  * An opaque type for an opened CHD file.
  */
 typedef struct chd_file chd_file;
 
+/**
+ * An opaque type for an opened CHD file that can be shared between threads.
+ *
+ * Every operation on a `chd_threadsafe_file*` takes an internal lock on the wrapped `chd_file`,
+ * so that hunks and metadata can be read from multiple threads without external locking.
+ */
+typedef struct chd_threadsafe_file chd_threadsafe_file;
+
 /**
  * libchdr-compatible CHD header struct.
  * This struct is ABI-compatible with [chd.h](https://github.com/rtissera/libchdr/blob/cdcb714235b9ff7d207b703260706a364282b063/include/libchdr/chd.h#L302)
@@ -84,10 +295,10 @@ extern "C" {
  * * `out` is aligned and can store a pointer to a `chd_file*`. On success, `out` will point to a valid `chd_file*`.
  * * After this function returns, `parent` is invalid and must not be used, otherwise it will be undefined behaviour. There is no way to retake ownership of `parent`.
  */
-chd_error chd_open(const char *filename,
-                   int mode,
-                   struct chd_file *parent,
-                   struct chd_file **out);
+enum chd_error chd_open(const char *filename,
+                        int mode,
+                        struct chd_file *parent,
+                        struct chd_file **out);
 
 /**
  * Close a CHD file.
@@ -105,7 +316,17 @@ void chd_close(struct chd_file *chd);
  * The returned string is leaked and the memory **should not and can not ever** be validly freed.
  * Attempting to free the returned pointer with `free` is **undefined behaviour**.
  */
-const char *chd_error_string(chd_error err);
+const char *chd_error_string(enum chd_error err);
+
+/**
+ * Returns the name of the `chd_error` constant for the corresponding CHD error, such as
+ * `"CHDERR_INVALID_FILE"`.
+ *
+ * Unlike [`chd_error_string`](crate::chd_error_string), the returned string is statically allocated
+ * and does not leak. The numeric value of each error is stable across versions of chd-rs, see
+ * [`Error::code`](chd::Error::code).
+ */
+const char *chd_error_code_name(enum chd_error err);
 
 /**
  * Returns a pointer to the extracted CHD header data.
@@ -116,6 +337,18 @@ const char *chd_error_string(chd_error err);
  */
 const struct chd_header *chd_get_header(const struct chd_file *chd);
 
+/**
+ * Returns the number of bytes of memory held by the CHD file and its parents, excluding the
+ * data of the underlying file stream, such as a file that was precached into memory.
+ *
+ * The memory held grows as hunks are read, since codecs are initialized when they are first used.
+ *
+ * # Safety
+ * * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
+ * * If `chd` is `NULL`, returns `0`.
+ */
+size_t chd_get_memory_footprint(const struct chd_file *chd);
+
 /**
  * Read a single hunk from the CHD file.
  *
@@ -124,13 +357,17 @@ const struct chd_header *chd_get_header(const struct chd_file *chd);
  * * `buffer` must an aligned pointer to a block of initialized memory of exactly the hunk size for the input `chd_file*` that is valid for both reads and writes. This size can be found with [`chd_get_header`](crate::chd_get_header).
  * * If `chd` is `NULL`, returns `CHDERR_INVALID_PARAMETER`.
  */
-chd_error chd_read(struct chd_file *chd,
-                   uint32_t hunknum,
-                   void *buffer);
+enum chd_error chd_read(struct chd_file *chd,
+                        uint32_t hunknum,
+                        void *buffer);
 
 /**
  * Get indexed metadata of the given search tag and index.
  *
+ * As in libchdr, V1 and V2 CHD files without hard disk metadata return a `GDDD` entry built from
+ * the geometry of their header for the first hard disk or wildcard metadata. Its length includes the
+ * terminating NUL, and its flags are `CHD_MDFLAGS_CHECKSUM`.
+ *
  * # Safety
  * * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
  * * `output` must be an aligned pointer to a block of initialized memory of size exactly `output_len` that is valid for writes.
@@ -139,21 +376,21 @@ chd_error chd_read(struct chd_file *chd,
  * * `result_flags` must be either NULL or an aligned pointer to a `uint8_t` that is valid for writes.
  * * If `chd` is `NULL`, returns `CHDERR_INVALID_PARAMETER`.
  */
-chd_error chd_get_metadata(struct chd_file *chd,
-                           uint32_t searchtag,
-                           uint32_t searchindex,
-                           void *output,
-                           uint32_t output_len,
-                           uint32_t *result_len,
-                           uint32_t *result_tag,
-                           uint8_t *result_flags);
+enum chd_error chd_get_metadata(struct chd_file *chd,
+                                uint32_t searchtag,
+                                uint32_t searchindex,
+                                void *output,
+                                uint32_t output_len,
+                                uint32_t *result_len,
+                                uint32_t *result_tag,
+                                uint8_t *result_flags);
 
 /**
  * Set codec internal parameters.
  *
  * This function is not supported and always returns `CHDERR_INVALID_PARAMETER`.
  */
-chd_error chd_codec_config(const struct chd_file *_chd, int32_t _param, void *_config);
+enum chd_error chd_codec_config(const struct chd_file *_chd, int32_t _param, void *_config);
 
 /**
  * Read CHD header data from the file into the pointed struct.
@@ -163,8 +400,8 @@ chd_error chd_codec_config(const struct chd_file *_chd, int32_t _param, void *_c
  * * `header` is either `NULL`, or an aligned pointer to a possibly uninitialized `chd_header` struct.
  * * If `header` is `NULL`, returns `CHDERR_INVALID_PARAMETER`
  */
-chd_error chd_read_header(const char *filename,
-                          struct chd_header *header);
+enum chd_error chd_read_header(const char *filename,
+                               struct chd_header *header);
 
 /**
  * Returns the associated `core_file*`.
@@ -206,10 +443,10 @@ core_file *chd_core_file(struct chd_file *chd);
  * * Until the returned `chd_file*` in `out` is closed with [`chd_close`](crate::chd_close) or [`chd_core_file`](crate::chd_core_file), external mutation of `file` will result in undefined behaviour.
  * * After this function returns, `parent` is invalid and must not be used, otherwise it will be undefined behaviour. There is no way to retake ownership of `parent`.
  */
-chd_error chd_open_file(core_file *file,
-                        int mode,
-                        struct chd_file *parent,
-                        struct chd_file **out);
+enum chd_error chd_open_file(core_file *file,
+                             int mode,
+                             struct chd_file *parent,
+                             struct chd_file **out);
 
 /**
  * Open an existing CHD file from an opened `core_file` object.
@@ -224,10 +461,10 @@ chd_error chd_open_file(core_file *file,
  * * Until the returned `chd_file*` in `out` is closed with [`chd_close`](crate::chd_close) or [`chd_core_file`](crate::chd_core_file), external mutation of `file` will result in undefined behaviour.
  * * After this function returns, `parent` is invalid and must not be used, otherwise it will be undefined behaviour. There is no way to retake ownership of `parent`.
  */
-chd_error chd_open_core_file(core_file *file,
-                             int mode,
-                             struct chd_file *parent,
-                             struct chd_file **out);
+enum chd_error chd_open_core_file(core_file *file,
+                                  int mode,
+                                  struct chd_file *parent,
+                                  struct chd_file **out);
 
 /**
  * Get the name of a particular codec.
@@ -236,6 +473,26 @@ chd_error chd_open_core_file(core_file *file,
  */
 const char *chd_get_codec_name(uint32_t _codec);
 
+/**
+ * Get the version of chd-rs-capi, packed as `(major << 16) | (minor << 8) | patch`.
+ */
+uint32_t chd_core_file_version(void);
+
+/**
+ * Get the version of chd-rs-capi as a null-terminated string, such as `"0.3.0"`.
+ *
+ * The returned string is static and must not be freed.
+ */
+const char *chd_get_version_string(void);
+
+/**
+ * Get the capabilities of this build of chd-rs-capi, as a bitmask of `CHD_CAPABILITY_*` flags.
+ *
+ * Functions that are gated behind a feature are only present if the corresponding
+ * capability is set.
+ */
+uint32_t chd_get_capabilities(void);
+
 /**
  * Precache the underlying file into memory with an optional callback to report progress.
  *
@@ -254,9 +511,9 @@ const char *chd_get_codec_name(uint32_t _codec);
  * # Safety
  * * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
  */
-chd_error chd_precache_progress(struct chd_file *chd,
-                                void (*progress)(size_t pos, size_t total, void *param),
-                                void *param);
+enum chd_error chd_precache_progress(struct chd_file *chd,
+                                     void (*progress)(size_t pos, size_t total, void *param),
+                                     void *param);
 
 /**
  * Precache the underlying file into memory.
@@ -276,7 +533,90 @@ chd_error chd_precache_progress(struct chd_file *chd,
  * # Safety
  * * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
  */
-chd_error chd_precache(struct chd_file *chd);
+enum chd_error chd_precache(struct chd_file *chd);
+
+/**
+ * Opens a CHD file by file name into a handle that can be shared between threads.
+ *
+ * This has the same semantics as [`chd_open`](crate::chd_open), except that the opened file is wrapped
+ * in a `chd_threadsafe_file*` as if by [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+ *
+ * # Safety
+ * * `filename` is a valid, null-terminated **UTF-8** string.
+ * * `parent` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
+ * * `out` is aligned and can store a pointer to a `chd_threadsafe_file*`. On success, `out` will point to a valid `chd_threadsafe_file*`.
+ * * After this function returns, `parent` is invalid and must not be used, otherwise it will be undefined behaviour. There is no way to retake ownership of `parent`.
+ */
+enum chd_error chd_open_threadsafe(const char *filename,
+                                   int mode,
+                                   struct chd_file *parent,
+                                   struct chd_threadsafe_file **out);
+
+/**
+ * Wraps an opened CHD file into a handle that can be shared between threads.
+ *
+ * Ownership is taken of the input `chd_file*`, which is closed when the returned
+ * `chd_threadsafe_file*` is closed with [`chd_threadsafe_close`](crate::chd_threadsafe_close).
+ *
+ * # Safety
+ * * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
+ * * If `chd` is `NULL`, returns `NULL`.
+ * * `chd` is **no longer valid** upon return of this function, and subsequent reuse of the `chd_file*` pointer is **undefined behaviour**.
+ */
+struct chd_threadsafe_file *chd_threadsafe_from_file(struct chd_file *chd);
+
+/**
+ * Close a CHD file that can be shared between threads.
+ *
+ * # Safety
+ * * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+ * * No other thread may be using `chd` when this function is called.
+ * * If `chd` is `NULL`, this does nothing.
+ */
+void chd_threadsafe_close(struct chd_threadsafe_file *chd);
+
+/**
+ * Read CHD header data from a CHD file that can be shared between threads into the pointed struct.
+ *
+ * # Safety
+ * * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+ * * `header` is either `NULL`, or an aligned pointer to a possibly uninitialized `chd_header` struct.
+ * * If `chd` or `header` is `NULL`, returns `CHDERR_INVALID_PARAMETER`
+ */
+enum chd_error chd_threadsafe_get_header(const struct chd_threadsafe_file *chd,
+                                         struct chd_header *header);
+
+/**
+ * Read a single hunk from a CHD file that can be shared between threads.
+ *
+ * # Safety
+ * * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+ * * `buffer` must an aligned pointer to a block of initialized memory of exactly the hunk size for the input `chd_threadsafe_file*` that is valid for both reads and writes. This size can be found with [`chd_threadsafe_get_header`](crate::chd_threadsafe_get_header).
+ * * If `chd` is `NULL`, returns `CHDERR_INVALID_PARAMETER`.
+ */
+enum chd_error chd_threadsafe_read(const struct chd_threadsafe_file *chd,
+                                   uint32_t hunknum,
+                                   void *buffer);
+
+/**
+ * Get indexed metadata of the given search tag and index from a CHD file that can be shared between threads.
+ *
+ * # Safety
+ * * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+ * * `output` must be an aligned pointer to a block of initialized memory of size exactly `output_len` that is valid for writes.
+ * * `result_len` must be either NULL or an aligned pointer to a `uint32_t` that is valid for writes.
+ * * `result_tag` must be either NULL or an aligned pointer to a `uint32_t` that is valid for writes.
+ * * `result_flags` must be either NULL or an aligned pointer to a `uint8_t` that is valid for writes.
+ * * If `chd` is `NULL`, returns `CHDERR_INVALID_PARAMETER`.
+ */
+enum chd_error chd_threadsafe_get_metadata(const struct chd_threadsafe_file *chd,
+                                           uint32_t searchtag,
+                                           uint32_t searchindex,
+                                           void *output,
+                                           uint32_t output_len,
+                                           uint32_t *result_len,
+                                           uint32_t *result_tag,
+                                           uint8_t *result_flags);
 
 #ifdef __cplusplus
 } // extern "C"
@@ -0,0 +1,79 @@
+/*
+ * Prints the header and metadata of a CHD file, and checks that every hunk can be read,
+ * using the libchdr-compatible C API of chd-capi.
+ *
+ *   chdinfo game.chd [parent.chd]
+ *
+ * Built by CMakeLists.txt when CHD_CAPI_BUILD_EXAMPLES is enabled.
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <libchdr/chd.h>
+
+#define CHDMETATAG_WILDCARD 0
+
+static void print_tag(uint32_t tag)
+{
+    printf("%c%c%c%c", (tag >> 24) & 0xff, (tag >> 16) & 0xff, (tag >> 8) & 0xff, tag & 0xff);
+}
+
+int main(int argc, char **argv)
+{
+    chd_file *parent = NULL;
+    chd_file *chd = NULL;
+    chd_error err;
+
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s <file.chd> [parent.chd]\n", argv[0]);
+        return 1;
+    }
+
+    if (argc > 2) {
+        err = chd_open(argv[2], CHD_OPEN_READ, NULL, &parent);
+        if (err != CHDERR_NONE) {
+            fprintf(stderr, "%s: %s\n", argv[2], chd_error_string(err));
+            return 1;
+        }
+    }
+
+    /* the parent is owned by the child from here on, and is closed with it. */
+    err = chd_open(argv[1], CHD_OPEN_READ, parent, &chd);
+    if (err != CHDERR_NONE) {
+        fprintf(stderr, "%s: %s (%s)\n", argv[1], chd_error_string(err), chd_error_code_name(err));
+        return 1;
+    }
+
+    const chd_header *header = chd_get_header(chd);
+    printf("version:       %u\n", header->version);
+    printf("logical bytes: %llu\n", (unsigned long long)header->logicalbytes);
+    printf("hunks:         %u of %u bytes\n", header->totalhunks, header->hunkbytes);
+    printf("unit bytes:    %u\n", header->unitbytes);
+
+    /* entries longer than the buffer are truncated, but their full length is returned. */
+    char value[256];
+    for (uint32_t index = 0;; index++) {
+        uint32_t length, tag;
+        uint8_t flags;
+        err = chd_get_metadata(chd, CHDMETATAG_WILDCARD, index, value, sizeof(value), &length, &tag, &flags);
+        if (err != CHDERR_NONE)
+            break;
+        printf("metadata:      ");
+        print_tag(tag);
+        printf(", %u bytes\n", length);
+    }
+
+    void *buffer = malloc(header->hunkbytes);
+    uint32_t failed = 0;
+    for (uint32_t hunk = 0; hunk < header->totalhunks; hunk++) {
+        err = chd_read(chd, hunk, buffer);
+        if (err != CHDERR_NONE) {
+            fprintf(stderr, "hunk %u: %s\n", hunk, chd_error_string(err));
+            failed++;
+        }
+    }
+    printf("%u of %u hunks read\n", header->totalhunks - failed, header->totalhunks);
+
+    free(buffer);
+    chd_close(chd);
+    return failed == 0 ? 0 : 2;
+}
@@ -0,0 +1,261 @@
+use chd::Error;
+use std::fmt::Display;
+
+/// Error codes returned by the C API.
+///
+/// This type is ABI-compatible with [libchdr](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L258),
+/// given sane defaults in the C compiler. See [repr(C) in the Rustonomicon](https://doc.rust-lang.org/nomicon/other-reprs.html#reprc) for more details.
+/// The discriminant of each error is its [`Error::code`](chd::Error::code).
+#[allow(non_camel_case_types)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub enum chd_error {
+    /// No error.
+    /// This is only used by the C API bindings.
+    None,
+    /// No drive interface.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    NoInterface,
+    /// Unable to allocate the required size of buffer.
+    OutOfMemory,
+    /// The file is not a valid CHD file.
+    InvalidFile,
+    /// An invalid parameter was provided.
+    InvalidParameter,
+    /// The data is invalid.
+    InvalidData,
+    /// The file was not found.
+    FileNotFound,
+    /// This CHD requires a parent CHD that was not provided.
+    RequiresParent,
+    /// The provided file is not writable.
+    /// chd-rs only opens CHD files for reading, so this is returned when a CHD file is opened for writing.
+    FileNotWriteable,
+    /// An error occurred when reading this CHD file.
+    ReadError,
+    /// An error occurred when writing this CHD file.
+    /// Since chd-rs does not implement CHD creation, this is unused.
+    WriteError,
+    /// An error occurred when initializing a codec.
+    CodecError,
+    /// The provided parent CHD is invalid.
+    InvalidParent,
+    /// The request hunk is out of range for this CHD file.
+    HunkOutOfRange,
+    /// An error occurred when decompressing a hunk.
+    DecompressionError,
+    /// An error occurred when compressing a hunk.
+    /// Since chd-rs does not implement CHD creation, this is unused.
+    CompressionError,
+    /// Could not create the file.
+    /// Since chd-rs does not implement CHD creation, this is unused.
+    CantCreateFile,
+    /// Could not verify the CHD.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    CantVerify,
+    /// The requested operation is not supported.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    NotSupported,
+    /// The requested metadata was not found.
+    MetadataNotFound,
+    /// The metadata has an invalid size.
+    ///
+    /// The contents of a metadata entry may extend past the end of the file, or be too long
+    /// for the 24-bit length field of a metadata entry.
+    InvalidMetadataSize,
+    /// The CHD version of the provided file is not supported by this library.
+    UnsupportedVersion,
+    /// Unable to verify the CHD completely.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    VerifyIncomplete,
+    /// The requested metadata is invalid.
+    InvalidMetadata,
+    /// The internal state of the decoder/encoder is invalid.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    InvalidState,
+    /// An operation is already pending.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    OperationPending,
+    /// No async operations are allowed.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    NoAsyncOperation,
+    /// Decompressing the CHD requires a codec that is not supported.
+    ///
+    /// This is also returned for [`Error::UnsupportedCodec`](chd::Error::UnsupportedCodec), since libchdr
+    /// has no error that carries the unsupported codec.
+    UnsupportedFormat,
+    /// Unknown error.
+    Unknown,
+    /// The CHD file is shorter than the length required by its header and hunk map,
+    /// usually because the file was not completely copied or downloaded.
+    ///
+    /// The length required by the hunk map can be found with
+    /// [`Map::required_length`](chd::map::Map::required_length).
+    Truncated,
+    /// The compressed hunk map of the CHD file is corrupted.
+    ///
+    /// The parameters of the map may be out of range, the map may end before every entry is decoded,
+    /// or the decoded map may not match its checksum.
+    InvalidMap,
+    /// The hunk count in the header of the CHD file is too small for its logical size, so the end
+    /// of the logical data is not stored in any hunk.
+    ///
+    /// Such CHD files can be opened with [`ChdOpenOptions::lenient`](chd::ChdOpenOptions::lenient),
+    /// which truncates the logical data to the end of the last hunk.
+    HunkCountMismatch,
+}
+
+impl From<Error> for chd_error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::None => chd_error::None,
+            Error::NoInterface => chd_error::NoInterface,
+            Error::OutOfMemory => chd_error::OutOfMemory,
+            Error::InvalidFile => chd_error::InvalidFile,
+            Error::InvalidParameter => chd_error::InvalidParameter,
+            Error::InvalidData => chd_error::InvalidData,
+            Error::FileNotFound => chd_error::FileNotFound,
+            Error::RequiresParent => chd_error::RequiresParent,
+            Error::FileNotWriteable => chd_error::FileNotWriteable,
+            Error::ReadError => chd_error::ReadError,
+            Error::WriteError => chd_error::WriteError,
+            Error::CodecError => chd_error::CodecError,
+            Error::InvalidParent => chd_error::InvalidParent,
+            Error::HunkOutOfRange => chd_error::HunkOutOfRange,
+            Error::DecompressionError => chd_error::DecompressionError,
+            Error::CompressionError => chd_error::CompressionError,
+            Error::CantCreateFile => chd_error::CantCreateFile,
+            Error::CantVerify => chd_error::CantVerify,
+            Error::NotSupported => chd_error::NotSupported,
+            Error::MetadataNotFound => chd_error::MetadataNotFound,
+            Error::InvalidMetadataSize => chd_error::InvalidMetadataSize,
+            Error::UnsupportedVersion => chd_error::UnsupportedVersion,
+            Error::VerifyIncomplete => chd_error::VerifyIncomplete,
+            Error::InvalidMetadata => chd_error::InvalidMetadata,
+            Error::InvalidState => chd_error::InvalidState,
+            Error::OperationPending => chd_error::OperationPending,
+            Error::NoAsyncOperation => chd_error::NoAsyncOperation,
+            Error::UnsupportedFormat | Error::UnsupportedCodec { .. } => {
+                chd_error::UnsupportedFormat
+            }
+            Error::Unknown => chd_error::Unknown,
+            Error::Truncated { .. } => chd_error::Truncated,
+            Error::InvalidMap => chd_error::InvalidMap,
+            Error::HunkCountMismatch => chd_error::HunkCountMismatch,
+        }
+    }
+}
+
+impl From<chd_error> for Error {
+    fn from(err: chd_error) -> Self {
+        match err {
+            chd_error::None => Error::None,
+            chd_error::NoInterface => Error::NoInterface,
+            chd_error::OutOfMemory => Error::OutOfMemory,
+            chd_error::InvalidFile => Error::InvalidFile,
+            chd_error::InvalidParameter => Error::InvalidParameter,
+            chd_error::InvalidData => Error::InvalidData,
+            chd_error::FileNotFound => Error::FileNotFound,
+            chd_error::RequiresParent => Error::RequiresParent,
+            chd_error::FileNotWriteable => Error::FileNotWriteable,
+            chd_error::ReadError => Error::ReadError,
+            chd_error::WriteError => Error::WriteError,
+            chd_error::CodecError => Error::CodecError,
+            chd_error::InvalidParent => Error::InvalidParent,
+            chd_error::HunkOutOfRange => Error::HunkOutOfRange,
+            chd_error::DecompressionError => Error::DecompressionError,
+            chd_error::CompressionError => Error::CompressionError,
+            chd_error::CantCreateFile => Error::CantCreateFile,
+            chd_error::CantVerify => Error::CantVerify,
+            chd_error::NotSupported => Error::NotSupported,
+            chd_error::MetadataNotFound => Error::MetadataNotFound,
+            chd_error::InvalidMetadataSize => Error::InvalidMetadataSize,
+            chd_error::UnsupportedVersion => Error::UnsupportedVersion,
+            chd_error::VerifyIncomplete => Error::VerifyIncomplete,
+            chd_error::InvalidMetadata => Error::InvalidMetadata,
+            chd_error::InvalidState => Error::InvalidState,
+            chd_error::OperationPending => Error::OperationPending,
+            chd_error::NoAsyncOperation => Error::NoAsyncOperation,
+            chd_error::UnsupportedFormat => Error::UnsupportedFormat,
+            chd_error::Unknown => Error::Unknown,
+            // the lengths are not carried by the C error.
+            chd_error::Truncated => Error::Truncated {
+                expected: 0,
+                actual: 0,
+            },
+            chd_error::InvalidMap => Error::InvalidMap,
+            chd_error::HunkCountMismatch => Error::HunkCountMismatch,
+        }
+    }
+}
+
+impl Display for chd_error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            chd_error::Truncated => f.write_str("file is truncated"),
+            _ => Display::fmt(&Error::from(*self), f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chd_error;
+    use chd::{Error, Tag};
+
+    #[test]
+    fn error_discriminant_test() {
+        // the discriminants are relied on by C API consumers and must match the error codes.
+        let errors = [
+            chd_error::None,
+            chd_error::NoInterface,
+            chd_error::OutOfMemory,
+            chd_error::InvalidFile,
+            chd_error::InvalidParameter,
+            chd_error::InvalidData,
+            chd_error::FileNotFound,
+            chd_error::RequiresParent,
+            chd_error::FileNotWriteable,
+            chd_error::ReadError,
+            chd_error::WriteError,
+            chd_error::CodecError,
+            chd_error::InvalidParent,
+            chd_error::HunkOutOfRange,
+            chd_error::DecompressionError,
+            chd_error::CompressionError,
+            chd_error::CantCreateFile,
+            chd_error::CantVerify,
+            chd_error::NotSupported,
+            chd_error::MetadataNotFound,
+            chd_error::InvalidMetadataSize,
+            chd_error::UnsupportedVersion,
+            chd_error::VerifyIncomplete,
+            chd_error::InvalidMetadata,
+            chd_error::InvalidState,
+            chd_error::OperationPending,
+            chd_error::NoAsyncOperation,
+            chd_error::UnsupportedFormat,
+            chd_error::Unknown,
+            chd_error::Truncated,
+            chd_error::InvalidMap,
+            chd_error::HunkCountMismatch,
+        ];
+        for (code, err) in errors.into_iter().enumerate() {
+            assert_eq!(code as i32, err as i32, "{:?}", err);
+            assert_eq!(code as i32, Error::from(err).code(), "{:?}", err);
+            assert_eq!(err, chd_error::from(Error::from(err)));
+        }
+
+        let unsupported = Error::UnsupportedCodec {
+            fourcc: Tag::new(b"zstd"),
+        };
+        assert_eq!(chd_error::UnsupportedFormat, chd_error::from(unsupported));
+
+        let truncated = Error::Truncated {
+            expected: 2048,
+            actual: 1024,
+        };
+        assert_eq!(chd_error::Truncated, chd_error::from(truncated));
+        assert_eq!("file is truncated", chd_error::Truncated.to_string());
+    }
+}
@@ -132,6 +132,7 @@ pub(crate) fn get_v5_header(chd: &chd_file) -> chd_header {
         _ => unreachable!(),
     };
     let mut map_data: Vec<u8> = match chd.map() {
+        // the C API never reads maps lazily, so the map is always decoded in full.
         Map::V5(map) => map.into(),
         _ => unreachable!(),
     };
@@ -16,9 +16,19 @@
 //! * `chd_file *` is an opaque pointer. It is **not layout compatible** with [chd.c](https://github.com/rtissera/libchdr/blob/cdcb714235b9ff7d207b703260706a364282b063/src/libchdr_chd.c#L265)
 //! * The layout of `core_file *` is user-defined when the `chd_core_file` feature is enabled.
 //! * Freeing any pointer returned by chd-rs with `free` is undefined behaviour. The exception are `chd_file *` pointers which can be safely freed with `chd_close`.
+//!
+//! ## Thread safety
+//! A `chd_file *` is not thread safe. Functions that take a `chd_file *` must not be called concurrently
+//! with the same `chd_file *` from multiple threads without external locking, including functions
+//! that only read from the CHD file such as `chd_read`, since reads move the position of the underlying stream.
+//!
+//! When the `chd_threadsafe` feature is enabled, `chd_open_threadsafe` or `chd_threadsafe_from_file` return
+//! a `chd_threadsafe_file *` that serializes every operation with an internal lock, and can be shared
+//! between threads.
 
 extern crate core;
 
+mod error;
 mod header;
 
 #[cfg(feature = "chd_core_file")]
@@ -29,11 +39,11 @@ mod chdcorefile;
 #[allow(unused)]
 mod chdcorefile_sys;
 
+pub use crate::error::chd_error;
 use crate::header::chd_header;
 use chd::header::Header;
-use chd::metadata::{KnownMetadata, Metadata, MetadataTag};
-pub use chd::Error as chd_error;
-use chd::{Chd, Error};
+use chd::metadata::{KnownMetadata, MetadataTag};
+use chd::Chd;
 use std::any::Any;
 use std::ffi::{CStr, CString};
 use std::fs::File;
@@ -45,10 +55,14 @@ use std::slice;
 
 /// Open a CHD for reading.
 pub const CHD_OPEN_READ: i32 = 1;
-/// Open a CHD for reading and writing. This mode is not supported and will always return an error
-/// when passed into a constructor function such as [`chd_open`](crate::chd_open).
+/// Open a CHD for reading and writing. This mode is not supported, since chd-rs only reads CHD files,
+/// and constructor functions such as [`chd_open`](crate::chd_open) will always return
+/// `CHDERR_FILE_NOT_WRITEABLE` when it is passed in.
 pub const CHD_OPEN_READWRITE: i32 = 2;
 
+/// The metadata entry is included in the SHA1 hash of the CHD file.
+pub const CHD_MDFLAGS_CHECKSUM: u8 = 0x01;
+
 /// Trait alias for `Read + Seek + Any`.
 #[doc(hidden)]
 pub trait SeekRead: Any + Read + Seek {
@@ -91,7 +105,7 @@ fn ffi_open_chd(
     let file = File::open(filename).map_err(|_| chd_error::FileNotFound)?;
 
     let bufread = Box::new(BufReader::new(file)) as Box<dyn SeekRead>;
-    Chd::open(bufread, parent)
+    Chd::open(bufread, parent).map_err(chd_error::from)
 }
 
 /// Opens a CHD file by file name, with a layout-undefined backing file pointer owned by
@@ -159,6 +173,51 @@ pub unsafe extern "C" fn chd_error_string(err: chd_error) -> *const c_char {
     err_string.into_raw()
 }
 
+#[no_mangle]
+/// Returns the name of the `chd_error` constant for the corresponding CHD error, such as
+/// `"CHDERR_INVALID_FILE"`.
+///
+/// Unlike [`chd_error_string`](crate::chd_error_string), the returned string is statically allocated
+/// and does not leak. The numeric value of each error is stable across versions of chd-rs, see
+/// [`Error::code`](chd::Error::code).
+pub extern "C" fn chd_error_code_name(err: chd_error) -> *const c_char {
+    let name: &'static [u8] = match err {
+        chd_error::None => b"CHDERR_NONE\0",
+        chd_error::NoInterface => b"CHDERR_NO_INTERFACE\0",
+        chd_error::OutOfMemory => b"CHDERR_OUT_OF_MEMORY\0",
+        chd_error::InvalidFile => b"CHDERR_INVALID_FILE\0",
+        chd_error::InvalidParameter => b"CHDERR_INVALID_PARAMETER\0",
+        chd_error::InvalidData => b"CHDERR_INVALID_DATA\0",
+        chd_error::FileNotFound => b"CHDERR_FILE_NOT_FOUND\0",
+        chd_error::RequiresParent => b"CHDERR_REQUIRES_PARENT\0",
+        chd_error::FileNotWriteable => b"CHDERR_FILE_NOT_WRITEABLE\0",
+        chd_error::ReadError => b"CHDERR_READ_ERROR\0",
+        chd_error::WriteError => b"CHDERR_WRITE_ERROR\0",
+        chd_error::CodecError => b"CHDERR_CODEC_ERROR\0",
+        chd_error::InvalidParent => b"CHDERR_INVALID_PARENT\0",
+        chd_error::HunkOutOfRange => b"CHDERR_HUNK_OUT_OF_RANGE\0",
+        chd_error::DecompressionError => b"CHDERR_DECOMPRESSION_ERROR\0",
+        chd_error::CompressionError => b"CHDERR_COMPRESSION_ERROR\0",
+        chd_error::CantCreateFile => b"CHDERR_CANT_CREATE_FILE\0",
+        chd_error::CantVerify => b"CHDERR_CANT_VERIFY\0",
+        chd_error::NotSupported => b"CHDERR_NOT_SUPPORTED\0",
+        chd_error::MetadataNotFound => b"CHDERR_METADATA_NOT_FOUND\0",
+        chd_error::InvalidMetadataSize => b"CHDERR_INVALID_METADATA_SIZE\0",
+        chd_error::UnsupportedVersion => b"CHDERR_UNSUPPORTED_VERSION\0",
+        chd_error::VerifyIncomplete => b"CHDERR_VERIFY_INCOMPLETE\0",
+        chd_error::InvalidMetadata => b"CHDERR_INVALID_METADATA\0",
+        chd_error::InvalidState => b"CHDERR_INVALID_STATE\0",
+        chd_error::OperationPending => b"CHDERR_OPERATION_PENDING\0",
+        chd_error::NoAsyncOperation => b"CHDERR_NO_ASYNC_OPERATION\0",
+        chd_error::UnsupportedFormat => b"CHDERR_UNSUPPORTED_FORMAT\0",
+        chd_error::Unknown => b"CHDERR_UNKNOWN\0",
+        chd_error::Truncated => b"CHDERR_TRUNCATED\0",
+        chd_error::InvalidMap => b"CHDERR_INVALID_MAP\0",
+        chd_error::HunkCountMismatch => b"CHDERR_HUNK_COUNT_MISMATCH\0",
+    };
+    name.as_ptr() as *const c_char
+}
+
 fn ffi_chd_get_header(chd: &chd_file) -> chd_header {
     match chd.header() {
         Header::V5Header(_) => header::get_v5_header(chd),
@@ -183,6 +242,22 @@ pub unsafe extern "C" fn chd_get_header(chd: *const chd_file) -> *const chd_head
     }
 }
 
+#[no_mangle]
+/// Returns the number of bytes of memory held by the CHD file and its parents, excluding the
+/// data of the underlying file stream, such as a file that was precached into memory.
+///
+/// The memory held grows as hunks are read, since codecs are initialized when they are first used.
+///
+/// # Safety
+/// * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
+/// * If `chd` is `NULL`, returns `0`.
+pub unsafe extern "C" fn chd_get_memory_footprint(chd: *const chd_file) -> usize {
+    match unsafe { chd.as_ref() } {
+        Some(chd) => chd.memory_footprint().total(),
+        None => 0,
+    }
+}
+
 #[no_mangle]
 /// Read a single hunk from the CHD file.
 ///
@@ -197,45 +272,37 @@ pub unsafe extern "C" fn chd_read(
 ) -> chd_error {
     match unsafe { chd.as_mut() } {
         None => chd_error::InvalidParameter,
-        Some(chd) => {
-            let hunk = chd.hunk(hunknum);
-            if let Ok(mut hunk) = hunk {
-                let size = hunk.len();
-                let mut comp_buf = Vec::new();
-                // SAFETY: The output buffer *must* be initialized and
-                // have a length of exactly the hunk size.
-                let output: &mut [u8] =
-                    unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) };
-                let result = hunk.read_hunk_in(&mut comp_buf, output);
-                match result {
-                    Ok(_) => chd_error::None,
-                    Err(e) => e,
-                }
-            } else {
-                chd_error::HunkOutOfRange
-            }
-        }
+        Some(chd) => unsafe { ffi_chd_read(chd, hunknum, buffer) },
     }
 }
 
-fn find_metadata(
-    chd: &mut chd_file,
-    search_tag: u32,
-    mut search_index: u32,
-) -> Result<Metadata, Error> {
-    for entry in chd.metadata_refs() {
-        if entry.metatag() == search_tag || entry.metatag() == KnownMetadata::Wildcard.metatag() {
-            if search_index == 0 {
-                return entry.read(chd.inner());
-            }
-            search_index -= 1;
+/// # Safety
+/// * `buffer` must an aligned pointer to a block of initialized memory of exactly the hunk size for `chd`.
+unsafe fn ffi_chd_read(chd: &mut chd_file, hunknum: u32, buffer: *mut c_void) -> chd_error {
+    let hunk = chd.hunk(hunknum);
+    if let Ok(mut hunk) = hunk {
+        let size = hunk.len();
+        let mut comp_buf = Vec::new();
+        // SAFETY: The output buffer *must* be initialized and
+        // have a length of exactly the hunk size.
+        let output: &mut [u8] = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) };
+        let result = hunk.read_hunk_in(&mut comp_buf, output);
+        match result {
+            Ok(_) => chd_error::None,
+            Err(e) => e.into(),
         }
+    } else {
+        chd_error::HunkOutOfRange
     }
-    Err(Error::MetadataNotFound)
 }
+
 #[no_mangle]
 /// Get indexed metadata of the given search tag and index.
 ///
+/// As in libchdr, V1 and V2 CHD files without hard disk metadata return a `GDDD` entry built from
+/// the geometry of their header for the first hard disk or wildcard metadata. Its length includes the
+/// terminating NUL, and its flags are `CHD_MDFLAGS_CHECKSUM`.
+///
 /// # Safety
 /// * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
 /// * `output` must be an aligned pointer to a block of initialized memory of size exactly `output_len` that is valid for writes.
@@ -254,68 +321,99 @@ pub unsafe extern "C" fn chd_get_metadata(
     result_flags: *mut u8,
 ) -> chd_error {
     match unsafe { chd.as_mut() } {
-        Some(chd) => {
-            let entry = find_metadata(chd, searchtag, searchindex);
-            match (entry, searchtag) {
-                (Ok(meta), _) => {
-                    unsafe {
-                        let output_len = std::cmp::min(output_len, meta.value.len() as u32);
-                        std::ptr::copy_nonoverlapping(
-                            meta.value.as_ptr() as *const c_void,
-                            output,
-                            output_len as usize,
-                        );
-
-                        if !result_tag.is_null() {
-                            result_tag.write(meta.metatag)
-                        }
-                        if !result_len.is_null() {
-                            result_len.write(meta.length)
-                        }
-                        if !result_flags.is_null() {
-                            result_flags.write(meta.flags)
-                        }
-                    }
-                    chd_error::None
+        Some(chd) => unsafe {
+            ffi_chd_get_metadata(
+                chd,
+                searchtag,
+                searchindex,
+                output,
+                output_len,
+                result_len,
+                result_tag,
+                result_flags,
+            )
+        },
+        None => chd_error::InvalidParameter,
+    }
+}
+
+/// # Safety
+/// * The pointer arguments must satisfy the safety requirements of [`chd_get_metadata`](crate::chd_get_metadata).
+#[allow(clippy::too_many_arguments)]
+unsafe fn ffi_chd_get_metadata(
+    chd: &mut chd_file,
+    searchtag: u32,
+    searchindex: u32,
+    output: *mut c_void,
+    output_len: u32,
+    result_len: *mut u32,
+    result_tag: *mut u32,
+    result_flags: *mut u8,
+) -> chd_error {
+    let entry = chd.metadata_by_tag(searchtag, searchindex);
+    match (entry, searchtag) {
+        (Ok(meta), _) => {
+            unsafe {
+                let output_len = std::cmp::min(output_len, meta.value.len() as u32);
+                std::ptr::copy_nonoverlapping(
+                    meta.value.as_ptr() as *const c_void,
+                    output,
+                    output_len as usize,
+                );
+
+                if !result_tag.is_null() {
+                    result_tag.write(meta.metatag)
+                }
+                if !result_len.is_null() {
+                    result_len.write(meta.length)
+                }
+                if !result_flags.is_null() {
+                    result_flags.write(meta.flags)
                 }
-                (Err(_), tag) => unsafe {
-                    if (tag == KnownMetadata::HardDisk.metatag()
-                        || tag == KnownMetadata::Wildcard.metatag())
-                        && searchindex == 0
-                    {
-                        let header = chd.header();
-                        if let Header::V1Header(header) = header {
-                            let fake_meta = format!(
-                                "CYLS:{},HEADS:{},SECS:{},BPS:{}",
-                                header.cylinders,
-                                header.heads,
-                                header.sectors,
-                                header.hunk_bytes / header.hunk_size
-                            );
-                            let cstring = CString::from_vec_unchecked(fake_meta.into_bytes());
-                            let bytes = cstring.into_bytes_with_nul();
-                            let len = bytes.len();
-                            let output_len = std::cmp::min(output_len, len as u32);
-
-                            std::ptr::copy_nonoverlapping(
-                                bytes.as_ptr() as *const c_void,
-                                output,
-                                output_len as usize,
-                            );
-                            if !result_tag.is_null() {
-                                result_tag.write(KnownMetadata::HardDisk.metatag())
-                            }
-                            if !result_len.is_null() {
-                                result_len.write(len as u32)
-                            }
-                            return chd_error::None;
-                        }
-                    }
-                    chd_error::MetadataNotFound
-                },
             }
+            chd_error::None
         }
-        None => chd_error::InvalidParameter,
+        (Err(_), tag) => unsafe {
+            // like libchdr, V1 and V2 files that do not have hard disk metadata report the
+            // geometry of their header as if it was a checksummed GDDD entry.
+            if (tag == KnownMetadata::HardDisk.metatag()
+                || tag == KnownMetadata::Wildcard.metatag())
+                && searchindex == 0
+            {
+                let header = chd.header();
+                if let Header::V1Header(header) | Header::V2Header(header) = header {
+                    let fake_meta = format!(
+                        "CYLS:{},HEADS:{},SECS:{},BPS:{}",
+                        header.cylinders,
+                        header.heads,
+                        header.sectors,
+                        header.hunk_bytes / header.hunk_size
+                    );
+                    let cstring = CString::from_vec_unchecked(fake_meta.into_bytes());
+                    // the length includes the terminating NUL, as with strlen + 1 in libchdr.
+                    let bytes = cstring.into_bytes_with_nul();
+                    let len = bytes.len();
+                    let output_len = std::cmp::min(output_len, len as u32);
+
+                    std::ptr::copy_nonoverlapping(
+                        bytes.as_ptr() as *const c_void,
+                        output,
+                        output_len as usize,
+                    );
+                    if !result_tag.is_null() {
+                        result_tag.write(KnownMetadata::HardDisk.metatag())
+                    }
+                    if !result_len.is_null() {
+                        result_len.write(len as u32)
+                    }
+                    if !result_flags.is_null() {
+                        result_flags.write(CHD_MDFLAGS_CHECKSUM)
+                    }
+                    return chd_error::None;
+                }
+            }
+            chd_error::MetadataNotFound
+        },
     }
 }
 
@@ -347,10 +445,10 @@ pub unsafe extern "C" fn chd_read_header(
         Ok(chd) => {
             let chd_header = ffi_chd_get_header(&chd);
             match unsafe { header.as_mut() } {
-                None => Error::InvalidParameter,
+                None => chd_error::InvalidParameter,
                 Some(header) => {
                     header.write(chd_header);
-                    Error::None
+                    chd_error::None
                 }
             }
         }
@@ -434,7 +532,7 @@ pub unsafe extern "C" fn chd_open_file(
     let core_file = Box::new(crate::chdcorefile::CoreFile(file)) as Box<dyn SeekRead>;
     let chd = match Chd::open(core_file, parent) {
         Ok(chd) => chd,
-        Err(e) => return e,
+        Err(e) => return e.into(),
     };
 
     unsafe { *out = ffi_expose_chd(Box::new(chd)) }
@@ -469,7 +567,73 @@ pub unsafe extern "C" fn chd_open_core_file(
 ///
 /// This method always returns the string "Unknown"
 pub extern "C" fn chd_get_codec_name(_codec: u32) -> *const c_char {
-    b"Unknown\0".as_ptr() as *const c_char
+    let name = b"Unknown\0";
+    name.as_ptr() as *const c_char
+}
+
+/// Zstandard compressed CHD files can be read.
+pub const CHD_CAPABILITY_ZSTD: u32 = 1 << 0;
+/// The `chd_precache` and `chd_precache_progress` functions are available.
+pub const CHD_CAPABILITY_PRECACHE: u32 = 1 << 1;
+/// The `chd_threadsafe_file*` handle and its functions are available.
+pub const CHD_CAPABILITY_THREADSAFE: u32 = 1 << 2;
+/// The `chd_open_file` and `chd_core_file` functions are available.
+pub const CHD_CAPABILITY_CORE_FILE: u32 = 1 << 3;
+/// The `chd_open_core_file` function is available.
+pub const CHD_CAPABILITY_VIRTIO: u32 = 1 << 4;
+/// Decompressed hunks are verified with their internal hash.
+pub const CHD_CAPABILITY_VERIFY_BLOCK_CRC: u32 = 1 << 5;
+
+#[no_mangle]
+/// Get the version of chd-rs-capi, packed as `(major << 16) | (minor << 8) | patch`.
+pub extern "C" fn chd_core_file_version() -> u32 {
+    const fn parse(s: &str) -> u32 {
+        let bytes = s.as_bytes();
+        let mut value = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            value = value * 10 + (bytes[i] - b'0') as u32;
+            i += 1;
+        }
+        value
+    }
+    const VERSION: u32 = (parse(env!("CARGO_PKG_VERSION_MAJOR")) << 16)
+        | (parse(env!("CARGO_PKG_VERSION_MINOR")) << 8)
+        | parse(env!("CARGO_PKG_VERSION_PATCH"));
+    VERSION
+}
+
+#[no_mangle]
+/// Get the version of chd-rs-capi as a null-terminated string, such as `"0.3.0"`.
+///
+/// The returned string is static and must not be freed.
+pub extern "C" fn chd_get_version_string() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[no_mangle]
+/// Get the capabilities of this build of chd-rs-capi, as a bitmask of `CHD_CAPABILITY_*` flags.
+///
+/// Functions that are gated behind a feature are only present if the corresponding
+/// capability is set.
+pub extern "C" fn chd_get_capabilities() -> u32 {
+    let mut capabilities = CHD_CAPABILITY_ZSTD;
+    if cfg!(feature = "chd_precache") {
+        capabilities |= CHD_CAPABILITY_PRECACHE;
+    }
+    if cfg!(feature = "chd_threadsafe") {
+        capabilities |= CHD_CAPABILITY_THREADSAFE;
+    }
+    if cfg!(feature = "chd_core_file") {
+        capabilities |= CHD_CAPABILITY_CORE_FILE;
+    }
+    if cfg!(feature = "chd_virtio") {
+        capabilities |= CHD_CAPABILITY_VIRTIO;
+    }
+    if cfg!(feature = "verify_block_crc") {
+        capabilities |= CHD_CAPABILITY_VERIFY_BLOCK_CRC;
+    }
+    capabilities
 }
 
 #[cfg(feature = "chd_precache")]
@@ -561,7 +725,7 @@ pub unsafe extern "C" fn chd_precache_progress(
     let (_file, parent) = chd_file.into_inner();
 
     let buffered_chd = match Chd::open(stream, parent) {
-        Err(e) => return e,
+        Err(e) => return e.into(),
         Ok(chd) => Box::new(chd),
     };
 
@@ -593,3 +757,170 @@ pub unsafe extern "C" fn chd_precache_progress(
 pub unsafe extern "C" fn chd_precache(chd: *mut chd_file) -> chd_error {
     chd_precache_progress(chd, None, std::ptr::null_mut())
 }
+
+#[cfg(feature = "chd_threadsafe")]
+use std::sync::{Mutex, PoisonError};
+
+#[allow(non_camel_case_types)]
+#[cfg(feature = "chd_threadsafe")]
+#[cfg_attr(docsrs, doc(cfg(chd_threadsafe)))]
+/// An opaque type for an opened CHD file that can be shared between threads.
+///
+/// Every operation on a `chd_threadsafe_file*` takes an internal lock on the wrapped `chd_file`,
+/// so that hunks and metadata can be read from multiple threads without external locking.
+pub struct chd_threadsafe_file(Mutex<Box<chd_file>>);
+
+#[cfg(feature = "chd_threadsafe")]
+fn ffi_with_threadsafe_chd(
+    chd: *const chd_threadsafe_file,
+    f: impl FnOnce(&mut chd_file) -> chd_error,
+) -> chd_error {
+    match unsafe { chd.as_ref() } {
+        None => chd_error::InvalidParameter,
+        Some(chd) => {
+            // A panic while the lock is held can not leave the CHD file in an invalid state,
+            // so poisoning is ignored.
+            let mut chd = chd.0.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&mut chd)
+        }
+    }
+}
+
+#[no_mangle]
+#[cfg(feature = "chd_threadsafe")]
+#[cfg_attr(docsrs, doc(cfg(chd_threadsafe)))]
+/// Opens a CHD file by file name into a handle that can be shared between threads.
+///
+/// This has the same semantics as [`chd_open`](crate::chd_open), except that the opened file is wrapped
+/// in a `chd_threadsafe_file*` as if by [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+///
+/// # Safety
+/// * `filename` is a valid, null-terminated **UTF-8** string.
+/// * `parent` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
+/// * `out` is aligned and can store a pointer to a `chd_threadsafe_file*`. On success, `out` will point to a valid `chd_threadsafe_file*`.
+/// * After this function returns, `parent` is invalid and must not be used, otherwise it will be undefined behaviour. There is no way to retake ownership of `parent`.
+pub unsafe extern "C" fn chd_open_threadsafe(
+    filename: *const c_char,
+    mode: c_int,
+    parent: *mut chd_file,
+    out: *mut *mut chd_threadsafe_file,
+) -> chd_error {
+    let mut chd = std::ptr::null_mut();
+    match unsafe { chd_open(filename, mode, parent, &mut chd) } {
+        chd_error::None => {
+            unsafe { *out = chd_threadsafe_from_file(chd) }
+            chd_error::None
+        }
+        err => err,
+    }
+}
+
+#[no_mangle]
+#[cfg(feature = "chd_threadsafe")]
+#[cfg_attr(docsrs, doc(cfg(chd_threadsafe)))]
+/// Wraps an opened CHD file into a handle that can be shared between threads.
+///
+/// Ownership is taken of the input `chd_file*`, which is closed when the returned
+/// `chd_threadsafe_file*` is closed with [`chd_threadsafe_close`](crate::chd_threadsafe_close).
+///
+/// # Safety
+/// * `chd` is either `NULL` or a valid pointer to a `chd_file` obtained from [`chd_open`](crate::chd_open), [`chd_open_file`](crate::chd_open_file), or [`chd_open_core_file`](crate::chd_open_core_file).
+/// * If `chd` is `NULL`, returns `NULL`.
+/// * `chd` is **no longer valid** upon return of this function, and subsequent reuse of the `chd_file*` pointer is **undefined behaviour**.
+pub unsafe extern "C" fn chd_threadsafe_from_file(chd: *mut chd_file) -> *mut chd_threadsafe_file {
+    if chd.is_null() {
+        return std::ptr::null_mut();
+    }
+    let chd = ffi_takeown_chd(chd);
+    Box::into_raw(Box::new(chd_threadsafe_file(Mutex::new(chd))))
+}
+
+#[no_mangle]
+#[cfg(feature = "chd_threadsafe")]
+#[cfg_attr(docsrs, doc(cfg(chd_threadsafe)))]
+/// Close a CHD file that can be shared between threads.
+///
+/// # Safety
+/// * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+/// * No other thread may be using `chd` when this function is called.
+/// * If `chd` is `NULL`, this does nothing.
+pub unsafe extern "C" fn chd_threadsafe_close(chd: *mut chd_threadsafe_file) {
+    if !chd.is_null() {
+        unsafe { drop(Box::from_raw(chd)) }
+    }
+}
+
+#[no_mangle]
+#[cfg(feature = "chd_threadsafe")]
+#[cfg_attr(docsrs, doc(cfg(chd_threadsafe)))]
+/// Read CHD header data from a CHD file that can be shared between threads into the pointed struct.
+///
+/// # Safety
+/// * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+/// * `header` is either `NULL`, or an aligned pointer to a possibly uninitialized `chd_header` struct.
+/// * If `chd` or `header` is `NULL`, returns `CHDERR_INVALID_PARAMETER`
+pub unsafe extern "C" fn chd_threadsafe_get_header(
+    chd: *const chd_threadsafe_file,
+    header: *mut MaybeUninit<chd_header>,
+) -> chd_error {
+    ffi_with_threadsafe_chd(chd, |chd| match unsafe { header.as_mut() } {
+        None => chd_error::InvalidParameter,
+        Some(header) => {
+            header.write(ffi_chd_get_header(chd));
+            chd_error::None
+        }
+    })
+}
+
+#[no_mangle]
+#[cfg(feature = "chd_threadsafe")]
+#[cfg_attr(docsrs, doc(cfg(chd_threadsafe)))]
+/// Read a single hunk from a CHD file that can be shared between threads.
+///
+/// # Safety
+/// * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+/// * `buffer` must an aligned pointer to a block of initialized memory of exactly the hunk size for the input `chd_threadsafe_file*` that is valid for both reads and writes. This size can be found with [`chd_threadsafe_get_header`](crate::chd_threadsafe_get_header).
+/// * If `chd` is `NULL`, returns `CHDERR_INVALID_PARAMETER`.
+pub unsafe extern "C" fn chd_threadsafe_read(
+    chd: *const chd_threadsafe_file,
+    hunknum: u32,
+    buffer: *mut c_void,
+) -> chd_error {
+    ffi_with_threadsafe_chd(chd, |chd| unsafe { ffi_chd_read(chd, hunknum, buffer) })
+}
+
+#[no_mangle]
+#[cfg(feature = "chd_threadsafe")]
+#[cfg_attr(docsrs, doc(cfg(chd_threadsafe)))]
+/// Get indexed metadata of the given search tag and index from a CHD file that can be shared between threads.
+///
+/// # Safety
+/// * `chd` is either `NULL` or a valid pointer to a `chd_threadsafe_file` obtained from [`chd_open_threadsafe`](crate::chd_open_threadsafe) or [`chd_threadsafe_from_file`](crate::chd_threadsafe_from_file).
+/// * `output` must be an aligned pointer to a block of initialized memory of size exactly `output_len` that is valid for writes.
+/// * `result_len` must be either NULL or an aligned pointer to a `uint32_t` that is valid for writes.
+/// * `result_tag` must be either NULL or an aligned pointer to a `uint32_t` that is valid for writes.
+/// * `result_flags` must be either NULL or an aligned pointer to a `uint8_t` that is valid for writes.
+/// * If `chd` is `NULL`, returns `CHDERR_INVALID_PARAMETER`.
+pub unsafe extern "C" fn chd_threadsafe_get_metadata(
+    chd: *const chd_threadsafe_file,
+    searchtag: u32,
+    searchindex: u32,
+    output: *mut c_void,
+    output_len: u32,
+    result_len: *mut u32,
+    result_tag: *mut u32,
+    result_flags: *mut u8,
+) -> chd_error {
+    ffi_with_threadsafe_chd(chd, |chd| unsafe {
+        ffi_chd_get_metadata(
+            chd,
+            searchtag,
+            searchindex,
+            output,
+            output_len,
+            result_len,
+            result_tag,
+            result_flags,
+        )
+    })
+}
@@ -0,0 +1,31 @@
+//! Prints the size and hashes of the data track of a CD-ROM CHD file, as they would be listed in a DAT file.
+//!
+//! ```text
+//! cargo run --example data_track_hash -- game.chd
+//! ```
+use chd::cdrom::data_track_hash;
+use chd::Chd;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = match std::env::args_os().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("usage: data_track_hash <file.chd>");
+            std::process::exit(1);
+        }
+    };
+
+    let mut chd = Chd::open(BufReader::new(File::open(&path)?), None)?;
+    let hash = data_track_hash(&mut chd)?;
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    let sha1: String = hash.sha1.iter().map(|b| format!("{:02x}", b)).collect();
+
+    println!(
+        "<rom name=\"{} (Track {:02}).bin\" size=\"{}\" crc=\"{:08x}\" sha1=\"{}\"/>",
+        name, hash.track, hash.bytes, hash.crc32, sha1
+    );
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! Extracts the raw data of a CHD file, such as a hard disk image, as chdman's `extractraw` would.
+//!
+//! ```text
+//! cargo run --example extract_raw -- game.chd game.img [parent.chd]
+//! ```
+use chd::read::ChdReader;
+use chd::Chd;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args_os().skip(1).map(PathBuf::from);
+    let (input, output) = match (args.next(), args.next()) {
+        (Some(input), Some(output)) => (input, output),
+        _ => {
+            eprintln!("usage: extract_raw <file.chd> <output> [parent.chd]");
+            std::process::exit(1);
+        }
+    };
+    let parent = args.next();
+
+    let chd = Chd::open_path(&input, parent.as_deref())?;
+    // decompress 16 hunks at a time, so that hunks stored uncompressed are read sequentially.
+    let mut reader = ChdReader::with_read_ahead(chd, 16);
+    let mut out = BufWriter::new(File::create(&output)?);
+    let copied = reader.copy_range(0, None, &mut out)?;
+    out.flush()?;
+
+    println!("{} bytes written to {}", copied, output.display());
+    Ok(())
+}
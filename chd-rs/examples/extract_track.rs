@@ -0,0 +1,55 @@
+//! Extracts a single track of a CD-ROM or GD-ROM CHD file. Data tracks are written as raw sectors,
+//! and audio tracks as WAV files.
+//!
+//! ```text
+//! cargo run --example extract_track -- game.chd 2 track02.wav
+//! ```
+use chd::cdrom::{ExtractOptions, PregapHandling};
+use chd::Chd;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    let (input, number, output) = match &args[..] {
+        [input, number, output] => (
+            PathBuf::from(input),
+            number.to_string_lossy().parse::<u32>()?,
+            PathBuf::from(output),
+        ),
+        _ => {
+            eprintln!("usage: extract_track <file.chd> <track number> <output>");
+            std::process::exit(1);
+        }
+    };
+
+    let mut chd = Chd::open_path(&input, None)?;
+    let tracks = chd.tracks()?;
+    let track = match tracks.iter().find(|track| track.number() == number) {
+        Some(track) => track,
+        None => {
+            eprintln!("{} has {} tracks", input.display(), tracks.len());
+            std::process::exit(1);
+        }
+    };
+
+    let mut out = BufWriter::new(File::create(&output)?);
+    let bytes = if track.track_type().is_audio() {
+        // write the audio from index 1 of the track, without its pregap.
+        track.write_wav(&mut chd, PregapHandling::Skip, &mut out)?
+    } else {
+        let extracted = ExtractOptions::new().write_track(&mut chd, track, &mut out)?;
+        extracted.bytes_written
+    };
+    out.flush()?;
+
+    println!(
+        "track {:02} ({}): {} bytes written to {}",
+        track.number(),
+        track.track_type().as_str(),
+        bytes,
+        output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Reads a CHD file over HTTP without downloading all of it, by implementing `Read` and `Seek` with
+//! HTTP range requests.
+//!
+//! Only the header, the map, the metadata and the hunks that are read are downloaded. This example
+//! uses a minimal HTTP/1.1 client over plain TCP to avoid extra dependencies; a real application
+//! would use an HTTP client library with HTTPS and connection reuse.
+//!
+//! ```text
+//! cargo run --example http_stream -- http://example.com/game.chd
+//! ```
+use chd::Chd;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+
+/// A file served over HTTP, read with a range request for each read.
+struct HttpFile {
+    host: String,
+    path: String,
+    len: u64,
+    pos: u64,
+}
+
+impl HttpFile {
+    fn open(url: &str) -> std::io::Result<HttpFile> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, "only http:// URLs are supported")
+        })?;
+        let (host, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let mut file = HttpFile {
+            host: host.to_string(),
+            path: path.to_string(),
+            len: 0,
+            pos: 0,
+        };
+        let (headers, _) = file.request("HEAD", None)?;
+        file.len = header_value(&headers, "content-length")
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "no Content-Length"))?;
+        Ok(file)
+    }
+
+    /// Sends a request, and returns the response headers and body.
+    fn request(
+        &self,
+        method: &str,
+        range: Option<(u64, u64)>,
+    ) -> std::io::Result<(String, Vec<u8>)> {
+        let address = if self.host.contains(':') {
+            self.host.clone()
+        } else {
+            format!("{}:80", self.host)
+        };
+        let mut stream = TcpStream::connect(address)?;
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            method, self.path, self.host
+        )?;
+        if let Some((start, end)) = range {
+            write!(stream, "Range: bytes={}-{}\r\n", start, end)?;
+        }
+        write!(stream, "\r\n")?;
+
+        let mut response = BufReader::new(stream);
+        let mut headers = String::new();
+        loop {
+            let len = response.read_line(&mut headers)?;
+            if len <= 2 {
+                break;
+            }
+        }
+        let status = headers.split_whitespace().nth(1).unwrap_or_default();
+        if !status.starts_with('2') {
+            return Err(Error::other(format!("HTTP status {}", status)));
+        }
+        let mut body = Vec::new();
+        if method != "HEAD" {
+            response.read_to_end(&mut body)?;
+        }
+        Ok((headers, body))
+    }
+}
+
+fn header_value<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.eq_ignore_ascii_case(name) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+impl Read for HttpFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.len {
+            return Ok(0);
+        }
+        let end = (self.pos + buf.len() as u64).min(self.len) - 1;
+        let (_, body) = self.request("GET", Some((self.pos, end)))?;
+        let len = body.len().min(buf.len());
+        buf[..len].copy_from_slice(&body[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for HttpFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid seek"))?;
+        Ok(self.pos)
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let url = match std::env::args().nth(1) {
+        Some(url) => url,
+        None => {
+            eprintln!("usage: http_stream <http://host/file.chd>");
+            std::process::exit(1);
+        }
+    };
+
+    // buffer reads, so that small reads of the header and map do not each make a request.
+    let file = BufReader::with_capacity(256 * 1024, HttpFile::open(&url)?);
+    let mut chd = Chd::open(file, None)?;
+    println!("{}", chd.header());
+    for meta in chd.metadata_refs().try_into_vec()? {
+        println!("{}", meta);
+    }
+
+    let mut hunk_buf = chd.get_hunksized_buffer();
+    let mut cmp_buf = Vec::new();
+    chd.hunk(0)?.read_hunk_in(&mut cmp_buf, &mut hunk_buf)?;
+    println!("read hunk 0 ({} bytes)", hunk_buf.len());
+    Ok(())
+}
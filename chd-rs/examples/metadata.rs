@@ -0,0 +1,72 @@
+//! Lists the metadata of a CHD file, parsing the well-known entries into typed structs.
+//!
+//! ```text
+//! cargo run --example metadata -- game.chd
+//! ```
+use chd::metadata::parse::{AtaIdentify, CardInformation, Creator, HardDiskGeometry};
+use chd::metadata::{KnownMetadata, Metadata, MetadataTag};
+use chd::Chd;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = match std::env::args_os().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("usage: metadata <file.chd>");
+            std::process::exit(1);
+        }
+    };
+
+    let mut chd = Chd::open_path(&path, None)?;
+    // V1 and V2 files store the geometry of their hard disk in the header instead of in metadata.
+    let metadata: Vec<Metadata> = match chd.header().legacy_hard_disk_metadata() {
+        Some(metadata) => vec![metadata],
+        None => chd.metadata_refs().try_into_vec()?,
+    };
+
+    for meta in &metadata {
+        print!("{:?} #{}: ", meta.tag(), meta.index);
+        match meta.tag().as_bytes() {
+            b"GDDD" => {
+                let geometry = HardDiskGeometry::from_bytes(&meta.value)?;
+                println!(
+                    "hard disk, {} sectors of {} bytes",
+                    geometry.sector_count(),
+                    geometry.bytes_per_sector
+                );
+            }
+            b"IDNT" => {
+                let identify = AtaIdentify::from_bytes(&meta.value)?;
+                println!(
+                    "ATA identify, model {}, serial {}",
+                    identify.model(),
+                    identify.serial()
+                );
+            }
+            b"CIS " => {
+                let cis = CardInformation::from_bytes(&meta.value)?;
+                println!("PCMCIA card {:?}", cis.product_info().unwrap_or_default());
+            }
+            b"CRTR" => println!("created by {}", Creator::from_bytes(&meta.value)?),
+            _ if KnownMetadata::is_cdrom(meta.tag()) => println!("track metadata"),
+            _ if KnownMetadata::is_dvd(meta.tag()) => println!("DVD"),
+            _ => println!("{}", meta),
+        }
+    }
+
+    // track metadata is parsed for the whole disc at once.
+    if metadata
+        .iter()
+        .any(|meta| KnownMetadata::is_cdrom(meta.tag()))
+    {
+        for track in chd.tracks()? {
+            println!(
+                "track {:02}: {}, {} frames",
+                track.number(),
+                track.track_type().as_str(),
+                track.frames()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! Verifies the SHA1 hashes stored in the header of a CHD file against its data and metadata,
+//! as chdman's `verify` would.
+//!
+//! ```text
+//! cargo run --example verify --features write_api -- game.chd [parent.chd]
+//! ```
+use chd::write::overall_sha1;
+use chd::Chd;
+use sha1::{Digest, Sha1};
+use std::path::PathBuf;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args_os().skip(1).map(PathBuf::from);
+    let input = match args.next() {
+        Some(input) => input,
+        None => {
+            eprintln!("usage: verify <file.chd> [parent.chd]");
+            std::process::exit(1);
+        }
+    };
+    let parent = args.next();
+
+    let mut chd = Chd::open_path(&input, parent.as_deref())?;
+    let header = chd.header().clone();
+    let raw_sha1 = match header.raw_sha1().or_else(|| header.sha1()) {
+        Some(sha1) => sha1,
+        None => {
+            eprintln!("{} has no SHA1 to verify", input.display());
+            std::process::exit(1);
+        }
+    };
+
+    // the padding of the last hunk past the logical size is not hashed.
+    let mut hasher = Sha1::new();
+    let mut hunk_buf = chd.get_hunksized_buffer();
+    let mut cmp_buf = Vec::new();
+    let mut remaining = header.logical_bytes();
+    for hunk_num in 0..header.hunk_count() {
+        chd.hunk(hunk_num)?
+            .read_hunk_in(&mut cmp_buf, &mut hunk_buf)?;
+        let len = remaining.min(hunk_buf.len() as u64) as usize;
+        hasher.update(&hunk_buf[..len]);
+        remaining -= len as u64;
+    }
+    let actual: [u8; 20] = hasher.finalize().into();
+    let mut ok = actual == raw_sha1;
+    println!(
+        "raw SHA1:     {} ({})",
+        hex(&actual),
+        if ok { "ok" } else { "MISMATCH" }
+    );
+
+    // the overall SHA1 of V4 and V5 files also covers the checksummed metadata.
+    if let (Some(_), Some(sha1)) = (header.raw_sha1(), header.sha1()) {
+        let metadata = chd.metadata_refs().try_into_vec()?;
+        let overall = overall_sha1(&actual, &metadata);
+        let matches = overall == sha1;
+        ok &= matches;
+        println!(
+            "overall SHA1: {} ({})",
+            hex(&overall),
+            if matches { "ok" } else { "MISMATCH" }
+        );
+    }
+
+    if !ok {
+        std::process::exit(2);
+    }
+    Ok(())
+}
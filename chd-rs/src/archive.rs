@@ -0,0 +1,253 @@
+//! Reading CHD files stored inside ZIP archives.
+//!
+//! [`ZipEntryReader`](crate::archive::ZipEntryReader) adapts an entry of a ZIP archive into a
+//! `Read + Seek` stream that a CHD file can be opened from. Entries that are stored without
+//! compression are read directly from the archive with random access. Compressed entries can not
+//! be randomly accessed, and are instead extracted in full into an in-memory spill buffer when opened.
+//!
+//! CHD files are usually stored without compression, since their contents are already compressed.
+//! 7z archives are not supported.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::ChdOpenOptions;
+//!
+//! let archive = BufReader::new(File::open("game.zip")?);
+//! // open the first CHD file in the archive.
+//! let mut chd = ChdOpenOptions::new().open_zip(archive, None)?;
+//! println!("{}", chd.header().hunk_count());
+//! ```
+use crate::error::{Error, Result};
+use crate::{Chd, ChdOpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use zip::{CompressionMethod, ZipArchive};
+
+/// A `Read + Seek` stream over the contents of an entry in a ZIP archive.
+pub struct ZipEntryReader<R: Read + Seek> {
+    inner: ZipEntryInner<R>,
+}
+
+enum ZipEntryInner<R: Read + Seek> {
+    Stored {
+        archive: R,
+        start: u64,
+        len: u64,
+        pos: u64,
+    },
+    Extracted(Cursor<Vec<u8>>),
+}
+
+impl<R: Read + Seek> ZipEntryReader<R> {
+    /// Opens the entry with the given name in the ZIP archive.
+    ///
+    /// If the entry is compressed, it is extracted into memory before this returns.
+    /// If there is no entry with the given name, returns [`Error::FileNotFound`](crate::Error::FileNotFound).
+    pub fn new(archive: R, name: &str) -> Result<Self> {
+        let mut archive = ZipArchive::new(archive)?;
+        let index = entry_names(&mut archive)?
+            .iter()
+            .position(|entry_name| entry_name == name)
+            .ok_or(Error::FileNotFound)?;
+        ZipEntryReader::from_index(archive, index)
+    }
+
+    /// Opens the first entry in the ZIP archive with a `.chd` extension.
+    ///
+    /// If the entry is compressed, it is extracted into memory before this returns.
+    /// If there is no CHD file in the archive, returns [`Error::FileNotFound`](crate::Error::FileNotFound).
+    pub fn first_chd(archive: R) -> Result<Self> {
+        let mut archive = ZipArchive::new(archive)?;
+        let index = entry_names(&mut archive)?
+            .iter()
+            .position(|entry_name| is_chd_name(entry_name))
+            .ok_or(Error::FileNotFound)?;
+        ZipEntryReader::from_index(archive, index)
+    }
+
+    fn from_index(mut archive: ZipArchive<R>, index: usize) -> Result<Self> {
+        let (compression, start, len) = {
+            let entry = archive.by_index_raw(index)?;
+            (entry.compression(), entry.data_start(), entry.size())
+        };
+
+        if compression == CompressionMethod::Stored {
+            return Ok(ZipEntryReader {
+                inner: ZipEntryInner::Stored {
+                    archive: archive.into_inner(),
+                    start,
+                    len,
+                    pos: 0,
+                },
+            });
+        }
+
+        let mut entry = archive.by_index(index)?;
+        let len = usize::try_from(entry.size()).map_err(|_| Error::OutOfMemory)?;
+        let mut buf = Vec::new();
+        buf.try_reserve_exact(len).map_err(|_| Error::OutOfMemory)?;
+        entry.read_to_end(&mut buf)?;
+        Ok(ZipEntryReader {
+            inner: ZipEntryInner::Extracted(Cursor::new(buf)),
+        })
+    }
+
+    /// Returns whether the entry is stored without compression and is read directly from the archive.
+    pub fn is_stored(&self) -> bool {
+        matches!(self.inner, ZipEntryInner::Stored { .. })
+    }
+
+    /// Returns the uncompressed length of the entry.
+    pub fn len(&self) -> u64 {
+        match &self.inner {
+            ZipEntryInner::Stored { len, .. } => *len,
+            ZipEntryInner::Extracted(cursor) => cursor.get_ref().len() as u64,
+        }
+    }
+
+    /// Returns whether the entry is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Returns the names of the entries in the ZIP archive with a `.chd` extension, in archive order.
+pub fn chd_entries<R: Read + Seek>(archive: R) -> Result<Vec<String>> {
+    let mut archive = ZipArchive::new(archive)?;
+    let mut names = entry_names(&mut archive)?;
+    names.retain(|name| is_chd_name(name));
+    Ok(names)
+}
+
+/// Returns the names of all entries in the ZIP archive, in archive order.
+fn entry_names<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<String>> {
+    (0..archive.len())
+        .map(|i| Ok(archive.by_index_raw(i)?.name().to_string()))
+        .collect()
+}
+
+fn is_chd_name(name: &str) -> bool {
+    name.len() > 4 && name.as_bytes()[name.len() - 4..].eq_ignore_ascii_case(b".chd")
+}
+
+impl<R: Read + Seek> Read for ZipEntryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            ZipEntryInner::Stored {
+                archive,
+                start,
+                len,
+                pos,
+            } => {
+                if *pos >= *len {
+                    return Ok(0);
+                }
+                let remaining = (*len - *pos).min(buf.len() as u64) as usize;
+                archive.seek(SeekFrom::Start(*start + *pos))?;
+                let read = archive.read(&mut buf[..remaining])?;
+                *pos += read as u64;
+                Ok(read)
+            }
+            ZipEntryInner::Extracted(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for ZipEntryReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match &mut self.inner {
+            ZipEntryInner::Stored { len, pos: curr, .. } => {
+                let new_pos = match pos {
+                    SeekFrom::Start(off) => Some(off),
+                    SeekFrom::End(off) => len.checked_add_signed(off),
+                    SeekFrom::Current(off) => curr.checked_add_signed(off),
+                };
+                match new_pos {
+                    Some(new_pos) => {
+                        *curr = new_pos;
+                        Ok(new_pos)
+                    }
+                    None => Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "invalid seek to a negative or overflowing position",
+                    )),
+                }
+            }
+            ZipEntryInner::Extracted(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+impl<R: Read + Seek, P: Read + Seek> ChdOpenOptions<ZipEntryReader<R>, P> {
+    /// Opens a CHD file stored in a ZIP archive with the options in `self`.
+    ///
+    /// If `name` is `None`, the first entry in the archive with a `.chd` extension is opened.
+    /// See [`ZipEntryReader`](crate::archive::ZipEntryReader) for how entries are read.
+    pub fn open_zip(self, archive: R, name: Option<&str>) -> Result<Chd<ZipEntryReader<R>, P>> {
+        let entry = match name {
+            Some(name) => ZipEntryReader::new(archive, name)?,
+            None => ZipEntryReader::first_chd(archive)?,
+        };
+        self.open(entry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::archive::{chd_entries, ZipEntryReader};
+    use crate::Error;
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    fn zip_with(entries: &[(&str, CompressionMethod, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, method, data) in entries {
+            writer
+                .start_file(*name, FileOptions::default().compression_method(*method))
+                .expect("start file");
+            writer.write_all(data).expect("write file");
+        }
+        let mut archive = writer.finish().expect("finish");
+        archive.rewind().expect("rewind");
+        archive
+    }
+
+    #[test]
+    fn zip_entry_reader_test() {
+        let data: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        let archive = zip_with(&[
+            ("readme.txt", CompressionMethod::Deflated, b"hello"),
+            ("stored.chd", CompressionMethod::Stored, &data),
+            ("deflated.CHD", CompressionMethod::Deflated, &data),
+        ]);
+        assert_eq!(
+            vec!["stored.chd", "deflated.CHD"],
+            chd_entries(archive.clone()).expect("entries")
+        );
+
+        for (name, stored) in [("stored.chd", true), ("deflated.CHD", false)] {
+            let mut entry = ZipEntryReader::new(archive.clone(), name).expect("entry");
+            assert_eq!(stored, entry.is_stored());
+            assert_eq!(4096, entry.len());
+
+            let mut buf = [0u8; 16];
+            entry.seek(SeekFrom::End(-16)).expect("seek");
+            entry.read_exact(&mut buf).expect("read");
+            assert_eq!(&data[4080..], &buf);
+            entry.seek(SeekFrom::Start(300)).expect("seek");
+            entry.read_exact(&mut buf).expect("read");
+            assert_eq!(&data[300..316], &buf);
+            entry.seek(SeekFrom::End(0)).expect("seek");
+            assert_eq!(0, entry.read(&mut buf).expect("read"));
+        }
+
+        assert!(ZipEntryReader::first_chd(archive.clone())
+            .expect("first")
+            .is_stored());
+        assert!(matches!(
+            ZipEntryReader::new(archive, "missing.chd"),
+            Err(Error::FileNotFound)
+        ));
+    }
+}
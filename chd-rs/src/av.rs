@@ -0,0 +1,371 @@
+//! Types and methods relating to A/V (LaserDisc) data stored in a CHD file.
+//!
+//! Hunks of A/V CHD files compressed with AV Huffman decompress into MAME's internal A/V frame
+//! layout, which consists of a 12-byte header, followed by the frame metadata, the audio samples of
+//! each channel as big-endian 16-bit signed integers, and the raw video data.
+//!
+//! [`AvFrame`](crate::av::AvFrame) parses this layout into its typed components. The raw layout
+//! remains available through [`Hunk::read_hunk_in`](crate::Hunk::read_hunk_in).
+//!
+//! The frame metadata of LaserDisc CHD files contains the VBI data of each field of the frame,
+//! which can be parsed with [`AvFrame::vbi`](crate::av::AvFrame::vbi) to find the frame numbers,
+//! chapters and lead-in and lead-out codes of the disc without re-implementing MAME's `vbiparse`.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::av::AvFrame;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! let mut hunk_buf = chd.get_hunksized_buffer();
+//! let mut cmp_buf = Vec::new();
+//!
+//! let mut hunk = chd.hunk(0)?;
+//! let frame = AvFrame::read(&mut hunk, &mut cmp_buf, &mut hunk_buf)?;
+//! println!("{}x{} with {} audio channels", frame.width, frame.height, frame.audio.len());
+//! ```
+use crate::error::{Error, Result};
+use crate::{Chd, Hunk};
+use byteorder::{BigEndian, ByteOrder};
+use std::io::{Read, Seek};
+
+const AV_FRAME_MAGIC: &[u8; 4] = b"chav";
+const AV_FRAME_HEADER_LEN: usize = 12;
+const VBI_PACKED_BYTES: usize = 16;
+
+const VBI_CODE_LEADIN: u32 = 0x88ffff;
+const VBI_CODE_LEADOUT: u32 = 0x80eeee;
+const VBI_CODE_STOP: u32 = 0x82cfff;
+const VBI_CODE_CLV: u32 = 0x87ffff;
+const VBI_MASK_CAV_PICTURE: u32 = 0xf00000;
+const VBI_CODE_CAV_PICTURE: u32 = 0xf00000;
+const VBI_MASK_CHAPTER: u32 = 0xf00fff;
+const VBI_CODE_CHAPTER: u32 = 0x800ddd;
+const VBI_MASK_CLV_TIME: u32 = 0xf0ff00;
+const VBI_CODE_CLV_TIME: u32 = 0xf0dd00;
+
+/// The VBI data of a single field of a LaserDisc frame, as packed by MAME's `vbi_metadata_pack`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct VbiData {
+    /// The index of the frame this field belongs to in the CHD file.
+    pub frame: u32,
+    /// Whether the white flag of line 11 is set, which marks the first field of a new film frame.
+    pub white: bool,
+    /// The 24-bit Philips code of line 16.
+    pub line16: u32,
+    /// The 24-bit Philips code of line 17.
+    pub line17: u32,
+    /// The 24-bit Philips code of line 18.
+    pub line18: u32,
+    /// The most reliable Philips code of lines 17 and 18.
+    pub line1718: u32,
+}
+
+/// The meaning of a 24-bit Philips code read from the VBI data of a LaserDisc field.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VbiCode {
+    /// The field is in the lead-in area of the disc.
+    LeadIn,
+    /// The field is in the lead-out area of the disc.
+    LeadOut,
+    /// The player should stop on this field.
+    Stop,
+    /// The disc is a CLV disc.
+    Clv,
+    /// The picture number of the field on a CAV disc.
+    CavPicture(u32),
+    /// The chapter number of the field.
+    Chapter(u8),
+    /// The time code of the field on a CLV disc.
+    ClvTime {
+        /// The hours of the time code.
+        hours: u8,
+        /// The minutes of the time code.
+        minutes: u8,
+    },
+    /// A code that is empty or not recognized.
+    Unknown(u32),
+}
+
+/// Decodes a binary-coded decimal value.
+fn bcd(value: u32, digits: u32) -> u32 {
+    (0..digits)
+        .rev()
+        .fold(0, |acc, digit| acc * 10 + ((value >> (digit * 4)) & 0x0f))
+}
+
+impl VbiCode {
+    /// Decodes a 24-bit Philips code.
+    pub fn from_code(code: u32) -> VbiCode {
+        match code {
+            VBI_CODE_LEADIN => VbiCode::LeadIn,
+            VBI_CODE_LEADOUT => VbiCode::LeadOut,
+            VBI_CODE_STOP => VbiCode::Stop,
+            VBI_CODE_CLV => VbiCode::Clv,
+            // CLV time codes are not valid BCD picture numbers.
+            _ if code & VBI_MASK_CLV_TIME == VBI_CODE_CLV_TIME => VbiCode::ClvTime {
+                hours: ((code >> 16) & 0x0f) as u8,
+                minutes: bcd(code & 0xff, 2) as u8,
+            },
+            _ if code & VBI_MASK_CAV_PICTURE == VBI_CODE_CAV_PICTURE => {
+                // the top digit of the picture number only has 3 bits.
+                VbiCode::CavPicture(bcd(code & 0x07ffff, 5))
+            }
+            _ if code & VBI_MASK_CHAPTER == VBI_CODE_CHAPTER => {
+                VbiCode::Chapter(bcd((code >> 12) & 0x7f, 2) as u8)
+            }
+            _ => VbiCode::Unknown(code),
+        }
+    }
+}
+
+impl VbiData {
+    /// Parses the VBI data of a single field from its 16-byte packed form.
+    ///
+    /// If the buffer is shorter than 16 bytes, returns
+    /// [`Error::InvalidData`](crate::Error::InvalidData).
+    pub fn from_bytes(buf: &[u8]) -> Result<VbiData> {
+        if buf.len() < VBI_PACKED_BYTES {
+            return Err(Error::InvalidData);
+        }
+        Ok(VbiData {
+            frame: BigEndian::read_u24(&buf[0..3]),
+            white: buf[3] != 0,
+            line16: BigEndian::read_u24(&buf[4..7]),
+            line17: BigEndian::read_u24(&buf[7..10]),
+            line18: BigEndian::read_u24(&buf[10..13]),
+            line1718: BigEndian::read_u24(&buf[13..16]),
+        })
+    }
+
+    /// Parses the VBI data of consecutive fields, such as the frame metadata of a LaserDisc
+    /// A/V frame, or the contents of `AVLD` metadata.
+    ///
+    /// If the length of the buffer is not a multiple of 16 bytes, returns
+    /// [`Error::InvalidData`](crate::Error::InvalidData).
+    pub fn from_fields(buf: &[u8]) -> Result<Vec<VbiData>> {
+        if !buf.len().is_multiple_of(VBI_PACKED_BYTES) {
+            return Err(Error::InvalidData);
+        }
+        buf.chunks_exact(VBI_PACKED_BYTES)
+            .map(VbiData::from_bytes)
+            .collect()
+    }
+
+    /// Decodes the code of lines 17 and 18, which holds the picture number, chapter or
+    /// time code of the field.
+    pub fn code(&self) -> VbiCode {
+        VbiCode::from_code(self.line1718)
+    }
+
+    /// Gets the picture number of this field on a CAV disc, if the field contains one.
+    pub fn picture_number(&self) -> Option<u32> {
+        match self.code() {
+            VbiCode::CavPicture(picture) => Some(picture),
+            _ => None,
+        }
+    }
+
+    /// Gets the chapter number of this field, if the field contains one.
+    ///
+    /// Chapter numbers are stored on line 16 of CAV discs, and on lines 17 and 18 otherwise.
+    pub fn chapter(&self) -> Option<u8> {
+        [self.line1718, self.line16]
+            .into_iter()
+            .find_map(|code| match VbiCode::from_code(code) {
+                VbiCode::Chapter(chapter) => Some(chapter),
+                _ => None,
+            })
+    }
+}
+
+/// A decoded A/V frame.
+#[derive(Debug, Clone)]
+pub struct AvFrame {
+    /// The metadata of this frame, typically containing VBI data.
+    pub metadata: Vec<u8>,
+    /// The raw video data of this frame, with 2 bytes per pixel in YUY2 format.
+    pub video: Vec<u8>,
+    /// The audio samples of each channel of this frame.
+    pub audio: Vec<Vec<i16>>,
+    /// The width of the video data in pixels.
+    pub width: u16,
+    /// The height of the video data in pixels.
+    pub height: u16,
+}
+
+impl AvFrame {
+    /// Parses an A/V frame from a decompressed A/V hunk.
+    ///
+    /// If the buffer does not contain a valid A/V frame, returns
+    /// [`Error::InvalidData`](crate::Error::InvalidData).
+    pub fn from_bytes(buf: &[u8]) -> Result<AvFrame> {
+        if buf.len() < AV_FRAME_HEADER_LEN || &buf[0..4] != AV_FRAME_MAGIC {
+            return Err(Error::InvalidData);
+        }
+
+        let meta_size = buf[4] as usize;
+        let channels = buf[5] as usize;
+        let samples = BigEndian::read_u16(&buf[6..8]) as usize;
+        let width = BigEndian::read_u16(&buf[8..10]);
+        let height = BigEndian::read_u16(&buf[10..12]);
+
+        let audio_len = channels * samples * 2;
+        let video_len = width as usize * height as usize * 2;
+        let rest = &buf[AV_FRAME_HEADER_LEN..];
+        if rest.len() < meta_size + audio_len + video_len {
+            return Err(Error::InvalidData);
+        }
+
+        let (metadata, rest) = rest.split_at(meta_size);
+        let (audio, rest) = rest.split_at(audio_len);
+        let video = &rest[..video_len];
+
+        let audio = if samples == 0 {
+            vec![Vec::new(); channels]
+        } else {
+            audio
+                .chunks_exact(samples * 2)
+                .map(|channel| {
+                    let mut pcm = vec![0i16; samples];
+                    BigEndian::read_i16_into(channel, &mut pcm);
+                    pcm
+                })
+                .collect()
+        };
+
+        Ok(AvFrame {
+            metadata: metadata.to_vec(),
+            video: video.to_vec(),
+            audio,
+            width,
+            height,
+        })
+    }
+
+    /// Decompresses the given hunk and parses it as an A/V frame, using the provided temporary
+    /// buffers. The size of `hunk_buf` must be equal to the hunk size of the CHD file.
+    ///
+    /// See [`Hunk::read_hunk_in`](crate::Hunk::read_hunk_in) for the errors that may be
+    /// returned when decompressing the hunk.
+    pub fn read<F: Read + Seek, P: Read + Seek>(
+        hunk: &mut Hunk<F, P>,
+        cmp_buf: &mut Vec<u8>,
+        hunk_buf: &mut [u8],
+    ) -> Result<AvFrame> {
+        hunk.read_hunk_in(cmp_buf, hunk_buf)?;
+        AvFrame::from_bytes(hunk_buf)
+    }
+
+    /// Parses the frame metadata as the VBI data of each field of a LaserDisc frame.
+    ///
+    /// If the frame metadata is not VBI data, returns
+    /// [`Error::InvalidData`](crate::Error::InvalidData).
+    pub fn vbi(&self) -> Result<Vec<VbiData>> {
+        VbiData::from_fields(&self.metadata)
+    }
+}
+
+/// Reads the frames of an A/V CHD file, where each hunk holds one frame.
+pub struct AvReader<F: Read + Seek, P: Read + Seek = F> {
+    chd: Chd<F, P>,
+    cmp_buf: Vec<u8>,
+    hunk_buf: Vec<u8>,
+}
+
+impl<F: Read + Seek, P: Read + Seek> AvReader<F, P> {
+    /// Creates a new `AvReader` from an opened [`Chd`](crate::Chd).
+    pub fn new(chd: Chd<F, P>) -> Self {
+        let hunk_buf = chd.get_hunksized_buffer();
+        AvReader {
+            chd,
+            cmp_buf: Vec::new(),
+            hunk_buf,
+        }
+    }
+
+    /// Returns the number of frames in the CHD file.
+    pub fn frame_count(&self) -> u32 {
+        self.chd.header().hunk_count()
+    }
+
+    /// Decompresses and parses the given frame.
+    ///
+    /// If the frame is past the end of the CHD file, returns
+    /// [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange).
+    pub fn read_frame(&mut self, frame: u32) -> Result<AvFrame> {
+        let mut hunk = self.chd.hunk(frame)?;
+        AvFrame::read(&mut hunk, &mut self.cmp_buf, &mut self.hunk_buf)
+    }
+
+    /// Consumes the reader and returns the underlying [`Chd`](crate::Chd).
+    pub fn into_inner(self) -> Chd<F, P> {
+        self.chd
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::av::{AvFrame, VbiCode, VbiData};
+    use crate::Error;
+
+    #[test]
+    fn av_frame_from_bytes_test() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"chav");
+        buf.extend_from_slice(&[1, 2, 0, 2, 0, 1, 0, 1]);
+        buf.push(0xaa);
+        buf.extend_from_slice(&[0x00, 0x01, 0xff, 0xff, 0x00, 0x02, 0x00, 0x03]);
+        buf.extend_from_slice(&[0x10, 0x80]);
+        let frame = AvFrame::from_bytes(&buf).expect("frame");
+        assert_eq!(vec![0xaa], frame.metadata);
+        assert_eq!(vec![vec![1, -1], vec![2, 3]], frame.audio);
+        assert_eq!(vec![0x10, 0x80], frame.video);
+    }
+
+    #[test]
+    fn vbi_test() {
+        let mut metadata = Vec::new();
+        // frame 7, white flag, chapter 12 on line 16 and CAV picture 54321.
+        metadata.extend_from_slice(&[0, 0, 7, 1]);
+        metadata.extend_from_slice(&[0x81, 0x2d, 0xdd]);
+        metadata.extend_from_slice(&[0xf5, 0x43, 0x21]);
+        metadata.extend_from_slice(&[0xf5, 0x43, 0x21]);
+        metadata.extend_from_slice(&[0xf5, 0x43, 0x21]);
+        // second field in the lead-out.
+        metadata.extend_from_slice(&[0, 0, 7, 0, 0, 0, 0]);
+        metadata.extend_from_slice(&[0x80, 0xee, 0xee, 0x80, 0xee, 0xee, 0x80, 0xee, 0xee]);
+
+        let frame = AvFrame {
+            metadata,
+            video: Vec::new(),
+            audio: Vec::new(),
+            width: 0,
+            height: 0,
+        };
+        let fields = frame.vbi().expect("vbi");
+        assert_eq!(2, fields.len());
+        assert_eq!(7, fields[0].frame);
+        assert!(fields[0].white);
+        assert_eq!(Some(54321), fields[0].picture_number());
+        assert_eq!(Some(12), fields[0].chapter());
+        assert!(!fields[1].white);
+        assert_eq!(VbiCode::LeadOut, fields[1].code());
+        assert_eq!(None, fields[1].picture_number());
+
+        assert_eq!(VbiCode::LeadIn, VbiCode::from_code(0x88ffff));
+        assert_eq!(
+            VbiCode::ClvTime {
+                hours: 1,
+                minutes: 59
+            },
+            VbiCode::from_code(0xf1dd59)
+        );
+        assert!(matches!(
+            VbiData::from_fields(&[0; 17]),
+            Err(Error::InvalidData)
+        ));
+    }
+}
@@ -1,4 +1,9 @@
-/// CD ROM ECC calculation helpers.
+//! CD-ROM ECC calculation helpers.
+//!
+//! Mode 1 and Mode 2 Form 1 sectors carry Reed-Solomon product code (RSPC) P and Q parity bytes
+//! after their EDC, which lets drives correct errors in the sector. These functions operate on
+//! raw sectors of [`CD_MAX_SECTOR_DATA`](crate::cdrom::CD_MAX_SECTOR_DATA) bytes, including the
+//! sync pattern and header.
 use crate::cdrom::{CD_MAX_SECTOR_DATA, CD_MODE_OFFSET, CD_SYNC_NUM_BYTES, CD_SYNC_OFFSET};
 
 const ECC_P_OFFSET: usize = 0x81c;
@@ -736,63 +741,52 @@ fn ecc_compute_bytes<const ROWLEN: usize>(
     (val1, val2)
 }
 
-/// A CD sector that contains ECC P and Q codes.
-///
-/// This trait is only implemented for `&mut [u8; CD_MAX_SECTOR_DATA as usize]`.
-pub(crate) trait ErrorCorrectedSector {
-    /// Reset the ECC P and Q codes to 0 within a sector
-    fn clear_ecc(&mut self);
-
-    /// Write ECC codes into the sector.
-    fn generate_ecc(&mut self);
-
-    /// Verify the ECC P and Q codes to 0 within a sector
-    fn verify_ecc(&self) -> bool;
+/// Resets the ECC P and Q parity bytes of a raw sector to 0.
+pub fn clear_ecc(sector: &mut [u8; CD_MAX_SECTOR_DATA as usize]) {
+    sector[ECC_P_OFFSET..][..2 * ECC_P_NUM_BYTES].fill(0);
+    sector[ECC_Q_OFFSET..][..2 * ECC_Q_NUM_BYTES].fill(0);
 }
 
-impl ErrorCorrectedSector for &mut [u8; CD_MAX_SECTOR_DATA as usize] {
-    fn clear_ecc(&mut self) {
-        self[ECC_P_OFFSET..][..2 * ECC_P_NUM_BYTES].fill(0);
-        self[ECC_Q_OFFSET..][..2 * ECC_Q_NUM_BYTES].fill(0);
+/// Writes the ECC P and Q parity bytes of a raw Mode 1 or Mode 2 Form 1 sector computed from
+/// its contents.
+#[inline(always)]
+pub fn generate_ecc(sector: &mut [u8; CD_MAX_SECTOR_DATA as usize]) {
+    // write P bytes
+    for (idx, row) in ECC_P_OFF.iter().enumerate() {
+        let (val1, val2) = ecc_compute_bytes(sector, row);
+        sector[ECC_P_OFFSET + idx] = val1;
+        sector[ECC_P_OFFSET + ECC_P_NUM_BYTES + idx] = val2;
     }
 
-    #[inline(always)]
-    fn generate_ecc(&mut self) {
-        // write P bytes
-        for (idx, row) in ECC_P_OFF.iter().enumerate() {
-            let (val1, val2) = ecc_compute_bytes(self, row);
-            self[ECC_P_OFFSET + idx] = val1;
-            self[ECC_P_OFFSET + ECC_P_NUM_BYTES + idx] = val2;
-        }
-
-        // verify Q bytes
-        for (idx, row) in ECC_Q_OFF.iter().enumerate() {
-            let (val1, val2) = ecc_compute_bytes(self, row);
-            self[ECC_Q_OFFSET + idx] = val1;
-            self[ECC_Q_OFFSET + ECC_Q_NUM_BYTES + idx] = val2;
-        }
+    // write Q bytes
+    for (idx, row) in ECC_Q_OFF.iter().enumerate() {
+        let (val1, val2) = ecc_compute_bytes(sector, row);
+        sector[ECC_Q_OFFSET + idx] = val1;
+        sector[ECC_Q_OFFSET + ECC_Q_NUM_BYTES + idx] = val2;
     }
+}
 
-    fn verify_ecc(&self) -> bool {
-        // verify P bytes
-        for (idx, row) in ECC_P_OFF.iter().enumerate() {
-            let (val1, val2) = ecc_compute_bytes(self, row);
-            if self[ECC_P_OFFSET + idx] != val1
-                || self[ECC_P_OFFSET + ECC_P_NUM_BYTES + idx] != val2
-            {
-                return false;
-            }
+/// Returns whether the ECC P and Q parity bytes of a raw Mode 1 or Mode 2 Form 1 sector match
+/// its contents.
+pub fn verify_ecc(sector: &[u8; CD_MAX_SECTOR_DATA as usize]) -> bool {
+    // verify P bytes
+    for (idx, row) in ECC_P_OFF.iter().enumerate() {
+        let (val1, val2) = ecc_compute_bytes(sector, row);
+        if sector[ECC_P_OFFSET + idx] != val1
+            || sector[ECC_P_OFFSET + ECC_P_NUM_BYTES + idx] != val2
+        {
+            return false;
         }
+    }
 
-        // verify Q bytes
-        for (idx, row) in ECC_Q_OFF.iter().enumerate() {
-            let (val1, val2) = ecc_compute_bytes(self, row);
-            if self[ECC_Q_OFFSET + idx] != val1
-                || self[ECC_Q_OFFSET + ECC_Q_NUM_BYTES + idx] != val2
-            {
-                return false;
-            }
+    // verify Q bytes
+    for (idx, row) in ECC_Q_OFF.iter().enumerate() {
+        let (val1, val2) = ecc_compute_bytes(sector, row);
+        if sector[ECC_Q_OFFSET + idx] != val1
+            || sector[ECC_Q_OFFSET + ECC_Q_NUM_BYTES + idx] != val2
+        {
+            return false;
         }
-        true
     }
+    true
 }
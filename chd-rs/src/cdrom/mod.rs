@@ -0,0 +1,2062 @@
+//! Types and methods relating to CD-ROM and GD-ROM data stored in a CHD file.
+//!
+//! CD-ROM CHD files store each track as a sequence of 2448 byte frames, consisting of up to 2352 bytes of sector data followed by 96 bytes of subcode data.
+//! The layout of the tracks is described by the CD-ROM metadata entries of the CHD file, which
+//! can be parsed with [`Chd::tracks`](crate::Chd::tracks).
+//!
+//! ## Extracting tracks
+//! Each [`Track`](crate::cdrom::Track) can be read as its own `Read` stream with
+//! [`Track::reader`](crate::cdrom::Track::reader).
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::cdrom::PregapHandling;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//!
+//! for track in chd.tracks()? {
+//!     let mut out = File::create(format!("track{:02}.bin", track.number()))?;
+//!     let mut reader = track.reader(&mut chd, PregapHandling::Include);
+//!     std::io::copy(&mut reader, &mut out)?;
+//! }
+//! ```
+//!
+//! Emulators that read tracks a sector at a time can use [`Track::sector_reader`](crate::cdrom::Track::sector_reader)
+//! instead, which returns a `Read + Seek` stream of sectors of 2352 or 2048 bytes starting at the
+//! first sector of the track after its pregap.
+//! ```rust
+//! use std::fs::File;
+//! use std::io::{BufReader, Read, Seek, SeekFrom};
+//! use chd::Chd;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! let track = chd.tracks()?.remove(0);
+//!
+//! let mut reader = track.sector_reader(&mut chd, 2048)?;
+//! let mut sector = [0u8; 2048];
+//! reader.seek(SeekFrom::Start(16 * 2048))?;
+//! reader.read_exact(&mut sector)?;
+//! ```
+//!
+//! Audio tracks can be written as WAV files with [`Track::write_wav`](crate::cdrom::Track::write_wav),
+//! which takes care of swapping the big-endian samples stored in the CHD file to little-endian.
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::cdrom::PregapHandling;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//!
+//! for track in chd.tracks()?.iter().filter(|t| t.track_type().is_audio()) {
+//!     let out = File::create(format!("track{:02}.wav", track.number()))?;
+//!     track.write_wav(&mut chd, PregapHandling::Skip, out)?;
+//! }
+//! ```
+//!
+//! ## Hashing tracks
+//! The size, CRC32 and SHA1 of the data track of a disc, as listed in DAT files, can be found with
+//! [`data_track_hash`](crate::cdrom::data_track_hash). Other tracks can be hashed as they would be
+//! extracted with [`ExtractOptions::hash_track`](crate::cdrom::ExtractOptions::hash_track).
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::cdrom::data_track_hash;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! let hash = data_track_hash(&mut chd)?;
+//! println!("track {:02}: {} bytes, crc32 {:08x}", hash.track, hash.bytes, hash.crc32);
+//! ```
+//!
+//! ## Verifying sectors
+//! The EDC and ECC P and Q parity of the sectors of `MODE1_RAW` and `MODE2_RAW` tracks can be
+//! checked with [`verify_sectors`](crate::cdrom::verify_sectors), which reports the logical block
+//! address of each sector that fails verification. The ECC of a single raw sector can be generated
+//! and checked with the functions of the [`ecc`](crate::cdrom::ecc) module.
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::cdrom::verify_sectors;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! for error in verify_sectors(&mut chd)? {
+//!     println!("track {:02}: bad sector at LBA {}", error.track, error.lba);
+//! }
+//! ```
+//!
+//! ## Pregaps and postgaps
+//! The pregap of a track (index 0) precedes the start of the track (index 1), and may or may not be
+//! stored in the CHD file, as indicated by [`Track::pregap_in_image`](crate::cdrom::Track::pregap_in_image).
+//! Pregaps that are not stored in the CHD file, and postgaps, which are never stored in the CHD file,
+//! still occupy logical block addresses. [`ExtractOptions`](crate::cdrom::ExtractOptions) extracts tracks
+//! into BIN files described by a CUE sheet, either as a single combined file or split into one file per
+//! track, and marks the pregaps and postgaps of each track accordingly.
+//!
+//! ## Disc image sources
+//! CUE sheets, GDI files and cdrdao TOC files describing disc images can be parsed with
+//! [`source::DiscSource`](crate::cdrom::source::DiscSource), and converted to the tracks they would
+//! have in a CD-ROM CHD file.
+use crate::error::{Error, Result};
+use crate::metadata::{KnownMetadata, MetadataTag};
+use crate::Chd;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use crc::{Crc, Digest as CrcDigest, CRC_32_CD_ROM_EDC, CRC_32_ISO_HDLC};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use sha1::{Digest, Sha1};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+pub mod ecc;
+pub mod source;
+
+/// The number of padding frames between the tracks of a CD-ROM CHD file.
+///
+/// Each track is padded to a multiple of this many frames.
+pub const CD_TRACK_PADDING: u32 = 4;
+/// The maximum number of tracks on a CD-ROM.
+pub const CD_MAX_TRACKS: u32 = 99;
+
+/// The number of bytes of sector data in a frame.
+pub const CD_MAX_SECTOR_DATA: u32 = 2352;
+/// The number of bytes of subcode data in a frame.
+pub const CD_MAX_SUBCODE_DATA: u32 = 96;
+/// The number of bytes in a frame, which is sector data followed by subcode data.
+pub const CD_FRAME_SIZE: u32 = CD_MAX_SECTOR_DATA + CD_MAX_SUBCODE_DATA;
+/// The number of frames in each hunk of CD-ROM CHD files created by chdman.
+///
+/// Other hunk sizes are valid as long as they are a multiple of [`CD_FRAME_SIZE`](crate::cdrom::CD_FRAME_SIZE).
+/// Use [`frames_per_hunk`](crate::cdrom::frames_per_hunk) to find the number of frames in a hunk of a given CHD file.
+pub const CD_FRAMES_PER_HUNK: u32 = 8;
+pub(crate) const CD_SYNC_NUM_BYTES: usize = 12;
+/// The number of bytes of user data in a Mode 1 or Mode 2 Form 1 sector.
+pub const CD_USER_DATA: u32 = 2048;
+
+const CD_EDC: Crc<u32> = Crc::<u32>::new(&CRC_32_CD_ROM_EDC);
+static TRACK_CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// The sync pattern at the start of every raw data sector.
+pub const CD_SYNC_HEADER: [u8; CD_SYNC_NUM_BYTES] = [
+    0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+];
+pub(crate) const CD_SYNC_OFFSET: usize = 0x000;
+pub(crate) const CD_MODE_OFFSET: usize = 0x00f;
+
+/// Which parts of each frame are decoded from hunks compressed with a CD-ROM codec.
+///
+/// Skipping the subcode data or the regeneration of the sync header and ECC of data sectors makes
+/// decoding faster when they are not needed. The policy is set when opening a CHD file with
+/// [`ChdOpenOptions::sector_policy`](crate::ChdOpenOptions::sector_policy), or afterwards with
+/// [`Chd::set_sector_policy`](crate::Chd::set_sector_policy), so that CHD files in the same process
+/// can be read with different policies. Hunks that are not compressed with a CD-ROM codec are
+/// always read as they are stored.
+///
+/// The default policy is [`SectorPolicy::Full`](crate::cdrom::SectorPolicy::Full) if both the
+/// `want_subcode` and `want_raw_data_sector` features are enabled, as they are by default, and skips
+/// the parts whose feature is disabled otherwise.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SectorPolicy {
+    /// Decode the subcode data, and regenerate the sync header and ECC of data sectors.
+    Full,
+    /// Regenerate the sync header and ECC of data sectors, but do not decode the subcode data.
+    /// The subcode data of each frame is zeroed.
+    SkipSubcode,
+    /// Decode the subcode data, but leave the sync header and ECC of data sectors as stored,
+    /// which is zeroed if they were stripped when compressing.
+    SkipEcc,
+    /// Only decode the sector data as stored. The subcode data of each frame is zeroed.
+    SectorDataOnly,
+}
+
+impl SectorPolicy {
+    /// Returns the policy for whether subcode data is decoded, and whether the sync header and ECC
+    /// of data sectors are regenerated.
+    pub const fn new(subcode: bool, raw_data_sector: bool) -> SectorPolicy {
+        match (subcode, raw_data_sector) {
+            (true, true) => SectorPolicy::Full,
+            (false, true) => SectorPolicy::SkipSubcode,
+            (true, false) => SectorPolicy::SkipEcc,
+            (false, false) => SectorPolicy::SectorDataOnly,
+        }
+    }
+
+    /// Returns whether the subcode data of each frame is decoded.
+    pub const fn wants_subcode(&self) -> bool {
+        matches!(self, SectorPolicy::Full | SectorPolicy::SkipEcc)
+    }
+
+    /// Returns whether the sync header and ECC of data sectors are regenerated.
+    pub const fn wants_raw_data_sector(&self) -> bool {
+        matches!(self, SectorPolicy::Full | SectorPolicy::SkipSubcode)
+    }
+}
+
+impl Default for SectorPolicy {
+    fn default() -> Self {
+        SectorPolicy::new(
+            cfg!(feature = "want_subcode"),
+            cfg!(feature = "want_raw_data_sector"),
+        )
+    }
+}
+
+/// The type of data stored in the sectors of a CD-ROM track.
+#[repr(u32)]
+#[derive(FromPrimitive, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TrackType {
+    /// Mode 1 cooked sectors (2048 bytes per sector).
+    Mode1 = 0,
+    /// Mode 1 raw sectors (2352 bytes per sector).
+    Mode1Raw,
+    /// Mode 2 sectors (2336 bytes per sector).
+    Mode2,
+    /// Mode 2 Form 1 sectors (2048 bytes per sector).
+    Mode2Form1,
+    /// Mode 2 Form 2 sectors (2324 bytes per sector).
+    Mode2Form2,
+    /// Mode 2 mixed form sectors (2336 bytes per sector).
+    Mode2FormMix,
+    /// Mode 2 raw sectors (2352 bytes per sector).
+    Mode2Raw,
+    /// Red Book audio (2352 bytes per sector).
+    Audio,
+}
+
+impl TrackType {
+    /// Returns the number of bytes of sector data stored per frame for this track type.
+    pub fn data_size(&self) -> u32 {
+        match self {
+            TrackType::Mode1 | TrackType::Mode2Form1 => 2048,
+            TrackType::Mode2 | TrackType::Mode2FormMix => 2336,
+            TrackType::Mode2Form2 => 2324,
+            TrackType::Mode1Raw | TrackType::Mode2Raw | TrackType::Audio => CD_MAX_SECTOR_DATA,
+        }
+    }
+
+    /// Returns whether or not this track type contains audio data.
+    pub fn is_audio(&self) -> bool {
+        matches!(self, TrackType::Audio)
+    }
+
+    /// Returns the name of this track type as it appears in CHD metadata.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackType::Mode1 => "MODE1",
+            TrackType::Mode1Raw => "MODE1_RAW",
+            TrackType::Mode2 => "MODE2",
+            TrackType::Mode2Form1 => "MODE2_FORM1",
+            TrackType::Mode2Form2 => "MODE2_FORM2",
+            TrackType::Mode2FormMix => "MODE2_FORM_MIX",
+            TrackType::Mode2Raw => "MODE2_RAW",
+            TrackType::Audio => "AUDIO",
+        }
+    }
+
+    /// Returns the name of this track type as it appears in a CUE sheet.
+    pub fn cue_str(&self) -> &'static str {
+        match self {
+            TrackType::Mode1 => "MODE1/2048",
+            TrackType::Mode1Raw => "MODE1/2352",
+            TrackType::Mode2 | TrackType::Mode2FormMix => "MODE2/2336",
+            TrackType::Mode2Form1 => "MODE2/2048",
+            TrackType::Mode2Form2 => "MODE2/2324",
+            TrackType::Mode2Raw => "MODE2/2352",
+            TrackType::Audio => "AUDIO",
+        }
+    }
+}
+
+impl FromStr for TrackType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "MODE1" | "MODE1/2048" => TrackType::Mode1,
+            "MODE1_RAW" | "MODE1/2352" => TrackType::Mode1Raw,
+            "MODE2" | "MODE2/2336" => TrackType::Mode2,
+            "MODE2_FORM1" | "MODE2/2048" => TrackType::Mode2Form1,
+            "MODE2_FORM2" | "MODE2/2324" => TrackType::Mode2Form2,
+            "MODE2_FORM_MIX" => TrackType::Mode2FormMix,
+            "MODE2_RAW" | "MODE2/2352" | "CDI/2352" => TrackType::Mode2Raw,
+            "AUDIO" => TrackType::Audio,
+            _ => return Err(Error::InvalidMetadata),
+        })
+    }
+}
+
+/// The type of subcode data stored in the frames of a CD-ROM track.
+#[repr(u32)]
+#[derive(FromPrimitive, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SubcodeType {
+    /// Interleaved R-W subcode data.
+    Rw = 0,
+    /// Raw, uninterleaved R-W subcode data.
+    RwRaw,
+    /// No subcode data.
+    None,
+}
+
+impl SubcodeType {
+    /// Returns the number of bytes of subcode data stored per frame for this subcode type.
+    pub fn data_size(&self) -> u32 {
+        match self {
+            SubcodeType::Rw | SubcodeType::RwRaw => CD_MAX_SUBCODE_DATA,
+            SubcodeType::None => 0,
+        }
+    }
+
+    /// Returns the name of this subcode type as it appears in CHD metadata.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubcodeType::Rw => "RW",
+            SubcodeType::RwRaw => "RW_RAW",
+            SubcodeType::None => "NONE",
+        }
+    }
+}
+
+impl FromStr for SubcodeType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "RW" => SubcodeType::Rw,
+            "RW_RAW" => SubcodeType::RwRaw,
+            "NONE" => SubcodeType::None,
+            _ => return Err(Error::InvalidMetadata),
+        })
+    }
+}
+
+/// How the pregap of a track is handled when reading the track.
+///
+/// This only affects tracks where the pregap is stored in the CHD file. Pregaps that
+/// are not stored in the CHD file are never synthesized.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum PregapHandling {
+    #[default]
+    /// Include the frames of the pregap at the beginning of the track, as they are stored in the
+    /// CHD file. This matches the output of `chdman extractcd`.
+    Include,
+    /// Skip the frames of the pregap, so that the track begins at index 1.
+    Skip,
+}
+
+/// How the tracks of a CD-ROM CHD file are laid out in files when extracted with
+/// [`ExtractOptions`](crate::cdrom::ExtractOptions).
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum TrackFileLayout {
+    #[default]
+    /// All tracks are written one after another to a single file. This matches the output of
+    /// `chdman extractcd`.
+    Combined,
+    /// Each track is written to its own file.
+    Split,
+}
+
+/// The byte order of the audio samples of audio tracks extracted with
+/// [`ExtractOptions`](crate::cdrom::ExtractOptions).
+///
+/// CHD files store audio samples in big-endian byte order, while BIN files described by a CUE sheet
+/// are expected to hold little-endian samples. Data tracks are never swapped.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum AudioByteOrder {
+    #[default]
+    /// Swap audio samples to little-endian byte order. This matches the output of `chdman extractcd`.
+    LittleEndian,
+    /// Write audio samples in big-endian byte order, as they are stored in the CHD file.
+    BigEndian,
+}
+
+/// Options which can be used to configure how the tracks of a CD-ROM CHD file are extracted
+/// into BIN files described by a CUE sheet.
+///
+/// Tracks are written with their sector data only, without subcode data. Pregaps that are stored in
+/// the CHD file are written as part of their track and marked with `INDEX 00` in the CUE sheet. Pregaps
+/// and postgaps that are not stored in the CHD file are not written, and are marked with `PREGAP` and
+/// `POSTGAP` in the CUE sheet instead. By default, audio samples are swapped to little-endian byte order.
+///
+/// ```rust
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use chd::Chd;
+/// use chd::cdrom::{ExtractOptions, TrackFileLayout};
+///
+/// let mut f = BufReader::new(File::open("file.chd")?);
+/// let mut chd = Chd::open(&mut f, None)?;
+/// let tracks = chd.tracks()?;
+/// let options = ExtractOptions::new().layout(TrackFileLayout::Split);
+///
+/// options.write_cue(&tracks, "file", File::create("file.cue")?)?;
+/// for track in &tracks {
+///     let out = File::create(options.bin_file_name("file", track))?;
+///     options.write_track(&mut chd, track, out)?;
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ExtractOptions {
+    layout: TrackFileLayout,
+    user_data_only: bool,
+    audio_byte_order: AudioByteOrder,
+}
+
+/// The result of extracting a track with [`ExtractOptions::write_track`](crate::cdrom::ExtractOptions::write_track).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ExtractedTrack {
+    /// The number of bytes written.
+    pub bytes_written: u64,
+    /// The logical block addresses of the sectors whose EDC did not match their contents.
+    ///
+    /// EDCs are only validated when only user data is extracted from raw data tracks.
+    pub edc_failures: Vec<u32>,
+}
+
+/// The size and hashes of the data of an extracted track, as listed in DAT files.
+///
+/// Tracks are hashed with [`ExtractOptions::hash_track`](crate::cdrom::ExtractOptions::hash_track)
+/// or [`data_track_hash`](crate::cdrom::data_track_hash).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TrackHash {
+    /// The number of the hashed track.
+    pub track: u32,
+    /// The number of bytes hashed.
+    pub bytes: u64,
+    /// The CRC32 of the track data.
+    pub crc32: u32,
+    /// The SHA1 of the track data.
+    pub sha1: [u8; 20],
+}
+
+/// A `Write` sink that hashes the data of a track.
+struct TrackHasher {
+    crc32: CrcDigest<'static, u32>,
+    sha1: Sha1,
+}
+
+impl Write for TrackHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.crc32.update(buf);
+        self.sha1.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ExtractOptions {
+    /// Creates a blank new set of options ready for configuration.
+    pub fn new() -> Self {
+        ExtractOptions::default()
+    }
+
+    /// Sets how tracks are laid out in files. By default, all tracks are written to a single file.
+    pub fn layout(mut self, layout: TrackFileLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets whether or not only the 2048 bytes of user data of each sector are written for raw
+    /// `MODE1_RAW` and `MODE2_RAW` data tracks. Disabled by default.
+    ///
+    /// When enabled, the sync pattern, header, EDC and ECC of each sector are stripped, and the
+    /// track is described as `MODE1/2048` or `MODE2/2048` in the CUE sheet, as expected by ISO tools.
+    /// The EDC of every sector is validated before it is stripped, and the sectors whose EDC does
+    /// not match are reported in [`ExtractedTrack::edc_failures`](crate::cdrom::ExtractedTrack::edc_failures).
+    /// Sectors of `MODE2_RAW` tracks are assumed to be Mode 2 Form 1 sectors.
+    pub fn user_data_only(mut self, user_data_only: bool) -> Self {
+        self.user_data_only = user_data_only;
+        self
+    }
+
+    /// Sets the byte order of the audio samples written for audio tracks. By default, audio samples
+    /// are swapped to little-endian byte order.
+    pub fn audio_byte_order(mut self, audio_byte_order: AudioByteOrder) -> Self {
+        self.audio_byte_order = audio_byte_order;
+        self
+    }
+
+    /// Returns the type of the sector data written for the given track.
+    fn output_type(&self, track: &Track) -> TrackType {
+        match track.track_type {
+            TrackType::Mode1Raw if self.user_data_only => TrackType::Mode1,
+            TrackType::Mode2Raw if self.user_data_only => TrackType::Mode2Form1,
+            track_type => track_type,
+        }
+    }
+
+    /// Returns the name of the BIN file that the given track is written to, for the given base name
+    /// without extension.
+    pub fn bin_file_name(&self, base_name: &str, track: &Track) -> String {
+        match self.layout {
+            TrackFileLayout::Combined => format!("{}.bin", base_name),
+            TrackFileLayout::Split => format!("{} (Track {:02}).bin", base_name, track.number),
+        }
+    }
+
+    /// Writes a CUE sheet describing the given tracks to `out`, with BIN file names derived from the
+    /// given base name as in [`bin_file_name`](crate::cdrom::ExtractOptions::bin_file_name).
+    ///
+    /// If writing to `out` fails, returns [`Error::WriteError`](crate::Error::WriteError).
+    pub fn write_cue<W: Write>(&self, tracks: &[Track], base_name: &str, out: W) -> Result<()> {
+        self.write_cue_inner(tracks, base_name, out)
+            .map_err(|_| Error::WriteError)
+    }
+
+    fn write_cue_inner<W: Write>(
+        &self,
+        tracks: &[Track],
+        base_name: &str,
+        mut out: W,
+    ) -> std::io::Result<()> {
+        fn msf(frames: u32) -> String {
+            // durations and offsets within files do not include the lead-in.
+            Msf::from_frames(frames).map_or_else(|| "00:00:00".into(), |m| m.to_string())
+        }
+
+        // offset of the track within its file in frames.
+        let mut file_frame = 0;
+        for (index, track) in tracks.iter().enumerate() {
+            if index == 0 || self.layout == TrackFileLayout::Split {
+                writeln!(
+                    out,
+                    "FILE \"{}\" BINARY",
+                    self.bin_file_name(base_name, track)
+                )?;
+                file_frame = 0;
+            }
+            writeln!(
+                out,
+                "  TRACK {:02} {}",
+                track.number,
+                self.output_type(track).cue_str()
+            )?;
+
+            let stored_pregap = track.stored_pregap();
+            if stored_pregap > 0 {
+                writeln!(out, "    INDEX 00 {}", msf(file_frame))?;
+            } else if track.pregap > 0 {
+                writeln!(out, "    PREGAP {}", msf(track.pregap))?;
+            }
+            writeln!(out, "    INDEX 01 {}", msf(file_frame + stored_pregap))?;
+            if track.postgap > 0 {
+                writeln!(out, "    POSTGAP {}", msf(track.postgap))?;
+            }
+            file_frame += track.frames;
+        }
+        Ok(())
+    }
+
+    /// Writes the sector data of the given track in the given CHD file to `out`. The `Track` must have
+    /// been read from the same CHD file.
+    ///
+    /// With [`TrackFileLayout::Combined`](crate::cdrom::TrackFileLayout::Combined), every track should be
+    /// written in order to the same file.
+    ///
+    /// If writing to `out` fails, returns [`Error::WriteError`](crate::Error::WriteError).
+    pub fn write_track<F: Read + Seek, P: Read + Seek, W: Write>(
+        &self,
+        chd: &mut Chd<F, P>,
+        track: &Track,
+        mut out: W,
+    ) -> Result<ExtractedTrack> {
+        let data_size = track.track_type.data_size() as usize;
+        let user_data = match self.output_type(track) {
+            output if output == track.track_type => None,
+            _ => raw_user_data_layout(track.track_type),
+        };
+
+        let mut result = ExtractedTrack::default();
+        let first_lba = track.start_lba - track.stored_pregap();
+        let mut reader = track.reader(chd, PregapHandling::Include);
+        let mut frame = vec![0u8; track.frame_size() as usize];
+        for lba in first_lba..first_lba + track.frames {
+            reader.read_exact(&mut frame)?;
+            let data = match &user_data {
+                Some(layout) => {
+                    if !layout.edc_ok(&frame) {
+                        result.edc_failures.push(lba);
+                    }
+                    &mut frame[layout.user_data..][..CD_USER_DATA as usize]
+                }
+                None => &mut frame[..data_size],
+            };
+            if track.track_type.is_audio() && self.audio_byte_order == AudioByteOrder::LittleEndian
+            {
+                swap_audio_samples(data);
+            }
+            out.write_all(data).map_err(|_| Error::WriteError)?;
+            result.bytes_written += data.len() as u64;
+        }
+        Ok(result)
+    }
+
+    /// Hashes the sector data of the given track in the given CHD file, exactly as it would be
+    /// written by [`write_track`](crate::cdrom::ExtractOptions::write_track). The `Track` must have
+    /// been read from the same CHD file.
+    pub fn hash_track<F: Read + Seek, P: Read + Seek>(
+        &self,
+        chd: &mut Chd<F, P>,
+        track: &Track,
+    ) -> Result<TrackHash> {
+        let mut hasher = TrackHasher {
+            crc32: TRACK_CRC32.digest(),
+            sha1: Sha1::new(),
+        };
+        let extracted = self.write_track(chd, track, &mut hasher)?;
+        Ok(TrackHash {
+            track: track.number,
+            bytes: extracted.bytes_written,
+            crc32: hasher.crc32.finalize(),
+            sha1: hasher.sha1.finalize().into(),
+        })
+    }
+}
+
+/// Hashes the first data track of the given CD-ROM or GD-ROM CHD file.
+///
+/// The track is hashed as it is extracted by `chdman extractcd`, with the full sector data of
+/// each sector and without subcode data, which matches the data track listed in DAT files.
+/// To hash only the user data of raw data tracks, use [`ExtractOptions::hash_track`](crate::cdrom::ExtractOptions::hash_track)
+/// with [`ExtractOptions::user_data_only`](crate::cdrom::ExtractOptions::user_data_only).
+///
+/// If the CHD file has no data track, returns [`Error::MetadataNotFound`](crate::Error::MetadataNotFound).
+pub fn data_track_hash<F: Read + Seek, P: Read + Seek>(chd: &mut Chd<F, P>) -> Result<TrackHash> {
+    let track = chd
+        .tracks()?
+        .into_iter()
+        .find(|t| !t.track_type.is_audio())
+        .ok_or(Error::MetadataNotFound)?;
+    ExtractOptions::new().hash_track(chd, &track)
+}
+
+/// The location of the user data and EDC within a raw data sector.
+struct RawSectorLayout {
+    user_data: usize,
+    edc_start: usize,
+    edc: usize,
+}
+
+impl RawSectorLayout {
+    /// Returns whether the EDC stored in the sector matches its contents.
+    fn edc_ok(&self, sector: &[u8]) -> bool {
+        let stored = LittleEndian::read_u32(&sector[self.edc..][..4]);
+        CD_EDC.checksum(&sector[self.edc_start..self.edc]) == stored
+    }
+}
+
+/// Returns the layout of a raw data sector of the given track type, or `None` if the track type
+/// does not store raw data sectors.
+fn raw_user_data_layout(track_type: TrackType) -> Option<RawSectorLayout> {
+    match track_type {
+        // sync (12) + header (4) + user data + EDC
+        TrackType::Mode1Raw => Some(RawSectorLayout {
+            user_data: 16,
+            edc_start: 0,
+            edc: 2064,
+        }),
+        // sync (12) + header (4) + subheader (8) + user data + EDC. The EDC excludes sync and header.
+        TrackType::Mode2Raw => Some(RawSectorLayout {
+            user_data: 24,
+            edc_start: 16,
+            edc: 2072,
+        }),
+        _ => None,
+    }
+}
+
+/// The layout of a raw Mode 2 Form 2 sector: sync (12) + header (4) + subheader (8) + user data + EDC.
+/// The EDC excludes sync and header.
+const MODE2_FORM2_LAYOUT: RawSectorLayout = RawSectorLayout {
+    user_data: 24,
+    edc_start: 16,
+    edc: 2348,
+};
+
+/// The offset of the submode byte of the subheader of a raw Mode 2 sector.
+const CD_SUBMODE_OFFSET: usize = 0x012;
+/// The bit of the submode byte that is set for Mode 2 Form 2 sectors.
+const CD_SUBMODE_FORM2: u8 = 0x20;
+
+/// A raw data sector whose EDC or ECC does not match its contents, as reported by
+/// [`verify_sectors`](crate::cdrom::verify_sectors).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SectorError {
+    /// The number of the track that contains the sector.
+    pub track: u32,
+    /// The logical block address of the sector.
+    pub lba: u32,
+    /// Whether the EDC stored in the sector does not match its contents.
+    pub bad_edc: bool,
+    /// Whether the ECC P and Q parity stored in the sector does not match its contents.
+    ///
+    /// Mode 2 Form 2 sectors have no ECC, and never report a bad ECC.
+    pub bad_ecc: bool,
+}
+
+/// Verifies the EDC and ECC of every sector of the raw data tracks of the given CD-ROM or GD-ROM
+/// CHD file, and returns the sectors that fail verification in order of their logical block address.
+///
+/// Only `MODE1_RAW` and `MODE2_RAW` tracks store the EDC and ECC of their sectors. The mode of each
+/// sector is read from its header, and the form of Mode 2 sectors from their subheader. Sectors
+/// without a sync pattern or with a mode other than 1 or 2, such as the empty sectors of a pregap,
+/// are skipped, as is the EDC of Mode 2 Form 2 sectors that leave it unused as zero.
+///
+/// Frames are decoded with [`SectorPolicy::Full`](crate::cdrom::SectorPolicy::Full) while verifying,
+/// and the sector policy of the CHD file is restored afterwards.
+pub fn verify_sectors<F: Read + Seek, P: Read + Seek>(
+    chd: &mut Chd<F, P>,
+) -> Result<Vec<SectorError>> {
+    let tracks = chd.tracks()?;
+    let policy = chd.sector_policy();
+    chd.set_sector_policy(SectorPolicy::Full);
+    let result = verify_track_sectors(chd, &tracks);
+    chd.set_sector_policy(policy);
+    result
+}
+
+fn verify_track_sectors<F: Read + Seek, P: Read + Seek>(
+    chd: &mut Chd<F, P>,
+    tracks: &[Track],
+) -> Result<Vec<SectorError>> {
+    let mut errors = Vec::new();
+    for track in tracks
+        .iter()
+        .filter(|t| raw_user_data_layout(t.track_type).is_some())
+    {
+        let first_lba = track.start_lba - track.stored_pregap();
+        let mut reader = track.reader(chd, PregapHandling::Include);
+        let mut frame = vec![0u8; track.frame_size() as usize];
+        for lba in first_lba..first_lba + track.frames {
+            reader.read_exact(&mut frame)?;
+            let sector = <&[u8; CD_MAX_SECTOR_DATA as usize]>::try_from(
+                &frame[..CD_MAX_SECTOR_DATA as usize],
+            )?;
+            if let Some((edc_ok, ecc_ok)) = verify_sector(sector) {
+                if !edc_ok || !ecc_ok {
+                    errors.push(SectorError {
+                        track: track.number,
+                        lba,
+                        bad_edc: !edc_ok,
+                        bad_ecc: !ecc_ok,
+                    });
+                }
+            }
+        }
+    }
+    Ok(errors)
+}
+
+/// Returns whether the EDC and ECC of a raw data sector match its contents, or `None` if the
+/// sector is not a Mode 1 or Mode 2 data sector.
+fn verify_sector(sector: &[u8; CD_MAX_SECTOR_DATA as usize]) -> Option<(bool, bool)> {
+    if sector[CD_SYNC_OFFSET..][..CD_SYNC_NUM_BYTES] != CD_SYNC_HEADER {
+        return None;
+    }
+    match sector[CD_MODE_OFFSET] {
+        1 => {
+            let layout = raw_user_data_layout(TrackType::Mode1Raw)?;
+            Some((layout.edc_ok(sector), ecc::verify_ecc(sector)))
+        }
+        2 if sector[CD_SUBMODE_OFFSET] & CD_SUBMODE_FORM2 != 0 => {
+            // The EDC of a Form 2 sector is optional, and zero if it is not used.
+            let unused = LittleEndian::read_u32(&sector[MODE2_FORM2_LAYOUT.edc..][..4]) == 0;
+            Some((unused || MODE2_FORM2_LAYOUT.edc_ok(sector), true))
+        }
+        2 => {
+            let layout = raw_user_data_layout(TrackType::Mode2Raw)?;
+            Some((layout.edc_ok(sector), ecc::verify_ecc(sector)))
+        }
+        _ => None,
+    }
+}
+
+/// A CD-ROM address in minutes, seconds and frames.
+///
+/// MSF addresses are absolute, and include the 2 second (150 frame) lead-in that precedes
+/// logical block address 0. Use [`Msf::from_lba`](crate::cdrom::Msf::from_lba) and
+/// [`Msf::to_lba`](crate::cdrom::Msf::to_lba) to convert between the two, and
+/// [`Msf::from_frames`](crate::cdrom::Msf::from_frames) for durations such as track
+/// lengths, which do not include the lead-in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Msf {
+    minute: u8,
+    second: u8,
+    frame: u8,
+}
+
+impl Msf {
+    /// The number of frames in one second.
+    pub const FRAMES_PER_SECOND: u32 = 75;
+    /// The number of seconds in one minute.
+    pub const SECONDS_PER_MINUTE: u32 = 60;
+    /// The number of frames between MSF 00:00:00 and logical block address 0.
+    pub const LBA_OFFSET: u32 = 150;
+
+    const FRAMES_PER_MINUTE: u32 = Self::FRAMES_PER_SECOND * Self::SECONDS_PER_MINUTE;
+
+    /// Creates a new MSF address, returning `None` if the seconds or frames are out of range.
+    pub fn new(minute: u8, second: u8, frame: u8) -> Option<Msf> {
+        if second as u32 >= Self::SECONDS_PER_MINUTE || frame as u32 >= Self::FRAMES_PER_SECOND {
+            return None;
+        }
+        Some(Msf {
+            minute,
+            second,
+            frame,
+        })
+    }
+
+    /// Creates an MSF value from a count of frames without adjusting for the lead-in.
+    ///
+    /// Returns `None` if the number of minutes does not fit in a `u8`.
+    pub fn from_frames(frames: u32) -> Option<Msf> {
+        let minute = u8::try_from(frames / Self::FRAMES_PER_MINUTE).ok()?;
+        let second = (frames / Self::FRAMES_PER_SECOND) % Self::SECONDS_PER_MINUTE;
+        let frame = frames % Self::FRAMES_PER_SECOND;
+        Some(Msf {
+            minute,
+            second: second as u8,
+            frame: frame as u8,
+        })
+    }
+
+    /// Returns the count of frames of this MSF value without adjusting for the lead-in.
+    pub fn frames(&self) -> u32 {
+        self.minute as u32 * Self::FRAMES_PER_MINUTE
+            + self.second as u32 * Self::FRAMES_PER_SECOND
+            + self.frame as u32
+    }
+
+    /// Creates the absolute MSF address of the given logical block address.
+    ///
+    /// Returns `None` if the address is out of range.
+    pub fn from_lba(lba: u32) -> Option<Msf> {
+        Msf::from_frames(lba.checked_add(Self::LBA_OFFSET)?)
+    }
+
+    /// Returns the logical block address of this absolute MSF address.
+    ///
+    /// Returns `None` if the address lies within the lead-in before logical block address 0.
+    pub fn to_lba(&self) -> Option<u32> {
+        self.frames().checked_sub(Self::LBA_OFFSET)
+    }
+
+    /// Returns the byte offset within the logical image of a CHD file of the frame that stores
+    /// the sector at this absolute MSF address.
+    ///
+    /// See [`lba_to_byte_offset`](crate::cdrom::lba_to_byte_offset).
+    pub fn to_byte_offset(&self, tracks: &[Track]) -> Option<u64> {
+        lba_to_byte_offset(tracks, self.to_lba()?)
+    }
+
+    /// Returns the minutes component of this MSF value.
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    /// Returns the seconds component of this MSF value.
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    /// Returns the frames component of this MSF value.
+    pub fn frame(&self) -> u8 {
+        self.frame
+    }
+}
+
+impl Display for Msf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.minute, self.second, self.frame)
+    }
+}
+
+impl FromStr for Msf {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().splitn(3, ':').map(|p| p.parse::<u8>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(minute)), Some(Ok(second)), Some(Ok(frame))) => {
+                Msf::new(minute, second, frame).ok_or(Error::InvalidParameter)
+            }
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+}
+
+/// Returns the byte offset within the logical image of a CHD file of the frame that stores the
+/// sector at the given logical block address.
+///
+/// Returns `None` if the sector is not stored in the CHD file, either because it is past the end
+/// of the last track, or because it is part of a pregap that is not stored in the CHD file.
+pub fn lba_to_byte_offset(tracks: &[Track], lba: u32) -> Option<u64> {
+    tracks
+        .iter()
+        .find_map(|t| t.lba_to_chd_frame(lba))
+        .map(|frame| frame as u64 * CD_FRAME_SIZE as u64)
+}
+
+/// Returns the number of frames stored in each hunk of a CD-ROM CHD file with the given hunk size.
+///
+/// Returns `None` if the hunk size is not a non-zero multiple of [`CD_FRAME_SIZE`](crate::cdrom::CD_FRAME_SIZE).
+pub const fn frames_per_hunk(hunk_size: u32) -> Option<u32> {
+    let frames = hunk_size / CD_FRAME_SIZE;
+    if frames == 0 || frames * CD_FRAME_SIZE != hunk_size {
+        return None;
+    }
+    Some(frames)
+}
+
+/// Returns the hunk that stores the frame at the given index within the logical image of a CHD
+/// file, and the byte offset of the frame within that hunk.
+///
+/// Returns `None` if the hunk size is not a non-zero multiple of [`CD_FRAME_SIZE`](crate::cdrom::CD_FRAME_SIZE).
+pub const fn frame_to_hunk(frame: u32, hunk_size: u32) -> Option<(u32, u32)> {
+    match frames_per_hunk(hunk_size) {
+        Some(frames) => Some((frame / frames, (frame % frames) * CD_FRAME_SIZE)),
+        None => None,
+    }
+}
+
+/// Returns the logical block address of the sector stored at the given byte offset within
+/// the logical image of a CHD file.
+///
+/// Returns `None` if the offset does not lie within a frame of any track, such as within
+/// the padding frames between tracks.
+pub fn byte_offset_to_lba(tracks: &[Track], offset: u64) -> Option<u32> {
+    let frame = u32::try_from(offset / CD_FRAME_SIZE as u64).ok()?;
+    tracks.iter().find_map(|t| {
+        let index = frame.checked_sub(t.chd_frame_offset)?;
+        if index >= t.frames {
+            return None;
+        }
+        Some(t.start_lba - t.stored_pregap() + index)
+    })
+}
+
+/// A track of a CD-ROM or GD-ROM CHD file, as described by its metadata.
+///
+/// A `Track` does not keep a reference to the CHD file it was read from, and can
+/// be read as a stream with [`Track::reader`](crate::cdrom::Track::reader).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Track {
+    number: u32,
+    track_type: TrackType,
+    subcode_type: SubcodeType,
+    frames: u32,
+    pad_frames: u32,
+    pregap: u32,
+    pregap_type: TrackType,
+    pregap_subcode_type: SubcodeType,
+    pregap_in_image: bool,
+    postgap: u32,
+    chd_frame_offset: u32,
+    start_lba: u32,
+}
+
+impl Track {
+    /// Returns the 1-based track number.
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// Returns the type of data stored in this track.
+    pub fn track_type(&self) -> TrackType {
+        self.track_type
+    }
+
+    /// Returns the type of subcode data stored in this track.
+    pub fn subcode_type(&self) -> SubcodeType {
+        self.subcode_type
+    }
+
+    /// Returns the number of frames of this track stored in the CHD file,
+    /// including the pregap if it is stored in the CHD file.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Returns the number of padding frames declared for this track. This is only
+    /// non-zero for GD-ROM tracks.
+    pub fn pad_frames(&self) -> u32 {
+        self.pad_frames
+    }
+
+    /// Returns the number of frames in the pregap of this track.
+    pub fn pregap(&self) -> u32 {
+        self.pregap
+    }
+
+    /// Returns the type of data stored in the pregap of this track.
+    pub fn pregap_type(&self) -> TrackType {
+        self.pregap_type
+    }
+
+    /// Returns the type of subcode data stored in the pregap of this track.
+    pub fn pregap_subcode_type(&self) -> SubcodeType {
+        self.pregap_subcode_type
+    }
+
+    /// Returns whether or not the pregap of this track is stored in the CHD file.
+    pub fn pregap_in_image(&self) -> bool {
+        self.pregap_in_image
+    }
+
+    /// Returns the number of frames of the pregap of this track that are stored in the CHD file.
+    ///
+    /// This is the same as [`pregap`](crate::cdrom::Track::pregap) if the pregap is stored in the
+    /// CHD file, and 0 otherwise.
+    pub fn stored_pregap(&self) -> u32 {
+        if self.pregap_in_image {
+            self.pregap.min(self.frames)
+        } else {
+            0
+        }
+    }
+
+    /// Returns the number of frames in the postgap of this track.
+    ///
+    /// Postgaps are never stored in the CHD file, but occupy logical block addresses after the
+    /// end of the track.
+    pub fn postgap(&self) -> u32 {
+        self.postgap
+    }
+
+    /// Returns the index of the first frame of this track within the CHD file.
+    ///
+    /// Tracks are padded to a multiple of 4 frames in the CHD file, so this is not
+    /// necessarily the sum of the frames of all previous tracks.
+    pub fn chd_frame_offset(&self) -> u32 {
+        self.chd_frame_offset
+    }
+
+    /// Returns the logical block address of the first sector of this track after the
+    /// pregap (index 1).
+    ///
+    /// Pregaps that are not stored in the CHD file still occupy logical block addresses
+    /// before the start of the track, following the same layout as MAME.
+    pub fn start_lba(&self) -> u32 {
+        self.start_lba
+    }
+
+    /// Returns the logical block address of the first sector of the pregap of
+    /// this track (index 0).
+    ///
+    /// If the track has no pregap, this is the same as [`start_lba`](crate::cdrom::Track::start_lba).
+    pub fn pregap_lba(&self) -> u32 {
+        self.start_lba - self.pregap
+    }
+
+    /// Returns the logical block address of the first sector of the postgap of this track, directly
+    /// after the last sector of the track stored in the CHD file.
+    pub fn postgap_lba(&self) -> u32 {
+        self.start_lba - self.stored_pregap() + self.frames
+    }
+
+    /// Returns the MSF address of the first sector of this track after the pregap (index 1).
+    pub fn start_msf(&self) -> Option<Msf> {
+        Msf::from_lba(self.start_lba)
+    }
+
+    /// Returns the MSF address of the first sector of the pregap of this track (index 0).
+    pub fn pregap_msf(&self) -> Option<Msf> {
+        Msf::from_lba(self.pregap_lba())
+    }
+
+    /// Returns the index of the frame within the CHD file that stores the sector at the given
+    /// logical block address, if the sector is part of this track and stored in the CHD file.
+    ///
+    /// Sectors of a pregap that is not stored in the CHD file are not part of any frame.
+    pub fn lba_to_chd_frame(&self, lba: u32) -> Option<u32> {
+        let first_stored_lba = self.start_lba - self.stored_pregap();
+        if lba < first_stored_lba || lba - first_stored_lba >= self.frames {
+            return None;
+        }
+        Some(self.chd_frame_offset + (lba - first_stored_lba))
+    }
+
+    /// Returns the number of bytes per frame when this track is read as a stream.
+    pub fn frame_size(&self) -> u32 {
+        self.track_type.data_size() + self.subcode_type.data_size()
+    }
+
+    /// Returns the number of frames that will be read from this track with the
+    /// given pregap handling.
+    pub fn frames_with(&self, pregap: PregapHandling) -> u32 {
+        self.frames - self.skipped_frames(pregap)
+    }
+
+    fn skipped_frames(&self, pregap: PregapHandling) -> u32 {
+        match pregap {
+            PregapHandling::Skip => self.stored_pregap(),
+            PregapHandling::Include => 0,
+        }
+    }
+
+    /// Returns a `Read` stream over the frames of this track in the given CHD file.
+    ///
+    /// Each frame consists of the sector data of the track type followed by the subcode data
+    /// if the track has any, as they are stored in the CHD file. The `Track` must have been
+    /// read from the same CHD file.
+    pub fn reader<'a, F: Read + Seek, P: Read + Seek>(
+        &self,
+        chd: &'a mut Chd<F, P>,
+        pregap: PregapHandling,
+    ) -> TrackReader<'a, F, P> {
+        TrackReader::new(chd, self.clone(), pregap)
+    }
+
+    /// Returns a `Read + Seek` stream over the sectors of this track in the given CHD file, with
+    /// each sector reframed to `sector_size` bytes. The `Track` must have been read from the same CHD file.
+    ///
+    /// The first sector of the stream is the first sector of the track after the pregap (index 1),
+    /// and subcode data is never included. A `sector_size` equal to the size of the sectors stored in
+    /// the track reads them as they are stored, and a `sector_size` of 2048 reads only the user data of
+    /// the sectors of data tracks.
+    ///
+    /// If the track can not be read with the given sector size, such as reading an audio track or
+    /// a Mode 1 track stored without sync and header with 2352 byte sectors, returns
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    pub fn sector_reader<'a, F: Read + Seek, P: Read + Seek>(
+        &self,
+        chd: &'a mut Chd<F, P>,
+        sector_size: u32,
+    ) -> Result<SectorReader<'a, F, P>> {
+        SectorReader::new(chd, self.clone(), sector_size)
+    }
+
+    /// Writes the audio of this track in the given CHD file to `out` as a WAV file, returning the
+    /// number of bytes of audio data written.
+    ///
+    /// CD audio is 16-bit stereo PCM sampled at 44100 Hz. CHD files store audio samples in big-endian
+    /// byte order, so samples are swapped to the little-endian byte order of WAV files. Subcode data
+    /// is not written. The `Track` must have been read from the same CHD file.
+    ///
+    /// If this track is not an audio track, or its audio is too long to fit in a WAV file, returns
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    /// If writing to `out` fails, returns [`Error::WriteError`](crate::Error::WriteError).
+    pub fn write_wav<F: Read + Seek, P: Read + Seek, W: Write>(
+        &self,
+        chd: &mut Chd<F, P>,
+        pregap: PregapHandling,
+        mut out: W,
+    ) -> Result<u64> {
+        if !self.track_type.is_audio() {
+            return Err(Error::InvalidParameter);
+        }
+
+        let frames = self.frames_with(pregap);
+        let data_len = u32::try_from(frames as u64 * CD_MAX_SECTOR_DATA as u64)
+            .map_err(|_| Error::InvalidParameter)?;
+        let header = wav_header(data_len).ok_or(Error::InvalidParameter)?;
+        out.write_all(&header).map_err(|_| Error::WriteError)?;
+
+        let mut reader = self.reader(chd, pregap);
+        let mut frame = vec![0u8; self.frame_size() as usize];
+        for _ in 0..frames {
+            reader.read_exact(&mut frame)?;
+            let audio = &mut frame[..CD_MAX_SECTOR_DATA as usize];
+            swap_audio_samples(audio);
+            out.write_all(audio).map_err(|_| Error::WriteError)?;
+        }
+        Ok(data_len as u64)
+    }
+}
+
+/// Swaps the byte order of 16-bit audio samples in place.
+fn swap_audio_samples(audio: &mut [u8]) {
+    for sample in audio.chunks_exact_mut(2) {
+        sample.swap(0, 1);
+    }
+}
+
+/// Returns the header of a WAV file of CD audio with the given length of audio data.
+///
+/// Returns `None` if the length of the RIFF chunk does not fit in 32 bits.
+fn wav_header(data_len: u32) -> Option<[u8; 44]> {
+    const CHANNELS: u16 = 2;
+    const SAMPLE_RATE: u32 = 44100;
+    const BITS_PER_SAMPLE: u16 = 16;
+    const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+    let riff_len = data_len.checked_add(36)?;
+    let mut header = [0u8; 44];
+    header[0..4].copy_from_slice(b"RIFF");
+    LittleEndian::write_u32(&mut header[4..8], riff_len);
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    LittleEndian::write_u32(&mut header[16..20], 16);
+    // PCM
+    LittleEndian::write_u16(&mut header[20..22], 1);
+    LittleEndian::write_u16(&mut header[22..24], CHANNELS);
+    LittleEndian::write_u32(&mut header[24..28], SAMPLE_RATE);
+    LittleEndian::write_u32(&mut header[28..32], SAMPLE_RATE * BLOCK_ALIGN as u32);
+    LittleEndian::write_u16(&mut header[32..34], BLOCK_ALIGN);
+    LittleEndian::write_u16(&mut header[34..36], BITS_PER_SAMPLE);
+    header[36..40].copy_from_slice(b"data");
+    LittleEndian::write_u32(&mut header[40..44], data_len);
+    Some(header)
+}
+
+/// Parses the track list of a CD-ROM or GD-ROM CHD file from its metadata.
+pub(crate) fn read_tracks<F: Read + Seek, P: Read + Seek>(
+    chd: &mut Chd<F, P>,
+) -> Result<Vec<Track>> {
+    let refs: Vec<_> = chd
+        .metadata_refs()
+        .filter(|m| KnownMetadata::is_cdrom(m.metatag()))
+        .collect();
+
+    let mut tracks = Vec::new();
+    for meta in refs {
+        let meta = meta.read(chd.inner())?;
+        if meta.metatag == KnownMetadata::CdRomOld as u32 {
+            tracks.append(&mut parse_legacy_tracks(&meta.value)?);
+        } else {
+            tracks.push(parse_track(meta.metatag, &meta.value)?);
+        }
+    }
+
+    layout_tracks(&mut tracks)?;
+    Ok(tracks)
+}
+
+/// Assigns the logical block address and the offset in the CHD file of each track, in order.
+pub(crate) fn layout_tracks(tracks: &mut [Track]) -> Result<()> {
+    if tracks.len() > CD_MAX_TRACKS as usize {
+        return Err(Error::InvalidMetadata);
+    }
+
+    let mut chd_frame_offset = 0u32;
+    let mut lba = 0u32;
+    for track in tracks.iter_mut() {
+        if track.pregap_in_image && track.pregap > track.frames {
+            return Err(Error::InvalidMetadata);
+        }
+
+        // pregaps not stored in the image still take up logical addresses.
+        let virtual_pregap = if track.pregap_in_image {
+            0
+        } else {
+            track.pregap
+        };
+        track.start_lba = lba
+            .checked_add(track.pregap)
+            .ok_or(Error::InvalidMetadata)?;
+        lba = [virtual_pregap, track.postgap, track.frames]
+            .iter()
+            .try_fold(lba, |acc, &f| acc.checked_add(f))
+            .ok_or(Error::InvalidMetadata)?;
+
+        track.chd_frame_offset = chd_frame_offset;
+        let padded = track.frames.div_ceil(CD_TRACK_PADDING);
+        chd_frame_offset = padded
+            .checked_mul(CD_TRACK_PADDING)
+            .and_then(|f| f.checked_add(chd_frame_offset))
+            .ok_or(Error::InvalidMetadata)?;
+    }
+    Ok(())
+}
+
+fn parse_track(metatag: u32, value: &[u8]) -> Result<Track> {
+    let text = std::str::from_utf8(value)?.trim_end_matches('\0');
+
+    let mut track = Track {
+        number: 0,
+        track_type: TrackType::Mode1,
+        subcode_type: SubcodeType::None,
+        frames: 0,
+        pad_frames: 0,
+        pregap: 0,
+        pregap_type: TrackType::Mode1,
+        pregap_subcode_type: SubcodeType::None,
+        pregap_in_image: false,
+        postgap: 0,
+        chd_frame_offset: 0,
+        start_lba: 0,
+    };
+
+    fn parse_num(value: &str) -> Result<u32> {
+        value.parse().map_err(|_| Error::InvalidMetadata)
+    }
+
+    let mut has_type = false;
+    for field in text.split_ascii_whitespace() {
+        let (key, value) = field.split_once(':').ok_or(Error::InvalidMetadata)?;
+        match key {
+            "TRACK" => track.number = parse_num(value)?,
+            "TYPE" => {
+                track.track_type = value.parse()?;
+                has_type = true;
+            }
+            "SUBTYPE" => track.subcode_type = value.parse()?,
+            "FRAMES" => track.frames = parse_num(value)?,
+            "PAD" => track.pad_frames = parse_num(value)?,
+            "PREGAP" => track.pregap = parse_num(value)?,
+            "PGTYPE" => {
+                // a 'V' prefix indicates that the pregap is stored in the image.
+                if let Some(pgtype) = value.strip_prefix('V') {
+                    track.pregap_in_image = true;
+                    track.pregap_type = pgtype.parse()?;
+                } else {
+                    track.pregap_type = value.parse()?;
+                }
+            }
+            "PGSUB" => track.pregap_subcode_type = value.parse()?,
+            "POSTGAP" => track.postgap = parse_num(value)?,
+            _ => {}
+        }
+    }
+
+    if track.number == 0 || !has_type {
+        return Err(Error::InvalidMetadata);
+    }
+
+    // CHTR predates pregap information.
+    if metatag == KnownMetadata::CdRomTrack as u32 {
+        track.pregap = 0;
+        track.postgap = 0;
+    }
+
+    Ok(track)
+}
+
+fn parse_legacy_tracks(value: &[u8]) -> Result<Vec<Track>> {
+    // CHCD metadata was written in the native byte order of the host machine.
+    if value.len() < 4 {
+        return Err(Error::InvalidMetadataSize);
+    }
+    let num_tracks = BigEndian::read_u32(value);
+    let big_endian = num_tracks <= CD_MAX_TRACKS;
+    let read_u32 = |buf: &[u8]| {
+        if big_endian {
+            BigEndian::read_u32(buf)
+        } else {
+            LittleEndian::read_u32(buf)
+        }
+    };
+
+    let num_tracks = read_u32(value);
+    if num_tracks > CD_MAX_TRACKS {
+        return Err(Error::InvalidMetadata);
+    }
+
+    let mut tracks = Vec::with_capacity(num_tracks as usize);
+    for (index, entry) in value[4..]
+        .chunks_exact(6 * std::mem::size_of::<u32>())
+        .take(num_tracks as usize)
+        .enumerate()
+    {
+        let track_type =
+            TrackType::from_u32(read_u32(&entry[0..4])).ok_or(Error::InvalidMetadata)?;
+        let subcode_type =
+            SubcodeType::from_u32(read_u32(&entry[4..8])).ok_or(Error::InvalidMetadata)?;
+        let frames = read_u32(&entry[16..20]);
+        tracks.push(Track {
+            number: index as u32 + 1,
+            track_type,
+            subcode_type,
+            frames,
+            pad_frames: 0,
+            pregap: 0,
+            pregap_type: track_type,
+            pregap_subcode_type: subcode_type,
+            pregap_in_image: false,
+            postgap: 0,
+            chd_frame_offset: 0,
+            start_lba: 0,
+        });
+    }
+
+    if tracks.len() != num_tracks as usize {
+        return Err(Error::InvalidMetadataSize);
+    }
+    Ok(tracks)
+}
+
+/// `Read` adapter over the frames of a single [`Track`](crate::cdrom::Track).
+///
+/// `TrackReader` will allocate and manage intermediate buffers to support reading
+/// at a byte granularity.
+pub struct TrackReader<'a, F: Read + Seek, P: Read + Seek = F> {
+    chd: &'a mut Chd<F, P>,
+    track: Track,
+    current_frame: u32,
+    end_frame: u32,
+    frame_buf: Vec<u8>,
+    frame_pos: usize,
+    frame_len: usize,
+    hunk_buf: Vec<u8>,
+    cmp_buf: Vec<u8>,
+    buffered_hunk: Option<u32>,
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> TrackReader<'a, F, P> {
+    /// Create a new `TrackReader` over the given track of a CHD file.
+    ///
+    /// The `Track` must have been read from the same CHD file.
+    pub fn new(chd: &'a mut Chd<F, P>, track: Track, pregap: PregapHandling) -> Self {
+        let start = track.chd_frame_offset + track.skipped_frames(pregap);
+        let end = track.chd_frame_offset + track.frames;
+        let hunk_buf = chd.get_hunksized_buffer();
+        TrackReader {
+            chd,
+            track,
+            current_frame: start,
+            end_frame: end,
+            frame_buf: vec![0u8; CD_FRAME_SIZE as usize],
+            frame_pos: 0,
+            frame_len: 0,
+            hunk_buf,
+            cmp_buf: Vec::new(),
+            buffered_hunk: None,
+        }
+    }
+
+    /// Returns the track being read.
+    pub fn track(&self) -> &Track {
+        &self.track
+    }
+
+    /// Reads the frame at the given index in the CHD file into the frame buffer.
+    fn buffer_frame(&mut self, frame: u32) -> Result<()> {
+        let hunk_size = self.hunk_buf.len() as u64;
+        let mut offset = frame as u64 * CD_FRAME_SIZE as u64;
+        let mut filled = 0;
+
+        // frames may straddle hunks if the hunk size is not a multiple of the frame size.
+        while filled < self.frame_buf.len() {
+            let hunk_num = (offset / hunk_size) as u32;
+            let hunk_off = (offset % hunk_size) as usize;
+            if self.buffered_hunk != Some(hunk_num) {
+                self.buffered_hunk = None;
+                let mut hunk = self.chd.hunk(hunk_num)?;
+                hunk.read_hunk_in(&mut self.cmp_buf, &mut self.hunk_buf)?;
+                self.buffered_hunk = Some(hunk_num);
+            }
+            let count = (self.frame_buf.len() - filled).min(self.hunk_buf.len() - hunk_off);
+            self.frame_buf[filled..][..count].copy_from_slice(&self.hunk_buf[hunk_off..][..count]);
+            filled += count;
+            offset += count as u64;
+        }
+
+        let data_size = self.track.track_type.data_size() as usize;
+        let sub_size = self.track.subcode_type.data_size() as usize;
+
+        // move the subcode data to directly follow the sector data.
+        if sub_size > 0 {
+            self.frame_buf.copy_within(
+                CD_MAX_SECTOR_DATA as usize..CD_FRAME_SIZE as usize,
+                data_size,
+            );
+        }
+
+        self.frame_pos = 0;
+        self.frame_len = data_size + sub_size;
+        Ok(())
+    }
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> Read for TrackReader<'a, F, P> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.frame_pos == self.frame_len {
+            if self.current_frame >= self.end_frame {
+                return Ok(0);
+            }
+            self.buffer_frame(self.current_frame)?;
+            self.current_frame += 1;
+        }
+
+        let count = buf.len().min(self.frame_len - self.frame_pos);
+        buf[..count].copy_from_slice(&self.frame_buf[self.frame_pos..][..count]);
+        self.frame_pos += count;
+        Ok(count)
+    }
+}
+
+/// `Read + Seek` adapter over the sectors of a single [`Track`](crate::cdrom::Track), created with
+/// [`Track::sector_reader`](crate::cdrom::Track::sector_reader).
+///
+/// Only the hunk containing the sector being read is kept in memory.
+pub struct SectorReader<'a, F: Read + Seek, P: Read + Seek = F> {
+    frames: TrackReader<'a, F, P>,
+    sector_size: u32,
+    data_offset: usize,
+    buffered_sector: Option<u32>,
+    pos: u64,
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> SectorReader<'a, F, P> {
+    fn new(chd: &'a mut Chd<F, P>, track: Track, sector_size: u32) -> Result<Self> {
+        let data_size = track.track_type.data_size();
+        let data_offset = if sector_size == data_size {
+            0
+        } else if sector_size == CD_USER_DATA {
+            match track.track_type {
+                TrackType::Mode1Raw | TrackType::Mode2Raw => {
+                    raw_user_data_layout(track.track_type).map_or(0, |layout| layout.user_data)
+                }
+                // subheader (8) + user data + EDC
+                TrackType::Mode2 | TrackType::Mode2FormMix => 8,
+                _ => return Err(Error::InvalidParameter),
+            }
+        } else {
+            return Err(Error::InvalidParameter);
+        };
+
+        Ok(SectorReader {
+            frames: TrackReader::new(chd, track, PregapHandling::Skip),
+            sector_size,
+            data_offset,
+            buffered_sector: None,
+            pos: 0,
+        })
+    }
+
+    /// Returns the track being read.
+    pub fn track(&self) -> &Track {
+        self.frames.track()
+    }
+
+    /// Returns the number of sectors in the stream.
+    pub fn sector_count(&self) -> u32 {
+        self.frames.end_frame - self.first_frame()
+    }
+
+    /// Returns the size in bytes of each sector in the stream.
+    pub fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    fn first_frame(&self) -> u32 {
+        let track = self.frames.track();
+        track.chd_frame_offset + track.stored_pregap()
+    }
+
+    fn len(&self) -> u64 {
+        self.sector_count() as u64 * self.sector_size as u64
+    }
+
+    /// Returns the contents of the given sector, relative to the start of the stream.
+    fn buffer_sector(&mut self, sector: u32) -> Result<&[u8]> {
+        if self.buffered_sector != Some(sector) {
+            self.buffered_sector = None;
+            self.frames.buffer_frame(self.first_frame() + sector)?;
+            self.buffered_sector = Some(sector);
+        }
+        Ok(&self.frames.frame_buf[self.data_offset..][..self.sector_size as usize])
+    }
+
+    /// Reads the given sector, relative to the start of the stream, into `buf` without
+    /// changing the position of the stream.
+    ///
+    /// If the length of `buf` is not the sector size of the stream, returns
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter). If the sector is past the end
+    /// of the track, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange).
+    pub fn read_sector(&mut self, sector: u32, buf: &mut [u8]) -> Result<()> {
+        if buf.len() != self.sector_size as usize {
+            return Err(Error::InvalidParameter);
+        }
+        if sector >= self.sector_count() {
+            return Err(Error::HunkOutOfRange);
+        }
+        buf.copy_from_slice(self.buffer_sector(sector)?);
+        Ok(())
+    }
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> Read for SectorReader<'a, F, P> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.len() {
+            return Ok(0);
+        }
+
+        let sector = (self.pos / self.sector_size as u64) as u32;
+        let offset = (self.pos % self.sector_size as u64) as usize;
+        let data = &self.buffer_sector(sector)?[offset..];
+        let count = buf.len().min(data.len());
+        buf[..count].copy_from_slice(&data[..count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> Seek for SectorReader<'a, F, P> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let len = self.len();
+
+        let (base_pos, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = std::cmp::min(n, len);
+                return Ok(self.pos);
+            }
+            SeekFrom::End(n) => (len, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+
+        match base_pos.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = std::cmp::min(n, len);
+                Ok(self.pos)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cdrom::{
+        frame_to_hunk, frames_per_hunk, parse_track, raw_user_data_layout, swap_audio_samples,
+        wav_header, ExtractOptions, Msf, SubcodeType, TrackFileLayout, TrackType, CD_EDC,
+        CD_FRAMES_PER_HUNK, CD_FRAME_SIZE, CD_SYNC_HEADER,
+    };
+    use crate::metadata::KnownMetadata;
+
+    #[test]
+    fn parse_cht2_test() {
+        let track = parse_track(
+            KnownMetadata::CdRomTrack2 as u32,
+            b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:1500 PREGAP:150 PGTYPE:VAUDIO PGSUB:RW POSTGAP:0\0",
+        )
+        .expect("track");
+        assert_eq!(2, track.number());
+        assert_eq!(TrackType::Audio, track.track_type());
+        assert_eq!(SubcodeType::None, track.subcode_type());
+        assert_eq!(1500, track.frames());
+        assert_eq!(150, track.pregap());
+        assert!(track.pregap_in_image());
+        assert_eq!(SubcodeType::Rw, track.pregap_subcode_type());
+    }
+
+    #[test]
+    fn msf_lba_test() {
+        let msf = Msf::from_lba(0).expect("msf");
+        assert_eq!("00:02:00", msf.to_string());
+        assert_eq!(Some(0), msf.to_lba());
+        assert_eq!(Msf::from_lba(16 * 75 + 74), "00:18:74".parse::<Msf>().ok());
+        assert_eq!(None, Msf::from_frames(149).unwrap().to_lba());
+        assert_eq!(None, Msf::new(0, 60, 0));
+    }
+
+    #[test]
+    fn frame_to_hunk_test() {
+        let hunk_size = CD_FRAMES_PER_HUNK * CD_FRAME_SIZE;
+        assert_eq!(Some(8), frames_per_hunk(hunk_size));
+        assert_eq!(None, frames_per_hunk(hunk_size + 1));
+        assert_eq!(None, frames_per_hunk(0));
+        assert_eq!(Some((0, 0)), frame_to_hunk(0, hunk_size));
+        assert_eq!(Some((1, 2 * CD_FRAME_SIZE)), frame_to_hunk(10, hunk_size));
+        assert_eq!(None, frame_to_hunk(10, 2048));
+    }
+
+    #[test]
+    fn wav_export_test() {
+        let header = wav_header(2352).expect("header");
+        assert_eq!(b"RIFF", &header[0..4]);
+        assert_eq!(&(36u32 + 2352).to_le_bytes(), &header[4..8]);
+        assert_eq!(&176400u32.to_le_bytes(), &header[28..32]);
+        assert_eq!(&2352u32.to_le_bytes(), &header[40..44]);
+        assert!(wav_header(u32::MAX - 36).is_some());
+        assert_eq!(None, wav_header(u32::MAX - 35));
+
+        let mut audio = [0x12, 0x34, 0xab, 0xcd];
+        swap_audio_samples(&mut audio);
+        assert_eq!([0x34, 0x12, 0xcd, 0xab], audio);
+    }
+
+    #[test]
+    fn write_cue_test() {
+        let data = parse_track(
+            KnownMetadata::CdRomTrack2 as u32,
+            b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:1000 PREGAP:150 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0",
+        )
+        .expect("track");
+        let audio = parse_track(
+            KnownMetadata::CdRomTrack2 as u32,
+            b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:500 PREGAP:150 PGTYPE:VAUDIO PGSUB:NONE POSTGAP:75",
+        )
+        .expect("track");
+        let tracks = [data, audio];
+
+        let mut cue = Vec::new();
+        ExtractOptions::new()
+            .write_cue(&tracks, "game", &mut cue)
+            .expect("cue");
+        assert_eq!(
+            "FILE \"game.bin\" BINARY\n  TRACK 01 MODE1/2352\n    PREGAP 00:02:00\n    INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n    INDEX 00 00:13:25\n    INDEX 01 00:15:25\n    POSTGAP 00:01:00\n",
+            String::from_utf8(cue).unwrap()
+        );
+
+        let mut cue = Vec::new();
+        ExtractOptions::new()
+            .layout(TrackFileLayout::Split)
+            .write_cue(&tracks, "game", &mut cue)
+            .expect("cue");
+        let cue = String::from_utf8(cue).unwrap();
+        assert!(cue.contains("FILE \"game (Track 02).bin\" BINARY\n  TRACK 02 AUDIO\n    INDEX 00 00:00:00\n    INDEX 01 00:02:00\n"));
+    }
+
+    #[test]
+    fn edc_test() {
+        let mut sector = [0u8; 2352];
+        sector[..12].copy_from_slice(&CD_SYNC_HEADER);
+        sector[12..16].copy_from_slice(&[0x00, 0x02, 0x00, 0x01]);
+        sector[16..2064].fill(0x5a);
+        let edc = CD_EDC.checksum(&sector[..2064]);
+        sector[2064..2068].copy_from_slice(&edc.to_le_bytes());
+
+        let layout = raw_user_data_layout(TrackType::Mode1Raw).expect("layout");
+        assert!(layout.edc_ok(&sector));
+        sector[100] ^= 1;
+        assert!(!layout.edc_ok(&sector));
+        assert!(raw_user_data_layout(TrackType::Audio).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "write_api")]
+    fn sector_reader_test() {
+        use crate::cdrom::CD_TRACK_PADDING;
+        use crate::header::CodecType;
+        use crate::metadata::Metadata;
+        use crate::write::ChdWriter;
+        use crate::{Chd, Error};
+        use std::io::{Cursor, Read, Seek, SeekFrom};
+
+        let cht2 = |value: &[u8]| Metadata {
+            metatag: KnownMetadata::CdRomTrack2 as u32,
+            value: value.to_vec(),
+            flags: 1,
+            index: 0,
+            length: value.len() as u32,
+        };
+        // the first byte of the sector data of each frame is its index, and the rest is its offset.
+        let frames = 2 * CD_TRACK_PADDING as usize;
+        let data: Vec<u8> = (0..frames * CD_FRAME_SIZE as usize)
+            .map(|i| match i % CD_FRAME_SIZE as usize {
+                0 => (i / CD_FRAME_SIZE as usize) as u8,
+                offset => offset as u8,
+            })
+            .collect();
+        let mut writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            data.len() as u32,
+            CD_FRAME_SIZE,
+            data.len() as u64,
+            &[CodecType::None],
+            &[
+                cht2(b"TRACK:1 TYPE:MODE2_RAW SUBTYPE:RW FRAMES:3 PREGAP:1 PGTYPE:VMODE2_RAW PGSUB:RW POSTGAP:0\0"),
+                cht2(b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:AUDIO PGSUB:NONE POSTGAP:0\0"),
+            ],
+        )
+        .expect("create");
+        writer.write_hunk(&data).expect("write hunk");
+        let mut file = writer.finish().expect("finish");
+        file.set_position(0);
+        let mut chd = Chd::open(file, None).expect("open");
+        let tracks = chd.tracks().expect("tracks");
+
+        // the pregap in frame 0 is skipped.
+        let mut reader = tracks[0].sector_reader(&mut chd, 2352).expect("reader");
+        assert_eq!(2, reader.sector_count());
+        let mut sectors = Vec::new();
+        reader.read_to_end(&mut sectors).expect("read");
+        assert_eq!(2 * 2352, sectors.len());
+        assert_eq!(1, sectors[0]);
+        assert_eq!(
+            &data[CD_FRAME_SIZE as usize + 1..][..2351],
+            &sectors[1..2352]
+        );
+        assert_eq!(2, sectors[2352]);
+
+        let mut reader = tracks[0].sector_reader(&mut chd, 2048).expect("reader");
+        reader.seek(SeekFrom::End(-2048)).expect("seek");
+        let mut sector = vec![0u8; 2048];
+        reader.read_exact(&mut sector).expect("read");
+        assert_eq!(
+            &data[2 * CD_FRAME_SIZE as usize + 24..][..2048],
+            &sector[..]
+        );
+        assert_eq!(0, reader.read(&mut sector).expect("read"));
+        reader.read_sector(0, &mut sector).expect("sector");
+        assert_eq!(&data[CD_FRAME_SIZE as usize + 24..][..2048], &sector[..]);
+        assert!(matches!(
+            reader.read_sector(2, &mut sector),
+            Err(Error::HunkOutOfRange)
+        ));
+
+        // audio tracks have no user data.
+        assert!(matches!(
+            tracks[1].sector_reader(&mut chd, 2048),
+            Err(Error::InvalidParameter)
+        ));
+        let reader = tracks[1].sector_reader(&mut chd, 2352).expect("reader");
+        assert_eq!(4, reader.sector_count());
+        assert!(matches!(
+            tracks[0].sector_reader(&mut chd, 2448),
+            Err(Error::InvalidParameter)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "write_api")]
+    fn verify_sectors_test() {
+        use crate::cdrom::{ecc, verify_sectors, SectorError, SectorPolicy};
+        use crate::header::CodecType;
+        use crate::metadata::Metadata;
+        use crate::write::ChdWriter;
+        use crate::Chd;
+        use std::io::Cursor;
+
+        let cht2 = |value: &[u8]| Metadata {
+            metatag: KnownMetadata::CdRomTrack2 as u32,
+            value: value.to_vec(),
+            flags: 1,
+            index: 0,
+            length: value.len() as u32,
+        };
+        let sector = |mode: u8, submode: u8, edc: Option<(usize, usize)>| {
+            let mut sector = [0x5au8; 2352];
+            sector[..12].copy_from_slice(&CD_SYNC_HEADER);
+            sector[12..16].copy_from_slice(&[0x00, 0x02, 0x00, mode]);
+            sector[18] = submode;
+            if let Some((start, end)) = edc {
+                let edc = CD_EDC.checksum(&sector[start..end]);
+                sector[end..end + 4].copy_from_slice(&edc.to_le_bytes());
+            } else {
+                sector[2348..].fill(0);
+            }
+            if submode & 0x20 == 0 {
+                ecc::generate_ecc(&mut sector);
+            }
+            sector
+        };
+
+        let mut bad_data = sector(1, 0, Some((0, 2064)));
+        bad_data[100] ^= 1;
+        let mut bad_ecc = sector(1, 0, Some((0, 2064)));
+        bad_ecc[0x81c] ^= 1;
+        let mut bad_form2 = sector(2, 0x20, Some((16, 2348)));
+        bad_form2[100] ^= 1;
+        let sectors = [
+            // track 1
+            sector(1, 0, Some((0, 2064))),
+            bad_data,
+            bad_ecc,
+            [0u8; 2352],
+            // track 2
+            sector(2, 0, Some((16, 2072))),
+            sector(2, 0x20, None),
+            bad_form2,
+            sector(2, 0x20, Some((16, 2348))),
+        ];
+        let mut data = Vec::new();
+        for sector in sectors.iter() {
+            data.extend_from_slice(sector);
+            data.extend_from_slice(&[0u8; 96]);
+        }
+
+        let mut writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            data.len() as u32,
+            CD_FRAME_SIZE,
+            data.len() as u64,
+            &[CodecType::None],
+            &[
+                cht2(b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0\0"),
+                cht2(b"TRACK:2 TYPE:MODE2_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE2 PGSUB:NONE POSTGAP:0\0"),
+            ],
+        )
+        .expect("create");
+        writer.write_hunk(&data).expect("write hunk");
+        let mut file = writer.finish().expect("finish");
+        file.set_position(0);
+        let mut chd = Chd::open(file, None).expect("open");
+        chd.set_sector_policy(SectorPolicy::SkipEcc);
+
+        let errors = verify_sectors(&mut chd).expect("verify");
+        assert_eq!(
+            vec![
+                SectorError {
+                    track: 1,
+                    lba: 1,
+                    bad_edc: true,
+                    bad_ecc: true,
+                },
+                SectorError {
+                    track: 1,
+                    lba: 2,
+                    bad_edc: false,
+                    bad_ecc: true,
+                },
+                SectorError {
+                    track: 2,
+                    lba: 6,
+                    bad_edc: true,
+                    bad_ecc: false,
+                },
+            ],
+            errors
+        );
+        assert_eq!(SectorPolicy::SkipEcc, chd.sector_policy());
+    }
+
+    #[test]
+    #[cfg(feature = "write_api")]
+    fn audio_byte_order_test() {
+        use crate::cdrom::{AudioByteOrder, CD_TRACK_PADDING};
+        use crate::header::CodecType;
+        use crate::metadata::Metadata;
+        use crate::write::ChdWriter;
+        use crate::Chd;
+        use std::io::Cursor;
+
+        let cht2 = |value: &[u8]| Metadata {
+            metatag: KnownMetadata::CdRomTrack2 as u32,
+            value: value.to_vec(),
+            flags: 1,
+            index: 0,
+            length: value.len() as u32,
+        };
+        let frames = 2 * CD_TRACK_PADDING as usize;
+        let data: Vec<u8> = (0..frames * CD_FRAME_SIZE as usize)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            data.len() as u32,
+            CD_FRAME_SIZE,
+            data.len() as u64,
+            &[CodecType::None],
+            &[
+                cht2(b"TRACK:1 TYPE:AUDIO SUBTYPE:NONE FRAMES:2 PREGAP:0 PGTYPE:AUDIO PGSUB:NONE POSTGAP:0\0"),
+                cht2(b"TRACK:2 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:2 PREGAP:0 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0\0"),
+            ],
+        )
+        .expect("create");
+        writer.write_hunk(&data).expect("write hunk");
+        let mut file = writer.finish().expect("finish");
+        file.set_position(0);
+        let mut chd = Chd::open(file, None).expect("open");
+        let tracks = chd.tracks().expect("tracks");
+
+        let extract = |chd: &mut Chd<_>, order, track| {
+            let mut out = Vec::new();
+            ExtractOptions::new()
+                .audio_byte_order(order)
+                .write_track(chd, track, &mut out)
+                .expect("write track");
+            out
+        };
+        let stored_audio: Vec<u8> = data
+            .chunks_exact(CD_FRAME_SIZE as usize)
+            .take(2)
+            .flat_map(|frame| frame[..2352].iter().copied())
+            .collect();
+        let mut swapped_audio = stored_audio.clone();
+        swap_audio_samples(&mut swapped_audio);
+
+        assert_eq!(
+            swapped_audio,
+            extract(&mut chd, AudioByteOrder::LittleEndian, &tracks[0])
+        );
+        assert_eq!(
+            stored_audio,
+            extract(&mut chd, AudioByteOrder::BigEndian, &tracks[0])
+        );
+        // data tracks are never swapped.
+        assert_eq!(
+            extract(&mut chd, AudioByteOrder::LittleEndian, &tracks[1]),
+            extract(&mut chd, AudioByteOrder::BigEndian, &tracks[1])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "write_api")]
+    fn data_track_hash_test() {
+        use crate::cdrom::{data_track_hash, CD_TRACK_PADDING};
+        use crate::header::CodecType;
+        use crate::metadata::Metadata;
+        use crate::write::ChdWriter;
+        use crate::{Chd, Error};
+        use crc::{Crc, CRC_32_ISO_HDLC};
+        use sha1::{Digest, Sha1};
+        use std::io::Cursor;
+
+        let cht2 = |value: &[u8]| Metadata {
+            metatag: KnownMetadata::CdRomTrack2 as u32,
+            value: value.to_vec(),
+            flags: 1,
+            index: 0,
+            length: value.len() as u32,
+        };
+        let frames = 2 * CD_TRACK_PADDING as usize;
+        let data: Vec<u8> = (0..frames * CD_FRAME_SIZE as usize)
+            .map(|i| (i / CD_FRAME_SIZE as usize * 31 + i % 251) as u8)
+            .collect();
+        let write = |metadata: &[Metadata]| {
+            let mut writer = ChdWriter::create(
+                Cursor::new(Vec::new()),
+                data.len() as u32,
+                CD_FRAME_SIZE,
+                data.len() as u64,
+                &[CodecType::None],
+                metadata,
+            )
+            .expect("create");
+            writer.write_hunk(&data).expect("write hunk");
+            let mut file = writer.finish().expect("finish");
+            file.set_position(0);
+            Chd::open(file, None).expect("open")
+        };
+
+        let mut chd = write(&[
+            cht2(b"TRACK:1 TYPE:AUDIO SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:AUDIO PGSUB:NONE POSTGAP:0\0"),
+            cht2(b"TRACK:2 TYPE:MODE1 SUBTYPE:RW FRAMES:3 PREGAP:0 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0\0"),
+        ]);
+        let hash = data_track_hash(&mut chd).expect("hash");
+
+        // the data track starts after the padded audio track, and only its sector data is hashed.
+        let track_data: Vec<u8> = data
+            .chunks_exact(CD_FRAME_SIZE as usize)
+            .skip(CD_TRACK_PADDING as usize)
+            .take(3)
+            .flat_map(|frame| frame[..2048].iter().copied())
+            .collect();
+        assert_eq!(2, hash.track);
+        assert_eq!(3 * 2048, hash.bytes);
+        assert_eq!(
+            Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&track_data),
+            hash.crc32
+        );
+        assert_eq!(<[u8; 20]>::from(Sha1::digest(&track_data)), hash.sha1);
+
+        let mut chd = write(&[cht2(
+            b"TRACK:1 TYPE:AUDIO SUBTYPE:NONE FRAMES:8 PREGAP:0 PGTYPE:AUDIO PGSUB:NONE POSTGAP:0\0",
+        )]);
+        assert!(matches!(
+            data_track_hash(&mut chd),
+            Err(Error::MetadataNotFound)
+        ));
+    }
+}
@@ -0,0 +1,857 @@
+//! Parsers for the CUE sheets, GDI files and cdrdao TOC files that describe the tracks of a disc image.
+//!
+//! A [`DiscSource`](crate::cdrom::source::DiscSource) lists the tracks of a disc image as a
+//! [`SourceTrack`](crate::cdrom::source::SourceTrack) for each track, along with the file and offset
+//! its sectors are stored at. This is independent of any CHD file, so that disc images can be examined
+//! before they are compressed into a CHD file.
+//!
+//! The length of the last track in each file is usually not given in the sheet, and is found from the
+//! length of the file with [`DiscSource::resolve`](crate::cdrom::source::DiscSource::resolve). Once resolved,
+//! the tracks can be converted to the [`Track`](crate::cdrom::Track)s they would have in a CD-ROM CHD file
+//! with [`DiscSource::to_tracks`](crate::cdrom::source::DiscSource::to_tracks).
+//!
+//! ```rust
+//! use chd::cdrom::source::DiscSource;
+//!
+//! // files referenced by the sheet are opened relative to the directory of the sheet.
+//! let source = DiscSource::open("file.cue")?;
+//! for track in source.tracks() {
+//!     println!("track {:02}: {} at {}", track.number, track.file, track.file_offset);
+//! }
+//! let tracks = source.to_tracks()?;
+//! ```
+use crate::cdrom::{layout_tracks, Msf, SubcodeType, Track, TrackType, CD_TRACK_PADDING};
+use crate::error::{Error, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The format of the description of a disc image.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SourceFormat {
+    /// A CUE sheet.
+    Cue,
+    /// A GDI file describing a GD-ROM.
+    Gdi,
+    /// A cdrdao TOC file.
+    Toc,
+}
+
+/// How the sectors of a track are stored in its file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FileFormat {
+    /// Raw sectors, with little-endian audio samples.
+    Binary,
+    /// Raw sectors, with big-endian audio samples as they are stored in a CHD file.
+    Motorola,
+    /// A RIFF WAVE file of 16-bit stereo samples at 44.1 kHz.
+    Wave,
+}
+
+bitflags::bitflags! {
+    /// The flags of a track given by the `FLAGS` command of a CUE sheet, or the equivalent
+    /// commands of a TOC file.
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+    pub struct TrackFlags: u8 {
+        /// Audio with pre-emphasis (`PRE`).
+        const PRE_EMPHASIS = 0x01;
+        /// Digital copy permitted (`DCP`).
+        const COPY_PERMITTED = 0x02;
+        /// Four channel audio (`4CH`).
+        const FOUR_CHANNEL = 0x08;
+        /// Serial copy management system (`SCMS`).
+        const SCMS = 0x80;
+    }
+}
+
+/// A track of a disc image, and where its sectors are stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceTrack {
+    /// The 1-based track number.
+    pub number: u32,
+    /// The type of the sectors of the track.
+    pub track_type: TrackType,
+    /// The type of the subcode data stored after each sector in the file.
+    pub subcode_type: SubcodeType,
+    /// The name of the file the track is stored in, as written in the sheet.
+    pub file: String,
+    /// The format of the file the track is stored in.
+    pub file_format: FileFormat,
+    /// The byte offset within the file of the first sector of the track, including its pregap if
+    /// the pregap is stored in the file.
+    ///
+    /// Offsets within WAVE files are relative to the start of the sample data until the
+    /// [`DiscSource`](crate::cdrom::source::DiscSource) is resolved.
+    pub file_offset: u64,
+    /// The number of sectors of the track stored in the file, including its pregap if the pregap is
+    /// stored in the file.
+    ///
+    /// This is `None` for tracks that extend to the end of their file until the
+    /// [`DiscSource`](crate::cdrom::source::DiscSource) is resolved.
+    pub frames: Option<u32>,
+    /// The length of the pregap of the track in frames.
+    pub pregap: u32,
+    /// Whether or not the pregap is stored in the file, such as with `INDEX 00` in a CUE sheet.
+    pub pregap_in_file: bool,
+    /// The length of the postgap of the track in frames, which is never stored in the file.
+    pub postgap: u32,
+    /// The flags of the track.
+    pub flags: TrackFlags,
+    /// The logical block address of the track, if it is given by the sheet.
+    ///
+    /// Only GDI files give the address of each track.
+    pub start_lba: Option<u32>,
+}
+
+impl SourceTrack {
+    /// Returns the number of bytes stored in the file for each sector of the track.
+    pub fn sector_size(&self) -> u32 {
+        self.track_type.data_size() + self.subcode_type.data_size()
+    }
+
+    /// Returns whether or not the audio samples of the track must be swapped to big-endian
+    /// to be stored in a CHD file.
+    pub fn swap_audio(&self) -> bool {
+        self.track_type.is_audio() && self.file_format != FileFormat::Motorola
+    }
+}
+
+/// The tracks of a disc image described by a CUE sheet, GDI file or TOC file.
+#[derive(Debug, Clone)]
+pub struct DiscSource {
+    format: SourceFormat,
+    tracks: Vec<SourceTrack>,
+    resolved: bool,
+}
+
+impl DiscSource {
+    /// Parses a CUE sheet.
+    ///
+    /// `FILE`, `TRACK`, `INDEX 00`, `INDEX 01`, `PREGAP`, `POSTGAP` and `FLAGS` are supported, and
+    /// other commands are ignored. If a track has both `INDEX 00` and `PREGAP`, the pregap is stored
+    /// in the file and `PREGAP` is ignored.
+    ///
+    /// Returns [`Error::InvalidData`](crate::Error::InvalidData) if the sheet is malformed, and
+    /// [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat) if a file or track type is not supported.
+    pub fn parse_cue(text: &str) -> Result<DiscSource> {
+        struct CueTrack {
+            track: SourceTrack,
+            file_id: usize,
+            index0: Option<u32>,
+            index1: Option<u32>,
+        }
+
+        let mut tracks: Vec<CueTrack> = Vec::new();
+        let mut file: Option<(usize, String, FileFormat)> = None;
+        let mut file_id = 0;
+        for line in text.lines() {
+            let tokens = tokenize(line)?;
+            let (command, args) = match tokens.split_first() {
+                Some((command, args)) => (command.to_ascii_uppercase(), args),
+                None => continue,
+            };
+            match (command.as_str(), args) {
+                ("FILE", [name, format]) => {
+                    let format = match format.to_ascii_uppercase().as_str() {
+                        "BINARY" => FileFormat::Binary,
+                        "MOTOROLA" => FileFormat::Motorola,
+                        "WAVE" => FileFormat::Wave,
+                        _ => return Err(Error::UnsupportedFormat),
+                    };
+                    file_id += 1;
+                    file = Some((file_id, name.to_string(), format));
+                }
+                ("TRACK", [number, track_type]) => {
+                    let (file_id, name, format) = file.as_ref().ok_or(Error::InvalidData)?;
+                    let mut track = new_track(parse_number(number)?, name, *format);
+                    track.track_type = track_type
+                        .to_ascii_uppercase()
+                        .parse()
+                        .map_err(|_| Error::UnsupportedFormat)?;
+                    tracks.push(CueTrack {
+                        track,
+                        file_id: *file_id,
+                        index0: None,
+                        index1: None,
+                    });
+                }
+                ("INDEX", [number, msf]) => {
+                    let current = tracks.last_mut().ok_or(Error::InvalidData)?;
+                    let frames = parse_msf(msf)?;
+                    match parse_number(number)? {
+                        0 => current.index0 = Some(frames),
+                        1 => current.index1 = Some(frames),
+                        _ => {}
+                    }
+                }
+                ("PREGAP", [msf]) => {
+                    tracks.last_mut().ok_or(Error::InvalidData)?.track.pregap = parse_msf(msf)?
+                }
+                ("POSTGAP", [msf]) => {
+                    tracks.last_mut().ok_or(Error::InvalidData)?.track.postgap = parse_msf(msf)?
+                }
+                ("FLAGS", flags) => {
+                    let current = tracks.last_mut().ok_or(Error::InvalidData)?;
+                    for flag in flags {
+                        current.track.flags |= match flag.to_ascii_uppercase().as_str() {
+                            "PRE" => TrackFlags::PRE_EMPHASIS,
+                            "DCP" => TrackFlags::COPY_PERMITTED,
+                            "4CH" => TrackFlags::FOUR_CHANNEL,
+                            "SCMS" => TrackFlags::SCMS,
+                            _ => TrackFlags::empty(),
+                        };
+                    }
+                }
+                ("FILE" | "TRACK" | "INDEX" | "PREGAP" | "POSTGAP", _) => {
+                    return Err(Error::InvalidData)
+                }
+                _ => {}
+            }
+        }
+
+        // the first stored sector of a track is at INDEX 00 if the pregap is stored in the file.
+        let mut prev: Option<(usize, u32, u64, u32)> = None;
+        let mut result = Vec::with_capacity(tracks.len());
+        for CueTrack {
+            mut track,
+            file_id,
+            index0,
+            index1,
+        } in tracks
+        {
+            let index1 = index1.ok_or(Error::InvalidData)?;
+            let start = match index0 {
+                Some(index0) if index0 > index1 => return Err(Error::InvalidData),
+                Some(index0) => {
+                    track.pregap = index1 - index0;
+                    track.pregap_in_file = true;
+                    index0
+                }
+                None => index1,
+            };
+
+            track.file_offset = match prev {
+                Some((prev_file, prev_start, prev_offset, prev_size)) if prev_file == file_id => {
+                    let frames = start.checked_sub(prev_start).ok_or(Error::InvalidData)?;
+                    prev_offset + frames as u64 * prev_size as u64
+                }
+                _ => start as u64 * track.sector_size() as u64,
+            };
+            prev = Some((file_id, start, track.file_offset, track.sector_size()));
+            result.push(track);
+        }
+        DiscSource::new(SourceFormat::Cue, result)
+    }
+
+    /// Parses a GDI file.
+    ///
+    /// Each track is stored in its own file and begins at the logical block address given by the
+    /// GDI file. Like chdman, the offset at the end of each track line is ignored.
+    ///
+    /// Returns [`Error::InvalidData`](crate::Error::InvalidData) if the file is malformed, and
+    /// [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat) if a track type or sector size is not supported.
+    pub fn parse_gdi(text: &str) -> Result<DiscSource> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let count = parse_number(lines.next().ok_or(Error::InvalidData)?.trim())?;
+
+        let mut tracks = Vec::new();
+        for line in lines {
+            let tokens = tokenize(line)?;
+            let (number, lba, track_type, sector_size, name) = match tokens.as_slice() {
+                [number, lba, track_type, sector_size, name, ..] => {
+                    (number, lba, track_type, sector_size, name)
+                }
+                _ => return Err(Error::InvalidData),
+            };
+
+            let track_type = match (parse_number(track_type)?, parse_number(sector_size)?) {
+                (0, 2352) => TrackType::Audio,
+                (4, 2352) => TrackType::Mode1Raw,
+                (4, 2048) => TrackType::Mode1,
+                (4, 2336) => TrackType::Mode2,
+                _ => return Err(Error::UnsupportedFormat),
+            };
+            let mut track = new_track(parse_number(number)?, name, FileFormat::Binary);
+            track.track_type = track_type;
+            track.start_lba = Some(parse_number(lba)?);
+            tracks.push(track);
+        }
+
+        if tracks.len() != count as usize {
+            return Err(Error::InvalidData);
+        }
+        DiscSource::new(SourceFormat::Gdi, tracks)
+    }
+
+    /// Parses a cdrdao TOC file.
+    ///
+    /// `TRACK`, `DATAFILE`, `AUDIOFILE`, `FILE`, `START`, `PREGAP`, `SWAP` and the copy, pre-emphasis
+    /// and channel flags are supported, and other statements are ignored. Audio samples are big-endian
+    /// unless the track is marked with `SWAP`. Lengths and offsets within a file must be given as MSF
+    /// values.
+    ///
+    /// Returns [`Error::InvalidData`](crate::Error::InvalidData) if the file is malformed, and
+    /// [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat) if a track type is not supported.
+    pub fn parse_toc(text: &str) -> Result<DiscSource> {
+        let mut tracks: Vec<SourceTrack> = Vec::new();
+        let mut has_file = false;
+        // CD_TEXT blocks may span several lines.
+        let mut depth = 0usize;
+        for line in text.lines() {
+            let line = line.split("//").next().unwrap_or_default();
+            if depth > 0 || line.contains('{') {
+                depth += line.matches('{').count();
+                depth = depth.saturating_sub(line.matches('}').count());
+                continue;
+            }
+
+            let tokens = tokenize(line)?;
+            let (command, args) = match tokens.split_first() {
+                Some((command, args)) => (command.to_ascii_uppercase(), args),
+                None => continue,
+            };
+            if command == "TRACK" {
+                let (track_type, subcode_type) = match args {
+                    [track_type] => (track_type, None),
+                    [track_type, subcode_type] => (track_type, Some(subcode_type)),
+                    _ => return Err(Error::InvalidData),
+                };
+                let track_type: TrackType = track_type
+                    .to_ascii_uppercase()
+                    .parse()
+                    .map_err(|_| Error::UnsupportedFormat)?;
+                let format = if track_type.is_audio() {
+                    FileFormat::Motorola
+                } else {
+                    FileFormat::Binary
+                };
+                let mut track = new_track(tracks.len() as u32 + 1, "", format);
+                track.track_type = track_type;
+                if let Some(subcode_type) = subcode_type {
+                    track.subcode_type = subcode_type
+                        .to_ascii_uppercase()
+                        .parse()
+                        .map_err(|_| Error::UnsupportedFormat)?;
+                }
+                tracks.push(track);
+                has_file = false;
+                continue;
+            }
+
+            let track = match tracks.last_mut() {
+                Some(track) => track,
+                None => continue,
+            };
+            match (command.as_str(), args) {
+                ("DATAFILE" | "AUDIOFILE" | "FILE", [name, rest @ ..]) => {
+                    // only the first file of a track is used.
+                    if has_file {
+                        continue;
+                    }
+                    has_file = true;
+                    track.file = name.to_string();
+
+                    let (offset, rest) = match rest.split_first() {
+                        Some((offset, rest)) if offset.starts_with('#') => {
+                            (parse_number(&offset[1..])? as u64, rest)
+                        }
+                        _ => (0, rest),
+                    };
+                    // data files have no start position, only a length.
+                    let (start, length) = match (command.as_str(), rest) {
+                        ("DATAFILE", []) => (0, None),
+                        ("DATAFILE", [length]) => (0, Some(parse_msf(length)?)),
+                        (_, [start]) => (parse_msf(start)?, None),
+                        (_, [start, length]) => (parse_msf(start)?, Some(parse_msf(length)?)),
+                        _ => return Err(Error::InvalidData),
+                    };
+                    track.file_offset = offset + start as u64 * track.sector_size() as u64;
+                    track.frames = length;
+                }
+                ("START", [msf]) => {
+                    track.pregap = parse_msf(msf)?;
+                    track.pregap_in_file = true;
+                }
+                ("PREGAP", [msf]) => track.pregap = parse_msf(msf)?,
+                ("SWAP", []) => track.file_format = FileFormat::Binary,
+                ("COPY", []) => track.flags |= TrackFlags::COPY_PERMITTED,
+                ("PRE_EMPHASIS", []) => track.flags |= TrackFlags::PRE_EMPHASIS,
+                ("FOUR_CHANNEL_AUDIO", []) => track.flags |= TrackFlags::FOUR_CHANNEL,
+                ("NO", [flag]) => match flag.to_ascii_uppercase().as_str() {
+                    "COPY" => track.flags -= TrackFlags::COPY_PERMITTED,
+                    "PRE_EMPHASIS" => track.flags -= TrackFlags::PRE_EMPHASIS,
+                    _ => {}
+                },
+                ("DATAFILE" | "AUDIOFILE" | "FILE" | "START" | "PREGAP", _) => {
+                    return Err(Error::InvalidData)
+                }
+                _ => {}
+            }
+        }
+
+        if tracks.iter().any(|t| t.file.is_empty()) {
+            return Err(Error::InvalidData);
+        }
+        DiscSource::new(SourceFormat::Toc, tracks)
+    }
+
+    /// Reads and parses the CUE sheet, GDI file or TOC file at the given path, chosen by its extension,
+    /// then resolves the lengths of its tracks from the files it references, relative to the directory
+    /// of the sheet.
+    ///
+    /// Returns [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat) if the extension is not
+    /// `cue`, `gdi` or `toc`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<DiscSource> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let parse = match extension.as_deref() {
+            Some("cue") => DiscSource::parse_cue,
+            Some("gdi") => DiscSource::parse_gdi,
+            Some("toc") => DiscSource::parse_toc,
+            _ => return Err(Error::UnsupportedFormat),
+        };
+
+        let mut source = parse(&std::fs::read_to_string(path)?)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        source.resolve(|name| File::open(dir.join(name)))?;
+        Ok(source)
+    }
+
+    /// Returns the format of the description this source was parsed from.
+    pub fn format(&self) -> SourceFormat {
+        self.format
+    }
+
+    /// Returns the tracks of this source, in order.
+    pub fn tracks(&self) -> &[SourceTrack] {
+        &self.tracks
+    }
+
+    /// Finds the lengths of tracks that extend to the end of their file, and the offsets of tracks
+    /// stored in WAVE files, from the files referenced by the sheet. `open` is called with the name
+    /// of each file as written in the sheet.
+    ///
+    /// Returns [`Error::InvalidData`](crate::Error::InvalidData) if a track begins past the end of its
+    /// file, and [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat) if a WAVE file is not
+    /// 16-bit stereo PCM at 44.1 kHz. Resolving a source again has no effect.
+    pub fn resolve<R: Read + Seek>(
+        &mut self,
+        mut open: impl FnMut(&str) -> std::io::Result<R>,
+    ) -> Result<()> {
+        if self.resolved {
+            return Ok(());
+        }
+
+        // the start and length of the track data within each file.
+        let mut files: HashMap<String, (u64, u64)> = HashMap::new();
+        for track in &self.tracks {
+            if files.contains_key(&track.file) {
+                continue;
+            }
+            let mut file = open(&track.file)?;
+            let range = match track.file_format {
+                FileFormat::Wave => wave_data_range(&mut file)?,
+                FileFormat::Binary | FileFormat::Motorola => (0, file.seek(SeekFrom::End(0))?),
+            };
+            files.insert(track.file.clone(), range);
+        }
+
+        for track in self.tracks.iter_mut() {
+            let (start, len) = files[&track.file];
+            track.file_offset += start;
+            if track.frames.is_none() {
+                let remaining = (start + len)
+                    .checked_sub(track.file_offset)
+                    .ok_or(Error::InvalidData)?;
+                track.frames = Some(
+                    u32::try_from(remaining / track.sector_size() as u64)
+                        .map_err(|_| Error::InvalidData)?,
+                );
+            }
+        }
+        self.resolved = true;
+        Ok(())
+    }
+
+    /// Returns the tracks of this source as they would be laid out in a CD-ROM CHD file.
+    ///
+    /// Returns [`Error::InvalidParameter`](crate::Error::InvalidParameter) if the length of a track
+    /// is not known because the source has not been [resolved](crate::cdrom::source::DiscSource::resolve),
+    /// and [`Error::InvalidData`](crate::Error::InvalidData) if the tracks do not fit on a disc.
+    pub fn to_tracks(&self) -> Result<Vec<Track>> {
+        let mut tracks = self
+            .tracks
+            .iter()
+            .map(|t| {
+                let frames = t.frames.ok_or(Error::InvalidParameter)?;
+                Ok(Track {
+                    number: t.number,
+                    track_type: t.track_type,
+                    subcode_type: t.subcode_type,
+                    frames,
+                    pad_frames: frames.div_ceil(CD_TRACK_PADDING) * CD_TRACK_PADDING - frames,
+                    pregap: t.pregap,
+                    pregap_type: t.track_type,
+                    pregap_subcode_type: t.subcode_type,
+                    pregap_in_image: t.pregap_in_file,
+                    postgap: t.postgap,
+                    chd_frame_offset: 0,
+                    start_lba: 0,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        layout_tracks(&mut tracks).map_err(|_| Error::InvalidData)?;
+        for (track, source) in tracks.iter_mut().zip(&self.tracks) {
+            if let Some(start_lba) = source.start_lba {
+                track.start_lba = start_lba;
+            }
+        }
+        Ok(tracks)
+    }
+
+    fn new(format: SourceFormat, mut tracks: Vec<SourceTrack>) -> Result<DiscSource> {
+        if tracks.is_empty() {
+            return Err(Error::InvalidData);
+        }
+        if tracks
+            .windows(2)
+            .any(|w| w[1].number <= w[0].number || w[1].number > 99)
+        {
+            return Err(Error::InvalidData);
+        }
+
+        // tracks followed by another track in the same file end where the next track begins.
+        for i in 0..tracks.len() - 1 {
+            let (current, next) = (&tracks[i], &tracks[i + 1]);
+            if current.frames.is_some() || current.file != next.file {
+                continue;
+            }
+            let len = next
+                .file_offset
+                .checked_sub(current.file_offset)
+                .ok_or(Error::InvalidData)?;
+            let frames = u32::try_from(len / current.sector_size() as u64)
+                .map_err(|_| Error::InvalidData)?;
+            tracks[i].frames = Some(frames);
+        }
+
+        Ok(DiscSource {
+            format,
+            tracks,
+            resolved: false,
+        })
+    }
+}
+
+fn new_track(number: u32, file: &str, file_format: FileFormat) -> SourceTrack {
+    SourceTrack {
+        number,
+        track_type: TrackType::Mode1,
+        subcode_type: SubcodeType::None,
+        file: file.to_string(),
+        file_format,
+        file_offset: 0,
+        frames: None,
+        pregap: 0,
+        pregap_in_file: false,
+        postgap: 0,
+        flags: TrackFlags::empty(),
+        start_lba: None,
+    }
+}
+
+/// Splits a line into whitespace separated tokens, where quoted tokens may contain whitespace.
+fn tokenize(line: &str) -> Result<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let (token, next) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').ok_or(Error::InvalidData)?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        tokens.push(token);
+        rest = next.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_number(value: &str) -> Result<u32> {
+    value.parse().map_err(|_| Error::InvalidData)
+}
+
+/// Parses an MSF value as a count of frames, without adjusting for the lead-in.
+fn parse_msf(value: &str) -> Result<u32> {
+    value
+        .parse::<Msf>()
+        .map(|msf| msf.frames())
+        .map_err(|_| Error::InvalidData)
+}
+
+/// Returns the byte offset and length of the sample data of a WAVE file.
+fn wave_data_range<R: Read + Seek>(file: &mut R) -> Result<(u64, u64)> {
+    let mut header = [0u8; 12];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(Error::InvalidData);
+    }
+
+    let mut offset = 12u64;
+    let mut has_format = false;
+    loop {
+        let mut chunk = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut chunk)?;
+        let len = LittleEndian::read_u32(&chunk[4..8]) as u64;
+        match &chunk[0..4] {
+            b"fmt " => {
+                let mut format = [0u8; 16];
+                file.read_exact(&mut format)?;
+                // 16-bit stereo PCM at 44.1 kHz.
+                if LittleEndian::read_u16(&format[0..2]) != 1
+                    || LittleEndian::read_u16(&format[2..4]) != 2
+                    || LittleEndian::read_u32(&format[4..8]) != 44100
+                    || LittleEndian::read_u16(&format[14..16]) != 16
+                {
+                    return Err(Error::UnsupportedFormat);
+                }
+                has_format = true;
+            }
+            b"data" if has_format => return Ok((offset + 8, len)),
+            b"data" => return Err(Error::InvalidData),
+            _ => {}
+        }
+        // chunks are padded to an even length.
+        offset += 8 + len + (len & 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cdrom::source::{DiscSource, FileFormat, SourceFormat, TrackFlags};
+    use crate::cdrom::{SubcodeType, TrackType};
+    use crate::Error;
+    use std::io::Cursor;
+
+    /// Resolves a source with files of the given lengths.
+    fn resolve(source: &mut DiscSource, files: &[(&str, Vec<u8>)]) -> crate::Result<()> {
+        source.resolve(|name| {
+            files
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, data)| Cursor::new(data.clone()))
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        })
+    }
+
+    #[test]
+    fn parse_cue_test() {
+        let cue = r#"
+REM a comment
+CATALOG 0000000000000
+FILE "game (Track 1).bin" BINARY
+  TRACK 01 MODE1/2352
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    FLAGS DCP PRE
+    INDEX 00 00:10:00
+    INDEX 01 00:12:00
+    POSTGAP 00:02:00
+FILE "game (Track 3).bin" BINARY
+  TRACK 03 AUDIO
+    PREGAP 00:02:00
+    INDEX 01 00:00:00
+"#;
+        let mut source = DiscSource::parse_cue(cue).expect("cue");
+        assert_eq!(SourceFormat::Cue, source.format());
+        let tracks = source.tracks();
+        assert_eq!(3, tracks.len());
+
+        assert_eq!(TrackType::Mode1Raw, tracks[0].track_type);
+        assert_eq!("game (Track 1).bin", tracks[0].file);
+        assert_eq!(Some(750), tracks[0].frames);
+
+        assert_eq!(TrackType::Audio, tracks[1].track_type);
+        assert_eq!(750 * 2352, tracks[1].file_offset);
+        assert_eq!(150, tracks[1].pregap);
+        assert!(tracks[1].pregap_in_file);
+        assert_eq!(150, tracks[1].postgap);
+        assert_eq!(
+            TrackFlags::COPY_PERMITTED | TrackFlags::PRE_EMPHASIS,
+            tracks[1].flags
+        );
+        assert!(tracks[1].swap_audio());
+        assert_eq!(None, tracks[1].frames);
+
+        assert_eq!(0, tracks[2].file_offset);
+        assert_eq!(150, tracks[2].pregap);
+        assert!(!tracks[2].pregap_in_file);
+
+        assert!(matches!(source.to_tracks(), Err(Error::InvalidParameter)));
+        resolve(
+            &mut source,
+            &[
+                ("game (Track 1).bin", vec![0; 2352 * 1000]),
+                ("game (Track 3).bin", vec![0; 2352 * 30]),
+            ],
+        )
+        .expect("resolve");
+        assert_eq!(Some(250), source.tracks()[1].frames);
+        assert_eq!(Some(30), source.tracks()[2].frames);
+
+        let tracks = source.to_tracks().expect("tracks");
+        assert_eq!(0, tracks[0].start_lba());
+        assert_eq!(2, tracks[0].pad_frames());
+        assert_eq!(750 + 150, tracks[1].start_lba());
+        assert_eq!(752, tracks[1].chd_frame_offset());
+        // the postgap of track 2 and the pregap of track 3 are not stored.
+        assert_eq!(1000 + 150 + 150, tracks[2].start_lba());
+
+        assert!(matches!(
+            DiscSource::parse_cue("TRACK 01 AUDIO"),
+            Err(Error::InvalidData)
+        ));
+        assert!(matches!(
+            DiscSource::parse_cue("FILE \"a.bin\" BINARY\nTRACK 01 AUDIO\n"),
+            Err(Error::InvalidData)
+        ));
+        assert!(matches!(
+            DiscSource::parse_cue("FILE \"a.mp3\" MP3\n"),
+            Err(Error::UnsupportedFormat)
+        ));
+    }
+
+    #[test]
+    fn parse_wave_cue_test() {
+        let cue = "FILE \"track.wav\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\n";
+        let mut source = DiscSource::parse_cue(cue).expect("cue");
+        assert_eq!(FileFormat::Wave, source.tracks()[0].file_format);
+
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+        wav.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        wav.extend_from_slice(b"fmt \x10\0\0\0");
+        for value in [1u16, 2, 0xac44, 0, 0, 0, 4, 16] {
+            wav.extend_from_slice(&value.to_le_bytes());
+        }
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(2352u32 * 3).to_le_bytes());
+        let data_offset = wav.len() as u64;
+        wav.resize(wav.len() + 2352 * 3, 0);
+
+        resolve(&mut source, &[("track.wav", wav)]).expect("resolve");
+        assert_eq!(data_offset, source.tracks()[0].file_offset);
+        assert_eq!(Some(3), source.tracks()[0].frames);
+    }
+
+    #[test]
+    fn parse_gdi_test() {
+        let gdi = r#"3
+1 0 4 2352 track01.bin 0
+2 600 0 2352 "track 02.raw" 0
+3 45000 4 2352 track03.bin 0
+"#;
+        let mut source = DiscSource::parse_gdi(gdi).expect("gdi");
+        assert_eq!(SourceFormat::Gdi, source.format());
+        assert_eq!("track 02.raw", source.tracks()[1].file);
+        assert_eq!(TrackType::Audio, source.tracks()[1].track_type);
+        assert_eq!(Some(45000), source.tracks()[2].start_lba);
+
+        resolve(
+            &mut source,
+            &[
+                ("track01.bin", vec![0; 2352 * 300]),
+                ("track 02.raw", vec![0; 2352 * 2]),
+                ("track03.bin", vec![0; 2352 * 5]),
+            ],
+        )
+        .expect("resolve");
+        let tracks = source.to_tracks().expect("tracks");
+        assert_eq!(300, tracks[0].frames());
+        assert_eq!(600, tracks[1].start_lba());
+        assert_eq!(45000, tracks[2].start_lba());
+
+        assert!(matches!(
+            DiscSource::parse_gdi("2\n1 0 4 2352 track01.bin 0\n"),
+            Err(Error::InvalidData)
+        ));
+        assert!(matches!(
+            DiscSource::parse_gdi("1\n1 0 4 2324 track01.bin 0\n"),
+            Err(Error::UnsupportedFormat)
+        ));
+    }
+
+    #[test]
+    fn parse_toc_test() {
+        let toc = r#"
+CD_ROM
+CD_TEXT {
+  LANGUAGE_MAP {
+    0 : EN
+  }
+}
+// track 1
+TRACK MODE1_RAW RW_RAW
+NO COPY
+DATAFILE "disc.bin" 00:10:00
+TRACK AUDIO
+COPY
+PRE_EMPHASIS
+AUDIOFILE "disc.bin" #24480 00:00:00
+START 00:02:00
+TRACK AUDIO
+SWAP
+PREGAP 00:01:00
+FILE "audio.raw" 00:00:00 00:05:00
+"#;
+        let mut source = DiscSource::parse_toc(toc).expect("toc");
+        assert_eq!(SourceFormat::Toc, source.format());
+        let tracks = source.tracks();
+        assert_eq!(3, tracks.len());
+
+        assert_eq!(SubcodeType::RwRaw, tracks[0].subcode_type);
+        assert_eq!(2448, tracks[0].sector_size());
+        assert_eq!(Some(750), tracks[0].frames);
+
+        assert_eq!(FileFormat::Motorola, tracks[1].file_format);
+        assert!(!tracks[1].swap_audio());
+        assert_eq!(24480, tracks[1].file_offset);
+        assert_eq!(150, tracks[1].pregap);
+        assert!(tracks[1].pregap_in_file);
+        assert_eq!(
+            TrackFlags::COPY_PERMITTED | TrackFlags::PRE_EMPHASIS,
+            tracks[1].flags
+        );
+
+        assert!(tracks[2].swap_audio());
+        assert_eq!(75, tracks[2].pregap);
+        assert!(!tracks[2].pregap_in_file);
+        assert_eq!(Some(375), tracks[2].frames);
+
+        resolve(
+            &mut source,
+            &[
+                ("disc.bin", vec![0; 24480 + 2352 * 200]),
+                ("audio.raw", vec![0; 2352 * 375]),
+            ],
+        )
+        .expect("resolve");
+        assert_eq!(Some(200), source.tracks()[1].frames);
+
+        assert!(matches!(
+            DiscSource::parse_toc("TRACK AUDIO\n"),
+            Err(Error::InvalidData)
+        ));
+    }
+}
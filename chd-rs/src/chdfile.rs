@@ -1,37 +1,152 @@
 use crate::block_hash::BlockChecksum;
+use crate::cdrom::{SectorPolicy, Track};
 use crate::compression::CompressionCodec;
+use crate::content::{Content, ContentKind};
 use crate::error::{Error, Result};
-use crate::header::Header;
+use crate::header::{CodecType, Header, Version, SHA1_BYTES};
+use crate::io::ChdIo;
+use crate::lending::{Hunks, MetadataEntries};
 use crate::map::{
-    CompressedEntryProof, CompressionTypeLegacy, CompressionTypeV5, Map, MapEntry,
-    UncompressedEntryProof,
+    CompressedEntryProof, CompressionTypeLegacy, CompressionTypeV5, HunkCrc, IntegrityReport, Map,
+    MapEntry, UncompressedEntryProof,
 };
-
-#[cfg(feature = "unstable_lending_iterators")]
-use crate::iter::{Hunks, MetadataEntries};
-
-use crate::metadata::MetadataRefs;
+use crate::metadata::parse::Creator;
+use crate::metadata::{KnownMetadata, Metadata, MetadataRef, MetadataRefs, MetadataTag};
+#[cfg(feature = "parallel")]
+use crate::prefetch::{PrefetchJob, Prefetcher};
+use crate::read::HunkBytesIter;
+use crate::Tag;
+use arrayvec::ArrayVec;
 use byteorder::{BigEndian, WriteBytesExt};
 use crc::Crc;
-use num_traits::ToPrimitive;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::marker::PhantomData;
 use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A CHD file in the parent chain of a [`Chd`](crate::Chd).
+///
+/// See [`Chd::parent_chain`](crate::Chd::parent_chain).
+#[derive(Copy, Clone)]
+pub struct ParentInfo {
+    /// The depth of the CHD file in the parent chain, where the direct parent has depth 1.
+    pub depth: u32,
+    /// The SHA1 of the CHD file, if its header version records it.
+    pub sha1: Option<[u8; SHA1_BYTES]>,
+    /// The header version of the CHD file.
+    pub version: Version,
+}
+
+/// The memory held by an open [`Chd`](crate::Chd), returned by [`Chd::memory_footprint`](crate::Chd::memory_footprint).
+///
+/// The data of the underlying stream of the CHD file is not included.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct MemoryFootprint {
+    /// The number of bytes held by the hunk map.
+    pub map_bytes: usize,
+    /// The number of bytes held by the buffers of the codecs that have been initialized.
+    pub codec_bytes: usize,
+    /// The number of bytes of decompressed hunks that are held until they are read,
+    /// such as hunks prefetched with the `parallel` feature.
+    pub cache_bytes: usize,
+    /// The total number of bytes held by the parent chain.
+    pub parent_bytes: usize,
+}
+
+impl MemoryFootprint {
+    /// Returns the total number of bytes held by the CHD file and its parent chain.
+    pub fn total(&self) -> usize {
+        self.map_bytes + self.codec_bytes + self.cache_bytes + self.parent_bytes
+    }
+}
+
+/// The hunks of a CHD file compressed with one of its codecs, returned by
+/// [`Chd::codecs_in_use`](crate::Chd::codecs_in_use).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CodecUsage {
+    /// The index of the codec in the header.
+    pub index: usize,
+    /// The type of the codec, or [`CodecType::None`](crate::header::CodecType::None) if the codec
+    /// is not known.
+    pub codec: CodecType,
+    /// The tag of the codec as declared in the header, which is kept for codecs that are not known.
+    pub fourcc: Tag,
+    /// The number of hunks compressed with the codec.
+    pub hunks: u32,
+}
+
+impl CodecUsage {
+    /// Returns whether the codec can be used to decompress hunks in this build of chd-rs.
+    ///
+    /// Codecs are not supported if they are not known, or if the feature that enables them is disabled.
+    pub fn is_supported(&self) -> bool {
+        self.codec.tag() == self.fourcc && self.codec.is_supported()
+    }
+}
+
+/// A hunk that was read from the data of a CHD file, reported to a [`DecodeObserver`](crate::DecodeObserver).
+#[derive(Debug, Copy, Clone)]
+pub struct DecodeEvent {
+    /// The number of the hunk that was read.
+    pub hunk_num: u32,
+    /// The codec the hunk was decompressed with, or [`CodecType::None`](crate::header::CodecType::None)
+    /// if the hunk is stored uncompressed.
+    pub codec: CodecType,
+    /// The length of the data of the hunk in the CHD file.
+    pub compressed_len: usize,
+    /// The number of bytes the hunk was decompressed into.
+    pub decompressed_len: usize,
+    /// The time spent decompressing the hunk, or reading it if it is stored uncompressed.
+    pub duration: Duration,
+}
+
+/// Receives an event for every hunk that is read from the data of a CHD file, for collecting
+/// statistics on the time spent in each codec.
+///
+/// See [`Chd::set_decode_observer`](crate::Chd::set_decode_observer).
+pub trait DecodeObserver: Send + Sync {
+    /// Called after a hunk was read and its checksum verified.
+    fn on_decode(&mut self, event: &DecodeEvent);
+}
+
+impl<T: FnMut(&DecodeEvent) + Send + Sync> DecodeObserver for T {
+    fn on_decode(&mut self, event: &DecodeEvent) {
+        self(event)
+    }
+}
 
 /// A CHD (MAME Compressed Hunks of Data) file.
-pub struct Chd<F: Read + Seek> {
+///
+/// The parent CHD file may be read from a stream of a different type `P` than the stream `F` of
+/// this CHD file, for example when the parent is held in memory. By default, the parent is
+/// read from the same type of stream.
+pub struct Chd<F: Read + Seek, P: Read + Seek = F> {
     file: F,
     header: Header,
-    parent: Option<Box<Chd<F>>>,
+    parent: Option<Box<Chd<P>>>,
     map: Map,
+    lenient: bool,
     // codecs contain Box<dyn CompressionCodec> which are all UnwindSafe.
     codecs: AssertUnwindSafe<Codecs>,
+    // an observer only collects statistics, which a panic can at worst leave incomplete.
+    observer: Option<AssertUnwindSafe<Box<dyn DecodeObserver>>>,
+    // the map CRC of a zero-filled hunk, computed on first use.
+    zero_crc: Option<HunkCrc>,
+    // the metadata chain of V3 and V4 files, which is read when the header is parsed.
+    metadata_cache: Option<Vec<MetadataRef>>,
+    #[cfg(feature = "parallel")]
+    prefetcher: Option<Prefetcher>,
 }
 
 impl<F: Read + Seek> Chd<F> {
     /// Open a CHD file from a `Read + Seek` stream. Optionally provide a parent of the same stream
     /// type.
     ///
-    /// The CHD header and hunk map are read and validated immediately.
+    /// The CHD header and hunk map are read and validated immediately. Codecs are not initialized
+    /// until the first hunk compressed with them is decoded, so the codecs of a parent chain only
+    /// cost memory once a hunk is read from that parent.
     ///
     /// If the CHD file requires a parent, and a parent is provided, the parent hash is
     /// validated. If hash validation fails, returns [`Error::InvalidParent`](crate::Error::InvalidParent).
@@ -42,45 +157,88 @@ impl<F: Read + Seek> Chd<F> {
     /// will not be immediately validated. However, calls to [`read_hunk_in`](crate::Hunk::read_hunk_in)
     /// will fail with [`Error::RequiresParent`](crate::Error::RequiresParent) when a hunk is read that
     /// refers to the parent CHD.
-    pub fn open(mut file: F, parent: Option<Box<Chd<F>>>) -> Result<Chd<F>> {
-        let header = Header::try_read_header(&mut file)?;
-        // No point in checking writable because traits are read only.
-        // In the future if we want to support a Write feature, will need to ensure writable.
-
-        if let Some(p) = parent.as_ref() {
-            if !header.has_parent() {
-                return Err(Error::InvalidParameter);
-            }
-            if p.header().sha1() != header.parent_sha1() {
-                return Err(Error::InvalidParent);
-            }
-            // should be None for V4+
-            if p.header().md5() != header.parent_md5() {
-                return Err(Error::InvalidParent);
-            }
+    ///
+    /// This is equivalent to opening the file with a default [`ChdOpenOptions`](crate::ChdOpenOptions)
+    /// and the given parent.
+    ///
+    /// If the hunk size or hunk map of the CHD file exceed the default limits of [`ChdOpenOptions`](crate::ChdOpenOptions),
+    /// returns [`Error::OutOfMemory`](crate::Error::OutOfMemory).
+    ///
+    /// To open a CHD file with a parent that is read from a different type of stream, use
+    /// [`ChdOpenOptions::parent`](crate::ChdOpenOptions::parent).
+    pub fn open(file: F, parent: Option<Box<Chd<F>>>) -> Result<Chd<F>> {
+        ChdOpenOptions {
+            parent,
+            ..ChdOpenOptions::new()
         }
+        .open(file)
+    }
+}
 
-        let map = Map::try_read_map(&header, &mut file)?;
-        let codecs = AssertUnwindSafe(header.create_compression_codecs()?);
+impl Chd<BufReader<File>> {
+    /// Opens the CHD file at the given path, reading it through a [`BufReader`](std::io::BufReader).
+    /// Optionally provide the path to a parent CHD file.
+    ///
+    /// This is equivalent to opening both files and wrapping them in a `BufReader` before calling
+    /// [`Chd::open`](crate::Chd::open). The parent is opened without a parent of its own, so to open a
+    /// CHD file with a chain of several parents, use [`Chd::open`](crate::Chd::open) instead.
+    ///
+    /// If either file can not be opened, the error is converted from the [`std::io::Error`],
+    /// so that [`Error::FileNotFound`](crate::Error::FileNotFound) is returned for a missing file.
+    pub fn open_path<A: AsRef<Path>>(path: A, parent: Option<&Path>) -> Result<Self> {
+        let parent = match parent {
+            Some(parent) => Some(Box::new(Chd::open_path(parent, None)?)),
+            None => None,
+        };
+        Chd::open(BufReader::new(File::open(path)?), parent)
+    }
+}
 
-        Ok(Chd {
-            file,
-            header,
-            parent,
-            map,
-            codecs,
-        })
+impl TryFrom<&Path> for Chd<BufReader<File>> {
+    type Error = Error;
+
+    /// Opens the CHD file at the given path without a parent.
+    ///
+    /// See [`Chd::open_path`](crate::Chd::open_path).
+    fn try_from(path: &Path) -> Result<Self> {
+        Chd::open_path(path, None)
+    }
+}
+
+impl TryFrom<File> for Chd<BufReader<File>> {
+    type Error = Error;
+
+    /// Opens a CHD file from an open file without a parent, reading it through a
+    /// [`BufReader`](std::io::BufReader).
+    fn try_from(file: File) -> Result<Self> {
+        Chd::open(BufReader::new(file), None)
     }
+}
 
+impl<F: Read + Seek, P: Read + Seek> Chd<F, P> {
     /// Returns a reference to the CHD header for this CHD file.
     pub fn header(&self) -> &Header {
         &self.header
     }
 
+    /// Returns whether or not this CHD file has a metadata section.
+    ///
+    /// See [`Header::has_metadata`](crate::header::Header::has_metadata).
+    pub fn has_metadata(&self) -> bool {
+        self.header.has_metadata()
+    }
+
     /// Returns an iterator over references to metadata entries for this CHD file.
     ///
-    /// The contents of each metadata entry are lazily read.
-    pub fn metadata_refs(&mut self) -> MetadataRefs<F> {
+    /// The contents of each metadata entry are lazily read. If this CHD file has no metadata section,
+    /// such as V1 and V2 CHD files, the iterator is empty.
+    ///
+    /// The metadata chain of V3 and V4 CHD files is read when the file is opened, and is not read
+    /// again from the file.
+    pub fn metadata_refs(&mut self) -> MetadataRefs<'_, F> {
+        if let Some(cached) = self.metadata_cache.as_deref() {
+            return MetadataRefs::from_cache(&mut self.file, cached);
+        }
         let offset = self.header().meta_offset();
         if let Some(offset) = offset {
             MetadataRefs::from_stream(&mut self.file, offset)
@@ -89,25 +247,195 @@ impl<F: Read + Seek> Chd<F> {
         }
     }
 
-    #[cfg(feature = "unstable_lending_iterators")]
-    #[cfg_attr(docsrs, doc(cfg(unstable_lending_iterators)))]
-    /// Returns an iterator over metadata entries for this CHD file.
+    /// Reads the metadata entry with the given tag at `index` among the entries with that tag.
+    ///
+    /// If `tag` is [`KnownMetadata::Wildcard`](crate::metadata::KnownMetadata::Wildcard), `index`
+    /// counts every metadata entry regardless of its tag. If there is no such entry, returns
+    /// [`Error::MetadataNotFound`](crate::Error::MetadataNotFound).
+    ///
+    /// Like chd.cpp, the first hard disk metadata entry of a V1 or V2 CHD file is synthesized from
+    /// its header with [`Header::legacy_hard_disk_metadata`](crate::header::Header::legacy_hard_disk_metadata).
+    pub fn metadata_by_tag(&mut self, tag: impl Into<Tag>, index: u32) -> Result<Metadata> {
+        let tag = tag.into().to_u32();
+        let hard_disk =
+            tag == KnownMetadata::HardDisk.metatag() || tag == KnownMetadata::Wildcard.metatag();
+        match self.metadata_refs().find_tag(tag, index) {
+            Err(Error::MetadataNotFound) if hard_disk && index == 0 => self
+                .header
+                .legacy_hard_disk_metadata()
+                .ok_or(Error::MetadataNotFound),
+            result => result,
+        }
+    }
+
+    /// Reads the well-known metadata entry at `index` among the entries with its tag.
+    ///
+    /// See [`Chd::metadata_by_tag`](crate::Chd::metadata_by_tag).
+    pub fn metadata_for(&mut self, tag: KnownMetadata, index: u32) -> Result<Metadata> {
+        self.metadata_by_tag(tag.metatag(), index)
+    }
+
+    /// Returns a lending iterator over metadata entries for this CHD file.
     ///
-    /// The contents of each metadata entry are lazily read.
-    pub fn metadata(&mut self) -> MetadataEntries<F> {
+    /// The contents of each metadata entry are lazily read. See [`lending`](crate::lending)
+    /// for how to iterate over the entries.
+    pub fn metadata(&mut self) -> MetadataEntries<'_, F> {
         MetadataEntries::new(self.metadata_refs())
     }
 
+    /// Returns the tracks of this CHD file, as described by its CD-ROM or GD-ROM metadata.
+    ///
+    /// Each [`Track`](crate::cdrom::Track) can be read as a separate stream with
+    /// [`Track::reader`](crate::cdrom::Track::reader). If this CHD file does not contain
+    /// CD-ROM or GD-ROM metadata, an empty list is returned.
+    pub fn tracks(&mut self) -> Result<Vec<Track>> {
+        crate::cdrom::read_tracks(self)
+    }
+
+    /// Returns the kind of content stored in this CHD file, as told by its metadata.
+    ///
+    /// See [`ContentKind`](crate::content::ContentKind) for how each kind is recognized. If the metadata
+    /// chain can not be read, the error is returned.
+    pub fn content_kind(&mut self) -> Result<ContentKind> {
+        crate::content::content_kind(self)
+    }
+
+    /// Consumes this CHD file and opens its content with the reader that fits its
+    /// [`content_kind`](crate::Chd::content_kind).
+    ///
+    /// CD-ROM and GD-ROM files are returned with their tracks, hard disks are read by sector,
+    /// A/V files are read by frame, and any other CHD file is read as a stream of bytes.
+    /// See [`Content`](crate::content::Content).
+    pub fn open_content(self) -> Result<Content<F, P>> {
+        crate::content::open_content(self)
+    }
+
+    /// Returns the application that created this CHD file, as recorded in its `CRTR` metadata.
+    ///
+    /// Most CHD files do not record their creator, in which case `None` is returned.
+    pub fn creator(&mut self) -> Result<Option<Creator>> {
+        match self.metadata_by_tag(KnownMetadata::Creator as u32, 0) {
+            Ok(meta) => Creator::from_bytes(&meta.value).map(Some),
+            Err(Error::MetadataNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Returns the hunk map of this CHD File.
     pub fn map(&self) -> &Map {
         &self.map
     }
 
+    /// Returns an iterator over the parent CHD files that were provided when this CHD file was opened,
+    /// starting with the direct parent at depth 1.
+    ///
+    /// The chain ends at the first CHD file that does not have a parent, or whose parent was not
+    /// provided. Use [`Chd::required_parent_sha1`](crate::Chd::required_parent_sha1) to find which
+    /// parent is missing from the chain, if any.
+    pub fn parent_chain(&self) -> impl Iterator<Item = ParentInfo> + '_ {
+        std::iter::successors(self.parent.as_deref(), |chd| chd.parent.as_deref())
+            .zip(1..)
+            .map(|(chd, depth)| ParentInfo {
+                depth,
+                sha1: chd.header().sha1(),
+                version: chd.header().version(),
+            })
+    }
+
+    /// Returns the SHA1 of the parent CHD file that is required to read this CHD file, but was not provided.
+    ///
+    /// If a parent was provided, the parent chain is followed to the first CHD file that requires a
+    /// parent that was not provided. Returns `None` if the parent chain is complete, or if the CHD file
+    /// that requires a parent has a header version that does not record the SHA1 of its parent.
+    pub fn required_parent_sha1(&self) -> Option<[u8; SHA1_BYTES]> {
+        match self.parent.as_deref() {
+            Some(parent) => parent.required_parent_sha1(),
+            None if self.header.has_parent() => self.header.parent_sha1(),
+            None => None,
+        }
+    }
+
+    /// Returns the number of bytes of memory held by this CHD file and its parent chain.
+    ///
+    /// Codecs are initialized when the first hunk compressed with them is read, so the
+    /// footprint grows as hunks are read. See [`MemoryFootprint`](crate::MemoryFootprint)
+    /// for what is accounted for.
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        #[allow(unused_mut)]
+        let mut cache_bytes = 0;
+        #[cfg(feature = "parallel")]
+        if let Some(prefetcher) = self.prefetcher.as_ref() {
+            cache_bytes = prefetcher.cached_bytes();
+        }
+        MemoryFootprint {
+            map_bytes: self.map.memory_footprint(),
+            codec_bytes: self.codecs.buffer_bytes(),
+            cache_bytes,
+            parent_bytes: self
+                .parent
+                .as_ref()
+                .map_or(0, |p| p.memory_footprint().total()),
+        }
+    }
+
+    /// Returns the codecs that compress at least one hunk of this CHD file, in the order of their
+    /// index in the header, with the number of hunks each compresses.
+    ///
+    /// The header lists up to four codecs, but a file may use only some of them. This scans the map
+    /// without reading any hunk data, so that missing support for a codec can be reported before any
+    /// hunks are read. Hunks stored uncompressed, copied from another hunk, or stored in the parent
+    /// are not counted, and neither are map entries that can not be decoded.
+    pub fn codecs_in_use(&self) -> Vec<CodecUsage> {
+        let mut counts = [0u32; 4];
+        for entry in self.map.iter() {
+            let index = match entry {
+                MapEntry::V5Compressed(entry) => match entry.hunk_type() {
+                    Ok(
+                        ty @ (CompressionTypeV5::CompressionType0
+                        | CompressionTypeV5::CompressionType1
+                        | CompressionTypeV5::CompressionType2
+                        | CompressionTypeV5::CompressionType3),
+                    ) => ty as usize,
+                    _ => continue,
+                },
+                MapEntry::LegacyEntry(entry) => match entry.hunk_type() {
+                    Ok(CompressionTypeLegacy::Compressed) => 0,
+                    _ => continue,
+                },
+                MapEntry::V5Uncompressed(_) => continue,
+            };
+            counts[index] += 1;
+        }
+
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &hunks)| hunks != 0)
+            .map(|(index, &hunks)| CodecUsage {
+                index,
+                codec: self.codec_type(index),
+                fourcc: self.codec_tag(index),
+                hunks,
+            })
+            .collect()
+    }
+
+    /// Checks that the hunk map is consistent with the header, and that all stored hunk data is
+    /// within the bounds of the file, without reading or decompressing any hunk data.
+    ///
+    /// This is much faster than decompressing every hunk, and is useful for detecting gross
+    /// corruption such as truncated files. Hunks that pass this check may still fail to decompress.
+    /// See [`Map::check_integrity`](crate::map::Map::check_integrity).
+    pub fn quick_integrity_scan(&mut self) -> Result<IntegrityReport> {
+        let file_len = ChdIo::new(&mut self.file).stream_len()?;
+        Ok(self.map.check_integrity(&self.header, file_len))
+    }
+
     /// Returns a reference to the given hunk in this CHD file.
     ///
     /// If the requested hunk is larger than the number of hunks in the CHD file,
     /// returns `Error::HunkOutOfRange`.
-    pub fn hunk(&mut self, hunk_num: u32) -> Result<Hunk<F>> {
+    pub fn hunk(&mut self, hunk_num: u32) -> Result<Hunk<'_, F, P>> {
         if hunk_num >= self.header.hunk_count() {
             return Err(Error::HunkOutOfRange);
         }
@@ -117,21 +445,266 @@ impl<F: Read + Seek> Chd<F> {
         })
     }
 
+    /// Follows the chain of references to other hunks of this CHD file starting at the given hunk,
+    /// returning the first hunk of the chain that does not refer to another hunk of this CHD file.
+    ///
+    /// The chain is followed iteratively, so that a malicious map can not overflow the stack.
+    /// If the chain forms a cycle, returns [`Error::InvalidData`](crate::Error::InvalidData).
+    fn resolve_self_hunk(&self, mut hunk_num: u32) -> Result<u32> {
+        // a chain without cycles visits every hunk at most once.
+        for _ in 0..=self.header.hunk_count() {
+            let target = match self.map().try_get_entry(hunk_num as usize)? {
+                MapEntry::V5Compressed(entry)
+                    if matches!(entry.hunk_type(), Ok(CompressionTypeV5::CompressionSelf)) =>
+                {
+                    entry.self_target().ok_or(Error::HunkOutOfRange)?
+                }
+                MapEntry::LegacyEntry(entry)
+                    if matches!(entry.hunk_type(), Ok(CompressionTypeLegacy::SelfHunk)) =>
+                {
+                    hunk_index(entry.block_offset())?
+                }
+                _ => return Ok(hunk_num),
+            };
+            hunk_num = target;
+        }
+        Err(Error::InvalidData)
+    }
+
+    /// Returns the CRC that the hunk map of this CHD file records for a zero-filled hunk.
+    fn zero_hunk_crc(&mut self) -> HunkCrc {
+        let header = &self.header;
+        *self.zero_crc.get_or_insert_with(|| {
+            let zeros = vec![0u8; header.hunk_size() as usize];
+            match header {
+                Header::V5Header(_) => HunkCrc::Crc16(crate::block_hash::crc16(&zeros)),
+                _ => HunkCrc::Crc32(crate::block_hash::crc32(&zeros)),
+            }
+        })
+    }
+
+    /// Returns the offset of the given hunk in the file if its contents are stored verbatim,
+    /// and can be copied without decompression or verification.
+    fn raw_hunk_offset(&self, hunk_num: u32) -> Option<u64> {
+        let proof = match self.map.get_entry(hunk_num as usize)? {
+            MapEntry::V5Uncompressed(entry) => entry.prove_uncompressed().ok()?,
+            // uncompressed hunks of compressed CHD files have a CRC that needs to be verified.
+            #[cfg(not(feature = "verify_block_crc"))]
+            MapEntry::V5Compressed(entry) => entry.prove_uncompressed().ok()?,
+            #[cfg(not(feature = "verify_block_crc"))]
+            MapEntry::LegacyEntry(entry) => entry.prove_uncompressed().ok()?,
+            #[cfg(feature = "verify_block_crc")]
+            _ => return None,
+        };
+
+        // uncompressed V5 hunks at offset 0 are stored in the parent or are unallocated.
+        if proof.block_offset() == 0 || proof.block_size() != self.header.hunk_size() {
+            return None;
+        }
+        Some(proof.block_offset())
+    }
+
+    /// Reads the run of consecutive hunks starting at `first_hunk` that are stored verbatim and
+    /// contiguously in the file into `dest` with a single read, up to the length of `dest`.
+    ///
+    /// Returns the number of hunks read, which is 0 if `first_hunk` is not stored verbatim.
+    pub(crate) fn read_raw_hunks(&mut self, first_hunk: u32, dest: &mut [u8]) -> Result<u32> {
+        let hunk_size = self.header.hunk_size() as u64;
+        let offset = match self.raw_hunk_offset(first_hunk) {
+            Some(offset) => offset,
+            None => return Ok(0),
+        };
+
+        let max_hunks = std::cmp::min(
+            dest.len() as u64 / hunk_size,
+            self.header.hunk_count().saturating_sub(first_hunk) as u64,
+        ) as u32;
+        let mut hunks = 1;
+        while hunks < max_hunks
+            && self.raw_hunk_offset(first_hunk + hunks) == Some(offset + hunks as u64 * hunk_size)
+        {
+            hunks += 1;
+        }
+
+        ChdIo::new(&mut self.file)
+            .read_exact_at(offset, &mut dest[..(hunks as u64 * hunk_size) as usize])?;
+        Ok(hunks)
+    }
+
+    /// Installs an observer that is called for every hunk that is read from the data of this CHD file,
+    /// replacing any previously installed observer.
+    ///
+    /// Hunks that copy another hunk are reported as the hunk they copy, and hunks read from the
+    /// parent are only reported to the observer of the parent. Hunks decompressed in the background
+    /// by [`Chd::prefetch_units`](crate::Chd::prefetch_units) are not reported. When no observer is
+    /// installed, reading hunks is not timed.
+    pub fn set_decode_observer<O: DecodeObserver + 'static>(&mut self, observer: O) {
+        self.observer = Some(AssertUnwindSafe(Box::new(observer)));
+    }
+
+    /// Removes the installed decode observer, returning it if there was one.
+    pub fn clear_decode_observer(&mut self) -> Option<Box<dyn DecodeObserver>> {
+        self.observer.take().map(|observer| observer.0)
+    }
+
+    /// Returns which parts of each frame are decoded from hunks compressed with a CD-ROM codec.
+    pub fn sector_policy(&self) -> SectorPolicy {
+        self.codecs.sector_policy()
+    }
+
+    /// Sets which parts of each frame are decoded from hunks compressed with a CD-ROM codec,
+    /// for this CHD file and its parent chain.
+    ///
+    /// Hunks decoded by a CD-ROM codec that skips parts of each frame are not verified against
+    /// their block CRC, since it covers the whole frame. Hunks already decompressed in the background
+    /// by [`Chd::prefetch_units`](crate::Chd::prefetch_units) are discarded.
+    pub fn set_sector_policy(&mut self, policy: SectorPolicy) {
+        self.codecs.set_sector_policy(policy);
+        if let Some(parent) = self.parent.as_deref_mut() {
+            parent.set_sector_policy(policy);
+        }
+        #[cfg(feature = "parallel")]
+        {
+            self.prefetcher = None;
+        }
+    }
+
+    /// Returns the tag of the codec at the given index of the codecs of the CHD file, as declared
+    /// in the header.
+    fn codec_tag(&self, index: usize) -> Tag {
+        let codec = match &self.header {
+            Header::V5Header(h) => h.compression.get(index).copied().unwrap_or(0),
+            Header::V3Header(h) => h.compression,
+            Header::V4Header(h) => h.compression,
+            Header::V1Header(h) | Header::V2Header(h) => h.compression,
+        };
+        Tag::from(codec)
+    }
+
+    /// Returns the type of the codec at the given index of the codecs of the CHD file, or
+    /// [`CodecType::None`](crate::header::CodecType::None) if the codec is not known.
+    fn codec_type(&self, index: usize) -> CodecType {
+        match self.codecs.codec_type(index) {
+            Some(Ok(codec_type)) => codec_type,
+            _ => CodecType::None,
+        }
+    }
+
+    /// Reports a hunk read from the data of the CHD file to the decode observer, if reading it was
+    /// timed from `start`. Hunks without a codec index are reported as stored uncompressed.
+    fn observe(
+        &mut self,
+        start: Option<Instant>,
+        hunk_num: u32,
+        codec: Option<usize>,
+        compressed_len: usize,
+        decompressed_len: usize,
+    ) {
+        if let Some(start) = start {
+            let event = DecodeEvent {
+                hunk_num,
+                codec: codec.map_or(CodecType::None, |index| self.codec_type(index)),
+                compressed_len,
+                decompressed_len,
+                duration: start.elapsed(),
+            };
+            if let Some(observer) = self.observer.as_mut() {
+                observer.on_decode(&event);
+            }
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[cfg_attr(docsrs, doc(cfg(parallel)))]
+    /// Starts decompressing the hunks that contain the given range of units on a background thread,
+    /// so that reading them later does not wait for decompression.
+    ///
+    /// This is a hint for hunks that are about to be read, such as the sectors after a seek on a
+    /// CD-ROM. The compressed data of each hunk is read from the file before this returns, and only
+    /// decompression happens in the background. Reading a hunk that is still being decompressed waits
+    /// for it to finish. Prefetched hunks are held until they are read, up to a limit of 64 hunks,
+    /// after which further hunks are not prefetched.
+    ///
+    /// Only hunks that are compressed in this CHD file are prefetched. Hunks that are stored uncompressed,
+    /// or that copy another hunk or a hunk of the parent, are read as usual. Hunks whose compressed data
+    /// can not be read are skipped, and the error is returned when the hunk is read.
+    pub fn prefetch_units(&mut self, units: std::ops::Range<u64>) -> Result<()> {
+        let unit_bytes = self.header.unit_bytes() as u64;
+        let hunk_bytes = self.header.hunk_size() as u64;
+        if unit_bytes == 0 || hunk_bytes == 0 || units.is_empty() {
+            return Ok(());
+        }
+        let first_hunk = units.start.saturating_mul(unit_bytes) / hunk_bytes;
+        let end_hunk = std::cmp::min(
+            units.end.saturating_mul(unit_bytes).div_ceil(hunk_bytes),
+            self.header.hunk_count() as u64,
+        );
+
+        if self.prefetcher.is_none() {
+            let mut codecs = self.header.create_compression_codecs();
+            codecs.set_sector_policy(self.codecs.sector_policy());
+            self.prefetcher = Some(Prefetcher::new(codecs, self.header.hunk_size()));
+        }
+        let prefetcher = match self.prefetcher.as_ref() {
+            Some(prefetcher) => prefetcher,
+            None => return Ok(()),
+        };
+
+        for hunk_num in first_hunk..end_hunk {
+            let hunk_num = hunk_num as u32;
+            if prefetcher.is_full() {
+                break;
+            }
+            if prefetcher.contains(hunk_num) {
+                continue;
+            }
+            let (offset, length, codec) = match prefetch_source(&self.map, hunk_num) {
+                Some(source) => source,
+                None => continue,
+            };
+            let mut compressed = vec![0u8; length as usize];
+            match ChdIo::new(&mut self.file).read_at(offset, &mut compressed) {
+                Ok(read) if read == compressed.len() => {}
+                _ => continue,
+            }
+            let crc = self
+                .map
+                .hunk_crc(hunk_num as usize)
+                .filter(|_| self.codecs.verifies_crc(codec));
+            prefetcher.submit(PrefetchJob {
+                hunk_num,
+                codec,
+                crc,
+                compressed,
+            });
+        }
+        Ok(())
+    }
+
     /// Allocates a buffer with the same length as the hunk size of this CHD file.
     pub fn get_hunksized_buffer(&self) -> Vec<u8> {
         let hunk_size = self.header.hunk_size() as usize;
         vec![0u8; hunk_size]
     }
 
-    #[cfg_attr(docsrs, doc(cfg(unstable_lending_iterators)))]
-    #[cfg(feature = "unstable_lending_iterators")]
-    /// Returns an iterator over the hunks of this CHD file.
-    pub fn hunks(&mut self) -> Hunks<F> {
+    /// Returns a lending iterator over the hunks of this CHD file.
+    ///
+    /// See [`lending`](crate::lending) for how to iterate over the hunks.
+    pub fn hunks(&mut self) -> Hunks<'_, F, P> {
         Hunks::new(self)
     }
 
+    /// Returns an iterator over the decompressed contents of each hunk of this CHD file.
+    ///
+    /// A new buffer is allocated for each hunk. This is convenient for one-off tools, but
+    /// [`Hunk::read_hunk_in`](crate::Hunk::read_hunk_in) with reused buffers should be preferred
+    /// where performance matters.
+    pub fn hunk_bytes_iter(&mut self) -> HunkBytesIter<'_, F, P> {
+        HunkBytesIter::new(self)
+    }
+
     /// Consumes the `Chd` and returns the underlying reader and parent if present.
-    pub fn into_inner(self) -> (F, Option<Box<Chd<F>>>) {
+    pub fn into_inner(self) -> (F, Option<Box<Chd<P>>>) {
         (self.file, self.parent)
     }
 
@@ -141,35 +714,306 @@ impl<F: Read + Seek> Chd<F> {
     }
 
     /// Returns a mutable reference to the inner parent stream if present.
-    pub fn inner_parent(&mut self) -> Option<&mut F> {
+    pub fn inner_parent(&mut self) -> Option<&mut P> {
         self.parent.as_deref_mut().map(|f| f.inner())
     }
 }
 
+/// Options and flags which can be used to configure how a CHD file is opened.
+///
+/// Options are set with builder methods, and the CHD file is opened with
+/// [`ChdOpenOptions::open`](crate::ChdOpenOptions::open).
+///
+/// ```rust
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use chd::{Chd, ChdOpenOptions};
+///
+/// let parent = Chd::open(BufReader::new(File::open("parent.chd")?), None)?;
+/// let chd = ChdOpenOptions::new()
+///     .parent(Box::new(parent))
+///     .open(BufReader::new(File::open("child.chd")?))?;
+/// ```
+///
+/// The parent may be read from a different type of stream than the CHD file to open.
+///
+/// ```rust
+/// use std::fs::File;
+/// use std::io::{BufReader, Cursor};
+/// use chd::{Chd, ChdOpenOptions};
+///
+/// let parent = Chd::open(Cursor::new(std::fs::read("parent.chd")?), None)?;
+/// let chd = ChdOpenOptions::new()
+///     .parent(Box::new(parent))
+///     .open(BufReader::new(File::open("child.chd")?))?;
+/// ```
+pub struct ChdOpenOptions<F: Read + Seek, P: Read + Seek = F> {
+    parent: Option<Box<Chd<P>>>,
+    _file: PhantomData<F>,
+    validate_parent: bool,
+    validate_length: bool,
+    lazy_map: bool,
+    lenient: bool,
+    max_hunk_bytes: u32,
+    max_map_bytes: u64,
+    sector_policy: SectorPolicy,
+}
+
+/// The default maximum hunk size of CHD files opened with [`ChdOpenOptions`](crate::ChdOpenOptions).
+///
+/// This is large enough for the hunks of A/V CHD files of laserdisc captures.
+const DEFAULT_MAX_HUNK_BYTES: u32 = 4 * 1024 * 1024;
+
+/// The default maximum size of the hunk map of CHD files opened with [`ChdOpenOptions`](crate::ChdOpenOptions).
+///
+/// This is large enough for the map of a hard disk CHD file of over 100 GiB with 4 KiB hunks.
+const DEFAULT_MAX_MAP_BYTES: u64 = 512 * 1024 * 1024;
+
+impl<F: Read + Seek> Default for ChdOpenOptions<F> {
+    fn default() -> Self {
+        ChdOpenOptions::new()
+    }
+}
+
+impl<F: Read + Seek> ChdOpenOptions<F> {
+    /// Creates a blank set of options, with no parent and parent validation enabled.
+    pub fn new() -> Self {
+        ChdOpenOptions {
+            parent: None,
+            _file: PhantomData,
+            validate_parent: true,
+            validate_length: true,
+            lazy_map: false,
+            lenient: false,
+            max_hunk_bytes: DEFAULT_MAX_HUNK_BYTES,
+            max_map_bytes: DEFAULT_MAX_MAP_BYTES,
+            sector_policy: SectorPolicy::default(),
+        }
+    }
+}
+
+impl<F: Read + Seek, P: Read + Seek> ChdOpenOptions<F, P> {
+    /// Sets the parent CHD file of the CHD file to open.
+    ///
+    /// The parent may be read from a different type of stream than the CHD file to open.
+    /// If the CHD file does not require a parent, opening will fail with
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    pub fn parent<Q: Read + Seek>(self, parent: Box<Chd<Q>>) -> ChdOpenOptions<F, Q> {
+        ChdOpenOptions {
+            parent: Some(parent),
+            _file: PhantomData,
+            validate_parent: self.validate_parent,
+            validate_length: self.validate_length,
+            lazy_map: self.lazy_map,
+            lenient: self.lenient,
+            max_hunk_bytes: self.max_hunk_bytes,
+            max_map_bytes: self.max_map_bytes,
+            sector_policy: self.sector_policy,
+        }
+    }
+
+    /// Sets whether or not the hashes of the parent CHD file are validated against the
+    /// parent hashes recorded in the header of the CHD file to open. Enabled by default.
+    ///
+    /// Disabling validation allows a CHD file to be read with a parent that has been modified
+    /// without changing its contents, but a mismatched parent will silently produce corrupt data.
+    pub fn validate_parent(mut self, validate: bool) -> Self {
+        self.validate_parent = validate;
+        self
+    }
+
+    /// Sets whether or not the length of the stream is validated against the header and hunk map
+    /// when the CHD file is opened. Enabled by default.
+    ///
+    /// If the stream is shorter than the offsets of the map or metadata in the header, or than the
+    /// end of the last hunk stored in the file, opening will fail with [`Error::Truncated`](crate::Error::Truncated).
+    /// The hunk map is not checked if it is decoded on demand, since that would decode the whole map.
+    /// Use [`Map::required_length`](crate::map::Map::required_length) to check it after opening.
+    ///
+    /// Disabling validation allows the hunks present in a truncated CHD file to be read. Reading a hunk
+    /// past the end of the stream will then fail with [`Error::ReadError`](crate::Error::ReadError).
+    pub fn validate_length(mut self, validate: bool) -> Self {
+        self.validate_length = validate;
+        self
+    }
+
+    /// Sets whether or not the hunk map is decoded on demand. Disabled by default.
+    ///
+    /// Decoding the map on demand reduces the time to open and the memory used by CHD files with
+    /// a very large number of hunks, at the cost of slower random access to hunks. The map checksum
+    /// is not verified when the map is decoded on demand, and neither is the length of the stream
+    /// against the hunks in the map (see [`validate_length`](ChdOpenOptions::validate_length)).
+    /// Only compressed V5 maps are decoded on demand.
+    /// See [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy) for details.
+    pub fn lazy_map(mut self, lazy: bool) -> Self {
+        self.lazy_map = lazy;
+        self
+    }
+
+    /// Sets whether or not degenerate entries in V1-4 hunk maps and headers are tolerated. Disabled by default.
+    ///
+    /// Some old CHD files contain compressed hunks with a length of 0. When enabled, these hunks
+    /// are read as zero-filled hunks, matching the behaviour of MAME. Otherwise, reading such a hunk
+    /// fails with [`Error::InvalidData`](crate::Error::InvalidData).
+    ///
+    /// Some CHD files written by buggy tools have a hunk count that is too small for their logical size.
+    /// When enabled, these CHD files can be opened, and only the data up to the end of the last hunk
+    /// can be read, as given by [`Header::readable_bytes`](crate::header::Header::readable_bytes).
+    /// Otherwise, opening such a file fails with [`Error::HunkCountMismatch`](crate::Error::HunkCountMismatch).
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Sets the maximum hunk size in bytes of the CHD file to open. Defaults to 4 MiB.
+    ///
+    /// Each codec of a CHD file allocates buffers of the size of a hunk when it first decompresses
+    /// a hunk, and reading hunks requires buffers of the same size. If the hunk size in the header
+    /// is larger than this limit, opening will fail with [`Error::OutOfMemory`](crate::Error::OutOfMemory)
+    /// so that these buffers are never allocated.
+    ///
+    /// When opening CHD files from untrusted sources, this can be lowered to the largest hunk size
+    /// expected, such as 19584 bytes for CD-ROM images.
+    pub fn max_hunk_bytes(mut self, max: u32) -> Self {
+        self.max_hunk_bytes = max;
+        self
+    }
+
+    /// Sets the maximum size in bytes of the hunk map read when the CHD file is opened.
+    /// Defaults to 512 MiB.
+    ///
+    /// This limits the size of both the compressed map read from the file and the decoded map.
+    /// If the map of the CHD file is larger than this limit, opening will fail with
+    /// [`Error::OutOfMemory`](crate::Error::OutOfMemory) instead of allocating the map.
+    pub fn max_map_bytes(mut self, max: u64) -> Self {
+        self.max_map_bytes = max;
+        self
+    }
+
+    /// Sets which parts of each frame are decoded from hunks compressed with a CD-ROM codec.
+    /// Defaults to [`SectorPolicy::default`](crate::cdrom::SectorPolicy::default), which depends
+    /// on the `want_subcode` and `want_raw_data_sector` features.
+    ///
+    /// The policy also applies to the parent, if one is set.
+    /// See [`Chd::set_sector_policy`](crate::Chd::set_sector_policy) for details.
+    pub fn sector_policy(mut self, policy: SectorPolicy) -> Self {
+        self.sector_policy = policy;
+        self
+    }
+
+    /// Opens a CHD file from a `Read + Seek` stream with the options in `self`.
+    ///
+    /// The CHD header and hunk map are read and validated immediately.
+    /// See [`Chd::open`](crate::Chd::open) for the errors that may be returned.
+    pub fn open(self, mut file: F) -> Result<Chd<F, P>> {
+        let (header, metadata_cache) = Header::try_read_header_with_metadata(&mut file)?;
+        if header.hunk_size() > self.max_hunk_bytes {
+            return Err(Error::OutOfMemory);
+        }
+        if !self.lenient && header.readable_bytes() < header.logical_bytes() {
+            return Err(Error::HunkCountMismatch);
+        }
+        // No point in checking writable because traits are read only.
+        // In the future if we want to support a Write feature, will need to ensure writable.
+
+        if let Some(p) = self.parent.as_ref() {
+            if !header.has_parent() {
+                return Err(Error::InvalidParameter);
+            }
+            if self.validate_parent {
+                header.validate_parent(p.header())?;
+            }
+        }
+
+        let file_len = if self.validate_length {
+            let file_len = ChdIo::new(&mut file).stream_len()?;
+            // the map and the first metadata entry must begin within the file.
+            let expected = header
+                .meta_offset()
+                .map_or(header.map_offset(), |o| o.max(header.map_offset()))
+                .saturating_add(1);
+            if expected > file_len {
+                return Err(Error::Truncated {
+                    expected,
+                    actual: file_len,
+                });
+            }
+            Some(file_len)
+        } else {
+            None
+        };
+
+        let map = Map::try_read_map_inner(&header, &mut file, self.lazy_map, self.max_map_bytes)?;
+
+        if let Some(file_len) = file_len.filter(|_| !self.lazy_map) {
+            let expected = map.required_length()?;
+            if expected > file_len {
+                return Err(Error::Truncated {
+                    expected,
+                    actual: file_len,
+                });
+            }
+        }
+        let mut codecs = AssertUnwindSafe(header.create_compression_codecs());
+        codecs.set_sector_policy(self.sector_policy);
+        let mut parent = self.parent;
+        if let Some(parent) = parent.as_deref_mut() {
+            parent.set_sector_policy(self.sector_policy);
+        }
+
+        Ok(Chd {
+            file,
+            header,
+            parent,
+            map,
+            lenient: self.lenient,
+            codecs,
+            observer: None,
+            zero_crc: None,
+            metadata_cache,
+            #[cfg(feature = "parallel")]
+            prefetcher: None,
+        })
+    }
+}
+
+/// Converts a hunk number stored in a map entry to a hunk index.
+///
+/// If the hunk number can not be a valid hunk index, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange).
+fn hunk_index(hunk_num: u64) -> Result<u32> {
+    u32::try_from(hunk_num).map_err(|_| Error::HunkOutOfRange)
+}
+
 /// A reference to a compressed Hunk in a CHD file.
-pub struct Hunk<'a, F: Read + Seek> {
-    inner: &'a mut Chd<F>,
+pub struct Hunk<'a, F: Read + Seek, P: Read + Seek = F> {
+    inner: &'a mut Chd<F, P>,
     hunk_num: u32,
 }
 
-impl<'a, F: Read + Seek> Hunk<'a, F> {
-    /// Buffer the compressed bytes into the hunk buffer.
-    fn read_compressed_in(
+impl<'a, F: Read + Seek, P: Read + Seek> Hunk<'a, F, P> {
+    /// Buffer the compressed bytes into the hunk buffer, returning the buffered bytes.
+    ///
+    /// The buffer is only grown and never shrunk, so that it is not zeroed again for every
+    /// hunk when the lengths of compressed hunks vary. Bytes past the returned slice are left
+    /// over from earlier hunks.
+    fn read_compressed_in<'b>(
         &mut self,
         map_entry: CompressedEntryProof,
-        comp_buf: &mut Vec<u8>,
-    ) -> Result<()> {
+        comp_buf: &'b mut Vec<u8>,
+    ) -> Result<&'b [u8]> {
         let offset = map_entry.block_offset();
-        let length = map_entry.block_size();
+        let length = map_entry.block_size() as usize;
 
-        comp_buf.resize(length as usize, 0);
+        if comp_buf.len() < length {
+            comp_buf.resize(length, 0);
+        }
+        let comp_buf = &mut comp_buf[..length];
 
-        self.inner.file.seek(SeekFrom::Start(offset))?;
-        let read = self.inner.file.read(comp_buf)?;
-        if read != length as usize {
+        let read = ChdIo::new(&mut self.inner.file).read_at(offset, comp_buf)?;
+        if read != length {
             return Err(Error::ReadError);
         }
-        Ok(())
+        Ok(comp_buf)
     }
 
     fn read_uncompressed(
@@ -183,17 +1027,15 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
         if dest.len() != length as usize {
             return Err(Error::InvalidParameter);
         }
-        self.inner.file.seek(SeekFrom::Start(offset))?;
-        let read = self.inner.file.read(dest)?;
+        let read = ChdIo::new(&mut self.inner.file).read_at(offset, dest)?;
+        if read != length as usize {
+            return Err(Error::ReadError);
+        }
         Ok(read)
     }
 
     fn read_hunk_legacy(&mut self, comp_buf: &mut Vec<u8>, dest: &mut [u8]) -> Result<usize> {
-        let map_entry = self
-            .inner
-            .map()
-            .get_entry(self.hunk_num as usize)
-            .ok_or(Error::HunkOutOfRange)?;
+        let map_entry = self.inner.map().try_get_entry(self.hunk_num as usize)?;
 
         match map_entry {
             MapEntry::LegacyEntry(entry) => {
@@ -202,22 +1044,34 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
                 let block_off = entry.block_offset();
 
                 match entry.hunk_type()? {
+                    CompressionTypeLegacy::Compressed if block_len == 0 => {
+                        // degenerate hunks are zero-filled.
+                        if !self.inner.lenient {
+                            return Err(Error::InvalidData);
+                        }
+                        dest.fill(0);
+                        Ok(dest.len())
+                    }
                     CompressionTypeLegacy::Compressed => {
                         // buffer the compressed data
                         let proof = entry.prove_compressed()?;
-                        self.read_compressed_in(proof, comp_buf)?;
-                        let res = &self
-                            .inner
-                            .codecs
-                            .first_mut()
-                            .decompress(&comp_buf[..block_len], dest)?;
+                        let compressed = self.read_compressed_in(proof, comp_buf)?;
+                        let start = self.inner.observer.is_some().then(Instant::now);
+                        let res = &self.inner.codecs.get_mut(0)?.decompress(compressed, dest)?;
 
-                        Crc::<u32>::verify_block_checksum(block_crc, dest, res.total_out())
+                        let read =
+                            Crc::<u32>::verify_block_checksum(block_crc, dest, res.total_out())?;
+                        self.inner
+                            .observe(start, self.hunk_num, Some(0), block_len, read);
+                        Ok(read)
                     }
                     CompressionTypeLegacy::Uncompressed => {
+                        let start = self.inner.observer.is_some().then(Instant::now);
                         let proof = entry.prove_uncompressed()?;
                         let res = self.read_uncompressed(proof, dest)?;
-                        Crc::<u32>::verify_block_checksum(block_crc, dest, res)
+                        let read = Crc::<u32>::verify_block_checksum(block_crc, dest, res)?;
+                        self.inner.observe(start, self.hunk_num, None, read, read);
+                        Ok(read)
                     }
                     CompressionTypeLegacy::Mini => {
                         let mut cursor = Cursor::new(dest);
@@ -236,14 +1090,15 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
                         Crc::<u32>::verify_block_checksum(block_crc, dest, bytes_read_into)
                     }
                     CompressionTypeLegacy::SelfHunk => {
-                        let mut self_hunk = self.inner.hunk(block_off as u32)?;
+                        let target = self.inner.resolve_self_hunk(hunk_index(block_off)?)?;
+                        let mut self_hunk = self.inner.hunk(target)?;
                         let res = self_hunk.read_hunk_in(comp_buf, dest)?;
                         Ok(res)
                     }
                     CompressionTypeLegacy::ParentHunk => match self.inner.parent.as_deref_mut() {
                         None => Err(Error::RequiresParent),
                         Some(parent) => {
-                            let mut parent = parent.hunk(block_off as u32)?;
+                            let mut parent = parent.hunk(hunk_index(block_off)?)?;
                             let res = parent.read_hunk_in(comp_buf, dest)?;
                             Ok(res)
                         }
@@ -257,66 +1112,81 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
     }
 
     fn read_hunk_v5(&mut self, comp_buf: &mut Vec<u8>, dest: &mut [u8]) -> Result<usize> {
-        let map_entry = self
-            .inner
-            .map()
-            .get_entry(self.hunk_num as usize)
-            .ok_or(Error::HunkOutOfRange)?;
+        let map_entry = self.inner.map().try_get_entry(self.hunk_num as usize)?;
 
         let has_parent = self.inner.header.has_parent();
 
         match map_entry {
             MapEntry::V5Compressed(entry) => {
-                let block_off = entry.block_offset()?;
                 let block_crc = Some(entry.hunk_crc()?);
                 match entry.hunk_type()? {
-                    comptype @ CompressionTypeV5::CompressionType0
-                    | comptype @ CompressionTypeV5::CompressionType1
-                    | comptype @ CompressionTypeV5::CompressionType2
-                    | comptype @ CompressionTypeV5::CompressionType3 => {
+                    CompressionTypeV5::CompressionType0
+                    | CompressionTypeV5::CompressionType1
+                    | CompressionTypeV5::CompressionType2
+                    | CompressionTypeV5::CompressionType3 => {
+                        let index = entry.codec_index().ok_or(Error::UnsupportedFormat)?;
                         // buffer the compressed data
                         let proof = entry.prove_compressed()?;
 
-                        self.read_compressed_in(proof, comp_buf)?;
+                        let compressed = self.read_compressed_in(proof, comp_buf)?;
+                        let block_crc = block_crc.filter(|_| self.inner.codecs.verifies_crc(index));
 
-                        if let Some(codec) = self.inner.codecs.get_mut(comptype.to_usize().unwrap())
-                        {
-                            let res = codec.decompress(comp_buf, dest)?;
-                            Crc::<u16>::verify_block_checksum(block_crc, dest, res.total_out())
-                        } else {
-                            Err(Error::UnsupportedFormat)
-                        }
+                        let start = self.inner.observer.is_some().then(Instant::now);
+                        let res = self
+                            .inner
+                            .codecs
+                            .get_mut(index)?
+                            .decompress(compressed, dest)?;
+                        let read =
+                            Crc::<u16>::verify_block_checksum(block_crc, dest, res.total_out())?;
+                        self.inner.observe(
+                            start,
+                            self.hunk_num,
+                            Some(index),
+                            compressed.len(),
+                            read,
+                        );
+                        Ok(read)
                     }
                     CompressionTypeV5::CompressionNone => {
+                        let start = self.inner.observer.is_some().then(Instant::now);
                         let proof = entry.prove_uncompressed()?;
                         let res = self.read_uncompressed(proof, dest)?;
-                        Crc::<u16>::verify_block_checksum(block_crc, dest, res)
+                        let read = Crc::<u16>::verify_block_checksum(block_crc, dest, res)?;
+                        self.inner.observe(start, self.hunk_num, None, read, read);
+                        Ok(read)
                     }
                     CompressionTypeV5::CompressionSelf => {
-                        let mut self_hunk = self.inner.hunk(block_off as u32)?;
+                        let target = entry.self_target().ok_or(Error::HunkOutOfRange)?;
+                        let target = self.inner.resolve_self_hunk(target)?;
+                        let mut self_hunk = self.inner.hunk(target)?;
                         let res = self_hunk.read_hunk_in(comp_buf, dest)?;
                         Ok(res)
                     }
                     CompressionTypeV5::CompressionParent => {
                         let hunk_bytes = self.inner.header().hunk_size();
                         let unit_bytes = self.inner.header().unit_bytes();
-                        let units_in_hunk = hunk_bytes / unit_bytes;
+                        let units_in_hunk = (hunk_bytes / unit_bytes) as u64;
+                        if units_in_hunk == 0 {
+                            return Err(Error::InvalidData);
+                        }
+                        let parent_unit = entry.parent_unit_offset().ok_or(Error::InvalidData)?;
+                        let parent_hunk_num = hunk_index(parent_unit / units_in_hunk)?;
 
                         match self.inner.parent.as_deref_mut() {
                             None => Err(Error::RequiresParent),
                             Some(parent) => {
                                 let mut buf = vec![0u8; hunk_bytes as usize];
 
-                                let mut parent_hunk =
-                                    parent.hunk(block_off as u32 / units_in_hunk)?;
+                                let mut parent_hunk = parent.hunk(parent_hunk_num)?;
                                 let res_1 = parent_hunk.read_hunk_in(comp_buf, &mut buf)?;
 
-                                if block_off % units_in_hunk as u64 == 0 {
+                                if parent_unit % units_in_hunk == 0 {
                                     dest.copy_from_slice(&buf);
                                     return Ok(res_1);
                                 }
 
-                                let remainder_in_hunk = block_off as usize % units_in_hunk as usize;
+                                let remainder_in_hunk = (parent_unit % units_in_hunk) as usize;
                                 let hunk_split = (units_in_hunk as usize - remainder_in_hunk)
                                     * unit_bytes as usize;
 
@@ -324,19 +1194,19 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
                                     &buf[remainder_in_hunk * unit_bytes as usize..][..hunk_split],
                                 );
 
-                                let mut parent_hunk =
-                                    parent.hunk((block_off as u32 / units_in_hunk) + 1)?;
+                                let mut parent_hunk = parent.hunk(
+                                    parent_hunk_num
+                                        .checked_add(1)
+                                        .ok_or(Error::HunkOutOfRange)?,
+                                )?;
                                 let _res_2 = parent_hunk.read_hunk_in(comp_buf, &mut buf)?;
 
                                 dest[hunk_split..].copy_from_slice(
                                     &buf[..remainder_in_hunk
                                         * self.inner.header().unit_bytes() as usize],
                                 );
-                                Crc::<u16>::verify_block_checksum(
-                                    block_crc,
-                                    dest,
-                                    hunk_split + remainder_in_hunk * unit_bytes as usize,
-                                )
+                                // parent entries do not record a CRC, so there is nothing to verify.
+                                Ok(hunk_split + remainder_in_hunk * unit_bytes as usize)
                             }
                         }
                     }
@@ -383,6 +1253,12 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
     /// If the provided output buffer is the wrong length, this will return
     /// If the hunk refers to a parent CHD that was not provided, this will return
     /// [`Error::OutOfMemory`](crate::Error::OutOfMemory).
+    ///
+    /// Each codec of the CHD file is initialized when the first hunk compressed with it is read.
+    /// If the codec can not be initialized, this will return [`Error::CodecError`](crate::Error::CodecError).
+    ///
+    /// If the hunk refers to other hunks of the CHD file in a cycle, this will return
+    /// [`Error::InvalidData`](crate::Error::InvalidData).
     pub fn read_hunk_in(
         &mut self,
         compressed_buffer: &mut Vec<u8>,
@@ -392,21 +1268,152 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
             return Err(Error::OutOfMemory);
         }
 
+        #[cfg(feature = "parallel")]
+        if let Some(res) = self
+            .inner
+            .prefetcher
+            .as_ref()
+            .and_then(|prefetcher| prefetcher.take(self.hunk_num, output))
+        {
+            return res;
+        }
+
         match self.inner.map() {
             Map::V5(_) => self.read_hunk_v5(compressed_buffer, output),
             Map::Legacy(_) => self.read_hunk_legacy(compressed_buffer, output),
         }
     }
 
+    /// Reads `output.len()` bytes of the decompressed hunk, starting at `offset` bytes into the hunk.
+    ///
+    /// Hunks that are stored uncompressed, filled with a repeated pattern, or copies of other hunks
+    /// are read directly from the CHD file without reading the rest of the hunk. Otherwise, the whole
+    /// hunk is decompressed into `hunk_buffer`, which is resized to the hunk size of the CHD file, using
+    /// `compressed_buffer` to hold the compressed hunk as in [`read_hunk_in`](crate::Hunk::read_hunk_in).
+    /// Hunk checksums are not verified when only part of a hunk is read.
+    ///
+    /// Returns the number of bytes read on success, which should be the length of the output buffer.
+    ///
+    /// If the range is not within the hunk, this will return [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    pub fn read_range_in(
+        &mut self,
+        offset: usize,
+        compressed_buffer: &mut Vec<u8>,
+        hunk_buffer: &mut Vec<u8>,
+        output: &mut [u8],
+    ) -> Result<usize> {
+        let hunk_size = self.inner.header.hunk_size() as usize;
+        let end = offset
+            .checked_add(output.len())
+            .ok_or(Error::InvalidParameter)?;
+        if end > hunk_size {
+            return Err(Error::InvalidParameter);
+        }
+
+        let map_entry = self.inner.map().try_get_entry(self.hunk_num as usize)?;
+
+        match map_entry {
+            MapEntry::LegacyEntry(entry) => match entry.hunk_type()? {
+                CompressionTypeLegacy::Uncompressed => {
+                    let block_off = entry.prove_uncompressed()?.block_offset();
+                    self.read_stored_range(block_off + offset as u64, output)
+                }
+                CompressionTypeLegacy::Mini => {
+                    let pattern = entry.block_offset().to_be_bytes();
+                    for (index, byte) in output.iter_mut().enumerate() {
+                        *byte = pattern[(offset + index) % pattern.len()];
+                    }
+                    Ok(output.len())
+                }
+                CompressionTypeLegacy::SelfHunk => {
+                    let target = self
+                        .inner
+                        .resolve_self_hunk(hunk_index(entry.block_offset())?)?;
+                    let mut self_hunk = self.inner.hunk(target)?;
+                    self_hunk.read_range_in(offset, compressed_buffer, hunk_buffer, output)
+                }
+                CompressionTypeLegacy::ParentHunk => {
+                    let parent_hunk = hunk_index(entry.block_offset())?;
+                    match self.inner.parent.as_deref_mut() {
+                        None => Err(Error::RequiresParent),
+                        Some(parent) => parent.hunk(parent_hunk)?.read_range_in(
+                            offset,
+                            compressed_buffer,
+                            hunk_buffer,
+                            output,
+                        ),
+                    }
+                }
+                _ => self.read_range_decompressed(offset, compressed_buffer, hunk_buffer, output),
+            },
+            MapEntry::V5Compressed(entry) => match entry.hunk_type()? {
+                CompressionTypeV5::CompressionNone => {
+                    let block_off = entry.prove_uncompressed()?.block_offset();
+                    self.read_stored_range(block_off + offset as u64, output)
+                }
+                CompressionTypeV5::CompressionSelf => {
+                    let target = entry.self_target().ok_or(Error::HunkOutOfRange)?;
+                    let target = self.inner.resolve_self_hunk(target)?;
+                    let mut self_hunk = self.inner.hunk(target)?;
+                    self_hunk.read_range_in(offset, compressed_buffer, hunk_buffer, output)
+                }
+                _ => self.read_range_decompressed(offset, compressed_buffer, hunk_buffer, output),
+            },
+            MapEntry::V5Uncompressed(entry) => {
+                match (entry.block_offset()?, self.inner.header.has_parent()) {
+                    (0, false) => {
+                        output.fill(0);
+                        Ok(output.len())
+                    }
+                    (0, true) => {
+                        let hunk_num = self.hunk_num;
+                        match self.inner.parent.as_deref_mut() {
+                            None => Err(Error::RequiresParent),
+                            Some(parent) => parent.hunk(hunk_num)?.read_range_in(
+                                offset,
+                                compressed_buffer,
+                                hunk_buffer,
+                                output,
+                            ),
+                        }
+                    }
+                    (_, _) => {
+                        let block_off = entry.prove_uncompressed()?.block_offset();
+                        self.read_stored_range(block_off + offset as u64, output)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads bytes of a hunk stored uncompressed at the given offset in the CHD file.
+    fn read_stored_range(&mut self, offset: u64, output: &mut [u8]) -> Result<usize> {
+        let read = ChdIo::new(&mut self.inner.file).read_at(offset, output)?;
+        if read != output.len() {
+            return Err(Error::ReadError);
+        }
+        Ok(read)
+    }
+
+    /// Reads a range of a hunk by decompressing the whole hunk into the hunk buffer.
+    fn read_range_decompressed(
+        &mut self,
+        offset: usize,
+        compressed_buffer: &mut Vec<u8>,
+        hunk_buffer: &mut Vec<u8>,
+        output: &mut [u8],
+    ) -> Result<usize> {
+        hunk_buffer.resize(self.inner.header.hunk_size() as usize, 0);
+        self.read_hunk_in(compressed_buffer, hunk_buffer)?;
+        output.copy_from_slice(&hunk_buffer[offset..][..output.len()]);
+        Ok(output.len())
+    }
+
     /// Read the raw, compressed contents of the hunk into the provided buffer.
     ///
     /// Returns the number of bytes read on success.
     pub fn read_raw_in(&mut self, output: &mut Vec<u8>) -> Result<usize> {
-        let map_entry = self
-            .inner
-            .map()
-            .get_entry(self.hunk_num as usize)
-            .ok_or(Error::HunkOutOfRange)?;
+        let map_entry = self.inner.map().try_get_entry(self.hunk_num as usize)?;
 
         let (offset, size) = match map_entry {
             MapEntry::V5Compressed(map_entry) => {
@@ -419,42 +1426,1349 @@ impl<'a, F: Read + Seek> Hunk<'a, F> {
         };
 
         output.resize(size as usize, 0);
-        self.inner.file.seek(SeekFrom::Start(offset))?;
-        let read = self.inner.file.read(output)?;
+        let read = ChdIo::new(&mut self.inner.file).read_at(offset, output)?;
         Ok(read)
     }
 
+    /// Returns a reference to the same hunk that borrows from this one.
+    pub(crate) fn reborrow(&mut self) -> Hunk<'_, F, P> {
+        Hunk {
+            inner: self.inner,
+            hunk_num: self.hunk_num,
+        }
+    }
+
     #[allow(clippy::len_without_is_empty)]
     /// Returns the length of this hunk in bytes.
     pub fn len(&self) -> usize {
         self.inner.header.hunk_size() as usize
     }
+
+    /// Returns whether the contents of this hunk are all zeroes, if that can be determined from
+    /// the hunk map alone without reading or decompressing the hunk.
+    ///
+    /// Returns `Some(true)` for unallocated hunks of uncompressed V5 CHD files without a parent,
+    /// and for V1-4 hunks stored in the map whose value is zero. Returns `Some(false)` if the CRC
+    /// recorded in the hunk map differs from the CRC of a zero-filled hunk. Otherwise, such as for
+    /// hunks that refer to another hunk or to the parent, or hunks whose recorded CRC matches,
+    /// returns `None`, and the hunk must be read to know its contents.
+    pub fn is_zero_filled(&mut self) -> Option<bool> {
+        let entry = self.inner.map.get_entry(self.hunk_num as usize)?;
+        let crc = match entry {
+            MapEntry::V5Uncompressed(entry) => {
+                return match entry.block_offset().ok()? {
+                    0 if !self.inner.header.has_parent() => Some(true),
+                    _ => None,
+                };
+            }
+            MapEntry::LegacyEntry(entry)
+                if matches!(entry.hunk_type(), Ok(CompressionTypeLegacy::Mini)) =>
+            {
+                return Some(entry.block_offset() == 0);
+            }
+            _ => self.inner.map.hunk_crc(self.hunk_num as usize)?,
+        };
+        match self.inner.zero_hunk_crc() == crc {
+            true => None,
+            false => Some(false),
+        }
+    }
 }
 
-pub(crate) enum Codecs {
-    Single(Box<dyn CompressionCodec>),
-    Four([Box<dyn CompressionCodec>; 4]),
+/// Returns the offset, length and codec index of the compressed data of the hunk, if it is
+/// compressed in the CHD file.
+#[cfg(feature = "parallel")]
+fn prefetch_source(map: &Map, hunk_num: u32) -> Option<(u64, u32, usize)> {
+    match map.get_entry(hunk_num as usize)? {
+        MapEntry::V5Compressed(entry) => {
+            let codec = entry.codec_index()?;
+            let proof = entry.prove_compressed().ok()?;
+            Some((proof.block_offset(), proof.block_size(), codec))
+        }
+        MapEntry::LegacyEntry(entry) => match entry.hunk_type().ok()? {
+            CompressionTypeLegacy::Compressed if entry.block_size() != 0 => {
+                let proof = entry.prove_compressed().ok()?;
+                Some((proof.block_offset(), proof.block_size(), 0))
+            }
+            _ => None,
+        },
+        MapEntry::V5Uncompressed(_) => None,
+    }
+}
+
+/// The codecs of a CHD file.
+///
+/// Codecs allocate buffers of the size of a hunk, so each codec is only initialized the first
+/// time a hunk compressed with it is read. Codecs that are not known are kept by their tag, and
+/// fail when a hunk compressed with them is read.
+pub(crate) struct Codecs {
+    types: ArrayVec<std::result::Result<CodecType, Tag>, 4>,
+    codecs: [Option<Box<dyn CompressionCodec>>; 4],
+    hunk_size: u32,
+    policy: SectorPolicy,
 }
 
 impl Codecs {
-    pub fn first_mut(&mut self) -> &mut Box<dyn CompressionCodec> {
-        match self {
-            Codecs::Single(c) => c,
-            Codecs::Four([c, ..]) => c,
+    /// Creates the codecs of the given types for the given hunk size, without initializing them.
+    pub fn new(types: ArrayVec<std::result::Result<CodecType, Tag>, 4>, hunk_size: u32) -> Codecs {
+        Codecs {
+            types,
+            codecs: [None, None, None, None],
+            hunk_size,
+            policy: SectorPolicy::default(),
+        }
+    }
+
+    /// Returns the type of the codec at the given index, or its tag if the codec is not known.
+    pub fn codec_type(&self, index: usize) -> Option<std::result::Result<CodecType, Tag>> {
+        self.types.get(index).copied()
+    }
+
+    /// Returns the codec at the given index, initializing it if it has not been used yet.
+    ///
+    /// Codecs that are not known or not supported return [`Error::UnsupportedCodec`](crate::Error::UnsupportedCodec).
+    pub fn get_mut(&mut self, index: usize) -> Result<&mut Box<dyn CompressionCodec>> {
+        let codec_type = match self.codec_type(index) {
+            Some(Ok(codec_type)) => codec_type,
+            Some(Err(fourcc)) => return Err(Error::UnsupportedCodec { fourcc }),
+            None => return Err(Error::UnsupportedFormat),
+        };
+        if self.codecs[index].is_none() {
+            let mut codec = codec_type.init(self.hunk_size)?;
+            codec.set_sector_policy(self.policy);
+            self.codecs[index] = Some(codec);
         }
+        self.codecs[index].as_mut().ok_or(Error::CodecError)
     }
 
-    pub fn get_mut(&mut self, index: usize) -> Option<&mut Box<dyn CompressionCodec>> {
-        if index == 0 {
-            match self {
-                Codecs::Single(c) => Some(c),
-                Codecs::Four([c, ..]) => Some(c),
+    /// Returns the sector policy of the CD-ROM codecs.
+    pub fn sector_policy(&self) -> SectorPolicy {
+        self.policy
+    }
+
+    /// Sets the sector policy of the CD-ROM codecs, including those already initialized.
+    pub fn set_sector_policy(&mut self, policy: SectorPolicy) {
+        self.policy = policy;
+        for codec in self.codecs.iter_mut().flatten() {
+            codec.set_sector_policy(policy);
+        }
+    }
+
+    /// Returns whether hunks decompressed by the codec at the given index can be verified against
+    /// their block CRC, which is not the case for CD-ROM codecs that skip parts of each frame.
+    pub fn verifies_crc(&self, index: usize) -> bool {
+        self.policy == SectorPolicy::Full
+            || !self
+                .codec_type(index)
+                .is_some_and(|t| t.is_ok_and(|t| t.is_cd()))
+    }
+
+    /// Returns the number of bytes held by the buffers of the codecs that have been initialized.
+    pub fn buffer_bytes(&self) -> usize {
+        self.codecs.iter().flatten().map(|c| c.buffer_bytes()).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::header::CodecType;
+    use crate::map::{IntegrityIssue, Map};
+    use crate::metadata::{KnownMetadata, MetadataTag};
+    use crate::read::ChdReader;
+    use crate::testing::{
+        v2_chd, v3_chd, v5_chd, v5_uncompressed_chd, Fault, FaultyReader, LegacyHunk, V5Hunk,
+    };
+    use crate::{Chd, ChdOpenOptions, DecodeEvent, Error, Result, Tag};
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
+    use std::sync::{Arc, Mutex};
+
+    /// Synthesizes a V2 CHD file with one uncompressed hunk, and one compressed hunk of length 0.
+    fn degenerate_v2_chd() -> Vec<u8> {
+        const HUNK_BYTES: u64 = 512;
+        let mut chd = vec![0u8; 80];
+        chd[0..8].copy_from_slice(b"MComprHD");
+        for (offset, value) in [
+            (8, 80), // header length
+            (12, 2), // version
+            (20, 1), // zlib
+            (24, 1), // sectors per hunk
+            (28, 2), // total hunks
+            (32, 1), // cylinders
+            (36, 1), // heads
+            (40, 2), // sectors
+            (76, HUNK_BYTES as u32),
+        ] {
+            chd[offset..offset + 4].copy_from_slice(&u32::to_be_bytes(value));
+        }
+
+        let data_offset = 80 + 3 * 8;
+        chd.extend_from_slice(&((HUNK_BYTES << 44) | data_offset).to_be_bytes());
+        chd.extend_from_slice(&0u64.to_be_bytes());
+        chd.extend_from_slice(b"EndOfLis");
+        chd.resize(chd.len() + HUNK_BYTES as usize, 0xa5);
+        chd
+    }
+
+    #[test]
+    fn legacy_zero_length_hunk_test() {
+        let mut chd = ChdOpenOptions::new()
+            .open(Cursor::new(degenerate_v2_chd()))
+            .expect("open");
+        let mut buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        chd.hunk(0)
+            .and_then(|mut h| h.read_hunk_in(&mut cmp_buf, &mut buf))
+            .expect("hunk 0");
+        assert!(buf.iter().all(|&b| b == 0xa5));
+        assert!(matches!(
+            chd.hunk(1)
+                .and_then(|mut h| h.read_hunk_in(&mut cmp_buf, &mut buf)),
+            Err(Error::InvalidData)
+        ));
+
+        let mut chd = ChdOpenOptions::new()
+            .lenient(true)
+            .open(Cursor::new(degenerate_v2_chd()))
+            .expect("open");
+        chd.hunk(1)
+            .and_then(|mut h| h.read_hunk_in(&mut cmp_buf, &mut buf))
+            .expect("hunk 1");
+        assert!(buf.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn v2_odd_geometry_test() {
+        // 2352-byte sectors with 5 sectors per hunk.
+        const HUNK_BYTES: usize = 2352 * 5;
+        let compressed: Vec<u8> = (0..HUNK_BYTES).map(|i| (i % 251) as u8).collect();
+        let uncompressed: Vec<u8> = (0..HUNK_BYTES).map(|i| (i * 7 % 256) as u8).collect();
+        let chd = v2_chd(
+            2352,
+            5,
+            [2, 3, 1],
+            &[
+                LegacyHunk::Zlib(compressed.clone()),
+                LegacyHunk::Uncompressed(uncompressed.clone()),
+            ],
+        );
+
+        let mut chd = Chd::open(Cursor::new(chd.data), None).expect("open");
+        assert_eq!(HUNK_BYTES as u32, chd.header().hunk_size());
+        assert_eq!(6, chd.header().unit_count());
+        assert_eq!(compressed, read_hunk(&mut chd, 0).expect("compressed"));
+        assert_eq!(uncompressed, read_hunk(&mut chd, 1).expect("uncompressed"));
+
+        // the last sector begins in the second hunk.
+        let mut reader = ChdReader::new(chd);
+        let mut sector = vec![0u8; 2352];
+        reader.seek(SeekFrom::Start(5 * 2352)).expect("seek");
+        reader.read_exact(&mut sector).expect("last sector");
+        assert_eq!(uncompressed[..2352], sector[..]);
+    }
+
+    /// A stream that records seeks to positions that are never read from.
+    struct SeekLog {
+        inner: Cursor<Vec<u8>>,
+        pending: Option<u64>,
+        unread_seeks: Vec<u64>,
+    }
+
+    impl Read for SeekLog {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.pending = None;
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for SeekLog {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            if let Some(unread) = self.pending.take() {
+                self.unread_seeks.push(unread);
             }
-        } else {
-            match self {
-                Codecs::Four(a) => Some(&mut a[index]),
-                _ => None,
+            let new_pos = self.inner.seek(pos)?;
+            if let SeekFrom::Start(_) = pos {
+                self.pending = Some(new_pos);
+            }
+            Ok(new_pos)
+        }
+    }
+
+    #[test]
+    fn absolute_offset_reads_test() {
+        let mut chd = ChdOpenOptions::new()
+            .open(SeekLog {
+                inner: Cursor::new(degenerate_v2_chd()),
+                pending: None,
+                unread_seeks: Vec::new(),
+            })
+            .expect("open");
+        let mut buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        chd.hunk(0)
+            .and_then(|mut h| h.read_hunk_in(&mut cmp_buf, &mut buf))
+            .expect("hunk 0");
+        assert_eq!(Vec::<u64>::new(), chd.inner().unread_seeks);
+    }
+
+    #[test]
+    fn mixed_parent_stream_test() {
+        let parent_data = degenerate_v2_chd();
+        let parent = ChdOpenOptions::new()
+            .open(Cursor::new(&parent_data[..]))
+            .expect("open parent");
+        // the parent is read from a borrowed slice, and the child from an owned buffer.
+        let child = ChdOpenOptions::new()
+            .parent(Box::new(parent))
+            .validate_parent(false)
+            .open(Cursor::new(degenerate_v2_chd()));
+        assert!(matches!(child, Err(Error::InvalidParameter)));
+
+        // the parent is read from an owned buffer, and the child from a borrowed slice.
+        let parent = v5_chd(
+            1024,
+            512,
+            &[
+                V5Hunk::Zlib(vec![0xaa; 1024]),
+                V5Hunk::Zlib(vec![0xbb; 1024]),
+            ],
+            None,
+        );
+        let parent = Chd::open(Cursor::new(parent.data), None).expect("open parent");
+        // parent entries are offsets in units, so unit 2 is the start of hunk 1 of the parent.
+        let child = v5_chd(
+            1024,
+            512,
+            &[V5Hunk::Zlib(vec![0x11; 1024]), V5Hunk::Parent(2)],
+            Some([1; 20]),
+        );
+        let mut child: Chd<Cursor<&[u8]>, Cursor<Vec<u8>>> = ChdOpenOptions::new()
+            .parent(Box::new(parent))
+            .validate_parent(false)
+            .open(Cursor::new(&child.data[..]))
+            .expect("open child");
+        assert_eq!(vec![0x11; 1024], read_hunk(&mut child, 0).expect("child"));
+        assert_eq!(vec![0xbb; 1024], read_hunk(&mut child, 1).expect("parent"));
+    }
+
+    #[test]
+    fn quick_integrity_scan_test() {
+        let mut chd = ChdOpenOptions::new()
+            .open(Cursor::new(degenerate_v2_chd()))
+            .expect("open");
+        let report = chd.quick_integrity_scan().expect("scan");
+        assert_eq!(2, report.checked_hunks);
+        assert_eq!(vec![(1, IntegrityIssue::InvalidSize)], report.issues);
+
+        // hunk 0 points into the header.
+        let mut corrupt = degenerate_v2_chd();
+        corrupt[80..88].copy_from_slice(&((512u64 << 44) | 40).to_be_bytes());
+        let mut chd = ChdOpenOptions::new()
+            .open(Cursor::new(corrupt))
+            .expect("open");
+        let report = chd.quick_integrity_scan().expect("scan");
+        assert_eq!(
+            vec![
+                (0, IntegrityIssue::OutOfBounds),
+                (1, IntegrityIssue::InvalidSize)
+            ],
+            report.issues
+        );
+    }
+
+    fn read_hunk<F: Read + Seek, P: Read + Seek>(
+        chd: &mut Chd<F, P>,
+        hunk_num: u32,
+    ) -> Result<Vec<u8>> {
+        let mut buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        chd.hunk(hunk_num)?.read_hunk_in(&mut cmp_buf, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Opens a CHD file, then injects faults into subsequent reads of hunks.
+    fn open_faulty<'a>(
+        data: &'a [u8],
+        faults: &[(u64, Fault)],
+    ) -> Chd<FaultyReader<Cursor<&'a [u8]>>> {
+        let mut chd = ChdOpenOptions::new()
+            .open(FaultyReader::new(Cursor::new(data)))
+            .expect("open");
+        for (offset, fault) in faults {
+            chd.inner().fault(*offset, *fault);
+        }
+        chd
+    }
+
+    fn legacy_hunks() -> Vec<LegacyHunk> {
+        vec![
+            LegacyHunk::Zlib(vec![0x11; 256]),
+            LegacyHunk::Uncompressed((0..=255).collect()),
+            LegacyHunk::Mini(0x0102030405060708),
+            LegacyHunk::SelfHunk(0),
+            LegacyHunk::SelfHunk(99),
+            LegacyHunk::Parent(0),
+            LegacyHunk::Raw(6), // external compressed
+            LegacyHunk::Raw(0), // invalid
+        ]
+    }
+
+    #[test]
+    fn metadata_cache_test() {
+        let mut data = v3_chd(512, &[LegacyHunk::Mini(0)], false).data;
+        let meta_offset = data.len() as u64;
+        let value = b"CYLS:1,HEADS:1,SECS:1,BPS:512\0";
+        data.extend_from_slice(&KnownMetadata::HardDisk.metatag().to_be_bytes());
+        data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(value);
+        data[36..44].copy_from_slice(&meta_offset.to_be_bytes());
+
+        let mut chd = open_faulty(&data, &[]);
+        assert_eq!(512, chd.header().unit_bytes());
+
+        // the header of the entry is not read again after the file is opened.
+        chd.inner()
+            .fault(meta_offset, Fault::Error(ErrorKind::Other));
+        let mut refs = chd.metadata_refs();
+        let meta_ref = refs.next().expect("metadata");
+        assert!(refs.next().is_none());
+        assert!(refs.take_error().is_none());
+        assert_eq!(KnownMetadata::HardDisk.metatag(), meta_ref.metatag());
+        let metadata = chd
+            .metadata_by_tag(KnownMetadata::HardDisk.metatag(), 0)
+            .expect("read");
+        assert_eq!(value.to_vec(), metadata.value);
+    }
+
+    #[test]
+    fn legacy_hunk_read_test() {
+        let chd = v3_chd(256, &legacy_hunks(), true);
+        let mut chd = open_faulty(&chd.data, &[]);
+        assert_eq!(vec![0x11; 256], read_hunk(&mut chd, 0).expect("zlib"));
+        assert_eq!(
+            (0..=255).collect::<Vec<u8>>(),
+            read_hunk(&mut chd, 1).expect("uncompressed")
+        );
+        let mini = read_hunk(&mut chd, 2).expect("mini");
+        assert!(mini.chunks(8).all(|c| c == [1, 2, 3, 4, 5, 6, 7, 8]));
+        assert_eq!(vec![0x11; 256], read_hunk(&mut chd, 3).expect("self"));
+        assert!(matches!(read_hunk(&mut chd, 4), Err(Error::HunkOutOfRange)));
+        assert!(matches!(read_hunk(&mut chd, 5), Err(Error::RequiresParent)));
+        assert!(matches!(
+            read_hunk(&mut chd, 6),
+            Err(Error::UnsupportedFormat)
+        ));
+        assert!(matches!(read_hunk(&mut chd, 7), Err(Error::InvalidData)));
+        assert!(matches!(read_hunk(&mut chd, 8), Err(Error::HunkOutOfRange)));
+    }
+
+    #[test]
+    fn self_hunk_cycle_test() {
+        let hunks = [
+            V5Hunk::Zlib(vec![0x22; 1024]),
+            V5Hunk::SelfHunk(2),
+            V5Hunk::SelfHunk(1),
+            V5Hunk::SelfHunk(3),
+            V5Hunk::SelfHunk(5),
+            V5Hunk::SelfHunk(0),
+        ];
+        let chd = v5_chd(1024, 512, &hunks, None);
+        let mut chd = ChdOpenOptions::new()
+            .open(Cursor::new(&chd.data[..]))
+            .expect("open");
+        assert!(matches!(read_hunk(&mut chd, 1), Err(Error::InvalidData)));
+        assert!(matches!(read_hunk(&mut chd, 3), Err(Error::InvalidData)));
+        assert!(matches!(
+            read_range(&mut chd, 2, 0, 16),
+            Err(Error::InvalidData)
+        ));
+        // chains without cycles are still followed.
+        assert_eq!(vec![0x22; 1024], read_hunk(&mut chd, 4).expect("self"));
+        assert_eq!(
+            vec![0x22; 16],
+            read_range(&mut chd, 4, 0, 16).expect("self")
+        );
+
+        let hunks = [LegacyHunk::SelfHunk(1), LegacyHunk::SelfHunk(0)];
+        let chd = v3_chd(256, &hunks, false);
+        let mut chd = open_faulty(&chd.data, &[]);
+        assert!(matches!(read_hunk(&mut chd, 0), Err(Error::InvalidData)));
+        assert!(matches!(
+            read_range(&mut chd, 1, 0, 16),
+            Err(Error::InvalidData)
+        ));
+    }
+
+    fn read_range<F: Read + Seek, P: Read + Seek>(
+        chd: &mut Chd<F, P>,
+        hunk_num: u32,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        chd.hunk(hunk_num)?
+            .read_range_in(offset, &mut Vec::new(), &mut Vec::new(), &mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn read_range_test() {
+        let chd = v3_chd(256, &legacy_hunks(), true);
+        // only the requested range of stored hunks is read.
+        let mut chd = open_faulty(
+            &chd.data,
+            &[(chd.hunk_offsets[1], Fault::Error(ErrorKind::Other))],
+        );
+        assert_eq!(
+            vec![0x11; 20],
+            read_range(&mut chd, 0, 10, 20).expect("zlib")
+        );
+        assert_eq!(
+            (16..48).collect::<Vec<u8>>(),
+            read_range(&mut chd, 1, 16, 32).expect("uncompressed")
+        );
+        assert!(read_hunk(&mut chd, 1).is_err());
+        assert_eq!(
+            vec![6, 7, 8, 1, 2, 3],
+            read_range(&mut chd, 2, 5, 6).expect("mini")
+        );
+        assert_eq!(vec![0x11; 4], read_range(&mut chd, 3, 0, 4).expect("self"));
+        assert!(matches!(
+            read_range(&mut chd, 5, 0, 4),
+            Err(Error::RequiresParent)
+        ));
+        assert!(matches!(
+            read_range(&mut chd, 0, 250, 10),
+            Err(Error::InvalidParameter)
+        ));
+
+        let chd = v5_chd(1024, 512, &v5_hunks(), None);
+        let mut chd = ChdOpenOptions::new()
+            .open(Cursor::new(&chd.data[..]))
+            .expect("open");
+        assert_eq!(
+            vec![0x22; 8],
+            read_range(&mut chd, 0, 512, 8).expect("zlib")
+        );
+        assert_eq!(
+            (1000..1024).map(|i| i as u8).collect::<Vec<u8>>(),
+            read_range(&mut chd, 1, 1000, 24).expect("uncompressed")
+        );
+        assert_eq!(vec![0x22; 8], read_range(&mut chd, 2, 0, 8).expect("self"));
+
+        let chd = v5_uncompressed_chd(512, &[Some(vec![0x33; 512]), None], None);
+        let mut chd = ChdOpenOptions::new()
+            .open(Cursor::new(&chd.data[..]))
+            .expect("open");
+        assert_eq!(
+            vec![0x33; 8],
+            read_range(&mut chd, 0, 100, 8).expect("stored")
+        );
+        assert_eq!(
+            vec![0; 8],
+            read_range(&mut chd, 1, 100, 8).expect("unallocated")
+        );
+    }
+
+    #[test]
+    fn legacy_hunk_faults_test() {
+        let chd = v3_chd(256, &legacy_hunks(), true);
+        let zlib = chd.hunk_offsets[0];
+        let raw = chd.hunk_offsets[1];
+
+        let mut faulty = open_faulty(&chd.data, &[(zlib + 4, Fault::ShortRead)]);
+        assert!(matches!(read_hunk(&mut faulty, 0), Err(Error::ReadError)));
+        // self hunks fail with the hunk they refer to.
+        assert!(matches!(read_hunk(&mut faulty, 3), Err(Error::ReadError)));
+
+        let mut faulty = open_faulty(&chd.data, &[(zlib, Fault::Error(ErrorKind::Other))]);
+        assert!(matches!(read_hunk(&mut faulty, 0), Err(Error::Unknown)));
+
+        let mut faulty = open_faulty(&chd.data, &[(zlib, Fault::Corrupt(0x06))]);
+        assert!(matches!(
+            read_hunk(&mut faulty, 0),
+            Err(Error::DecompressionError)
+        ));
+
+        let mut faulty = open_faulty(&chd.data, &[(raw + 255, Fault::ShortRead)]);
+        assert!(matches!(read_hunk(&mut faulty, 1), Err(Error::ReadError)));
+
+        // faults in one hunk do not affect reads of other hunks.
+        let mut faulty = open_faulty(&chd.data, &[(raw, Fault::ShortRead)]);
+        assert_eq!(vec![0x11; 256], read_hunk(&mut faulty, 0).expect("zlib"));
+    }
+
+    #[test]
+    #[cfg(feature = "verify_block_crc")]
+    fn legacy_hunk_crc_test() {
+        let chd = v3_chd(256, &legacy_hunks(), true);
+        let mut faulty = open_faulty(&chd.data, &[(chd.hunk_offsets[1], Fault::Corrupt(0xff))]);
+        assert!(matches!(
+            read_hunk(&mut faulty, 1),
+            Err(Error::DecompressionError)
+        ));
+
+        let mut corrupt = chd.data.clone();
+        // the CRC of the map entry of the mini hunk.
+        corrupt[120 + 2 * 16 + 8] ^= 0xff;
+        let mut chd = open_faulty(&corrupt, &[]);
+        assert!(matches!(
+            read_hunk(&mut chd, 2),
+            Err(Error::DecompressionError)
+        ));
+    }
+
+    fn v5_hunks() -> Vec<V5Hunk> {
+        vec![
+            V5Hunk::Zlib(vec![0x22; 1024]),
+            V5Hunk::Uncompressed((0..1024).map(|i| i as u8).collect()),
+            V5Hunk::SelfHunk(0),
+            V5Hunk::SelfHunk(99),
+            V5Hunk::Parent(0),
+            V5Hunk::Parent(1),
+            V5Hunk::Parent(3),
+        ]
+    }
+
+    fn v5_parent() -> Chd<Cursor<Vec<u8>>> {
+        let parent = v5_uncompressed_chd(
+            1024,
+            &[Some(vec![0xaa; 1024]), Some(vec![0xbb; 1024])],
+            None,
+        );
+        ChdOpenOptions::new()
+            .open(Cursor::new(parent.data))
+            .expect("open parent")
+    }
+
+    #[test]
+    fn v5_hunk_read_test() {
+        let chd = v5_chd(1024, 512, &v5_hunks(), Some([1; 20]));
+        let mut chd = ChdOpenOptions::new()
+            .validate_parent(false)
+            .parent(Box::new(v5_parent()))
+            .open(Cursor::new(&chd.data[..]))
+            .expect("open");
+
+        assert_eq!(vec![0x22; 1024], read_hunk(&mut chd, 0).expect("zlib"));
+        assert_eq!(
+            (0..1024).map(|i| i as u8).collect::<Vec<u8>>(),
+            read_hunk(&mut chd, 1).expect("uncompressed")
+        );
+        assert_eq!(vec![0x22; 1024], read_hunk(&mut chd, 2).expect("self"));
+        assert!(matches!(read_hunk(&mut chd, 3), Err(Error::HunkOutOfRange)));
+        assert_eq!(vec![0xaa; 1024], read_hunk(&mut chd, 4).expect("parent"));
+
+        // unit 1 spans the second half of the first parent hunk and the first half of the second.
+        let split = read_hunk(&mut chd, 5).expect("split parent");
+        assert_eq!(vec![0xaa; 512], split[..512]);
+        assert_eq!(vec![0xbb; 512], split[512..]);
+
+        // unit 3 spans past the last hunk of the parent.
+        assert!(matches!(read_hunk(&mut chd, 6), Err(Error::HunkOutOfRange)));
+    }
+
+    #[test]
+    fn v5_hunk_faults_test() {
+        let chd = v5_chd(1024, 512, &v5_hunks(), Some([1; 20]));
+        let zlib = chd.hunk_offsets[0];
+        let raw = chd.hunk_offsets[1];
+
+        let mut faulty = open_faulty(&chd.data, &[(zlib + 2, Fault::ShortRead)]);
+        assert!(matches!(read_hunk(&mut faulty, 0), Err(Error::ReadError)));
+        assert!(matches!(read_hunk(&mut faulty, 2), Err(Error::ReadError)));
+
+        let mut faulty = open_faulty(&chd.data, &[(zlib, Fault::Error(ErrorKind::Other))]);
+        assert!(matches!(read_hunk(&mut faulty, 0), Err(Error::Unknown)));
+
+        let mut faulty = open_faulty(&chd.data, &[(zlib, Fault::Corrupt(0x06))]);
+        assert!(matches!(
+            read_hunk(&mut faulty, 0),
+            Err(Error::DecompressionError)
+        ));
+
+        let mut faulty = open_faulty(&chd.data, &[(raw + 1000, Fault::ShortRead)]);
+        assert!(matches!(read_hunk(&mut faulty, 1), Err(Error::ReadError)));
+        assert!(matches!(
+            read_hunk(&mut faulty, 4),
+            Err(Error::RequiresParent)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "verify_block_crc")]
+    fn v5_hunk_crc_test() {
+        let chd = v5_chd(1024, 512, &v5_hunks(), None);
+        let mut faulty = open_faulty(&chd.data, &[(chd.hunk_offsets[1], Fault::Corrupt(0xff))]);
+        assert!(matches!(
+            read_hunk(&mut faulty, 1),
+            Err(Error::DecompressionError)
+        ));
+    }
+
+    #[test]
+    fn reused_compressed_buffer_test() {
+        let noisy: Vec<u8> = (0..1024u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let hunks = [
+            V5Hunk::Zlib(noisy.clone()),
+            V5Hunk::Zlib(vec![0x22; 1024]),
+            V5Hunk::Zlib(noisy.clone()),
+        ];
+        let chd = v5_chd(1024, 512, &hunks, None);
+        let mut chd = Chd::open(Cursor::new(&chd.data[..]), None).expect("open");
+
+        let mut cmp_buf = Vec::new();
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut max_len = 0;
+        for (hunk_num, expected) in [&noisy, &vec![0x22; 1024], &noisy].iter().enumerate() {
+            chd.hunk(hunk_num as u32)
+                .and_then(|mut hunk| hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf))
+                .expect("read hunk");
+            assert_eq!(&&hunk_buf, expected);
+            // the buffer is never shrunk for a smaller compressed hunk.
+            assert!(cmp_buf.len() >= max_len);
+            max_len = cmp_buf.len();
+        }
+    }
+
+    #[test]
+    fn v5_uncompressed_hunk_faults_test() {
+        let hunks = [Some(vec![0x33; 512]), None];
+        let chd = v5_uncompressed_chd(512, &hunks, None);
+        let mut faulty = open_faulty(&chd.data, &[]);
+        assert_eq!(vec![0x33; 512], read_hunk(&mut faulty, 0).expect("stored"));
+        assert_eq!(
+            vec![0; 512],
+            read_hunk(&mut faulty, 1).expect("unallocated")
+        );
+
+        let mut faulty = open_faulty(&chd.data, &[(chd.hunk_offsets[0] + 100, Fault::ShortRead)]);
+        assert!(matches!(read_hunk(&mut faulty, 0), Err(Error::ReadError)));
+
+        let chd = v5_uncompressed_chd(512, &hunks, Some([1; 20]));
+        let mut faulty = open_faulty(&chd.data, &[]);
+        assert!(matches!(
+            read_hunk(&mut faulty, 1),
+            Err(Error::RequiresParent)
+        ));
+    }
+
+    #[test]
+    #[cfg(all(feature = "lzma", feature = "zlib"))]
+    fn lazy_codecs_test() {
+        use crate::cdrom::CD_FRAME_SIZE;
+        use crate::chdfile::Codecs;
+
+        let types = [
+            Ok(CodecType::ZLibCdV5),
+            Ok(CodecType::LzmaV5),
+            Err(Tag::new(b"abcd")),
+        ]
+        .into_iter()
+        .collect();
+        let mut codecs = Codecs::new(types, 8 * CD_FRAME_SIZE);
+        assert!(codecs.codecs.iter().all(Option::is_none));
+
+        codecs.get_mut(1).expect("lzma");
+        assert!(codecs.codecs[0].is_none());
+        assert!(codecs.codecs[1].is_some());
+        assert!(matches!(
+            codecs.get_mut(2),
+            Err(Error::UnsupportedCodec { fourcc }) if fourcc == Tag::new(b"abcd")
+        ));
+        assert!(matches!(codecs.get_mut(3), Err(Error::UnsupportedFormat)));
+
+        // cdzl buffers a whole hunk.
+        let buffered = codecs.buffer_bytes();
+        codecs.get_mut(0).expect("cdzl");
+        assert!(codecs.buffer_bytes() >= buffered + 8 * CD_FRAME_SIZE as usize);
+
+        // CD codecs require a whole number of frames per hunk, which is checked on first use.
+        let types = [Ok(CodecType::ZLibCdV5)].into_iter().collect();
+        let mut codecs = Codecs::new(types, 4096);
+        assert!(matches!(codecs.get_mut(0), Err(Error::CodecError)));
+    }
+
+    #[test]
+    fn codecs_in_use_test() {
+        let hunks = [
+            V5Hunk::Zlib(vec![1; 1024]),
+            V5Hunk::Uncompressed(vec![2; 1024]),
+            V5Hunk::SelfHunk(0),
+            V5Hunk::Zlib(vec![3; 1024]),
+        ];
+        let chd = v5_chd(1024, 512, &hunks, None);
+        let chd = Chd::open(Cursor::new(&chd.data[..]), None).expect("open");
+        let usage = chd.codecs_in_use();
+        assert_eq!(1, usage.len());
+        assert_eq!(0, usage[0].index);
+        assert_eq!(CodecType::ZLibV5, usage[0].codec);
+        assert_eq!(2, usage[0].hunks);
+        assert_eq!(cfg!(feature = "zlib"), usage[0].is_supported());
+
+        // only the hunks compressed with the codec of a legacy file are counted.
+        let chd = v3_chd(256, &legacy_hunks(), true);
+        let chd = open_faulty(&chd.data, &[]);
+        let usage = chd.codecs_in_use();
+        assert_eq!(1, usage.len());
+        assert_eq!(CodecType::Zlib, usage[0].codec);
+        assert_eq!(1, usage[0].hunks);
+
+        // files without compressed hunks use no codecs.
+        let chd = v5_uncompressed_chd(1024, &[Some(vec![1; 1024])], None);
+        let chd = Chd::open(Cursor::new(&chd.data[..]), None).expect("open");
+        assert!(chd.codecs_in_use().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "zlib")]
+    fn unused_codecs_test() {
+        let hunks = [
+            V5Hunk::Zlib(vec![1; 1024]),
+            V5Hunk::Uncompressed(vec![2; 1024]),
+            V5Hunk::SelfHunk(1),
+        ];
+        let mut chd = v5_chd(1024, 512, &hunks, None).data;
+
+        // codecs that are declared but never used do not prevent the file from being read.
+        chd[20..24].copy_from_slice(b"abcd");
+        chd[24..28].copy_from_slice(&1u32.to_be_bytes());
+        let mut file = Chd::open(Cursor::new(&chd[..]), None).expect("open");
+        assert_eq!(vec![1; 1024], read_hunk(&mut file, 0).expect("zlib"));
+        assert_eq!(vec![2; 1024], read_hunk(&mut file, 2).expect("self"));
+
+        // an unknown codec fails when a hunk compressed with it is read.
+        chd[16..20].copy_from_slice(b"efgh");
+        let mut file = Chd::open(Cursor::new(&chd[..]), None).expect("open");
+        let usage = file.codecs_in_use();
+        assert_eq!(Tag::new(b"efgh"), usage[0].fourcc);
+        assert!(!usage[0].is_supported());
+        assert!(matches!(
+            read_hunk(&mut file, 0),
+            Err(Error::UnsupportedCodec { fourcc }) if fourcc == Tag::new(b"efgh")
+        ));
+        assert_eq!(
+            vec![2; 1024],
+            read_hunk(&mut file, 1).expect("uncompressed")
+        );
+        assert_eq!(vec![2; 1024], read_hunk(&mut file, 2).expect("self"));
+    }
+
+    #[test]
+    fn memory_footprint_test() {
+        let hunks: Vec<_> = (0..8u8).map(|i| V5Hunk::Zlib(vec![i; 1024])).collect();
+        let chd = v5_chd(1024, 512, &hunks, None);
+        let expanded = Chd::open(Cursor::new(&chd.data[..]), None).expect("open");
+        let footprint = expanded.memory_footprint();
+        // every entry of an expanded compressed map is 12 bytes.
+        assert!(footprint.map_bytes >= 8 * 12);
+        assert_eq!(0, footprint.cache_bytes);
+        assert_eq!(0, footprint.parent_bytes);
+
+        // a lazy map holds the entries of the most recently decoded block.
+        let mut lazy = ChdOpenOptions::new()
+            .lazy_map(true)
+            .open(Cursor::new(&chd.data[..]))
+            .expect("open");
+        let before = lazy.memory_footprint().map_bytes;
+        read_hunk(&mut lazy, 0).expect("read");
+        assert!(lazy.memory_footprint().map_bytes >= before + 8 * 12);
+
+        let legacy = v3_chd(512, &[LegacyHunk::Mini(0), LegacyHunk::Mini(1)], true);
+        let parent = Chd::open(Cursor::new(chd.data.clone()), None).expect("parent");
+        let parent_total = parent.memory_footprint().total();
+        let child = ChdOpenOptions::new()
+            .parent(Box::new(parent))
+            .validate_parent(false)
+            .open(Cursor::new(legacy.data))
+            .expect("child");
+        let footprint = child.memory_footprint();
+        assert_eq!(parent_total, footprint.parent_bytes);
+        assert_eq!(
+            footprint.total(),
+            footprint.map_bytes + footprint.codec_bytes + parent_total
+        );
+    }
+
+    #[test]
+    fn parent_codecs_deferred_test() {
+        let parent = v5_chd(
+            1024,
+            512,
+            &[
+                V5Hunk::Zlib(vec![0xaa; 1024]),
+                V5Hunk::Zlib(vec![0xbb; 1024]),
+            ],
+            None,
+        );
+        let parent = Chd::open(Cursor::new(parent.data), None).expect("parent");
+        let child = v5_chd(
+            1024,
+            512,
+            &[V5Hunk::Zlib(vec![0x11; 1024]), V5Hunk::Parent(0)],
+            Some([1; 20]),
+        );
+        let mut child = ChdOpenOptions::new()
+            .validate_parent(false)
+            .parent(Box::new(parent))
+            .open(Cursor::new(&child.data[..]))
+            .expect("child");
+
+        fn initialized<F: Read + Seek, P: Read + Seek>(chd: &Chd<F, P>) -> usize {
+            chd.codecs.codecs.iter().flatten().count()
+        }
+        let parent_initialized =
+            |chd: &Chd<_, _>| initialized(chd.parent.as_deref().expect("parent"));
+
+        // opening the chain initializes no codecs.
+        assert_eq!(0, initialized(&child));
+        assert_eq!(0, parent_initialized(&child));
+
+        // hunks of the child only initialize the codecs of the child.
+        assert_eq!(vec![0x11; 1024], read_hunk(&mut child, 0).expect("child"));
+        assert_eq!(1, initialized(&child));
+        assert_eq!(0, parent_initialized(&child));
+
+        // the codec of the parent is initialized by the first hunk decoded from the parent.
+        assert_eq!(vec![0xaa; 1024], read_hunk(&mut child, 1).expect("parent"));
+        assert_eq!(1, parent_initialized(&child));
+    }
+
+    #[test]
+    fn sector_policy_test() {
+        use crate::cdrom::{SectorPolicy, CD_FRAME_SIZE};
+        use crate::chdfile::Codecs;
+
+        let parent = v5_chd(1024, 512, &[V5Hunk::Zlib(vec![0xaa; 1024])], None);
+        let parent = Chd::open(Cursor::new(parent.data), None).expect("parent");
+        let child = v5_chd(1024, 512, &[V5Hunk::Parent(0)], Some([1; 20]));
+        let mut child = ChdOpenOptions::new()
+            .validate_parent(false)
+            .sector_policy(SectorPolicy::SkipSubcode)
+            .parent(Box::new(parent))
+            .open(Cursor::new(&child.data[..]))
+            .expect("child");
+
+        // the policy applies to the whole parent chain.
+        let parent_policy =
+            |chd: &Chd<_, _>| chd.parent.as_deref().expect("parent").sector_policy();
+        assert_eq!(SectorPolicy::SkipSubcode, child.sector_policy());
+        assert_eq!(SectorPolicy::SkipSubcode, parent_policy(&child));
+        child.set_sector_policy(SectorPolicy::Full);
+        assert_eq!(SectorPolicy::Full, child.sector_policy());
+        assert_eq!(SectorPolicy::Full, parent_policy(&child));
+        assert_eq!(vec![0xaa; 1024], read_hunk(&mut child, 0).expect("parent"));
+
+        // only hunks of CD-ROM codecs that skip part of each frame are not verified.
+        let types = [Ok(CodecType::ZLibCdV5), Ok(CodecType::ZLibV5)]
+            .into_iter()
+            .collect();
+        let mut codecs = Codecs::new(types, 8 * CD_FRAME_SIZE);
+        codecs.set_sector_policy(SectorPolicy::Full);
+        assert!(codecs.verifies_crc(0) && codecs.verifies_crc(1));
+        codecs.set_sector_policy(SectorPolicy::SkipEcc);
+        assert!(!codecs.verifies_crc(0));
+        assert!(codecs.verifies_crc(1));
+    }
+
+    #[test]
+    fn memory_limits_test() {
+        let hunks: Vec<_> = (0..8u8).map(|i| V5Hunk::Zlib(vec![i; 1024])).collect();
+        let chd = v5_chd(1024, 512, &hunks, None);
+        let open = |options: ChdOpenOptions<_>| options.open(Cursor::new(&chd.data[..]));
+
+        assert!(matches!(
+            open(ChdOpenOptions::new().max_hunk_bytes(1023)),
+            Err(Error::OutOfMemory)
+        ));
+        open(ChdOpenOptions::new().max_hunk_bytes(1024)).expect("hunk limit");
+
+        // the expanded map is 12 bytes per hunk.
+        assert!(matches!(
+            open(ChdOpenOptions::new().max_map_bytes(95)),
+            Err(Error::OutOfMemory)
+        ));
+        open(ChdOpenOptions::new().max_map_bytes(4096)).expect("map limit");
+        open(ChdOpenOptions::new().lazy_map(true).max_map_bytes(95)).expect("lazy map limit");
+
+        let legacy = v3_chd(512, &[LegacyHunk::Mini(0), LegacyHunk::Mini(1)], false);
+        assert!(matches!(
+            ChdOpenOptions::new()
+                .max_map_bytes(31)
+                .open(Cursor::new(&legacy.data[..])),
+            Err(Error::OutOfMemory)
+        ));
+        ChdOpenOptions::new()
+            .max_map_bytes(32)
+            .open(Cursor::new(&legacy.data[..]))
+            .expect("legacy map limit");
+    }
+
+    #[test]
+    fn decode_observer_test() {
+        let chd = v5_chd(
+            512,
+            512,
+            &[
+                V5Hunk::Zlib(vec![0x11; 512]),
+                V5Hunk::Uncompressed(vec![0x22; 512]),
+                V5Hunk::SelfHunk(0),
+            ],
+            None,
+        );
+        let mut chd = Chd::open(Cursor::new(chd.data), None).expect("open");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        chd.set_decode_observer({
+            let events = Arc::clone(&events);
+            move |event: &DecodeEvent| events.lock().unwrap().push(*event)
+        });
+
+        let mut buf = chd.get_hunksized_buffer();
+        let mut cmp = Vec::new();
+        for hunk_num in 0..3 {
+            chd.hunk(hunk_num)
+                .expect("hunk")
+                .read_hunk_in(&mut cmp, &mut buf)
+                .expect("read");
+        }
+        let events: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|e| (e.hunk_num, e.codec, e.decompressed_len))
+            .collect();
+        assert_eq!(
+            vec![
+                (0, CodecType::ZLibV5, 512),
+                (1, CodecType::None, 512),
+                (0, CodecType::ZLibV5, 512)
+            ],
+            events
+        );
+
+        assert!(chd.clear_decode_observer().is_some());
+        chd.hunk(0)
+            .expect("hunk")
+            .read_hunk_in(&mut cmp, &mut buf)
+            .expect("read");
+        assert!(chd.clear_decode_observer().is_none());
+    }
+
+    #[test]
+    fn parent_chain_test() {
+        let hunks = [Some(vec![0x11; 512])];
+        let open = |sha1: [u8; 20], parent_sha1: Option<[u8; 20]>| {
+            let mut chd = v5_uncompressed_chd(512, &hunks, parent_sha1).data;
+            chd[84..104].copy_from_slice(&sha1);
+            Chd::open(Cursor::new(chd), None).expect("open")
+        };
+
+        let child = open([1; 20], Some([2; 20]));
+        assert_eq!(0, child.parent_chain().count());
+        assert_eq!(Some([2; 20]), child.required_parent_sha1());
+
+        let parent = open([2; 20], Some([3; 20]));
+        let child = ChdOpenOptions::new()
+            .parent(Box::new(parent))
+            .open(Cursor::new(child.into_inner().0.into_inner()))
+            .expect("open with parent");
+        let chain: Vec<_> = child.parent_chain().map(|p| (p.depth, p.sha1)).collect();
+        assert_eq!(vec![(1, Some([2; 20]))], chain);
+        assert_eq!(Some([3; 20]), child.required_parent_sha1());
+
+        let (child, parent) = child.into_inner();
+        let grandparent = open([3; 20], None);
+        let parent = ChdOpenOptions::new()
+            .parent(Box::new(grandparent))
+            .open(parent.expect("parent").into_inner().0)
+            .expect("open parent");
+        let child = ChdOpenOptions::new()
+            .parent(Box::new(parent))
+            .open(child)
+            .expect("open with chain");
+        let chain: Vec<_> = child
+            .parent_chain()
+            .map(|p| (p.depth, p.sha1, p.version as u32))
+            .collect();
+        assert_eq!(vec![(1, Some([2; 20]), 5), (2, Some([3; 20]), 5)], chain);
+        assert_eq!(None, child.required_parent_sha1());
+    }
+
+    #[test]
+    fn legacy_metadata_test() {
+        let hunks = [LegacyHunk::Uncompressed(vec![0; 2352 * 5])];
+        let mut chd =
+            Chd::open(Cursor::new(v2_chd(2352, 5, [5, 1, 1], &hunks).data), None).expect("open");
+        assert!(!chd.has_metadata());
+        assert_eq!(0, chd.metadata_refs().count());
+
+        let hard_disk = chd
+            .metadata_for(KnownMetadata::HardDisk, 0)
+            .expect("hard disk");
+        assert_eq!(
+            b"CYLS:5,HEADS:1,SECS:1,BPS:2352\0",
+            hard_disk.value.as_slice()
+        );
+        assert_eq!(hard_disk.value.len() as u32, hard_disk.length);
+        let wildcard = chd
+            .metadata_by_tag(KnownMetadata::Wildcard.metatag(), 0)
+            .expect("wildcard");
+        assert_eq!(hard_disk.value, wildcard.value);
+        assert!(matches!(
+            chd.metadata_for(KnownMetadata::HardDisk, 1),
+            Err(Error::MetadataNotFound)
+        ));
+        assert!(matches!(
+            chd.metadata_for(KnownMetadata::CdRomTrack2, 0),
+            Err(Error::MetadataNotFound)
+        ));
+
+        // later versions are not synthesized.
+        let mut chd = Chd::open(
+            Cursor::new(v5_uncompressed_chd(512, &[Some(vec![0; 512])], None).data),
+            None,
+        )
+        .expect("open");
+        assert!(!chd.has_metadata());
+        assert!(matches!(
+            chd.metadata_for(KnownMetadata::HardDisk, 0),
+            Err(Error::MetadataNotFound)
+        ));
+    }
+
+    #[test]
+    fn mismatched_parent_test() {
+        let hunks = [Some(vec![0x11; 512])];
+        let chd = |sha1: [u8; 20], parent_sha1: Option<[u8; 20]>| {
+            let mut chd = v5_uncompressed_chd(512, &hunks, parent_sha1).data;
+            chd[84..104].copy_from_slice(&sha1);
+            Cursor::new(chd)
+        };
+        let parent = |sha1: [u8; 20]| Box::new(Chd::open(chd(sha1, None), None).expect("parent"));
+
+        let child = ChdOpenOptions::new()
+            .parent(parent([2; 20]))
+            .open(chd([1; 20], Some([2; 20])));
+        assert!(child.is_ok());
+
+        let child = ChdOpenOptions::new()
+            .parent(parent([3; 20]))
+            .open(chd([1; 20], Some([2; 20])));
+        assert!(matches!(child, Err(Error::InvalidParent)));
+
+        let child = ChdOpenOptions::new()
+            .parent(parent([3; 20]))
+            .validate_parent(false)
+            .open(chd([1; 20], Some([2; 20])));
+        assert!(child.is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn prefetch_units_test() {
+        let hunks: Vec<_> = (0..8u8).map(|i| V5Hunk::Zlib(vec![i; 1024])).collect();
+        let chd = v5_chd(1024, 512, &hunks, None);
+        let mut faulty = open_faulty(
+            &chd.data,
+            &[
+                (chd.hunk_offsets[5], Fault::Corrupt(0xff)),
+                (chd.hunk_offsets[6] + 4, Fault::ShortRead),
+            ],
+        );
+
+        // units 3 to 13 span hunks 1 to 6.
+        faulty.prefetch_units(3..13).expect("prefetch");
+        for hunk_num in 1..5 {
+            assert!(faulty
+                .prefetcher
+                .as_ref()
+                .is_some_and(|p| p.contains(hunk_num)));
+        }
+        assert!(!faulty.prefetcher.as_ref().is_some_and(|p| p.contains(0)));
+        for hunk_num in 0..5 {
+            assert_eq!(
+                vec![hunk_num as u8; 1024],
+                read_hunk(&mut faulty, hunk_num).expect("read")
+            );
+        }
+        // prefetched hunks are released once read.
+        assert!(!faulty.prefetcher.as_ref().is_some_and(|p| p.contains(1)));
+
+        // errors while decompressing in the background are returned when the hunk is read.
+        assert!(matches!(
+            read_hunk(&mut faulty, 5),
+            Err(Error::DecompressionError)
+        ));
+        // hunks that could not be read are not prefetched, and fail when read.
+        assert!(!faulty.prefetcher.as_ref().is_some_and(|p| p.contains(6)));
+        assert!(matches!(read_hunk(&mut faulty, 6), Err(Error::ReadError)));
+
+        // ranges past the end of the CHD file are clamped.
+        faulty.prefetch_units(14..100).expect("prefetch");
+        assert_eq!(vec![7; 1024], read_hunk(&mut faulty, 7).expect("read"));
+    }
+
+    #[test]
+    fn is_zero_filled_test() {
+        let zero_filled = |data: Vec<u8>| -> Vec<Option<bool>> {
+            let mut chd = Chd::open(Cursor::new(data), None).expect("chd");
+            (0..chd.header().hunk_count())
+                .map(|hunk_num| chd.hunk(hunk_num).expect("hunk").is_zero_filled())
+                .collect()
+        };
+
+        // unallocated hunks of uncompressed V5 files are zero-filled.
+        let v5 = v5_uncompressed_chd(512, &[Some(vec![0; 512]), None], None);
+        assert_eq!(vec![None, Some(true)], zero_filled(v5.data));
+
+        // hunks with a recorded CRC that differs from the CRC of zeroes are not zero-filled.
+        let v5 = v5_chd(
+            512,
+            512,
+            &[
+                V5Hunk::Zlib(vec![0; 512]),
+                V5Hunk::Zlib(vec![1; 512]),
+                V5Hunk::Uncompressed(vec![2; 512]),
+                V5Hunk::SelfHunk(0),
+            ],
+            None,
+        );
+        assert_eq!(
+            vec![None, Some(false), Some(false), None],
+            zero_filled(v5.data)
+        );
+
+        let v3 = v3_chd(
+            512,
+            &[
+                LegacyHunk::Mini(0),
+                LegacyHunk::Mini(1),
+                LegacyHunk::Zlib(vec![0; 512]),
+                LegacyHunk::Uncompressed(vec![3; 512]),
+            ],
+            false,
+        );
+        assert_eq!(
+            vec![Some(true), Some(false), None, Some(false)],
+            zero_filled(v3.data)
+        );
+    }
+
+    #[test]
+    fn open_path_test() {
+        let dir = std::env::temp_dir().join(format!("chd-rs-open-path-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("dir");
+        let parent_sha1 = [7; 20];
+        let mut parent = v5_uncompressed_chd(512, &[Some(vec![1; 512])], None).data;
+        // the SHA1 of the parent is only compared with the parent SHA1 of the child.
+        parent[84..104].copy_from_slice(&parent_sha1);
+        let child = v5_uncompressed_chd(512, &[None], Some(parent_sha1)).data;
+        std::fs::write(dir.join("parent.chd"), parent).expect("write");
+        std::fs::write(dir.join("child.chd"), child).expect("write");
+
+        let mut chd =
+            Chd::open_path(dir.join("child.chd"), Some(&dir.join("parent.chd"))).expect("open");
+        assert_eq!(vec![1; 512], read_hunk(&mut chd, 0).expect("read"));
+
+        let chd = Chd::try_from(dir.join("parent.chd").as_path()).expect("open");
+        assert_eq!(1, chd.header().hunk_count());
+        let file = std::fs::File::open(dir.join("parent.chd")).expect("file");
+        assert!(Chd::try_from(file).is_ok());
+
+        assert!(matches!(
+            Chd::open_path(dir.join("missing.chd"), None),
+            Err(Error::FileNotFound)
+        ));
+        assert!(matches!(
+            Chd::open_path(dir.join("child.chd"), Some(&dir.join("missing.chd"))),
+            Err(Error::FileNotFound)
+        ));
+        std::fs::remove_dir_all(&dir).expect("cleanup");
+    }
+
+    /// Moves the compressed map of a synthesized V5 CHD file in front of its hunk data, so that
+    /// cutting the file short cuts the hunk data instead of the map.
+    fn map_first_v5_chd(hunks: &[V5Hunk]) -> Vec<u8> {
+        let chd = v5_chd(1024, 512, hunks, None).data;
+        let map_offset = u64::from_be_bytes(chd[40..48].try_into().unwrap()) as usize;
+        let mut raw_map = match ChdOpenOptions::new()
+            .open(Cursor::new(&chd[..]))
+            .expect("open")
+            .map()
+        {
+            Map::V5(map) => Vec::from(map),
+            _ => unreachable!(),
+        };
+
+        // every stored hunk moves by the length of the map.
+        let shift = (chd.len() - map_offset) as u64;
+        let shift_u48 = |field: &mut [u8]| {
+            let mut offset = [0u8; 8];
+            offset[2..].copy_from_slice(field);
+            let offset = u64::from_be_bytes(offset) + shift;
+            field.copy_from_slice(&offset.to_be_bytes()[2..]);
+        };
+        for entry in raw_map.chunks_exact_mut(12) {
+            // compressed with a codec, or uncompressed.
+            if entry[0] <= 4 {
+                shift_u48(&mut entry[4..10]);
             }
         }
+        let mut map = chd[map_offset..].to_vec();
+        shift_u48(&mut map[4..10]);
+        map[10..12].copy_from_slice(&crate::block_hash::crc16(&raw_map).to_be_bytes());
+
+        let mut data = chd[..124].to_vec();
+        data[40..48].copy_from_slice(&124u64.to_be_bytes());
+        data.extend_from_slice(&map);
+        data.extend_from_slice(&chd[124..map_offset]);
+        data
+    }
+
+    #[test]
+    fn truncated_test() {
+        let hunks = [
+            V5Hunk::Zlib(vec![0x11; 1024]),
+            V5Hunk::Uncompressed(vec![0x22; 1024]),
+            V5Hunk::SelfHunk(0),
+        ];
+        let open = |data: &[u8], lazy: bool| {
+            ChdOpenOptions::new()
+                .lazy_map(lazy)
+                .open(Cursor::new(data.to_vec()))
+        };
+
+        // the map of a CHD file written by chdman follows the hunk data, so it is cut first.
+        let data = v5_chd(1024, 512, &hunks, None).data;
+        let map_offset = u64::from_be_bytes(data[40..48].try_into().unwrap());
+        for lazy in [false, true] {
+            assert!(matches!(
+                open(&data[..map_offset as usize], lazy),
+                Err(Error::Truncated { expected, actual })
+                    if expected == map_offset + 1 && actual == map_offset
+            ));
+        }
+
+        // the hunk data is checked only if the map is not decoded on demand.
+        let data = map_first_v5_chd(&hunks);
+        let len = data.len() as u64;
+        for lazy in [false, true] {
+            let mut chd = open(&data, lazy).expect("open");
+            assert_eq!(vec![0x22; 1024], read_hunk(&mut chd, 1).expect("hunk"));
+            assert_eq!(vec![0x11; 1024], read_hunk(&mut chd, 2).expect("hunk"));
+        }
+        assert!(matches!(
+            open(&data[..data.len() - 1], false),
+            Err(Error::Truncated { expected, actual }) if expected == len && actual == len - 1
+        ));
+        let chd = open(&data[..data.len() - 1], true).expect("lazy open");
+        assert_eq!(len, chd.map().required_length().expect("required length"));
+
+        // hunks before the end of the file can still be read without validating the length.
+        let mut chd = ChdOpenOptions::new()
+            .validate_length(false)
+            .open(Cursor::new(data[..data.len() - 1].to_vec()))
+            .expect("open");
+        assert_eq!(vec![0x11; 1024], read_hunk(&mut chd, 0).expect("hunk"));
     }
 }
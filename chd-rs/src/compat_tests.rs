@@ -0,0 +1,255 @@
+//! Tests comparing chd-rs against libchdr.
+//!
+//! Every CHD file in `.testimages` is opened with both libraries, and every hunk and metadata
+//! query is compared. Parents are found among the test images by their SHA1, and images whose
+//! parent is not among them are skipped.
+//!
+//! These tests link against libchdr, and are only built with the `compat_tests` feature.
+//! libchdr must be installed where the linker can find it, for example with
+//! `RUSTFLAGS="-L /path/to/libchdr/build"`.
+use crate::header::Header;
+use crate::metadata::Metadata;
+use crate::{Chd, Error};
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::fs::File;
+use std::io::BufReader;
+use std::mem::ManuallyDrop;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+const CHD_OPEN_READ: c_int = 1;
+const CHDMETATAG_WILDCARD: u32 = 0;
+
+#[repr(C)]
+struct ChdrFile {
+    _private: [u8; 0],
+}
+
+#[link(name = "chdr")]
+extern "C" {
+    fn chd_open(
+        filename: *const c_char,
+        mode: c_int,
+        parent: *mut ChdrFile,
+        chd: *mut *mut ChdrFile,
+    ) -> c_int;
+    fn chd_close(chd: *mut ChdrFile);
+    fn chd_read(chd: *mut ChdrFile, hunknum: u32, buffer: *mut c_void) -> c_int;
+    fn chd_get_metadata(
+        chd: *mut ChdrFile,
+        searchtag: u32,
+        searchindex: u32,
+        output: *mut c_void,
+        outputlen: u32,
+        resultlen: *mut u32,
+        resulttag: *mut u32,
+        resultflags: *mut u8,
+    ) -> c_int;
+}
+
+/// A CHD file opened with libchdr. Closing it also closes its parent.
+struct Chdr(*mut ChdrFile);
+
+impl Chdr {
+    fn open(path: &Path, parent: Option<Chdr>) -> Chdr {
+        let filename = CString::new(path.to_str().expect("path is not UTF-8")).unwrap();
+        let parent = parent.map_or(ptr::null_mut(), |parent| ManuallyDrop::new(parent).0);
+        let mut chd = ptr::null_mut();
+        let err = unsafe { chd_open(filename.as_ptr(), CHD_OPEN_READ, parent, &mut chd) };
+        assert_eq!(
+            Error::None.code(),
+            err,
+            "libchdr could not open {}",
+            path.display()
+        );
+        Chdr(chd)
+    }
+
+    fn read_hunk(&self, hunk_num: u32, buf: &mut [u8]) -> c_int {
+        unsafe { chd_read(self.0, hunk_num, buf.as_mut_ptr() as *mut c_void) }
+    }
+
+    /// Returns the tag, flags and value of the metadata entry, or `None` if it was not found.
+    fn metadata(&self, tag: u32, index: u32) -> Option<(u32, u8, Vec<u8>)> {
+        let mut len = 0;
+        let mut result_tag = 0;
+        let mut flags = 0;
+        let err = unsafe {
+            chd_get_metadata(
+                self.0,
+                tag,
+                index,
+                ptr::null_mut(),
+                0,
+                &mut len,
+                &mut result_tag,
+                &mut flags,
+            )
+        };
+        if err == Error::MetadataNotFound.code() {
+            return None;
+        }
+        assert_eq!(Error::None.code(), err, "libchdr could not read metadata");
+
+        let mut value = vec![0u8; len as usize];
+        let err = unsafe {
+            chd_get_metadata(
+                self.0,
+                tag,
+                index,
+                value.as_mut_ptr() as *mut c_void,
+                len,
+                &mut len,
+                &mut result_tag,
+                &mut flags,
+            )
+        };
+        assert_eq!(Error::None.code(), err, "libchdr could not read metadata");
+        Some((result_tag, flags, value))
+    }
+}
+
+impl Drop for Chdr {
+    fn drop(&mut self) {
+        unsafe { chd_close(self.0) }
+    }
+}
+
+fn test_images() -> Vec<(PathBuf, Header)> {
+    let mut images: Vec<_> = std::fs::read_dir(".testimages")
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_else(|_| Vec::new());
+    images.sort();
+    images
+        .into_iter()
+        .filter(|path| path.extension() == Some(OsStr::new("chd")))
+        .filter_map(|path| {
+            let header = Header::try_read_header(&mut File::open(&path).ok()?).ok()?;
+            Some((path, header))
+        })
+        .collect()
+}
+
+/// Opens the CHD file with both chd-rs and libchdr, or returns `None` if its parent is not
+/// among the test images.
+fn open_both(
+    path: &Path,
+    header: &Header,
+    images: &[(PathBuf, Header)],
+) -> Option<(Chd<BufReader<File>>, Chdr)> {
+    let (parent, chdr_parent) = if header.has_parent() {
+        let sha1 = header.parent_sha1()?;
+        let (parent_path, parent_header) = images
+            .iter()
+            .find(|(_, parent)| parent.sha1() == Some(sha1))?;
+        let (parent, chdr_parent) = open_both(parent_path, parent_header, images)?;
+        (Some(Box::new(parent)), Some(chdr_parent))
+    } else {
+        (None, None)
+    };
+    let file = BufReader::new(File::open(path).expect("could not open file"));
+    let chd = Chd::open(file, parent).expect("chd-rs could not open file");
+    Some((chd, Chdr::open(path, chdr_parent)))
+}
+
+#[test]
+fn compat_hunks_test() {
+    let images = test_images();
+    for (path, header) in &images {
+        let (mut chd, chdr) = match open_both(path, header, &images) {
+            Some(chd) => chd,
+            None => continue,
+        };
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut chdr_buf = vec![0u8; hunk_buf.len()];
+        let mut cmp_buf = Vec::new();
+        for hunk_num in 0..header.hunk_count() {
+            let result = chd
+                .hunk(hunk_num)
+                .and_then(|mut hunk| hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf));
+            let chdr_result = chdr.read_hunk(hunk_num, &mut chdr_buf);
+            match result {
+                Ok(_) => {
+                    assert_eq!(
+                        Error::None.code(),
+                        chdr_result,
+                        "{}: hunk {} could not be read by libchdr",
+                        path.display(),
+                        hunk_num
+                    );
+                    assert!(
+                        hunk_buf == chdr_buf,
+                        "{}: hunk {} differs from libchdr",
+                        path.display(),
+                        hunk_num
+                    );
+                }
+                Err(err) => assert_ne!(
+                    Error::None.code(),
+                    chdr_result,
+                    "{}: hunk {} could not be read by chd-rs: {}",
+                    path.display(),
+                    hunk_num,
+                    err
+                ),
+            }
+        }
+    }
+}
+
+#[test]
+fn compat_metadata_test() {
+    let images = test_images();
+    for (path, header) in &images {
+        let (mut chd, chdr) = match open_both(path, header, &images) {
+            Some(chd) => chd,
+            None => continue,
+        };
+        let metadata: Vec<Metadata> = match header.legacy_hard_disk_metadata() {
+            Some(metadata) => vec![metadata],
+            None => chd
+                .metadata_refs()
+                .try_into_vec()
+                .expect("chd-rs could not read metadata"),
+        };
+        let expected = |meta: &Metadata| Some((meta.metatag, meta.flags, meta.value.clone()));
+
+        for (index, meta) in metadata.iter().enumerate() {
+            assert_eq!(
+                expected(meta),
+                chdr.metadata(CHDMETATAG_WILDCARD, index as u32),
+                "{}: metadata entry {} differs from libchdr",
+                path.display(),
+                index
+            );
+        }
+        assert_eq!(
+            None,
+            chdr.metadata(CHDMETATAG_WILDCARD, metadata.len() as u32)
+        );
+
+        let mut by_tag: HashMap<u32, Vec<&Metadata>> = HashMap::new();
+        for meta in &metadata {
+            by_tag.entry(meta.metatag).or_default().push(meta);
+        }
+        for (tag, entries) in by_tag {
+            for (index, meta) in entries.iter().enumerate() {
+                assert_eq!(
+                    expected(meta),
+                    chdr.metadata(tag, index as u32),
+                    "{}: metadata entry {} of tag {:08x} differs from libchdr",
+                    path.display(),
+                    index,
+                    tag
+                );
+            }
+            assert_eq!(None, chdr.metadata(tag, entries.len() as u32));
+        }
+    }
+}
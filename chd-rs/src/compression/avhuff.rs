@@ -41,9 +41,9 @@ struct DeltaRleDecoder<'a> {
 }
 
 impl<'a> DeltaRleDecoder<'a> {
-    pub fn new(huff: DeltaRleHuffman<'a>) -> Self {
+    pub fn new(huffman: Box<DeltaRleHuffman<'a>>) -> Self {
         Self {
-            huffman: Box::new(huff),
+            huffman,
             rle_count: 0,
             prev_data: 0,
         }
@@ -138,6 +138,10 @@ impl CodecImplementation for AVHuffCodec {
         Ok(AVHuffCodec { buffer: Vec::new() })
     }
 
+    fn buffer_bytes(&self) -> usize {
+        self.buffer.capacity() * std::mem::size_of::<i32>()
+    }
+
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
         // https://github.com/mamedev/mame/blob/master/src/lib/util/avhuff.cpp#L723
         if input.len() < 8 {
@@ -351,9 +355,10 @@ impl AVHuffCodec {
                 let mut bytes_read = 0;
                 let mut bit_reader = BitReader::new(&source[..tree_size as usize]);
 
-                let hi_decoder = Huffman8BitDecoder::from_tree_rle(&mut bit_reader)?;
+                // the lookup arrays of the decoders are too large for small stacks.
+                let hi_decoder = Huffman8BitDecoder::from_tree_rle_boxed(&mut bit_reader)?;
                 bit_reader.align(1)?;
-                let lo_decoder = Huffman8BitDecoder::from_tree_rle(&mut bit_reader)?;
+                let lo_decoder = Huffman8BitDecoder::from_tree_rle_boxed(&mut bit_reader)?;
 
                 bit_reader.align(1)?;
                 if bit_reader.remaining() != 0 {
@@ -409,16 +414,19 @@ impl AVHuffCodec {
 
         // Skip first byte that indicates lossless.
         let mut bit_reader = BitReader::new(&input[1..]);
-        let mut y_context = DeltaRleDecoder::new(DeltaRleHuffman::from_tree_rle(&mut bit_reader)?);
+        let mut y_context =
+            DeltaRleDecoder::new(DeltaRleHuffman::from_tree_rle_boxed(&mut bit_reader)?);
         bit_reader.align(1)?;
-        let mut cb_context = DeltaRleDecoder::new(DeltaRleHuffman::from_tree_rle(&mut bit_reader)?);
+        let mut cb_context =
+            DeltaRleDecoder::new(DeltaRleHuffman::from_tree_rle_boxed(&mut bit_reader)?);
         bit_reader.align(1)?;
-        let mut cr_context = DeltaRleDecoder::new(DeltaRleHuffman::from_tree_rle(&mut bit_reader)?);
+        let mut cr_context =
+            DeltaRleDecoder::new(DeltaRleHuffman::from_tree_rle_boxed(&mut bit_reader)?);
         bit_reader.align(1)?;
 
         // The decoders here are one-shot and do not need to be reset.
-        // Unfortunately because three of them are too big to fit onto one stack frame
-        // we have to box the inner Huffman decoders.
+        // Three of them are too big to fit onto one stack frame, so the inner Huffman decoders
+        // are built on the heap.
 
         let mut bytes_written = 0;
         for dy in 0..height as usize {
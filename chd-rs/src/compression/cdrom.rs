@@ -1,8 +1,12 @@
 /// Common logic for CD-ROM decompression codecs.
-use crate::cdrom::{CD_FRAME_SIZE, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA, CD_SYNC_HEADER};
-use crate::compression::ecc::ErrorCorrectedSector;
+use crate::cdrom::{
+    ecc, SectorPolicy, CD_FRAME_SIZE, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA, CD_SYNC_HEADER,
+};
+#[cfg(all(feature = "lzma", feature = "zlib"))]
 use crate::compression::lzma::LzmaCodec;
+#[cfg(feature = "zlib")]
 use crate::compression::zlib::ZlibCodec;
+#[cfg(feature = "zstd")]
 use crate::compression::zstd::ZstdCodec;
 use crate::compression::{
     CodecImplementation, CompressionCodec, CompressionCodecType, DecompressResult,
@@ -50,6 +54,8 @@ use std::convert::TryFrom;
 /// 2448, the size of each CD frame.
 /// The input buffer must contain exactly enough data to fill the hunk-sized output buffer
 /// when decompressed.
+#[cfg(all(feature = "lzma", feature = "zlib"))]
+#[cfg_attr(docsrs, doc(cfg(all(lzma, zlib))))]
 pub type CdLzmaCodec = CdCodec<LzmaCodec, ZlibCodec>;
 
 /// CD-ROM wrapper decompression codec (cdzl) using the [Deflate codec](crate::codecs::ZlibCodec)
@@ -92,6 +98,8 @@ pub type CdLzmaCodec = CdCodec<LzmaCodec, ZlibCodec>;
 /// 2448, the size of each CD frame.
 /// The input buffer must contain exactly enough data to fill the output buffer
 /// when decompressed.
+#[cfg(feature = "zlib")]
+#[cfg_attr(docsrs, doc(cfg(zlib)))]
 pub type CdZlibCodec = CdCodec<ZlibCodec, ZlibCodec>;
 
 /// CD-ROM wrapper decompression codec (cdzs) using the [Zstandard codec](crate::codecs::ZstdCodec)
@@ -105,8 +113,8 @@ pub type CdZlibCodec = CdCodec<ZlibCodec, ZlibCodec>;
 /// [Header, Frame0, Frame1, ..., FrameN, Subcode0, Subcode1, ..., SubcodeN]
 /// ```
 ///
-/// The slice of the input buffer from `Frame0` to `Frame1` is a single Deflate compressed stream,
-/// followed by the subcode data which is a single Deflate compressed stream.
+/// The slice of the input buffer from `Frame0` to `Frame1` is a single Zstandard compressed stream,
+/// followed by the subcode data which is a single Zstandard compressed stream.
 ///
 /// The size of the header is determined by the number of 2448-byte sized frames that can fit
 /// into a hunk-sized buffer and the length of such buffer. First, the number of ECC bytes
@@ -134,29 +142,49 @@ pub type CdZlibCodec = CdCodec<ZlibCodec, ZlibCodec>;
 /// 2448, the size of each CD frame.
 /// The input buffer must contain exactly enough data to fill the output buffer
 /// when decompressed.
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(zstd)))]
 pub type CdZstdCodec = CdCodec<ZstdCodec, ZstdCodec>;
 
-impl CompressionCodecType for CdLzmaCodec {
-    fn codec_type(&self) -> CodecType {
-        CodecType::LzmaCdV5
-    }
+/// A codec that decompresses the sector data of a [`CdCodec`](crate::compression::cdrom::CdCodec).
+///
+/// The codec used for sector data identifies the CD-ROM wrapper codec, so supporting a new
+/// CD-ROM codec only requires implementing this trait for its sector codec, then adding a
+/// [`CodecType`](crate::header::CodecType) variant and initializing the wrapper for it in
+/// `CodecType::init`.
+pub trait CdSectorCodec: CodecImplementation {
+    /// The codec type of the CD-ROM wrapper codec that uses this codec for sector data.
+    #[cfg_attr(not(feature = "codec_api"), allow(dead_code))]
+    const CD_CODEC_TYPE: CodecType;
 }
 
-impl CompressionCodecType for CdZlibCodec {
-    fn codec_type(&self) -> CodecType {
-        CodecType::ZLibCdV5
-    }
+#[cfg(all(feature = "lzma", feature = "zlib"))]
+impl CdSectorCodec for LzmaCodec {
+    const CD_CODEC_TYPE: CodecType = CodecType::LzmaCdV5;
+}
+
+#[cfg(feature = "zlib")]
+impl CdSectorCodec for ZlibCodec {
+    const CD_CODEC_TYPE: CodecType = CodecType::ZLibCdV5;
+}
+
+#[cfg(feature = "zstd")]
+impl CdSectorCodec for ZstdCodec {
+    const CD_CODEC_TYPE: CodecType = CodecType::ZstdCdV5;
 }
 
-impl CompressionCodecType for CdZstdCodec {
+impl<Engine: CdSectorCodec, SubEngine: CodecImplementation> CompressionCodecType
+    for CdCodec<Engine, SubEngine>
+{
     fn codec_type(&self) -> CodecType {
-        CodecType::ZstdCdV5
+        Engine::CD_CODEC_TYPE
     }
 }
 
-impl CompressionCodec for CdZlibCodec {}
-impl CompressionCodec for CdLzmaCodec {}
-impl CompressionCodec for CdZstdCodec {}
+impl<Engine: CdSectorCodec + Send + Sync, SubEngine: CodecImplementation + Send + Sync>
+    CompressionCodec for CdCodec<Engine, SubEngine>
+{
+}
 
 // unstable(adt_const_params): const TYPE: CodecType, but marker traits bring us
 // most of the way.
@@ -165,6 +193,7 @@ pub struct CdCodec<Engine: CodecImplementation, SubEngine: CodecImplementation>
     engine: Engine,
     sub_engine: SubEngine,
     buffer: Vec<u8>,
+    policy: SectorPolicy,
 }
 
 impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CodecImplementation
@@ -180,9 +209,18 @@ impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CodecImplement
             engine: Engine::new((hunk_size / CD_FRAME_SIZE) * CD_MAX_SECTOR_DATA)?,
             sub_engine: SubEngine::new((hunk_size / CD_FRAME_SIZE) * CD_MAX_SUBCODE_DATA)?,
             buffer,
+            policy: SectorPolicy::default(),
         })
     }
 
+    fn buffer_bytes(&self) -> usize {
+        self.buffer.capacity() + self.engine.buffer_bytes() + self.sub_engine.buffer_bytes()
+    }
+
+    fn set_sector_policy(&mut self, policy: SectorPolicy) {
+        self.policy = policy;
+    }
+
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
         // https://github.com/rtissera/libchdr/blob/cdcb714235b9ff7d207b703260706a364282b063/src/libchdr_chd.c#L647
         let frames = output.len() / CD_FRAME_SIZE as usize;
@@ -204,15 +242,15 @@ impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CodecImplement
             &mut self.buffer[..frames * CD_MAX_SECTOR_DATA as usize],
         )?;
 
-        #[cfg(feature = "want_subcode")]
-        let sub_res = self.sub_engine.decompress(
-            &input[header_bytes + sector_compressed_len as usize..],
-            &mut self.buffer[frames * CD_MAX_SECTOR_DATA as usize..]
-                [..frames * CD_MAX_SUBCODE_DATA as usize],
-        )?;
-
-        #[cfg(not(feature = "want_subcode"))]
-        let sub_res = DecompressResult::default();
+        let sub_res = if self.policy.wants_subcode() {
+            self.sub_engine.decompress(
+                &input[header_bytes + sector_compressed_len as usize..],
+                &mut self.buffer[frames * CD_MAX_SECTOR_DATA as usize..]
+                    [..frames * CD_MAX_SUBCODE_DATA as usize],
+            )?
+        } else {
+            DecompressResult::default()
+        };
 
         // Decompressed data has layout
         // [Frame0, Frame1, ..., FrameN, Subcode0, Subcode1, ..., SubcodeN]
@@ -229,28 +267,124 @@ impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CodecImplement
         }
 
         // Reassemble subcode data to expected layout.
-        #[cfg(feature = "want_subcode")]
         for (frame_num, chunk) in self.buffer[frames * CD_MAX_SECTOR_DATA as usize..]
+            [..frames * CD_MAX_SUBCODE_DATA as usize]
             .chunks_exact(CD_MAX_SUBCODE_DATA as usize)
             .enumerate()
         {
-            output[frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
-                [..CD_MAX_SUBCODE_DATA as usize]
-                .copy_from_slice(chunk);
+            let subcode = &mut output
+                [frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
+                [..CD_MAX_SUBCODE_DATA as usize];
+            if self.policy.wants_subcode() {
+                subcode.copy_from_slice(chunk);
+            } else {
+                subcode.fill(0);
+            }
         }
 
         // Recreate ECC data
-        #[cfg(feature = "want_raw_data_sector")]
-        for frame_num in 0..frames {
-            let mut sector = <&mut [u8; CD_MAX_SECTOR_DATA as usize]>::try_from(
-                &mut output[frame_num * CD_FRAME_SIZE as usize..][..CD_MAX_SECTOR_DATA as usize],
-            )?;
-            if (input[frame_num / 8] & (1 << (frame_num % 8))) != 0 {
-                sector[0..12].copy_from_slice(&CD_SYNC_HEADER);
-                sector.generate_ecc();
+        if self.policy.wants_raw_data_sector() {
+            for frame_num in 0..frames {
+                let sector = <&mut [u8; CD_MAX_SECTOR_DATA as usize]>::try_from(
+                    &mut output[frame_num * CD_FRAME_SIZE as usize..]
+                        [..CD_MAX_SECTOR_DATA as usize],
+                )?;
+                if (input[frame_num / 8] & (1 << (frame_num % 8))) != 0 {
+                    sector[0..12].copy_from_slice(&CD_SYNC_HEADER);
+                    ecc::generate_ecc(sector);
+                }
             }
         }
 
         Ok(frame_res + sub_res)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::cdrom::CD_FRAME_SIZE;
+    #[allow(unused_imports)]
+    use crate::compression::{CodecImplementation, CompressionCodecType};
+    use crate::header::CodecType;
+
+    #[test]
+    fn cd_codec_type_test() {
+        let hunk_size = CD_FRAME_SIZE * 8;
+        #[cfg(all(feature = "lzma", feature = "zlib"))]
+        assert!(matches!(
+            crate::compression::cdrom::CdLzmaCodec::new(hunk_size)
+                .expect("cdlz")
+                .codec_type(),
+            CodecType::LzmaCdV5
+        ));
+        #[cfg(feature = "zlib")]
+        assert!(matches!(
+            crate::compression::cdrom::CdZlibCodec::new(hunk_size)
+                .expect("cdzl")
+                .codec_type(),
+            CodecType::ZLibCdV5
+        ));
+        #[cfg(feature = "zstd")]
+        assert!(matches!(
+            crate::compression::cdrom::CdZstdCodec::new(hunk_size)
+                .expect("cdzs")
+                .codec_type(),
+            CodecType::ZstdCdV5
+        ));
+        #[cfg(not(feature = "zstd"))]
+        assert!(matches!(
+            CodecType::ZstdCdV5.init(hunk_size),
+            Err(crate::Error::UnsupportedCodec { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "zlib")]
+    fn cd_sector_policy_test() {
+        use crate::cdrom::{SectorPolicy, CD_MAX_SECTOR_DATA, CD_SYNC_HEADER};
+        use crate::compression::cdrom::CdZlibCodec;
+        use crate::testing::deflate;
+
+        const FRAMES: usize = 2;
+        // a Mode 1 sector with its sync header and ECC stripped, followed by an audio sector.
+        let mut sectors = vec![0u8; FRAMES * CD_MAX_SECTOR_DATA as usize];
+        sectors[15] = 1;
+        sectors[16..2064].fill(0x5a);
+        sectors[2352..].fill(0x33);
+        let subcode: Vec<u8> = (0..FRAMES * 96).map(|i| i as u8).collect();
+
+        // only the first frame has its sync header and ECC stripped.
+        let compressed = deflate(&sectors);
+        let mut input = vec![0b01];
+        input.extend_from_slice(&(compressed.len() as u16).to_be_bytes());
+        input.extend_from_slice(&compressed);
+        input.extend_from_slice(&deflate(&subcode));
+
+        let mut codec = CdZlibCodec::new(CD_FRAME_SIZE * FRAMES as u32).expect("cdzl");
+        for policy in [
+            SectorPolicy::Full,
+            SectorPolicy::SkipSubcode,
+            SectorPolicy::SkipEcc,
+            SectorPolicy::SectorDataOnly,
+        ] {
+            codec.set_sector_policy(policy);
+            let mut output = vec![0xaau8; CD_FRAME_SIZE as usize * FRAMES];
+            codec.decompress(&input, &mut output).expect("decompress");
+
+            let (first, second) = output.split_at(CD_FRAME_SIZE as usize);
+            let regenerated = policy.wants_raw_data_sector();
+            assert_eq!(regenerated, first[..12] == CD_SYNC_HEADER);
+            assert_eq!(regenerated, first[2064..2352].iter().any(|&b| b != 0));
+            assert_eq!(&sectors[12..2064], &first[12..2064]);
+            assert_eq!(&sectors[2352..], &second[..2352]);
+            for (frame, out) in output.chunks_exact(CD_FRAME_SIZE as usize).enumerate() {
+                let sub = &out[CD_MAX_SECTOR_DATA as usize..];
+                if policy.wants_subcode() {
+                    assert_eq!(&subcode[frame * 96..][..96], sub);
+                } else {
+                    assert!(sub.iter().all(|&b| b == 0));
+                }
+            }
+        }
+    }
+}
@@ -2,10 +2,12 @@ use std::io::Cursor;
 use std::marker::PhantomData;
 use std::mem;
 
-use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use claxon::frame::FrameReader;
 
-use crate::cdrom::{CD_FRAME_SIZE, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA};
+#[cfg(feature = "zlib")]
+use crate::cdrom::{SectorPolicy, CD_FRAME_SIZE, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA};
+#[cfg(feature = "zlib")]
 use crate::compression::zlib::ZlibCodec;
 use crate::compression::{
     CodecImplementation, CompressionCodec, CompressionCodecType, DecompressResult,
@@ -13,6 +15,9 @@ use crate::compression::{
 use crate::error::{Error, Result};
 use crate::header::CodecType;
 
+/// The size of each sample of a single channel in bytes.
+const SAMPLE_BYTES: usize = mem::size_of::<i16>();
+
 /// Generic block decoder for FLAC.
 ///
 /// Defaults assume 2 channel interleaved FLAC.
@@ -27,7 +32,7 @@ impl<T: ByteOrder, const CHANNELS: usize> CodecImplementation for FlacCodec<T, C
     where
         Self: Sized,
     {
-        if hunk_bytes % (CHANNELS * mem::size_of::<i16>()) as u32 != 0 {
+        if !hunk_bytes.is_multiple_of((CHANNELS * SAMPLE_BYTES) as u32) {
             return Err(Error::CodecError);
         }
 
@@ -37,63 +42,88 @@ impl<T: ByteOrder, const CHANNELS: usize> CodecImplementation for FlacCodec<T, C
         })
     }
 
-    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
-        let comp_buf = Cursor::new(input);
+    fn buffer_bytes(&self) -> usize {
+        self.buffer.capacity() * std::mem::size_of::<i32>()
+    }
 
-        // Number of samples to write to the buffer.
-        let sample_len = output.len() / (CHANNELS * mem::size_of::<i16>());
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
+        // every row of the output holds exactly one sample.
+        self.decompress_rows(
+            input,
+            output,
+            SAMPLE_BYTES * CHANNELS,
+            SAMPLE_BYTES * CHANNELS,
+        )
+    }
+}
 
+impl<T: ByteOrder, const CHANNELS: usize> FlacCodec<T, CHANNELS> {
+    /// Decompresses samples directly into `output`, where every `row_bytes` of the output begin with
+    /// `data_bytes` bytes of samples, leaving the rest of each row untouched.
+    ///
+    /// This lets cdfl write sector data to its final position in each frame, without copying the
+    /// decoded samples through an intermediate buffer.
+    fn decompress_rows(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        data_bytes: usize,
+        row_bytes: usize,
+    ) -> Result<DecompressResult> {
         // We don't need to create a fake header since claxon will read raw FLAC frames just fine.
         // We just need to be careful not to read past the number of blocks in the input buffer.
-        let mut frame_read = FrameReader::new(comp_buf);
+        let mut frame_read = FrameReader::new(Cursor::new(input));
 
-        let mut cursor = Cursor::new(output);
+        // A little bit of a misnomer. 1 'sample' refers to a sample for all channels.
+        let sample_len = output.len() / row_bytes * (data_bytes / (SAMPLE_BYTES * CHANNELS));
+        let mut samples = output
+            .chunks_exact_mut(row_bytes)
+            .flat_map(|row| row[..data_bytes].chunks_exact_mut(SAMPLE_BYTES * CHANNELS));
 
         // Buffer to hold decompressed FLAC block data.
         let mut block_buf = mem::take(&mut self.buffer);
-
-        // A little bit of a misnomer. 1 'sample' refers to a sample for all channels.
         let mut samples_written = 0;
 
         while samples_written < sample_len {
             // Loop through all blocks until we have enough samples written.
-            match frame_read.read_next_or_eof(block_buf) {
-                Ok(Some(block)) => {
-                    // We assume 2 channels (by default), so we can use claxon's stereo_samples
-                    // iterator for slightly better performance.
-                    #[cfg(not(feature = "nonstandard_channel_count"))]
-                    for (l, r) in block.stereo_samples() {
-                        cursor.write_i16::<T>(l as i16)?;
-                        cursor.write_i16::<T>(r as i16)?;
-                        samples_written += 1;
-                    }
-
-                    // This is generic over number of assumed channels, but is broken effectively
-                    // for any value other than 2.
-                    // What we really want here is specialization for CHANNELS = 2 ...
-                    #[cfg(feature = "nonstandard_channel_count")]
-                    for sample in 0..block.len() / block.channels() {
-                        for channel in 0..block.channels() {
-                            let sample_data = block.sample(channel, sample) as u16;
-                            cursor.write_i16::<T>(sample_data as i16)?;
-                        }
-                        samples_written += 1;
-                    }
-
-                    block_buf = block.into_buffer();
-                }
-                _ => {
-                    // If frame_read dies our buffer just gets eaten. The Error return for a failed
-                    // read does not expose the inner buffer.
-                    return Err(Error::DecompressionError);
+            let block = match frame_read.read_next_or_eof(block_buf) {
+                Ok(Some(block)) => block,
+                // If frame_read dies our buffer just gets eaten. The Error return for a failed
+                // read does not expose the inner buffer.
+                _ => return Err(Error::DecompressionError),
+            };
+            if samples_written + block.duration() as usize > sample_len
+                || block.channels() as usize != CHANNELS
+            {
+                return Err(Error::DecompressionError);
+            }
+
+            // We assume 2 channels (by default), so we can use claxon's stereo_samples
+            // iterator for slightly better performance. Blocks are checked to fit above, so
+            // there is an output sample for every decoded sample.
+            #[cfg(not(feature = "nonstandard_channel_count"))]
+            for ((l, r), out) in block.stereo_samples().zip(samples.by_ref()) {
+                T::write_i16(&mut out[0..2], l as i16);
+                T::write_i16(&mut out[2..4], r as i16);
+            }
+
+            // This is generic over number of assumed channels.
+            // What we really want here is specialization for CHANNELS = 2 ...
+            #[cfg(feature = "nonstandard_channel_count")]
+            for (sample, out) in (0..block.duration()).zip(samples.by_ref()) {
+                for (channel, out) in out.chunks_exact_mut(SAMPLE_BYTES).enumerate() {
+                    T::write_i16(out, block.sample(channel as u32, sample) as i16);
                 }
             }
+
+            samples_written += block.duration() as usize;
+            block_buf = block.into_buffer();
         }
 
         self.buffer = block_buf;
         let bytes_in = frame_read.into_inner().position();
         Ok(DecompressResult::new(
-            samples_written * 4,
+            samples_written * SAMPLE_BYTES * CHANNELS,
             bytes_in as usize,
         ))
     }
@@ -136,6 +166,10 @@ impl CodecImplementation for RawFlacCodec {
         })
     }
 
+    fn buffer_bytes(&self) -> usize {
+        self.be.buffer_bytes() + self.le.buffer_bytes()
+    }
+
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
         match input[0] {
             b'L' => self.le.decompress(&input[1..], output),
@@ -172,27 +206,36 @@ impl CodecImplementation for RawFlacCodec {
 /// ```c
 /// [Frame0, Subcode0, Frame1, Subcode1, ..., FrameN, SubcodeN]
 /// ```
+/// Sector data is decoded directly into its position in the output, so only the subcode data is
+/// copied when swizzling.
+///
 /// FLAC compressed frames does not require manual reconstruction of the sync header or ECC bytes.
 ///
 /// ## Buffer Restrictions
 /// Each compressed CDFL hunk decompresses to a hunk-sized chunk. The hunk size must be a multiple
 /// of 2448, the size of each CD frame. The input buffer must contain enough samples to fill
 /// the number of CD sectors that can fit into the output buffer.
+#[cfg(feature = "zlib")]
+#[cfg_attr(docsrs, doc(cfg(zlib)))]
 pub struct CdFlacCodec {
     // cdfl always writes in big endian.
     engine: FlacCodec<BigEndian>,
     sub_engine: ZlibCodec,
     buffer: Vec<u8>,
+    policy: SectorPolicy,
 }
 
+#[cfg(feature = "zlib")]
 impl CompressionCodec for CdFlacCodec {}
 
+#[cfg(feature = "zlib")]
 impl CompressionCodecType for CdFlacCodec {
     fn codec_type(&self) -> CodecType {
         CodecType::FlacCdV5
     }
 }
 
+#[cfg(feature = "zlib")]
 impl CodecImplementation for CdFlacCodec {
     fn new(hunk_size: u32) -> Result<Self>
     where
@@ -207,55 +250,150 @@ impl CodecImplementation for CdFlacCodec {
         let flac_data_size = max_frames * CD_MAX_SECTOR_DATA;
 
         // neither FlacCodec nor ZlibCodec actually make use of hunk_size.
+        // Sector data is decoded directly into the output, so only subcode data is buffered.
         Ok(CdFlacCodec {
             engine: FlacCodec::new(flac_data_size)?,
             sub_engine: ZlibCodec::new(hunk_size)?,
-            buffer: vec![0u8; hunk_size as usize],
+            buffer: vec![0u8; (max_frames * CD_MAX_SUBCODE_DATA) as usize],
+            policy: SectorPolicy::default(),
         })
     }
 
-    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
-        let total_frames = output.len() / CD_FRAME_SIZE as usize;
-        let frame_res = self.engine.decompress(
-            input,
-            &mut self.buffer[..total_frames * CD_MAX_SECTOR_DATA as usize],
-        )?;
+    fn buffer_bytes(&self) -> usize {
+        self.buffer.capacity() + self.engine.buffer_bytes() + self.sub_engine.buffer_bytes()
+    }
 
-        #[cfg(feature = "want_subcode")]
-        let sub_res = self.sub_engine.decompress(
-            &input[frame_res.total_in()..],
-            &mut self.buffer[total_frames * CD_MAX_SECTOR_DATA as usize..]
-                [..total_frames * CD_MAX_SUBCODE_DATA as usize],
-        )?;
+    fn set_sector_policy(&mut self, policy: SectorPolicy) {
+        self.policy = policy;
+    }
 
-        #[cfg(not(feature = "want_subcode"))]
-        let sub_res = DecompressResult::default();
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
+        let total_frames = output.len() / CD_FRAME_SIZE as usize;
 
         // Decompressed FLAC data has layout
         // [Frame0, Frame1, ..., FrameN, Subcode0, Subcode1, ..., SubcodeN]
         // We need to reassemble the data to be
         // [Frame0, Subcode0, Frame1, Subcode1, ..., FrameN, SubcodeN]
+        // so the sector data of each frame is decoded directly into its position in the output.
+        let frame_res = self.engine.decompress_rows(
+            input,
+            &mut output[..total_frames * CD_FRAME_SIZE as usize],
+            CD_MAX_SECTOR_DATA as usize,
+            CD_FRAME_SIZE as usize,
+        )?;
 
-        // Reassemble frame data to expected layout.
-        for (frame_num, chunk) in self.buffer[..total_frames * CD_MAX_SECTOR_DATA as usize]
-            .chunks_exact(CD_MAX_SECTOR_DATA as usize)
-            .enumerate()
-        {
-            output[frame_num * CD_FRAME_SIZE as usize..][..CD_MAX_SECTOR_DATA as usize]
-                .copy_from_slice(chunk);
-        }
+        let sub_res = if self.policy.wants_subcode() {
+            self.sub_engine.decompress(
+                &input[frame_res.total_in()..],
+                &mut self.buffer[..total_frames * CD_MAX_SUBCODE_DATA as usize],
+            )?
+        } else {
+            DecompressResult::default()
+        };
 
         // Reassemble subcode data to expected layout.
-        #[cfg(feature = "want_subcode")]
-        for (frame_num, chunk) in self.buffer[total_frames * CD_MAX_SECTOR_DATA as usize..]
+        for (frame_num, chunk) in self.buffer[..total_frames * CD_MAX_SUBCODE_DATA as usize]
             .chunks_exact(CD_MAX_SUBCODE_DATA as usize)
             .enumerate()
         {
-            output[frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
-                [..CD_MAX_SUBCODE_DATA as usize]
-                .copy_from_slice(chunk);
+            let subcode = &mut output
+                [frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
+                [..CD_MAX_SUBCODE_DATA as usize];
+            if self.policy.wants_subcode() {
+                subcode.copy_from_slice(chunk);
+            } else {
+                subcode.fill(0);
+            }
         }
 
         Ok(frame_res + sub_res)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::compression::flac::RawFlacCodec;
+    use crate::compression::CodecImplementation;
+    use crate::testing::flac_frames;
+    use crate::Error;
+
+    fn samples(count: usize) -> Vec<(i16, i16)> {
+        (0..count)
+            .map(|i| (i as i16 * 3 - 1000, -(i as i16) * 7))
+            .collect()
+    }
+
+    #[test]
+    fn raw_flac_test() {
+        let samples = samples(1024);
+        let frames = flac_frames(&samples, 256);
+        let mut codec = RawFlacCodec::new(4096).expect("flac");
+        let mut output = vec![0u8; 4096];
+
+        for (endian, to_bytes) in [
+            (b'B', i16::to_be_bytes as fn(i16) -> [u8; 2]),
+            (b'L', i16::to_le_bytes as fn(i16) -> [u8; 2]),
+        ] {
+            let mut input = vec![endian];
+            input.extend_from_slice(&frames);
+            let res = codec.decompress(&input, &mut output).expect("decompress");
+            assert_eq!(4096, res.total_out());
+            assert_eq!(frames.len(), res.total_in());
+
+            let expected: Vec<u8> = samples
+                .iter()
+                .flat_map(|&(l, r)| [to_bytes(l), to_bytes(r)])
+                .flatten()
+                .collect();
+            assert_eq!(expected, output);
+        }
+
+        // a block that does not fit in the output is an error.
+        let long: Vec<_> = samples.iter().cycle().take(1100).copied().collect();
+        let mut input = vec![b'B'];
+        input.extend_from_slice(&flac_frames(&long, 1100));
+        assert!(matches!(
+            codec.decompress(&input, &mut output),
+            Err(Error::DecompressionError)
+        ));
+        assert!(matches!(
+            codec.decompress(b"X", &mut output),
+            Err(Error::DecompressionError)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "zlib")]
+    fn cd_flac_test() {
+        use crate::cdrom::{SectorPolicy, CD_FRAME_SIZE, CD_MAX_SECTOR_DATA};
+        use crate::compression::flac::CdFlacCodec;
+
+        const FRAMES: usize = 3;
+        let samples = samples(FRAMES * CD_MAX_SECTOR_DATA as usize / 4);
+        let mut input = flac_frames(&samples, 588);
+        let subcode: Vec<u8> = (0..FRAMES * 96).map(|i| i as u8).collect();
+        input.extend_from_slice(&crate::testing::deflate(&subcode));
+
+        let sectors: Vec<u8> = samples
+            .iter()
+            .flat_map(|&(l, r)| [l.to_be_bytes(), r.to_be_bytes()])
+            .flatten()
+            .collect();
+        let mut codec = CdFlacCodec::new(CD_FRAME_SIZE * FRAMES as u32).expect("cdfl");
+        for policy in [SectorPolicy::Full, SectorPolicy::SkipSubcode] {
+            codec.set_sector_policy(policy);
+            let mut output = vec![0xaau8; CD_FRAME_SIZE as usize * FRAMES];
+            codec.decompress(&input, &mut output).expect("decompress");
+
+            for (frame, out) in output.chunks_exact(CD_FRAME_SIZE as usize).enumerate() {
+                let (sector, sub) = out.split_at(CD_MAX_SECTOR_DATA as usize);
+                assert_eq!(&sectors[frame * 2352..][..2352], sector);
+                if policy.wants_subcode() {
+                    assert_eq!(&subcode[frame * 96..][..96], sub);
+                } else {
+                    assert!(sub.iter().all(|&b| b == 0));
+                }
+            }
+        }
+    }
+}
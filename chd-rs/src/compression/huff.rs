@@ -30,7 +30,8 @@ impl CodecImplementation for HuffmanCodec {
 
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
         let mut bit_reader = BitReader::new(input);
-        let decoder = Huffman8BitDecoder::from_huffman_tree(&mut bit_reader)?;
+        // the lookup array of the decoder is too large for small stacks.
+        let decoder = Huffman8BitDecoder::from_huffman_tree_boxed(&mut bit_reader)?;
 
         for i in output.iter_mut() {
             *i = decoder.decode_one(&mut bit_reader)? as u8;
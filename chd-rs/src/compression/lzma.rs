@@ -3,7 +3,7 @@ use crate::compression::{
 };
 use crate::error::{Error, Result};
 use crate::header::CodecType;
-use lzma_rs::decompress::raw::{LzmaDecoder, LzmaParams, LzmaProperties};
+use lzma_rs::decompress::raw::{LzAccumBuffer, LzmaDecoder, LzmaParams, LzmaProperties};
 use std::io::Cursor;
 /// LZMA (lzma) decompression codec.
 ///
@@ -49,11 +49,20 @@ use std::io::Cursor;
 /// Each compressed LZMA hunk decompresses to a hunk-sized chunk.
 /// The input buffer must contain exactly enough data to fill the output buffer
 /// when decompressed.
+///
+/// ## Decoder Reuse
+/// Each hunk is compressed as an independent LZMA stream by a freshly reset encoder, so the
+/// dictionary and probability state of one hunk can never be carried over to the next.
+/// Instead of constructing a new decoder for every hunk, the decoder state is reset in place.
+/// The dictionary buffer is allocated once when the codec is created and reused for every hunk,
+/// so decompressing a hunk does not allocate.
 pub struct LzmaCodec {
     // The LZMA codec for CHD uses raw LZMA chunks without a stream header. The result
     // is that the chunks are encoded with the defaults used in LZMA 19.0.
     // These defaults are lc = 3, lp = 0, pb = 2.
     engine: LzmaDecoder,
+    // the length of the dictionary buffer allocated when the codec is created.
+    dict_bytes: usize,
 }
 
 impl CompressionCodec for LzmaCodec {}
@@ -100,38 +109,6 @@ impl CompressionCodecType for LzmaCodec {
     }
 }
 
-#[cfg(not(feature = "fast_lzma"))]
-impl CodecImplementation for LzmaCodec {
-    fn new(hunk_size: u32) -> Result<Self> {
-        Ok(LzmaCodec {
-            engine: LzmaDecoder::new(
-                LzmaParams::new(
-                    LzmaProperties {
-                        lc: 3,
-                        lp: 0,
-                        pb: 2,
-                    },
-                    get_lzma_dict_size(9, hunk_size),
-                    None,
-                ),
-                None,
-            )
-            .map_err(|_| Error::DecompressionError)?,
-        })
-    }
-
-    fn decompress(&mut self, input: &[u8], mut output: &mut [u8]) -> Result<DecompressResult> {
-        let mut read = Cursor::new(input);
-        let len = output.len();
-        self.engine.reset(Some(Some(len as u64)));
-        self.engine
-            .decompress(&mut read, &mut output)
-            .map_err(|_| Error::DecompressionError)?;
-        Ok(DecompressResult::new(len, read.position() as usize))
-    }
-}
-
-#[cfg(feature = "fast_lzma")]
 impl CodecImplementation for LzmaCodec {
     fn new(hunk_size: u32) -> Result<Self> {
         let dict_size = get_lzma_dict_size(9, hunk_size);
@@ -150,13 +127,19 @@ impl CodecImplementation for LzmaCodec {
                 vec![0; dict_size as usize],
             )
             .map_err(|_| Error::CodecError)?,
+            dict_bytes: dict_size as usize,
         })
     }
 
+    fn buffer_bytes(&self) -> usize {
+        self.dict_bytes
+    }
+
     fn decompress(&mut self, input: &[u8], mut output: &mut [u8]) -> Result<DecompressResult> {
-        use lzma_rs::decompress::raw::LzAccumBuffer;
         let mut read = Cursor::new(input);
         let len = output.len();
+        // Hunks are independent streams, so the decoder must always start from a clean state,
+        // even if the previous hunk failed to decompress.
         self.engine.reset(Some(Some(len as u64)));
         self.engine
             .decompress_with_buffer::<LzAccumBuffer<_>, _, _>(&mut read, &mut output)
@@ -164,3 +147,40 @@ impl CodecImplementation for LzmaCodec {
         Ok(DecompressResult::new(len, read.position() as usize))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::compression::lzma::LzmaCodec;
+    use crate::compression::CodecImplementation;
+    use std::io::Cursor;
+
+    // Compresses a raw LZMA stream without the 13 byte LZMA header.
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        lzma_rs::lzma_compress(&mut Cursor::new(data), &mut out).expect("compress");
+        out.split_off(13)
+    }
+
+    #[test]
+    fn lzma_reuse_test() {
+        let hunk_size = 4096;
+        let first: Vec<u8> = (0..hunk_size).map(|i| (i % 251) as u8).collect();
+        let second: Vec<u8> = (0..hunk_size).map(|i| (i * 7 % 13) as u8).collect();
+
+        let mut codec = LzmaCodec::new(hunk_size as u32).expect("codec");
+        let mut output = vec![0u8; hunk_size];
+        for data in [&first, &second, &first] {
+            codec
+                .decompress(&compress(data), &mut output)
+                .expect("decompress");
+            assert_eq!(data, &output);
+        }
+
+        // a failed hunk must not affect the next hunk.
+        assert!(codec.decompress(&[0xff; 16], &mut output).is_err());
+        codec
+            .decompress(&compress(&second), &mut output)
+            .expect("decompress");
+        assert_eq!(second, output);
+    }
+}
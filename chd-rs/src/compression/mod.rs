@@ -1,28 +1,56 @@
+use crate::cdrom::SectorPolicy;
 use crate::error::Result;
 use crate::header::CodecType;
 use std::ops::{Add, AddAssign};
 
+#[cfg(feature = "avhuff")]
 mod avhuff;
+#[cfg(any(feature = "zlib", feature = "zstd"))]
 mod cdrom;
-mod ecc;
+#[cfg(feature = "flac")]
 mod flac;
+#[cfg(feature = "huffman")]
 mod huff;
+#[cfg(feature = "lzma")]
 mod lzma;
 mod none;
+#[cfg(feature = "zlib")]
 mod zlib;
+#[cfg(feature = "zstd")]
 mod zstd;
 
+// every codec other than NoneCodec is enabled by its own feature.
 pub mod codecs {
+    #[cfg(feature = "avhuff")]
+    #[cfg_attr(docsrs, doc(cfg(avhuff)))]
     pub use crate::compression::avhuff::AVHuffCodec;
+    #[cfg(all(feature = "lzma", feature = "zlib"))]
+    #[cfg_attr(docsrs, doc(cfg(all(lzma, zlib))))]
     pub use crate::compression::cdrom::CdLzmaCodec;
+    #[cfg(feature = "zlib")]
+    #[cfg_attr(docsrs, doc(cfg(zlib)))]
     pub use crate::compression::cdrom::CdZlibCodec;
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(docsrs, doc(cfg(zstd)))]
     pub use crate::compression::cdrom::CdZstdCodec;
+    #[cfg(all(feature = "flac", feature = "zlib"))]
+    #[cfg_attr(docsrs, doc(cfg(all(flac, zlib))))]
     pub use crate::compression::flac::CdFlacCodec;
+    #[cfg(feature = "flac")]
+    #[cfg_attr(docsrs, doc(cfg(flac)))]
     pub use crate::compression::flac::RawFlacCodec;
+    #[cfg(feature = "huffman")]
+    #[cfg_attr(docsrs, doc(cfg(huffman)))]
     pub use crate::compression::huff::HuffmanCodec;
+    #[cfg(feature = "lzma")]
+    #[cfg_attr(docsrs, doc(cfg(lzma)))]
     pub use crate::compression::lzma::LzmaCodec;
     pub use crate::compression::none::NoneCodec;
+    #[cfg(feature = "zlib")]
+    #[cfg_attr(docsrs, doc(cfg(zlib)))]
     pub use crate::compression::zlib::ZlibCodec;
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(docsrs, doc(cfg(zstd)))]
     pub use crate::compression::zstd::ZstdCodec;
 }
 
@@ -53,6 +81,20 @@ pub trait CodecImplementation {
     /// length as `hunk_size`, but this may be dependent on the codec
     /// implementation.
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult>;
+
+    /// Returns the number of bytes of memory held by the buffers of this codec.
+    ///
+    /// This accounts for the buffers allocated by the codec itself, but not for the internal state
+    /// of the decoder library it wraps, unless it is allocated up front like the LZMA dictionary.
+    /// The default implementation returns `0`.
+    fn buffer_bytes(&self) -> usize {
+        0
+    }
+
+    /// Sets which parts of each frame are decoded by a CD-ROM codec.
+    ///
+    /// Codecs that do not decode CD-ROM frames ignore the policy, which is the default implementation.
+    fn set_sector_policy(&mut self, _policy: SectorPolicy) {}
 }
 
 /// The result of a chunk decompression operation.
@@ -0,0 +1,364 @@
+//! Types and methods for telling what kind of content a CHD file stores, and reading it.
+//!
+//! The same container holds CD-ROMs, GD-ROMs, DVDs, hard disks and LaserDiscs, and only the metadata
+//! of a CHD file says which it is. [`Chd::content_kind`](crate::Chd::content_kind) classifies a CHD
+//! file by its metadata, and [`Chd::open_content`](crate::Chd::open_content) opens it with the
+//! reader that fits its content.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::content::Content;
+//! use chd::dvd::DVD_SECTOR_SIZE;
+//!
+//! let f = BufReader::new(File::open("file.chd")?);
+//! let chd = Chd::open(f, None)?;
+//! match chd.open_content()? {
+//!     Content::Cd { tracks, .. } | Content::GdRom { tracks, .. } => {
+//!         println!("{} tracks", tracks.len());
+//!     }
+//!     Content::HardDisk(mut disk) => {
+//!         let mut sector = vec![0u8; disk.sector_size() as usize];
+//!         disk.read_sectors(0, &mut sector)?;
+//!     }
+//!     Content::LaserDisc(mut frames) | Content::AudioVideo(mut frames) => {
+//!         let frame = frames.read_frame(0)?;
+//!         println!("{}x{}", frame.width, frame.height);
+//!     }
+//!     Content::Dvd(mut dvd) => {
+//!         let mut sector = [0u8; DVD_SECTOR_SIZE as usize];
+//!         dvd.read_sectors(16, &mut sector)?;
+//!     }
+//!     Content::Raw(reader) => println!("{} bytes", reader.logical_len()),
+//! }
+//! ```
+use crate::av::AvReader;
+use crate::cdrom::Track;
+use crate::dvd::DvdReader;
+use crate::error::{Error, Result};
+use crate::metadata::parse::HardDiskGeometry;
+use crate::metadata::{KnownMetadata, MetadataTag};
+use crate::read::ChdReader;
+use crate::Chd;
+use std::io::{Read, Seek};
+
+/// The kind of content stored in a CHD file, as told by its metadata.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ContentKind {
+    /// A CD-ROM, with `CHCD`, `CHTR` or `CHT2` track metadata.
+    Cd,
+    /// A GD-ROM, with `CHGT` or `CHGD` track metadata.
+    GdRom,
+    /// A DVD, with `DVD ` metadata.
+    Dvd,
+    /// A hard disk, with `GDDD` metadata or a V1 or V2 header.
+    HardDisk,
+    /// A LaserDisc, with `AVLD` metadata.
+    LaserDisc,
+    /// A video that is not a LaserDisc, with only `AVAV` metadata.
+    AudioVideo,
+    /// Data without any of the metadata above, which can only be read as a stream of bytes.
+    Raw,
+}
+
+/// The content of a CHD file, opened with the reader that fits it.
+///
+/// See [`Chd::open_content`](crate::Chd::open_content).
+pub enum Content<F: Read + Seek, P: Read + Seek = F> {
+    /// A CD-ROM and its tracks, which can be read with [`Track::reader`](crate::cdrom::Track::reader).
+    Cd {
+        /// The CHD file.
+        chd: Chd<F, P>,
+        /// The tracks of the CD-ROM.
+        tracks: Vec<Track>,
+    },
+    /// A GD-ROM and its tracks, which can be read with [`Track::reader`](crate::cdrom::Track::reader).
+    GdRom {
+        /// The CHD file.
+        chd: Chd<F, P>,
+        /// The tracks of the GD-ROM.
+        tracks: Vec<Track>,
+    },
+    /// A DVD, read by sector.
+    Dvd(DvdReader<F, P>),
+    /// A hard disk, read by sector.
+    HardDisk(HardDisk<F, P>),
+    /// The frames of a LaserDisc.
+    LaserDisc(AvReader<F, P>),
+    /// The frames of a video that is not a LaserDisc.
+    AudioVideo(AvReader<F, P>),
+    /// The data of the CHD file as a stream of bytes.
+    Raw(ChdReader<F, P>),
+}
+
+impl<F: Read + Seek, P: Read + Seek> Content<F, P> {
+    /// Returns the kind of this content.
+    pub fn kind(&self) -> ContentKind {
+        match self {
+            Content::Cd { .. } => ContentKind::Cd,
+            Content::GdRom { .. } => ContentKind::GdRom,
+            Content::Dvd(_) => ContentKind::Dvd,
+            Content::HardDisk(_) => ContentKind::HardDisk,
+            Content::LaserDisc(_) => ContentKind::LaserDisc,
+            Content::AudioVideo(_) => ContentKind::AudioVideo,
+            Content::Raw(_) => ContentKind::Raw,
+        }
+    }
+}
+
+/// A hard disk stored in a CHD file, read by logical block address.
+pub struct HardDisk<F: Read + Seek, P: Read + Seek = F> {
+    chd: Chd<F, P>,
+    geometry: HardDiskGeometry,
+    cmp_buf: Vec<u8>,
+    hunk_buf: Vec<u8>,
+}
+
+impl<F: Read + Seek, P: Read + Seek> HardDisk<F, P> {
+    /// Creates a new `HardDisk` from an opened [`Chd`](crate::Chd), reading its geometry from
+    /// its `GDDD` metadata, or from the header of V1 and V2 CHD files.
+    ///
+    /// If the CHD file has no hard disk metadata, returns [`Error::MetadataNotFound`](crate::Error::MetadataNotFound).
+    /// If the geometry can not be parsed, returns [`Error::InvalidMetadata`](crate::Error::InvalidMetadata).
+    pub fn new(mut chd: Chd<F, P>) -> Result<Self> {
+        let meta = match chd.header().legacy_hard_disk_metadata() {
+            Some(meta) => meta,
+            None => chd.metadata_for(KnownMetadata::HardDisk, 0)?,
+        };
+        let geometry = HardDiskGeometry::from_bytes(&meta.value)?;
+        Ok(HardDisk {
+            chd,
+            geometry,
+            cmp_buf: Vec::new(),
+            hunk_buf: Vec::new(),
+        })
+    }
+
+    /// Returns the geometry of the hard disk.
+    pub fn geometry(&self) -> HardDiskGeometry {
+        self.geometry
+    }
+
+    /// Returns the size in bytes of each sector.
+    pub fn sector_size(&self) -> u32 {
+        self.geometry.bytes_per_sector
+    }
+
+    /// Returns the number of sectors of the hard disk.
+    pub fn sector_count(&self) -> u64 {
+        self.geometry.sector_count()
+    }
+
+    /// Reads consecutive sectors starting at the logical block address `lba` into `buf`.
+    ///
+    /// If the length of `buf` is not a multiple of the sector size, returns
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter). If any of the sectors are past
+    /// the end of the hard disk, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange).
+    pub fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        let sector_size = self.sector_size() as u64;
+        if !(buf.len() as u64).is_multiple_of(sector_size) {
+            return Err(Error::InvalidParameter);
+        }
+        let count = buf.len() as u64 / sector_size;
+        let end = lba.checked_add(count);
+        if end.filter(|&end| end <= self.sector_count()).is_none() {
+            return Err(Error::HunkOutOfRange);
+        }
+
+        read_logical(
+            &mut self.chd,
+            &mut self.cmp_buf,
+            &mut self.hunk_buf,
+            lba * sector_size,
+            buf,
+        )
+    }
+
+    /// Consumes the hard disk and returns the underlying [`Chd`](crate::Chd).
+    pub fn into_inner(self) -> Chd<F, P> {
+        self.chd
+    }
+}
+
+/// Reads `buf.len()` bytes of the logical data of a CHD file starting at `pos`, without checking
+/// that they are within its logical size.
+pub(crate) fn read_logical<F: Read + Seek, P: Read + Seek>(
+    chd: &mut Chd<F, P>,
+    cmp_buf: &mut Vec<u8>,
+    hunk_buf: &mut Vec<u8>,
+    mut pos: u64,
+    mut buf: &mut [u8],
+) -> Result<()> {
+    let hunk_size = chd.header().hunk_size() as u64;
+    while !buf.is_empty() {
+        let hunk_num = u32::try_from(pos / hunk_size).map_err(|_| Error::HunkOutOfRange)?;
+        let offset = (pos % hunk_size) as usize;
+        let len = buf.len().min(hunk_size as usize - offset);
+        let (out, rest) = buf.split_at_mut(len);
+        chd.hunk(hunk_num)?
+            .read_range_in(offset, cmp_buf, hunk_buf, out)?;
+        pos += len as u64;
+        buf = rest;
+    }
+    Ok(())
+}
+
+/// Classifies content by the tags of its metadata entries.
+///
+/// Track metadata takes precedence, since CD-ROM and GD-ROM files never have other content.
+/// LaserDisc files have both `AVAV` and `AVLD` metadata. Tags that are not known are ignored,
+/// so files with only unknown metadata are read as a stream of bytes.
+fn kind_of_tags(tags: &[u32]) -> ContentKind {
+    let has = |kinds: &[KnownMetadata]| {
+        tags.iter()
+            .any(|&tag| kinds.iter().any(|kind| kind.metatag() == tag))
+    };
+    if has(&[KnownMetadata::GdRomOld, KnownMetadata::GdRomTrack]) {
+        ContentKind::GdRom
+    } else if has(&[
+        KnownMetadata::CdRomOld,
+        KnownMetadata::CdRomTrack,
+        KnownMetadata::CdRomTrack2,
+    ]) {
+        ContentKind::Cd
+    } else if has(&[KnownMetadata::Dvd]) {
+        ContentKind::Dvd
+    } else if has(&[KnownMetadata::AudioVideoLaserDisc]) {
+        ContentKind::LaserDisc
+    } else if has(&[KnownMetadata::AudioVideo]) {
+        ContentKind::AudioVideo
+    } else if has(&[KnownMetadata::HardDisk]) {
+        ContentKind::HardDisk
+    } else {
+        ContentKind::Raw
+    }
+}
+
+pub(crate) fn content_kind<F: Read + Seek, P: Read + Seek>(
+    chd: &mut Chd<F, P>,
+) -> Result<ContentKind> {
+    if chd.header().legacy_hard_disk_metadata().is_some() {
+        return Ok(ContentKind::HardDisk);
+    }
+    let mut refs = chd.metadata_refs();
+    let tags: Vec<u32> = refs.by_ref().map(|meta| meta.metatag()).collect();
+    if let Some(err) = refs.take_error() {
+        return Err(err);
+    }
+    Ok(kind_of_tags(&tags))
+}
+
+pub(crate) fn open_content<F: Read + Seek, P: Read + Seek>(
+    mut chd: Chd<F, P>,
+) -> Result<Content<F, P>> {
+    Ok(match content_kind(&mut chd)? {
+        ContentKind::Cd => {
+            let tracks = chd.tracks()?;
+            Content::Cd { chd, tracks }
+        }
+        ContentKind::GdRom => {
+            let tracks = chd.tracks()?;
+            Content::GdRom { chd, tracks }
+        }
+        ContentKind::Dvd => Content::Dvd(DvdReader::new(chd)?),
+        ContentKind::HardDisk => Content::HardDisk(HardDisk::new(chd)?),
+        ContentKind::LaserDisc => Content::LaserDisc(AvReader::new(chd)),
+        ContentKind::AudioVideo => Content::AudioVideo(AvReader::new(chd)),
+        ContentKind::Raw => Content::Raw(ChdReader::new(chd)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::content::{kind_of_tags, Content, ContentKind};
+    use crate::metadata::{KnownMetadata, MetadataTag};
+    use crate::testing::{v3_chd, v5_uncompressed_chd, LegacyHunk};
+    use crate::{Chd, Error};
+    use std::io::Cursor;
+
+    #[test]
+    fn kind_of_tags_test() {
+        let kind = |tags: &[KnownMetadata]| {
+            let tags: Vec<u32> = tags.iter().map(|tag| tag.metatag()).collect();
+            kind_of_tags(&tags)
+        };
+        assert_eq!(ContentKind::Raw, kind(&[]));
+        assert_eq!(ContentKind::Raw, kind(&[KnownMetadata::Creator]));
+        assert_eq!(ContentKind::Cd, kind(&[KnownMetadata::CdRomTrack2]));
+        assert_eq!(ContentKind::Cd, kind(&[KnownMetadata::CdRomOld]));
+        assert_eq!(ContentKind::GdRom, kind(&[KnownMetadata::GdRomTrack]));
+        assert_eq!(
+            ContentKind::LaserDisc,
+            kind(&[
+                KnownMetadata::AudioVideo,
+                KnownMetadata::AudioVideoLaserDisc
+            ])
+        );
+        assert_eq!(ContentKind::AudioVideo, kind(&[KnownMetadata::AudioVideo]));
+        assert_eq!(
+            ContentKind::HardDisk,
+            kind(&[KnownMetadata::HardDisk, KnownMetadata::HardDiskIdent])
+        );
+        assert_eq!(ContentKind::Dvd, kind(&[KnownMetadata::Dvd]));
+        assert_eq!(
+            ContentKind::Dvd,
+            kind(&[KnownMetadata::Creator, KnownMetadata::Dvd])
+        );
+        assert_eq!(ContentKind::Raw, kind_of_tags(&[0x12345678]));
+        assert_eq!(
+            ContentKind::Dvd,
+            kind_of_tags(&[0x12345678, KnownMetadata::Dvd.metatag()])
+        );
+    }
+
+    #[test]
+    fn open_content_test() {
+        // 2 hunks of 2 sectors of 256 bytes.
+        let hunks = [
+            LegacyHunk::Uncompressed((0..512).map(|n| (n / 256) as u8).collect()),
+            LegacyHunk::Uncompressed((0..512).map(|n| (n / 256 + 2) as u8).collect()),
+        ];
+        let mut data = v3_chd(512, &hunks, false).data;
+        let meta_offset = data.len() as u64;
+        let value = b"CYLS:1,HEADS:1,SECS:4,BPS:256\0";
+        data.extend_from_slice(&KnownMetadata::HardDisk.metatag().to_be_bytes());
+        data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(value);
+        data[36..44].copy_from_slice(&meta_offset.to_be_bytes());
+
+        let mut chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        assert_eq!(ContentKind::HardDisk, chd.content_kind().expect("kind"));
+        let content = chd.open_content().expect("content");
+        assert_eq!(ContentKind::HardDisk, content.kind());
+        let mut disk = match content {
+            Content::HardDisk(disk) => disk,
+            _ => unreachable!(),
+        };
+        assert_eq!(4, disk.sector_count());
+        assert_eq!(256, disk.sector_size());
+
+        // sectors that cross a hunk boundary.
+        let mut buf = vec![0u8; 512];
+        disk.read_sectors(1, &mut buf).expect("read");
+        assert!(buf[..256].iter().all(|&b| b == 1));
+        assert!(buf[256..].iter().all(|&b| b == 2));
+        assert!(matches!(
+            disk.read_sectors(3, &mut buf),
+            Err(Error::HunkOutOfRange)
+        ));
+        assert!(matches!(
+            disk.read_sectors(0, &mut buf[..100]),
+            Err(Error::InvalidParameter)
+        ));
+
+        // files without metadata are read as a stream of bytes.
+        let chd = v5_uncompressed_chd(512, &[Some(vec![1; 512])], None);
+        let chd = Chd::open(Cursor::new(&chd.data[..]), None).expect("open");
+        match chd.open_content().expect("content") {
+            Content::Raw(reader) => assert_eq!(512, reader.logical_len()),
+            _ => panic!("not raw content"),
+        }
+    }
+}
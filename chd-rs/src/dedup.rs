@@ -0,0 +1,366 @@
+//! Detection of identical hunks across a set of CHD files.
+//!
+//! [`find_duplicates`](crate::dedup::find_duplicates) scans a set of CHD files and reports the hunks
+//! with identical contents that are stored in more than one of them. Hunks stored in a CHD file that
+//! are identical to hunks of another CHD file could instead refer to the other CHD file as its parent,
+//! so the report estimates how much space could be saved by re-linking CHD files to a parent.
+//!
+//! The hunk maps of the CHD files are used as a prefilter, so that only hunks whose recorded CRC
+//! matches a hunk of another CHD file are decompressed and hashed to confirm they are identical.
+//!
+//! ```rust
+//! use chd::Chd;
+//! use chd::dedup::find_duplicates;
+//!
+//! let mut chds = vec![Chd::open_path("a.chd", None)?, Chd::open_path("b.chd", None)?];
+//! let report = find_duplicates(&mut chds)?;
+//! println!("{} bytes could be saved by making a.chd the parent of b.chd", report.relink_savings(1, 0));
+//! ```
+use crate::block_hash::{crc16, crc32};
+use crate::error::Result;
+use crate::map::{CompressionTypeLegacy, CompressionTypeV5, HunkCrc, MapEntry};
+use crate::Chd;
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Seek};
+
+/// A hunk of one of the CHD files scanned by [`find_duplicates`](crate::dedup::find_duplicates).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct HunkRef {
+    /// The index of the CHD file in the scanned set.
+    pub file: usize,
+    /// The hunk number in the CHD file.
+    pub hunk_num: u32,
+    /// The number of bytes the data of the hunk occupies in the CHD file.
+    pub stored_bytes: u32,
+}
+
+/// A set of hunks with identical contents, stored in at least two different CHD files.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DuplicateGroup {
+    /// The SHA1 of the contents of the hunks.
+    pub sha1: [u8; 20],
+    /// The hunks with these contents, ordered by file then hunk number.
+    pub hunks: Vec<HunkRef>,
+}
+
+impl DuplicateGroup {
+    /// Returns the number of distinct CHD files that store a hunk of this group.
+    pub fn file_count(&self) -> usize {
+        self.hunks
+            .iter()
+            .map(|h| h.file)
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+}
+
+/// The result of scanning a set of CHD files with [`find_duplicates`](crate::dedup::find_duplicates).
+#[derive(Debug, Clone, Default)]
+pub struct DedupReport {
+    /// The groups of identical hunks, ordered by their first hunk.
+    pub groups: Vec<DuplicateGroup>,
+    /// The number of hunks with data stored in their CHD file that were scanned.
+    pub scanned_hunks: u64,
+    /// The number of hunks that were skipped because they do not store data in their CHD file,
+    /// such as hunks that copy another hunk or refer to the parent.
+    pub skipped_hunks: u64,
+    /// The number of hunks that were decompressed and hashed after passing the CRC prefilter.
+    pub hashed_hunks: u64,
+}
+
+impl DedupReport {
+    /// Returns the total number of hunks that are identical to a hunk of another CHD file.
+    pub fn duplicate_hunks(&self) -> u64 {
+        self.groups.iter().map(|g| g.hunks.len() as u64).sum()
+    }
+
+    /// Returns the number of bytes that would be saved if every group of identical hunks
+    /// were only stored once, in the CHD file where it takes the least space.
+    pub fn estimated_savings(&self) -> u64 {
+        self.groups
+            .iter()
+            .map(|g| {
+                let total: u64 = g.hunks.iter().map(|h| h.stored_bytes as u64).sum();
+                let kept = g.hunks.iter().map(|h| h.stored_bytes).min().unwrap_or(0);
+                total - kept as u64
+            })
+            .sum()
+    }
+
+    /// Returns the number of bytes that the CHD file at index `child` would save if it were
+    /// re-linked to the CHD file at index `parent`, so that its hunks that are identical to
+    /// hunks of the parent refer to the parent instead of being stored.
+    pub fn relink_savings(&self, child: usize, parent: usize) -> u64 {
+        if child == parent {
+            return 0;
+        }
+        self.groups
+            .iter()
+            .filter(|g| g.hunks.iter().any(|h| h.file == parent))
+            .flat_map(|g| g.hunks.iter().filter(|h| h.file == child))
+            .map(|h| h.stored_bytes as u64)
+            .sum()
+    }
+}
+
+/// The number of bytes of hunk data stored in the CHD file for a map entry, or `None` if the hunk
+/// does not store data in the CHD file.
+fn stored_bytes(entry: MapEntry) -> Option<u32> {
+    match entry {
+        MapEntry::V5Compressed(entry) => match entry.hunk_type().ok()? {
+            CompressionTypeV5::CompressionType0
+            | CompressionTypeV5::CompressionType1
+            | CompressionTypeV5::CompressionType2
+            | CompressionTypeV5::CompressionType3
+            | CompressionTypeV5::CompressionNone => entry.block_size().ok(),
+            _ => None,
+        },
+        // uncompressed hunks at offset 0 are stored in the parent or are unallocated.
+        MapEntry::V5Uncompressed(entry) => match entry.block_offset().ok()? {
+            0 => None,
+            _ => Some(entry.block_size()),
+        },
+        MapEntry::LegacyEntry(entry) => match entry.hunk_type().ok()? {
+            CompressionTypeLegacy::Compressed | CompressionTypeLegacy::Uncompressed => {
+                Some(entry.block_size())
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Decompresses hunks and groups them by the SHA1 of their contents.
+#[derive(Default)]
+struct HunkHasher {
+    by_hash: HashMap<(u32, [u8; 20]), Vec<HunkRef>>,
+    hashed_hunks: u64,
+    cmp_buf: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl HunkHasher {
+    /// Decompresses and hashes a hunk of `chd`, returning its contents.
+    fn hash<F: Read + Seek, P: Read + Seek>(
+        &mut self,
+        chd: &mut Chd<F, P>,
+        hunk: HunkRef,
+    ) -> Result<&[u8]> {
+        let hunk_size = chd.header().hunk_size();
+        self.buf.resize(hunk_size as usize, 0);
+        chd.hunk(hunk.hunk_num)?
+            .read_hunk_in(&mut self.cmp_buf, &mut self.buf)?;
+        self.hashed_hunks += 1;
+
+        let sha1: [u8; 20] = Sha1::digest(&self.buf).into();
+        self.by_hash
+            .entry((hunk_size, sha1))
+            .or_default()
+            .push(hunk);
+        Ok(&self.buf)
+    }
+}
+
+/// The index of the kind of a map CRC, since CRCs of different kinds can not be compared.
+fn crc_kind(crc: HunkCrc) -> usize {
+    match crc {
+        HunkCrc::Crc16(_) => 0,
+        HunkCrc::Crc32(_) => 1,
+    }
+}
+
+/// Scans a set of CHD files for hunks with identical contents stored in more than one of them.
+///
+/// Only hunks of CHD files with the same hunk size are compared, and only hunks that store data in
+/// their CHD file are scanned. Hunks that copy another hunk, refer to the parent, or are stored in
+/// the hunk map are skipped, since they would not take less space if re-linked.
+///
+/// Hunks are first compared by the CRC recorded in the hunk map, and only hunks with a CRC that
+/// matches a hunk of another CHD file are decompressed and hashed with SHA1 to confirm they are
+/// identical. Hunks without a CRC in the hunk map are always hashed. Because V5 hunk maps record
+/// CRC16s and V1-4 hunk maps record CRC32s, every hunk of a CHD file is hashed if another CHD file
+/// with the same hunk size records a different kind of CRC.
+///
+/// Indices in the returned [`DedupReport`](crate::dedup::DedupReport) refer to the position of each
+/// CHD file in `chds`. Returns an error if a hunk can not be read.
+pub fn find_duplicates<F: Read + Seek, P: Read + Seek>(
+    chds: &mut [Chd<F, P>],
+) -> Result<DedupReport> {
+    let mut report = DedupReport::default();
+
+    // the map CRC of every scanned hunk, grouped by hunk size.
+    let mut scanned: HashMap<u32, Vec<(HunkRef, Option<HunkCrc>)>> = HashMap::new();
+    for (file, chd) in chds.iter().enumerate() {
+        let hunks = scanned.entry(chd.header().hunk_size()).or_default();
+        for hunk_num in 0..chd.header().hunk_count() {
+            match chd
+                .map()
+                .get_entry(hunk_num as usize)
+                .and_then(stored_bytes)
+            {
+                Some(stored_bytes) => {
+                    report.scanned_hunks += 1;
+                    hunks.push((
+                        HunkRef {
+                            file,
+                            hunk_num,
+                            stored_bytes,
+                        },
+                        chd.map().hunk_crc(hunk_num as usize),
+                    ))
+                }
+                None => report.skipped_hunks += 1,
+            }
+        }
+    }
+
+    let mut hasher = HunkHasher::default();
+    let mut sorted_sizes: Vec<_> = scanned.keys().copied().collect();
+    sorted_sizes.sort_unstable();
+    for hunk_size in sorted_sizes {
+        let hunks = &scanned[&hunk_size];
+
+        // the files each CRC is found in. Hunks without a CRC in the map are hashed
+        // first, and the CRCs of their contents are used to prefilter the other hunks.
+        let mut files_by_crc: HashMap<HunkCrc, BTreeSet<usize>> = HashMap::new();
+        let mut files_by_kind: [BTreeSet<usize>; 2] = Default::default();
+        for (hunk, crc) in hunks {
+            match crc {
+                Some(crc) => {
+                    files_by_crc.entry(*crc).or_default().insert(hunk.file);
+                    files_by_kind[crc_kind(*crc)].insert(hunk.file);
+                }
+                None => {
+                    let data = hasher.hash(&mut chds[hunk.file], *hunk)?;
+                    for crc in [HunkCrc::Crc16(crc16(data)), HunkCrc::Crc32(crc32(data))] {
+                        files_by_crc.entry(crc).or_default().insert(hunk.file);
+                    }
+                }
+            }
+        }
+
+        for (hunk, crc) in hunks {
+            let crc = match crc {
+                Some(crc) => *crc,
+                None => continue,
+            };
+            let shared = files_by_crc[&crc].iter().any(|&file| file != hunk.file);
+            let incomparable = files_by_kind[1 - crc_kind(crc)]
+                .iter()
+                .any(|&file| file != hunk.file);
+            if shared || incomparable {
+                hasher.hash(&mut chds[hunk.file], *hunk)?;
+            }
+        }
+    }
+
+    report.hashed_hunks = hasher.hashed_hunks;
+    report.groups = hasher
+        .by_hash
+        .into_iter()
+        .map(|((_, sha1), mut hunks)| {
+            hunks.sort();
+            DuplicateGroup { sha1, hunks }
+        })
+        .filter(|g| g.file_count() > 1)
+        .collect();
+    report.groups.sort_by_key(|g| g.hunks[0]);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dedup::find_duplicates;
+    use crate::testing::{v3_chd, v5_chd, v5_uncompressed_chd, LegacyHunk, V5Hunk};
+    use crate::Chd;
+    use std::io::Cursor;
+
+    fn fill(byte: u8) -> Vec<u8> {
+        vec![byte; 512]
+    }
+
+    fn open_all(files: &[&[u8]]) -> Vec<Chd<Cursor<Vec<u8>>>> {
+        files
+            .iter()
+            .map(|data| Chd::open(Cursor::new(data.to_vec()), None).expect("chd"))
+            .collect()
+    }
+
+    #[test]
+    fn find_duplicates_test() {
+        let a = v5_chd(
+            512,
+            512,
+            &[
+                V5Hunk::Zlib(fill(1)),
+                V5Hunk::Zlib(fill(2)),
+                V5Hunk::SelfHunk(0),
+                V5Hunk::Uncompressed((0..=255).cycle().take(512).collect()),
+            ],
+            None,
+        );
+        let b = v5_chd(
+            512,
+            512,
+            &[
+                V5Hunk::Zlib(fill(3)),
+                V5Hunk::Uncompressed(fill(2)),
+                V5Hunk::Zlib(fill(1)),
+            ],
+            None,
+        );
+        // uncompressed maps do not record CRCs.
+        let c = v5_uncompressed_chd(512, &[Some(fill(1)), None], None);
+
+        let mut chds = open_all(&[&a.data, &b.data, &c.data]);
+        let report = find_duplicates(&mut chds).expect("scan");
+        assert_eq!(7, report.scanned_hunks);
+        assert_eq!(2, report.skipped_hunks);
+        // the hunks with unique CRCs are not hashed.
+        assert_eq!(5, report.hashed_hunks);
+
+        let hunks = |group: usize| -> Vec<(usize, u32)> {
+            report.groups[group]
+                .hunks
+                .iter()
+                .map(|h| (h.file, h.hunk_num))
+                .collect()
+        };
+        assert_eq!(2, report.groups.len());
+        assert_eq!(vec![(0, 0), (1, 2), (2, 0)], hunks(0));
+        assert_eq!(vec![(0, 1), (1, 1)], hunks(1));
+        assert_eq!(3, report.groups[0].file_count());
+        assert_eq!(5, report.duplicate_hunks());
+
+        let zlib_bytes = report.groups[0].hunks[1].stored_bytes as u64;
+        assert_eq!(report.groups[0].hunks[0].stored_bytes as u64, zlib_bytes);
+        assert_eq!(512 + zlib_bytes, report.relink_savings(1, 0));
+        assert_eq!(zlib_bytes, report.relink_savings(1, 2));
+        assert_eq!(512, report.relink_savings(2, 0));
+        assert_eq!(0, report.relink_savings(0, 0));
+        assert_eq!(zlib_bytes + 512 + 512, report.estimated_savings());
+
+        // legacy maps record CRC32s, which can not be compared with the CRC16s of V5 maps.
+        let d = v3_chd(512, &[LegacyHunk::Zlib(fill(2))], false);
+        let mut chds = open_all(&[&a.data, &d.data]);
+        let report = find_duplicates(&mut chds).expect("scan");
+        assert_eq!(4, report.hashed_hunks);
+        assert_eq!(1, report.groups.len());
+        let group = &report.groups[0];
+        assert_eq!(
+            vec![(0, 1), (1, 0)],
+            group
+                .hunks
+                .iter()
+                .map(|h| (h.file, h.hunk_num))
+                .collect::<Vec<_>>()
+        );
+
+        // hunks of files with different hunk sizes are never identical.
+        let e = v5_uncompressed_chd(1024, &[Some(vec![1; 1024])], None);
+        let mut chds = open_all(&[&a.data, &e.data]);
+        let report = find_duplicates(&mut chds).expect("scan");
+        assert!(report.groups.is_empty());
+        assert_eq!(1, report.hashed_hunks);
+    }
+}
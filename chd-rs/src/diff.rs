@@ -0,0 +1,236 @@
+//! Comparison of the decompressed contents of two CHD files.
+//!
+//! [`diff`](crate::diff::diff) compares two CHD files with the same hunk size hunk-by-hunk, and reports
+//! the ranges of hunks that differ. This is useful for validating that a CHD file was transcoded without
+//! changing its contents, or for locating damaged hunks in a corrupt CHD file against a known good copy.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::diff::{diff, DiffMode};
+//!
+//! let mut a = Chd::open(BufReader::new(File::open("a.chd")?), None)?;
+//! let mut b = Chd::open(BufReader::new(File::open("b.chd")?), None)?;
+//! let result = diff(&mut a, &mut b, DiffMode::Exact)?;
+//! for range in result.ranges {
+//!     println!("hunks {}..{} differ", range.start_hunk, range.start_hunk + range.hunk_count);
+//! }
+//! ```
+use crate::error::{Error, Result};
+use crate::map::HunkCrc;
+use crate::Chd;
+use std::io::{Read, Seek};
+
+/// How hunks are compared by [`diff`](crate::diff::diff).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum DiffMode {
+    /// Decompress and compare every hunk, counting the exact number of differing bytes.
+    #[default]
+    Exact,
+    /// Hunks whose uncompressed CRCs recorded in the hunk maps differ are reported as differing
+    /// without being decompressed, and the number of differing bytes in such hunks is not counted.
+    ///
+    /// Hunks with matching CRCs, or without a CRC of the same width in both hunk maps, are decompressed
+    /// and compared. V5 maps record a CRC16 and V1-4 maps record a CRC32, so every hunk is decompressed
+    /// when comparing a V5 CHD file against a V1-4 CHD file.
+    Quick,
+}
+
+/// A range of consecutive hunks that differ between two CHD files.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiffRange {
+    /// The first hunk in the range.
+    pub start_hunk: u32,
+    /// The number of hunks in the range.
+    pub hunk_count: u32,
+    /// The number of differing bytes in the range, or `None` if some hunks
+    /// in the range were reported as differing without being decompressed.
+    pub differing_bytes: Option<u64>,
+}
+
+/// The result of comparing two CHD files with [`diff`](crate::diff::diff).
+#[derive(Debug, Clone, Default)]
+pub struct ChdDiff {
+    /// The ranges of hunks that differ, in order.
+    pub ranges: Vec<DiffRange>,
+    /// The number of hunks that were decompressed and compared.
+    pub compared_hunks: u32,
+    /// The number of hunks that were reported as differing because their CRCs differ.
+    pub crc_mismatched_hunks: u32,
+    /// Whether the CHD files were found to be identical by the raw SHA1 recorded in their headers,
+    /// without comparing any hunks.
+    pub identical_by_hash: bool,
+}
+
+impl ChdDiff {
+    /// Returns whether the contents of the compared CHD files are identical.
+    pub fn is_identical(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the total number of differing hunks.
+    pub fn differing_hunks(&self) -> u64 {
+        self.ranges.iter().map(|r| r.hunk_count as u64).sum()
+    }
+
+    fn push(&mut self, hunk_num: u32, differing_bytes: Option<u64>) {
+        if let Some(last) = self.ranges.last_mut() {
+            if last.start_hunk + last.hunk_count == hunk_num {
+                last.hunk_count += 1;
+                last.differing_bytes = last
+                    .differing_bytes
+                    .zip(differing_bytes)
+                    .map(|(a, b)| a + b);
+                return;
+            }
+        }
+        self.ranges.push(DiffRange {
+            start_hunk: hunk_num,
+            hunk_count: 1,
+            differing_bytes,
+        });
+    }
+}
+
+/// Compares the decompressed contents of two CHD files hunk-by-hunk.
+///
+/// If the raw SHA1 of both CHD files is recorded in their headers and is equal, the CHD files are
+/// reported as identical without comparing any hunks. Otherwise, hunks are compared according to `mode`.
+/// If one CHD file has more hunks than the other, the hunks that only exist in the larger CHD file are
+/// reported as differing in their entirety.
+///
+/// Both CHD files must have the same hunk size, otherwise returns
+/// [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+pub fn diff<F: Read + Seek, P: Read + Seek, G: Read + Seek, Q: Read + Seek>(
+    a: &mut Chd<F, P>,
+    b: &mut Chd<G, Q>,
+    mode: DiffMode,
+) -> Result<ChdDiff> {
+    let hunk_size = a.header().hunk_size();
+    if hunk_size != b.header().hunk_size() {
+        return Err(Error::InvalidParameter);
+    }
+
+    let mut result = ChdDiff::default();
+    if a.header().logical_bytes() == b.header().logical_bytes() {
+        if let (Some(a_sha1), Some(b_sha1)) = (a.header().raw_sha1(), b.header().raw_sha1()) {
+            if a_sha1 == b_sha1 {
+                result.identical_by_hash = true;
+                return Ok(result);
+            }
+        }
+    }
+
+    let a_hunks = a.header().hunk_count();
+    let b_hunks = b.header().hunk_count();
+    let mut a_buf = a.get_hunksized_buffer();
+    let mut b_buf = b.get_hunksized_buffer();
+    let mut cmp_buf = Vec::new();
+
+    for hunk_num in 0..std::cmp::min(a_hunks, b_hunks) {
+        if mode == DiffMode::Quick {
+            let a_crc = a.map().hunk_crc(hunk_num as usize);
+            let b_crc = b.map().hunk_crc(hunk_num as usize);
+            let crc_mismatch = match (a_crc, b_crc) {
+                (Some(HunkCrc::Crc16(a_crc)), Some(HunkCrc::Crc16(b_crc))) => a_crc != b_crc,
+                (Some(HunkCrc::Crc32(a_crc)), Some(HunkCrc::Crc32(b_crc))) => a_crc != b_crc,
+                // CRCs of different widths can not be compared without decompressing.
+                _ => false,
+            };
+            if crc_mismatch {
+                result.crc_mismatched_hunks += 1;
+                result.push(hunk_num, None);
+                continue;
+            }
+        }
+
+        a.hunk(hunk_num)?.read_hunk_in(&mut cmp_buf, &mut a_buf)?;
+        b.hunk(hunk_num)?.read_hunk_in(&mut cmp_buf, &mut b_buf)?;
+        result.compared_hunks += 1;
+
+        let differing_bytes = a_buf.iter().zip(&b_buf).filter(|(a, b)| a != b).count() as u64;
+        if differing_bytes != 0 {
+            result.push(hunk_num, Some(differing_bytes));
+        }
+    }
+
+    for hunk_num in std::cmp::min(a_hunks, b_hunks)..std::cmp::max(a_hunks, b_hunks) {
+        result.push(hunk_num, Some(hunk_size as u64));
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::diff::{diff, ChdDiff, DiffMode, DiffRange};
+    use crate::testing::{v3_chd, v5_chd, LegacyHunk, V5Hunk};
+    use crate::Chd;
+    use std::io::Cursor;
+
+    #[test]
+    fn diff_range_merge_test() {
+        let mut diff = ChdDiff::default();
+        diff.push(1, Some(4));
+        diff.push(2, Some(8));
+        diff.push(4, Some(1));
+        diff.push(5, None);
+        assert_eq!(
+            vec![
+                DiffRange {
+                    start_hunk: 1,
+                    hunk_count: 2,
+                    differing_bytes: Some(12)
+                },
+                DiffRange {
+                    start_hunk: 4,
+                    hunk_count: 2,
+                    differing_bytes: None
+                }
+            ],
+            diff.ranges
+        );
+        assert_eq!(4, diff.differing_hunks());
+    }
+
+    #[test]
+    fn quick_diff_across_versions_test() {
+        let v5 = v5_chd(
+            1024,
+            512,
+            &[
+                V5Hunk::Zlib(vec![0x11; 1024]),
+                V5Hunk::Zlib(vec![0x22; 1024]),
+                V5Hunk::Uncompressed(vec![0x33; 1024]),
+            ],
+            None,
+        );
+        let mut changed = vec![0x22; 1024];
+        changed[..4].fill(0);
+        let v3 = v3_chd(
+            1024,
+            &[
+                LegacyHunk::Zlib(vec![0x11; 1024]),
+                LegacyHunk::Zlib(changed),
+                LegacyHunk::Uncompressed(vec![0x33; 1024]),
+            ],
+            false,
+        );
+        let mut a = Chd::open(Cursor::new(v5.data), None).expect("open");
+        let mut b = Chd::open(Cursor::new(v3.data), None).expect("open");
+
+        // the CRC16 of the V5 map can not be compared with the CRC32 of the V3 map.
+        let result = diff(&mut a, &mut b, DiffMode::Quick).expect("diff");
+        assert_eq!(0, result.crc_mismatched_hunks);
+        assert_eq!(3, result.compared_hunks);
+        assert_eq!(
+            vec![DiffRange {
+                start_hunk: 1,
+                hunk_count: 1,
+                differing_bytes: Some(4)
+            }],
+            result.ranges
+        );
+    }
+}
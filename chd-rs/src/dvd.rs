@@ -0,0 +1,178 @@
+//! Types and methods relating to DVD data stored in a CHD file.
+//!
+//! DVD CHD files store the disc as a sequence of 2048 byte sectors without subcode data, and are
+//! marked by an empty `DVD ` metadata entry. Unlike CD-ROM CHD files, they have no track metadata,
+//! and the sectors can be read directly by logical block address with a [`DvdReader`](crate::dvd::DvdReader).
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::dvd::{DvdReader, DVD_SECTOR_SIZE};
+//!
+//! let f = BufReader::new(File::open("file.chd")?);
+//! let mut dvd = DvdReader::new(Chd::open(f, None)?)?;
+//!
+//! // the primary volume descriptor of an ISO 9660 file system.
+//! let mut sector = [0u8; DVD_SECTOR_SIZE as usize];
+//! dvd.read_sectors(16, &mut sector)?;
+//!
+//! // the image as chdman's `extractdvd` would write it.
+//! let mut out = File::create("file.iso")?;
+//! dvd.extract_iso(&mut out)?;
+//! ```
+use crate::content::read_logical;
+use crate::error::{Error, Result};
+use crate::metadata::KnownMetadata;
+use crate::Chd;
+use std::io::{Read, Seek, Write};
+
+/// The size in bytes of a DVD sector.
+pub const DVD_SECTOR_SIZE: u32 = 2048;
+
+/// A DVD stored in a CHD file, read by logical block address.
+pub struct DvdReader<F: Read + Seek, P: Read + Seek = F> {
+    chd: Chd<F, P>,
+    cmp_buf: Vec<u8>,
+    hunk_buf: Vec<u8>,
+}
+
+impl<F: Read + Seek, P: Read + Seek> DvdReader<F, P> {
+    /// Creates a new `DvdReader` from an opened [`Chd`](crate::Chd).
+    ///
+    /// If the CHD file has no DVD metadata, returns [`Error::MetadataNotFound`](crate::Error::MetadataNotFound).
+    /// If its hunks do not hold a whole number of sectors, returns [`Error::InvalidData`](crate::Error::InvalidData).
+    pub fn new(mut chd: Chd<F, P>) -> Result<Self> {
+        chd.metadata_for(KnownMetadata::Dvd, 0)?;
+        if !chd.header().hunk_size().is_multiple_of(DVD_SECTOR_SIZE) {
+            return Err(Error::InvalidData);
+        }
+        Ok(DvdReader {
+            chd,
+            cmp_buf: Vec::new(),
+            hunk_buf: Vec::new(),
+        })
+    }
+
+    /// Returns the number of sectors of the DVD.
+    pub fn sector_count(&self) -> u64 {
+        self.chd.header().logical_bytes() / DVD_SECTOR_SIZE as u64
+    }
+
+    /// Reads consecutive sectors starting at the logical block address `lba` into `buf`.
+    ///
+    /// If the length of `buf` is not a multiple of [`DVD_SECTOR_SIZE`](crate::dvd::DVD_SECTOR_SIZE),
+    /// returns [`Error::InvalidParameter`](crate::Error::InvalidParameter). If any of the sectors
+    /// are past the end of the DVD, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange).
+    pub fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        let sector_size = DVD_SECTOR_SIZE as u64;
+        if !(buf.len() as u64).is_multiple_of(sector_size) {
+            return Err(Error::InvalidParameter);
+        }
+        let count = buf.len() as u64 / sector_size;
+        let end = lba.checked_add(count);
+        if end.filter(|&end| end <= self.sector_count()).is_none() {
+            return Err(Error::HunkOutOfRange);
+        }
+        read_logical(
+            &mut self.chd,
+            &mut self.cmp_buf,
+            &mut self.hunk_buf,
+            lba * sector_size,
+            buf,
+        )
+    }
+
+    /// Writes every sector of the DVD to `out` as an ISO image, as chdman's `extractdvd` does,
+    /// and returns the number of bytes written.
+    pub fn extract_iso<W: Write + ?Sized>(&mut self, out: &mut W) -> Result<u64> {
+        let sectors_per_hunk = (self.chd.header().hunk_size() / DVD_SECTOR_SIZE) as u64;
+        let sector_count = self.sector_count();
+        let mut buf = Vec::new();
+        let mut lba = 0;
+        while lba < sector_count {
+            let count = sectors_per_hunk.min(sector_count - lba);
+            buf.resize((count * DVD_SECTOR_SIZE as u64) as usize, 0);
+            self.read_sectors(lba, &mut buf)?;
+            out.write_all(&buf)?;
+            lba += count;
+        }
+        Ok(sector_count * DVD_SECTOR_SIZE as u64)
+    }
+
+    /// Consumes the DVD and returns the underlying [`Chd`](crate::Chd).
+    pub fn into_inner(self) -> Chd<F, P> {
+        self.chd
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dvd::{DvdReader, DVD_SECTOR_SIZE};
+    use crate::metadata::{KnownMetadata, MetadataTag};
+    use crate::testing::v5_uncompressed_chd;
+    use crate::{Chd, Error};
+    use std::io::Cursor;
+
+    /// A DVD of 2 hunks of 2 sectors, where every byte of a sector is its logical block address.
+    fn dvd_chd(with_metadata: bool) -> Vec<u8> {
+        let hunk_bytes = 2 * DVD_SECTOR_SIZE;
+        let hunks: Vec<_> = (0..2u8)
+            .map(|hunk| {
+                Some(
+                    (0..hunk_bytes)
+                        .map(|n| hunk * 2 + (n / DVD_SECTOR_SIZE) as u8)
+                        .collect(),
+                )
+            })
+            .collect();
+        let mut data = v5_uncompressed_chd(hunk_bytes, &hunks, None).data;
+        if with_metadata {
+            let meta_offset = data.len() as u64;
+            data.extend_from_slice(&KnownMetadata::Dvd.metatag().to_be_bytes());
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend_from_slice(&0u64.to_be_bytes());
+            data[48..56].copy_from_slice(&meta_offset.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn dvd_read_sectors_test() {
+        let data = dvd_chd(true);
+        let chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        let mut dvd = DvdReader::new(chd).expect("dvd");
+        assert_eq!(4, dvd.sector_count());
+
+        // sectors that cross a hunk boundary.
+        let mut buf = vec![0u8; 2 * DVD_SECTOR_SIZE as usize];
+        dvd.read_sectors(1, &mut buf).expect("read");
+        assert!(buf[..2048].iter().all(|&b| b == 1));
+        assert!(buf[2048..].iter().all(|&b| b == 2));
+        assert!(matches!(
+            dvd.read_sectors(3, &mut buf),
+            Err(Error::HunkOutOfRange)
+        ));
+        assert!(matches!(
+            dvd.read_sectors(0, &mut buf[..100]),
+            Err(Error::InvalidParameter)
+        ));
+
+        let data = dvd_chd(false);
+        let chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        assert!(matches!(DvdReader::new(chd), Err(Error::MetadataNotFound)));
+    }
+
+    #[test]
+    fn dvd_extract_iso_test() {
+        let data = dvd_chd(true);
+        let chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        let mut dvd = DvdReader::new(chd).expect("dvd");
+        let mut iso = Vec::new();
+        assert_eq!(4 * 2048, dvd.extract_iso(&mut iso).expect("extract"));
+        assert_eq!(4 * 2048, iso.len());
+        for (lba, sector) in iso.chunks(2048).enumerate() {
+            assert!(sector.iter().all(|&b| b == lba as u8));
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::huffman::HuffmanError;
+use crate::Tag;
 use bitreader::BitReaderError;
 use std::array::TryFromSliceError;
 use std::ffi::FromBytesWithNulError;
@@ -8,10 +9,9 @@ use std::str::Utf8Error;
 
 /// Error types that may occur when reading a CHD file or hunk.
 ///
-/// This type tries to be ABI-compatible with [libchdr](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L258),
-/// given sane defaults in the C compiler. See [repr(C) in the Rustonomicon](https://doc.rust-lang.org/nomicon/other-reprs.html#reprc) for more details.
+/// Every error maps to one of the error codes of [libchdr](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L258)
+/// with [`Error::code`](crate::Error::code).
 #[derive(Debug)]
-#[repr(C)]
 pub enum Error {
     /// No error.
     /// This is only used by the C API bindings.
@@ -32,7 +32,7 @@ pub enum Error {
     /// This CHD requires a parent CHD that was not provided.
     RequiresParent,
     /// The provided file is not writable.
-    /// Since chd-rs does not implement CHD creation, this is unused.
+    /// chd-rs only opens CHD files for reading, so this is returned when a CHD file is opened for writing.
     FileNotWriteable,
     /// An error occurred when reading this CHD file.
     ReadError,
@@ -60,10 +60,11 @@ pub enum Error {
     /// This is only for C-compatibility purposes and is otherwise unused.
     NotSupported,
     /// The requested metadata was not found.
-    /// This is only used by the C API bindings.
     MetadataNotFound,
     /// The metadata has an invalid size.
-    /// This is only for C-compatibility purposes and is otherwise unused.
+    ///
+    /// The contents of a metadata entry may extend past the end of the file, or be too long
+    /// for the 24-bit length field of a metadata entry.
     InvalidMetadataSize,
     /// The CHD version of the provided file is not supported by this library.
     UnsupportedVersion,
@@ -85,6 +86,89 @@ pub enum Error {
     UnsupportedFormat,
     /// Unknown error.
     Unknown,
+    /// The CHD file is shorter than the length required by its header and hunk map,
+    /// usually because the file was not completely copied or downloaded.
+    ///
+    /// The length required by the hunk map can be found with
+    /// [`Map::required_length`](crate::map::Map::required_length).
+    Truncated {
+        /// The minimum length in bytes required by the header and hunk map.
+        expected: u64,
+        /// The length in bytes of the file.
+        actual: u64,
+    },
+    /// The compressed hunk map of the CHD file is corrupted.
+    ///
+    /// The parameters of the map may be out of range, the map may end before every entry is decoded,
+    /// or the decoded map may not match its checksum.
+    InvalidMap,
+    /// The hunk count in the header of the CHD file is too small for its logical size, so the end
+    /// of the logical data is not stored in any hunk.
+    ///
+    /// Such CHD files can be opened with [`ChdOpenOptions::lenient`](crate::ChdOpenOptions::lenient),
+    /// which truncates the logical data to the end of the last hunk.
+    HunkCountMismatch,
+    /// Decompressing the CHD requires a codec that is not supported.
+    ///
+    /// This is a more specific form of [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat)
+    /// that is returned when the unsupported codec is known, either because chd-rs does not
+    /// implement it, or because it was disabled by a feature.
+    UnsupportedCodec {
+        /// The tag of the unsupported codec, such as `zstd`.
+        ///
+        /// Legacy (V1-4) codecs are numbered rather than tagged, and have the tag of their number.
+        fourcc: Tag,
+    },
+}
+
+impl Error {
+    /// Returns the stable numeric code of this error.
+    ///
+    /// The codes of the errors defined by libchdr are identical to the values of the
+    /// corresponding `CHDERR_*` constants of `chd_error`, from `CHDERR_NONE` (0) to
+    /// `CHDERR_UNSUPPORTED_FORMAT` (27). Errors specific to chd-rs are numbered after them, starting with
+    /// [`Error::Unknown`](crate::Error::Unknown) (28). The code of an error never changes between
+    /// versions of chd-rs, and new errors are always given the next unused code, except for errors
+    /// that only add detail to an existing error, which share its code.
+    ///
+    /// [`Error::UnsupportedCodec`](crate::Error::UnsupportedCodec) shares the code of
+    /// [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat).
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::None => 0,
+            Error::NoInterface => 1,
+            Error::OutOfMemory => 2,
+            Error::InvalidFile => 3,
+            Error::InvalidParameter => 4,
+            Error::InvalidData => 5,
+            Error::FileNotFound => 6,
+            Error::RequiresParent => 7,
+            Error::FileNotWriteable => 8,
+            Error::ReadError => 9,
+            Error::WriteError => 10,
+            Error::CodecError => 11,
+            Error::InvalidParent => 12,
+            Error::HunkOutOfRange => 13,
+            Error::DecompressionError => 14,
+            Error::CompressionError => 15,
+            Error::CantCreateFile => 16,
+            Error::CantVerify => 17,
+            Error::NotSupported => 18,
+            Error::MetadataNotFound => 19,
+            Error::InvalidMetadataSize => 20,
+            Error::UnsupportedVersion => 21,
+            Error::VerifyIncomplete => 22,
+            Error::InvalidMetadata => 23,
+            Error::InvalidState => 24,
+            Error::OperationPending => 25,
+            Error::NoAsyncOperation => 26,
+            Error::UnsupportedFormat | Error::UnsupportedCodec { .. } => 27,
+            Error::Unknown => 28,
+            Error::Truncated { .. } => 29,
+            Error::InvalidMap => 30,
+            Error::HunkCountMismatch => 31,
+        }
+    }
 }
 
 impl std::error::Error for Error {}
@@ -121,6 +205,14 @@ impl Display for Error {
             Error::NoAsyncOperation => f.write_str("no async operation in progress"),
             Error::UnsupportedFormat => f.write_str("unsupported format"),
             Error::Unknown => f.write_str("undocumented error"),
+            Error::Truncated { expected, actual } => write!(
+                f,
+                "file is truncated to {} bytes, but {} bytes are required",
+                actual, expected
+            ),
+            Error::InvalidMap => f.write_str("invalid hunk map"),
+            Error::HunkCountMismatch => f.write_str("hunk count does not cover logical size"),
+            Error::UnsupportedCodec { fourcc } => write!(f, "unsupported codec {}", fourcc),
         }
     }
 }
@@ -177,6 +269,18 @@ impl From<std::io::Error> for Error {
     }
 }
 
+#[cfg(feature = "archive")]
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        match err {
+            zip::result::ZipError::Io(err) => Error::from(err),
+            zip::result::ZipError::InvalidArchive(_) => Error::InvalidFile,
+            zip::result::ZipError::UnsupportedArchive(_) => Error::UnsupportedFormat,
+            zip::result::ZipError::FileNotFound => Error::FileNotFound,
+        }
+    }
+}
+
 impl From<HuffmanError> for Error {
     fn from(_e: HuffmanError) -> Self {
         Error::DecompressionError
@@ -191,3 +295,70 @@ impl From<Error> for std::io::Error {
 
 /// Result type for chd-rs.
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Tag};
+
+    #[test]
+    fn error_code_test() {
+        // these codes are relied on by C API consumers and must never change.
+        let codes = [
+            (Error::None, 0),
+            (Error::NoInterface, 1),
+            (Error::OutOfMemory, 2),
+            (Error::InvalidFile, 3),
+            (Error::InvalidParameter, 4),
+            (Error::InvalidData, 5),
+            (Error::FileNotFound, 6),
+            (Error::RequiresParent, 7),
+            (Error::FileNotWriteable, 8),
+            (Error::ReadError, 9),
+            (Error::WriteError, 10),
+            (Error::CodecError, 11),
+            (Error::InvalidParent, 12),
+            (Error::HunkOutOfRange, 13),
+            (Error::DecompressionError, 14),
+            (Error::CompressionError, 15),
+            (Error::CantCreateFile, 16),
+            (Error::CantVerify, 17),
+            (Error::NotSupported, 18),
+            (Error::MetadataNotFound, 19),
+            (Error::InvalidMetadataSize, 20),
+            (Error::UnsupportedVersion, 21),
+            (Error::VerifyIncomplete, 22),
+            (Error::InvalidMetadata, 23),
+            (Error::InvalidState, 24),
+            (Error::OperationPending, 25),
+            (Error::NoAsyncOperation, 26),
+            (Error::UnsupportedFormat, 27),
+            (Error::Unknown, 28),
+            (
+                Error::Truncated {
+                    expected: 2048,
+                    actual: 1024,
+                },
+                29,
+            ),
+            (Error::InvalidMap, 30),
+            (Error::HunkCountMismatch, 31),
+            (
+                Error::UnsupportedCodec {
+                    fourcc: Tag::new(b"zstd"),
+                },
+                27,
+            ),
+        ];
+        for (error, code) in codes {
+            assert_eq!(code, error.code(), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn unsupported_codec_display_test() {
+        let error = Error::UnsupportedCodec {
+            fourcc: Tag::new(b"zstd"),
+        };
+        assert_eq!("unsupported codec zstd", error.to_string());
+    }
+}
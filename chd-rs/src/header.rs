@@ -8,25 +8,41 @@
 //! [`Header`](crate::header::Header) makes no ABI guarantees and is not ABI-compatible
 //! with [`libchdr::chd_header`](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L302).
 use crate::chdfile::Codecs;
-use crate::compression::codecs::{
-    AVHuffCodec, CdFlacCodec, CdLzmaCodec, CdZlibCodec, CdZstdCodec, HuffmanCodec, LzmaCodec,
-    NoneCodec, RawFlacCodec, ZlibCodec, ZstdCodec,
-};
+#[cfg(feature = "avhuff")]
+use crate::compression::codecs::AVHuffCodec;
+#[cfg(all(feature = "flac", feature = "zlib"))]
+use crate::compression::codecs::CdFlacCodec;
+#[cfg(all(feature = "lzma", feature = "zlib"))]
+use crate::compression::codecs::CdLzmaCodec;
+#[cfg(feature = "huffman")]
+use crate::compression::codecs::HuffmanCodec;
+#[cfg(feature = "lzma")]
+use crate::compression::codecs::LzmaCodec;
+use crate::compression::codecs::NoneCodec;
+#[cfg(feature = "flac")]
+use crate::compression::codecs::RawFlacCodec;
+#[cfg(feature = "zlib")]
+use crate::compression::codecs::{CdZlibCodec, ZlibCodec};
+#[cfg(feature = "zstd")]
+use crate::compression::codecs::{CdZstdCodec, ZstdCodec};
 use crate::compression::{CodecImplementation, CompressionCodec};
 use crate::error::{Error, Result};
-use crate::metadata::{KnownMetadata, MetadataRefs, MetadataTag};
-use crate::{make_tag, map};
+use crate::metadata::{KnownMetadata, Metadata, MetadataRef, MetadataRefs, MetadataTag};
+use crate::{make_tag, map, Tag};
 use arrayvec::ArrayVec;
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use std::ffi::CStr;
+use std::fmt::{Display, Formatter};
+#[cfg(feature = "write_api")]
+use std::io::Write;
 use std::io::{Cursor, Read, Seek, SeekFrom};
 use text_io::try_scan;
 
 /// The types of compression codecs supported in a CHD file.
 #[repr(u32)]
-#[derive(FromPrimitive, Debug)]
+#[derive(FromPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum CodecType {
     /// No compression.
     None = 0,
@@ -68,51 +84,125 @@ impl CodecType {
         }
     }
 
+    /// Returns the codec type with the given tag, such as `cdlz`, or `None` if the codec is not known.
+    ///
+    /// Legacy codecs are numbered rather than tagged, and have the tag of their number.
+    pub fn from_tag(tag: impl Into<Tag>) -> Option<CodecType> {
+        FromPrimitive::from_u32(tag.into().to_u32())
+    }
+
+    /// Returns the tag of the codec type as stored in the header of a V5 CHD file.
+    pub const fn tag(&self) -> Tag {
+        Tag((*self as u32).to_be_bytes())
+    }
+
+    /// Returns whether or not codecs of this type can be initialized.
+    ///
+    /// Codec types whose codec is disabled by a feature are not supported.
+    pub(crate) const fn is_supported(&self) -> bool {
+        match self {
+            CodecType::None => true,
+            CodecType::Zlib | CodecType::ZlibPlus | CodecType::ZLibV5 | CodecType::ZLibCdV5 => {
+                cfg!(feature = "zlib")
+            }
+            CodecType::LzmaV5 => cfg!(feature = "lzma"),
+            CodecType::LzmaCdV5 => cfg!(all(feature = "lzma", feature = "zlib")),
+            CodecType::FlacV5 => cfg!(feature = "flac"),
+            CodecType::FlacCdV5 => cfg!(all(feature = "flac", feature = "zlib")),
+            CodecType::HuffV5 => cfg!(feature = "huffman"),
+            CodecType::AV | CodecType::AVHuffV5 => cfg!(feature = "avhuff"),
+            CodecType::ZstdV5 | CodecType::ZstdCdV5 => cfg!(feature = "zstd"),
+        }
+    }
+
+    /// Returns whether or not this codec type decodes CD-ROM frames, and so is affected by the
+    /// [`SectorPolicy`](crate::cdrom::SectorPolicy) of the CHD file.
+    pub(crate) const fn is_cd(&self) -> bool {
+        matches!(
+            self,
+            CodecType::ZLibCdV5 | CodecType::LzmaCdV5 | CodecType::FlacCdV5 | CodecType::ZstdCdV5
+        )
+    }
+
     /// Initializes the codec for the provided hunk size.
+    ///
+    /// Codec types whose codec is disabled by a feature return [`Error::UnsupportedCodec`](crate::Error::UnsupportedCodec).
     pub(crate) fn init(&self, hunk_size: u32) -> Result<Box<dyn CompressionCodec>> {
         match self {
             CodecType::None => {
                 NoneCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "zlib")]
             CodecType::Zlib | CodecType::ZlibPlus | CodecType::ZLibV5 => {
                 ZlibCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "zlib")]
             CodecType::ZLibCdV5 => {
                 CdZlibCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(all(feature = "lzma", feature = "zlib"))]
             CodecType::LzmaCdV5 => {
                 CdLzmaCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(all(feature = "flac", feature = "zlib"))]
             CodecType::FlacCdV5 => {
                 CdFlacCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "lzma")]
             CodecType::LzmaV5 => {
                 LzmaCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "flac")]
             CodecType::FlacV5 => {
                 RawFlacCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "huffman")]
             CodecType::HuffV5 => {
                 HuffmanCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "avhuff")]
             CodecType::AV | CodecType::AVHuffV5 => {
                 AVHuffCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "zstd")]
             CodecType::ZstdV5 => {
                 ZstdCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
+            #[cfg(feature = "zstd")]
             CodecType::ZstdCdV5 => {
                 CdZstdCodec::new(hunk_size).map(|x| Box::new(x) as Box<dyn CompressionCodec>)
             }
             #[allow(unreachable_patterns)]
-            _ => Err(Error::UnsupportedFormat),
+            _ => Err(Error::UnsupportedCodec { fourcc: self.tag() }),
         }
     }
 }
 
+impl Display for CodecType {
+    /// Formats the codec with the name `chdman` reports it with, such as `cdlz (CD LZMA)`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CodecType::None => "none",
+            CodecType::Zlib => "Legacy zlib (Deflate)",
+            CodecType::ZlibPlus => "Legacy zlib+ (Deflate)",
+            CodecType::AV => "Legacy av (AV)",
+            CodecType::ZLibV5 => "zlib (Deflate)",
+            CodecType::ZLibCdV5 => "cdzl (CD Deflate)",
+            CodecType::LzmaCdV5 => "cdlz (CD LZMA)",
+            CodecType::FlacCdV5 => "cdfl (CD FLAC)",
+            CodecType::FlacV5 => "flac (FLAC)",
+            CodecType::LzmaV5 => "lzma (LZMA)",
+            CodecType::AVHuffV5 => "avhu (A/V Huffman)",
+            CodecType::HuffV5 => "huff (Huffman)",
+            CodecType::ZstdV5 => "zstd (Zstandard)",
+            CodecType::ZstdCdV5 => "cdzs (CD Zstandard)",
+        })
+    }
+}
+
 /// The CHD header version.
 #[repr(u32)]
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Version {
     /// CHD version 1.
     ChdV1 = 1,
@@ -131,7 +221,7 @@ pub enum Version {
 ///
 /// While all members of this struct are public, prefer the [`Header`](crate::header::Header) API over the fields
 /// of this struct.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct HeaderV1 {
     /// The CHD version (1, or 2).
     pub version: Version,
@@ -143,6 +233,9 @@ pub struct HeaderV1 {
     /// valid codec types supported by this library.
     pub compression: u32,
     /// The size of each hunk in the CHD file in units of sector length.
+    ///
+    /// This is the number of sectors in each hunk, and not the size of the hunk in bytes, which is
+    /// [`hunk_bytes`](crate::header::HeaderV1::hunk_bytes).
     pub hunk_size: u32,
     /// The total number of hunks in the CHD file.
     pub total_hunks: u32,
@@ -159,6 +252,7 @@ pub struct HeaderV1 {
     /// The MD5 hash of the parent CHD file.
     pub parent_md5: [u8; MD5_BYTES],
     /// The size of each unit in the CHD file in bytes.
+    /// For V1 and V2 CHD files, this is always the sector length.
     pub unit_bytes: u32,
     /// The number of units in each hunk.
     pub unit_count: u64,
@@ -174,7 +268,7 @@ pub struct HeaderV1 {
 ///
 /// While all members of this struct are public, prefer the [`Header`](crate::header::Header) API over the fields
 /// of this struct.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct HeaderV3 {
     /// The CHD version (3).
     pub version: Version,
@@ -212,7 +306,7 @@ pub struct HeaderV3 {
 ///
 /// While all members of this struct are public, prefer the [`Header`](crate::header::Header) API over the fields
 /// of this struct.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct HeaderV4 {
     /// The CHD version (4).
     pub version: Version,
@@ -247,7 +341,7 @@ pub struct HeaderV4 {
 ///
 /// While all members of this struct are public, prefer the [`Header`](crate::header::Header) API over the fields
 /// of this struct.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct HeaderV5 {
     /// The CHD version (5).
     pub version: Version,
@@ -257,7 +351,8 @@ pub struct HeaderV5 {
     /// single CHD file.
     ///
     /// See [`CodecType`](crate::header::CodecType) for the
-    /// valid codec types supported by this library.
+    /// valid codec types supported by this library. Codecs that are not known are only rejected
+    /// when a hunk compressed with them is read.
     pub compression: [u32; 4],
     /// The logical size of the compressed data in bytes.
     pub logical_bytes: u64,
@@ -284,7 +379,10 @@ pub struct HeaderV5 {
 }
 
 /// A CHD header of unspecified version.
-#[derive(Clone)]
+///
+/// The `Display` implementation formats a summary of the header like `chdman info`,
+/// with one field per line.
+#[derive(Debug, Clone)]
 pub enum Header {
     /// A CHD V1 header.
     V1Header(HeaderV1),
@@ -298,34 +396,282 @@ pub enum Header {
     V5Header(HeaderV5),
 }
 
+impl Display for Header {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        writeln!(f, "File Version:\t{}", self.version() as u32)?;
+        writeln!(f, "Logical size:\t{} bytes", self.logical_bytes())?;
+        writeln!(f, "Hunk Size:\t{} bytes", self.hunk_size())?;
+        writeln!(f, "Total Hunks:\t{}", self.hunk_count())?;
+        writeln!(f, "Unit Size:\t{} bytes", self.unit_bytes())?;
+        writeln!(f, "Total Units:\t{}", self.unit_count())?;
+
+        let compression: ArrayVec<u32, 4> = match self {
+            Header::V1Header(c) | Header::V2Header(c) => [c.compression].into_iter().collect(),
+            Header::V3Header(c) => [c.compression].into_iter().collect(),
+            Header::V4Header(c) => [c.compression].into_iter().collect(),
+            Header::V5Header(c) => c.compression.into_iter().collect(),
+        };
+        let codecs: Vec<String> = compression
+            .into_iter()
+            .take_while(|&c| c != 0)
+            .map(|c| match CodecType::from_u32(c) {
+                Some(codec) => codec.to_string(),
+                None => format!("unknown ({:#x})", c),
+            })
+            .collect();
+        match codecs.is_empty() {
+            true => write!(f, "Compression:\tnone")?,
+            false => write!(f, "Compression:\t{}", codecs.join(", "))?,
+        }
+
+        if let Some(md5) = self.md5() {
+            write!(f, "\nMD5:\t\t{}", hex(&md5))?;
+        }
+        if let Some(parent_md5) = self.parent_md5().filter(|_| self.has_parent()) {
+            write!(f, "\nParent MD5:\t{}", hex(&parent_md5))?;
+        }
+        if let Some(sha1) = self.sha1() {
+            write!(f, "\nSHA1:\t\t{}", hex(&sha1))?;
+        }
+        if let Some(raw_sha1) = self.raw_sha1() {
+            write!(f, "\nData SHA1:\t{}", hex(&raw_sha1))?;
+        }
+        if let Some(parent_sha1) = self.parent_sha1().filter(|_| self.has_parent()) {
+            write!(f, "\nParent SHA1:\t{}", hex(&parent_sha1))?;
+        }
+        Ok(())
+    }
+}
+
 const MD5_BYTES: usize = 16;
-const SHA1_BYTES: usize = 20;
+pub(crate) const SHA1_BYTES: usize = 20;
 
 /// The CHD magic number.
 pub const CHD_MAGIC: &str = "MComprHD";
 
-const CHD_V1_HEADER_SIZE: u32 = 76;
-const CHD_V2_HEADER_SIZE: u32 = 80;
-const CHD_V3_HEADER_SIZE: u32 = 120;
-const CHD_V4_HEADER_SIZE: u32 = 108;
-const CHD_V5_HEADER_SIZE: u32 = 124;
+/// Declares the layout of a header version as a module of field offsets.
+///
+/// Fields are listed in order with their size in bytes, and the offset of each field is the sum
+/// of the sizes of the fields before it. `LENGTH` is the length of the whole header.
+macro_rules! header_layout {
+    (@offsets $offset:expr;) => {};
+    (@offsets $offset:expr; $field:ident: $size:expr, $($rest:tt)*) => {
+        pub(super) const $field: usize = $offset;
+        header_layout!(@offsets $offset + $size; $($rest)*);
+    };
+    ($(#[$meta:meta])* mod $name:ident { $($field:ident: $size:expr),* $(,)? }) => {
+        $(#[$meta])*
+        #[allow(dead_code, unused_imports)]
+        mod $name {
+            use super::{MD5_BYTES, SHA1_BYTES};
+            header_layout!(@offsets 0; $($field: $size,)*);
+            pub(super) const LENGTH: usize = 0 $(+ $size)*;
+        }
+    };
+}
+
+header_layout! {
+    /// The layout of a V1 header.
+    mod v1 {
+        MAGIC: 8,
+        LENGTH_FIELD: 4,
+        VERSION: 4,
+        FLAGS: 4,
+        COMPRESSION: 4,
+        HUNK_SIZE: 4,
+        TOTAL_HUNKS: 4,
+        CYLINDERS: 4,
+        HEADS: 4,
+        SECTORS: 4,
+        MD5: MD5_BYTES,
+        PARENT_MD5: MD5_BYTES,
+    }
+}
+
+header_layout! {
+    /// The layout of a V2 header, which extends the V1 header with the sector length.
+    mod v2 {
+        V1: super::v1::LENGTH,
+        SECTOR_LENGTH: 4,
+    }
+}
+
+header_layout! {
+    /// The layout of a V3 header.
+    mod v3 {
+        MAGIC: 8,
+        LENGTH_FIELD: 4,
+        VERSION: 4,
+        FLAGS: 4,
+        COMPRESSION: 4,
+        TOTAL_HUNKS: 4,
+        LOGICAL_BYTES: 8,
+        META_OFFSET: 8,
+        MD5: MD5_BYTES,
+        PARENT_MD5: MD5_BYTES,
+        HUNK_BYTES: 4,
+        SHA1: SHA1_BYTES,
+        PARENT_SHA1: SHA1_BYTES,
+    }
+}
+
+header_layout! {
+    /// The layout of a V4 header.
+    mod v4 {
+        MAGIC: 8,
+        LENGTH_FIELD: 4,
+        VERSION: 4,
+        FLAGS: 4,
+        COMPRESSION: 4,
+        TOTAL_HUNKS: 4,
+        LOGICAL_BYTES: 8,
+        META_OFFSET: 8,
+        HUNK_BYTES: 4,
+        SHA1: SHA1_BYTES,
+        PARENT_SHA1: SHA1_BYTES,
+        RAW_SHA1: SHA1_BYTES,
+    }
+}
+
+header_layout! {
+    /// The layout of a V5 header.
+    mod v5 {
+        MAGIC: 8,
+        LENGTH_FIELD: 4,
+        VERSION: 4,
+        COMPRESSION: 16,
+        LOGICAL_BYTES: 8,
+        MAP_OFFSET: 8,
+        META_OFFSET: 8,
+        HUNK_BYTES: 4,
+        UNIT_BYTES: 4,
+        RAW_SHA1: SHA1_BYTES,
+        SHA1: SHA1_BYTES,
+        PARENT_SHA1: SHA1_BYTES,
+    }
+}
+
+const CHD_V1_HEADER_SIZE: u32 = v1::LENGTH as u32;
+const CHD_V2_HEADER_SIZE: u32 = v2::LENGTH as u32;
+const CHD_V3_HEADER_SIZE: u32 = v3::LENGTH as u32;
+const CHD_V4_HEADER_SIZE: u32 = v4::LENGTH as u32;
+const CHD_V5_HEADER_SIZE: u32 = v5::LENGTH as u32;
 
 const CHD_MAX_HEADER_SIZE: usize = CHD_V5_HEADER_SIZE as usize;
+
+/// A big-endian field of a raw header, read and written at the offsets of a header layout.
+trait HeaderField: Sized {
+    fn read_field(raw: &[u8]) -> Self;
+    #[cfg(feature = "write_api")]
+    fn write_field(&self, raw: &mut [u8]);
+}
+
+impl HeaderField for u32 {
+    fn read_field(raw: &[u8]) -> Self {
+        BigEndian::read_u32(raw)
+    }
+
+    #[cfg(feature = "write_api")]
+    fn write_field(&self, raw: &mut [u8]) {
+        BigEndian::write_u32(raw, *self)
+    }
+}
+
+impl HeaderField for u64 {
+    fn read_field(raw: &[u8]) -> Self {
+        BigEndian::read_u64(raw)
+    }
+
+    #[cfg(feature = "write_api")]
+    fn write_field(&self, raw: &mut [u8]) {
+        BigEndian::write_u64(raw, *self)
+    }
+}
+
+impl<const N: usize> HeaderField for [u32; N] {
+    fn read_field(raw: &[u8]) -> Self {
+        let mut values = [0; N];
+        BigEndian::read_u32_into(&raw[..N * 4], &mut values);
+        values
+    }
+
+    #[cfg(feature = "write_api")]
+    fn write_field(&self, raw: &mut [u8]) {
+        BigEndian::write_u32_into(self, &mut raw[..N * 4])
+    }
+}
+
+impl<const N: usize> HeaderField for [u8; N] {
+    fn read_field(raw: &[u8]) -> Self {
+        let mut values = [0; N];
+        values.copy_from_slice(&raw[..N]);
+        values
+    }
+
+    #[cfg(feature = "write_api")]
+    fn write_field(&self, raw: &mut [u8]) {
+        raw[..N].copy_from_slice(self)
+    }
+}
+
+/// Reads the field at the given offset of a raw header.
+fn get<T: HeaderField>(raw: &[u8; CHD_MAX_HEADER_SIZE], offset: usize) -> T {
+    T::read_field(&raw[offset..])
+}
+
+/// Writes the field at the given offset of a raw header.
+#[cfg(feature = "write_api")]
+fn put<T: HeaderField>(raw: &mut [u8; CHD_MAX_HEADER_SIZE], offset: usize, value: T) {
+    value.write_field(&mut raw[offset..])
+}
 // pub const COOKIE_VALUE: u32 = 0xbaadf00d;
 
 impl Header {
     /// Reads CHD header data from the provided stream.
     ///
     /// If the header is not valid, returns `Error::InvalidParameter`.
-    /// If the header indicates an unsupported compression format, returns `Error::UnsupportedFormat`
+    /// If the header of a V1-4 CHD file indicates an unsupported compression format, returns
+    /// `Error::UnsupportedCodec` with the tag of the codec. Unsupported codecs of V5 CHD files are
+    /// only reported when a hunk compressed with them is read.
     pub fn try_read_header<F: Read + Seek>(file: &mut F) -> Result<Header> {
-        let header = read_header(file)?;
+        Header::try_read_header_with_metadata(file).map(|(header, _)| header)
+    }
+
+    /// Reads and parses a CHD header from a stream, along with the references to the metadata
+    /// entries that were read to find the unit size of V3 and V4 CHD files.
+    ///
+    /// The metadata references are only returned if the whole metadata chain was read without errors.
+    pub(crate) fn try_read_header_with_metadata<F: Read + Seek>(
+        file: &mut F,
+    ) -> Result<(Header, Option<Vec<MetadataRef>>)> {
+        let mut metadata = None;
+        let header = read_header(file, &mut metadata)?;
         if !header.validate() {
             return Err(Error::InvalidParameter);
         }
-        if !header.validate_compression() {
-            return Err(Error::UnsupportedFormat);
+        header.validate_compression()?;
+        Ok((header, metadata))
+    }
+
+    /// Reads a CHD header from a stream that does not implement [`Seek`](std::io::Seek),
+    /// such as standard input or a pipe.
+    ///
+    /// Only the bytes of the header are read for V1, V2 and V5 files. The unit size of V3 and V4 files
+    /// is not stored in the header, and is guessed from the metadata chain if its entries can be reached
+    /// by reading forward, which consumes the stream up to the entry that was needed. Otherwise the
+    /// unit size falls back to the hunk size, as it would for a file without metadata.
+    ///
+    /// Errors are the same as [`Header::try_read_header`](crate::header::Header::try_read_header).
+    pub fn try_read_from_stream<R: Read>(mut reader: R) -> Result<Header> {
+        let header = read_header_from_stream(&mut reader)?;
+        if !header.validate() {
+            return Err(Error::InvalidParameter);
         }
+        header.validate_compression()?;
         Ok(header)
     }
 
@@ -351,6 +697,50 @@ impl Header {
         }
     }
 
+    /// Returns the offset of the hunk map.
+    ///
+    /// The offset of the map is recorded in V5 headers. The map of V1-4 CHD files immediately
+    /// follows the header, so its offset is the length of the header.
+    pub fn map_offset(&self) -> u64 {
+        match self {
+            Header::V5Header(c) => c.map_offset,
+            _ => self.len() as u64,
+        }
+    }
+
+    /// Returns whether or not the CHD file has a metadata section.
+    ///
+    /// V1 and V2 CHD files never have metadata, and later versions without metadata have a
+    /// metadata offset of 0.
+    pub fn has_metadata(&self) -> bool {
+        self.meta_offset().is_some_and(|offset| offset != 0)
+    }
+
+    /// Returns the hard disk metadata (`GDDD`) describing the geometry of a V1 or V2 CHD file.
+    ///
+    /// V1 and V2 CHD files have no metadata section, and store the geometry of the hard disk in
+    /// the header instead. Like chd.cpp, this synthesizes the equivalent metadata entry from the header.
+    /// Returns `None` for later versions.
+    pub fn legacy_hard_disk_metadata(&self) -> Option<Metadata> {
+        let header = match self {
+            Header::V1Header(c) | Header::V2Header(c) => c,
+            _ => return None,
+        };
+        let mut value = format!(
+            "CYLS:{},HEADS:{},SECS:{},BPS:{}",
+            header.cylinders, header.heads, header.sectors, header.sector_length
+        )
+        .into_bytes();
+        value.push(0);
+        Some(Metadata {
+            metatag: KnownMetadata::HardDisk.metatag(),
+            length: value.len() as u32,
+            value,
+            flags: 0x01,
+            index: 0,
+        })
+    }
+
     /// Returns the flags of the CHD file, if available.
     pub fn flags(&self) -> Option<u32> {
         match self {
@@ -362,6 +752,14 @@ impl Header {
         }
     }
 
+    /// Returns the decoded flags of the CHD file, if available.
+    ///
+    /// V5 headers do not have flags, and whether a V5 CHD file has a parent is determined
+    /// by its parent SHA1 instead.
+    pub fn flags_decoded(&self) -> Option<HeaderFlags> {
+        self.flags().map(HeaderFlags::from_bits_truncate)
+    }
+
     /// Returns the total number of hunks in the CHD file.
     pub fn hunk_count(&self) -> u32 {
         match self {
@@ -395,6 +793,18 @@ impl Header {
         }
     }
 
+    /// Returns the number of bytes of the logical data that are stored in the hunks of the CHD file.
+    ///
+    /// This is the smaller of [`logical_bytes`](crate::header::Header::logical_bytes) and the total
+    /// size of every hunk. They only differ if the header is inconsistent, such as a V1-4 header
+    /// whose hunk count is too small for its logical size.
+    pub fn readable_bytes(&self) -> u64 {
+        std::cmp::min(
+            self.logical_bytes(),
+            self.hunk_count() as u64 * self.hunk_size() as u64,
+        )
+    }
+
     /// Returns the number of bytes per unit within each hunk.
     pub fn unit_bytes(&self) -> u32 {
         match self {
@@ -422,8 +832,8 @@ impl Header {
         match self {
             Header::V5Header(c) => c.parent_sha1 != [0u8; SHA1_BYTES],
             _ => self
-                .flags()
-                .map(|f| (f & Flags::HasParent as u32) != 0)
+                .flags_decoded()
+                .map(|f| f.contains(HeaderFlags::HAS_PARENT))
                 .unwrap_or(false),
         }
     }
@@ -488,6 +898,31 @@ impl Header {
         }
     }
 
+    /// Validates that `parent` is the parent of this CHD file, by comparing the parent hashes recorded
+    /// in this header against the hashes of the parent.
+    ///
+    /// Like chd.cpp, only parent hashes that are recorded (non-zero) in this header are compared, and
+    /// each must match the corresponding hash of the parent. Returns
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter) if this CHD file does not have a parent,
+    /// and [`Error::InvalidParent`](crate::Error::InvalidParent) if a hash does not match.
+    pub fn validate_parent(&self, parent: &Header) -> Result<()> {
+        if !self.has_parent() {
+            return Err(Error::InvalidParameter);
+        }
+
+        if let Some(parent_sha1) = self.parent_sha1().filter(|h| h != &[0u8; SHA1_BYTES]) {
+            if parent.sha1() != Some(parent_sha1) {
+                return Err(Error::InvalidParent);
+            }
+        }
+        if let Some(parent_md5) = self.parent_md5().filter(|h| h != &[0u8; MD5_BYTES]) {
+            if parent.md5() != Some(parent_md5) {
+                return Err(Error::InvalidParent);
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the length of the header.
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> u32 {
@@ -500,37 +935,151 @@ impl Header {
         }
     }
 
-    pub(crate) fn create_compression_codecs(&self) -> Result<Codecs> {
+    /// Sets the SHA1 of the CHD file.
+    ///
+    /// For V4 and V5 headers, this is the combined SHA1 of the raw data and metadata. For V3
+    /// headers, this is the SHA1 of the raw data. V1 and V2 headers have no SHA1, and
+    /// will return [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    #[cfg(feature = "write_api")]
+    #[cfg_attr(docsrs, doc(cfg(write_api)))]
+    pub fn set_sha1(&mut self, sha1: [u8; SHA1_BYTES]) -> Result<()> {
+        match self {
+            Header::V3Header(c) => c.sha1 = sha1,
+            Header::V4Header(c) => c.sha1 = sha1,
+            Header::V5Header(c) => c.sha1 = sha1,
+            _ => return Err(Error::InvalidParameter),
+        }
+        Ok(())
+    }
+
+    /// Sets the raw (hunk data only) SHA1 of the CHD file.
+    ///
+    /// V3 headers do not distinguish between the raw SHA1 and the SHA1 of the CHD file, so the
+    /// SHA1 of a V3 header is set instead. V1 and V2 headers have no SHA1, and will return
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    #[cfg(feature = "write_api")]
+    #[cfg_attr(docsrs, doc(cfg(write_api)))]
+    pub fn set_raw_sha1(&mut self, raw_sha1: [u8; SHA1_BYTES]) -> Result<()> {
         match self {
-            Header::V1Header(c) => CodecType::from_u32(c.compression)
-                .map(|e| (e.init(self.hunk_size())))
-                .ok_or(Error::UnsupportedFormat)?
-                .map(|e| Codecs::Single(e)),
-            Header::V2Header(c) => CodecType::from_u32(c.compression)
-                .map(|e| e.init(self.hunk_size()))
-                .ok_or(Error::UnsupportedFormat)?
-                .map(|e| Codecs::Single(e)),
-            Header::V3Header(c) => CodecType::from_u32(c.compression)
-                .map(|e| e.init(self.hunk_size()))
-                .ok_or(Error::UnsupportedFormat)?
-                .map(|e| Codecs::Single(e)),
-            Header::V4Header(c) => CodecType::from_u32(c.compression)
-                .map(|e| e.init(self.hunk_size()))
-                .ok_or(Error::UnsupportedFormat)?
-                .map(|e| Codecs::Single(e)),
+            Header::V3Header(c) => c.sha1 = raw_sha1,
+            Header::V4Header(c) => c.raw_sha1 = raw_sha1,
+            Header::V5Header(c) => c.raw_sha1 = raw_sha1,
+            _ => return Err(Error::InvalidParameter),
+        }
+        Ok(())
+    }
+
+    /// Writes the header to the beginning of the provided stream, overwriting the existing
+    /// header of the CHD file in place.
+    ///
+    /// Only the header is written. Because the length of a header is fixed for each version,
+    /// the rest of the CHD file is left intact, but no validation is done that the written
+    /// header is consistent with the contents of the stream.
+    #[cfg(feature = "write_api")]
+    #[cfg_attr(docsrs, doc(cfg(write_api)))]
+    pub fn write_to<F: Write + Seek>(&self, file: &mut F) -> Result<()> {
+        let mut raw = [0u8; CHD_MAX_HEADER_SIZE];
+        // every version begins with the magic, length and version at the same offsets.
+        raw[v1::MAGIC..][..CHD_MAGIC.len()].copy_from_slice(CHD_MAGIC.as_bytes());
+        put(&mut raw, v1::LENGTH_FIELD, self.len());
+        put(&mut raw, v1::VERSION, self.version() as u32);
+
+        let length = match self {
+            Header::V1Header(c) | Header::V2Header(c) => {
+                put(&mut raw, v1::FLAGS, c.flags);
+                put(&mut raw, v1::COMPRESSION, c.compression);
+                put(&mut raw, v1::HUNK_SIZE, c.hunk_size);
+                put(&mut raw, v1::TOTAL_HUNKS, c.total_hunks);
+                put(&mut raw, v1::CYLINDERS, c.cylinders);
+                put(&mut raw, v1::HEADS, c.heads);
+                put(&mut raw, v1::SECTORS, c.sectors);
+                put(&mut raw, v1::MD5, c.md5);
+                put(&mut raw, v1::PARENT_MD5, c.parent_md5);
+                if let Header::V2Header(_) = self {
+                    put(&mut raw, v2::SECTOR_LENGTH, c.sector_length);
+                    v2::LENGTH
+                } else {
+                    v1::LENGTH
+                }
+            }
+            Header::V3Header(c) => {
+                put(&mut raw, v3::FLAGS, c.flags);
+                put(&mut raw, v3::COMPRESSION, c.compression);
+                put(&mut raw, v3::TOTAL_HUNKS, c.total_hunks);
+                put(&mut raw, v3::LOGICAL_BYTES, c.logical_bytes);
+                put(&mut raw, v3::META_OFFSET, c.meta_offset);
+                put(&mut raw, v3::MD5, c.md5);
+                put(&mut raw, v3::PARENT_MD5, c.parent_md5);
+                put(&mut raw, v3::HUNK_BYTES, c.hunk_bytes);
+                put(&mut raw, v3::SHA1, c.sha1);
+                put(&mut raw, v3::PARENT_SHA1, c.parent_sha1);
+                v3::LENGTH
+            }
+            Header::V4Header(c) => {
+                put(&mut raw, v4::FLAGS, c.flags);
+                put(&mut raw, v4::COMPRESSION, c.compression);
+                put(&mut raw, v4::TOTAL_HUNKS, c.total_hunks);
+                put(&mut raw, v4::LOGICAL_BYTES, c.logical_bytes);
+                put(&mut raw, v4::META_OFFSET, c.meta_offset);
+                put(&mut raw, v4::HUNK_BYTES, c.hunk_bytes);
+                put(&mut raw, v4::SHA1, c.sha1);
+                put(&mut raw, v4::PARENT_SHA1, c.parent_sha1);
+                put(&mut raw, v4::RAW_SHA1, c.raw_sha1);
+                v4::LENGTH
+            }
             Header::V5Header(c) => {
-                let array = c
-                    .compression
-                    .map(CodecType::from_u32)
-                    .map(|f| f.ok_or(Error::UnsupportedFormat))
-                    .map(|f| f.and_then(|f| f.init(self.hunk_size())))
-                    .into_iter()
-                    .collect::<Result<ArrayVec<Box<dyn CompressionCodec>, 4>>>()?;
-                Ok(Codecs::Four(
-                    array.into_inner().map_err(|_| Error::InvalidFile)?,
-                ))
+                put(&mut raw, v5::COMPRESSION, c.compression);
+                put(&mut raw, v5::LOGICAL_BYTES, c.logical_bytes);
+                put(&mut raw, v5::MAP_OFFSET, c.map_offset);
+                put(&mut raw, v5::META_OFFSET, c.meta_offset);
+                put(&mut raw, v5::HUNK_BYTES, c.hunk_bytes);
+                put(&mut raw, v5::UNIT_BYTES, c.unit_bytes);
+                put(&mut raw, v5::RAW_SHA1, c.raw_sha1);
+                put(&mut raw, v5::SHA1, c.sha1);
+                put(&mut raw, v5::PARENT_SHA1, c.parent_sha1);
+                v5::LENGTH
             }
+        };
+
+        if length != self.len() as usize {
+            return Err(Error::InvalidParameter);
         }
+
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&raw[..length])?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Creates the codecs of this CHD file. Codecs are initialized when they are first used.
+    ///
+    /// Codecs of V5 CHD files that are not known are kept by their tag, and only fail with
+    /// [`Error::UnsupportedCodec`](crate::Error::UnsupportedCodec) when a hunk compressed with them
+    /// is read, so that files that declare codecs they never use can still be opened.
+    pub(crate) fn create_compression_codecs(&self) -> Codecs {
+        let codec_of = |c: u32, valid: fn(u32) -> bool| match CodecType::from_u32(c) {
+            Some(codec) if valid(c) => Ok(codec),
+            _ => Err(Tag::from(c)),
+        };
+        let types = match self {
+            Header::V1Header(c) | Header::V2Header(c) => {
+                [codec_of(c.compression, Header::validate_legacy_compression)]
+                    .into_iter()
+                    .collect()
+            }
+            Header::V3Header(c) => [codec_of(c.compression, Header::validate_legacy_compression)]
+                .into_iter()
+                .collect(),
+            Header::V4Header(c) => [codec_of(c.compression, Header::validate_legacy_compression)]
+                .into_iter()
+                .collect(),
+            Header::V5Header(c) => c
+                .compression
+                .into_iter()
+                .map(|c| codec_of(c, Header::validate_v5_compression))
+                .collect(),
+        };
+        Codecs::new(types, self.hunk_size())
     }
 
     /// Validate the header.
@@ -591,18 +1140,23 @@ impl Header {
         true
     }
 
-    /// Validate the compression types of the CHD file can be read.
-    fn validate_compression(&self) -> bool {
-        match self {
-            Header::V1Header(c) => Header::validate_legacy_compression(c.compression),
-            Header::V2Header(c) => Header::validate_legacy_compression(c.compression),
-            Header::V3Header(c) => Header::validate_legacy_compression(c.compression),
-            Header::V4Header(c) => Header::validate_legacy_compression(c.compression),
-            Header::V5Header(c) => c
-                .compression
-                .map(Header::validate_v5_compression)
-                .iter()
-                .all(|&x| x),
+    /// Validate the compression type of a V1-4 CHD file can be read, returning it as
+    /// [`Error::UnsupportedCodec`](crate::Error::UnsupportedCodec) if it can not.
+    ///
+    /// The codecs of V5 CHD files are not validated, since files may declare codecs that no hunk
+    /// is compressed with. Unknown V5 codecs fail when a hunk compressed with them is read instead.
+    fn validate_compression(&self) -> Result<()> {
+        let compression = match self {
+            Header::V1Header(c) | Header::V2Header(c) => c.compression,
+            Header::V3Header(c) => c.compression,
+            Header::V4Header(c) => c.compression,
+            Header::V5Header(_) => return Ok(()),
+        };
+        match Header::validate_legacy_compression(compression) {
+            true => Ok(()),
+            false => Err(Error::UnsupportedCodec {
+                fourcc: Tag::from(compression),
+            }),
         }
     }
 
@@ -623,6 +1177,8 @@ impl Header {
 }
 
 /// CHD flags for legacy V1-4 headers.
+///
+/// See [`HeaderFlags`](crate::header::HeaderFlags) for the flags of a header.
 #[repr(u32)]
 pub enum Flags {
     /// This CHD file has a parent.
@@ -635,75 +1191,134 @@ pub enum Flags {
     Undefined = 0xfffffffc,
 }
 
-fn read_header<T: Read + Seek>(chd: &mut T) -> Result<Header> {
-    let mut raw_header: [u8; CHD_MAX_HEADER_SIZE] = [0; CHD_MAX_HEADER_SIZE];
+bitflags::bitflags! {
+    /// The decoded flags of a legacy V1-4 header.
+    ///
+    /// chd-rs only reads CHD files, so writable CHD files are opened read-only like any other.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct HeaderFlags: u32 {
+        /// This CHD file has a parent.
+        const HAS_PARENT = Flags::HasParent as u32;
+        /// This CHD file is writable.
+        const IS_WRITABLE = Flags::IsWritable as u32;
+    }
+}
 
+fn read_header<T: Read + Seek>(
+    chd: &mut T,
+    metadata: &mut Option<Vec<MetadataRef>>,
+) -> Result<Header> {
     chd.seek(SeekFrom::Start(0))?;
-    chd.read_exact(&mut raw_header)?;
+    let raw_header = read_raw_header(chd)?;
+
+    parse_header(&raw_header, |meta_offset| {
+        guess_unit_bytes(chd, meta_offset, metadata)
+    })
+}
+
+/// Reads a CHD header from a stream that can not seek.
+///
+/// Only the bytes of the header are read, except for the metadata scan of V3 and V4 files.
+fn read_header_from_stream<R: Read>(chd: &mut R) -> Result<Header> {
+    let raw_header = read_raw_header(chd)?;
+    let length: u32 = get(&raw_header, v1::LENGTH_FIELD);
 
+    parse_header(&raw_header, |meta_offset| {
+        guess_unit_bytes_sequential(chd, length as u64, meta_offset)
+    })
+}
+
+/// Reads exactly the bytes of a CHD header, as given by its length field, so that files
+/// shorter than the largest header can still be read. The rest of the buffer is left zeroed.
+fn read_raw_header<R: Read>(chd: &mut R) -> Result<[u8; CHD_MAX_HEADER_SIZE]> {
+    let mut raw_header: [u8; CHD_MAX_HEADER_SIZE] = [0; CHD_MAX_HEADER_SIZE];
+
+    // the magic, length and version, which come before the flags of every version, are enough
+    // to know how much more of the header to read.
+    let prefix = v1::FLAGS;
+    chd.read_exact(&mut raw_header[..prefix])?;
+    let length: u32 = get(&raw_header, v1::LENGTH_FIELD);
+    let rest = (length as usize)
+        .checked_sub(prefix)
+        .filter(|&rest| rest <= CHD_MAX_HEADER_SIZE - prefix)
+        .ok_or(Error::InvalidData)?;
+    chd.read_exact(&mut raw_header[prefix..prefix + rest])?;
+    Ok(raw_header)
+}
+
+/// Parses the raw bytes of a CHD header.
+///
+/// `guess_unit_bytes` is called with the metadata offset of V3 and V4 headers to find
+/// their unit size, which is not stored in the header.
+fn parse_header<G: FnOnce(u64) -> Option<u32>>(
+    raw_header: &[u8; CHD_MAX_HEADER_SIZE],
+    guess_unit_bytes: G,
+) -> Result<Header> {
     let magic = CStr::from_bytes_with_nul(&raw_header[0..9])?.to_str()?;
     if CHD_MAGIC != magic {
         return Err(Error::InvalidData);
     }
-    let mut reader = Cursor::new(&raw_header);
-    reader.seek(SeekFrom::Start(8))?;
-    let length = reader.read_u32::<BigEndian>()?;
-    let version = reader.read_u32::<BigEndian>()?;
+    let length: u32 = get(raw_header, v1::LENGTH_FIELD);
+    let version: u32 = get(raw_header, v1::VERSION);
 
     // ensure version is known and header size match up
     match (version, length) {
         (1, CHD_V1_HEADER_SIZE) => Ok(Header::V1Header(read_v1_header(
-            &mut reader,
-            version,
-            length,
+            raw_header, version, length,
         )?)),
         (2, CHD_V2_HEADER_SIZE) => Ok(Header::V2Header(read_v1_header(
-            &mut reader,
-            version,
+            raw_header, version, length,
+        )?)),
+        (3, CHD_V3_HEADER_SIZE) => Ok(Header::V3Header(read_v3_header(
+            raw_header,
+            length,
+            guess_unit_bytes,
+        )?)),
+        (4, CHD_V4_HEADER_SIZE) => Ok(Header::V4Header(read_v4_header(
+            raw_header,
             length,
+            guess_unit_bytes,
         )?)),
-        (3, CHD_V3_HEADER_SIZE) => Ok(Header::V3Header(read_v3_header(&mut reader, length, chd)?)),
-        (4, CHD_V4_HEADER_SIZE) => Ok(Header::V4Header(read_v4_header(&mut reader, length, chd)?)),
-        (5, CHD_V5_HEADER_SIZE) => Ok(Header::V5Header(read_v5_header(&mut reader, length)?)),
+        (5, CHD_V5_HEADER_SIZE) => Ok(Header::V5Header(read_v5_header(raw_header, length)?)),
         (1 | 2 | 3 | 4 | 5, _) => Err(Error::InvalidData),
         _ => Err(Error::UnsupportedVersion),
     }
 }
 
-fn read_v1_header<T: Read + Seek>(header: &mut T, version: u32, length: u32) -> Result<HeaderV1> {
+fn read_v1_header(raw: &[u8; CHD_MAX_HEADER_SIZE], version: u32, length: u32) -> Result<HeaderV1> {
     // get sector size
     const CHD_V1_SECTOR_SIZE: u32 = 512;
-    header.seek(SeekFrom::Start(76))?;
     let sector_length = match version {
         1 => CHD_V1_SECTOR_SIZE,
-        _ => header.read_u32::<BigEndian>()?,
+        _ => get(raw, v2::SECTOR_LENGTH),
     };
-    let mut md5: [u8; MD5_BYTES] = [0; MD5_BYTES];
-    let mut parent_md5: [u8; MD5_BYTES] = [0; MD5_BYTES];
-
-    header.seek(SeekFrom::Start(16))?;
-    let flags = header.read_u32::<BigEndian>()?;
-    let compression = header.read_u32::<BigEndian>()?;
-    let hunk_size = header.read_u32::<BigEndian>()?;
-    let total_hunks = header.read_u32::<BigEndian>()?;
-    let cylinders = header.read_u32::<BigEndian>()?;
-    let heads = header.read_u32::<BigEndian>()?;
-    let sectors = header.read_u32::<BigEndian>()?;
-    header.read_exact(&mut md5)?;
-    header.read_exact(&mut parent_md5)?;
-
-    let logical_bytes =
-        (cylinders as u64) * (heads as u64) * (sectors as u64) * (sector_length as u64);
-
-    // verify assumptions about hunk sizes.
-    let hunk_bytes: u32 =
-        u32::try_from(sector_length as u64 * hunk_size as u64).map_err(|_| Error::InvalidData)?;
-    if hunk_bytes == 0 || hunk_size == 0 {
+    let flags = get(raw, v1::FLAGS);
+    let compression = get(raw, v1::COMPRESSION);
+    let hunk_size: u32 = get(raw, v1::HUNK_SIZE);
+    let total_hunks = get(raw, v1::TOTAL_HUNKS);
+    let cylinders: u32 = get(raw, v1::CYLINDERS);
+    let heads: u32 = get(raw, v1::HEADS);
+    let sectors: u32 = get(raw, v1::SECTORS);
+    let md5 = get(raw, v1::MD5);
+    let parent_md5 = get(raw, v1::PARENT_MD5);
+
+    // V2 sector lengths are arbitrary, and need not be a power of two (such as 2352-byte CD sectors).
+    if sector_length == 0 || hunk_size == 0 {
         return Err(Error::InvalidData);
     }
 
-    let unit_bytes = hunk_bytes / hunk_size;
-    let unit_count = (logical_bytes + unit_bytes as u64 - 1) / unit_bytes as u64;
+    let logical_bytes = [heads, sectors, sector_length]
+        .iter()
+        .try_fold(cylinders as u64, |acc, &n| acc.checked_mul(n as u64))
+        .ok_or(Error::InvalidData)?;
+
+    // hunks are always a whole number of sectors, so like chd.cpp, each sector is one unit.
+    // hunks that are too large to fit in a u32 would also be rejected by Header::validate.
+    let hunk_bytes = sector_length
+        .checked_mul(hunk_size)
+        .ok_or(Error::InvalidData)?;
+    let unit_bytes = sector_length;
+    let unit_count = count_of(logical_bytes, unit_bytes)?;
     Ok(HeaderV1 {
         version: match version {
             1 => Version::ChdV1,
@@ -728,84 +1343,56 @@ fn read_v1_header<T: Read + Seek>(header: &mut T, version: u32, length: u32) ->
     })
 }
 
-fn read_v3_header<T: Read + Seek, F: Read + Seek>(
-    header: &mut T,
+fn read_v3_header<G: FnOnce(u64) -> Option<u32>>(
+    raw: &[u8; CHD_MAX_HEADER_SIZE],
     length: u32,
-    chd: &mut F,
+    guess_unit_bytes: G,
 ) -> Result<HeaderV3> {
-    header.seek(SeekFrom::Start(16))?;
-    let mut md5: [u8; MD5_BYTES] = [0; MD5_BYTES];
-    let mut parent_md5: [u8; MD5_BYTES] = [0; MD5_BYTES];
-    let mut sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-    let mut parent_sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-
-    let flags = header.read_u32::<BigEndian>()?;
-    let compression = header.read_u32::<BigEndian>()?;
-    let total_hunks = header.read_u32::<BigEndian>()?;
-    let logical_bytes = header.read_u64::<BigEndian>()?;
-    let meta_offset = header.read_u64::<BigEndian>()?;
-    header.read_exact(&mut md5)?;
-    header.read_exact(&mut parent_md5)?;
-    let hunk_bytes = header.read_u32::<BigEndian>()?;
-    header.seek(SeekFrom::Start(80))?;
-    header.read_exact(&mut sha1)?;
-    header.read_exact(&mut parent_sha1)?;
-    let unit_bytes = guess_unit_bytes(chd, meta_offset).unwrap_or(hunk_bytes);
-    let unit_count = (logical_bytes + (unit_bytes as u64) - 1) / unit_bytes as u64;
+    let logical_bytes = get(raw, v3::LOGICAL_BYTES);
+    let meta_offset = get(raw, v3::META_OFFSET);
+    let hunk_bytes = get(raw, v3::HUNK_BYTES);
+    let unit_bytes = guess_unit_bytes(meta_offset).unwrap_or(hunk_bytes);
+    let unit_count = count_of(logical_bytes, unit_bytes)?;
     Ok(HeaderV3 {
         version: Version::ChdV3,
         length,
-        flags,
-        compression,
+        flags: get(raw, v3::FLAGS),
+        compression: get(raw, v3::COMPRESSION),
         hunk_bytes,
-        sha1,
-        total_hunks,
+        sha1: get(raw, v3::SHA1),
+        total_hunks: get(raw, v3::TOTAL_HUNKS),
         logical_bytes,
         meta_offset,
-        md5,
-        parent_md5,
+        md5: get(raw, v3::MD5),
+        parent_md5: get(raw, v3::PARENT_MD5),
         unit_bytes,
         unit_count,
-        parent_sha1,
+        parent_sha1: get(raw, v3::PARENT_SHA1),
     })
 }
 
-fn read_v4_header<T: Read + Seek, F: Read + Seek>(
-    header: &mut T,
+fn read_v4_header<G: FnOnce(u64) -> Option<u32>>(
+    raw: &[u8; CHD_MAX_HEADER_SIZE],
     length: u32,
-    chd: &mut F,
+    guess_unit_bytes: G,
 ) -> Result<HeaderV4> {
-    header.seek(SeekFrom::Start(16))?;
-    let mut sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-    let mut parent_sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-    let mut raw_sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-
-    let flags = header.read_u32::<BigEndian>()?;
-    let compression = header.read_u32::<BigEndian>()?;
-    let total_hunks = header.read_u32::<BigEndian>()?;
-    let logical_bytes = header.read_u64::<BigEndian>()?;
-    let meta_offset = header.read_u64::<BigEndian>()?;
-    let hunk_bytes = header.read_u32::<BigEndian>()?;
-
-    header.seek(SeekFrom::Start(48))?;
-    header.read_exact(&mut sha1)?;
-    header.read_exact(&mut parent_sha1)?;
-    header.read_exact(&mut raw_sha1)?;
-
-    let unit_bytes = guess_unit_bytes(chd, meta_offset).unwrap_or(hunk_bytes);
-    let unit_count = (logical_bytes + unit_bytes as u64 - 1) / unit_bytes as u64;
+    let logical_bytes = get(raw, v4::LOGICAL_BYTES);
+    let meta_offset = get(raw, v4::META_OFFSET);
+    let hunk_bytes = get(raw, v4::HUNK_BYTES);
+    let unit_bytes = guess_unit_bytes(meta_offset).unwrap_or(hunk_bytes);
+    let unit_count = count_of(logical_bytes, unit_bytes)?;
     Ok(HeaderV4 {
         version: Version::ChdV4,
         length,
-        flags,
-        compression,
+        flags: get(raw, v4::FLAGS),
+        compression: get(raw, v4::COMPRESSION),
         hunk_bytes,
-        total_hunks,
+        total_hunks: get(raw, v4::TOTAL_HUNKS),
         logical_bytes,
         meta_offset,
-        sha1,
-        raw_sha1,
-        parent_sha1,
+        sha1: get(raw, v4::SHA1),
+        raw_sha1: get(raw, v4::RAW_SHA1),
+        parent_sha1: get(raw, v4::PARENT_SHA1),
         unit_bytes,
         unit_count,
     })
@@ -823,8 +1410,21 @@ fn extract_bps_value(bps_meta: &[u8]) -> Option<u32> {
     extract_bps_inner(bps_meta).ok()
 }
 
-fn guess_unit_bytes<F: Read + Seek>(chd: &mut F, off: u64) -> Option<u32> {
-    let metas: Vec<_> = MetadataRefs::from_stream(chd, off).collect();
+/// Guesses the unit size of a V3 or V4 CHD file from its metadata.
+///
+/// If the metadata chain is read without errors, its entries are stored in `metadata` so that they
+/// do not need to be read again.
+fn guess_unit_bytes<F: Read + Seek>(
+    chd: &mut F,
+    off: u64,
+    metadata: &mut Option<Vec<MetadataRef>>,
+) -> Option<u32> {
+    let mut refs = MetadataRefs::from_stream(chd, off);
+    let metas: Vec<_> = refs.by_ref().collect();
+    if refs.take_error().is_none() {
+        *metadata = Some(metas.clone());
+    }
+
     if let Some(hard_disk) = metas
         .iter()
         .find(|&e| e.metatag() == KnownMetadata::HardDisk as u32)
@@ -832,7 +1432,7 @@ fn guess_unit_bytes<F: Read + Seek>(chd: &mut F, off: u64) -> Option<u32> {
         if let Ok(text) = hard_disk.read(chd) {
             let bps = extract_bps_value(&text.value);
             // Only return this if we can parse it properly. Fallback to cdrom otherwise.
-            if let Some(bps) = bps {
+            if let Some(bps) = bps.filter(|&bps| bps != 0) {
                 return Some(bps);
             }
         }
@@ -841,39 +1441,79 @@ fn guess_unit_bytes<F: Read + Seek>(chd: &mut F, off: u64) -> Option<u32> {
     if metas.iter().any(|e| KnownMetadata::is_cdrom(e.metatag())) {
         return Some(crate::cdrom::CD_FRAME_SIZE as u32);
     }
+    if metas.iter().any(|e| KnownMetadata::is_dvd(e.metatag())) {
+        return Some(crate::dvd::DVD_SECTOR_SIZE);
+    }
+    None
+}
+
+/// Guesses the unit size of a V3 or V4 CHD file from its metadata, without seeking.
+///
+/// `pos` is the number of bytes already read from `chd`. The metadata chain is only followed
+/// while each entry lies after the end of the previous one, skipping forward over any data between
+/// entries, and stops at the first entry that can not be reached without going back.
+fn guess_unit_bytes_sequential<R: Read>(chd: &mut R, mut pos: u64, mut off: u64) -> Option<u32> {
+    while off != 0 && off >= pos {
+        let skip = off - pos;
+        if std::io::copy(&mut chd.by_ref().take(skip), &mut std::io::sink()).ok()? != skip {
+            return None;
+        }
+
+        let mut raw_meta = [0u8; 16];
+        chd.read_exact(&mut raw_meta).ok()?;
+        let mut cursor = Cursor::new(&raw_meta);
+        let metatag = cursor.read_u32::<BigEndian>().ok()?;
+        let length = cursor.read_u32::<BigEndian>().ok()? & 0x00ffffff;
+        let next = cursor.read_u64::<BigEndian>().ok()?;
+        pos = off + raw_meta.len() as u64;
+
+        if metatag == KnownMetadata::HardDisk as u32 {
+            let mut text = vec![0u8; length as usize];
+            chd.read_exact(&mut text).ok()?;
+            pos += length as u64;
+            if let Some(bps) = extract_bps_value(&text).filter(|&bps| bps != 0) {
+                return Some(bps);
+            }
+        } else if KnownMetadata::is_cdrom(metatag) {
+            return Some(crate::cdrom::CD_FRAME_SIZE);
+        } else if KnownMetadata::is_dvd(metatag) {
+            return Some(crate::dvd::DVD_SECTOR_SIZE);
+        }
+        off = next;
+    }
     None
 }
 
-fn read_v5_header<T: Read + Seek>(header: &mut T, length: u32) -> Result<HeaderV5> {
-    header.seek(SeekFrom::Start(16))?;
-    let mut sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-    let mut parent_sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-    let mut raw_sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
-    let mut compression: [u32; 4] = [0; 4];
-    header.read_u32_into::<BigEndian>(&mut compression)?;
-    let logical_bytes = header.read_u64::<BigEndian>()?;
-    let map_offset = header.read_u64::<BigEndian>()?;
-    let meta_offset = header.read_u64::<BigEndian>()?;
-    let hunk_bytes = header.read_u32::<BigEndian>()?;
-    let unit_bytes = header.read_u32::<BigEndian>()?;
+/// Returns the number of items of `size` bytes needed to hold `len` bytes.
+///
+/// If `size` is 0, returns [`Error::InvalidData`](crate::Error::InvalidData).
+fn count_of(len: u64, size: u32) -> Result<u64> {
+    if size == 0 {
+        return Err(Error::InvalidData);
+    }
+    Ok(len.div_ceil(size as u64))
+}
+
+fn read_v5_header(raw: &[u8; CHD_MAX_HEADER_SIZE], length: u32) -> Result<HeaderV5> {
+    let compression: [u32; 4] = get(raw, v5::COMPRESSION);
+    let logical_bytes = get(raw, v5::LOGICAL_BYTES);
+    let hunk_bytes = get(raw, v5::HUNK_BYTES);
+    let unit_bytes = get(raw, v5::UNIT_BYTES);
 
     // guard divide by zero
     if hunk_bytes == 0 || unit_bytes == 0 {
         return Err(Error::InvalidData);
     }
 
-    let hunk_count = ((logical_bytes + hunk_bytes as u64 - 1) / hunk_bytes as u64) as u32;
-    let unit_count = (logical_bytes + unit_bytes as u64 - 1) / unit_bytes as u64;
-    header.seek(SeekFrom::Start(84))?;
-    header.read_exact(&mut sha1)?;
-    header.read_exact(&mut parent_sha1)?;
-    header.seek(SeekFrom::Start(64))?;
-    header.read_exact(&mut raw_sha1)?;
+    // the hunk count of a V5 file must be representable in the u32 hunk numbers of the map.
+    let hunk_count =
+        u32::try_from(count_of(logical_bytes, hunk_bytes)?).map_err(|_| Error::InvalidData)?;
+    let unit_count = count_of(logical_bytes, unit_bytes)?;
+    // the map is compressed if the first codec is not none, even if the codec is not known.
     let map_entry_bytes = match CodecType::from_u32(compression[0]) {
         // uncompressed map entries are 4 bytes long
         Some(CodecType::None) => map::V5_UNCOMPRESSED_MAP_ENTRY_SIZE as u32,
-        Some(_) => map::V5_COMPRESSED_MAP_ENTRY_SIZE as u32,
-        None => return Err(Error::UnsupportedFormat),
+        _ => map::V5_COMPRESSED_MAP_ENTRY_SIZE as u32,
     };
 
     Ok(HeaderV5 {
@@ -882,11 +1522,11 @@ fn read_v5_header<T: Read + Seek>(header: &mut T, length: u32) -> Result<HeaderV
         compression,
         hunk_bytes,
         logical_bytes,
-        meta_offset,
-        map_offset,
-        sha1,
-        raw_sha1,
-        parent_sha1,
+        meta_offset: get(raw, v5::META_OFFSET),
+        map_offset: get(raw, v5::MAP_OFFSET),
+        sha1: get(raw, v5::SHA1),
+        raw_sha1: get(raw, v5::RAW_SHA1),
+        parent_sha1: get(raw, v5::PARENT_SHA1),
         unit_bytes,
         unit_count,
         hunk_count,
@@ -896,10 +1536,410 @@ fn read_v5_header<T: Read + Seek>(header: &mut T, length: u32) -> Result<HeaderV
 
 #[cfg(test)]
 mod test {
-    use crate::header::extract_bps_value;
+    use crate::header::{extract_bps_value, Header, HeaderFlags, CHD_V1_HEADER_SIZE};
+    #[cfg(feature = "write_api")]
+    use crate::header::{CodecType, HeaderV5, Version};
+    use crate::testing::{v2_chd, v3_chd, v5_uncompressed_chd, LegacyHunk};
+    use crate::{map, Error, Tag};
+    use std::io::Cursor;
+
+    fn v5_header_bytes(logical_bytes: u64, hunk_bytes: u32, unit_bytes: u32) -> Vec<u8> {
+        let mut buf = vec![0u8; 124];
+        buf[0..8].copy_from_slice(b"MComprHD");
+        buf[8..12].copy_from_slice(&124u32.to_be_bytes());
+        buf[12..16].copy_from_slice(&5u32.to_be_bytes());
+        buf[32..40].copy_from_slice(&logical_bytes.to_be_bytes());
+        buf[40..48].copy_from_slice(&124u64.to_be_bytes());
+        buf[56..60].copy_from_slice(&hunk_bytes.to_be_bytes());
+        buf[60..64].copy_from_slice(&unit_bytes.to_be_bytes());
+        buf
+    }
+
+    #[test]
+    fn short_header_test() {
+        // a V1 file with nothing after its 76-byte header is shorter than the largest header.
+        let chd = v2_chd(512, 1, [1, 1, 1], &[LegacyHunk::Uncompressed(vec![0; 512])]).data;
+        let mut v1 = chd[..CHD_V1_HEADER_SIZE as usize].to_vec();
+        v1[8..12].copy_from_slice(&CHD_V1_HEADER_SIZE.to_be_bytes());
+        v1[12..16].copy_from_slice(&1u32.to_be_bytes());
+        let header = Header::try_read_header(&mut Cursor::new(&v1)).expect("v1 header");
+        assert_eq!(CHD_V1_HEADER_SIZE, header.len());
+        assert_eq!(512, header.hunk_size());
+        let header = Header::try_read_from_stream(&v1[..]).expect("v1 stream");
+        assert_eq!(512, header.hunk_size());
+
+        // files that end within the header are still errors.
+        assert!(Header::try_read_header(&mut Cursor::new(&v1[..60])).is_err());
+        assert!(Header::try_read_header(&mut Cursor::new(&chd[..79])).is_err());
+    }
+
+    #[test]
+    fn flags_decoded_test() {
+        let mut chd = v3_chd(512, &[LegacyHunk::Mini(0)], true).data;
+        chd[16..20].copy_from_slice(&3u32.to_be_bytes());
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        assert_eq!(
+            Some(HeaderFlags::HAS_PARENT | HeaderFlags::IS_WRITABLE),
+            header.flags_decoded()
+        );
+        assert!(header.has_parent());
+
+        chd[16..20].copy_from_slice(&0u32.to_be_bytes());
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        assert_eq!(Some(HeaderFlags::empty()), header.flags_decoded());
+        assert!(!header.has_parent());
+
+        let header = Header::try_read_header(&mut Cursor::new(v5_header_bytes(512, 512, 512)))
+            .expect("header");
+        assert_eq!(None, header.flags_decoded());
+    }
+
+    #[test]
+    fn display_test() {
+        let chd = v5_uncompressed_chd(4096, &[Some(vec![0; 4096])], None).data;
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        let summary = header.to_string();
+        assert!(summary.contains("File Version:\t5"));
+        assert!(summary.contains("Hunk Size:\t4096 bytes"));
+        assert!(summary.contains("Compression:\tnone"));
+        assert!(!summary.contains("Parent SHA1:"));
+
+        let chd = v3_chd(512, &[LegacyHunk::Mini(0)], true).data;
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        let summary = header.to_string();
+        assert!(summary.contains("File Version:\t3"));
+        assert!(summary.contains("Compression:\tLegacy zlib (Deflate)"));
+        assert!(summary.contains("Parent SHA1:"));
+    }
+
+    #[test]
+    fn map_offset_test() {
+        // the legacy map immediately follows the header.
+        let chd = v3_chd(512, &[LegacyHunk::Mini(0)], false).data;
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        assert_eq!(120, header.map_offset());
+
+        let chd = v2_chd(512, 1, [1, 1, 1], &[LegacyHunk::Uncompressed(vec![0; 512])]).data;
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        assert_eq!(80, header.map_offset());
+
+        let chd = v5_uncompressed_chd(512, &[Some(vec![0; 512])], None).data;
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        let map_offset = u64::from_be_bytes(chd[40..48].try_into().unwrap());
+        assert_eq!(map_offset, header.map_offset());
+    }
+
+    #[test]
+    fn v2_geometry_test() {
+        // 2352-byte sectors, 3 sectors per hunk, and a disk that does not end on a hunk boundary.
+        let hunks = [
+            LegacyHunk::Uncompressed(vec![0; 7056]),
+            LegacyHunk::Uncompressed(vec![0; 7056]),
+        ];
+        let chd = v2_chd(2352, 3, [5, 1, 1], &hunks).data;
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+        assert_eq!(7056, header.hunk_size());
+        assert_eq!(2352, header.unit_bytes());
+        assert_eq!(5, header.unit_count());
+        assert_eq!(5 * 2352, header.logical_bytes());
+        assert_eq!(2, header.hunk_count());
+
+        let read = |sector_length: u32, hunk_sectors: u32, geometry: [u32; 3]| {
+            let chd = v2_chd(
+                sector_length,
+                hunk_sectors,
+                geometry,
+                &[LegacyHunk::Uncompressed(vec![0; 64])],
+            )
+            .data;
+            Header::try_read_header(&mut Cursor::new(&chd))
+        };
+
+        // geometry of an empty disk is allowed.
+        assert_eq!(0, read(2352, 1, [0, 1, 1]).expect("header").logical_bytes());
+        assert!(matches!(read(0, 1, [1, 1, 1]), Err(Error::InvalidData)));
+        assert!(matches!(read(2352, 0, [1, 1, 1]), Err(Error::InvalidData)));
+        assert!(matches!(
+            read(2352, u32::MAX / 2352 + 1, [1, 1, 1]),
+            Err(Error::InvalidData)
+        ));
+        assert!(matches!(
+            read(u32::MAX, u32::MAX, [u32::MAX; 3]),
+            Err(Error::InvalidData)
+        ));
+        // hunks of 16 MiB or larger are invalid.
+        assert!(read(2352, 7133, [1, 1, 1]).is_ok());
+        assert!(matches!(
+            read(2352, 7134, [1, 1, 1]),
+            Err(Error::InvalidParameter)
+        ));
+    }
+
+    #[test]
+    fn validate_parent_test() {
+        let hunks = [LegacyHunk::Zlib(vec![0x22; 512])];
+        let read = |chd: &[u8]| Header::try_read_header(&mut Cursor::new(chd)).expect("header");
+        let v3_parent = |md5: u8, sha1: u8| {
+            let mut chd = v3_chd(512, &hunks, false).data;
+            chd[44..60].fill(md5);
+            chd[80..100].fill(sha1);
+            read(&chd)
+        };
+
+        // the child requires a parent with an MD5 and SHA1 of all ones.
+        let child = read(&v3_chd(512, &hunks, true).data);
+        assert!(child.validate_parent(&v3_parent(1, 1)).is_ok());
+        assert!(matches!(
+            child.validate_parent(&v3_parent(1, 2)),
+            Err(Error::InvalidParent)
+        ));
+        assert!(matches!(
+            child.validate_parent(&v3_parent(2, 1)),
+            Err(Error::InvalidParent)
+        ));
+
+        // V5 parents have no MD5 to compare against.
+        let mut v5 = v5_uncompressed_chd(512, &[Some(vec![0; 512])], None).data;
+        v5[84..104].fill(1);
+        assert!(matches!(
+            child.validate_parent(&read(&v5)),
+            Err(Error::InvalidParent)
+        ));
+
+        let orphan = read(&v3_chd(512, &hunks, false).data);
+        assert!(matches!(
+            orphan.validate_parent(&v3_parent(1, 1)),
+            Err(Error::InvalidParameter)
+        ));
+    }
 
     #[test]
     fn extract_hard_drive_unit_bytes_test() {
         assert_eq!(Some(10), extract_bps_value(b"CYLS:2,HEADS:3,SECS:4,BPS:10"))
     }
+
+    #[test]
+    fn codec_tag_test() {
+        use crate::header::CodecType;
+        use crate::make_tag;
+
+        assert_eq!(
+            Some(CodecType::LzmaCdV5),
+            CodecType::from_tag(make_tag(b"cdlz"))
+        );
+        assert_eq!(Some(CodecType::ZstdV5), CodecType::from_tag(*b"zstd"));
+        assert_eq!(Some(CodecType::Zlib), CodecType::from_tag(1));
+        assert_eq!("cdlz", CodecType::LzmaCdV5.tag().to_string());
+        assert_eq!(None, CodecType::from_tag(make_tag(b"abcd")));
+    }
+
+    #[test]
+    fn codec_supported_test() {
+        use crate::header::CodecType;
+        use crate::make_tag;
+        use num_traits::FromPrimitive;
+
+        let codecs = [0, 1, 2, 3]
+            .into_iter()
+            .chain(
+                [
+                    b"zlib", b"cdzl", b"cdlz", b"cdfl", b"flac", b"lzma", b"avhu", b"huff",
+                    b"zstd", b"cdzs",
+                ]
+                .map(make_tag),
+            )
+            .map(|tag| CodecType::from_u32(tag).expect("known codec"));
+
+        // a CD-sized hunk is valid for every codec.
+        for codec in codecs {
+            match codec.init(2448 * 8) {
+                Ok(_) => assert!(codec.is_supported(), "{:?}", codec),
+                Err(Error::UnsupportedCodec { fourcc }) => {
+                    assert!(!codec.is_supported(), "{:?}", codec);
+                    assert_eq!(codec.tag(), fourcc);
+                }
+                Err(e) => panic!("{:?} failed to initialize: {:?}", codec, e),
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_codec_test() {
+        let mut chd = v2_chd(512, 1, [1, 1, 1], &[LegacyHunk::Uncompressed(vec![0; 512])]).data;
+        chd[20..24].copy_from_slice(&7u32.to_be_bytes());
+        let err = Header::try_read_header(&mut Cursor::new(&chd)).expect_err("unsupported");
+        assert!(matches!(
+            err,
+            Error::UnsupportedCodec { fourcc } if fourcc == Tag::from(7)
+        ));
+
+        // unknown V5 codecs are only reported when they are used.
+        let mut data = v5_header_bytes(8192, 4096, 512);
+        data[16..20].copy_from_slice(b"zlib");
+        data[20..24].copy_from_slice(b"abcd");
+        let header = Header::try_read_from_stream(&data[..]).expect("header");
+        assert!(header.is_compressed());
+        assert_eq!(
+            map::V5_COMPRESSED_MAP_ENTRY_SIZE as u32,
+            match header {
+                Header::V5Header(h) => h.map_entry_bytes,
+                _ => unreachable!(),
+            }
+        );
+    }
+
+    #[test]
+    fn large_logical_bytes_test() {
+        let logical_bytes = 6 * 1024 * 1024 * 1024 + 1;
+        let mut buf = Cursor::new(v5_header_bytes(logical_bytes, 4096, 512));
+        let header = Header::try_read_header(&mut buf).expect("header could not be read");
+        assert_eq!(logical_bytes, header.logical_bytes());
+        assert_eq!(1572865, header.hunk_count());
+        assert_eq!(12582913, header.unit_count());
+
+        // hunk counts that do not fit in the map must be rejected.
+        let mut buf = Cursor::new(v5_header_bytes(4096 * (u32::MAX as u64 + 1), 4096, 512));
+        assert!(matches!(
+            Header::try_read_header(&mut buf),
+            Err(Error::InvalidData)
+        ));
+        let mut buf = Cursor::new(v5_header_bytes(u64::MAX, 4096, 512));
+        assert!(matches!(
+            Header::try_read_header(&mut buf),
+            Err(Error::InvalidData)
+        ));
+        let mut buf = Cursor::new(v5_header_bytes(u64::MAX, u32::MAX, 1));
+        assert!(matches!(
+            Header::try_read_header(&mut buf),
+            Err(Error::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn read_from_stream_test() {
+        // only the header is consumed from a V5 stream.
+        let mut data = v5_header_bytes(8192, 4096, 512);
+        data.extend_from_slice(&[0xff; 64]);
+        let mut stream = &data[..];
+        let header = Header::try_read_from_stream(&mut stream).expect("header");
+        assert_eq!(64, stream.len());
+        assert_eq!(8192, header.logical_bytes());
+        assert_eq!(512, header.unit_bytes());
+        assert_eq!(2, header.hunk_count());
+
+        let chd = v2_chd(
+            2352,
+            3,
+            [5, 1, 1],
+            &[LegacyHunk::Uncompressed(vec![0; 7056])],
+        )
+        .data;
+        let header = Header::try_read_from_stream(&chd[..]).expect("v2 header");
+        assert_eq!(2352, header.unit_bytes());
+        assert_eq!(7056, header.hunk_size());
+
+        // the unit size of a V3 file is found by reading forward to its metadata.
+        let mut chd = v3_chd(512, &[LegacyHunk::Mini(0)], false).data;
+        let meta_offset = chd.len() as u64;
+        let value = b"CYLS:1,HEADS:1,SECS:2,BPS:256\0";
+        chd.extend_from_slice(b"GDDD");
+        chd.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        chd.extend_from_slice(&0u64.to_be_bytes());
+        chd.extend_from_slice(value);
+        chd[36..44].copy_from_slice(&meta_offset.to_be_bytes());
+        let header = Header::try_read_from_stream(&chd[..]).expect("v3 header");
+        assert_eq!(256, header.unit_bytes());
+        assert_eq!(
+            Header::try_read_header(&mut Cursor::new(&chd))
+                .expect("seekable")
+                .unit_bytes(),
+            header.unit_bytes()
+        );
+
+        // metadata before the end of the header can not be reached.
+        chd[36..44].copy_from_slice(&16u64.to_be_bytes());
+        let header = Header::try_read_from_stream(&chd[..]).expect("v3 header");
+        assert_eq!(512, header.unit_bytes());
+
+        // DVD metadata has 2048-byte units.
+        let mut chd = v3_chd(4096, &[LegacyHunk::Mini(0)], false).data;
+        let meta_offset = chd.len() as u64;
+        chd.extend_from_slice(b"DVD ");
+        chd.extend_from_slice(&[0; 12]);
+        chd[36..44].copy_from_slice(&meta_offset.to_be_bytes());
+        let header = Header::try_read_from_stream(&chd[..]).expect("v3 header");
+        assert_eq!(2048, header.unit_bytes());
+        let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("seekable");
+        assert_eq!(2048, header.unit_bytes());
+
+        // truncated headers and bad lengths are errors.
+        assert!(Header::try_read_from_stream(&data[..100]).is_err());
+        let mut data = v5_header_bytes(8192, 4096, 512);
+        data[8..12].copy_from_slice(&4096u32.to_be_bytes());
+        assert!(matches!(
+            Header::try_read_from_stream(&data[..]),
+            Err(Error::InvalidData)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "write_api")]
+    fn write_v5_header_roundtrip_test() {
+        let header = Header::V5Header(HeaderV5 {
+            version: Version::ChdV5,
+            length: 124,
+            compression: [CodecType::None as u32; 4],
+            logical_bytes: 4096,
+            map_offset: 124,
+            meta_offset: 0,
+            hunk_bytes: 4096,
+            unit_bytes: 512,
+            sha1: [1; 20],
+            parent_sha1: [0; 20],
+            raw_sha1: [2; 20],
+            unit_count: 8,
+            hunk_count: 1,
+            map_entry_bytes: 4,
+        });
+
+        let mut buf = std::io::Cursor::new(Vec::new());
+        header
+            .write_to(&mut buf)
+            .expect("header could not be written");
+        let read = Header::try_read_header(&mut buf).expect("header could not be read");
+        assert_eq!(header.sha1(), read.sha1());
+        assert_eq!(header.raw_sha1(), read.raw_sha1());
+        assert_eq!(header.logical_bytes(), read.logical_bytes());
+        assert_eq!(header.hunk_count(), read.hunk_count());
+    }
+
+    #[test]
+    fn layout_test() {
+        use crate::header::{v1, v2, v3, v4, v5};
+        assert_eq!(
+            [76, 80, 120, 108, 124],
+            [v1::LENGTH, v2::LENGTH, v3::LENGTH, v4::LENGTH, v5::LENGTH]
+        );
+        assert_eq!(76, v2::SECTOR_LENGTH);
+        assert_eq!((76, 80, 100), (v3::HUNK_BYTES, v3::SHA1, v3::PARENT_SHA1));
+        assert_eq!((44, 48, 88), (v4::HUNK_BYTES, v4::SHA1, v4::RAW_SHA1));
+        assert_eq!((40, 64, 84), (v5::MAP_OFFSET, v5::RAW_SHA1, v5::SHA1));
+    }
+
+    #[test]
+    #[cfg(feature = "write_api")]
+    fn write_legacy_header_roundtrip_test() {
+        let hunks = [LegacyHunk::Uncompressed(vec![0; 512])];
+        for chd in [
+            v2_chd(512, 1, [1, 1, 1], &hunks).data,
+            v3_chd(512, &[LegacyHunk::Mini(0)], true).data,
+        ] {
+            let header = Header::try_read_header(&mut Cursor::new(&chd)).expect("header");
+            let mut buf = Cursor::new(Vec::new());
+            header
+                .write_to(&mut buf)
+                .expect("header could not be written");
+            assert_eq!(&chd[..header.len() as usize], &buf.into_inner()[..]);
+        }
+    }
 }
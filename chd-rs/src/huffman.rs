@@ -4,6 +4,7 @@
 //! For format descriptions, see [huffman.cpp](https://github.com/mamedev/mame/blob/master/src/lib/util/huffman.cpp).
 use crate::const_assert;
 use bitreader::{BitReader, BitReaderError};
+use std::alloc::{alloc_zeroed, handle_alloc_error, Layout};
 use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
@@ -103,9 +104,11 @@ pub type Huffman8BitDecoder<'a> = HuffmanDecoder<'a, 256, 16, { lookup_len::<16>
 /// `LOOKUP_ARRAY_LEN` must be equal to `lookup_len::<MAX_BITS>()`,
 /// which is `2 ** MAX_BITS`.
 ///
-/// The Huffman lookup array is stack-allocated. If `MAX_BITS` is too large,
-/// the stack may not be large enough to hold the `HuffmanDecoder`. To free up
-/// stack space, the `HuffmanDecoder` should be boxed.
+/// The Huffman lookup array is stored inline. If `MAX_BITS` is too large,
+/// the stack may not be large enough to hold the `HuffmanDecoder`, such as the 128 KiB lookup array
+/// of a [`Huffman8BitDecoder`](crate::huffman::Huffman8BitDecoder) on a thread with a small stack.
+/// The `_boxed` constructors build the decoder directly on the heap instead, without the lookup array
+/// ever being on the stack.
 pub struct HuffmanDecoder<
     'a,
     const NUM_CODES: usize,
@@ -155,8 +158,44 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
         }
     }
 
+    /// Creates an empty decoder on the heap, without creating it on the stack first.
+    fn new_boxed() -> Box<Self> {
+        const_assert!(MAX_BITS: u8 => MAX_BITS <= 24u8);
+        const_assert!(MAX_BITS: u8, LOOKUP_ARRAY_LEN: usize => LOOKUP_ARRAY_LEN == lookup_len::<MAX_BITS>());
+
+        let layout = Layout::new::<Self>();
+        // SAFETY: the decoder only holds an array of integers and a PhantomData, so zeroed memory
+        // with its layout is a valid empty decoder. The layout is never zero-sized, since the lookup
+        // array has 2 ** MAX_BITS entries.
+        unsafe {
+            let decoder = alloc_zeroed(layout) as *mut Self;
+            if decoder.is_null() {
+                handle_alloc_error(layout);
+            }
+            Box::from_raw(decoder)
+        }
+    }
+
     /// Import RLE encoded Huffman tree from the bit stream.
     pub fn from_tree_rle(reader: &mut BitReader<'_>) -> Result<Self, HuffmanError> {
+        let huffnode_array = Self::read_tree_rle(reader)?;
+        let mut decoder = HuffmanDecoder::new();
+        decoder.build_lookup_table(&huffnode_array)?;
+        Ok(decoder)
+    }
+
+    /// Import RLE encoded Huffman tree from the bit stream into a decoder on the heap.
+    pub fn from_tree_rle_boxed(reader: &mut BitReader<'_>) -> Result<Box<Self>, HuffmanError> {
+        let huffnode_array = Self::read_tree_rle(reader)?;
+        let mut decoder = HuffmanDecoder::new_boxed();
+        decoder.build_lookup_table(&huffnode_array)?;
+        Ok(decoder)
+    }
+
+    /// Read the code lengths of an RLE encoded Huffman tree, and assign their canonical codes.
+    fn read_tree_rle(
+        reader: &mut BitReader<'_>,
+    ) -> Result<[HuffmanNode<'a>; NUM_CODES], HuffmanError> {
         let mut huffnode_array = [HuffmanNode::default(); NUM_CODES];
 
         let mut curr_node = 0;
@@ -178,26 +217,45 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
                 continue;
             }
 
-            let rep_count = reader.read_u8(Self::RLE_NUM_BITS)? + 3;
-            for _ in 0..rep_count {
-                huffnode_array[curr_node].num_bits = node_bits;
-                curr_node += 1;
+            let rep_count = (reader.read_u8(Self::RLE_NUM_BITS)? + 3) as usize;
+            let nodes = huffnode_array
+                .get_mut(curr_node..curr_node + rep_count)
+                .ok_or(HuffmanError::InvalidData)?;
+            for node in nodes {
+                node.num_bits = node_bits;
             }
+            curr_node += rep_count;
         }
 
         if curr_node != NUM_CODES {
             return Err(HuffmanError::InvalidData);
         }
 
-        let mut decoder = HuffmanDecoder::new();
         Self::assign_canonical_codes(&mut huffnode_array)?;
-        decoder.build_lookup_table(&huffnode_array);
-
-        Ok(decoder)
+        Ok(huffnode_array)
     }
 
     /// Import a pre-encoded Huffman tree from the bitstream.
+    #[cfg_attr(not(feature = "huffman_api"), allow(dead_code))]
     pub fn from_huffman_tree(reader: &mut BitReader<'_>) -> Result<Self, HuffmanError> {
+        let huffnode_array = Self::read_huffman_tree(reader)?;
+        let mut decoder = HuffmanDecoder::new();
+        decoder.build_lookup_table(&huffnode_array)?;
+        Ok(decoder)
+    }
+
+    /// Import a pre-encoded Huffman tree from the bitstream into a decoder on the heap.
+    pub fn from_huffman_tree_boxed(reader: &mut BitReader<'_>) -> Result<Box<Self>, HuffmanError> {
+        let huffnode_array = Self::read_huffman_tree(reader)?;
+        let mut decoder = HuffmanDecoder::new_boxed();
+        decoder.build_lookup_table(&huffnode_array)?;
+        Ok(decoder)
+    }
+
+    /// Read the code lengths of a pre-encoded Huffman tree, and assign their canonical codes.
+    fn read_huffman_tree(
+        reader: &mut BitReader<'_>,
+    ) -> Result<[HuffmanNode<'a>; NUM_CODES], HuffmanError> {
         // Parse the small tree
         let mut small_huf = HuffmanDecoder::<24, 6, { lookup_len::<6>() }>::new();
         let mut huffnode_array = [HuffmanNode::default(); 24];
@@ -217,10 +275,9 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
         HuffmanDecoder::<24, 6, { lookup_len::<6>() }>::assign_canonical_codes(
             &mut huffnode_array,
         )?;
-        small_huf.build_lookup_table(&huffnode_array);
+        small_huf.build_lookup_table(&huffnode_array)?;
 
         // Process the rest of the data referring to the small tree.
-        let mut new_huffman = Self::new();
         let mut huffnode_array = [HuffmanNode::default(); NUM_CODES];
         let mut last: u32 = 0;
         let mut curr_node = 0;
@@ -251,9 +308,7 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
         }
 
         Self::assign_canonical_codes(&mut huffnode_array)?;
-        new_huffman.build_lookup_table(&huffnode_array);
-
-        Ok(new_huffman)
+        Ok(huffnode_array)
     }
 
     /// Decode a single code from the Huffman bitstream
@@ -317,9 +372,17 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
         ((code) << 5) | ((bits as u16) & 0x1f)
     }
 
-    fn build_lookup_table(&mut self, huffnode_array: &[HuffmanNode<'a>; NUM_CODES]) {
+    fn build_lookup_table(
+        &mut self,
+        huffnode_array: &[HuffmanNode<'a>; NUM_CODES],
+    ) -> Result<(), HuffmanError> {
         for (curr_code, node) in huffnode_array.iter().enumerate().take(NUM_CODES) {
             if node.num_bits > 0 {
+                // An over-subscribed tree assigns codes that do not fit in their bit length.
+                if node.bits >> node.num_bits != 0 {
+                    return Err(HuffmanError::InvalidData);
+                }
+
                 // Get entry
                 let value = Self::make_lookup(curr_code as u16, node.num_bits);
 
@@ -333,5 +396,109 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
                 }
             }
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::huffman::{Huffman8BitDecoder, HuffmanError};
+    use crate::testing::BitWriter;
+    use bitreader::BitReader;
+
+    #[test]
+    fn rle_tree_test() {
+        let bytes = BitWriter::default().rle(8, 256).write(8, 0x41).bytes();
+        let mut reader = BitReader::new(&bytes);
+        let decoder = Huffman8BitDecoder::from_tree_rle(&mut reader).expect("valid tree");
+        assert_eq!(0x41, decoder.decode_one(&mut reader).expect("code"));
+    }
+
+    #[test]
+    fn boxed_tree_test() {
+        let rle = BitWriter::default().rle(8, 256).write(8, 0x41).bytes();
+        let tree = BitWriter::default()
+            // small tree: code 0 and code 9 (8 bits in the large tree) with 1 bit each.
+            .write(3, 1)
+            .write(3, 7)
+            .write(3, 0)
+            .write(3, 1)
+            .write(3, 7)
+            // large tree: one 8 bit code, then repeat it for the remaining 255 codes.
+            .write(1, 1)
+            .write(1, 0)
+            .write(3, 7)
+            .write(8, 246)
+            .write(8, 0x41)
+            .bytes();
+
+        // the decoders are built on the heap, so they fit on a thread with a small stack.
+        let (rle_bytes, tree_bytes) = (rle.clone(), tree.clone());
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                let mut reader = BitReader::new(&rle_bytes);
+                let decoder = Huffman8BitDecoder::from_tree_rle_boxed(&mut reader).expect("rle");
+                assert_eq!(0x41, decoder.decode_one(&mut reader).expect("code"));
+
+                let mut reader = BitReader::new(&tree_bytes);
+                let decoder =
+                    Huffman8BitDecoder::from_huffman_tree_boxed(&mut reader).expect("tree");
+                assert_eq!(0x41, decoder.decode_one(&mut reader).expect("code"));
+            })
+            .expect("spawn")
+            .join()
+            .expect("small stack");
+
+        // the same decoders built on the stack.
+        let mut reader = BitReader::new(&rle);
+        let decoder = Huffman8BitDecoder::from_tree_rle(&mut reader).expect("rle");
+        assert_eq!(0x41, decoder.decode_one(&mut reader).expect("code"));
+        let mut reader = BitReader::new(&tree);
+        let decoder = Huffman8BitDecoder::from_huffman_tree(&mut reader).expect("tree");
+        assert_eq!(0x41, decoder.decode_one(&mut reader).expect("code"));
+    }
+
+    #[test]
+    fn rle_tree_overflow_test() {
+        // 8 repeats of 34 nodes run past the 256 codes of the tree.
+        let bytes = BitWriter::default().rle(8, 8 * 34).bytes();
+        let mut reader = BitReader::new(&bytes);
+        assert!(matches!(
+            Huffman8BitDecoder::from_tree_rle(&mut reader),
+            Err(HuffmanError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn rle_tree_oversubscribed_test() {
+        // 6 codes of 2 bits can not be assigned, but pass the canonical code parity checks.
+        let bytes = BitWriter::default().rle(2, 6).rle(0, 250).bytes();
+        let mut reader = BitReader::new(&bytes);
+        assert!(matches!(
+            Huffman8BitDecoder::from_tree_rle(&mut reader),
+            Err(HuffmanError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn huffman_tree_oversubscribed_test() {
+        let bytes = BitWriter::default()
+            // small tree: code 0 and code 3 (2 bits in the large tree) with 1 bit each.
+            .write(3, 1)
+            .write(3, 2)
+            .write(3, 1)
+            .write(3, 7)
+            // large tree: one 2 bit code, then repeat it for the remaining 255 codes.
+            .write(1, 1)
+            .write(1, 0)
+            .write(3, 7)
+            .write(8, 255)
+            .bytes();
+        let mut reader = BitReader::new(&bytes);
+        assert!(matches!(
+            Huffman8BitDecoder::from_huffman_tree(&mut reader),
+            Err(HuffmanError::InvalidData)
+        ));
     }
 }
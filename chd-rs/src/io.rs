@@ -0,0 +1,57 @@
+//! Positioned reads from the stream of a CHD file.
+use crate::error::Result;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+/// Reads from the stream of a CHD file at absolute offsets.
+///
+/// Every offset in a CHD file, whether of the map, metadata, or hunk data, is an absolute
+/// offset from the start of the stream. Reads through `ChdIo` always seek to their offset first,
+/// and never depend on the current position of the stream or seek anywhere they do not read.
+/// This keeps stream adapters that only support seeking within a window, such as CHD files
+/// embedded in another file, working without special cases.
+pub(crate) struct ChdIo<'a, F: Read + Seek> {
+    file: &'a mut F,
+}
+
+impl<'a, F: Read + Seek> ChdIo<'a, F> {
+    /// Wraps the stream of a CHD file.
+    pub fn new(file: &'a mut F) -> Self {
+        ChdIo { file }
+    }
+
+    /// Returns the stream positioned at `offset` for sequential reads.
+    pub fn reader_at(&mut self, offset: u64) -> Result<&mut F> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        Ok(self.file)
+    }
+
+    /// Reads exactly enough bytes to fill `buf` at `offset`.
+    pub fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        self.reader_at(offset)?.read_exact(buf)?;
+        Ok(())
+    }
+
+    /// Reads as many bytes as are available up to the length of `buf` at `offset`,
+    /// returning the number of bytes read.
+    ///
+    /// Unlike a single call to [`Read::read`](std::io::Read::read), this only returns less
+    /// than the length of `buf` if the end of the stream was reached.
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let file = self.reader_at(offset)?;
+        let mut read = 0;
+        while read < buf.len() {
+            match file.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(read)
+    }
+
+    /// Returns the length of the stream.
+    pub fn stream_len(&mut self) -> Result<u64> {
+        Ok(self.file.seek(SeekFrom::End(0))?)
+    }
+}
@@ -1,7 +1,9 @@
-//! Traits and implementations for lending iterators for hunks and metadata.
-//! These APIs should be considered unstable and will be replaced with APIs
-//! based around GATs and `LendingIterator` once stabilized. See [rust#44265](https://github.com/rust-lang/rust/issues/44265)
-//! for the tracking issue on GAT stabilization.
+//! Implementations of the `LendingIterator` trait of the [lending-iterator](https://crates.io/crates/lending-iterator)
+//! crate for the iterators in [`lending`](crate::lending), which emulates Generic Associated Types
+//! for compilers that predate their stabilization.
+//!
+//! [`chd::lending::LendingIterator`](crate::lending::LendingIterator) uses GATs directly and does not require
+//! this feature, so it should be preferred in new code.
 //!
 //! ## Iterating over hunks with `LendingIterator`
 //! `LendingIterator` allows a more ergonomic interface to iterate over hunks.
@@ -39,9 +41,7 @@
 //!    let metadata = metadata.read()?;
 //! }
 //! ```
-use crate::metadata::{Metadata, MetadataRef, MetadataRefs, MetadataTag};
-use crate::Result;
-use crate::{Chd, Hunk};
+pub use crate::lending::{Hunks, MetadataEntries, MetadataEntry};
 use lending_iterator::prelude::*;
 use std::io::{Read, Seek};
 
@@ -55,69 +55,15 @@ use std::io::{Read, Seek};
 ///
 pub use lending_iterator::lending_iterator::LendingIterator;
 
-/// An iterator over the hunks of a CHD file.
-pub struct Hunks<'a, F: Read + Seek> {
-    inner: &'a mut Chd<F>,
-    last_hunk: u32,
-    current_hunk: u32,
-}
-
-impl<'a, F: Read + Seek> Hunks<'a, F> {
-    pub(crate) fn new(inner: &'a mut Chd<F>) -> Self {
-        let last_hunk = inner.header().hunk_count();
-        Hunks {
-            inner,
-            last_hunk,
-            current_hunk: 0,
-        }
-    }
-}
-
 #[::nougat::gat]
-impl<'a, F: Read + Seek> LendingIterator for Hunks<'a, F> {
+impl<'a, F: Read + Seek, P: Read + Seek> LendingIterator for Hunks<'a, F, P> {
     type Item<'next>
     where
         Self: 'next,
-    = Hunk<'next, F>;
-
-    fn next(&'_ mut self) -> Option<Hunk<'_, F>> {
-        if self.current_hunk == self.last_hunk {
-            return None;
-        }
-        let curr = self.current_hunk;
-        self.current_hunk += 1;
-        self.inner.hunk(curr).ok()
-    }
-}
-
-/// An iterator over the metadata entries of a CHD file.
-pub struct MetadataEntries<'a, F: Read + Seek + 'a> {
-    inner: MetadataRefs<'a, F>,
-}
-
-impl<'a, F: Read + Seek + 'a> MetadataEntries<'a, F> {
-    pub(crate) fn new(inner: MetadataRefs<'a, F>) -> Self {
-        MetadataEntries { inner }
-    }
-}
-
-impl<'a, F: Read + Seek + 'a> MetadataEntry<'a, F> {
-    /// Read the contents of the metadata from the input stream.
-    pub fn read(&mut self) -> Result<Metadata> {
-        self.meta_ref.read(self.file)
-    }
-}
-
-/// A metadata entry for a CHD file that has a reference to the source file,
-/// allowing read metadata from the stream without an explicit reference.
-pub struct MetadataEntry<'a, F: Read + Seek + 'a> {
-    meta_ref: MetadataRef,
-    file: &'a mut F,
-}
+    = crate::Hunk<'next, F, P>;
 
-impl<'a, F: Read + Seek + 'a> MetadataTag for MetadataEntry<'a, F> {
-    fn metatag(&self) -> u32 {
-        self.meta_ref.metatag()
+    fn next(&'_ mut self) -> Option<crate::Hunk<'_, F, P>> {
+        self.next_hunk()
     }
 }
 
@@ -129,14 +75,6 @@ impl<'a, F: Read + Seek> LendingIterator for MetadataEntries<'a, F> {
     = MetadataEntry<'next, F>;
 
     fn next(&'_ mut self) -> Option<Item<'_, Self>> {
-        let next = self.inner.next();
-        if let Some(next) = next {
-            Some(MetadataEntry {
-                meta_ref: next,
-                file: self.inner.file,
-            })
-        } else {
-            None
-        }
+        self.next_entry()
     }
 }
@@ -0,0 +1,244 @@
+//! Lending iterators over the hunks and metadata of a CHD file, built on Generic Associated Types.
+//!
+//! Because [`Hunk`](crate::Hunk) keeps a mutable reference to its owning [`Chd`](crate::Chd),
+//! hunks can not be yielded by an [`Iterator`](core::iter::Iterator). A [`LendingIterator`](crate::lending::LendingIterator)
+//! lends each item for only as long as the next call to `next`, so it is iterated with a `while let` loop.
+//!
+//! GATs are stable since Rust 1.65. For code written against earlier versions of chd-rs, the
+//! `unstable_lending_iterators` feature and the [`iter`](crate::iter) module still implement the
+//! `LendingIterator` trait of the [lending-iterator](https://crates.io/crates/lending-iterator) crate
+//! for the same types.
+//!
+//! ## Iterating over hunks
+//!```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::lending::LendingIterator;
+//! use chd::Chd;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//!
+//! // buffer to store uncompressed hunk data must be the same length as the hunk size.
+//! let mut hunk_buf = chd.get_hunksized_buffer();
+//! // buffer to store compressed data.
+//! let mut cmp_buf = Vec::new();
+//! let mut hunks = chd.hunks();
+//! while let Some(mut hunk) = hunks.next() {
+//!    hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)?;
+//! }
+//! ```
+//!
+//! ## Iterating over metadata
+//! Metadata entries can be read without keeping a separate reference to the source file.
+//!```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::lending::LendingIterator;
+//! use chd::Chd;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! let mut metadata = chd.metadata();
+//! while let Some(mut metadata) = metadata.next() {
+//!    let metadata = metadata.read()?;
+//! }
+//! ```
+use crate::metadata::{Metadata, MetadataRef, MetadataRefs, MetadataTag};
+use crate::{Chd, Hunk};
+use crate::{Error, Result};
+use std::io::{Read, Seek};
+
+/// An iterator that lends each of its items until the next call to [`next`](crate::lending::LendingIterator::next).
+pub trait LendingIterator {
+    /// The type of the items lent by this iterator.
+    type Item<'next>
+    where
+        Self: 'next;
+
+    /// Advances the iterator and returns the next item, which borrows from the iterator.
+    fn next(&mut self) -> Option<Self::Item<'_>>;
+}
+
+/// An iterator over the hunks of a CHD file.
+pub struct Hunks<'a, F: Read + Seek, P: Read + Seek = F> {
+    inner: &'a mut Chd<F, P>,
+    last_hunk: u32,
+    current_hunk: u32,
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> Hunks<'a, F, P> {
+    pub(crate) fn new(inner: &'a mut Chd<F, P>) -> Self {
+        let last_hunk = inner.header().hunk_count();
+        Hunks {
+            inner,
+            last_hunk,
+            current_hunk: 0,
+        }
+    }
+
+    /// Returns the next hunk, or `None` if every hunk has been iterated over.
+    pub(crate) fn next_hunk(&mut self) -> Option<Hunk<'_, F, P>> {
+        if self.current_hunk == self.last_hunk {
+            return None;
+        }
+        let curr = self.current_hunk;
+        self.current_hunk += 1;
+        self.inner.hunk(curr).ok()
+    }
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> LendingIterator for Hunks<'a, F, P> {
+    type Item<'next>
+        = Hunk<'next, F, P>
+    where
+        Self: 'next;
+
+    fn next(&mut self) -> Option<Hunk<'_, F, P>> {
+        self.next_hunk()
+    }
+}
+
+/// An iterator over the metadata entries of a CHD file.
+pub struct MetadataEntries<'a, F: Read + Seek + 'a> {
+    inner: MetadataRefs<'a, F>,
+}
+
+impl<'a, F: Read + Seek + 'a> MetadataEntries<'a, F> {
+    pub(crate) fn new(inner: MetadataRefs<'a, F>) -> Self {
+        MetadataEntries { inner }
+    }
+
+    /// Takes the error that ended iteration early, if the chain of metadata entries is invalid.
+    ///
+    /// See [`MetadataRefs::take_error`](crate::metadata::MetadataRefs::take_error).
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.inner.take_error()
+    }
+
+    /// Returns the next metadata entry, or `None` if the end of the chain has been reached.
+    pub(crate) fn next_entry(&mut self) -> Option<MetadataEntry<'_, F>> {
+        let meta_ref = self.inner.next()?;
+        Some(MetadataEntry {
+            meta_ref,
+            file: self.inner.file,
+        })
+    }
+}
+
+impl<'a, F: Read + Seek> LendingIterator for MetadataEntries<'a, F> {
+    type Item<'next>
+        = MetadataEntry<'next, F>
+    where
+        Self: 'next;
+
+    fn next(&mut self) -> Option<MetadataEntry<'_, F>> {
+        self.next_entry()
+    }
+}
+
+/// A metadata entry for a CHD file that has a reference to the source file,
+/// allowing read metadata from the stream without an explicit reference.
+pub struct MetadataEntry<'a, F: Read + Seek + 'a> {
+    meta_ref: MetadataRef,
+    file: &'a mut F,
+}
+
+impl<'a, F: Read + Seek + 'a> MetadataEntry<'a, F> {
+    /// Read the contents of the metadata from the input stream.
+    pub fn read(&mut self) -> Result<Metadata> {
+        self.meta_ref.read(self.file)
+    }
+
+    /// Reads the contents of the metadata into `buf` from the input stream without allocating,
+    /// returning the number of bytes read.
+    ///
+    /// See [`MetadataRef::read_into`](crate::metadata::MetadataRef::read_into).
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.meta_ref.read_into(self.file, buf)
+    }
+
+    /// Returns the length of the contents of this metadata entry.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        self.meta_ref.len()
+    }
+}
+
+impl<'a, F: Read + Seek + 'a> MetadataTag for MetadataEntry<'a, F> {
+    fn metatag(&self) -> u32 {
+        self.meta_ref.metatag()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lending::LendingIterator;
+    use crate::metadata::{KnownMetadata, MetadataTag};
+    use crate::testing::v5_uncompressed_chd;
+    use crate::Chd;
+    use std::io::Cursor;
+
+    #[test]
+    fn hunks_test() {
+        let hunks = [Some(vec![1; 512]), None, Some(vec![3; 512])];
+        let data = v5_uncompressed_chd(512, &hunks, None).data;
+        let mut chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+
+        let mut read = Vec::new();
+        let mut hunks = chd.hunks();
+        while let Some(mut hunk) = hunks.next() {
+            hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+                .expect("hunk");
+            read.push(hunk_buf[0]);
+        }
+        assert_eq!(vec![1, 0, 3], read);
+        assert!(hunks.next().is_none());
+    }
+
+    #[test]
+    fn metadata_test() {
+        let mut data = v5_uncompressed_chd(512, &[None], None).data;
+        let entries = [
+            (
+                KnownMetadata::HardDisk,
+                &b"CYLS:1,HEADS:1,SECS:1,BPS:512\0"[..],
+            ),
+            (KnownMetadata::HardDiskIdent, &b"ident"[..]),
+        ];
+        let meta_offset = data.len() as u64;
+        for (index, (tag, value)) in entries.iter().enumerate() {
+            let next = if index + 1 < entries.len() {
+                data.len() as u64 + 16 + value.len() as u64
+            } else {
+                0
+            };
+            data.extend_from_slice(&tag.metatag().to_be_bytes());
+            data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            data.extend_from_slice(&next.to_be_bytes());
+            data.extend_from_slice(value);
+        }
+        data[48..56].copy_from_slice(&meta_offset.to_be_bytes());
+
+        let mut chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        let mut metas = chd.metadata();
+        let mut read = Vec::new();
+        while let Some(mut meta) = metas.next() {
+            let mut buf = [0u8; 64];
+            let len = meta.read_into(&mut buf).expect("read");
+            assert_eq!(meta.len() as usize, len);
+            assert_eq!(meta.read().expect("read").value, buf[..len].to_vec());
+            read.push((meta.metatag(), buf[..len].to_vec()));
+        }
+        assert!(metas.take_error().is_none());
+        assert_eq!(
+            entries
+                .iter()
+                .map(|(tag, value)| (tag.metatag(), value.to_vec()))
+                .collect::<Vec<_>>(),
+            read
+        );
+    }
+}
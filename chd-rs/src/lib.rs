@@ -18,10 +18,19 @@
 //! * Zstandard
 //! * CDZS (CD Zstandard)
 //!
+//! Each codec is enabled by its own feature (`zlib`, `lzma`, `flac`, `huffman`, `avhuff` and `zstd`),
+//! all of which are enabled by default through the `all_codecs` feature. The CD-ROM codecs are enabled
+//! along with the codec for their sector data, but apart from CDZS also require `zlib` for subcode data.
+//! Opening a CHD file that uses a disabled codec fails with [`Error::UnsupportedCodec`](crate::Error::UnsupportedCodec),
+//! which carries the tag of the codec.
+//!
+//! With the `write_api` feature, new CHD V5 files can be written with [`ChdWriter`](crate::write::ChdWriter),
+//! which compresses with the `zlib`, `lzma`, `cdzl` and `cdlz` codecs.
+//!
 //! ## Iterating over hunks
 //! Because [`Hunk`](crate::Hunk) keeps a mutable reference to its owning
-//! [`Chd`](crate::Chd), direct iteration of hunks is not possible without
-//! Generic Associated Types. Instead, the hunk indices should be iterated over.
+//! [`Chd`](crate::Chd), hunks can not be iterated over with an [`Iterator`](core::iter::Iterator).
+//! Instead, the hunk indices can be iterated over.
 //!
 //!```rust
 //! use std::fs::File;
@@ -44,6 +53,41 @@
 //! }
 //! ```
 //!
+//! [`Chd::hunks`](crate::Chd::hunks) returns a [`LendingIterator`](crate::lending::LendingIterator)
+//! over the hunks, which can be iterated over with a `while let` loop.
+//!
+//!```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::lending::LendingIterator;
+//! use chd::Chd;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! let mut hunk_buf = chd.get_hunksized_buffer();
+//! let mut cmp_buf = Vec::new();
+//!
+//! let mut hunks = chd.hunks();
+//! while let Some(mut hunk) = hunks.next() {
+//!     hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)?;
+//! }
+//! ```
+//!
+//! For quick scripts where performance is not a concern, [`Chd::hunk_bytes_iter`](crate::Chd::hunk_bytes_iter)
+//! yields the contents of each hunk in a newly allocated buffer.
+//!
+//!```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! for hunk in chd.hunk_bytes_iter() {
+//!     let hunk: Vec<u8> = hunk?;
+//! }
+//! ```
+//!
 //! ## Iterating over metadata
 //! Metadata in a CHD file consists of a list of entries that contain offsets to the
 //! byte data of the metadata contents in the CHD file. The individual metadata entries
@@ -61,6 +105,9 @@
 //!     let metadata = entry.read(&mut f)?;
 //! }
 //!```
+//! [`Chd::metadata`](crate::Chd::metadata) lends entries that keep a reference to the source stream,
+//! so that their contents can be read without providing it.
+//!
 //! `Vec<Metadata>` implements `TryFrom<MetadataRefs>` so all metadata entries
 //! can be collected at once without requiring a reference to the file.
 //! ```rust
@@ -88,14 +135,24 @@ compile_error!(
 mod error;
 
 mod block_hash;
-mod cdrom;
 mod chdfile;
 mod compression;
+mod io;
+mod tag;
+#[cfg(feature = "parallel")]
+mod prefetch;
+
+#[cfg(test)]
+mod testing;
+
+#[cfg(all(test, feature = "compat_tests"))]
+mod compat_tests;
 
 #[cfg(feature = "huffman_api")]
 pub mod huffman;
 
 #[cfg(not(feature = "huffman_api"))]
+#[cfg_attr(not(any(feature = "huffman", feature = "avhuff")), allow(dead_code))]
 mod huffman;
 
 #[cfg(feature = "codec_api")]
@@ -110,6 +167,9 @@ mod huffman;
 /// CD-ROM wrapped codecs that use Deflate to decompress subcode data, the codec implementations
 /// do not check the length of the output buffer against the hunk size. It is up to the caller
 /// of [`decompress`](crate::codecs::CodecImplementation::decompress) to uphold length invariants.
+///
+/// Only the codecs enabled by the codec features of this crate are available. CD-ROM codecs other
+/// than `cdzs` also require the `zlib` feature to decompress subcode data.
 #[cfg_attr(docsrs, doc(cfg(codec_api)))]
 pub mod codecs {
     pub use crate::compression::codecs::*;
@@ -137,17 +197,44 @@ macro_rules! const_assert {
 
 pub(crate) use const_assert;
 
-pub use chdfile::{Chd, Hunk};
+pub use chdfile::{
+    Chd, ChdOpenOptions, CodecUsage, DecodeEvent, DecodeObserver, Hunk, MemoryFootprint,
+    ParentInfo,
+};
 pub use error::{Error, Result};
+pub use tag::Tag;
+#[cfg(feature = "archive")]
+#[cfg_attr(docsrs, doc(cfg(archive)))]
+pub mod archive;
+pub mod av;
+pub mod cdrom;
+pub mod content;
+pub mod dedup;
+pub mod diff;
+pub mod dvd;
 pub mod header;
+pub mod lending;
 pub mod map;
 pub mod metadata;
 pub mod read;
+pub mod split;
+
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(serde)))]
+pub mod snapshot;
 
 #[cfg(feature = "unstable_lending_iterators")]
 #[cfg_attr(docsrs, doc(cfg(unstable_lending_iterators)))]
 pub mod iter;
 
+#[cfg(feature = "write_api")]
+#[cfg_attr(docsrs, doc(cfg(write_api)))]
+pub mod repair;
+
+#[cfg(feature = "write_api")]
+#[cfg_attr(docsrs, doc(cfg(write_api)))]
+pub mod write;
+
 #[cfg(test)]
 mod tests {
     use crate::metadata::Metadata;
@@ -155,7 +242,7 @@ mod tests {
     use crate::Chd;
     use std::convert::TryInto;
     use std::fs::File;
-    use std::io::{BufReader, Read, Write};
+    use std::io::{BufRead, BufReader, Read, Write};
 
     #[cfg(feature = "unstable_lending_iterators")]
     use crate::iter::LendingIterator;
@@ -183,7 +270,9 @@ mod tests {
         let mut cmp_buf = Vec::new();
         for hunk_num in 0..hunk_count {
             let mut hunk = chd.hunk(hunk_num).expect("could not acquire hunk");
-            let read = HunkBufReader::new_in(&mut hunk, &mut cmp_buf, hunk_buf)
+            let mut read = HunkBufReader::new_in(&mut hunk, &mut cmp_buf, hunk_buf)
+                .expect(format!("could not read_hunk {}", hunk_num).as_str());
+            read.fill_buf()
                 .expect(format!("could not read_hunk {}", hunk_num).as_str());
             hunk_buf = read.into_inner();
         }
@@ -217,6 +306,42 @@ mod tests {
         f_out.write_all(&buf).expect("did not write")
     }
 
+    #[test]
+    fn read_file_read_ahead_test() {
+        let f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+        let mut read = ChdReader::new(Chd::open(f, None).expect("file"));
+        let mut buf = Vec::new();
+        read.read_to_end(&mut buf).expect("can read to end");
+
+        let f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+        let mut read = ChdReader::with_read_ahead(Chd::open(f, None).expect("file"), 8);
+        let mut ahead_buf = Vec::new();
+        while let Ok(chunk) = read.fill_buf() {
+            if chunk.is_empty() {
+                break;
+            }
+            let len = chunk.len();
+            ahead_buf.extend_from_slice(chunk);
+            read.consume(len);
+        }
+        assert_eq!(buf, ahead_buf);
+    }
+
+    #[test]
+    fn hunk_bytes_iter_test() {
+        let mut f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+        let mut chd = Chd::open(&mut f, None).expect("file");
+        let hunk_count = chd.header().hunk_count() as usize;
+        let hunk_size = chd.header().hunk_size() as usize;
+
+        let hunks: Vec<Vec<u8>> = chd
+            .hunk_bytes_iter()
+            .collect::<crate::Result<_>>()
+            .expect("hunks");
+        assert_eq!(hunk_count, hunks.len());
+        assert!(hunks.iter().all(|h| h.len() == hunk_size));
+    }
+
     #[test]
     fn read_parent_test() {
         let p = BufReader::new(File::open(".testimages/TombRaider.chd").expect(""));
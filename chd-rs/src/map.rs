@@ -1,17 +1,22 @@
 //! Types and methods relating to the CHD hunk map.
 
+use std::collections::BTreeSet;
 use std::convert::TryFrom;
-use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{BufReader, Cursor, Read, Seek, Write};
+use std::marker::PhantomData;
+use std::sync::Mutex;
 
 use bitreader::BitReader;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use num_derive::{FromPrimitive, ToPrimitive};
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::const_assert;
 use crate::error::{Error, Result};
 use crate::header::{Header, HeaderV5};
 use crate::huffman::{lookup_len, HuffmanDecoder};
+use crate::io::ChdIo;
 
 pub(crate) const V5_UNCOMPRESSED_MAP_ENTRY_SIZE: usize = 4;
 pub(crate) const V5_COMPRESSED_MAP_ENTRY_SIZE: usize = 12;
@@ -19,6 +24,7 @@ const V3_MAP_ENTRY_SIZE: usize = 16; // V3-V4
 const V1_MAP_ENTRY_SIZE: usize = 8; // V1-V2
 const MAP_ENTRY_FLAG_TYPE_MASK: u8 = 0x0f; // type of hunk
 const MAP_ENTRY_FLAG_NO_CRC: u8 = 0x10; // no crc is present
+const END_OF_LIST_COOKIE: &[u8; V3_MAP_ENTRY_SIZE] = b"EndOfListCookie\0";
 
 /// The types of compression allowed for a CHD V5 hunk.
 #[repr(u8)]
@@ -75,19 +81,53 @@ pub enum CompressionTypeLegacy {
 }
 
 /// Opaque type for a V5 map.
-pub struct RawMapV5(Vec<u8>, bool, u32);
+pub struct RawMapV5(V5MapData, bool, u32);
+
+/// The storage of a V5 map, either fully expanded or lazily decoded.
+enum V5MapData {
+    Expanded(Vec<u8>),
+    Lazy(Box<LazyMapV5>),
+}
 /// Opaque type for a legacy map.
 pub struct RawMapLegacy(Vec<LegacyMapEntry>);
 
+impl RawMapV5 {
+    /// Expands the map into its raw entries.
+    ///
+    /// Maps read with [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy) are decoded in full,
+    /// and return [`Error::InvalidMap`](crate::Error::InvalidMap) if an entry can not be decoded.
+    pub fn try_expand(&self) -> Result<Vec<u8>> {
+        match &self.0 {
+            V5MapData::Expanded(raw_map) => Ok(raw_map.clone()),
+            V5MapData::Lazy(lazy) => lazy.expand(),
+        }
+    }
+}
+
+/// Expands the map into its raw entries.
+///
+/// # Panics
+/// Panics if the map was read with [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy)
+/// and an entry can not be decoded. Use [`RawMapV5::try_expand`](crate::map::RawMapV5::try_expand)
+/// to expand such maps.
 impl From<RawMapV5> for Vec<u8> {
     fn from(map: RawMapV5) -> Self {
-        map.0
+        match map.0 {
+            V5MapData::Expanded(raw_map) => raw_map,
+            V5MapData::Lazy(lazy) => lazy.expand().expect("lazy map could not be decoded"),
+        }
     }
 }
 
+/// Expands the map into its raw entries.
+///
+/// # Panics
+/// Panics if the map was read with [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy)
+/// and an entry can not be decoded. Use [`RawMapV5::try_expand`](crate::map::RawMapV5::try_expand)
+/// to expand such maps.
 impl From<&RawMapV5> for Vec<u8> {
     fn from(map: &RawMapV5) -> Self {
-        map.0.clone()
+        map.try_expand().expect("lazy map could not be decoded")
     }
 }
 
@@ -100,7 +140,10 @@ pub struct LegacyMapEntry {
 }
 
 /// A CHD V5 map entry for a compressed hunk.
-pub struct V5CompressedMapEntry<'a>(&'a [u8; V5_COMPRESSED_MAP_ENTRY_SIZE]);
+pub struct V5CompressedMapEntry<'a>(
+    [u8; V5_COMPRESSED_MAP_ENTRY_SIZE],
+    PhantomData<&'a RawMapV5>,
+);
 
 /// A CHD V5 map entry for an uncompressed hunk.
 pub struct V5UncompressedMapEntry<'a>(&'a [u8; V5_UNCOMPRESSED_MAP_ENTRY_SIZE], u32);
@@ -174,6 +217,41 @@ impl V5CompressedMapEntry<'_> {
         Ok(Cursor::new(&self.0[10..]).read_u16::<BigEndian>()?)
     }
 
+    /// Returns the index into the codecs of the header of the codec that compressed this hunk,
+    /// or `None` if the hunk is not compressed with a codec.
+    pub fn codec_index(&self) -> Option<usize> {
+        match self.hunk_type().ok()? {
+            comptype @ (CompressionTypeV5::CompressionType0
+            | CompressionTypeV5::CompressionType1
+            | CompressionTypeV5::CompressionType2
+            | CompressionTypeV5::CompressionType3) => comptype.to_usize(),
+            _ => None,
+        }
+    }
+
+    /// Returns the hunk in this CHD file that this hunk is a copy of, or `None` if the hunk is
+    /// not a copy of another hunk in this CHD file.
+    ///
+    /// The target may itself be a copy of another hunk.
+    pub fn self_target(&self) -> Option<u32> {
+        match self.hunk_type().ok()? {
+            CompressionTypeV5::CompressionSelf => u32::try_from(self.block_offset().ok()?).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the offset in units into the parent CHD file that this hunk is a copy of, or `None`
+    /// if the hunk is not a copy of data in the parent.
+    ///
+    /// The offset is in units of the parent rather than hunks, and does not need to be aligned
+    /// to the start of a hunk.
+    pub fn parent_unit_offset(&self) -> Option<u64> {
+        match self.hunk_type().ok()? {
+            CompressionTypeV5::CompressionParent => self.block_offset().ok(),
+            _ => None,
+        }
+    }
+
     /// Obtain a proof that the hunk this entry refers to is compressed.
     /// If the hunk is uncompressed, returns `Error::InvalidParameter`.
     #[inline(always)]
@@ -245,6 +323,48 @@ pub enum MapEntry<'a> {
     LegacyEntry(&'a LegacyMapEntry),
 }
 
+/// The checksum of the uncompressed data of a hunk, as recorded in its map entry.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum HunkCrc {
+    /// The CRC16 of the hunk data, recorded in V5 maps.
+    Crc16(u16),
+    /// The CRC32 of the hunk data, recorded in V1-4 maps.
+    Crc32(u32),
+}
+
+/// A problem with a map entry found by [`Map::check_integrity`](crate::map::Map::check_integrity).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IntegrityIssue {
+    /// The map entry could not be decoded, or has a hunk type that is not valid in a hunk map.
+    InvalidEntry,
+    /// The hunk is compressed with a codec slot that has no codec in the header.
+    MissingCodec,
+    /// The size of the stored hunk data is not valid for the hunk type.
+    InvalidSize,
+    /// The stored hunk data begins within the header, or extends past the end of the file.
+    OutOfBounds,
+    /// The hunk is a copy of itself, or of a hunk that does not exist.
+    InvalidSelfReference,
+    /// The hunk refers to data in the parent, but the CHD file does not have a parent.
+    MissingParent,
+}
+
+/// The result of checking the integrity of a hunk map.
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityReport {
+    /// The number of map entries that were checked.
+    pub checked_hunks: u32,
+    /// The hunk numbers of the map entries with problems, and the problem found, in order.
+    pub issues: Vec<(u32, IntegrityIssue)>,
+}
+
+impl IntegrityReport {
+    /// Returns whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
 /// A proof that a hunk is compressed.
 /// An instance of this type can only be constructed from an compressed hunk.
 pub(crate) struct CompressedEntryProof(u64, u32);
@@ -275,6 +395,24 @@ impl UncompressedEntryProof {
     }
 }
 
+/// The format of a hunk map listing written by [`Map::export`](crate::map::Map::export).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MapExportFormat {
+    /// Comma-separated values, with a header row of column names.
+    Csv,
+    /// A JSON array with an object for each hunk.
+    Json,
+}
+
+/// The columns of a single hunk written by [`Map::export`](crate::map::Map::export).
+struct ExportedEntry {
+    kind: &'static str,
+    codec: Option<String>,
+    offset: Option<u64>,
+    length: Option<u32>,
+    crc: Option<HunkCrc>,
+}
+
 /// Iterator for [`Map`](crate::map::Map).
 pub struct MapEntries<'a> {
     map: &'a Map,
@@ -295,72 +433,580 @@ impl<'a> Iterator for MapEntries<'a> {
     }
 }
 
+/// Iterator over the hunks of a [`Map`](crate::map::Map) that refer to the parent CHD.
+///
+/// See [`Map::parent_refs`](crate::map::Map::parent_refs).
+pub struct ParentRefs<'a> {
+    entries: std::iter::Enumerate<MapEntries<'a>>,
+    units_per_hunk: u64,
+    has_parent: bool,
+}
+
+impl<'a> Iterator for ParentRefs<'a> {
+    type Item = (u32, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (hunk_num, entry) in self.entries.by_ref() {
+            let parent_unit = match entry {
+                MapEntry::V5Compressed(entry) => entry.parent_unit_offset(),
+                // an uncompressed entry with no offset is read from the same hunk in the parent.
+                MapEntry::V5Uncompressed(entry) => match entry.block_offset() {
+                    Ok(0) if self.has_parent => Some(hunk_num as u64 * self.units_per_hunk),
+                    _ => None,
+                },
+                // legacy maps refer to parent hunks rather than units.
+                MapEntry::LegacyEntry(entry) => match entry.hunk_type() {
+                    Ok(CompressionTypeLegacy::ParentHunk) => {
+                        Some(entry.block_offset() * self.units_per_hunk)
+                    }
+                    _ => None,
+                },
+            };
+
+            if let Some(parent_unit) = parent_unit {
+                return Some((hunk_num as u32, parent_unit));
+            }
+        }
+        None
+    }
+}
+
+impl Map {
+    /// Returns a description of the kind of this map.
+    fn kind(&self) -> &'static str {
+        match self {
+            Map::V5(RawMapV5(_, true, _)) => "V5 compressed",
+            Map::V5(RawMapV5(_, false, _)) => "V5 uncompressed",
+            Map::Legacy(_) => "V1-4",
+        }
+    }
+}
+
+impl Debug for Map {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Map")
+            .field("kind", &self.kind())
+            .field("len", &self.len())
+            .field("memory_footprint", &self.memory_footprint())
+            .finish()
+    }
+}
+
+impl Display for Map {
+    /// Formats a table of the number of hunks of each kind in the map, like `chdman info --verbose`.
+    ///
+    /// Compressed hunks are listed by the index of their codec in the header. Hunks of uncompressed
+    /// V5 maps that are not stored in the file are either unallocated or stored in the parent,
+    /// which can only be told apart with the header.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        const NAMES: [&str; 10] = [
+            "Codec 0",
+            "Codec 1",
+            "Codec 2",
+            "Codec 3",
+            "Uncompressed",
+            "Copy from self",
+            "Copy from parent",
+            "Legacy 8-byte mini",
+            "Unallocated or parent",
+            "Unknown",
+        ];
+        let mut counts = [0u64; 10];
+        for entry in self.iter() {
+            let index = match entry {
+                MapEntry::V5Compressed(entry) => match entry.hunk_type() {
+                    Ok(
+                        ty @ (CompressionTypeV5::CompressionType0
+                        | CompressionTypeV5::CompressionType1
+                        | CompressionTypeV5::CompressionType2
+                        | CompressionTypeV5::CompressionType3),
+                    ) => ty as usize,
+                    Ok(CompressionTypeV5::CompressionNone) => 4,
+                    Ok(CompressionTypeV5::CompressionSelf) => 5,
+                    Ok(CompressionTypeV5::CompressionParent) => 6,
+                    _ => 9,
+                },
+                MapEntry::V5Uncompressed(entry) => match entry.block_offset() {
+                    Ok(0) => 8,
+                    Ok(_) => 4,
+                    Err(_) => 9,
+                },
+                MapEntry::LegacyEntry(entry) => match entry.hunk_type() {
+                    Ok(CompressionTypeLegacy::Compressed) => 0,
+                    Ok(CompressionTypeLegacy::Uncompressed) => 4,
+                    Ok(CompressionTypeLegacy::SelfHunk) => 5,
+                    Ok(CompressionTypeLegacy::ParentHunk) => 6,
+                    Ok(CompressionTypeLegacy::Mini) => 7,
+                    _ => 9,
+                },
+            };
+            counts[index] += 1;
+        }
+
+        let total = self.len();
+        writeln!(f, "{} hunk map, {} hunks", self.kind(), total)?;
+        writeln!(f)?;
+        writeln!(f, "     Hunks  Percent  Name")?;
+        write!(
+            f,
+            "----------  -------  ------------------------------------"
+        )?;
+        for (count, name) in counts.iter().zip(NAMES) {
+            if *count == 0 {
+                continue;
+            }
+            write!(
+                f,
+                "\n{:>10}   {:>5.1}%  {}",
+                count,
+                100.0 * *count as f64 / total as f64,
+                name
+            )?;
+        }
+        Ok(())
+    }
+}
+
 impl Map {
     /// Gets the number of entries in the CHD Map.
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         match self {
-            Map::V5(m) => {
+            Map::V5(RawMapV5(V5MapData::Lazy(lazy), ..)) => lazy.types.len(),
+            Map::V5(RawMapV5(V5MapData::Expanded(raw_map), compressed, _)) => {
                 // map_entry_bytes = 12 if compressed, else 4
-                let map_entry_bytes = if m.1 {
+                let map_entry_bytes = if *compressed {
                     V5_COMPRESSED_MAP_ENTRY_SIZE
                 } else {
                     V5_UNCOMPRESSED_MAP_ENTRY_SIZE
                 };
-                m.0.len() / map_entry_bytes
+                raw_map.len() / map_entry_bytes
             }
             Map::Legacy(m) => m.0.len(),
         }
     }
 
+    /// Returns the number of bytes of memory held by the entries of this map.
+    ///
+    /// For a map that is decoded on demand, this includes the compressed map and the
+    /// entries that have been decoded so far.
+    pub fn memory_footprint(&self) -> usize {
+        match self {
+            Map::V5(RawMapV5(V5MapData::Lazy(lazy), ..)) => lazy.memory_footprint(),
+            Map::V5(RawMapV5(V5MapData::Expanded(raw_map), ..)) => raw_map.capacity(),
+            Map::Legacy(m) => m.0.capacity() * std::mem::size_of::<LegacyMapEntry>(),
+        }
+    }
+
     /// Gets the `MapEntry` for the specified hunk number if it exists.
-    pub fn get_entry(&self, hunk_num: usize) -> Option<MapEntry> {
+    ///
+    /// Returns `None` if the hunk does not exist, or if its entry in a map read with
+    /// [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy) can not be decoded.
+    /// Use [`Map::try_get_entry`](crate::map::Map::try_get_entry) to tell the two apart.
+    pub fn get_entry(&self, hunk_num: usize) -> Option<MapEntry<'_>> {
+        self.try_get_entry(hunk_num).ok()
+    }
+
+    /// Gets the `MapEntry` for the specified hunk number.
+    ///
+    /// If the hunk does not exist, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange).
+    /// If the entry of a map read with [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy)
+    /// can not be decoded, returns [`Error::InvalidMap`](crate::Error::InvalidMap).
+    pub fn try_get_entry(&self, hunk_num: usize) -> Result<MapEntry<'_>> {
         match self {
-            Map::V5(m) => {
-                let map_entry_bytes = if m.1 { 12 } else { 4 };
-
-                let entry_slice =
-                    &m.0.get(hunk_num * map_entry_bytes..(hunk_num + 1) * map_entry_bytes);
-                if let &Some(entry_slice) = entry_slice {
-                    return if m.1 {
-                        <&[u8; 12]>::try_from(entry_slice)
-                            .map(|e| MapEntry::V5Compressed(V5CompressedMapEntry(e)))
-                            .ok()
-                    } else {
-                        <&[u8; 4]>::try_from(entry_slice)
-                            .map(|e| MapEntry::V5Uncompressed(V5UncompressedMapEntry(e, m.2)))
-                            .ok()
-                    };
+            Map::V5(RawMapV5(V5MapData::Lazy(lazy), ..)) => lazy
+                .get_entry(hunk_num)
+                .map(|e| MapEntry::V5Compressed(V5CompressedMapEntry(e, PhantomData))),
+            Map::V5(RawMapV5(V5MapData::Expanded(raw_map), compressed, hunk_bytes)) => {
+                let map_entry_bytes = if *compressed { 12 } else { 4 };
+
+                let entry_slice = hunk_num
+                    .checked_mul(map_entry_bytes)
+                    .and_then(|entry_start| raw_map.get(entry_start..)?.get(..map_entry_bytes))
+                    .ok_or(Error::HunkOutOfRange)?;
+                if *compressed {
+                    <[u8; 12]>::try_from(entry_slice)
+                        .map(|e| MapEntry::V5Compressed(V5CompressedMapEntry(e, PhantomData)))
+                        .map_err(|_| Error::HunkOutOfRange)
+                } else {
+                    <&[u8; 4]>::try_from(entry_slice)
+                        .map(|e| MapEntry::V5Uncompressed(V5UncompressedMapEntry(e, *hunk_bytes)))
+                        .map_err(|_| Error::HunkOutOfRange)
                 }
-                None
             }
-            Map::Legacy(m) => m.0.get(hunk_num).map(MapEntry::LegacyEntry),
+            Map::Legacy(m) => {
+                m.0.get(hunk_num)
+                    .map(MapEntry::LegacyEntry)
+                    .ok_or(Error::HunkOutOfRange)
+            }
+        }
+    }
+
+    /// Gets the checksum of the uncompressed data of the specified hunk recorded in its map entry.
+    ///
+    /// V5 map entries record a CRC16 for compressed and uncompressed hunks, and legacy map entries
+    /// record a CRC32 for compressed, uncompressed and mini hunks. Returns `None` if the hunk does
+    /// not exist, or its map entry does not record a checksum, such as entries in an uncompressed
+    /// V5 map, or entries that copy data from another hunk.
+    pub fn hunk_crc(&self, hunk_num: usize) -> Option<HunkCrc> {
+        match self.get_entry(hunk_num)? {
+            MapEntry::V5Compressed(entry) => match entry.hunk_type().ok()? {
+                CompressionTypeV5::CompressionType0
+                | CompressionTypeV5::CompressionType1
+                | CompressionTypeV5::CompressionType2
+                | CompressionTypeV5::CompressionType3
+                | CompressionTypeV5::CompressionNone => entry.hunk_crc().ok().map(HunkCrc::Crc16),
+                _ => None,
+            },
+            MapEntry::V5Uncompressed(_) => None,
+            MapEntry::LegacyEntry(entry) => match entry.hunk_type().ok()? {
+                CompressionTypeLegacy::Compressed
+                | CompressionTypeLegacy::Uncompressed
+                | CompressionTypeLegacy::Mini => entry.hunk_crc().map(HunkCrc::Crc32),
+                _ => None,
+            },
+        }
+    }
+
+    /// Checks that every entry of this map is consistent with the header and refers to data
+    /// within a file of length `file_len`, without reading or decompressing any hunk data.
+    ///
+    /// The header must be the header of the CHD file this map was read from.
+    pub fn check_integrity(&self, header: &Header, file_len: u64) -> IntegrityReport {
+        let hunk_size = header.hunk_size();
+        let hunk_count = self.len() as u64;
+        let has_parent = header.has_parent();
+        let codecs = match header {
+            Header::V5Header(h) => h.compression,
+            _ => [0; 4],
+        };
+        // stored hunk data can not begin within the header.
+        let check_bounds = |offset: u64, size: u32| {
+            if offset < header.len() as u64 || offset.saturating_add(size as u64) > file_len {
+                Err(IntegrityIssue::OutOfBounds)
+            } else {
+                Ok(())
+            }
+        };
+        let check_self = |hunk_num: u64, target: u64| {
+            if target >= hunk_count || target == hunk_num {
+                Err(IntegrityIssue::InvalidSelfReference)
+            } else {
+                Ok(())
+            }
+        };
+        let check_parent = || {
+            if has_parent {
+                Ok(())
+            } else {
+                Err(IntegrityIssue::MissingParent)
+            }
+        };
+
+        let mut report = IntegrityReport::default();
+        for hunk_num in 0..self.len() {
+            let result = match self.get_entry(hunk_num) {
+                None => Err(IntegrityIssue::InvalidEntry),
+                Some(MapEntry::V5Compressed(entry)) => {
+                    match (entry.hunk_type(), entry.block_offset(), entry.block_size()) {
+                        (
+                            Ok(
+                                ty @ (CompressionTypeV5::CompressionType0
+                                | CompressionTypeV5::CompressionType1
+                                | CompressionTypeV5::CompressionType2
+                                | CompressionTypeV5::CompressionType3),
+                            ),
+                            Ok(offset),
+                            Ok(size),
+                        ) => {
+                            if codecs[ty as usize] == 0 {
+                                Err(IntegrityIssue::MissingCodec)
+                            } else if size == 0 {
+                                Err(IntegrityIssue::InvalidSize)
+                            } else {
+                                check_bounds(offset, size)
+                            }
+                        }
+                        (Ok(CompressionTypeV5::CompressionNone), Ok(offset), Ok(size)) => {
+                            if size != hunk_size {
+                                Err(IntegrityIssue::InvalidSize)
+                            } else {
+                                check_bounds(offset, size)
+                            }
+                        }
+                        (Ok(CompressionTypeV5::CompressionSelf), Ok(target), _) => {
+                            check_self(hunk_num as u64, target)
+                        }
+                        (Ok(CompressionTypeV5::CompressionParent), ..) => check_parent(),
+                        _ => Err(IntegrityIssue::InvalidEntry),
+                    }
+                }
+                Some(MapEntry::V5Uncompressed(entry)) => match entry.block_offset() {
+                    // unallocated hunks, or hunks that are stored in the parent.
+                    Ok(0) => Ok(()),
+                    Ok(offset) => check_bounds(offset, entry.block_size()),
+                    Err(_) => Err(IntegrityIssue::InvalidEntry),
+                },
+                Some(MapEntry::LegacyEntry(entry)) => match entry.hunk_type() {
+                    Ok(CompressionTypeLegacy::Compressed) => {
+                        if entry.block_size() == 0 {
+                            Err(IntegrityIssue::InvalidSize)
+                        } else {
+                            check_bounds(entry.block_offset(), entry.block_size())
+                        }
+                    }
+                    Ok(CompressionTypeLegacy::Uncompressed) => {
+                        if entry.block_size() != hunk_size {
+                            Err(IntegrityIssue::InvalidSize)
+                        } else {
+                            check_bounds(entry.block_offset(), entry.block_size())
+                        }
+                    }
+                    Ok(CompressionTypeLegacy::Mini) => Ok(()),
+                    Ok(CompressionTypeLegacy::SelfHunk) => {
+                        check_self(hunk_num as u64, entry.block_offset())
+                    }
+                    Ok(CompressionTypeLegacy::ParentHunk) => check_parent(),
+                    _ => Err(IntegrityIssue::InvalidEntry),
+                },
+            };
+
+            report.checked_hunks += 1;
+            if let Err(issue) = result {
+                report.issues.push((hunk_num as u32, issue));
+            }
+        }
+        report
+    }
+
+    /// Checks that the compressed V5 map was expanded correctly, by comparing the CRC16 of the
+    /// expanded map with the CRC16 stored with the compressed map.
+    ///
+    /// Maps that are read in full are checked when they are read, so this only does work for maps
+    /// read with [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy), which are expanded
+    /// in full to be checked. Uncompressed V5 maps and V1-4 maps do not store a CRC, and always pass.
+    ///
+    /// If an entry can not be decoded or the CRC does not match, returns [`Error::InvalidMap`](crate::Error::InvalidMap).
+    /// See [`Map::check_integrity`](crate::map::Map::check_integrity) to check the entries against the header.
+    pub fn self_check(&self) -> Result<()> {
+        match self {
+            Map::V5(RawMapV5(V5MapData::Lazy(lazy), ..)) => {
+                let raw_map = lazy.expand()?;
+                if raw_map.len() != lazy.types.len() * V5_COMPRESSED_MAP_ENTRY_SIZE
+                    || crate::block_hash::crc16(&raw_map) != lazy.map_crc
+                {
+                    return Err(Error::InvalidMap);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
         }
     }
 
     /// Gets an iterator over the entries of this hunk map.
-    pub fn iter(&self) -> MapEntries {
+    pub fn iter(&self) -> MapEntries<'_> {
         MapEntries { map: self, curr: 0 }
     }
 
+    /// Gets an iterator over the hunks of this map that refer to data in the parent CHD.
+    ///
+    /// Each item is a pair of the hunk number in this CHD, and the offset in units into the parent
+    /// CHD where the data of the hunk begins. The header must be the header of the CHD file this
+    /// map was read from.
+    pub fn parent_refs<'a>(&'a self, header: &Header) -> ParentRefs<'a> {
+        ParentRefs {
+            entries: self.iter().enumerate(),
+            units_per_hunk: (header.hunk_size() / header.unit_bytes().max(1)) as u64,
+            has_parent: header.has_parent(),
+        }
+    }
+
+    /// Returns the set of hunks in the parent CHD that are needed to read all hunks of this map.
+    ///
+    /// Parent references that do not begin on a hunk boundary require two consecutive parent hunks.
+    /// The header must be the header of the CHD file this map was read from, and the parent is
+    /// assumed to have the same hunk size as the child.
+    pub fn required_parent_hunks(&self, header: &Header) -> BTreeSet<u32> {
+        let units_per_hunk = (header.hunk_size() / header.unit_bytes().max(1)).max(1) as u64;
+        let mut hunks = BTreeSet::new();
+        for (_, parent_unit) in self.parent_refs(header) {
+            // out of range references saturate, so they are never found in the parent.
+            let parent_hunk = u32::try_from(parent_unit / units_per_hunk).unwrap_or(u32::MAX);
+            hunks.insert(parent_hunk);
+            if parent_unit % units_per_hunk != 0 {
+                hunks.insert(parent_hunk.saturating_add(1));
+            }
+        }
+        hunks
+    }
+
+    /// Returns the minimum length in bytes of a CHD file that contains the data of every hunk
+    /// stored in the file according to this map.
+    ///
+    /// Hunks that are stored in the parent CHD file, copied from other hunks, or stored within
+    /// the map entry itself do not contribute to the required length.
+    ///
+    /// Maps read with [`Map::try_read_map_lazy`](crate::map::Map::try_read_map_lazy) are decoded
+    /// up to their last entry without keeping the decoded entries. If an entry can not be decoded,
+    /// returns [`Error::InvalidMap`](crate::Error::InvalidMap).
+    pub fn required_length(&self) -> Result<u64> {
+        if let Map::V5(RawMapV5(V5MapData::Lazy(lazy), ..)) = self {
+            return lazy.required_length();
+        }
+        let required = self
+            .iter()
+            .filter_map(|entry| match entry {
+                MapEntry::V5Compressed(entry) => entry
+                    .prove_compressed()
+                    .map(|p| (p.block_offset(), p.block_size()))
+                    .or_else(|_| {
+                        entry
+                            .prove_uncompressed()
+                            .map(|p| (p.block_offset(), p.block_size()))
+                    })
+                    .ok(),
+                // uncompressed hunks at offset 0 are stored in the parent or are unallocated.
+                MapEntry::V5Uncompressed(entry) => entry
+                    .prove_uncompressed()
+                    .ok()
+                    .filter(|p| p.block_offset() != 0)
+                    .map(|p| (p.block_offset(), p.block_size())),
+                MapEntry::LegacyEntry(entry) => entry
+                    .prove_compressed()
+                    .map(|p| (p.block_offset(), p.block_size()))
+                    .or_else(|_| {
+                        entry
+                            .prove_uncompressed()
+                            .map(|p| (p.block_offset(), p.block_size()))
+                    })
+                    .ok(),
+            })
+            .map(|(offset, size)| offset + size as u64)
+            .max()
+            .unwrap_or(0);
+        Ok(required)
+    }
+
+    /// Writes a listing of every entry of this map to `out` in the given format, without reading
+    /// or decompressing any hunk data.
+    ///
+    /// Each hunk is listed with its hunk number, type, codec, offset, stored length and the CRC of
+    /// its data in hexadecimal. The offset of a hunk that copies another hunk is the number of the
+    /// hunk it copies, and the offset of a hunk that refers to the parent is the unit of the parent
+    /// it begins at, or the parent hunk in V1-4 maps. The offset of a legacy mini hunk is its data. Columns that do not apply to a
+    /// hunk are empty in CSV, and `null` in JSON.
+    ///
+    /// Entries that can not be decoded are listed with the type `invalid`, so that the map of a
+    /// partially corrupt CHD file can still be examined. The header must be the header of the CHD
+    /// file this map was read from.
+    pub fn export<W: Write>(
+        &self,
+        header: &Header,
+        format: MapExportFormat,
+        mut out: W,
+    ) -> std::io::Result<()> {
+        match format {
+            MapExportFormat::Csv => writeln!(out, "hunk,type,codec,offset,length,crc")?,
+            MapExportFormat::Json => writeln!(out, "[")?,
+        }
+
+        let len = self.len();
+        for (hunk_num, entry) in self.iter().enumerate() {
+            let entry = export_entry(header, entry);
+            let crc = entry.crc.map(|crc| match crc {
+                HunkCrc::Crc16(crc) => format!("{:04x}", crc),
+                HunkCrc::Crc32(crc) => format!("{:08x}", crc),
+            });
+            match format {
+                MapExportFormat::Csv => writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    hunk_num,
+                    entry.kind,
+                    entry.codec.unwrap_or_default(),
+                    entry.offset.map(|o| o.to_string()).unwrap_or_default(),
+                    entry.length.map(|l| l.to_string()).unwrap_or_default(),
+                    crc.unwrap_or_default()
+                )?,
+                MapExportFormat::Json => {
+                    fn json<T: std::fmt::Display>(value: Option<T>, quoted: bool) -> String {
+                        match value {
+                            Some(value) if quoted => format!("\"{}\"", value),
+                            Some(value) => value.to_string(),
+                            None => String::from("null"),
+                        }
+                    }
+                    writeln!(
+                        out,
+                        "  {{\"hunk\":{},\"type\":\"{}\",\"codec\":{},\"offset\":{},\"length\":{},\"crc\":{}}}{}",
+                        hunk_num,
+                        entry.kind,
+                        json(entry.codec, true),
+                        json(entry.offset, false),
+                        json(entry.length, false),
+                        json(crc, true),
+                        if hunk_num + 1 == len { "" } else { "," }
+                    )?
+                }
+            }
+        }
+
+        if format == MapExportFormat::Json {
+            writeln!(out, "]")?;
+        }
+        Ok(())
+    }
+
     /// Reads the hunk map from the provided stream given the parameters in the header,
     /// which must have the same stream provenance as the input header.
-    pub fn try_read_map<F: Read + Seek>(header: &Header, mut file: F) -> Result<Map> {
+    pub fn try_read_map<F: Read + Seek>(header: &Header, file: F) -> Result<Map> {
+        Map::try_read_map_inner(header, file, false, u64::MAX)
+    }
+
+    /// Reads the hunk map from the provided stream given the parameters in the header,
+    /// which must have the same stream provenance as the input header.
+    ///
+    /// Compressed V5 maps are not fully expanded when read. Instead, the entries of the map are
+    /// decoded on demand in blocks of hunks, which reduces the time to open and the memory used
+    /// by very large CHD files. Since the map is not fully expanded, the map checksum is
+    /// not verified, and entries that fail to decode are treated as missing.
+    ///
+    /// For all other maps, this is the same as [`Map::try_read_map`](crate::map::Map::try_read_map).
+    pub fn try_read_map_lazy<F: Read + Seek>(header: &Header, file: F) -> Result<Map> {
+        Map::try_read_map_inner(header, file, true, u64::MAX)
+    }
+
+    /// Reads the hunk map, failing with [`Error::OutOfMemory`](crate::Error::OutOfMemory) instead
+    /// of allocating more than `max_bytes` bytes to hold the map.
+    pub(crate) fn try_read_map_inner<F: Read + Seek>(
+        header: &Header,
+        mut file: F,
+        lazy: bool,
+        max_bytes: u64,
+    ) -> Result<Map> {
         match header {
-            Header::V5Header(v5) => {
-                Ok(Map::V5(read_map_v5(v5, &mut file, header.is_compressed())?))
-            }
+            Header::V5Header(v5) => Ok(Map::V5(read_map_v5(
+                v5,
+                &mut file,
+                header.is_compressed(),
+                lazy,
+                max_bytes,
+            )?)),
             Header::V3Header(_) | Header::V4Header(_) => {
                 Ok(Map::Legacy(RawMapLegacy(read_map_legacy::<
                     _,
                     V3_MAP_ENTRY_SIZE,
-                >(header, file)?)))
+                >(
+                    header, file, max_bytes
+                )?)))
             }
             Header::V2Header(_) | Header::V1Header(_) => {
                 Ok(Map::Legacy(RawMapLegacy(read_map_legacy::<
                     _,
                     V1_MAP_ENTRY_SIZE,
-                >(header, file)?)))
+                >(
+                    header, file, max_bytes
+                )?)))
             }
         }
     }
@@ -369,28 +1015,30 @@ impl Map {
 fn read_map_legacy<F: Read + Seek, const MAP_ENTRY_SIZE: usize>(
     header: &Header,
     mut file: F,
+    max_bytes: u64,
 ) -> Result<Vec<LegacyMapEntry>> {
     // Probably can express this better in the type system once const generics get a bit more stabilized.
     // Essentially we ensure at compile time that the only possible MAP_ENTRY_SIZEs are
     // V3_MAP_ENTRY_SIZE or V1_MAP_ENTRY_SIZE.
     const_assert!(MAP_ENTRY_SIZE: usize => V3_MAP_ENTRY_SIZE >=
         MAP_ENTRY_SIZE && (MAP_ENTRY_SIZE == V3_MAP_ENTRY_SIZE || MAP_ENTRY_SIZE == V1_MAP_ENTRY_SIZE));
+    if header.hunk_count() as u64 * MAP_ENTRY_SIZE as u64 > max_bytes {
+        return Err(Error::OutOfMemory);
+    }
     let mut map = Vec::new();
 
     let mut max_off = 0;
     let mut cookie = [0u8; MAP_ENTRY_SIZE];
-    file.seek(SeekFrom::Start(0))?;
 
-    // wrap into bufreader
-    let mut file = BufReader::new(file);
-    file.seek(SeekFrom::Start(header.len() as u64))?;
+    let mut io = ChdIo::new(&mut file);
+    let mut reader = BufReader::new(io.reader_at(header.map_offset())?);
 
     // SAFETY: V3_MAP_ENTRY_SIZE is strictly greater than V1_MAP_ENTRY_SIZE so it is safe to overallocate.
     // the read will instead read only to the first 8 bytes = u64 in the V1 case.
     // the alternative is to use a transmute but that's not ideal, or to wait for const_generics to mature.
     let mut entry_buf = [0u8; V3_MAP_ENTRY_SIZE];
     for _ in 0..header.hunk_count() {
-        file.read_exact(&mut entry_buf[0..MAP_ENTRY_SIZE])?;
+        reader.read_exact(&mut entry_buf[0..MAP_ENTRY_SIZE])?;
         let entry = match MAP_ENTRY_SIZE {
             V3_MAP_ENTRY_SIZE => read_map_entry_v3(&entry_buf)?,
             V1_MAP_ENTRY_SIZE => {
@@ -410,20 +1058,156 @@ fn read_map_legacy<F: Read + Seek, const MAP_ENTRY_SIZE: usize>(
     }
 
     // verify cookie
-    file.read_exact(&mut cookie)?;
+    reader.read_exact(&mut cookie)?;
+    drop(reader);
 
-    // need to confirm this is the same behaviour
-    if &cookie[..] < b"EndOfListCookie\0" {
+    // the cookie is truncated to the size of a map entry.
+    if cookie[..] != END_OF_LIST_COOKIE[..MAP_ENTRY_SIZE] {
         return Err(Error::InvalidFile);
     }
 
-    if max_off > file.seek(SeekFrom::End(0))? {
-        return Err(Error::InvalidFile);
+    let file_len = io.stream_len()?;
+    if max_off > file_len {
+        return Err(Error::Truncated {
+            expected: max_off,
+            actual: file_len,
+        });
     }
 
     Ok(map)
 }
 
+/// Returns the columns of a map entry listed by [`Map::export`](crate::map::Map::export).
+fn export_entry(header: &Header, entry: MapEntry) -> ExportedEntry {
+    let invalid = ExportedEntry {
+        kind: "invalid",
+        codec: None,
+        offset: None,
+        length: None,
+        crc: None,
+    };
+    // codecs are named by their FourCC, and legacy codecs by their name in chdman.
+    let codec_name = |slot: usize| match header {
+        Header::V5Header(h) => match h.compression[slot] {
+            0 => None,
+            c => Some(String::from_utf8_lossy(&c.to_be_bytes()).into_owned()),
+        },
+        Header::V1Header(h) | Header::V2Header(h) => legacy_codec_name(h.compression),
+        Header::V3Header(h) => legacy_codec_name(h.compression),
+        Header::V4Header(h) => legacy_codec_name(h.compression),
+    };
+
+    match entry {
+        MapEntry::V5Compressed(entry) => {
+            let (hunk_type, offset, length, crc) = match (
+                entry.hunk_type(),
+                entry.block_offset(),
+                entry.block_size(),
+                entry.hunk_crc(),
+            ) {
+                (Ok(hunk_type), Ok(offset), Ok(length), Ok(crc)) => {
+                    (hunk_type, offset, length, crc)
+                }
+                _ => return invalid,
+            };
+            match hunk_type {
+                ty @ (CompressionTypeV5::CompressionType0
+                | CompressionTypeV5::CompressionType1
+                | CompressionTypeV5::CompressionType2
+                | CompressionTypeV5::CompressionType3) => ExportedEntry {
+                    kind: "compressed",
+                    codec: codec_name(ty as usize),
+                    offset: Some(offset),
+                    length: Some(length),
+                    crc: Some(HunkCrc::Crc16(crc)),
+                },
+                CompressionTypeV5::CompressionNone => ExportedEntry {
+                    kind: "uncompressed",
+                    codec: None,
+                    offset: Some(offset),
+                    length: Some(length),
+                    crc: Some(HunkCrc::Crc16(crc)),
+                },
+                CompressionTypeV5::CompressionSelf => ExportedEntry {
+                    kind: "self",
+                    offset: Some(offset),
+                    ..invalid
+                },
+                CompressionTypeV5::CompressionParent => ExportedEntry {
+                    kind: "parent",
+                    offset: Some(offset),
+                    ..invalid
+                },
+                _ => invalid,
+            }
+        }
+        // uncompressed hunks at offset 0 are stored in the parent or are unallocated.
+        MapEntry::V5Uncompressed(entry) => match entry.block_offset() {
+            Ok(0) if header.has_parent() => ExportedEntry {
+                kind: "parent",
+                ..invalid
+            },
+            Ok(0) => ExportedEntry {
+                kind: "unallocated",
+                ..invalid
+            },
+            Ok(offset) => ExportedEntry {
+                kind: "uncompressed",
+                offset: Some(offset),
+                length: Some(entry.block_size()),
+                ..invalid
+            },
+            Err(_) => invalid,
+        },
+        MapEntry::LegacyEntry(entry) => {
+            let crc = entry.hunk_crc().map(HunkCrc::Crc32);
+            match entry.hunk_type() {
+                Ok(CompressionTypeLegacy::Compressed) => ExportedEntry {
+                    kind: "compressed",
+                    codec: codec_name(0),
+                    offset: Some(entry.block_offset()),
+                    length: Some(entry.block_size()),
+                    crc,
+                },
+                Ok(CompressionTypeLegacy::Uncompressed) => ExportedEntry {
+                    kind: "uncompressed",
+                    codec: None,
+                    offset: Some(entry.block_offset()),
+                    length: Some(entry.block_size()),
+                    crc,
+                },
+                Ok(CompressionTypeLegacy::Mini) => ExportedEntry {
+                    kind: "mini",
+                    offset: Some(entry.block_offset()),
+                    crc,
+                    ..invalid
+                },
+                Ok(CompressionTypeLegacy::SelfHunk) => ExportedEntry {
+                    kind: "self",
+                    offset: Some(entry.block_offset()),
+                    ..invalid
+                },
+                Ok(CompressionTypeLegacy::ParentHunk) => ExportedEntry {
+                    kind: "parent",
+                    offset: Some(entry.block_offset()),
+                    ..invalid
+                },
+                _ => invalid,
+            }
+        }
+    }
+}
+
+fn legacy_codec_name(compression: u32) -> Option<String> {
+    match compression {
+        0 => None,
+        1 => Some("zlib".into()),
+        2 => Some("zlib+".into()),
+        3 => Some("av".into()),
+        other => Some(other.to_string()),
+    }
+}
+
 #[inline]
 fn read_map_entry_v1(val: u64, hunk_bytes: u32) -> LegacyMapEntry {
     let length = (val >> 44) as u32;
@@ -458,129 +1242,289 @@ fn read_map_entry_v3(buf: &[u8; V3_MAP_ENTRY_SIZE]) -> Result<LegacyMapEntry> {
     })
 }
 
-fn read_map_v5<F: Read + Seek>(
-    header: &HeaderV5,
-    mut file: F,
-    is_compressed: bool,
-) -> Result<RawMapV5> {
-    let map_size = header.hunk_count as usize * header.map_entry_bytes as usize;
-    let mut raw_map = vec![0u8; map_size];
+/// The parameters of a compressed V5 map needed to decode map entries.
+#[derive(Copy, Clone)]
+struct V5MapParams {
+    length_bits: u8,
+    self_bits: u8,
+    parent_bits: u8,
+    hunk_bytes: u32,
+    unit_bytes: u32,
+}
 
-    if !is_compressed {
-        file.seek(SeekFrom::Start(header.map_offset))?;
-        file.read_exact(&mut raw_map[..])?;
-        return Ok(RawMapV5(raw_map, is_compressed, header.hunk_bytes));
+impl V5MapParams {
+    /// Validates that the entries decoded with these parameters fit in an expanded map entry,
+    /// which stores lengths in 24 bits and offsets in 48 bits.
+    fn validate(&self) -> Result<()> {
+        if self.length_bits > 24 || self.self_bits > 32 || self.parent_bits > 48 {
+            return Err(Error::InvalidMap);
+        }
+        Ok(())
     }
+}
 
-    // Read compressed map parameters.
-    file.seek(SeekFrom::Start(header.map_offset))?;
+/// The state of the V5 map decoder between map entries.
+#[derive(Copy, Clone)]
+struct V5MapDecodeState {
+    bit_pos: u64,
+    curr_off: u64,
+    last_self: u64,
+    last_parent: u64,
+}
 
-    let map_bytes = file.read_u32::<BigEndian>()?;
-    let first_offs = file.read_u48::<BigEndian>()?;
-    let map_crc = file.read_u16::<BigEndian>()?;
-    let length_bits = file.read_u8()?;
-    let self_bits = file.read_u8()?;
-    let parent_bits = file.read_u8()?;
+// The number of hunks decoded at a time by a lazy map.
+const LAZY_MAP_BLOCK_HUNKS: usize = 1024;
+
+/// A compressed V5 map that decodes its entries on demand.
+struct LazyMapV5 {
+    compressed: Vec<u8>,
+    types: Vec<u8>,
+    params: V5MapParams,
+    // The CRC16 of the expanded map, which is only verified by Map::self_check.
+    map_crc: u16,
+    state: Mutex<LazyMapV5State>,
+}
 
-    // Read the map data
-    let mut compressed: Vec<u8> = vec![0u8; map_bytes as usize];
-    file.seek(SeekFrom::Start(header.map_offset + 16))?;
-    file.read_exact(&mut compressed[..])?;
+struct LazyMapV5State {
+    // The decoder state at the start of each block that has been reached so far.
+    checkpoints: Vec<V5MapDecodeState>,
+    // The most recently decoded block.
+    block: Option<(usize, Vec<u8>)>,
+}
 
-    let mut bitstream = BitReader::new(&compressed[..]);
-    let decoder = HuffmanDecoder::<16, 8, { lookup_len::<8>() }>::from_tree_rle(&mut bitstream)?;
+impl LazyMapV5 {
+    /// Decodes the given block, returning the expanded entries and the state at the next block.
+    fn decode_block(
+        &self,
+        block: usize,
+        mut state: V5MapDecodeState,
+    ) -> Result<(Vec<u8>, V5MapDecodeState)> {
+        let first_hunk = block * LAZY_MAP_BLOCK_HUNKS;
+        let types =
+            &self.types[first_hunk..(first_hunk + LAZY_MAP_BLOCK_HUNKS).min(self.types.len())];
+        let mut entries = vec![0u8; types.len() * V5_COMPRESSED_MAP_ENTRY_SIZE];
+        for (entry, &hunk_type) in entries
+            .chunks_exact_mut(V5_COMPRESSED_MAP_ENTRY_SIZE)
+            .zip(types)
+        {
+            entry[0] = hunk_type;
+        }
+
+        let mut bitstream = BitReader::new(&self.compressed[..]);
+        bitstream.skip(state.bit_pos)?;
+        decode_map_entries_v5(
+            &self.params,
+            &mut state,
+            &mut bitstream,
+            first_hunk,
+            &mut entries,
+        )?;
+        state.bit_pos = bitstream.position();
+        Ok((entries, state))
+    }
+
+    /// Decodes the entry of the given hunk, decoding every block before it that has not been
+    /// reached yet.
+    ///
+    /// If the hunk does not exist, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange),
+    /// and if an entry can not be decoded, returns [`Error::InvalidMap`](crate::Error::InvalidMap).
+    fn get_entry(&self, hunk_num: usize) -> Result<[u8; V5_COMPRESSED_MAP_ENTRY_SIZE]> {
+        if hunk_num >= self.types.len() {
+            return Err(Error::HunkOutOfRange);
+        }
+
+        let block = hunk_num / LAZY_MAP_BLOCK_HUNKS;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = matches!(&state.block, Some((cached, _)) if *cached == block);
+        if !cached {
+            // entries depend on all previous entries, so decode up to the requested block.
+            while state.checkpoints.len() <= block {
+                let last = state.checkpoints.len() - 1;
+                let (_, next) = self
+                    .decode_block(last, state.checkpoints[last])
+                    .map_err(|_| Error::InvalidMap)?;
+                state.checkpoints.push(next);
+            }
+            let (entries, _) = self
+                .decode_block(block, state.checkpoints[block])
+                .map_err(|_| Error::InvalidMap)?;
+            state.block = Some((block, entries));
+        }
+
+        let (_, entries) = state.block.as_ref().ok_or(Error::InvalidMap)?;
+        let offset = (hunk_num % LAZY_MAP_BLOCK_HUNKS) * V5_COMPRESSED_MAP_ENTRY_SIZE;
+        <[u8; V5_COMPRESSED_MAP_ENTRY_SIZE]>::try_from(
+            &entries[offset..][..V5_COMPRESSED_MAP_ENTRY_SIZE],
+        )
+        .map_err(|_| Error::InvalidMap)
+    }
+
+    /// Returns the offset of the end of the last hunk stored in the file, or 0 if no hunk is stored
+    /// in the file.
+    ///
+    /// Every block that has not been reached yet is decoded to find the offset, but only the
+    /// decoder state at the start of each block is kept.
+    fn required_length(&self) -> Result<u64> {
+        let block_count = self.types.len().div_ceil(LAZY_MAP_BLOCK_HUNKS);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.checkpoints.len() <= block_count {
+            let last = state.checkpoints.len() - 1;
+            let (_, next) = self
+                .decode_block(last, state.checkpoints[last])
+                .map_err(|_| Error::InvalidMap)?;
+            state.checkpoints.push(next);
+        }
+        // stored hunks are laid out one after another starting at the offset of the first hunk.
+        let first_offs = state.checkpoints[0].curr_off;
+        let end = state.checkpoints[block_count].curr_off;
+        Ok(if end > first_offs { end } else { 0 })
+    }
+
+    /// Returns the number of bytes held by the compressed map and the decoded entries.
+    fn memory_footprint(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.compressed.capacity()
+            + self.types.capacity()
+            + state.checkpoints.capacity() * std::mem::size_of::<V5MapDecodeState>()
+            + state
+                .block
+                .as_ref()
+                .map_or(0, |(_, entries)| entries.capacity())
+    }
+
+    /// Expands all the entries of the map.
+    ///
+    /// If an entry can not be decoded, returns [`Error::InvalidMap`](crate::Error::InvalidMap).
+    fn expand(&self) -> Result<Vec<u8>> {
+        let block_count = self.types.len().div_ceil(LAZY_MAP_BLOCK_HUNKS);
+        let mut raw_map = Vec::with_capacity(self.types.len() * V5_COMPRESSED_MAP_ENTRY_SIZE);
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .checkpoints[0];
+        for block in 0..block_count {
+            let (entries, next) = self
+                .decode_block(block, state)
+                .map_err(|_| Error::InvalidMap)?;
+            raw_map.extend_from_slice(&entries);
+            state = next;
+        }
+        Ok(raw_map)
+    }
+}
+
+/// Decodes the Huffman-RLE encoded compression types of each hunk in a compressed V5 map.
+fn decode_map_types_v5(
+    bitstream: &mut BitReader,
+    hunk_count: usize,
+    mut set_type: impl FnMut(usize, u8),
+) -> Result<()> {
+    let decoder = HuffmanDecoder::<16, 8, { lookup_len::<8>() }>::from_tree_rle(bitstream)?;
 
     let mut rep_count = 0;
     let mut last_cmp = 0;
 
     // V5 Map data is Huffman-RLE encoded so we need to expand.
-    for map_slice in raw_map.chunks_exact_mut(V5_COMPRESSED_MAP_ENTRY_SIZE) {
+    for hunk_num in 0..hunk_count {
         if rep_count > 0 {
-            map_slice[0] = last_cmp;
+            set_type(hunk_num, last_cmp);
             rep_count -= 1;
         } else {
-            let val = decoder.decode_one(&mut bitstream)? as u8;
-            match CompressionTypeV5::from_u8(val).ok_or(Error::DecompressionError)? {
+            let val = decoder.decode_one(bitstream)? as u8;
+            match CompressionTypeV5::from_u8(val).ok_or(Error::InvalidMap)? {
                 CompressionTypeV5::CompressionRleSmall => {
                     // COMPRESSION_RLE_SMALL
-                    map_slice[0] = last_cmp;
-                    rep_count = 2 + decoder.decode_one(&mut bitstream)?;
+                    set_type(hunk_num, last_cmp);
+                    rep_count = 2 + decoder.decode_one(bitstream)?;
                 }
                 CompressionTypeV5::CompressionRleLarge => {
                     // COMPRESSION_RLE_LARGE
-                    map_slice[0] = last_cmp;
-                    rep_count = 2 + 16 + (decoder.decode_one(&mut bitstream)? << 4);
-                    rep_count += decoder.decode_one(&mut bitstream)?;
+                    set_type(hunk_num, last_cmp);
+                    rep_count = 2 + 16 + (decoder.decode_one(bitstream)? << 4);
+                    rep_count += decoder.decode_one(bitstream)?;
                 }
                 _ => {
-                    map_slice[0] = val;
+                    set_type(hunk_num, val);
                     last_cmp = val;
                 }
             }
         }
     }
+    Ok(())
+}
 
+/// Decodes the map entries of consecutive hunks starting at `first_hunk` into `raw_map`.
+/// The first byte of each entry of `raw_map` must already contain the compression type of the hunk.
+fn decode_map_entries_v5(
+    params: &V5MapParams,
+    state: &mut V5MapDecodeState,
+    bitstream: &mut BitReader,
+    first_hunk: usize,
+    raw_map: &mut [u8],
+) -> Result<()> {
     // Iterate and decompress each map entry.
-    let mut curr_off = first_offs;
-    let mut last_self = 0;
-    let mut last_parent = 0;
-
     for (hunk_num, map_slice) in raw_map
         .chunks_exact_mut(V5_COMPRESSED_MAP_ENTRY_SIZE)
         .enumerate()
     {
-        let mut off = curr_off;
+        let hunk_num = first_hunk + hunk_num;
+        let mut off = state.curr_off;
         let mut len: u32 = 0;
         let mut crc: u16 = 0;
 
-        match CompressionTypeV5::from_u8(map_slice[0]).ok_or(Error::DecompressionError)? {
+        match CompressionTypeV5::from_u8(map_slice[0]).ok_or(Error::InvalidMap)? {
             CompressionTypeV5::CompressionType0
             | CompressionTypeV5::CompressionType1
             | CompressionTypeV5::CompressionType2
             | CompressionTypeV5::CompressionType3 => {
-                len = bitstream.read_u32(length_bits)?;
-                curr_off += len as u64;
+                len = bitstream.read_u32(params.length_bits)?;
+                // compressed hunks are never empty.
+                if len == 0 {
+                    return Err(Error::InvalidMap);
+                }
+                state.curr_off += len as u64;
                 crc = bitstream.read_u32(16)? as u16;
             }
             CompressionTypeV5::CompressionNone => {
-                len = header.hunk_bytes;
-                curr_off += len as u64;
+                len = params.hunk_bytes;
+                state.curr_off += len as u64;
                 crc = bitstream.read_u32(16)? as u16;
             }
             CompressionTypeV5::CompressionSelf => {
-                off = bitstream.read_u64(self_bits)?;
-                last_self = off;
+                off = bitstream.read_u64(params.self_bits)?;
+                state.last_self = off;
             }
             CompressionTypeV5::CompressionParent => {
-                off = bitstream.read_u64(parent_bits)?;
-                last_parent = off;
+                off = bitstream.read_u64(params.parent_bits)?;
+                state.last_parent = off;
             }
 
             // Expand pseudo codecs to concrete.
             CompressionTypeV5::CompressionSelf1 => {
-                last_self += 1;
+                state.last_self += 1;
                 map_slice[0] = CompressionTypeV5::CompressionSelf as u8;
-                off = last_self;
+                off = state.last_self;
             }
             CompressionTypeV5::CompressionSelf0 => {
                 map_slice[0] = CompressionTypeV5::CompressionSelf as u8;
-                off = last_self;
+                off = state.last_self;
             }
             CompressionTypeV5::CompressionParentSelf => {
                 map_slice[0] = CompressionTypeV5::CompressionParent as u8;
-                off = ((hunk_num * header.hunk_bytes as usize) / header.unit_bytes as usize) as u64;
-                last_parent = off;
+                off = (hunk_num as u64 * params.hunk_bytes as u64) / params.unit_bytes as u64;
+                state.last_parent = off;
             }
             CompressionTypeV5::CompressionParent1 => {
-                last_parent += (header.hunk_bytes / header.unit_bytes) as u64;
+                state.last_parent += (params.hunk_bytes / params.unit_bytes) as u64;
                 map_slice[0] = CompressionTypeV5::CompressionParent as u8;
-                off = last_parent;
+                off = state.last_parent;
             }
             CompressionTypeV5::CompressionParent0 => {
                 map_slice[0] = CompressionTypeV5::CompressionParent as u8;
-                off = last_parent;
+                off = state.last_parent;
             }
-            _ => return Err(Error::DecompressionError),
+            _ => return Err(Error::InvalidMap),
         }
 
         let mut cursor = Cursor::new(&mut map_slice[1..]);
@@ -588,11 +1532,429 @@ fn read_map_v5<F: Read + Seek>(
         cursor.write_u48::<BigEndian>(off)?;
         cursor.write_u16::<BigEndian>(crc)?;
     }
+    Ok(())
+}
+
+fn read_map_v5<F: Read + Seek>(
+    header: &HeaderV5,
+    mut file: F,
+    is_compressed: bool,
+    lazy: bool,
+    max_bytes: u64,
+) -> Result<RawMapV5> {
+    let map_size = header.hunk_count as u64 * header.map_entry_bytes as u64;
+    // lazy maps only expand the hunk types up front.
+    let expanded_size = if lazy && is_compressed {
+        header.hunk_count as u64
+    } else {
+        map_size
+    };
+    if expanded_size > max_bytes {
+        return Err(Error::OutOfMemory);
+    }
+    // the map of a file with a very large number of hunks may not be addressable on 32-bit targets.
+    let map_size = usize::try_from(map_size).map_err(|_| Error::OutOfMemory)?;
+    let mut io = ChdIo::new(&mut file);
+
+    if !is_compressed {
+        let mut raw_map = vec![0u8; map_size];
+        io.read_exact_at(header.map_offset, &mut raw_map[..])?;
+        return Ok(RawMapV5(
+            V5MapData::Expanded(raw_map),
+            is_compressed,
+            header.hunk_bytes,
+        ));
+    }
+
+    // Read compressed map parameters.
+    let file = io.reader_at(header.map_offset)?;
+
+    let map_bytes = file.read_u32::<BigEndian>()?;
+    if expanded_size + map_bytes as u64 > max_bytes {
+        return Err(Error::OutOfMemory);
+    }
+    let first_offs = file.read_u48::<BigEndian>()?;
+    let map_crc = file.read_u16::<BigEndian>()?;
+    let params = V5MapParams {
+        length_bits: file.read_u8()?,
+        self_bits: file.read_u8()?,
+        parent_bits: file.read_u8()?,
+        hunk_bytes: header.hunk_bytes,
+        unit_bytes: header.unit_bytes,
+    };
+    params.validate()?;
+
+    // Read the map data
+    let mut compressed: Vec<u8> = vec![0u8; map_bytes as usize];
+    io.read_exact_at(header.map_offset + 16, &mut compressed[..])?;
+
+    let hunk_count = header.hunk_count as usize;
+    let mut bitstream = BitReader::new(&compressed[..]);
+
+    if lazy {
+        let mut types = vec![0u8; hunk_count];
+        decode_map_types_v5(&mut bitstream, hunk_count, |hunk_num, hunk_type| {
+            types[hunk_num] = hunk_type
+        })
+        .map_err(|_| Error::InvalidMap)?;
+        let start = V5MapDecodeState {
+            bit_pos: bitstream.position(),
+            curr_off: first_offs,
+            last_self: 0,
+            last_parent: 0,
+        };
+        return Ok(RawMapV5(
+            V5MapData::Lazy(Box::new(LazyMapV5 {
+                compressed,
+                types,
+                params,
+                map_crc,
+                state: Mutex::new(LazyMapV5State {
+                    checkpoints: vec![start],
+                    block: None,
+                }),
+            })),
+            is_compressed,
+            header.hunk_bytes,
+        ));
+    }
+
+    let mut raw_map = vec![0u8; map_size];
+    decode_map_types_v5(&mut bitstream, hunk_count, |hunk_num, hunk_type| {
+        raw_map[hunk_num * V5_COMPRESSED_MAP_ENTRY_SIZE] = hunk_type
+    })
+    .map_err(|_| Error::InvalidMap)?;
+
+    let mut state = V5MapDecodeState {
+        bit_pos: 0,
+        curr_off: first_offs,
+        last_self: 0,
+        last_parent: 0,
+    };
+    decode_map_entries_v5(&params, &mut state, &mut bitstream, 0, &mut raw_map)
+        .map_err(|_| Error::InvalidMap)?;
 
     // Verify map CRC
-    if crate::block_hash::CRC16.checksum(&raw_map[0..header.hunk_count as usize * 12]) != map_crc {
-        return Err(Error::DecompressionError);
+    if crate::block_hash::crc16(&raw_map[..map_size]) != map_crc {
+        return Err(Error::InvalidMap);
+    }
+
+    Ok(RawMapV5(
+        V5MapData::Expanded(raw_map),
+        is_compressed,
+        header.hunk_bytes,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::map::{CompressionTypeV5, Map, MapEntry, MapExportFormat};
+    use crate::testing::{v3_chd, v5_chd, BitWriter, LegacyHunk, V5Hunk};
+    use crate::{Chd, ChdOpenOptions, Error};
+    use std::io::Cursor;
+
+    /// Synthesizes a V5 CHD file, then patches its compressed map.
+    fn patched_chd(patch: impl FnOnce(&mut [u8])) -> Vec<u8> {
+        let hunks = [
+            V5Hunk::Zlib(vec![0x11; 512]),
+            V5Hunk::Uncompressed(vec![0x22; 512]),
+            V5Hunk::SelfHunk(0),
+        ];
+        let mut data = v5_chd(512, 512, &hunks, None).data;
+        let map_offset = u64::from_be_bytes(data[40..48].try_into().unwrap()) as usize;
+        patch(&mut data[map_offset..]);
+        data
+    }
+
+    fn open(data: &[u8], lazy: bool) -> crate::Result<Chd<Cursor<&[u8]>>> {
+        ChdOpenOptions::new().lazy_map(lazy).open(Cursor::new(data))
+    }
+
+    #[test]
+    fn display_test() {
+        let data = patched_chd(|_| {});
+        let chd = open(&data, false).expect("open");
+        let summary = chd.map().to_string();
+        assert!(summary.starts_with("V5 compressed hunk map, 3 hunks\n"));
+        assert!(summary.contains("         1    33.3%  Codec 0"));
+        assert!(summary.contains("         1    33.3%  Uncompressed"));
+        assert!(summary.contains("         1    33.3%  Copy from self"));
+        assert!(!summary.contains("Copy from parent"));
+
+        let data = v3_chd(512, &[LegacyHunk::Mini(0), LegacyHunk::Mini(1)], false).data;
+        let chd = open(&data, false).expect("open");
+        let summary = chd.map().to_string();
+        assert!(summary.starts_with("V1-4 hunk map, 2 hunks\n"));
+        assert!(summary.ends_with("         2   100.0%  Legacy 8-byte mini"));
+        assert!(format!("{:?}", chd.map()).contains("len: 2"));
+    }
+
+    #[test]
+    fn entry_accessors_test() {
+        let hunks = [
+            V5Hunk::Zlib(vec![0x11; 512]),
+            V5Hunk::Uncompressed(vec![0x22; 512]),
+            V5Hunk::SelfHunk(0),
+            V5Hunk::Parent(3),
+        ];
+        let data = v5_chd(512, 256, &hunks, Some([1; 20])).data;
+        let chd = open(&data, false).expect("open");
+        let entries: Vec<_> = chd
+            .map()
+            .iter()
+            .map(|entry| match entry {
+                MapEntry::V5Compressed(entry) => (
+                    entry.codec_index(),
+                    entry.self_target(),
+                    entry.parent_unit_offset(),
+                ),
+                _ => panic!("expected a compressed map entry"),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Some(0), None, None),
+                (None, None, None),
+                (None, Some(0), None),
+                (None, None, Some(3)),
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn invalid_map_test() {
+        let data = patched_chd(|_| {});
+        open(&data, false).expect("open");
+
+        // length bits that do not fit in a map entry.
+        let data = patched_chd(|map| map[12] = 25);
+        assert!(matches!(open(&data, false), Err(Error::InvalidMap)));
+        assert!(matches!(open(&data, true), Err(Error::InvalidMap)));
+        let data = patched_chd(|map| map[13] = 33);
+        assert!(matches!(open(&data, false), Err(Error::InvalidMap)));
+        let data = patched_chd(|map| map[14] = 49);
+        assert!(matches!(open(&data, false), Err(Error::InvalidMap)));
+
+        // compressed hunks with a length of 0.
+        let data = patched_chd(|map| map[12] = 0);
+        assert!(matches!(open(&data, false), Err(Error::InvalidMap)));
+
+        // the map ends before every entry is decoded.
+        let data = patched_chd(|map| map[0..4].copy_from_slice(&10u32.to_be_bytes()));
+        assert!(matches!(open(&data, false), Err(Error::InvalidMap)));
+
+        // the decoded map does not match its checksum.
+        let data = patched_chd(|map| map[10] ^= 0xff);
+        assert!(matches!(open(&data, false), Err(Error::InvalidMap)));
     }
 
-    Ok(RawMapV5(raw_map, is_compressed, header.hunk_bytes))
+    #[test]
+    fn pseudo_codec_test() {
+        const SELF: u8 = CompressionTypeV5::CompressionSelf as u8;
+        const PARENT: u8 = CompressionTypeV5::CompressionParent as u8;
+
+        // every code of the map type tree is 4 bits long, so each type is written as itself.
+        let mut bits = BitWriter::default();
+        for _ in 0..16 {
+            bits.write(4, 4);
+        }
+        // parent 100, parent 1, parent 0, parent self, self 0, self 1, self 0, a small run of
+        // 3 self 0, parent 1, then a large run of 19 parent 1.
+        for ty in [6, 13, 12, 11, 5, 10, 9, 7, 0, 13, 8, 0, 0] {
+            bits.write(4, ty);
+        }
+        bits.write(32, 100).write(32, 0);
+
+        // 512 byte hunks of 256 byte units, so each hunk is 2 units of the parent.
+        let mut expected = vec![(PARENT, 100), (PARENT, 102), (PARENT, 102), (PARENT, 6)];
+        expected.extend([
+            (SELF, 0),
+            (SELF, 1),
+            (SELF, 1),
+            (SELF, 1),
+            (SELF, 1),
+            (SELF, 1),
+        ]);
+        expected.extend((0..20).map(|n| (PARENT, 8 + 2 * n)));
+        let mut raw_map = Vec::new();
+        for &(ty, offset) in &expected {
+            raw_map.push(ty);
+            raw_map.extend_from_slice(&[0; 3]);
+            raw_map.extend_from_slice(&(offset as u64).to_be_bytes()[2..]);
+            raw_map.extend_from_slice(&[0; 2]);
+        }
+
+        // every hunk is a copy, so the map directly follows the header.
+        let hunks: Vec<_> = (0..expected.len()).map(|_| V5Hunk::SelfHunk(0)).collect();
+        let mut data = v5_chd(512, 256, &hunks, Some([1; 20])).data;
+        data.truncate(124);
+        let compressed_map = bits.bytes();
+        data.extend_from_slice(&(compressed_map.len() as u32).to_be_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&crate::block_hash::crc16(&raw_map).to_be_bytes());
+        data.extend_from_slice(&[24, 32, 32, 0]);
+        data.extend_from_slice(&compressed_map);
+
+        for lazy in [false, true] {
+            let chd = open(&data, lazy).expect("open");
+            chd.map().self_check().expect("self check");
+            let entries: Vec<_> = chd
+                .map()
+                .iter()
+                .map(|entry| match entry {
+                    MapEntry::V5Compressed(entry) => (
+                        entry.hunk_type().expect("type") as u8,
+                        entry.block_offset().expect("offset") as i32,
+                    ),
+                    _ => panic!("not a compressed map entry"),
+                })
+                .collect();
+            assert_eq!(expected, entries);
+        }
+
+        // a map that does not match its checksum can only be opened lazily, and fails the self check.
+        let crc_offset = 124 + 10;
+        data[crc_offset] ^= 0xff;
+        assert!(matches!(open(&data, false), Err(Error::InvalidMap)));
+        let chd = open(&data, true).expect("open");
+        assert!(matches!(chd.map().self_check(), Err(Error::InvalidMap)));
+    }
+
+    #[test]
+    fn lazy_map_entries_test() {
+        // more hunks than a single block of a lazy map, with every kind of stored entry.
+        let hunks: Vec<_> = (0..2500u32)
+            .map(|i| match i % 4 {
+                0 => V5Hunk::Zlib(vec![i as u8; 512]),
+                1 => V5Hunk::Uncompressed(vec![i as u8; 512]),
+                2 => V5Hunk::SelfHunk(i / 2),
+                _ => V5Hunk::Parent(i as u64 * 2),
+            })
+            .collect();
+        let data = v5_chd(512, 256, &hunks, Some([1; 20])).data;
+        let eager = open(&data, false).expect("open");
+        let lazy = open(&data, true).expect("open lazy");
+        lazy.map().self_check().expect("self check");
+
+        let fields = |entry: MapEntry| match entry {
+            MapEntry::V5Compressed(entry) => (
+                entry.hunk_type().expect("type") as u8,
+                entry.block_offset().expect("offset"),
+                entry.block_size().expect("size"),
+                entry.hunk_crc().expect("crc"),
+            ),
+            _ => panic!("not a compressed map entry"),
+        };
+        // entries are decoded out of order, so that earlier blocks are decoded again.
+        for hunk_num in (0..hunks.len()).rev().chain(0..hunks.len()) {
+            assert_eq!(
+                fields(eager.map().try_get_entry(hunk_num).expect("eager")),
+                fields(lazy.map().try_get_entry(hunk_num).expect("lazy")),
+                "hunk {}",
+                hunk_num
+            );
+        }
+        assert!(matches!(
+            lazy.map().try_get_entry(hunks.len()),
+            Err(Error::HunkOutOfRange)
+        ));
+
+        match (eager.map(), lazy.map()) {
+            (Map::V5(eager), Map::V5(lazy)) => {
+                assert_eq!(Vec::from(eager), lazy.try_expand().expect("lazy"))
+            }
+            _ => panic!("not a V5 map"),
+        }
+    }
+
+    #[test]
+    fn lazy_map_corrupt_test() {
+        // the hunk types fit in the first 10 bytes of the map, but the entries do not.
+        let data = patched_chd(|map| map[0..4].copy_from_slice(&10u32.to_be_bytes()));
+        // the entries are not decoded when the file is opened.
+        let mut chd = open(&data, true).expect("open");
+        assert!(matches!(
+            chd.map().required_length(),
+            Err(Error::InvalidMap)
+        ));
+        assert!(matches!(chd.map().try_get_entry(0), Err(Error::InvalidMap)));
+        assert!(chd.map().get_entry(0).is_none());
+        assert!(matches!(chd.map().self_check(), Err(Error::InvalidMap)));
+        match chd.map() {
+            Map::V5(map) => assert!(matches!(map.try_expand(), Err(Error::InvalidMap))),
+            _ => panic!("not a V5 map"),
+        }
+
+        let mut buf = chd.get_hunksized_buffer();
+        let mut cmp = Vec::new();
+        assert!(matches!(
+            chd.hunk(2)
+                .and_then(|mut h| h.read_hunk_in(&mut cmp, &mut buf)),
+            Err(Error::InvalidMap)
+        ));
+    }
+
+    fn export(chd: &Chd<Cursor<&[u8]>>, format: MapExportFormat) -> String {
+        let mut out = Vec::new();
+        chd.map()
+            .export(chd.header(), format, &mut out)
+            .expect("export");
+        String::from_utf8(out).expect("utf8")
+    }
+
+    #[test]
+    fn export_test() {
+        let hunks = [
+            V5Hunk::Zlib(vec![0x11; 512]),
+            V5Hunk::Uncompressed(vec![0x22; 512]),
+            V5Hunk::SelfHunk(0),
+            V5Hunk::Parent(3),
+        ];
+        let chd = v5_chd(512, 512, &hunks, Some([1; 20]));
+        let stored = chd.hunk_offsets[1];
+        let chd = ChdOpenOptions::new()
+            .open(Cursor::new(&chd.data[..]))
+            .expect("open");
+
+        let csv = export(&chd, MapExportFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(5, lines.len());
+        assert_eq!("hunk,type,codec,offset,length,crc", lines[0]);
+        assert!(lines[1].starts_with("0,compressed,zlib,"));
+        assert_eq!(
+            format!(
+                "1,uncompressed,,{},512,{:04x}",
+                stored,
+                crate::block_hash::crc16(&[0x22; 512])
+            ),
+            lines[2]
+        );
+        assert_eq!("2,self,,0,,", lines[3]);
+        assert_eq!("3,parent,,3,,", lines[4]);
+
+        let json = export(&chd, MapExportFormat::Json);
+        let lines: Vec<&str> = json.lines().collect();
+        assert_eq!(6, lines.len());
+        assert_eq!("[", lines[0]);
+        assert_eq!(
+            r#"  {"hunk":2,"type":"self","codec":null,"offset":0,"length":null,"crc":null},"#,
+            lines[3]
+        );
+        assert_eq!(
+            r#"  {"hunk":3,"type":"parent","codec":null,"offset":3,"length":null,"crc":null}"#,
+            lines[4]
+        );
+        assert_eq!("]", lines[5]);
+
+        // corrupt entries are still listed.
+        let chd = v3_chd(512, &[LegacyHunk::Mini(7), LegacyHunk::Raw(0)], false);
+        let chd = ChdOpenOptions::new()
+            .open(Cursor::new(&chd.data[..]))
+            .expect("open");
+        let csv = export(&chd, MapExportFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[1].starts_with("0,mini,,7,,"));
+        assert_eq!("1,invalid,,,,", lines[2]);
+    }
 }
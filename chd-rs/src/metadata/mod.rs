@@ -0,0 +1,780 @@
+//! Types and methods relating to metadata stored in a CHD file.
+//!
+//! Parsers for the contents of well-known metadata entries are provided in [`parse`](crate::metadata::parse).
+
+use crate::error::{Error, Result};
+use crate::io::ChdIo;
+use crate::{make_tag, Chd, Tag};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{Cursor, Read, Seek};
+
+pub mod parse;
+
+const METADATA_HEADER_SIZE: usize = 16;
+// the length of a metadata entry is stored in the low 24 bits of its length field.
+const METADATA_MAX_LENGTH: usize = 0x00ffffff;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+/// A list of well-known metadata tags.
+#[derive(FromPrimitive, Copy, Clone)]
+#[repr(u32)]
+pub enum KnownMetadata {
+    /// Wildcard for search (0)
+    Wildcard = 0,
+    /// Hard Disk (`GDDD`)
+    HardDisk = make_tag(b"GDDD"),
+    /// Hard Disk Identifier (`IDNT`)
+    HardDiskIdent = make_tag(b"IDNT"),
+    /// Hard Disk Key (`KEY `)
+    HardDiskKey = make_tag(b"KEY "),
+    /// PCMCIA Card Information (`CIS `)
+    PcmciaCIS = make_tag(b"CIS "),
+    /// Legacy CD-ROM metadata (`CHCD`)
+    CdRomOld = make_tag(b"CHCD"),
+    /// CD-ROM track metadata (`CHTR`)
+    CdRomTrack = make_tag(b"CHTR"),
+    /// CD-ROM track metadata (`CHT2`)
+    CdRomTrack2 = make_tag(b"CHT2"),
+    /// Legacy GD-ROM metadata (`CHGT`)
+    GdRomOld = make_tag(b"CHGT"),
+    /// GD-ROM track metadata (`CHGD`)
+    GdRomTrack = make_tag(b"CHGD"),
+    /// DVD metadata (`DVD `)
+    ///
+    /// MAME writes a single empty entry with this tag to mark that a CHD file contains a DVD.
+    Dvd = make_tag(b"DVD "),
+    /// A/V metadata (`AVAV`)
+    AudioVideo = make_tag(b"AVAV"),
+    /// LaserDisc A/V metadata (`AVLD`)
+    AudioVideoLaserDisc = make_tag(b"AVLD"),
+    /// The application that created the CHD file (`CRTR`)
+    ///
+    /// This is an extension of chd-rs that is not written by chdman.
+    /// See [`Creator`](crate::metadata::parse::Creator).
+    Creator = make_tag(b"CRTR"),
+}
+
+impl KnownMetadata {
+    /// Returns whether a given tag indicates that the CHD contains CDROM data.
+    pub fn is_cdrom(tag: impl Into<Tag>) -> bool {
+        if let Some(tag) = FromPrimitive::from_u32(tag.into().to_u32()) {
+            return matches!(
+                tag,
+                KnownMetadata::CdRomOld
+                    | KnownMetadata::CdRomTrack
+                    | KnownMetadata::CdRomTrack2
+                    | KnownMetadata::GdRomOld
+                    | KnownMetadata::GdRomTrack
+            );
+        }
+        false
+    }
+
+    /// Returns whether a given tag indicates that the CHD contains DVD data.
+    pub fn is_dvd(tag: impl Into<Tag>) -> bool {
+        matches!(
+            FromPrimitive::from_u32(tag.into().to_u32()),
+            Some(KnownMetadata::Dvd)
+        )
+    }
+}
+
+/// Trait for structs that contain or represent tagged metadata.
+pub trait MetadataTag {
+    /// Returns the FourCC metatag that this struct represents or refers to, as stored in the CHD file.
+    fn metatag(&self) -> u32;
+
+    /// Returns the FourCC metatag that this struct represents or refers to as a [`Tag`](crate::Tag).
+    fn tag(&self) -> Tag {
+        Tag::from(self.metatag())
+    }
+}
+
+impl MetadataTag for KnownMetadata {
+    fn metatag(&self) -> u32 {
+        *self as u32
+    }
+}
+
+/// A complete CHD metadata entry with contents read into memory.
+///
+/// The [`Display`](std::fmt::Display) implementation prints the tag, index and length of the entry,
+/// followed by a preview of its contents as ASCII text.
+pub struct Metadata {
+    /// The FourCC metadata tag, as stored in the CHD file.
+    ///
+    /// Use [`MetadataTag::tag`](crate::metadata::MetadataTag::tag) to get it as a [`Tag`](crate::Tag).
+    pub metatag: u32,
+    /// The contents of this metadata entry.
+    pub value: Vec<u8>,
+    /// The flags of this metadata entry.
+    pub flags: u8,
+    /// The index of this metadata entry relative to the beginning of the metadata section.
+    pub index: u32,
+    /// The length of the contents of this metadata entry, without the flags stored in the top byte
+    /// of the length field.
+    pub length: u32,
+}
+
+impl MetadataTag for Metadata {
+    fn metatag(&self) -> u32 {
+        self.metatag
+    }
+}
+
+/// The number of bytes of the contents shown by the `Debug` implementation of [`Metadata`](crate::metadata::Metadata).
+const DEBUG_PREVIEW_LEN: usize = 32;
+/// The number of bytes of the contents shown by the `Display` implementation of [`Metadata`](crate::metadata::Metadata).
+const DISPLAY_PREVIEW_LEN: usize = 64;
+
+/// Formats a prefix of some bytes as hex.
+struct HexPreview<'a>(&'a [u8]);
+
+impl Debug for HexPreview<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0.iter().take(DEBUG_PREVIEW_LEN) {
+            write!(f, "{:02x}", byte)?;
+        }
+        if self.0.len() > DEBUG_PREVIEW_LEN {
+            write!(f, "...")?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Metadata {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metadata")
+            .field("metatag", &self.tag())
+            .field("flags", &self.flags)
+            .field("index", &self.index)
+            .field("length", &self.length)
+            .field("value", &HexPreview(&self.value))
+            .finish()
+    }
+}
+
+impl Display for Metadata {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Tag={:?}  Index={}  Length={} bytes: ",
+            self.tag(),
+            self.index,
+            self.length
+        )?;
+        // Most metadata is NUL-terminated text.
+        let text = self.value.strip_suffix(&[0]).unwrap_or(&self.value);
+        for &byte in text.iter().take(DISPLAY_PREVIEW_LEN) {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                write!(f, "{}", byte as char)?;
+            } else {
+                write!(f, ".")?;
+            }
+        }
+        if text.len() > DISPLAY_PREVIEW_LEN {
+            write!(f, "...")?;
+        }
+        Ok(())
+    }
+}
+
+/// A reference to a metadata entry within the CHD file.
+#[derive(Clone)]
+pub struct MetadataRef {
+    offset: u64,
+    length: u32,
+    flags: u8,
+    index: u32,
+    metatag: u32,
+}
+
+impl MetadataRef {
+    /// Returns the length of the contents of this metadata entry.
+    ///
+    /// This is the low 24 bits of the length field of the entry, so the contents of a metadata entry
+    /// can be at most 16 MiB long.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        self.length
+    }
+
+    /// Returns the flags of this metadata entry, stored in the top byte of the length field.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the raw length field of this metadata entry, with the flags in the top byte
+    /// and the length of the contents in the low 24 bits.
+    pub fn raw_length(&self) -> u32 {
+        (self.flags as u32) << 24 | self.length
+    }
+
+    /// Reads the contents of the metadata into `buf` from the input stream without allocating,
+    /// returning the number of bytes read. The `MetadataRef` must have the same provenance as the
+    /// input stream for a successful read.
+    ///
+    /// Like `chd_get_metadata`, if `buf` is shorter than the contents, only the first `buf.len()`
+    /// bytes of the contents are read. Compare the number of bytes read with [`len`](crate::metadata::MetadataRef::len)
+    /// to detect this.
+    pub fn read_into<F: Read + Seek>(&self, file: &mut F, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(self.length as usize);
+        ChdIo::new(file)
+            .read_exact_at(self.offset + METADATA_HEADER_SIZE as u64, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    /// Read the contents of the metadata from the input stream. The `ChdMetadataRef` must have
+    /// the same provenance as the input stream for a successful read.
+    pub fn read<F: Read + Seek>(&self, file: &mut F) -> Result<Metadata> {
+        let mut buf = vec![0u8; self.length as usize];
+        self.read_into(file, &mut buf)?;
+        Ok(Metadata {
+            metatag: self.metatag,
+            value: buf,
+            flags: self.flags,
+            index: self.index,
+            length: self.length,
+        })
+    }
+}
+
+impl MetadataTag for MetadataRef {
+    #[inline(always)]
+    fn metatag(&self) -> u32 {
+        self.metatag
+    }
+}
+
+/// An error reading a metadata entry with [`MetadataRefs::try_into_vec`](crate::metadata::MetadataRefs::try_into_vec),
+/// with the location of the entry that could not be read.
+#[derive(Debug)]
+pub struct MetadataReadError {
+    /// The position of the entry in the metadata chain, starting from 0.
+    pub position: usize,
+    /// The offset of the entry in the CHD file.
+    pub offset: u64,
+    /// The tag of the entry, if its header was read successfully.
+    pub metatag: Option<u32>,
+    /// The error that occurred reading the entry.
+    pub error: Error,
+}
+
+impl Display for MetadataReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "metadata entry {} at offset {:#x}",
+            self.position, self.offset
+        )?;
+        if let Some(metatag) = self.metatag {
+            write!(f, " ({})", String::from_utf8_lossy(&metatag.to_be_bytes()))?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+impl std::error::Error for MetadataReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<MetadataReadError> for Error {
+    fn from(err: MetadataReadError) -> Self {
+        err.error
+    }
+}
+
+/// An iterator over references to the metadata entries of a CHD file.
+/// If `unstable_lending_iterators` is enabled, metadata can be
+/// more ergonomically iterated over with [`MetadataEntries`](crate::iter::MetadataEntries).
+///
+/// If the chain of metadata entries is invalid, such as when an entry can not be read, or
+/// the chain loops back to an entry that was already visited, iteration ends early and the
+/// error can be retrieved with [`MetadataRefs::take_error`](crate::metadata::MetadataRefs::take_error).
+/// Entries whose contents extend past the end of the file end iteration with
+/// [`Error::InvalidMetadataSize`](crate::Error::InvalidMetadataSize).
+pub struct MetadataRefs<'a, F: Read + Seek + 'a> {
+    pub(crate) file: &'a mut F,
+    curr_offset: u64,
+    curr: Option<MetadataRef>,
+    // Just use a tuple because we rarely have more than 2 or 3 types of tag.
+    indices: Vec<(u32, u32)>,
+    visited: HashSet<u64>,
+    error: Option<Error>,
+    // the length of the file, found when the first entry is read.
+    file_len: Option<u64>,
+    // the remaining entries of a chain that was already read, which are returned without reading the file.
+    cached: Option<&'a [MetadataRef]>,
+}
+
+impl<'a, F: Read + Seek + 'a> MetadataRefs<'a, F> {
+    pub(crate) fn from_stream(file: &'a mut F, initial_offset: u64) -> Self {
+        MetadataRefs {
+            file,
+            curr_offset: initial_offset,
+            curr: None,
+            indices: Vec::new(),
+            visited: HashSet::new(),
+            error: None,
+            file_len: None,
+            cached: None,
+        }
+    }
+
+    /// Creates an iterator over the entries of a metadata chain that was already read from `file`.
+    pub(crate) fn from_cache(file: &'a mut F, cached: &'a [MetadataRef]) -> Self {
+        MetadataRefs {
+            file,
+            curr_offset: cached.first().map_or(0, |meta_ref| meta_ref.offset),
+            curr: None,
+            indices: Vec::new(),
+            visited: HashSet::new(),
+            error: None,
+            file_len: None,
+            cached: Some(cached),
+        }
+    }
+
+    pub(crate) fn dead(file: &'a mut F) -> Self {
+        MetadataRefs {
+            file,
+            curr_offset: 0,
+            curr: None,
+            indices: Vec::new(),
+            visited: HashSet::new(),
+            error: None,
+            file_len: None,
+            cached: None,
+        }
+    }
+
+    /// Takes the error that ended iteration early, if the chain of metadata entries is invalid.
+    ///
+    /// If the chain loops back to an entry that was already visited, the error is
+    /// [`Error::InvalidMetadata`](crate::Error::InvalidMetadata). Returns `None` if iteration
+    /// has not ended early.
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    /// Reads the contents of every remaining metadata entry, in chain order.
+    ///
+    /// Each entry is read as soon as it is found in the chain. If the chain of metadata entries is
+    /// invalid, or the contents of an entry can not be read, returns a
+    /// [`MetadataReadError`](crate::metadata::MetadataReadError) with the position and offset of the
+    /// entry that could not be read.
+    pub fn try_into_vec(mut self) -> std::result::Result<Vec<Metadata>, MetadataReadError> {
+        let mut metadata = Vec::new();
+        loop {
+            let position = metadata.len();
+            let offset = self.curr_offset;
+            match self.next() {
+                Some(meta_ref) => {
+                    let meta = meta_ref
+                        .read(self.file)
+                        .map_err(|error| MetadataReadError {
+                            position,
+                            offset,
+                            metatag: Some(meta_ref.metatag),
+                            error,
+                        })?;
+                    metadata.push(meta);
+                }
+                None => {
+                    return match self.take_error() {
+                        Some(error) => Err(MetadataReadError {
+                            position,
+                            offset,
+                            metatag: None,
+                            error,
+                        }),
+                        None => Ok(metadata),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the metadata entry with the given tag at `index` among the remaining entries with that tag.
+    ///
+    /// If `tag` is [`KnownMetadata::Wildcard`](crate::metadata::KnownMetadata::Wildcard), `index`
+    /// counts every remaining metadata entry regardless of its tag. If there is no such entry, returns
+    /// [`Error::MetadataNotFound`](crate::Error::MetadataNotFound), or the error that ended iteration early.
+    pub fn find_tag(mut self, tag: impl Into<Tag>, index: u32) -> Result<Metadata> {
+        let tag = tag.into().to_u32();
+        let wildcard = tag == KnownMetadata::Wildcard.metatag();
+        let mut remaining = index;
+        let found = self.find(|meta_ref| {
+            if !wildcard && meta_ref.metatag != tag {
+                return false;
+            }
+            if remaining == 0 {
+                return true;
+            }
+            remaining -= 1;
+            false
+        });
+
+        match (found, self.take_error()) {
+            (Some(meta_ref), _) => meta_ref.read(self.file),
+            (None, Some(err)) => Err(err),
+            (None, None) => Err(Error::MetadataNotFound),
+        }
+    }
+}
+
+/// Reads every metadata entry of the CHD file, in chain order.
+///
+/// The entries can be written back as a metadata chain with [`serialize_chain`](crate::metadata::serialize_chain).
+/// If the chain of metadata entries is invalid, returns the error that ended iteration.
+pub fn dump_all<F: Read + Seek, P: Read + Seek>(chd: &mut Chd<F, P>) -> Result<Vec<Metadata>> {
+    chd.metadata_refs().try_into()
+}
+
+/// Serializes metadata entries into a metadata chain, to be embedded in a CHD file at `offset`.
+///
+/// Each entry is written as its 16 byte header of tag, flags, length and the absolute offset of the
+/// next entry, followed by its contents. Entries are laid out contiguously in the given order, and the
+/// last entry has a next offset of 0. Reading the chain back at `offset` yields the same tags, flags
+/// and contents, such as for restoring metadata read with [`dump_all`](crate::metadata::dump_all).
+///
+/// The length written for each entry is the length of its contents. If the contents of an entry are
+/// longer than the 16 MiB that the length field can represent, returns
+/// [`Error::InvalidMetadataSize`](crate::Error::InvalidMetadataSize).
+pub fn serialize_chain(metadata: &[Metadata], offset: u64) -> Result<Vec<u8>> {
+    let total = metadata
+        .iter()
+        .map(|meta| METADATA_HEADER_SIZE + meta.value.len())
+        .sum();
+    let mut chain = Vec::with_capacity(total);
+    let mut next = offset;
+    for (i, meta) in metadata.iter().enumerate() {
+        if meta.value.len() > METADATA_MAX_LENGTH {
+            return Err(Error::InvalidMetadataSize);
+        }
+        next += (METADATA_HEADER_SIZE + meta.value.len()) as u64;
+        let next_offset = if i + 1 == metadata.len() { 0 } else { next };
+        chain.write_u32::<BigEndian>(meta.metatag)?;
+        chain.write_u32::<BigEndian>((meta.flags as u32) << 24 | meta.value.len() as u32)?;
+        chain.write_u64::<BigEndian>(next_offset)?;
+        chain.extend_from_slice(&meta.value);
+    }
+    Ok(chain)
+}
+
+impl<'a, F: Read + Seek + 'a> TryFrom<MetadataRefs<'a, F>> for Vec<Metadata> {
+    type Error = Error;
+
+    fn try_from(value: MetadataRefs<'a, F>) -> std::result::Result<Self, Self::Error> {
+        value.try_into_vec().map_err(Error::from)
+    }
+}
+
+impl<'a, F: Read + Seek + 'a> Iterator for MetadataRefs<'a, F> {
+    // really need GATs to do this properly...
+    type Item = MetadataRef;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(cached) = self.cached {
+            let (next, rest) = cached.split_first()?;
+            self.cached = Some(rest);
+            self.curr_offset = rest.first().map_or(0, |meta_ref| meta_ref.offset);
+            self.curr = Some(next.clone());
+            return Some(next.clone());
+        }
+
+        if self.curr_offset == 0 {
+            return None;
+        }
+
+        fn next_inner<'a, F: Read + Seek + 'a>(s: &mut MetadataRefs<'a, F>) -> Result<MetadataRef> {
+            // a corrupt chain may loop forever if the next offset points to a visited entry.
+            if !s.visited.insert(s.curr_offset) {
+                return Err(Error::InvalidMetadata);
+            }
+
+            let mut raw_header: [u8; METADATA_HEADER_SIZE] = [0; METADATA_HEADER_SIZE];
+            let count = ChdIo::new(s.file).read_at(s.curr_offset, &mut raw_header)?;
+            if count != METADATA_HEADER_SIZE {
+                return Err(Error::MetadataNotFound);
+            }
+            let mut cursor = Cursor::new(raw_header);
+
+            // extract data
+            let metatag = cursor.read_u32::<BigEndian>()?;
+            let length = cursor.read_u32::<BigEndian>()?;
+            let next = cursor.read_u64::<BigEndian>()?;
+
+            let flags = length >> 24;
+            // mask off flags
+            let length = length & METADATA_MAX_LENGTH as u32;
+
+            // the contents of the entry must fit in the file.
+            let file_len = match s.file_len {
+                Some(file_len) => file_len,
+                None => *s.file_len.insert(ChdIo::new(s.file).stream_len()?),
+            };
+            if s.curr_offset
+                .saturating_add((METADATA_HEADER_SIZE as u64) + length as u64)
+                > file_len
+            {
+                return Err(Error::InvalidMetadataSize);
+            }
+
+            let mut index = 0;
+
+            for indice in s.indices.iter_mut() {
+                if indice.0 == metatag {
+                    index = indice.1;
+                    // increment current index
+                    indice.1 += 1;
+                    break;
+                }
+            }
+
+            if index == 0 {
+                s.indices.push((metatag, 1))
+            }
+
+            let new = MetadataRef {
+                offset: s.curr_offset,
+                length,
+                metatag,
+                flags: flags as u8,
+                index,
+            };
+
+            s.curr_offset = next;
+            s.curr = Some(new.clone());
+            Ok(new)
+        }
+        match next_inner(self) {
+            Ok(next) => Some(next),
+            Err(err) => {
+                self.curr_offset = 0;
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::make_tag;
+    use crate::metadata::{
+        serialize_chain, KnownMetadata, Metadata, MetadataRefs, MetadataTag, METADATA_HEADER_SIZE,
+    };
+    use crate::Error;
+    use std::io::Cursor;
+
+    fn metadata_entry(tag: &[u8; 4], value: &[u8], next: u64) -> Vec<u8> {
+        let mut entry = Vec::with_capacity(METADATA_HEADER_SIZE + value.len());
+        entry.extend_from_slice(&make_tag(tag).to_be_bytes());
+        entry.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        entry.extend_from_slice(&next.to_be_bytes());
+        entry.extend_from_slice(value);
+        entry
+    }
+
+    #[test]
+    fn known_metadata_kind_test() {
+        assert_eq!(make_tag(b"DVD "), KnownMetadata::Dvd.metatag());
+        assert!(KnownMetadata::is_dvd(make_tag(b"DVD ")));
+        assert!(!KnownMetadata::is_dvd(KnownMetadata::CdRomTrack2.metatag()));
+        assert!(!KnownMetadata::is_dvd(0x12345678));
+        assert!(KnownMetadata::is_cdrom(KnownMetadata::GdRomTrack.metatag()));
+        assert!(!KnownMetadata::is_cdrom(KnownMetadata::Dvd.metatag()));
+        assert!(!KnownMetadata::is_cdrom(0x12345678));
+    }
+
+    #[test]
+    fn metadata_display_test() {
+        let metadata = Metadata {
+            metatag: KnownMetadata::HardDisk.metatag(),
+            value: b"CYLS:10,HEADS:2,SECS:32,BPS:512\0".to_vec(),
+            flags: 1,
+            index: 0,
+            length: 32,
+        };
+        assert_eq!(
+            "Tag='GDDD'  Index=0  Length=32 bytes: CYLS:10,HEADS:2,SECS:32,BPS:512",
+            metadata.to_string()
+        );
+        assert_eq!(
+            "Metadata { metatag: 'GDDD', flags: 1, index: 0, length: 32, value: \
+             43594c533a31302c48454144533a322c534543533a33322c4250533a35313200 }",
+            format!("{:?}", metadata)
+        );
+
+        let metadata = Metadata {
+            metatag: 0x01020304,
+            value: vec![0xff; 100],
+            flags: 0,
+            index: 2,
+            length: 100,
+        };
+        let display = metadata.to_string();
+        assert!(display.starts_with("Tag=0x01020304  Index=2  Length=100 bytes: ...."));
+        assert!(display.ends_with(&format!("{}...", ".".repeat(60))));
+        assert!(format!("{:?}", metadata).ends_with(&format!("{}... }}", "ff".repeat(32))));
+    }
+
+    #[test]
+    fn metadata_chain_cycle_test() {
+        let mut buf = vec![0u8; 16];
+        buf.extend(metadata_entry(b"GDDD", b"abcd", 36));
+        buf.extend(metadata_entry(b"IDNT", b"efgh", 16));
+        let mut file = Cursor::new(buf);
+
+        let mut refs = MetadataRefs::from_stream(&mut file, 16);
+        assert_eq!(2, refs.by_ref().count());
+        assert!(matches!(refs.take_error(), Some(Error::InvalidMetadata)));
+
+        let metas = Vec::<Metadata>::try_from(MetadataRefs::from_stream(&mut file, 16));
+        assert!(matches!(metas, Err(Error::InvalidMetadata)));
+
+        let err = MetadataRefs::from_stream(&mut file, 16)
+            .try_into_vec()
+            .expect_err("cycle");
+        assert_eq!((2, 16, None), (err.position, err.offset, err.metatag));
+        assert!(matches!(err.error, Error::InvalidMetadata));
+        assert_eq!(
+            "metadata entry 2 at offset 0x10: invalid metadata",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn metadata_length_test() {
+        let mut buf = vec![0u8; 16];
+        buf.extend(metadata_entry(b"CIS ", b"abcd", 36));
+        buf.extend(metadata_entry(b"GDDD", b"efgh", 0));
+        // flags are stored in the high byte of the length.
+        buf[20] = 0x01;
+        let mut file = Cursor::new(buf);
+
+        let refs: Vec<_> = MetadataRefs::from_stream(&mut file, 16).collect();
+        assert_eq!(2, refs.len());
+        assert_eq!(4, refs[0].len());
+        assert_eq!(0x01, refs[0].flags());
+        assert_eq!(0x01000004, refs[0].raw_length());
+        assert_eq!(0, refs[1].flags());
+        assert_eq!(4, refs[1].raw_length());
+
+        // the contents of the second entry extend past the end of the file.
+        file.get_mut()[36 + 5..36 + 8].copy_from_slice(&[0x00, 0x00, 0x05]);
+        let mut refs = MetadataRefs::from_stream(&mut file, 16);
+        assert_eq!(1, refs.by_ref().count());
+        assert!(matches!(
+            refs.take_error(),
+            Some(Error::InvalidMetadataSize)
+        ));
+
+        // lengths with every bit set do not overflow into the flags.
+        file.get_mut()[36 + 4..36 + 8].copy_from_slice(&[0xff; 4]);
+        let metas = Vec::<Metadata>::try_from(MetadataRefs::from_stream(&mut file, 16));
+        assert!(matches!(metas, Err(Error::InvalidMetadataSize)));
+        let err = MetadataRefs::from_stream(&mut file, 16)
+            .try_into_vec()
+            .expect_err("past the end");
+        assert_eq!((1, 36), (err.position, err.offset));
+        assert!(matches!(err.error, Error::InvalidMetadataSize));
+    }
+
+    #[test]
+    fn metadata_read_into_test() {
+        let mut buf = vec![0u8; 16];
+        buf.extend(metadata_entry(b"CIS ", b"abcd", 0));
+        let mut file = Cursor::new(buf);
+        let meta_ref = MetadataRefs::from_stream(&mut file, 16)
+            .next()
+            .expect("entry");
+
+        let mut out = [0xffu8; 6];
+        assert_eq!(4, meta_ref.read_into(&mut file, &mut out).expect("read"));
+        assert_eq!(b"abcd\xff\xff", &out);
+
+        // shorter buffers are filled with the start of the contents.
+        let mut out = [0u8; 2];
+        assert_eq!(2, meta_ref.read_into(&mut file, &mut out).expect("read"));
+        assert_eq!(b"ab", &out);
+        assert_eq!(0, meta_ref.read_into(&mut file, &mut []).expect("read"));
+    }
+
+    #[test]
+    fn metadata_find_tag_test() {
+        let mut buf = vec![0u8; 16];
+        buf.extend(metadata_entry(b"CHT2", b"one", 35));
+        buf.extend(metadata_entry(b"IDNT", b"id", 53));
+        buf.extend(metadata_entry(b"CHT2", b"two", 0));
+        let mut file = Cursor::new(buf);
+
+        let find = |file: &mut Cursor<Vec<u8>>, tag: u32, index: u32| {
+            MetadataRefs::from_stream(file, 16)
+                .find_tag(tag, index)
+                .map(|m| m.value)
+        };
+        let cht2 = KnownMetadata::CdRomTrack2.metatag();
+        assert_eq!(b"two", &find(&mut file, cht2, 1).expect("CHT2 1")[..]);
+        assert_eq!(
+            b"id",
+            &find(&mut file, make_tag(b"IDNT"), 0).expect("IDNT")[..]
+        );
+        assert_eq!(b"id", &find(&mut file, 0, 1).expect("wildcard 1")[..]);
+        assert!(matches!(
+            find(&mut file, cht2, 2),
+            Err(Error::MetadataNotFound)
+        ));
+    }
+
+    #[test]
+    fn metadata_serialize_chain_test() {
+        let mut buf = vec![0u8; 16];
+        buf.extend(metadata_entry(b"CHT2", b"one", 35));
+        buf.extend(metadata_entry(b"IDNT", b"id", 53));
+        buf.extend(metadata_entry(b"CHT2", b"two", 0));
+        // flags are stored in the high byte of the length.
+        buf[20] = 0x01;
+        let mut file = Cursor::new(buf.clone());
+
+        let metas = Vec::<Metadata>::try_from(MetadataRefs::from_stream(&mut file, 16))
+            .expect("read chain");
+        assert_eq!(
+            &buf[16..],
+            &serialize_chain(&metas, 16).expect("serialize")[..]
+        );
+
+        let mut file = Cursor::new(serialize_chain(&metas, 0x1000).expect("serialize"));
+        // move the chain to the offset it was serialized for.
+        file.get_mut().splice(0..0, vec![0u8; 0x1000]);
+        let reread = Vec::<Metadata>::try_from(MetadataRefs::from_stream(&mut file, 0x1000))
+            .expect("reread chain");
+        let summary = |metas: &[Metadata]| {
+            metas
+                .iter()
+                .map(|m| (m.metatag, m.flags, m.index, m.value.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(&metas), summary(&reread));
+        assert_eq!(1, reread[0].flags);
+
+        assert!(serialize_chain(&[], 16).expect("empty").is_empty());
+        let large = Metadata {
+            metatag: make_tag(b"GDDD"),
+            value: vec![0; 0x01000000],
+            flags: 0,
+            index: 0,
+            length: 0x01000000,
+        };
+        assert!(matches!(
+            serialize_chain(&[large], 16),
+            Err(Error::InvalidMetadataSize)
+        ));
+    }
+}
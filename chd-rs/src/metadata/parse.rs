@@ -0,0 +1,424 @@
+//! Parsers for the contents of hard disk, PCMCIA and creator metadata entries.
+//!
+//! The `GDDD` metadata entry of a hard disk records its geometry, which can be parsed with
+//! [`HardDiskGeometry`](crate::metadata::parse::HardDiskGeometry).
+//!
+//! The `IDNT` metadata entry of a hard disk contains the ATA IDENTIFY DEVICE block returned by the drive,
+//! which can be parsed with [`AtaIdentify`](crate::metadata::parse::AtaIdentify). The `CIS ` metadata
+//! entry of a PCMCIA card contains its Card Information Structure, which can be parsed with
+//! [`CardInformation`](crate::metadata::parse::CardInformation).
+//!
+//! ```rust
+//! use chd::Chd;
+//! use chd::metadata::{KnownMetadata, Metadata, MetadataTag};
+//! use chd::metadata::parse::AtaIdentify;
+//!
+//! let mut chd = Chd::open_path("file.chd", None)?;
+//! let metadatas: Vec<Metadata> = chd.metadata_refs().try_into()?;
+//! for meta in metadatas.iter().filter(|m| m.metatag == KnownMetadata::HardDiskIdent.metatag()) {
+//!     let ident = AtaIdentify::from_bytes(&meta.value)?;
+//!     println!("{} ({})", ident.model(), ident.serial());
+//! }
+//! ```
+//!
+//! The `CRTR` metadata entry records the application that created a CHD file, which can be parsed with
+//! [`Creator`](crate::metadata::parse::Creator) or found with [`Chd::creator`](crate::Chd::creator).
+//! This is an extension of chd-rs that is not written by chdman.
+use crate::error::{Error, Result};
+use crate::metadata::{KnownMetadata, Metadata};
+use byteorder::{ByteOrder, LittleEndian};
+use std::fmt::{Display, Formatter};
+
+const ATA_IDENTIFY_WORDS: usize = 256;
+
+const CISTPL_NULL: u8 = 0x00;
+const CISTPL_VERS_1: u8 = 0x15;
+const CISTPL_END: u8 = 0xff;
+
+/// The geometry of a hard disk, stored in `GDDD` metadata.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct HardDiskGeometry {
+    /// The number of cylinders.
+    pub cylinders: u32,
+    /// The number of heads.
+    pub heads: u32,
+    /// The number of sectors per track.
+    pub sectors: u32,
+    /// The number of bytes per sector.
+    pub bytes_per_sector: u32,
+}
+
+impl HardDiskGeometry {
+    /// Parses the geometry of a hard disk from the contents of a `GDDD` metadata entry,
+    /// which are formatted as `CYLS:%d,HEADS:%d,SECS:%d,BPS:%d`.
+    ///
+    /// If the contents are not in this format, or the sector size is 0, returns
+    /// [`Error::InvalidMetadata`](crate::Error::InvalidMetadata).
+    pub fn from_bytes(buf: &[u8]) -> Result<HardDiskGeometry> {
+        let text = std::str::from_utf8(buf)
+            .map_err(|_| Error::InvalidMetadata)?
+            .trim_end_matches('\0');
+        let mut values = [0u32; 4];
+        let mut fields = text.split(',');
+        for (value, key) in values.iter_mut().zip(["CYLS", "HEADS", "SECS", "BPS"]) {
+            *value = fields
+                .next()
+                .and_then(|field| field.strip_prefix(key)?.strip_prefix(':')?.parse().ok())
+                .ok_or(Error::InvalidMetadata)?;
+        }
+        let [cylinders, heads, sectors, bytes_per_sector] = values;
+        if bytes_per_sector == 0 {
+            return Err(Error::InvalidMetadata);
+        }
+        Ok(HardDiskGeometry {
+            cylinders,
+            heads,
+            sectors,
+            bytes_per_sector,
+        })
+    }
+
+    /// Returns the total number of sectors of the hard disk.
+    pub fn sector_count(&self) -> u64 {
+        self.cylinders as u64 * self.heads as u64 * self.sectors as u64
+    }
+}
+
+/// The ATA IDENTIFY DEVICE block of a hard disk, stored in `IDNT` metadata.
+#[derive(Debug, Clone)]
+pub struct AtaIdentify {
+    words: [u16; ATA_IDENTIFY_WORDS],
+}
+
+impl AtaIdentify {
+    /// Parses an ATA IDENTIFY DEVICE block from the contents of an `IDNT` metadata entry.
+    ///
+    /// The block consists of 256 little-endian words. If the buffer is shorter than 512 bytes,
+    /// returns [`Error::InvalidMetadata`](crate::Error::InvalidMetadata).
+    pub fn from_bytes(buf: &[u8]) -> Result<AtaIdentify> {
+        if buf.len() < ATA_IDENTIFY_WORDS * 2 {
+            return Err(Error::InvalidMetadata);
+        }
+        let mut words = [0u16; ATA_IDENTIFY_WORDS];
+        LittleEndian::read_u16_into(&buf[..ATA_IDENTIFY_WORDS * 2], &mut words);
+        Ok(AtaIdentify { words })
+    }
+
+    /// Returns the raw words of the IDENTIFY DEVICE block.
+    pub fn words(&self) -> &[u16; ATA_IDENTIFY_WORDS] {
+        &self.words
+    }
+
+    /// Decodes an ATA string from the given range of words.
+    ///
+    /// ATA strings store two characters per word, with the first character in the high byte.
+    fn string(&self, start: usize, end: usize) -> String {
+        let bytes: Vec<u8> = self.words[start..end]
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect();
+        String::from_utf8_lossy(&bytes)
+            .trim_matches(|c: char| c == ' ' || c == '\0')
+            .to_string()
+    }
+
+    /// The model number of the drive.
+    pub fn model(&self) -> String {
+        self.string(27, 47)
+    }
+
+    /// The serial number of the drive.
+    pub fn serial(&self) -> String {
+        self.string(10, 20)
+    }
+
+    /// The firmware revision of the drive.
+    pub fn firmware(&self) -> String {
+        self.string(23, 27)
+    }
+
+    /// The number of logical cylinders of the drive.
+    pub fn cylinders(&self) -> u16 {
+        self.words[1]
+    }
+
+    /// The number of logical heads of the drive.
+    pub fn heads(&self) -> u16 {
+        self.words[3]
+    }
+
+    /// The number of logical sectors per track of the drive.
+    pub fn sectors(&self) -> u16 {
+        self.words[6]
+    }
+
+    /// The total number of user addressable sectors in LBA mode, if the drive supports LBA.
+    pub fn lba_sectors(&self) -> Option<u32> {
+        // word 49 bit 9: LBA supported
+        if self.words[49] & (1 << 9) == 0 {
+            return None;
+        }
+        Some(self.words[60] as u32 | ((self.words[61] as u32) << 16))
+    }
+}
+
+/// A single tuple in a PCMCIA Card Information Structure.
+#[derive(Debug, Clone)]
+pub struct CisTuple {
+    /// The tuple code.
+    pub code: u8,
+    /// The body of the tuple, excluding the tuple code and link bytes.
+    pub data: Vec<u8>,
+}
+
+/// The Card Information Structure of a PCMCIA card, stored in `CIS ` metadata.
+#[derive(Debug, Clone)]
+pub struct CardInformation {
+    /// The tuples in the tuple chain, in order, excluding the end of chain tuple.
+    pub tuples: Vec<CisTuple>,
+}
+
+impl CardInformation {
+    /// Parses the tuple chain of a Card Information Structure from the contents of a `CIS `
+    /// metadata entry.
+    ///
+    /// The tuple chain ends at the first end of chain tuple, or at the end of the buffer. If a tuple
+    /// extends past the end of the buffer, returns [`Error::InvalidMetadata`](crate::Error::InvalidMetadata).
+    pub fn from_bytes(buf: &[u8]) -> Result<CardInformation> {
+        let mut tuples = Vec::new();
+        let mut offset = 0;
+        while offset < buf.len() {
+            let code = buf[offset];
+            match code {
+                CISTPL_END => break,
+                // null tuples have no link byte.
+                CISTPL_NULL => {
+                    offset += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let link = *buf.get(offset + 1).ok_or(Error::InvalidMetadata)?;
+            // a link of 0xff also terminates the tuple chain.
+            if link == 0xff {
+                tuples.push(CisTuple {
+                    code,
+                    data: Vec::new(),
+                });
+                break;
+            }
+
+            let data = buf
+                .get(offset + 2..offset + 2 + link as usize)
+                .ok_or(Error::InvalidMetadata)?;
+            tuples.push(CisTuple {
+                code,
+                data: data.to_vec(),
+            });
+            offset += 2 + link as usize;
+        }
+        Ok(CardInformation { tuples })
+    }
+
+    /// Returns the first tuple in the tuple chain with the given code.
+    pub fn find(&self, code: u8) -> Option<&CisTuple> {
+        self.tuples.iter().find(|t| t.code == code)
+    }
+
+    /// Returns the product information strings of the `CISTPL_VERS_1` tuple, typically
+    /// the manufacturer, the product name, and additional product information.
+    pub fn product_info(&self) -> Option<Vec<String>> {
+        let vers = self.find(CISTPL_VERS_1)?;
+        // skip major and minor version bytes.
+        let strings = vers.data.get(2..)?;
+        let strings = strings
+            .split(|&b| b == 0)
+            .take_while(|s| s.first() != Some(&0xff))
+            .filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect();
+        Some(strings)
+    }
+}
+
+/// The application that created a CHD file, stored in `CRTR` metadata.
+///
+/// The contents of the metadata entry are the name of the application followed by its version,
+/// such as `chd-rs 0.3.2`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Creator {
+    /// The name of the application.
+    pub application: String,
+    /// The version of the application, if known.
+    pub version: Option<String>,
+}
+
+impl Creator {
+    /// Creates a new creator with the given application name and version.
+    pub fn new(application: impl Into<String>, version: Option<String>) -> Creator {
+        Creator {
+            application: application.into(),
+            version,
+        }
+    }
+
+    /// Returns the creator that identifies this version of chd-rs.
+    pub fn chd_rs() -> Creator {
+        Creator::new("chd-rs", Some(env!("CARGO_PKG_VERSION").to_string()))
+    }
+
+    /// Parses the creator of a CHD file from the contents of a `CRTR` metadata entry.
+    ///
+    /// The version is the first word that starts with a digit, and the application is the text
+    /// before it. Version strings printed by chdman, such as
+    /// `chdman - MAME Compressed Hunks of Data (CHD) manager 0.258 (mame0258)`, are parsed as
+    /// the application `chdman` with the version `0.258`.
+    ///
+    /// If the contents are not valid UTF-8 or are empty, returns [`Error::InvalidMetadata`](crate::Error::InvalidMetadata).
+    pub fn from_bytes(buf: &[u8]) -> Result<Creator> {
+        let text = std::str::from_utf8(buf)
+            .map_err(|_| Error::InvalidMetadata)?
+            .trim_end_matches('\0')
+            .trim();
+
+        let words: Vec<&str> = text.split_ascii_whitespace().collect();
+        let version_index = words
+            .iter()
+            .position(|w| w.starts_with(|c: char| c.is_ascii_digit()));
+        let application = match version_index {
+            Some(index) => words[..index].join(" "),
+            None => words.join(" "),
+        };
+        // chdman prefixes its description with its name.
+        let application = application
+            .split(" - ")
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        if application.is_empty() {
+            return Err(Error::InvalidMetadata);
+        }
+
+        Ok(Creator {
+            application,
+            version: version_index.map(|index| words[index].to_string()),
+        })
+    }
+
+    /// Returns a `CRTR` metadata entry that records this creator.
+    ///
+    /// The entry is not included in the SHA1 hash of the CHD file, so stamping a CHD file with its
+    /// creator does not change its hash.
+    pub fn to_metadata(&self) -> Metadata {
+        let mut value = self.to_string().into_bytes();
+        value.push(0);
+        Metadata {
+            metatag: KnownMetadata::Creator as u32,
+            length: value.len() as u32,
+            value,
+            flags: 0,
+            index: 0,
+        }
+    }
+}
+
+impl Display for Creator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.application, version),
+            None => f.write_str(&self.application),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::metadata::parse::{AtaIdentify, CardInformation, Creator, HardDiskGeometry};
+    use crate::Error;
+
+    #[test]
+    fn parse_gddd_test() {
+        let geometry =
+            HardDiskGeometry::from_bytes(b"CYLS:980,HEADS:10,SECS:17,BPS:512\0").expect("gddd");
+        assert_eq!(980, geometry.cylinders);
+        assert_eq!(10, geometry.heads);
+        assert_eq!(17, geometry.sectors);
+        assert_eq!(512, geometry.bytes_per_sector);
+        assert_eq!(980 * 10 * 17, geometry.sector_count());
+
+        assert!(matches!(
+            HardDiskGeometry::from_bytes(b"CYLS:980,HEADS:10,SECS:17"),
+            Err(Error::InvalidMetadata)
+        ));
+        assert!(matches!(
+            HardDiskGeometry::from_bytes(b"CYLS:980,HEADS:10,SECS:17,BPS:0\0"),
+            Err(Error::InvalidMetadata)
+        ));
+        assert!(matches!(
+            HardDiskGeometry::from_bytes(b"CYLS:a,HEADS:10,SECS:17,BPS:512"),
+            Err(Error::InvalidMetadata)
+        ));
+    }
+
+    #[test]
+    fn parse_idnt_test() {
+        let mut buf = vec![0u8; 512];
+        buf[2..4].copy_from_slice(&980u16.to_le_bytes());
+        buf[6..8].copy_from_slice(&10u16.to_le_bytes());
+        buf[12..14].copy_from_slice(&17u16.to_le_bytes());
+        // model starts at word 27, with swapped characters.
+        buf[54..64].copy_from_slice(b"AMEMH-1D  ");
+        let ident = AtaIdentify::from_bytes(&buf).expect("identify");
+        assert_eq!("MAME-HD1", ident.model());
+        assert_eq!(
+            (980, 10, 17),
+            (ident.cylinders(), ident.heads(), ident.sectors())
+        );
+        assert_eq!(None, ident.lba_sectors());
+        assert!(AtaIdentify::from_bytes(&buf[..511]).is_err());
+    }
+
+    #[test]
+    fn parse_cis_test() {
+        let mut buf = vec![0x01, 0x03, 0xd9, 0x01, 0xff, 0x00];
+        buf.extend_from_slice(&[0x15, 0x0b, 0x04, 0x01]);
+        buf.extend_from_slice(b"MAME\0CF\0\xff");
+        buf.push(0xff);
+        let cis = CardInformation::from_bytes(&buf).expect("cis");
+        assert_eq!(2, cis.tuples.len());
+        assert_eq!(vec![0xd9, 0x01, 0xff], cis.tuples[0].data);
+        assert_eq!(
+            Some(vec!["MAME".to_string(), "CF".to_string()]),
+            cis.product_info()
+        );
+        assert!(CardInformation::from_bytes(&[0x01, 0x04, 0x00]).is_err());
+    }
+
+    #[test]
+    fn parse_creator_test() {
+        let creator = Creator::from_bytes(b"chd-rs 0.3.2\0").expect("creator");
+        assert_eq!(Creator::new("chd-rs", Some("0.3.2".to_string())), creator);
+        assert_eq!(
+            Creator::new("chdman", Some("0.258".to_string())),
+            Creator::from_bytes(
+                b"chdman - MAME Compressed Hunks of Data (CHD) manager 0.258 (mame0258)"
+            )
+            .expect("chdman")
+        );
+        assert_eq!(
+            Creator::new("My Tool", None),
+            Creator::from_bytes(b"My Tool").expect("no version")
+        );
+        assert!(Creator::from_bytes(b"\0").is_err());
+        assert!(Creator::from_bytes(b"1.0").is_err());
+
+        let stamp = Creator::chd_rs().to_metadata();
+        assert_eq!(Some(&0), stamp.value.last());
+        assert_eq!(
+            Creator::chd_rs(),
+            Creator::from_bytes(&stamp.value).expect("round trip")
+        );
+    }
+}
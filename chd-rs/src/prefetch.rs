@@ -0,0 +1,159 @@
+//! Background decompression of hunks that are about to be read.
+use crate::block_hash::BlockChecksum;
+use crate::chdfile::Codecs;
+use crate::error::{Error, Result};
+use crate::map::HunkCrc;
+use crc::Crc;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+
+/// The maximum number of prefetched hunks that are held until they are read.
+pub(crate) const PREFETCH_CAPACITY: usize = 64;
+
+/// The compressed contents of a hunk to decompress in the background.
+pub(crate) struct PrefetchJob {
+    pub hunk_num: u32,
+    /// The index of the codec to decompress the hunk with.
+    pub codec: usize,
+    pub crc: Option<HunkCrc>,
+    pub compressed: Vec<u8>,
+}
+
+enum Slot {
+    Pending,
+    Done(Result<Vec<u8>>),
+}
+
+#[derive(Default)]
+struct Slots {
+    slots: Mutex<HashMap<u32, Slot>>,
+    ready: Condvar,
+}
+
+impl Slots {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u32, Slot>> {
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Decompresses hunks on a background thread with its own set of codecs, and holds the
+/// decompressed hunks until they are read.
+pub(crate) struct Prefetcher {
+    slots: Arc<Slots>,
+    jobs: Option<Sender<PrefetchJob>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Prefetcher {
+    /// Starts the background thread, which decompresses hunks of `hunk_size` bytes with `codecs`.
+    pub fn new(mut codecs: Codecs, hunk_size: u32) -> Prefetcher {
+        let slots = Arc::new(Slots::default());
+        let (jobs, queue) = channel::<PrefetchJob>();
+        let worker = {
+            let slots = Arc::clone(&slots);
+            std::thread::spawn(move || {
+                for job in queue {
+                    let mut buf = vec![0u8; hunk_size as usize];
+                    // codecs may panic on malformed data, which must not leave the hunk pending forever.
+                    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        decompress(&mut codecs, &job, &mut buf)
+                    }))
+                    .unwrap_or(Err(Error::DecompressionError));
+                    slots
+                        .lock()
+                        .insert(job.hunk_num, Slot::Done(res.map(|_| buf)));
+                    slots.ready.notify_all();
+                }
+            })
+        };
+        Prefetcher {
+            slots,
+            jobs: Some(jobs),
+            worker: Some(worker),
+        }
+    }
+
+    /// Returns whether the hunk is prefetched or being prefetched.
+    pub fn contains(&self, hunk_num: u32) -> bool {
+        self.slots.lock().contains_key(&hunk_num)
+    }
+
+    /// Returns the number of bytes of decompressed hunks that are held until they are read.
+    pub fn cached_bytes(&self) -> usize {
+        self.slots
+            .lock()
+            .values()
+            .map(|slot| match slot {
+                Slot::Done(Ok(buf)) => buf.capacity(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Returns whether no more hunks can be prefetched until some prefetched hunks are read.
+    pub fn is_full(&self) -> bool {
+        self.slots.lock().len() >= PREFETCH_CAPACITY
+    }
+
+    /// Queues the hunk to be decompressed in the background.
+    pub fn submit(&self, job: PrefetchJob) {
+        let jobs = match self.jobs.as_ref() {
+            Some(jobs) => jobs,
+            None => return,
+        };
+        self.slots.lock().insert(job.hunk_num, Slot::Pending);
+        let hunk_num = job.hunk_num;
+        if jobs.send(job).is_err() {
+            self.slots.lock().remove(&hunk_num);
+        }
+    }
+
+    /// Copies the prefetched hunk into `output`, waiting for it to be decompressed if it is pending,
+    /// and releases it from the prefetcher.
+    ///
+    /// Returns `None` if the hunk was not prefetched.
+    pub fn take(&self, hunk_num: u32, output: &mut [u8]) -> Option<Result<usize>> {
+        let mut slots = self.slots.lock();
+        while let Some(Slot::Pending) = slots.get(&hunk_num) {
+            slots = self
+                .slots
+                .ready
+                .wait(slots)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        match slots.remove(&hunk_num)? {
+            Slot::Done(res) => Some(res.map(|buf| {
+                output.copy_from_slice(&buf);
+                buf.len()
+            })),
+            Slot::Pending => None,
+        }
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        // closing the queue stops the worker once it finishes its pending hunks.
+        drop(self.jobs.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn decompress(codecs: &mut Codecs, job: &PrefetchJob, dest: &mut [u8]) -> Result<usize> {
+    let codec = codecs.get_mut(job.codec)?;
+    let res = codec.decompress(&job.compressed, dest)?;
+    match job.crc {
+        Some(HunkCrc::Crc16(crc)) => {
+            Crc::<u16>::verify_block_checksum(Some(crc), dest, res.total_out())
+        }
+        Some(HunkCrc::Crc32(crc)) => {
+            Crc::<u32>::verify_block_checksum(Some(crc), dest, res.total_out())
+        }
+        None => Ok(res.total_out()),
+    }
+}
@@ -5,22 +5,28 @@
 //! an internal buffer of decompressed hunk data. For the best performance and flexibility,
 //! [`Hunk::read_hunk_in`](crate::Hunk::read_hunk_in) should be used which will
 //! avoid unnecessary buffering.
-use crate::error::Result;
-use crate::{Chd, Error, Hunk};
-use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
+use crate::error::{Error, Result};
+use crate::{Chd, Hunk};
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
 
 /// Buffered `BufRead + Seek` adapter for [`Hunk`](crate::Hunk).
-pub struct HunkBufReader {
+///
+/// The hunk is decompressed when data is first read from the reader, so seeking is cheap, and
+/// reads past the end of the hunk do not decompress it at all.
+pub struct HunkBufReader<'a, F: Read + Seek, P: Read + Seek = F> {
+    // the hunk to decompress, until it has been decompressed into the buffer.
+    hunk: Option<Hunk<'a, F, P>>,
+    cmp_buffer: Option<&'a mut Vec<u8>>,
     inner: Cursor<Vec<u8>>,
 }
 
-impl HunkBufReader {
+impl<'a, F: Read + Seek, P: Read + Seek> HunkBufReader<'a, F, P> {
     /// Create a new `HunkBufReader` with new buffers.
     ///
-    /// New buffers are allocated and the hunk contents are immediately buffered
-    /// from the stream upon creation.
-    pub fn new<F: Read + Seek>(hunk: &mut Hunk<F>) -> Result<Self> {
-        HunkBufReader::new_in(hunk, &mut Vec::new(), Vec::new())
+    /// New buffers are allocated and the hunk contents are buffered from the stream
+    /// when data is first read.
+    pub fn new(hunk: &'a mut Hunk<F, P>) -> Result<Self> {
+        HunkBufReader::with_buffers(hunk, None, Vec::new())
     }
 
     /// Creates a `HunkBufReader` with the provided buffers.
@@ -28,46 +34,85 @@ impl HunkBufReader {
     /// Ownership of `buffer` is transferred to the created `ChdHunkBufReader` and can be
     /// reacquired with [`HunkBufReader::into_inner`](crate::read::HunkBufReader::into_inner).
     ///
-    /// The hunk contents are immediately buffered from the stream upon creation.
+    /// The hunk contents are buffered from the stream when data is first read.
     ///
     /// `cmp_buffer` is used temporarily to hold the compressed data from the hunk. Ownership of
     /// `buffer` is taken to be used as the internal buffer for this reader.
     ///
     /// Unlike [`Hunk::read_hunk_in`](crate::Hunk::read_hunk_in), there are no
     /// length restrictions on the provided buffers.
-    pub fn new_in<F: Read + Seek>(
-        hunk: &mut Hunk<F>,
-        cmp_buffer: &mut Vec<u8>,
+    pub fn new_in(
+        hunk: &'a mut Hunk<F, P>,
+        cmp_buffer: &'a mut Vec<u8>,
+        buffer: Vec<u8>,
+    ) -> Result<Self> {
+        HunkBufReader::with_buffers(hunk, Some(cmp_buffer), buffer)
+    }
+
+    fn with_buffers(
+        hunk: &'a mut Hunk<F, P>,
+        cmp_buffer: Option<&'a mut Vec<u8>>,
         mut buffer: Vec<u8>,
     ) -> Result<Self> {
-        let len = hunk.len();
-        buffer.resize(len, 0);
-        hunk.read_hunk_in(cmp_buffer, &mut buffer)?;
+        buffer.resize(hunk.len(), 0);
         Ok(HunkBufReader {
+            hunk: Some(hunk.reborrow()),
+            cmp_buffer,
             inner: Cursor::new(buffer),
         })
     }
 
+    /// Decompresses the hunk into the buffer if it has not been decompressed yet.
+    fn ensure_buffered(&mut self) -> Result<()> {
+        if let Some(hunk) = self.hunk.as_mut() {
+            match self.cmp_buffer.as_deref_mut() {
+                Some(cmp_buffer) => hunk.read_hunk_in(cmp_buffer, self.inner.get_mut())?,
+                None => hunk.read_hunk_in(&mut Vec::new(), self.inner.get_mut())?,
+            };
+            self.hunk = None;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the position of the reader is at or past the end of the hunk.
+    fn is_at_end(&self) -> bool {
+        self.inner.position() >= self.inner.get_ref().len() as u64
+    }
+
     /// Consumes the reader and returns the underlying value.
+    ///
+    /// If no data was read from the reader, the hunk may not have been decompressed
+    /// into the returned buffer.
     pub fn into_inner(self) -> Vec<u8> {
         self.inner.into_inner()
     }
 }
 
-impl Read for HunkBufReader {
+impl<F: Read + Seek, P: Read + Seek> Read for HunkBufReader<'_, F, P> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.inner.read(buf)
+        let read = {
+            let available = self.fill_buf()?;
+            let read = available.len().min(buf.len());
+            buf[..read].copy_from_slice(&available[..read]);
+            read
+        };
+        self.consume(read);
+        Ok(read)
     }
 }
 
-impl Seek for HunkBufReader {
+impl<F: Read + Seek, P: Read + Seek> Seek for HunkBufReader<'_, F, P> {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         self.inner.seek(pos)
     }
 }
 
-impl BufRead for HunkBufReader {
+impl<F: Read + Seek, P: Read + Seek> BufRead for HunkBufReader<'_, F, P> {
     fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.is_at_end() {
+            return Ok(&[]);
+        }
+        self.ensure_buffered()?;
         self.inner.fill_buf()
     }
 
@@ -76,139 +121,259 @@ impl BufRead for HunkBufReader {
     }
 }
 
-/// Utility adapter for [`Chd`](crate::Chd) that implements `Read`.
+/// Iterator over the decompressed contents of the hunks of a [`Chd`](crate::Chd), created with
+/// [`Chd::hunk_bytes_iter`](crate::Chd::hunk_bytes_iter).
+///
+/// A new buffer is allocated for every hunk. If performance is a concern, it is recommended
+/// to instead iterate over hunk indices and reuse buffers with [`Hunk::read_hunk_in`](crate::Hunk::read_hunk_in).
+pub struct HunkBytesIter<'a, F: Read + Seek, P: Read + Seek = F> {
+    chd: &'a mut Chd<F, P>,
+    cmp_buf: Vec<u8>,
+    curr: u32,
+    end: u32,
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> HunkBytesIter<'a, F, P> {
+    pub(crate) fn new(chd: &'a mut Chd<F, P>) -> Self {
+        let end = chd.header().hunk_count();
+        HunkBytesIter {
+            chd,
+            cmp_buf: Vec::new(),
+            curr: 0,
+            end,
+        }
+    }
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> Iterator for HunkBytesIter<'a, F, P> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.curr >= self.end {
+            return None;
+        }
+        let hunk_num = self.curr;
+        self.curr += 1;
+
+        let mut buf = self.chd.get_hunksized_buffer();
+        Some(
+            self.chd
+                .hunk(hunk_num)
+                .and_then(|mut hunk| hunk.read_hunk_in(&mut self.cmp_buf, &mut buf))
+                .map(|_| buf),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.end - self.curr) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<'a, F: Read + Seek, P: Read + Seek> ExactSizeIterator for HunkBytesIter<'a, F, P> {}
+
+/// Utility adapter for [`Chd`](crate::Chd) that implements `BufRead + Seek`.
 ///
 /// `ChdReader` will allocate and manage intermediate buffers to support
 /// reading at a byte granularity. If performance is a concern, it is recommended
 /// to instead iterate over hunk indices.
-pub struct ChdReader<F: Read + Seek> {
-    chd: Chd<F>,
-    current_hunk: u32,
+///
+/// The contents of the internal buffer are exposed with [`BufRead::fill_buf`](std::io::BufRead::fill_buf),
+/// which avoids an extra copy for consumers that can work directly on borrowed data. By default,
+/// one hunk is decompressed into the internal buffer at a time. For sequential reads, more hunks can
+/// be decompressed at once with [`ChdReader::with_read_ahead`](crate::read::ChdReader::with_read_ahead).
+///
+/// Consecutive hunks that are stored uncompressed and contiguously in the file, such as the hunks of
+/// an uncompressed CHD file, are copied into the internal buffer with a single read instead of
+/// being read hunk by hunk. Unless the `verify_block_crc` feature is enabled, this also applies to
+/// uncompressed hunks in compressed CHD files. Combined with a large read-ahead, reading an
+/// uncompressed CHD file is a sequential copy of the underlying stream.
+pub struct ChdReader<F: Read + Seek, P: Read + Seek = F> {
+    chd: Chd<F, P>,
     cmp_buf: Vec<u8>,
-    buf_read: Option<HunkBufReader>,
-    eof: bool,
+    // decompressed contents of the hunks starting at buf_hunk.
+    buf: Vec<u8>,
+    buf_hunk: u32,
+    // the position of the reader in the uncompressed stream.
+    pos: u64,
+    read_ahead: u32,
 }
 
-impl<F: Read + Seek> ChdReader<F> {
+impl<F: Read + Seek, P: Read + Seek> ChdReader<F, P> {
     /// Create a new `ChdReader` from an opened [`Chd`](crate::Chd).
-    pub fn new(chd: Chd<F>) -> Self {
+    pub fn new(chd: Chd<F, P>) -> Self {
+        ChdReader::with_read_ahead(chd, 1)
+    }
+
+    /// Create a new `ChdReader` from an opened [`Chd`](crate::Chd) that decompresses
+    /// up to `hunks` consecutive hunks into its internal buffer at a time.
+    ///
+    /// A larger read-ahead reduces the overhead of refilling the buffer for sequential reads,
+    /// at the cost of memory and of decompressing hunks that may not be read after a seek.
+    /// A read-ahead of `0` is treated as `1`.
+    pub fn with_read_ahead(chd: Chd<F, P>, hunks: u32) -> Self {
         ChdReader {
             chd,
-            current_hunk: 0,
             cmp_buf: Vec::new(),
-            buf_read: None,
-            eof: false,
+            buf: Vec::new(),
+            buf_hunk: 0,
+            pos: 0,
+            read_ahead: hunks.max(1),
         }
     }
-}
 
-impl<F: Read + Seek> Read for ChdReader<F> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        if self.eof {
-            return Ok(0);
+    /// Consumes the reader and returns the underlying [`Chd`](crate::Chd).
+    pub fn into_inner(self) -> Chd<F, P> {
+        self.chd
+    }
+
+    /// Returns the length of the logical image of the CHD file in bytes.
+    ///
+    /// This is the length of the stream read by the `ChdReader`, and is the total to report
+    /// progress against. If the hunks of the CHD file do not cover its logical size, the stream
+    /// ends at the end of the last hunk. See [`Header::readable_bytes`](crate::header::Header::readable_bytes).
+    pub fn logical_len(&self) -> u64 {
+        self.chd.header().readable_bytes()
+    }
+
+    /// Returns the current position of the reader in the logical image of the CHD file.
+    ///
+    /// Unlike [`Seek::stream_position`](std::io::Seek::stream_position), this can not fail
+    /// and does not require a mutable reference to the reader.
+    pub fn logical_position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Moves the reader to the start of the hunk `hunk_num`, returning the new position.
+    ///
+    /// If the hunk is already decompressed in the internal buffer, it is not decompressed again.
+    /// If the hunk number is out of range, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange)
+    /// and the position of the reader is unchanged.
+    pub fn rewind_to_hunk(&mut self, hunk_num: u32) -> Result<u64> {
+        if hunk_num >= self.chd.header().hunk_count() {
+            return Err(Error::HunkOutOfRange);
         }
+        let pos = hunk_num as u64 * self.chd.header().hunk_size() as u64;
+        self.pos = std::cmp::min(pos, self.logical_len());
+        Ok(self.pos)
+    }
 
-        if self.current_hunk == 0 && self.buf_read.is_none() {
-            // do init
-            let mut hunk = match self.chd.hunk(self.current_hunk) {
-                Ok(hunk) => hunk,
-                // never was a hunk to begin with.
-                Err(Error::HunkOutOfRange) => {
-                    self.eof = true;
-                    return Ok(0);
-                }
-                Err(e) => return Err(e.into()),
-            };
-            let buf = Vec::new();
-            self.buf_read = Some(HunkBufReader::new_in(&mut hunk, &mut self.cmp_buf, buf)?)
+    /// Copies a window of the logical image of the CHD file into `output`, starting at byte `start`
+    /// and spanning `len` bytes, or until the end of the logical image if `len` is `None`.
+    ///
+    /// Only the hunks that overlap the window are decompressed. If the window extends past the
+    /// end of the logical image, only the bytes up to the end are copied. Returns the number of
+    /// bytes copied. The position of the reader is left at the end of the copied window.
+    pub fn copy_range<W: Write + ?Sized>(
+        &mut self,
+        start: u64,
+        len: Option<u64>,
+        output: &mut W,
+    ) -> std::io::Result<u64> {
+        self.seek(SeekFrom::Start(start))?;
+        let mut remaining = len.unwrap_or(u64::MAX);
+        let mut copied = 0;
+        while remaining > 0 {
+            let buf = self.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let count = std::cmp::min(buf.len() as u64, remaining) as usize;
+            output.write_all(&buf[..count])?;
+            self.consume(count);
+            remaining -= count as u64;
+            copied += count as u64;
         }
+        Ok(copied)
+    }
 
-        match self.buf_read.as_mut().unwrap().read(buf) {
-            Ok(0) => {
-                self.current_hunk += 1;
-                let mut hunk = match self.chd.hunk(self.current_hunk) {
-                    Ok(hunk) => hunk,
-                    // never was a hunk to begin with.
-                    Err(Error::HunkOutOfRange) => {
-                        self.eof = true;
-                        return Ok(0);
-                    }
-                    Err(e) => return Err(e.into()),
-                };
-                let inner = self.buf_read.take();
-                self.buf_read = Some(HunkBufReader::new_in(
-                    &mut hunk,
-                    &mut self.cmp_buf,
-                    inner.unwrap().into_inner(),
-                )?);
-                self.read(buf)
+    /// Decompresses the hunks starting at the hunk containing the current position into the buffer.
+    fn fill_hunks(&mut self, hunk_size: u64) -> Result<()> {
+        let first_hunk = (self.pos / hunk_size) as u32;
+        let hunk_count = std::cmp::min(
+            self.read_ahead,
+            self.chd.header().hunk_count().saturating_sub(first_hunk),
+        );
+
+        // clear the buffer first so that it is empty if decompression fails.
+        self.buf.clear();
+        let mut buf = std::mem::take(&mut self.buf);
+        let len = usize::try_from(hunk_count as u64 * hunk_size).map_err(|_| Error::OutOfMemory)?;
+        buf.resize(len, 0);
+        let mut hunk_num = first_hunk;
+        let mut offset = 0;
+        while offset < buf.len() {
+            // hunks stored verbatim are copied straight from the file in as few reads as possible.
+            let mut read = self.chd.read_raw_hunks(hunk_num, &mut buf[offset..])?;
+            if read == 0 {
+                self.chd
+                    .hunk(hunk_num)?
+                    .read_hunk_in(&mut self.cmp_buf, &mut buf[offset..][..hunk_size as usize])?;
+                read = 1;
             }
-            Ok(r) => Ok(r),
-            Err(e) => Err(e),
+            hunk_num += read;
+            offset += read as usize * hunk_size as usize;
+        }
+        self.buf = buf;
+        self.buf_hunk = first_hunk;
+        Ok(())
+    }
+}
+
+impl<F: Read + Seek, P: Read + Seek> BufRead for ChdReader<F, P> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        let len = self.logical_len();
+        let hunk_size = self.chd.header().hunk_size() as u64;
+        if self.pos >= len || hunk_size == 0 {
+            return Ok(&[]);
+        }
+
+        let buf_start = self.buf_hunk as u64 * hunk_size;
+        let buf_end = buf_start + self.buf.len() as u64;
+        if self.pos < buf_start || self.pos >= buf_end {
+            self.fill_hunks(hunk_size)?;
         }
+
+        let buf_start = self.buf_hunk as u64 * hunk_size;
+        let start = (self.pos - buf_start) as usize;
+        let end = std::cmp::min(self.buf.len() as u64, len - buf_start) as usize;
+        Ok(&self.buf[start..end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = std::cmp::min(self.pos + amt as u64, self.logical_len());
     }
 }
 
-impl<F: Read + Seek> Seek for ChdReader<F> {
-    // todo: !#[feature(mixed_integer_ops)], otherwise this is subtly broken in certain cases...
+impl<F: Read + Seek, P: Read + Seek> Read for ChdReader<F, P> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = {
+            let mut available = self.fill_buf()?;
+            available.read(buf)?
+        };
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<F: Read + Seek, P: Read + Seek> Seek for ChdReader<F, P> {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         // length of the uncompressed stream
-        let len = self.chd.header().logical_bytes();
-        let hunk_size = self.chd.header().hunk_size();
+        let len = self.logical_len();
 
         let (base_pos, offset) = match pos {
             SeekFrom::Start(n) => {
-                if n >= len {
-                    self.eof = true;
-                    return Ok(len);
-                }
-                let hunk_num = n / hunk_size as u64;
-                let hunk_off = n % hunk_size as u64;
-                return if let Ok(mut hunk) = self.chd.hunk(hunk_num as u32) {
-                    self.eof = false;
-                    self.current_hunk = hunk_num as u32;
-                    let mut buf_read =
-                        HunkBufReader::new_in(&mut hunk, &mut self.cmp_buf, Vec::new())?;
-                    buf_read.inner.seek(SeekFrom::Start(hunk_off))?;
-                    self.buf_read = Some(buf_read);
-                    Ok(n)
-                } else {
-                    self.eof = true;
-                    Ok(n)
-                };
+                self.pos = std::cmp::min(n, len);
+                return Ok(self.pos);
             }
             SeekFrom::End(n) => (len, n),
-            SeekFrom::Current(n) => {
-                let hunk_pos = match &self.buf_read {
-                    None => 0,
-                    Some(hunk) => hunk.inner.position(),
-                };
-                (hunk_pos, n)
-            }
+            SeekFrom::Current(n) => (self.pos, n),
         };
 
-        // todo: !#[feature(mixed_integer_ops)]
-        match (base_pos as i64).checked_add(offset) {
+        match base_pos.checked_add_signed(offset) {
             Some(n) => {
-                let n = n as u64;
-                if n >= len {
-                    self.eof = true;
-                    return Ok(len);
-                }
-                let hunk_num = n / hunk_size as u64;
-                let hunk_off = n % hunk_size as u64;
-                return if let Ok(mut hunk) = self.chd.hunk(hunk_num as u32) {
-                    self.eof = false;
-                    self.current_hunk = hunk_num as u32;
-                    let mut buf_read =
-                        HunkBufReader::new_in(&mut hunk, &mut self.cmp_buf, Vec::new())?;
-                    buf_read.inner.seek(SeekFrom::Start(hunk_off))?;
-                    self.buf_read = Some(buf_read);
-                    Ok(n)
-                } else {
-                    self.eof = true;
-                    Ok(n)
-                };
+                self.pos = std::cmp::min(n, len);
+                Ok(self.pos)
             }
             None => Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
@@ -216,4 +381,194 @@ impl<F: Read + Seek> Seek for ChdReader<F> {
             )),
         }
     }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::read::{ChdReader, HunkBufReader};
+    use crate::testing::{v3_chd, v5_uncompressed_chd, Fault, FaultyReader, LegacyHunk};
+    use crate::{Chd, ChdOpenOptions, Error};
+    use std::io::{BufRead, Cursor, ErrorKind, Read, Seek, SeekFrom};
+
+    #[test]
+    fn lazy_hunk_buf_reader_test() {
+        let hunks = [Some(vec![1; 512]), Some(vec![2; 512])];
+        let test_chd = v5_uncompressed_chd(512, &hunks, None);
+        let mut chd = ChdOpenOptions::new()
+            .open(FaultyReader::new(Cursor::new(&test_chd.data[..])))
+            .expect("open");
+        chd.inner()
+            .fault(test_chd.hunk_offsets[0], Fault::Error(ErrorKind::Other));
+
+        // seeking and reading past the end does not read the hunk.
+        let mut hunk = chd.hunk(0).expect("hunk");
+        let mut reader = HunkBufReader::new(&mut hunk).expect("reader");
+        assert_eq!(512, reader.seek(SeekFrom::End(0)).expect("seek"));
+        assert_eq!(0, reader.read(&mut [0; 16]).expect("read"));
+        assert!(reader.fill_buf().expect("fill").is_empty());
+        reader.seek(SeekFrom::Start(500)).expect("seek");
+        assert!(reader.read(&mut [0; 16]).is_err());
+
+        let mut hunk = chd.hunk(1).expect("hunk");
+        let mut cmp_buf = Vec::new();
+        let mut reader =
+            HunkBufReader::new_in(&mut hunk, &mut cmp_buf, Vec::new()).expect("reader");
+        reader.seek(SeekFrom::End(-4)).expect("seek");
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).expect("read");
+        assert_eq!(vec![2; 4], tail);
+        assert_eq!(vec![2; 512], reader.into_inner());
+    }
+
+    #[test]
+    fn copy_range_test() {
+        let hunks: Vec<_> = (0..4u8).map(|i| Some(vec![i; 512])).collect();
+        let chd = v5_uncompressed_chd(512, &hunks, None);
+        let chd = ChdOpenOptions::new()
+            .open(Cursor::new(chd.data))
+            .expect("open");
+        let mut reader = ChdReader::new(chd);
+
+        let mut out = Vec::new();
+        assert_eq!(
+            600,
+            reader.copy_range(1000, Some(600), &mut out).expect("copy")
+        );
+        assert_eq!(vec![1; 24], out[..24]);
+        assert_eq!(vec![2; 512], out[24..536]);
+        assert_eq!(vec![3; 64], out[536..]);
+
+        // windows past the end of the image are truncated.
+        let mut out = Vec::new();
+        assert_eq!(48, reader.copy_range(2000, None, &mut out).expect("copy"));
+        assert_eq!(vec![3; 48], out);
+        assert_eq!(
+            0,
+            reader.copy_range(4096, Some(16), &mut out).expect("copy")
+        );
+    }
+
+    #[test]
+    fn logical_position_test() {
+        let hunks: Vec<_> = (0..4u8).map(|i| Some(vec![i; 512])).collect();
+        let chd = v5_uncompressed_chd(512, &hunks, None);
+        let chd = ChdOpenOptions::new()
+            .open(Cursor::new(chd.data))
+            .expect("open");
+        let mut reader = ChdReader::new(chd);
+        assert_eq!(2048, reader.logical_len());
+        assert_eq!(0, reader.logical_position());
+
+        let mut buf = [0u8; 100];
+        reader.seek(SeekFrom::Start(700)).expect("seek");
+        reader.read_exact(&mut buf).expect("read");
+        assert_eq!(800, reader.logical_position());
+        assert_eq!(800, reader.stream_position().expect("position"));
+
+        assert_eq!(512, reader.rewind_to_hunk(1).expect("rewind"));
+        reader.read_exact(&mut buf).expect("read");
+        assert_eq!([1; 100], buf);
+        assert_eq!(1536, reader.rewind_to_hunk(3).expect("rewind"));
+        reader.read_exact(&mut buf).expect("read");
+        assert_eq!([3; 100], buf);
+
+        assert!(matches!(
+            reader.rewind_to_hunk(4),
+            Err(crate::Error::HunkOutOfRange)
+        ));
+        assert_eq!(1636, reader.logical_position());
+    }
+
+    #[test]
+    fn hunk_count_mismatch_test() {
+        let mut data = v3_chd(
+            512,
+            &[
+                LegacyHunk::Uncompressed(vec![1; 512]),
+                LegacyHunk::Uncompressed(vec![2; 512]),
+            ],
+            false,
+        )
+        .data;
+        // the logical size implies a third hunk that is missing from the map.
+        data[28..36].copy_from_slice(&(2 * 512 + 100u64).to_be_bytes());
+
+        assert!(matches!(
+            Chd::open(Cursor::new(data.clone()), None),
+            Err(Error::HunkCountMismatch)
+        ));
+
+        let mut chd = ChdOpenOptions::new()
+            .lenient(true)
+            .open(Cursor::new(data))
+            .expect("open");
+        assert_eq!(1124, chd.header().logical_bytes());
+        assert_eq!(1024, chd.header().readable_bytes());
+        assert!(matches!(chd.hunk(2), Err(Error::HunkOutOfRange)));
+
+        // the logical data is truncated to the end of the last hunk.
+        let mut reader = ChdReader::new(chd);
+        assert_eq!(1024, reader.logical_len());
+        let mut out = Vec::new();
+        assert_eq!(1024, reader.read_to_end(&mut out).expect("read"));
+        assert_eq!(vec![2; 512], out[512..]);
+        assert_eq!(1024, reader.seek(SeekFrom::Start(1100)).expect("seek"));
+        assert_eq!(0, reader.read(&mut [0; 16]).expect("read"));
+    }
+
+    #[test]
+    fn raw_hunk_read_test() {
+        // hunks 0, 1, 3 and 4 are stored in order, but hunk 4 is changed to be a copy of hunk 0.
+        let hunks: Vec<_> = (1..=6u8)
+            .map(|i| (i % 3 != 0).then(|| vec![i; 512]))
+            .collect();
+        let parent_hunks: Vec<_> = (1..=6u8).map(|i| Some(vec![0xa0 + i; 512])).collect();
+
+        for has_parent in [false, true] {
+            let mut data = v5_uncompressed_chd(512, &hunks, has_parent.then_some([1; 20])).data;
+            data.copy_within(124..128, 124 + 4 * 4);
+            let open = || {
+                let options = ChdOpenOptions::new().validate_parent(false);
+                let chd = match has_parent {
+                    // unallocated hunks are read from the parent.
+                    true => {
+                        let parent = v5_uncompressed_chd(512, &parent_hunks, None).data;
+                        let parent = ChdOpenOptions::new()
+                            .open(Cursor::new(parent))
+                            .expect("open parent");
+                        options
+                            .parent(Box::new(parent))
+                            .open(Cursor::new(data.clone()))
+                    }
+                    false => options.open(Cursor::new(data.clone())),
+                };
+                chd.expect("open")
+            };
+
+            let mut chd = open();
+            let mut expected = Vec::new();
+            let mut buf = chd.get_hunksized_buffer();
+            let mut cmp_buf = Vec::new();
+            for hunk_num in 0..chd.header().hunk_count() {
+                chd.hunk(hunk_num)
+                    .and_then(|mut h| h.read_hunk_in(&mut cmp_buf, &mut buf))
+                    .expect("hunk");
+                expected.extend_from_slice(&buf);
+            }
+            assert_eq!(vec![1; 512], expected[4 * 512..5 * 512]);
+            let unallocated = if has_parent { 0xa3 } else { 0 };
+            assert_eq!(vec![unallocated; 512], expected[2 * 512..3 * 512]);
+
+            for read_ahead in [1, 4, 8] {
+                let mut reader = ChdReader::with_read_ahead(open(), read_ahead);
+                let mut actual = Vec::new();
+                reader.read_to_end(&mut actual).expect("read");
+                assert_eq!(expected, actual, "read ahead {}", read_ahead);
+            }
+        }
+    }
 }
@@ -0,0 +1,463 @@
+//! Repair of recoverable corruptions in CHD files.
+//!
+//! [`repair`](crate::repair::repair) reads every hunk of a CHD file and fixes the corruptions that
+//! can be fixed without recompressing the file.
+//!
+//! * The SHA1 hashes of the header are recomputed from the hunk data and metadata.
+//! * A V5 compressed map that does not match its checksum is re-encoded from its decoded entries.
+//! * Hunks that can not be read are replaced by a zero-filled hunk. This loses the data of those
+//!   hunks, but makes the rest of the file readable by tools that refuse files with bad hunks.
+//!
+//! Repairs never overwrite hunk data. Zero-filled hunks and a re-encoded map are appended to
+//! the end of the file, and only the header and the entries of an uncompressed V5 map are
+//! written in place. Because a compressed map does not record where each hunk is stored,
+//! zero-filling a file with a compressed map copies all of its hunk data to the end of the file,
+//! which doubles its size. The file can be compacted afterwards by recompressing it.
+//!
+//! A compressed map that does not match its checksum can not be read in full, so the file
+//! should be opened with [`ChdOpenOptions::lazy_map`](crate::ChdOpenOptions::lazy_map) and
+//! [`ChdOpenOptions::lenient`](crate::ChdOpenOptions::lenient) before it is repaired.
+//!
+//! ```rust,no_run
+//! # use std::fs::OpenOptions;
+//! # use chd::ChdOpenOptions;
+//! # use chd::repair::{repair, RepairOptions};
+//! # fn main() -> chd::Result<()> {
+//! # let file = OpenOptions::new().read(true).write(true).open("damaged.chd")?;
+//! let chd = ChdOpenOptions::new().lazy_map(true).lenient(true).open(file)?;
+//! let report = repair(chd, RepairOptions::new().fix_hashes(true).zero_fill(true))?;
+//! for (hunk_num, err) in &report.unreadable_hunks {
+//!     eprintln!("hunk {} was replaced with zeroes: {}", hunk_num, err);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::block_hash::crc16;
+use crate::error::{Error, Result};
+use crate::header::{Header, SHA1_BYTES};
+use crate::map::{Map, MapEntry};
+use crate::write::{compress_v5_map, overall_sha1, V5MapEntry};
+use crate::Chd;
+use byteorder::{BigEndian, WriteBytesExt};
+use sha1::{Digest, Sha1};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const MAP_TYPE_NONE: u8 = 4;
+const MAP_TYPE_SELF: u8 = 5;
+const MAP_TYPE_PARENT: u8 = 6;
+
+/// Options for [`repair`](crate::repair::repair).
+///
+/// By default no repairs are made, and the file is only checked for unreadable hunks.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RepairOptions {
+    fix_hashes: bool,
+    rebuild_map: bool,
+    zero_fill: bool,
+}
+
+impl RepairOptions {
+    /// Creates options that make no repairs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Recompute the SHA1 hashes of the header from the hunk data and metadata, and write them to
+    /// the header if they differ.
+    ///
+    /// The hashes are only fixed if every hunk could be read, or unreadable hunks were zero-filled.
+    /// V1 and V2 headers have no SHA1 to fix.
+    pub fn fix_hashes(&mut self, fix_hashes: bool) -> &mut Self {
+        self.fix_hashes = fix_hashes;
+        self
+    }
+
+    /// Re-encode a V5 compressed map that fails [`Map::self_check`](crate::map::Map::self_check)
+    /// from its decoded entries.
+    pub fn rebuild_map(&mut self, rebuild_map: bool) -> &mut Self {
+        self.rebuild_map = rebuild_map;
+        self
+    }
+
+    /// Replace hunks that can not be read with a zero-filled hunk.
+    ///
+    /// Only V5 CHD files can be zero-filled. Repairing a V1-4 CHD file with unreadable hunks
+    /// returns [`Error::NotSupported`](crate::Error::NotSupported) before anything is written.
+    pub fn zero_fill(&mut self, zero_fill: bool) -> &mut Self {
+        self.zero_fill = zero_fill;
+        self
+    }
+}
+
+/// The result of [`repair`](crate::repair::repair).
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// The hunks that could not be read, with the error reading them.
+    pub unreadable_hunks: Vec<(u32, Error)>,
+    /// Whether the unreadable hunks were replaced with a zero-filled hunk.
+    pub zero_filled: bool,
+    /// Whether the compressed map was re-encoded.
+    pub map_rebuilt: bool,
+    /// The SHA1 of the hunk data, if every hunk could be read or was zero-filled.
+    pub raw_sha1: Option<[u8; SHA1_BYTES]>,
+    /// Whether the SHA1 hashes of the header were changed.
+    pub hashes_fixed: bool,
+}
+
+/// Reads every hunk of the CHD file and repairs it in place according to the provided options.
+///
+/// The parent of a CHD file that requires one must be provided, otherwise
+/// [`Error::RequiresParent`](crate::Error::RequiresParent) is returned. The parent is only read,
+/// and is never repaired.
+pub fn repair<F: Read + Write + Seek, P: Read + Seek>(
+    mut chd: Chd<F, P>,
+    options: &RepairOptions,
+) -> Result<RepairReport> {
+    if chd.header().has_parent() && chd.parent_chain().next().is_none() {
+        return Err(Error::RequiresParent);
+    }
+
+    let mut report = RepairReport::default();
+    let mut hasher = Sha1::new();
+    let mut hunk_buf = chd.get_hunksized_buffer();
+    let mut cmp_buf = Vec::new();
+    let mut remaining = chd.header().logical_bytes();
+    for hunk_num in 0..chd.header().hunk_count() {
+        if let Err(err) = chd
+            .hunk(hunk_num)?
+            .read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+        {
+            report.unreadable_hunks.push((hunk_num, err));
+            hunk_buf.fill(0);
+        }
+        let len = remaining.min(hunk_buf.len() as u64);
+        hasher.update(&hunk_buf[..len as usize]);
+        remaining -= len;
+    }
+
+    let mut header = chd.header().clone();
+    let zero_fill = options.zero_fill && !report.unreadable_hunks.is_empty();
+    let is_v5 = matches!(header, Header::V5Header(_));
+    if zero_fill && !is_v5 {
+        return Err(Error::NotSupported);
+    }
+
+    let metadata =
+        if options.fix_hashes && matches!(header, Header::V4Header(_) | Header::V5Header(_)) {
+            Some(chd.metadata_refs().try_into_vec()?)
+        } else {
+            None
+        };
+
+    let mut header_changed = false;
+    if is_v5 && header.is_compressed() {
+        let rebuild = options.rebuild_map && chd.map().self_check().is_err();
+        if zero_fill || rebuild {
+            let zero_filled: &[_] = if zero_fill {
+                &report.unreadable_hunks
+            } else {
+                &[]
+            };
+            let entries = map_entries(chd.map(), zero_filled)?;
+            let entries = if zero_fill {
+                copy_with_zero_fill(chd.inner(), &entries, header.hunk_size())?
+            } else {
+                entries.into_iter().flatten().collect()
+            };
+            let file = chd.inner();
+            let map_offset = file.seek(SeekFrom::End(0))?;
+            file.write_all(&compress_v5_map(&entries, map_offset))?;
+            if let Header::V5Header(v5) = &mut header {
+                v5.map_offset = map_offset;
+            }
+            header_changed = true;
+            report.map_rebuilt = rebuild;
+        }
+    } else if zero_fill {
+        let map_offset = header.map_offset();
+        let hunk_bytes = header.hunk_size();
+        let file = chd.inner();
+        let offset = append_zero_hunk(file, hunk_bytes)?;
+        let entry =
+            u32::try_from(offset / hunk_bytes as u64).map_err(|_| Error::InvalidParameter)?;
+        for (hunk_num, _) in &report.unreadable_hunks {
+            file.seek(SeekFrom::Start(map_offset + 4 * *hunk_num as u64))?;
+            file.write_u32::<BigEndian>(entry)?;
+        }
+    }
+    report.zero_filled = zero_fill;
+
+    if report.unreadable_hunks.is_empty() || zero_fill {
+        let raw_sha1: [u8; SHA1_BYTES] = hasher.finalize().into();
+        report.raw_sha1 = Some(raw_sha1);
+        if options.fix_hashes {
+            let sha1 = match &metadata {
+                Some(metadata) => overall_sha1(&raw_sha1, metadata),
+                None => raw_sha1,
+            };
+            if header.raw_sha1().filter(|&h| h != raw_sha1).is_some()
+                || header.sha1().filter(|&h| h != sha1).is_some()
+            {
+                header.set_raw_sha1(raw_sha1)?;
+                header.set_sha1(sha1)?;
+                header_changed = true;
+                report.hashes_fixed = true;
+            }
+        }
+    }
+
+    if header_changed {
+        header.write_to(chd.inner())?;
+    }
+    chd.inner().flush()?;
+    Ok(report)
+}
+
+/// Appends a zero-filled hunk at the next multiple of `hunk_bytes` past the end of the file,
+/// returning its offset.
+fn append_zero_hunk<F: Write + Seek>(file: &mut F, hunk_bytes: u32) -> Result<u64> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let offset = end + (hunk_bytes as u64 - end % hunk_bytes as u64) % hunk_bytes as u64;
+    file.write_all(&vec![0u8; (offset - end) as usize + hunk_bytes as usize])?;
+    Ok(offset)
+}
+
+/// Decodes the entries of a V5 compressed map. The entries of the hunks in `zero_filled` are
+/// not decoded, and are `None`.
+fn map_entries(map: &Map, zero_filled: &[(u32, Error)]) -> Result<Vec<Option<V5MapEntry>>> {
+    let mut zero_filled = zero_filled
+        .iter()
+        .map(|(hunk_num, _)| *hunk_num as usize)
+        .peekable();
+    let mut entries = Vec::with_capacity(map.len());
+    for (hunk_num, entry) in map.iter().enumerate() {
+        if zero_filled.next_if_eq(&hunk_num).is_some() {
+            entries.push(None);
+            continue;
+        }
+        let entry = match entry {
+            MapEntry::V5Compressed(entry) => entry,
+            _ => return Err(Error::InvalidMap),
+        };
+        entries.push(Some(V5MapEntry {
+            hunk_type: entry.hunk_type().map_err(|_| Error::InvalidMap)? as u8,
+            length: entry.block_size()?,
+            offset: entry.block_offset()?,
+            crc: entry.hunk_crc()?,
+        }));
+    }
+    Ok(entries)
+}
+
+/// Copies the stored hunk data to the end of the file, replacing the hunks without an entry
+/// with a zero-filled hunk, and returns the entries of the copied hunks.
+///
+/// The offsets of stored hunks are not recorded in a compressed map, but follow from the
+/// lengths of the hunks before them, so a zero-filled hunk can not be added without moving
+/// every stored hunk. Only the first zero-filled hunk is stored, the others refer to it.
+fn copy_with_zero_fill<F: Read + Write + Seek>(
+    file: &mut F,
+    entries: &[Option<V5MapEntry>],
+    hunk_bytes: u32,
+) -> Result<Vec<V5MapEntry>> {
+    let mut offset = file.seek(SeekFrom::End(0))?;
+    let mut zero_hunk = None;
+    let mut block = Vec::new();
+    let mut copied = Vec::with_capacity(entries.len());
+    for (hunk_num, entry) in entries.iter().enumerate() {
+        let mut entry = match (entry, zero_hunk) {
+            (Some(entry), _)
+                if entry.hunk_type == MAP_TYPE_SELF || entry.hunk_type == MAP_TYPE_PARENT =>
+            {
+                copied.push(*entry);
+                continue;
+            }
+            (Some(entry), _) => {
+                block.resize(entry.length as usize, 0);
+                file.seek(SeekFrom::Start(entry.offset))?;
+                file.read_exact(&mut block)?;
+                *entry
+            }
+            (None, Some(zero_hunk)) => {
+                copied.push(V5MapEntry {
+                    hunk_type: MAP_TYPE_SELF,
+                    length: 0,
+                    offset: zero_hunk,
+                    crc: 0,
+                });
+                continue;
+            }
+            (None, None) => {
+                zero_hunk = Some(hunk_num as u64);
+                block.clear();
+                block.resize(hunk_bytes as usize, 0);
+                V5MapEntry {
+                    hunk_type: MAP_TYPE_NONE,
+                    length: hunk_bytes,
+                    offset: 0,
+                    crc: crc16(&block),
+                }
+            }
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&block)?;
+        entry.offset = offset;
+        offset += entry.length as u64;
+        copied.push(entry);
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::repair::{repair, RepairOptions};
+    use crate::testing::{v5_chd, v5_uncompressed_chd, V5Hunk};
+    use crate::{Chd, ChdOpenOptions, Error};
+    use sha1::{Digest, Sha1};
+    use std::io::Cursor;
+
+    fn hunk(fill: u8) -> Vec<u8> {
+        (0..4096u32).map(|i| (i as u8) ^ fill).collect()
+    }
+
+    fn read_all(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut chd = Chd::open(Cursor::new(data), None).expect("open");
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        (0..chd.header().hunk_count())
+            .map(|hunk_num| {
+                chd.hunk(hunk_num)
+                    .and_then(|mut hunk| hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf))
+                    .expect("read hunk");
+                hunk_buf.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fix_hashes_test() {
+        let mut data = v5_chd(
+            4096,
+            512,
+            &[V5Hunk::Zlib(hunk(0)), V5Hunk::Zlib(hunk(1))],
+            None,
+        )
+        .data;
+        let chd = Chd::open(Cursor::new(&mut data), None).expect("open");
+        let report = repair(chd, RepairOptions::new().fix_hashes(true)).expect("repair");
+        assert!(report.hashes_fixed);
+        assert!(report.unreadable_hunks.is_empty());
+
+        let raw: Vec<u8> = [hunk(0), hunk(1)].concat();
+        let raw_sha1: [u8; 20] = Sha1::digest(&raw).into();
+        let chd = Chd::open(Cursor::new(&data), None).expect("reopen");
+        assert_eq!(Some(raw_sha1), chd.header().raw_sha1());
+        assert_eq!(report.raw_sha1, Some(raw_sha1));
+
+        let chd = Chd::open(Cursor::new(&mut data), None).expect("open");
+        let report = repair(chd, RepairOptions::new().fix_hashes(true)).expect("repair");
+        assert!(!report.hashes_fixed);
+    }
+
+    #[test]
+    fn zero_fill_compressed_test() {
+        let chd = v5_chd(
+            4096,
+            512,
+            &[
+                V5Hunk::Zlib(hunk(0)),
+                V5Hunk::Zlib(hunk(1)),
+                V5Hunk::Zlib(hunk(2)),
+                V5Hunk::Zlib(hunk(3)),
+            ],
+            None,
+        );
+        let mut data = chd.data;
+        data[chd.hunk_offsets[1] as usize] ^= 0xff;
+        data[chd.hunk_offsets[3] as usize] ^= 0xff;
+
+        let chd = Chd::open(Cursor::new(&mut data), None).expect("open");
+        let report = repair(chd, &RepairOptions::new()).expect("check");
+        assert_eq!(
+            vec![1, 3],
+            report
+                .unreadable_hunks
+                .iter()
+                .map(|(h, _)| *h)
+                .collect::<Vec<_>>()
+        );
+        assert!(!report.zero_filled);
+        assert_eq!(None, report.raw_sha1);
+
+        let chd = Chd::open(Cursor::new(&mut data), None).expect("open");
+        let report =
+            repair(chd, RepairOptions::new().zero_fill(true).fix_hashes(true)).expect("repair");
+        assert!(report.zero_filled);
+        assert!(report.hashes_fixed);
+
+        let zeroes = vec![0; 4096];
+        assert_eq!(
+            vec![hunk(0), zeroes.clone(), hunk(2), zeroes.clone()],
+            read_all(&data)
+        );
+        let raw: Vec<u8> = [hunk(0), zeroes.clone(), hunk(2), zeroes].concat();
+        let chd = Chd::open(Cursor::new(&data), None).expect("reopen");
+        assert_eq!(Some(Sha1::digest(&raw).into()), chd.header().raw_sha1());
+    }
+
+    #[test]
+    fn zero_fill_uncompressed_test() {
+        let mut data = v5_uncompressed_chd(4096, &[Some(hunk(0)), Some(hunk(1))], None).data;
+        data.truncate(data.len() - 1024);
+
+        let chd = ChdOpenOptions::new()
+            .lazy_map(true)
+            .lenient(true)
+            .open(Cursor::new(&mut data))
+            .expect("lazy open");
+        let report = repair(chd, RepairOptions::new().zero_fill(true)).expect("repair");
+        assert_eq!(
+            vec![1],
+            report
+                .unreadable_hunks
+                .iter()
+                .map(|(h, _)| *h)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(0, data.len() % 4096);
+        assert_eq!(vec![hunk(0), vec![0; 4096]], read_all(&data));
+    }
+
+    #[test]
+    fn rebuild_map_test() {
+        let mut data = v5_chd(
+            4096,
+            512,
+            &[V5Hunk::Zlib(hunk(0)), V5Hunk::SelfHunk(0)],
+            None,
+        )
+        .data;
+        let map_offset = u64::from_be_bytes(data[40..48].try_into().unwrap()) as usize;
+        data[map_offset + 10] ^= 0xff;
+        assert!(Chd::open(Cursor::new(&data), None).is_err());
+
+        let chd = ChdOpenOptions::new()
+            .lazy_map(true)
+            .lenient(true)
+            .open(Cursor::new(&mut data))
+            .expect("lazy open");
+        let report = repair(chd, RepairOptions::new().rebuild_map(true)).expect("repair");
+        assert!(report.map_rebuilt);
+        assert_eq!(vec![hunk(0), hunk(0)], read_all(&data));
+    }
+
+    #[test]
+    fn requires_parent_test() {
+        let mut data = v5_chd(4096, 512, &[V5Hunk::Parent(0)], Some([1; 20])).data;
+        let chd = Chd::open(Cursor::new(&mut data), None).expect("open");
+        assert!(matches!(
+            repair(chd, &RepairOptions::new()),
+            Err(Error::RequiresParent)
+        ));
+    }
+}
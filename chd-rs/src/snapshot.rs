@@ -0,0 +1,267 @@
+//! A stable, serializable summary of a CHD file.
+//!
+//! [`FileSnapshot`](crate::snapshot::FileSnapshot) captures the header fields, hashes, compression
+//! codecs, hunk map statistics, and optionally the digests of the metadata entries of a CHD file
+//! into plain data that can be serialized with [serde](https://serde.rs), stored, and later compared.
+//! Hashes are stored as lowercase hexadecimal strings, and codecs as their FourCC strings.
+//!
+//! The layout of a snapshot is versioned with [`FileSnapshot::snapshot_version`](crate::snapshot::FileSnapshot::snapshot_version).
+//! Fields are only ever added in a new snapshot version, and existing fields will not change meaning.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::snapshot::FileSnapshot;
+//!
+//! let mut f = BufReader::new(File::open("file.chd")?);
+//! let mut chd = Chd::open(&mut f, None)?;
+//! let snapshot = FileSnapshot::with_metadata(&mut chd)?;
+//! println!("{:?} {:?}", snapshot.sha1, snapshot.codecs);
+//! ```
+use crate::error::Result;
+use crate::header::Header;
+use crate::map::{CompressionTypeLegacy, CompressionTypeV5, MapEntry};
+use crate::metadata::MetadataTag;
+use crate::{Chd, Tag};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek};
+
+/// The current version of the [`FileSnapshot`](crate::snapshot::FileSnapshot) layout.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A serializable summary of a CHD file.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    /// The version of the snapshot layout. See [`SNAPSHOT_VERSION`](crate::snapshot::SNAPSHOT_VERSION).
+    pub snapshot_version: u32,
+    /// The CHD header version.
+    pub version: u32,
+    /// The length of the header in bytes.
+    pub header_length: u32,
+    /// The flags of the CHD file, if the header version has flags.
+    pub flags: Option<u32>,
+    /// Whether the CHD file is compressed.
+    pub compressed: bool,
+    /// Whether the CHD file requires a parent.
+    pub has_parent: bool,
+    /// The logical size of the uncompressed data in bytes.
+    pub logical_bytes: u64,
+    /// The number of bytes in each hunk.
+    pub hunk_size: u32,
+    /// The total number of hunks.
+    pub hunk_count: u32,
+    /// The number of bytes in each unit.
+    pub unit_bytes: u32,
+    /// The total number of units.
+    pub unit_count: u64,
+    /// The SHA1 hash of the CHD file, if present.
+    pub sha1: Option<String>,
+    /// The SHA1 hash of the raw uncompressed data, if present.
+    pub raw_sha1: Option<String>,
+    /// The SHA1 hash of the parent CHD file, if present.
+    pub parent_sha1: Option<String>,
+    /// The MD5 hash of the CHD file, if present.
+    pub md5: Option<String>,
+    /// The MD5 hash of the parent CHD file, if present.
+    pub parent_md5: Option<String>,
+    /// The FourCC strings of the compression codecs of the CHD file, in order.
+    ///
+    /// Legacy (V1-4) codecs are named `zlib`, `zlib+`, and `av`. Unused codec slots are omitted.
+    pub codecs: Vec<String>,
+    /// Statistics of the hunk map.
+    pub map: MapStatistics,
+    /// Digests of the metadata entries, in order.
+    ///
+    /// This is empty if the snapshot was created without reading metadata.
+    pub metadata: Vec<MetadataDigest>,
+}
+
+/// Statistics of the kinds of hunks in the hunk map of a CHD file.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MapStatistics {
+    /// The number of hunks compressed with each codec, indexed by codec slot.
+    pub compressed_hunks: [u32; 4],
+    /// The number of hunks stored uncompressed.
+    pub uncompressed_hunks: u32,
+    /// The number of hunks that are copies of another hunk in the same CHD file.
+    pub self_hunks: u32,
+    /// The number of hunks that refer to data in the parent CHD file.
+    pub parent_hunks: u32,
+    /// The number of hunks stored in the map entry itself.
+    pub mini_hunks: u32,
+    /// The number of hunks that are not allocated in an uncompressed CHD file, and read as zeroes.
+    pub unallocated_hunks: u32,
+    /// The number of hunks with an invalid or unsupported type.
+    pub invalid_hunks: u32,
+    /// The total number of bytes of hunk data stored in the CHD file.
+    pub stored_bytes: u64,
+}
+
+/// A digest of a single metadata entry.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MetadataDigest {
+    /// The FourCC metadata tag.
+    pub tag: String,
+    /// The index of this metadata entry among the entries with the same tag.
+    pub index: u32,
+    /// The flags of this metadata entry.
+    pub flags: u8,
+    /// The length of this metadata entry.
+    pub length: u32,
+    /// The CRC32 of the contents of this metadata entry.
+    pub crc32: u32,
+}
+
+impl FileSnapshot {
+    /// Creates a snapshot of the CHD file that includes the digests of its metadata entries.
+    ///
+    /// Unlike the `From<&Chd<F, P>>` implementation, this reads every metadata entry from the CHD file.
+    pub fn with_metadata<F: Read + Seek, P: Read + Seek>(
+        chd: &mut Chd<F, P>,
+    ) -> Result<FileSnapshot> {
+        let mut snapshot = FileSnapshot::from(&*chd);
+        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        let refs: Vec<_> = chd.metadata_refs().collect();
+        for meta_ref in refs {
+            let meta = meta_ref.read(chd.inner())?;
+            snapshot.metadata.push(MetadataDigest {
+                tag: fourcc_string(meta.metatag()),
+                index: meta.index,
+                flags: meta.flags,
+                length: meta.length,
+                crc32: crc.checksum(&meta.value),
+            });
+        }
+        Ok(snapshot)
+    }
+}
+
+impl<F: Read + Seek, P: Read + Seek> From<&Chd<F, P>> for FileSnapshot {
+    /// Creates a snapshot of the CHD file from its header and hunk map.
+    ///
+    /// Metadata entries are not read, and [`FileSnapshot::metadata`](crate::snapshot::FileSnapshot::metadata)
+    /// is empty. Use [`FileSnapshot::with_metadata`](crate::snapshot::FileSnapshot::with_metadata) to
+    /// include metadata digests.
+    fn from(chd: &Chd<F, P>) -> Self {
+        let header = chd.header();
+        FileSnapshot {
+            snapshot_version: SNAPSHOT_VERSION,
+            version: header.version() as u32,
+            header_length: header.len(),
+            flags: header.flags(),
+            compressed: header.is_compressed(),
+            has_parent: header.has_parent(),
+            logical_bytes: header.logical_bytes(),
+            hunk_size: header.hunk_size(),
+            hunk_count: header.hunk_count(),
+            unit_bytes: header.unit_bytes(),
+            unit_count: header.unit_count(),
+            sha1: header.sha1().map(|h| hex_string(&h)),
+            raw_sha1: header.raw_sha1().map(|h| hex_string(&h)),
+            parent_sha1: header.parent_sha1().map(|h| hex_string(&h)),
+            md5: header.md5().map(|h| hex_string(&h)),
+            parent_md5: header.parent_md5().map(|h| hex_string(&h)),
+            codecs: codec_names(header),
+            map: map_statistics(chd),
+            metadata: Vec::new(),
+        }
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn fourcc_string(tag: u32) -> String {
+    Tag::from(tag).to_string()
+}
+
+fn codec_names(header: &Header) -> Vec<String> {
+    fn legacy_name(compression: u32) -> Option<String> {
+        match compression {
+            0 => None,
+            1 => Some("zlib".into()),
+            2 => Some("zlib+".into()),
+            3 => Some("av".into()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    match header {
+        Header::V1Header(h) | Header::V2Header(h) => {
+            legacy_name(h.compression).into_iter().collect()
+        }
+        Header::V3Header(h) => legacy_name(h.compression).into_iter().collect(),
+        Header::V4Header(h) => legacy_name(h.compression).into_iter().collect(),
+        Header::V5Header(h) => h
+            .compression
+            .iter()
+            .filter(|&&c| c != 0)
+            .map(|&c| fourcc_string(c))
+            .collect(),
+    }
+}
+
+fn map_statistics<F: Read + Seek, P: Read + Seek>(chd: &Chd<F, P>) -> MapStatistics {
+    let mut stats = MapStatistics::default();
+    let has_parent = chd.header().has_parent();
+    for entry in chd.map().iter() {
+        match entry {
+            MapEntry::V5Compressed(entry) => match entry.hunk_type() {
+                Ok(
+                    ty @ (CompressionTypeV5::CompressionType0
+                    | CompressionTypeV5::CompressionType1
+                    | CompressionTypeV5::CompressionType2
+                    | CompressionTypeV5::CompressionType3),
+                ) => {
+                    stats.compressed_hunks[ty as usize] += 1;
+                    stats.stored_bytes += entry.block_size().unwrap_or(0) as u64;
+                }
+                Ok(CompressionTypeV5::CompressionNone) => {
+                    stats.uncompressed_hunks += 1;
+                    stats.stored_bytes += entry.block_size().unwrap_or(0) as u64;
+                }
+                Ok(CompressionTypeV5::CompressionSelf) => stats.self_hunks += 1,
+                Ok(CompressionTypeV5::CompressionParent) => stats.parent_hunks += 1,
+                _ => stats.invalid_hunks += 1,
+            },
+            MapEntry::V5Uncompressed(entry) => match entry.block_offset() {
+                Ok(0) if has_parent => stats.parent_hunks += 1,
+                Ok(0) => stats.unallocated_hunks += 1,
+                Ok(_) => {
+                    stats.uncompressed_hunks += 1;
+                    stats.stored_bytes += entry.block_size() as u64;
+                }
+                Err(_) => stats.invalid_hunks += 1,
+            },
+            MapEntry::LegacyEntry(entry) => match entry.hunk_type() {
+                Ok(CompressionTypeLegacy::Compressed) => {
+                    stats.compressed_hunks[0] += 1;
+                    stats.stored_bytes += entry.block_size() as u64;
+                }
+                Ok(CompressionTypeLegacy::Uncompressed) => {
+                    stats.uncompressed_hunks += 1;
+                    stats.stored_bytes += entry.block_size() as u64;
+                }
+                Ok(CompressionTypeLegacy::Mini) => stats.mini_hunks += 1,
+                Ok(CompressionTypeLegacy::SelfHunk) => stats.self_hunks += 1,
+                Ok(CompressionTypeLegacy::ParentHunk) => stats.parent_hunks += 1,
+                _ => stats.invalid_hunks += 1,
+            },
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod test {
+    use crate::snapshot::{fourcc_string, hex_string};
+
+    #[test]
+    fn snapshot_string_test() {
+        assert_eq!("cdlz", fourcc_string(crate::make_tag(b"cdlz")));
+        assert_eq!("00ff1a", hex_string(&[0x00, 0xff, 0x1a]));
+    }
+}
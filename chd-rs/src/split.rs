@@ -0,0 +1,453 @@
+//! Splitting the logical image of a CHD file into parts, and concatenating the parts back together.
+//!
+//! [`split`](crate::split::split) writes the decompressed logical image of a CHD file as a sequence of raw parts
+//! of at most a given size, such as to fit on media with a limited file size like FAT32. The SHA1 of every part
+//! and of the whole image is recorded in a [`SplitManifest`](crate::split::SplitManifest), which can be written
+//! alongside the parts as a text file.
+//!
+//! [`concat`](crate::split::concat) joins the parts back into the logical image, verifying every part and the
+//! whole image against the manifest.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use chd::Chd;
+//! use chd::split::{concat, split};
+//!
+//! let mut chd = Chd::open_path("disk.chd", None)?;
+//! let manifest = split(&mut chd, 0xFFFF_FFFF, "disk.raw", File::create)?;
+//! manifest.write_to(&mut File::create("disk.raw.manifest")?)?;
+//!
+//! let mut output = File::create("disk.raw")?;
+//! let report = concat(&manifest, |part| File::open(&part.name), &mut output)?;
+//! assert!(report.is_valid());
+//! ```
+use crate::error::{Error, Result};
+use crate::header::SHA1_BYTES;
+use crate::Chd;
+use sha1::{Digest, Sha1};
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+
+/// The first line of a manifest written by [`SplitManifest::write_to`](crate::split::SplitManifest::write_to).
+const MANIFEST_MAGIC: &str = "chd-rs split manifest 1";
+
+/// A part of the logical image of a CHD file, as recorded in a [`SplitManifest`](crate::split::SplitManifest).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SplitPart {
+    /// The file name of the part.
+    pub name: String,
+    /// The offset of the part in the logical image.
+    pub offset: u64,
+    /// The length of the part in bytes.
+    pub len: u64,
+    /// The SHA1 of the contents of the part.
+    pub sha1: [u8; SHA1_BYTES],
+}
+
+/// A record of the parts a CHD file was split into by [`split`](crate::split::split).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SplitManifest {
+    /// The SHA1 recorded in the header of the CHD file the parts were split from, if it has one.
+    pub chd_sha1: Option<[u8; SHA1_BYTES]>,
+    /// The length of the logical image in bytes.
+    pub len: u64,
+    /// The SHA1 of the whole logical image.
+    pub sha1: [u8; SHA1_BYTES],
+    /// The parts of the logical image, in order.
+    pub parts: Vec<SplitPart>,
+}
+
+impl SplitManifest {
+    /// Writes the manifest as text to `output`.
+    ///
+    /// Every field of the manifest is written on its own line, and each part is written
+    /// on a line with its offset, length, SHA1 and name.
+    pub fn write_to<W: Write + ?Sized>(&self, output: &mut W) -> std::io::Result<()> {
+        writeln!(output, "{}", MANIFEST_MAGIC)?;
+        if let Some(chd_sha1) = self.chd_sha1 {
+            writeln!(output, "chd-sha1 {}", hex(&chd_sha1))?;
+        }
+        writeln!(output, "size {}", self.len)?;
+        writeln!(output, "sha1 {}", hex(&self.sha1))?;
+        for part in &self.parts {
+            writeln!(
+                output,
+                "part {} {} {} {}",
+                part.offset,
+                part.len,
+                hex(&part.sha1),
+                part.name
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reads a manifest written by [`SplitManifest::write_to`](crate::split::SplitManifest::write_to).
+    ///
+    /// If the manifest is malformed, its parts do not cover the logical image in order, or the name
+    /// of a part is not a plain file name, returns [`Error::InvalidData`](crate::Error::InvalidData).
+    pub fn read_from<R: Read>(input: R) -> Result<SplitManifest> {
+        let mut lines = BufReader::new(input).lines();
+        let magic = lines.next().ok_or(Error::InvalidData)??;
+        if magic.trim_end() != MANIFEST_MAGIC {
+            return Err(Error::InvalidData);
+        }
+
+        let mut chd_sha1 = None;
+        let mut len = None;
+        let mut sha1 = None;
+        let mut parts = Vec::new();
+        for line in lines {
+            let line = line?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').ok_or(Error::InvalidData)?;
+            match key {
+                "chd-sha1" => chd_sha1 = Some(parse_sha1(value)?),
+                "size" => len = Some(parse_u64(value)?),
+                "sha1" => sha1 = Some(parse_sha1(value)?),
+                "part" => {
+                    let mut fields = value.splitn(4, ' ');
+                    let mut next = || fields.next().ok_or(Error::InvalidData);
+                    let offset = parse_u64(next()?)?;
+                    let len = parse_u64(next()?)?;
+                    let sha1 = parse_sha1(next()?)?;
+                    let name = next()?.to_string();
+                    parts.push(SplitPart {
+                        name,
+                        offset,
+                        len,
+                        sha1,
+                    });
+                }
+                _ => return Err(Error::InvalidData),
+            }
+        }
+
+        let manifest = SplitManifest {
+            chd_sha1,
+            len: len.ok_or(Error::InvalidData)?,
+            sha1: sha1.ok_or(Error::InvalidData)?,
+            parts,
+        };
+        let mut offset = 0;
+        for part in &manifest.parts {
+            // parts are named by their file name only, so that they are found next to the manifest.
+            let is_file_name = !part.name.is_empty() && !part.name.contains(['/', '\\']);
+            if part.offset != offset || !is_file_name {
+                return Err(Error::InvalidData);
+            }
+            offset = offset.checked_add(part.len).ok_or(Error::InvalidData)?;
+        }
+        if offset != manifest.len {
+            return Err(Error::InvalidData);
+        }
+        Ok(manifest)
+    }
+}
+
+/// The result of concatenating parts with [`concat`](crate::split::concat).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ConcatReport {
+    /// The indices of the parts whose length or SHA1 does not match the manifest.
+    pub mismatched_parts: Vec<usize>,
+    /// The number of bytes written to the output.
+    pub len: u64,
+    /// Whether the SHA1 of the written image matches the SHA1 of the manifest.
+    pub sha1_matches: bool,
+}
+
+impl ConcatReport {
+    /// Returns whether every part and the whole image match the manifest.
+    pub fn is_valid(&self) -> bool {
+        self.mismatched_parts.is_empty() && self.sha1_matches
+    }
+}
+
+/// Returns the name of the part at `index` of a logical image split with the base name `base_name`.
+///
+/// Parts are named with the base name followed by a 3 digit index, such as `disk.raw.000`.
+pub fn part_name(base_name: &str, index: usize) -> String {
+    format!("{}.{:03}", base_name, index)
+}
+
+/// Splits the logical image of a CHD file into parts of at most `part_len` bytes.
+///
+/// For each part, `create` is called with the name of the part, as given by [`part_name`](crate::split::part_name),
+/// and the contents of the part are written to the returned writer. Hunks are decompressed one at a time,
+/// so the parts are written in order. Returns the manifest of the written parts.
+///
+/// If `part_len` is 0, returns [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+pub fn split<F, P, W, C>(
+    chd: &mut Chd<F, P>,
+    part_len: u64,
+    base_name: &str,
+    mut create: C,
+) -> Result<SplitManifest>
+where
+    F: Read + Seek,
+    P: Read + Seek,
+    W: Write,
+    C: FnMut(&str) -> std::io::Result<W>,
+{
+    if part_len == 0 {
+        return Err(Error::InvalidParameter);
+    }
+    let len = chd.header().readable_bytes();
+    let hunk_size = chd.header().hunk_size() as u64;
+    let mut hunk_buf = chd.get_hunksized_buffer();
+    let mut cmp_buf = Vec::new();
+
+    let mut image_hasher = Sha1::new();
+    let mut parts = Vec::new();
+    let mut current: Option<(W, Sha1)> = None;
+    let mut offset = 0;
+    let mut hunk_num = 0;
+    while offset < len {
+        chd.hunk(hunk_num)?
+            .read_hunk_in(&mut cmp_buf, &mut hunk_buf)?;
+        hunk_num += 1;
+
+        let mut data = &hunk_buf[..std::cmp::min(hunk_size, len - offset) as usize];
+        while !data.is_empty() {
+            if current.is_none() {
+                let name = part_name(base_name, parts.len());
+                current = Some((create(&name)?, Sha1::new()));
+                parts.push(SplitPart {
+                    name,
+                    offset,
+                    len: 0,
+                    sha1: [0; SHA1_BYTES],
+                });
+            }
+            let part = parts.last_mut().expect("part was just created");
+            let (output, hasher) = current.as_mut().expect("part was just created");
+
+            let count = std::cmp::min(data.len() as u64, part_len - part.len) as usize;
+            output.write_all(&data[..count])?;
+            hasher.update(&data[..count]);
+            image_hasher.update(&data[..count]);
+            part.len += count as u64;
+            offset += count as u64;
+            data = &data[count..];
+
+            if part.len == part_len {
+                if let Some((mut output, hasher)) = current.take() {
+                    output.flush()?;
+                    part.sha1 = hasher.finalize().into();
+                }
+            }
+        }
+    }
+    if let Some((mut output, hasher)) = current.take() {
+        output.flush()?;
+        if let Some(part) = parts.last_mut() {
+            part.sha1 = hasher.finalize().into();
+        }
+    }
+
+    Ok(SplitManifest {
+        chd_sha1: chd.header().sha1(),
+        len,
+        sha1: image_hasher.finalize().into(),
+        parts,
+    })
+}
+
+/// Concatenates the parts of a logical image recorded in `manifest` into `output`.
+///
+/// For each part, `open` is called and the contents of the returned reader are copied to the output.
+/// Every part is copied even if it does not match the manifest, and the parts and whole image that
+/// do not match are reported. To only verify the parts, [`std::io::sink`](std::io::sink) can be used as the output.
+pub fn concat<R, W, O>(
+    manifest: &SplitManifest,
+    mut open: O,
+    output: &mut W,
+) -> Result<ConcatReport>
+where
+    R: Read,
+    W: Write + ?Sized,
+    O: FnMut(&SplitPart) -> std::io::Result<R>,
+{
+    let mut report = ConcatReport::default();
+    let mut image_hasher = Sha1::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    for (index, part) in manifest.parts.iter().enumerate() {
+        let mut input = open(part)?;
+        let mut hasher = Sha1::new();
+        let mut len = 0;
+        loop {
+            let read = match input.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            output.write_all(&buf[..read])?;
+            hasher.update(&buf[..read]);
+            image_hasher.update(&buf[..read]);
+            len += read as u64;
+        }
+        let sha1: [u8; SHA1_BYTES] = hasher.finalize().into();
+        if len != part.len || sha1 != part.sha1 {
+            report.mismatched_parts.push(index);
+        }
+        report.len += len;
+    }
+    output.flush()?;
+
+    let sha1: [u8; SHA1_BYTES] = image_hasher.finalize().into();
+    report.sha1_matches = report.len == manifest.len && sha1 == manifest.sha1;
+    Ok(report)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_u64(s: &str) -> Result<u64> {
+    s.parse().map_err(|_| Error::InvalidData)
+}
+
+fn parse_sha1(s: &str) -> Result<[u8; SHA1_BYTES]> {
+    if s.len() != SHA1_BYTES * 2 || !s.is_ascii() {
+        return Err(Error::InvalidData);
+    }
+    let mut sha1 = [0u8; SHA1_BYTES];
+    for (i, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| Error::InvalidData)?;
+    }
+    Ok(sha1)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::split::{concat, split, SplitManifest};
+    use crate::testing::v5_uncompressed_chd;
+    use crate::{Chd, Error};
+    use sha1::{Digest, Sha1};
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::io::{Cursor, Write};
+    use std::rc::Rc;
+
+    /// A part written into a map of file names to contents.
+    struct PartFile(Rc<RefCell<BTreeMap<String, Vec<u8>>>>, String);
+    impl Write for PartFile {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut files = self.0.borrow_mut();
+            files
+                .entry(self.1.clone())
+                .or_default()
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn image() -> (Vec<u8>, Vec<u8>) {
+        let hunks: Vec<_> = (1..=3u8).map(|n| Some(vec![n; 512])).collect();
+        let raw = hunks.iter().flatten().flatten().copied().collect();
+        (v5_uncompressed_chd(512, &hunks, None).data, raw)
+    }
+
+    #[test]
+    fn split_concat_test() {
+        let (data, raw) = image();
+        let mut chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        let written = Rc::new(RefCell::new(BTreeMap::new()));
+        let manifest = split(&mut chd, 700, "disk.raw", |name| {
+            Ok(PartFile(written.clone(), name.to_string()))
+        })
+        .expect("split");
+        let mut files = written.take();
+
+        // parts do not have to be aligned to hunks.
+        assert_eq!(raw.len() as u64, manifest.len);
+        assert_eq!(<[u8; 20]>::from(Sha1::digest(&raw)), manifest.sha1);
+        assert_eq!(
+            vec![(0, 700), (700, 700), (1400, 136)],
+            manifest
+                .parts
+                .iter()
+                .map(|p| (p.offset, p.len))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(raw[700..1400], files["disk.raw.001"][..]);
+        for part in &manifest.parts {
+            assert_eq!(
+                <[u8; 20]>::from(Sha1::digest(&files[&part.name])),
+                part.sha1
+            );
+        }
+
+        let mut text = Vec::new();
+        manifest.write_to(&mut text).expect("write");
+        let read = SplitManifest::read_from(&text[..]).expect("read");
+        assert_eq!(manifest, read);
+
+        let mut output = Vec::new();
+        let report = concat(&read, |part| Ok(&files[&part.name][..]), &mut output).expect("concat");
+        assert!(report.is_valid());
+        assert_eq!(raw, output);
+
+        // a corrupt part is reported, but still copied.
+        files.get_mut("disk.raw.001").expect("part")[5] ^= 0xFF;
+        let mut output = Vec::new();
+        let report = concat(&read, |part| Ok(&files[&part.name][..]), &mut output).expect("concat");
+        assert_eq!(vec![1], report.mismatched_parts);
+        assert!(!report.sha1_matches);
+        assert_eq!(raw.len(), output.len());
+
+        // a truncated part is reported.
+        files.get_mut("disk.raw.002").expect("part").pop();
+        let report = concat(
+            &read,
+            |part| Ok(&files[&part.name][..]),
+            &mut std::io::sink(),
+        )
+        .expect("concat");
+        assert_eq!(vec![1, 2], report.mismatched_parts);
+        assert_eq!(raw.len() as u64 - 1, report.len);
+    }
+
+    #[test]
+    fn split_invalid_test() {
+        let (data, _) = image();
+        let mut chd = Chd::open(Cursor::new(&data[..]), None).expect("open");
+        assert!(matches!(
+            split(&mut chd, 0, "disk.raw", |_| Ok(std::io::sink())),
+            Err(Error::InvalidParameter)
+        ));
+
+        let manifest = split(&mut chd, 4096, "disk.raw", |_| Ok(std::io::sink())).expect("split");
+        assert_eq!(1, manifest.parts.len());
+        let mut text = Vec::new();
+        manifest.write_to(&mut text).expect("write");
+
+        // parts that do not cover the image are rejected.
+        let mut truncated = text.clone();
+        write!(truncated, "part 1536 1 {} disk.raw.001", "00".repeat(20)).expect("write");
+        assert!(matches!(
+            SplitManifest::read_from(&truncated[..]),
+            Err(Error::InvalidData)
+        ));
+        let text = String::from_utf8(text).expect("utf8");
+        assert!(matches!(
+            SplitManifest::read_from(text.replace(" disk.raw", " ../disk.raw").as_bytes()),
+            Err(Error::InvalidData)
+        ));
+        assert!(matches!(
+            SplitManifest::read_from(text.replace("size 1536", "size 1537").as_bytes()),
+            Err(Error::InvalidData)
+        ));
+        assert!(matches!(
+            SplitManifest::read_from(&text.as_bytes()[1..]),
+            Err(Error::InvalidData)
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::error::{Error, Result};
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+
+/// A FourCC tag, such as a metadata tag or the tag of a V5 compression codec.
+///
+/// Tags are stored in CHD files as big-endian `u32`, so that the first byte of the tag is the most
+/// significant byte. `Tag` converts to and from that representation with [`From`](std::convert::From),
+/// which avoids mixing up the byte order when comparing against raw tags.
+///
+/// ```rust
+/// use chd::Tag;
+/// use chd::metadata::{KnownMetadata, MetadataTag};
+///
+/// let tag: Tag = "CHT2".parse()?;
+/// assert_eq!(KnownMetadata::CdRomTrack2.tag(), tag);
+/// assert_eq!(0x43485432, u32::from(tag));
+/// assert_eq!("CHT2", tag.to_string());
+/// # Ok::<(), chd::Error>(())
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct Tag(pub [u8; 4]);
+
+impl Tag {
+    /// Creates a tag from its four bytes.
+    pub const fn new(bytes: &[u8; 4]) -> Tag {
+        Tag(*bytes)
+    }
+
+    /// Returns the tag as stored in a CHD file.
+    pub const fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// Returns the four bytes of the tag.
+    pub const fn as_bytes(&self) -> &[u8; 4] {
+        &self.0
+    }
+
+    /// Returns whether every byte of the tag is a printable ASCII character or a space.
+    pub fn is_printable(&self) -> bool {
+        self.0.iter().all(|b| b.is_ascii_graphic() || *b == b' ')
+    }
+}
+
+impl From<u32> for Tag {
+    fn from(tag: u32) -> Self {
+        Tag(tag.to_be_bytes())
+    }
+}
+
+impl From<Tag> for u32 {
+    fn from(tag: Tag) -> Self {
+        tag.to_u32()
+    }
+}
+
+impl From<[u8; 4]> for Tag {
+    fn from(bytes: [u8; 4]) -> Self {
+        Tag(bytes)
+    }
+}
+
+impl FromStr for Tag {
+    type Err = Error;
+
+    /// Parses a tag of 1 to 4 ASCII characters. Tags shorter than 4 characters are padded
+    /// with spaces, like `KEY ` and `CIS `.
+    ///
+    /// Any other string returns [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() || s.len() > 4 || !s.is_ascii() {
+            return Err(Error::InvalidParameter);
+        }
+        let mut tag = [b' '; 4];
+        tag[..s.len()].copy_from_slice(s.as_bytes());
+        Ok(Tag(tag))
+    }
+}
+
+/// Prints the four characters of the tag, or the tag as hex if it is not printable.
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_printable() {
+            write!(f, "{}", String::from_utf8_lossy(&self.0))
+        } else {
+            write!(f, "{:#010x}", self.to_u32())
+        }
+    }
+}
+
+/// Prints the tag in quotes, or the tag as hex if it is not printable.
+impl Debug for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_printable() {
+            write!(f, "'{}'", self)
+        } else {
+            Display::fmt(self, f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::make_tag;
+    use crate::Error;
+    use crate::Tag;
+
+    #[test]
+    fn tag_test() {
+        let tag: Tag = "CHT2".parse().expect("tag");
+        assert_eq!(Tag::new(b"CHT2"), tag);
+        assert_eq!(make_tag(b"CHT2"), u32::from(tag));
+        assert_eq!(tag, Tag::from(make_tag(b"CHT2")));
+        assert_eq!("CHT2", tag.to_string());
+        assert_eq!("'CHT2'", format!("{:?}", tag));
+
+        // short tags are padded with spaces.
+        assert_eq!(Tag::new(b"KEY "), "KEY".parse().expect("tag"));
+        assert!(matches!("".parse::<Tag>(), Err(Error::InvalidParameter)));
+        assert!(matches!(
+            "CHTR2".parse::<Tag>(),
+            Err(Error::InvalidParameter)
+        ));
+        assert!(matches!("CHé".parse::<Tag>(), Err(Error::InvalidParameter)));
+
+        let tag = Tag::from(0x01020304);
+        assert!(!tag.is_printable());
+        assert_eq!("0x01020304", tag.to_string());
+        assert_eq!("0x01020304", format!("{:?}", tag));
+    }
+}
@@ -0,0 +1,440 @@
+//! Utilities for tests that synthesize CHD files and inject faults into reads of them.
+use crate::block_hash::CRC16;
+use crate::make_tag;
+use crc::{Crc, CRC_16_UMTS, CRC_32_ISO_HDLC, CRC_8_SMBUS};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Writes values into a big-endian bitstream.
+#[derive(Default)]
+pub(crate) struct BitWriter {
+    bits: Vec<bool>,
+}
+
+impl BitWriter {
+    pub fn write(&mut self, num_bits: u8, value: u32) -> &mut Self {
+        for bit in (0..num_bits).rev() {
+            self.bits.push(value >> bit & 1 != 0);
+        }
+        self
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        // pad with enough zeroes for decode_one to peek past the end.
+        let mut bytes = vec![0u8; self.bits.len() / 8 + 4];
+        for (idx, &bit) in self.bits.iter().enumerate() {
+            bytes[idx / 8] |= (bit as u8) << (7 - idx % 8);
+        }
+        bytes
+    }
+
+    /// Writes `count` nodes of `node_bits` as an RLE encoded tree with 5-bit values.
+    pub fn rle(&mut self, node_bits: u32, mut count: u32) -> &mut Self {
+        while count >= 3 {
+            let rep = count.min(34);
+            self.write(5, 1).write(5, node_bits).write(5, rep - 3);
+            count -= rep;
+        }
+        for _ in 0..count {
+            self.write(5, node_bits);
+        }
+        self
+    }
+}
+
+/// A fault injected by a [`FaultyReader`](crate::testing::FaultyReader) at an offset of the stream.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Fault {
+    /// Reads stop short at the offset, as if the stream ended there.
+    ShortRead,
+    /// Reads that include the offset fail with an error of the given kind.
+    Error(ErrorKind),
+    /// The byte at the offset is XORed with the given mask when read.
+    Corrupt(u8),
+}
+
+/// A stream that injects faults into reads at configurable offsets.
+///
+/// Faults only affect reads, so seeking to and past the end of the stream is unaffected.
+pub(crate) struct FaultyReader<R> {
+    inner: R,
+    faults: Vec<(u64, Fault)>,
+}
+
+impl<R: Read + Seek> FaultyReader<R> {
+    pub fn new(inner: R) -> Self {
+        FaultyReader {
+            inner,
+            faults: Vec::new(),
+        }
+    }
+
+    /// Injects a fault at the given offset.
+    ///
+    /// Faults can be injected after a CHD file is opened from this stream with [`Chd::inner`](crate::Chd::inner),
+    /// to only affect reads of hunks and not of the header and map.
+    pub fn fault(&mut self, offset: u64, fault: Fault) {
+        self.faults.push((offset, fault));
+    }
+}
+
+impl<R: Read + Seek> Read for FaultyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let pos = self.inner.stream_position()?;
+        let mut len = buf.len() as u64;
+        for (offset, fault) in &self.faults {
+            if let Fault::ShortRead = fault {
+                if (pos..pos + len).contains(offset) {
+                    len = offset - pos;
+                }
+            }
+        }
+        for (offset, fault) in &self.faults {
+            if let Fault::Error(kind) = fault {
+                if (pos..pos + len).contains(offset) {
+                    return Err(std::io::Error::new(*kind, "injected fault"));
+                }
+            }
+        }
+
+        let read = self.inner.read(&mut buf[..len as usize])?;
+        for (offset, fault) in &self.faults {
+            if let Fault::Corrupt(mask) = fault {
+                if (pos..pos + read as u64).contains(offset) {
+                    buf[(offset - pos) as usize] ^= mask;
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for FaultyReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Compresses data with raw Deflate, as stored by the zlib codecs.
+pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).expect("deflate");
+    encoder.finish().expect("deflate")
+}
+
+/// Encodes 16-bit stereo samples as raw FLAC frames of `block_size` samples, without a stream header,
+/// as stored by the FLAC codecs.
+///
+/// Each channel is stored as a verbatim subframe, so the frames are larger than the samples.
+pub(crate) fn flac_frames(samples: &[(i16, i16)], block_size: usize) -> Vec<u8> {
+    const CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+    const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_UMTS);
+
+    let mut out = Vec::new();
+    for (frame_num, block) in samples.chunks(block_size).enumerate() {
+        assert!(
+            frame_num < 0x80,
+            "frame numbers are encoded as a single byte"
+        );
+        let start = out.len();
+        // fixed block size, 16-bit block size at the end of the header, 44.1 kHz,
+        // independent stereo, 16-bit samples.
+        out.extend_from_slice(&[0xff, 0xf8, 0x79, 0x18, frame_num as u8]);
+        out.extend_from_slice(&(block.len() as u16 - 1).to_be_bytes());
+        out.push(CRC8.checksum(&out[start..]));
+        for channel in 0..2 {
+            // verbatim subframe without wasted bits.
+            out.push(0x02);
+            for &(l, r) in block {
+                out.extend_from_slice(&[l, r][channel].to_be_bytes());
+            }
+        }
+        let crc = CRC16.checksum(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    out
+}
+
+/// A hunk of a synthesized V5 CHD file with a compressed map.
+pub(crate) enum V5Hunk {
+    /// Compressed with the zlib codec in the first codec slot.
+    Zlib(Vec<u8>),
+    /// Stored uncompressed.
+    Uncompressed(Vec<u8>),
+    /// A copy of another hunk in the same file.
+    SelfHunk(u32),
+    /// A copy of data in the parent at the given unit.
+    Parent(u64),
+}
+
+/// A synthesized CHD file.
+pub(crate) struct TestChd {
+    /// The contents of the CHD file.
+    pub data: Vec<u8>,
+    /// The offset of the stored data of each hunk, or 0 if the hunk has no stored data.
+    pub hunk_offsets: Vec<u64>,
+}
+
+const V5_HEADER_SIZE: usize = 124;
+const V3_HEADER_SIZE: usize = 120;
+const V2_HEADER_SIZE: usize = 80;
+
+fn v5_header(compression: u32, hunk_bytes: u32, unit_bytes: u32, hunks: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; V5_HEADER_SIZE];
+    buf[0..8].copy_from_slice(b"MComprHD");
+    buf[8..12].copy_from_slice(&(V5_HEADER_SIZE as u32).to_be_bytes());
+    buf[12..16].copy_from_slice(&5u32.to_be_bytes());
+    buf[16..20].copy_from_slice(&compression.to_be_bytes());
+    buf[32..40].copy_from_slice(&(hunks as u64 * hunk_bytes as u64).to_be_bytes());
+    buf[56..60].copy_from_slice(&hunk_bytes.to_be_bytes());
+    buf[60..64].copy_from_slice(&unit_bytes.to_be_bytes());
+    buf
+}
+
+/// Synthesizes a V5 CHD file with a compressed map, using zlib as the only codec.
+///
+/// If `parent_sha1` is provided, the CHD file requires a parent with that SHA1.
+pub(crate) fn v5_chd(
+    hunk_bytes: u32,
+    unit_bytes: u32,
+    hunks: &[V5Hunk],
+    parent_sha1: Option<[u8; 20]>,
+) -> TestChd {
+    const LENGTH_BITS: u8 = 24;
+    const SELF_BITS: u8 = 32;
+    const PARENT_BITS: u8 = 32;
+
+    let mut data = v5_header(make_tag(b"zlib"), hunk_bytes, unit_bytes, hunks.len());
+    if let Some(sha1) = parent_sha1 {
+        data[104..124].copy_from_slice(&sha1);
+    }
+
+    // every code of the map type tree is 4 bits long, so each type is written as itself.
+    let mut bits = BitWriter::default();
+    for _ in 0..16 {
+        bits.write(4, 4);
+    }
+    for hunk in hunks {
+        let hunk_type = match hunk {
+            V5Hunk::Zlib(_) => 0,
+            V5Hunk::Uncompressed(_) => 4,
+            V5Hunk::SelfHunk(_) => 5,
+            V5Hunk::Parent(_) => 6,
+        };
+        bits.write(4, hunk_type);
+    }
+
+    let first_offs = data.len() as u64;
+    let mut hunk_offsets = Vec::new();
+    let mut raw_map = Vec::new();
+    for hunk in hunks {
+        let offset = data.len() as u64;
+        let (hunk_type, length, offset, crc) = match hunk {
+            V5Hunk::Zlib(hunk) => {
+                let compressed = deflate(hunk);
+                let crc = CRC16.checksum(hunk);
+                bits.write(LENGTH_BITS, compressed.len() as u32)
+                    .write(16, crc as u32);
+                data.extend_from_slice(&compressed);
+                (0, compressed.len() as u32, offset, crc)
+            }
+            V5Hunk::Uncompressed(hunk) => {
+                let crc = CRC16.checksum(hunk);
+                bits.write(16, crc as u32);
+                data.extend_from_slice(hunk);
+                (4, hunk_bytes, offset, crc)
+            }
+            V5Hunk::SelfHunk(hunk_num) => {
+                bits.write(SELF_BITS, *hunk_num);
+                (5, 0, *hunk_num as u64, 0)
+            }
+            V5Hunk::Parent(unit) => {
+                bits.write(PARENT_BITS, *unit as u32);
+                (6, 0, *unit, 0)
+            }
+        };
+        hunk_offsets.push(if length != 0 { offset } else { 0 });
+        raw_map.push(hunk_type);
+        raw_map.extend_from_slice(&length.to_be_bytes()[1..]);
+        raw_map.extend_from_slice(&offset.to_be_bytes()[2..]);
+        raw_map.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    let map_offset = data.len() as u64;
+    data[40..48].copy_from_slice(&map_offset.to_be_bytes());
+    let compressed_map = bits.bytes();
+    data.extend_from_slice(&(compressed_map.len() as u32).to_be_bytes());
+    data.extend_from_slice(&first_offs.to_be_bytes()[2..]);
+    data.extend_from_slice(&CRC16.checksum(&raw_map).to_be_bytes());
+    data.extend_from_slice(&[LENGTH_BITS, SELF_BITS, PARENT_BITS, 0]);
+    data.extend_from_slice(&compressed_map);
+    TestChd { data, hunk_offsets }
+}
+
+/// Synthesizes a V5 CHD file without compression, where each hunk is either stored or unallocated.
+///
+/// If `parent_sha1` is provided, the CHD file requires a parent with that SHA1, and
+/// unallocated hunks are read from the parent.
+pub(crate) fn v5_uncompressed_chd(
+    hunk_bytes: u32,
+    hunks: &[Option<Vec<u8>>],
+    parent_sha1: Option<[u8; 20]>,
+) -> TestChd {
+    let mut data = v5_header(0, hunk_bytes, hunk_bytes, hunks.len());
+    if let Some(sha1) = parent_sha1 {
+        data[104..124].copy_from_slice(&sha1);
+    }
+    data[40..48].copy_from_slice(&(V5_HEADER_SIZE as u64).to_be_bytes());
+
+    // hunks are stored at multiples of the hunk size after the map.
+    let map_end = V5_HEADER_SIZE + hunks.len() * 4;
+    let mut next_hunk = (map_end as u64).div_ceil(hunk_bytes as u64);
+    let mut stored = Vec::new();
+    let mut hunk_offsets = Vec::new();
+    for hunk in hunks {
+        match hunk {
+            Some(hunk) => {
+                data.extend_from_slice(&(next_hunk as u32).to_be_bytes());
+                hunk_offsets.push(next_hunk * hunk_bytes as u64);
+                stored.push(hunk);
+                next_hunk += 1;
+            }
+            None => {
+                data.extend_from_slice(&0u32.to_be_bytes());
+                hunk_offsets.push(0);
+            }
+        }
+    }
+    for (hunk, offset) in stored.iter().zip(hunk_offsets.iter().filter(|&&o| o != 0)) {
+        data.resize(*offset as usize, 0);
+        data.extend_from_slice(hunk);
+    }
+    TestChd { data, hunk_offsets }
+}
+
+/// A hunk of a synthesized V3 CHD file.
+pub(crate) enum LegacyHunk {
+    /// Compressed with the zlib codec.
+    Zlib(Vec<u8>),
+    /// Stored uncompressed.
+    Uncompressed(Vec<u8>),
+    /// The hunk is the 8 bytes of the offset, repeated.
+    Mini(u64),
+    /// A copy of another hunk in the same file.
+    SelfHunk(u64),
+    /// A copy of a hunk in the parent.
+    Parent(u64),
+    /// A map entry with the given hunk type and no stored data.
+    Raw(u8),
+}
+
+/// Synthesizes a V3 CHD file, using zlib as the codec.
+///
+/// If `has_parent` is set, the CHD file requires a parent with an MD5 and SHA1 of all ones.
+pub(crate) fn v3_chd(hunk_bytes: u32, hunks: &[LegacyHunk], has_parent: bool) -> TestChd {
+    let mut data = vec![0u8; V3_HEADER_SIZE];
+    data[0..8].copy_from_slice(b"MComprHD");
+    for (offset, value) in [
+        (8, V3_HEADER_SIZE as u32),
+        (12, 3),
+        (16, has_parent as u32),
+        (20, 1), // zlib
+        (24, hunks.len() as u32),
+        (76, hunk_bytes),
+    ] {
+        data[offset..offset + 4].copy_from_slice(&u32::to_be_bytes(value));
+    }
+    data[28..36].copy_from_slice(&(hunks.len() as u64 * hunk_bytes as u64).to_be_bytes());
+    if has_parent {
+        // the parent MD5 and SHA1 must be present.
+        data[60..76].fill(1);
+        data[100..120].fill(1);
+    }
+
+    let mut stored = Vec::new();
+    let mut hunk_offsets = Vec::new();
+    let mut next_offset = (V3_HEADER_SIZE + (hunks.len() + 1) * 16) as u64;
+    for hunk in hunks {
+        let (hunk_type, offset, crc, block) = match hunk {
+            LegacyHunk::Zlib(hunk) => (1, next_offset, CRC32.checksum(hunk), deflate(hunk)),
+            LegacyHunk::Uncompressed(hunk) => (2, next_offset, CRC32.checksum(hunk), hunk.clone()),
+            LegacyHunk::Mini(value) => {
+                let hunk: Vec<u8> = value
+                    .to_be_bytes()
+                    .iter()
+                    .copied()
+                    .cycle()
+                    .take(hunk_bytes as usize)
+                    .collect();
+                (3, *value, CRC32.checksum(&hunk), Vec::new())
+            }
+            LegacyHunk::SelfHunk(hunk_num) => (4, *hunk_num, 0, Vec::new()),
+            LegacyHunk::Parent(hunk_num) => (5, *hunk_num, 0, Vec::new()),
+            LegacyHunk::Raw(hunk_type) => (*hunk_type, 0, 0, Vec::new()),
+        };
+
+        hunk_offsets.push(if block.is_empty() { 0 } else { offset });
+        data.extend_from_slice(&offset.to_be_bytes());
+        data.extend_from_slice(&crc.to_be_bytes());
+        data.extend_from_slice(&(block.len() as u16).to_be_bytes());
+        data.push((block.len() >> 16) as u8);
+        data.push(hunk_type);
+        next_offset += block.len() as u64;
+        stored.push(block);
+    }
+    data.extend_from_slice(b"EndOfListCookie\0");
+    for block in stored {
+        data.extend_from_slice(&block);
+    }
+    TestChd { data, hunk_offsets }
+}
+
+/// Synthesizes a V2 CHD file with the given sector length, number of sectors per hunk,
+/// and cylinders, heads and sectors of the hard disk, using zlib as the codec.
+///
+/// Only [`LegacyHunk::Zlib`] and [`LegacyHunk::Uncompressed`] hunks can be stored in a V2 map.
+pub(crate) fn v2_chd(
+    sector_length: u32,
+    hunk_sectors: u32,
+    geometry: [u32; 3],
+    hunks: &[LegacyHunk],
+) -> TestChd {
+    let mut data = vec![0u8; V2_HEADER_SIZE];
+    data[0..8].copy_from_slice(b"MComprHD");
+    for (offset, value) in [
+        (8, V2_HEADER_SIZE as u32),
+        (12, 2),
+        (20, 1), // zlib
+        (24, hunk_sectors),
+        (28, hunks.len() as u32),
+        (32, geometry[0]),
+        (36, geometry[1]),
+        (40, geometry[2]),
+        (76, sector_length),
+    ] {
+        data[offset..offset + 4].copy_from_slice(&u32::to_be_bytes(value));
+    }
+
+    let mut stored = Vec::new();
+    let mut hunk_offsets = Vec::new();
+    let mut next_offset = (V2_HEADER_SIZE + (hunks.len() + 1) * 8) as u64;
+    for hunk in hunks {
+        let block = match hunk {
+            LegacyHunk::Zlib(hunk) => deflate(hunk),
+            LegacyHunk::Uncompressed(hunk) => hunk.clone(),
+            _ => unimplemented!("V2 maps only store compressed or uncompressed hunks"),
+        };
+        hunk_offsets.push(next_offset);
+        data.extend_from_slice(&((block.len() as u64) << 44 | next_offset).to_be_bytes());
+        next_offset += block.len() as u64;
+        stored.push(block);
+    }
+    data.extend_from_slice(&b"EndOfListCookie\0"[..8]);
+    for block in stored {
+        data.extend_from_slice(&block);
+    }
+    TestChd { data, hunk_offsets }
+}
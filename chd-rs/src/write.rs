@@ -0,0 +1,1133 @@
+//! Writing CHD V5 files.
+//!
+//! [`ChdWriter`](crate::write::ChdWriter) compresses hunks into a new CHD V5 file.
+//! Like chdman, each hunk is compressed with every codec of the file and the smallest result is
+//! kept. Hunks that no codec makes smaller are stored uncompressed, and hunks identical to an
+//! earlier hunk are stored as a reference to that hunk. Once every hunk is written, the map,
+//! the raw SHA1 and the overall SHA1 are regenerated into the header.
+//!
+//! A compressed file can also be written as the child of a parent CHD file, like the `--outputparent`
+//! option of chdman. With [`ChdWriter::with_parent`](crate::write::ChdWriter::with_parent), hunks that are
+//! identical to data of the parent at any unit are stored as a reference to the parent instead. Writing
+//! the hunks of an existing standalone CHD file this way re-links it to the parent.
+//!
+//! Only the codecs that chd-rs can compress with are supported when writing, which are
+//! `zlib`, `lzma`, `cdzl` and `cdlz`. A file with no codecs is written without compression.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use chd::header::CodecType;
+//! use chd::write::ChdWriter;
+//!
+//! let data = vec![0u8; 4096 * 4];
+//! let output = File::create("out.chd")?;
+//! let mut writer = ChdWriter::create(output, 4096, 512, data.len() as u64,
+//!     &[CodecType::LzmaV5, CodecType::ZLibV5], &[])?;
+//! for hunk in data.chunks(4096) {
+//!     writer.write_hunk(hunk)?;
+//! }
+//! writer.finish()?;
+//! ```
+//!
+//! To write a child of a parent CHD file, the parent is indexed with [`ParentIndex::build`](crate::write::ParentIndex::build).
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use chd::Chd;
+//! use chd::header::CodecType;
+//! use chd::write::{ChdWriter, ParentIndex};
+//!
+//! let mut parent = Chd::open(BufReader::new(File::open("parent.chd")?), None)?;
+//! let index = ParentIndex::build(&mut parent)?;
+//! let data = vec![0u8; 4096 * 4];
+//! let output = File::create("child.chd")?;
+//! let mut writer = ChdWriter::create(output, 4096, 512, data.len() as u64,
+//!     &[CodecType::LzmaV5, CodecType::ZLibV5], &[])?
+//!     .with_parent(index)?;
+//! for hunk in data.chunks(4096) {
+//!     writer.write_hunk(hunk)?;
+//! }
+//! writer.finish()?;
+//! ```
+use crate::block_hash::crc16;
+use crate::cdrom::{CD_FRAME_SIZE, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA};
+use crate::error::{Error, Result};
+use crate::header::{CodecType, Header, HeaderV5, Version, SHA1_BYTES};
+use crate::map::{V5_COMPRESSED_MAP_ENTRY_SIZE, V5_UNCOMPRESSED_MAP_ENTRY_SIZE};
+use crate::metadata::{serialize_chain, Metadata};
+use crate::Chd;
+use flate2::{Compress, Compression, FlushCompress, Status};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+const V5_HEADER_SIZE: u64 = 124;
+const CHD_MDFLAGS_CHECKSUM: u8 = 0x01;
+
+// map entry types of a V5 compressed map.
+const MAP_TYPE_NONE: u8 = 4;
+const MAP_TYPE_SELF: u8 = 5;
+const MAP_TYPE_PARENT: u8 = 6;
+
+/// Computes the overall SHA1 of a V4 or V5 CHD file from the SHA1 of its raw data and its metadata.
+///
+/// Only metadata entries with the checksum flag set contribute to the overall SHA1.
+pub fn overall_sha1(raw_sha1: &[u8], metadata: &[Metadata]) -> [u8; SHA1_BYTES] {
+    hash_with_metadata(raw_sha1, &metadata_hashes(metadata))
+}
+
+/// Returns the tag followed by the SHA1 of the contents of each checksummed metadata entry, sorted.
+fn metadata_hashes(metadata: &[Metadata]) -> Vec<[u8; 24]> {
+    let mut meta_hashes: Vec<[u8; 24]> = metadata
+        .iter()
+        .filter(|m| m.flags & CHD_MDFLAGS_CHECKSUM != 0)
+        .map(|m| {
+            let mut entry = [0u8; 24];
+            entry[..4].copy_from_slice(&m.metatag.to_be_bytes());
+            entry[4..].copy_from_slice(&Sha1::digest(&m.value));
+            entry
+        })
+        .collect();
+    meta_hashes.sort_unstable();
+    meta_hashes
+}
+
+fn hash_with_metadata(raw_sha1: &[u8], meta_hashes: &[[u8; 24]]) -> [u8; SHA1_BYTES] {
+    let mut hasher = Sha1::new();
+    hasher.update(raw_sha1);
+    for entry in meta_hashes {
+        hasher.update(entry);
+    }
+    hasher.finalize().into()
+}
+
+/// Compresses a hunk into the format its codec decompresses.
+trait HunkCompressor {
+    /// Compresses `input` into `output`, replacing its contents.
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<()>;
+}
+
+/// Raw DEFLATE compression, as decompressed by `zlib`.
+struct DeflateCompressor(Compress);
+
+impl DeflateCompressor {
+    fn new() -> Self {
+        DeflateCompressor(Compress::new(Compression::best(), false))
+    }
+}
+
+impl HunkCompressor for DeflateCompressor {
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        // leave room for the stored blocks that incompressible data is written as.
+        output.reserve(input.len() + input.len() / 1024 + 64);
+        self.0.reset();
+        match self.0.compress_vec(input, output, FlushCompress::Finish) {
+            Ok(Status::StreamEnd) => Ok(()),
+            _ => Err(Error::CompressionError),
+        }
+    }
+}
+
+/// Raw LZMA compression without the LZMA header, as decompressed by `lzma`.
+struct LzmaCompressor;
+
+impl HunkCompressor for LzmaCompressor {
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        use lzma_rs::compress::{Options, UnpackedSize};
+        // the properties and dictionary size are implied by the codec, so only the stream is kept.
+        const LZMA_PROPS_SIZE: usize = 5;
+        output.clear();
+        lzma_rs::lzma_compress_with_options(
+            &mut Cursor::new(input),
+            output,
+            &Options {
+                unpacked_size: UnpackedSize::SkipWritingToHeader,
+            },
+        )
+        .map_err(|_| Error::CompressionError)?;
+        output.drain(..LZMA_PROPS_SIZE);
+        Ok(())
+    }
+}
+
+/// CD-ROM compression, as decompressed by `cdzl` and `cdlz`.
+///
+/// The sector data of every frame is compressed with the engine, and the subcode data with DEFLATE.
+/// ECC data is stored as is, so the ECC bitmap is always empty.
+struct CdCompressor<E: HunkCompressor> {
+    engine: E,
+    sub_engine: DeflateCompressor,
+    sectors: Vec<u8>,
+    subcode: Vec<u8>,
+    compressed: Vec<u8>,
+}
+
+impl<E: HunkCompressor> CdCompressor<E> {
+    fn new(engine: E, hunk_bytes: u32) -> Result<Self> {
+        if !hunk_bytes.is_multiple_of(CD_FRAME_SIZE) {
+            return Err(Error::CodecError);
+        }
+        Ok(CdCompressor {
+            engine,
+            sub_engine: DeflateCompressor::new(),
+            sectors: Vec::new(),
+            subcode: Vec::new(),
+            compressed: Vec::new(),
+        })
+    }
+}
+
+impl<E: HunkCompressor> HunkCompressor for CdCompressor<E> {
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let frames = input.len() / CD_FRAME_SIZE as usize;
+        let complen_bytes = if input.len() < 65536 { 2 } else { 3 };
+        let ecc_bytes = frames.div_ceil(8);
+
+        self.sectors.clear();
+        self.subcode.clear();
+        for frame in input.chunks_exact(CD_FRAME_SIZE as usize) {
+            let (sector, subcode) = frame.split_at(CD_MAX_SECTOR_DATA as usize);
+            self.sectors.extend_from_slice(sector);
+            self.subcode
+                .extend_from_slice(&subcode[..CD_MAX_SUBCODE_DATA as usize]);
+        }
+
+        output.clear();
+        self.engine.compress(&self.sectors, output)?;
+        let complen = output.len();
+        if complen >> (complen_bytes * 8) != 0 {
+            return Err(Error::CompressionError);
+        }
+        self.sub_engine
+            .compress(&self.subcode, &mut self.compressed)?;
+
+        let mut hunk =
+            Vec::with_capacity(ecc_bytes + complen_bytes + complen + self.compressed.len());
+        hunk.resize(ecc_bytes, 0);
+        hunk.extend_from_slice(&(complen as u32).to_be_bytes()[4 - complen_bytes..]);
+        hunk.extend_from_slice(output);
+        hunk.extend_from_slice(&self.compressed);
+        *output = hunk;
+        Ok(())
+    }
+}
+
+/// Returns whether hunks can be compressed with the codec when writing.
+pub fn can_compress(codec: CodecType) -> bool {
+    matches!(
+        codec,
+        CodecType::None
+            | CodecType::ZLibV5
+            | CodecType::LzmaV5
+            | CodecType::ZLibCdV5
+            | CodecType::LzmaCdV5
+    )
+}
+
+fn compressor_for(codec: &CodecType, hunk_bytes: u32) -> Result<Option<Box<dyn HunkCompressor>>> {
+    Ok(match codec {
+        CodecType::None => None,
+        CodecType::ZLibV5 => Some(Box::new(DeflateCompressor::new())),
+        CodecType::LzmaV5 => Some(Box::new(LzmaCompressor)),
+        CodecType::ZLibCdV5 => Some(Box::new(CdCompressor::new(
+            DeflateCompressor::new(),
+            hunk_bytes,
+        )?)),
+        CodecType::LzmaCdV5 => Some(Box::new(CdCompressor::new(LzmaCompressor, hunk_bytes)?)),
+        _ => return Err(Error::UnsupportedFormat),
+    })
+}
+
+/// A codec that a [`CompressionStrategy`](crate::write::CompressionStrategy) may compress a hunk with.
+#[derive(Debug, Copy, Clone)]
+pub struct CompressionAttempt {
+    /// The number of the hunk being compressed.
+    pub hunk_num: u32,
+    /// The length of the hunk in bytes.
+    pub hunk_len: usize,
+    /// The codec that the hunk may be compressed with.
+    pub codec: CodecType,
+    /// The number of codecs the hunk has already been compressed with.
+    pub tried: usize,
+    /// The length of the smallest compressed result so far, if any codec has shrunk the hunk.
+    pub best_len: Option<usize>,
+}
+
+/// Chooses which codecs each hunk is compressed with when writing.
+///
+/// Codecs are offered in the order of the codecs of the file. The smallest result of the codecs
+/// that are tried is kept, and if no codec that is tried shrinks the hunk, it is stored uncompressed.
+pub trait CompressionStrategy {
+    /// Returns whether the hunk should be compressed with the codec of the attempt.
+    fn should_try(&mut self, attempt: &CompressionAttempt) -> bool;
+}
+
+/// Compresses every hunk with only the first codec of the file.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct AlwaysFirst;
+
+impl CompressionStrategy for AlwaysFirst {
+    fn should_try(&mut self, attempt: &CompressionAttempt) -> bool {
+        attempt.tried == 0
+    }
+}
+
+/// Compresses every hunk with every codec of the file and keeps the smallest result, like chdman.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SmallestOutput;
+
+impl CompressionStrategy for SmallestOutput {
+    fn should_try(&mut self, _attempt: &CompressionAttempt) -> bool {
+        true
+    }
+}
+
+/// Trades compression ratio for speed.
+///
+/// Codecs are tried in order until one compresses the hunk to at most half of its length, and the
+/// slow LZMA codecs are only tried if no other codec has shrunk the hunk.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FastPreset;
+
+impl CompressionStrategy for FastPreset {
+    fn should_try(&mut self, attempt: &CompressionAttempt) -> bool {
+        match attempt.best_len {
+            None => true,
+            Some(len) if len <= attempt.hunk_len / 2 => false,
+            Some(_) => !matches!(attempt.codec, CodecType::LzmaV5 | CodecType::LzmaCdV5),
+        }
+    }
+}
+
+/// How a hunk was stored by the writer.
+enum WrittenHunk {
+    Compressed {
+        codec: u8,
+        offset: u64,
+        length: u32,
+        crc: u16,
+    },
+    Uncompressed {
+        offset: u64,
+        crc: u16,
+    },
+    SelfHunk(u32),
+    Parent(u64),
+}
+
+/// An index of the data of a parent CHD file, used by [`ChdWriter::with_parent`](crate::write::ChdWriter::with_parent)
+/// to store hunks identical to data of the parent as references to the parent.
+///
+/// Hunks of a child can refer to data of the parent beginning at any unit, so the SHA1 of the
+/// hunk-sized data at every unit of the parent is indexed. This takes memory proportional to the
+/// number of units of the parent.
+pub struct ParentIndex {
+    sha1: [u8; SHA1_BYTES],
+    hunk_bytes: u32,
+    unit_bytes: u32,
+    units: HashMap<[u8; SHA1_BYTES], u64>,
+}
+
+impl ParentIndex {
+    /// Indexes the data of a parent CHD file, decompressing every hunk.
+    ///
+    /// If the parent does not record its SHA1 in its header, returns
+    /// [`Error::InvalidParent`](crate::Error::InvalidParent).
+    pub fn build<F: Read + Seek, P: Read + Seek>(parent: &mut Chd<F, P>) -> Result<Self> {
+        let sha1 = parent.header().sha1().ok_or(Error::InvalidParent)?;
+        let hunk_bytes = parent.header().hunk_size();
+        let unit_bytes = parent.header().unit_bytes();
+        if unit_bytes == 0 || !hunk_bytes.is_multiple_of(unit_bytes) {
+            return Err(Error::InvalidParent);
+        }
+        let units_in_hunk = (hunk_bytes / unit_bytes) as u64;
+        let hunk_count = parent.header().hunk_count();
+
+        // the previous hunk followed by the current hunk, so that the data at every unit
+        // of the previous hunk is contiguous.
+        let hunk_len = hunk_bytes as usize;
+        let mut window = vec![0u8; hunk_len * 2];
+        let mut cmp_buf = Vec::new();
+        let mut units = HashMap::new();
+        let mut index = |data: &[u8], unit: u64| {
+            let digest: [u8; SHA1_BYTES] = Sha1::digest(data).into();
+            units.entry(digest).or_insert(unit);
+        };
+        for hunk_num in 0..hunk_count {
+            parent
+                .hunk(hunk_num)?
+                .read_hunk_in(&mut cmp_buf, &mut window[hunk_len..])?;
+            if hunk_num > 0 {
+                let first_unit = (hunk_num - 1) as u64 * units_in_hunk;
+                for unit in 0..units_in_hunk {
+                    let start = (unit * unit_bytes as u64) as usize;
+                    index(&window[start..start + hunk_len], first_unit + unit);
+                }
+            }
+            window.copy_within(hunk_len.., 0);
+        }
+        if hunk_count > 0 {
+            index(&window[..hunk_len], (hunk_count - 1) as u64 * units_in_hunk);
+        }
+
+        Ok(ParentIndex {
+            sha1,
+            hunk_bytes,
+            unit_bytes,
+            units,
+        })
+    }
+
+    /// Returns the SHA1 of the parent, which is recorded as the parent SHA1 of the child.
+    pub fn sha1(&self) -> [u8; SHA1_BYTES] {
+        self.sha1
+    }
+
+    /// Returns the first unit of the parent at which the data is identical to `hunk`, if any.
+    pub fn find(&self, hunk: &[u8]) -> Option<u64> {
+        if hunk.len() != self.hunk_bytes as usize {
+            return None;
+        }
+        let digest: [u8; SHA1_BYTES] = Sha1::digest(hunk).into();
+        self.units.get(&digest).copied()
+    }
+}
+
+/// Writes bits from most significant to least significant, as read by the map decoder.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, num_bits: u8, value: u64) {
+        for bit in (0..num_bits).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if value >> bit & 1 != 0 {
+                *self.bytes.last_mut().unwrap() |= 1 << (7 - self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+/// Returns the number of bits needed to write `value`, which is at least 1.
+fn bits_for_value(value: u64) -> u8 {
+    (u64::BITS - value.leading_zeros()).max(1) as u8
+}
+
+/// Writes a CHD V5 file from its hunks.
+///
+/// Hunks must be written in order with [`write_hunk`](crate::write::ChdWriter::write_hunk), and the file
+/// is only valid once [`finish`](crate::write::ChdWriter::finish) succeeds.
+pub struct ChdWriter<W: Write + Seek> {
+    output: W,
+    compression: [u32; 4],
+    compressors: Vec<Option<(CodecType, Box<dyn HunkCompressor>)>>,
+    strategy: Box<dyn CompressionStrategy>,
+    parent: Option<ParentIndex>,
+    hunk_bytes: u32,
+    unit_bytes: u32,
+    logical_bytes: u64,
+    hunk_count: u32,
+    meta_offset: u64,
+    meta_hashes: Vec<[u8; 24]>,
+    hunks: Vec<WrittenHunk>,
+    hunk_hashes: HashMap<[u8; SHA1_BYTES], u32>,
+    raw_sha1: Sha1,
+    next_offset: u64,
+    comp_buf: Vec<u8>,
+    best_buf: Vec<u8>,
+}
+
+impl<W: Write + Seek> ChdWriter<W> {
+    /// Starts writing a CHD V5 file with the given geometry, codecs and metadata to `output`.
+    ///
+    /// Up to 4 codecs can be provided. If no codecs other than [`CodecType::None`](crate::header::CodecType::None)
+    /// are provided, the file is written without compression. Codecs that can not be compressed with
+    /// return [`Error::UnsupportedFormat`](crate::Error::UnsupportedFormat).
+    ///
+    /// The metadata is stored in the order it is provided, with its flags as is.
+    pub fn create(
+        mut output: W,
+        hunk_bytes: u32,
+        unit_bytes: u32,
+        logical_bytes: u64,
+        codecs: &[CodecType],
+        metadata: &[Metadata],
+    ) -> Result<Self> {
+        if hunk_bytes == 0
+            || unit_bytes == 0
+            || !hunk_bytes.is_multiple_of(unit_bytes)
+            || hunk_bytes > 0x00ffffff
+            || codecs.len() > 4
+        {
+            return Err(Error::InvalidParameter);
+        }
+        let hunk_count = u32::try_from(logical_bytes.div_ceil(hunk_bytes as u64))
+            .map_err(|_| Error::InvalidParameter)?;
+
+        let mut compression = [0u32; 4];
+        let mut compressors = Vec::with_capacity(codecs.len());
+        for (i, codec) in codecs.iter().enumerate() {
+            compression[i] = *codec as u32;
+            compressors.push(compressor_for(codec, hunk_bytes)?.map(|c| (*codec, c)));
+        }
+        if compressors.iter().all(Option::is_none) {
+            compression = [0; 4];
+            compressors.clear();
+        }
+
+        // the header is written once the hashes are known, and an uncompressed map directly follows it.
+        let mut next_offset = V5_HEADER_SIZE;
+        if compression[0] == 0 {
+            next_offset += hunk_count as u64 * V5_UNCOMPRESSED_MAP_ENTRY_SIZE as u64;
+        }
+        output.seek(SeekFrom::Start(0))?;
+        output.write_all(&vec![0u8; next_offset as usize])?;
+
+        let mut meta_offset = 0;
+        if !metadata.is_empty() {
+            meta_offset = next_offset;
+            let chain = serialize_chain(metadata, meta_offset)?;
+            output.write_all(&chain)?;
+            next_offset += chain.len() as u64;
+        }
+
+        Ok(ChdWriter {
+            output,
+            compression,
+            compressors,
+            strategy: Box::new(SmallestOutput),
+            parent: None,
+            hunk_bytes,
+            unit_bytes,
+            logical_bytes,
+            hunk_count,
+            meta_offset,
+            meta_hashes: metadata_hashes(metadata),
+            hunks: Vec::with_capacity(hunk_count as usize),
+            hunk_hashes: HashMap::new(),
+            raw_sha1: Sha1::new(),
+            next_offset,
+            comp_buf: Vec::new(),
+            best_buf: Vec::new(),
+        })
+    }
+
+    /// Sets the strategy that chooses which codecs each hunk is compressed with.
+    ///
+    /// By default, every codec is tried and the smallest result is kept, as with [`SmallestOutput`](crate::write::SmallestOutput).
+    pub fn with_strategy<S: CompressionStrategy + 'static>(mut self, strategy: S) -> Self {
+        self.strategy = Box::new(strategy);
+        self
+    }
+
+    /// Writes the file as a child of the indexed parent, so that hunks identical to data of the
+    /// parent are stored as references to the parent.
+    ///
+    /// The parent must have the same hunk size and unit size as the file, and the file must be
+    /// compressed, otherwise returns [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    /// If any hunks have already been written, returns [`Error::InvalidState`](crate::Error::InvalidState).
+    pub fn with_parent(mut self, parent: ParentIndex) -> Result<Self> {
+        if !self.hunks.is_empty() {
+            return Err(Error::InvalidState);
+        }
+        // uncompressed maps can only refer to the parent hunk at the same position.
+        if parent.hunk_bytes != self.hunk_bytes
+            || parent.unit_bytes != self.unit_bytes
+            || !self.is_compressed()
+        {
+            return Err(Error::InvalidParameter);
+        }
+        self.parent = Some(parent);
+        Ok(self)
+    }
+
+    /// Returns the number of hunks written so far that are stored as references to the parent.
+    pub fn parent_hunks(&self) -> u32 {
+        self.hunks
+            .iter()
+            .filter(|h| matches!(h, WrittenHunk::Parent(_)))
+            .count() as u32
+    }
+
+    fn is_compressed(&self) -> bool {
+        self.compression[0] != 0
+    }
+
+    /// Returns the number of hunks that have been written.
+    pub fn hunks_written(&self) -> u32 {
+        self.hunks.len() as u32
+    }
+
+    /// Compresses and writes the next hunk of the file.
+    ///
+    /// The hunk must be exactly as long as the hunk size of the file. The part of the last hunk past
+    /// the logical size of the file is stored, but does not contribute to the raw SHA1.
+    /// If every hunk of the file has already been written, returns [`Error::HunkOutOfRange`](crate::Error::HunkOutOfRange).
+    pub fn write_hunk(&mut self, hunk: &[u8]) -> Result<()> {
+        if hunk.len() != self.hunk_bytes as usize {
+            return Err(Error::InvalidParameter);
+        }
+        let hunk_num = self.hunks.len() as u32;
+        if hunk_num >= self.hunk_count {
+            return Err(Error::HunkOutOfRange);
+        }
+
+        let logical_remaining = self.logical_bytes - hunk_num as u64 * self.hunk_bytes as u64;
+        self.raw_sha1
+            .update(&hunk[..logical_remaining.min(self.hunk_bytes as u64) as usize]);
+
+        let digest: [u8; SHA1_BYTES] = Sha1::digest(hunk).into();
+        if let Some(&earlier) = self.hunk_hashes.get(&digest) {
+            self.hunks.push(WrittenHunk::SelfHunk(earlier));
+            return Ok(());
+        }
+        if let Some(unit) = self.parent.as_ref().and_then(|p| p.find(hunk)) {
+            self.hunks.push(WrittenHunk::Parent(unit));
+            return Ok(());
+        }
+        self.hunk_hashes.insert(digest, hunk_num);
+
+        let crc = crc16(hunk);
+        let mut best = None;
+        let mut tried = 0;
+        for (index, compressor) in self.compressors.iter_mut().enumerate() {
+            let (codec, compressor) = match compressor {
+                Some((codec, compressor)) => (*codec, compressor),
+                None => continue,
+            };
+            let attempt = CompressionAttempt {
+                hunk_num,
+                hunk_len: hunk.len(),
+                codec,
+                tried,
+                best_len: best.map(|_| self.best_buf.len()),
+            };
+            if !self.strategy.should_try(&attempt) {
+                continue;
+            }
+            tried += 1;
+            // like chdman, a codec that fails or does not shrink the hunk is not used for it.
+            if compressor.compress(hunk, &mut self.comp_buf).is_ok()
+                && self.comp_buf.len() < hunk.len()
+                && (best.is_none() || self.comp_buf.len() < self.best_buf.len())
+            {
+                std::mem::swap(&mut self.comp_buf, &mut self.best_buf);
+                best = Some(index as u8);
+            }
+        }
+
+        let written = match best {
+            Some(codec) => {
+                let offset = self.next_offset;
+                self.output.seek(SeekFrom::Start(offset))?;
+                self.output.write_all(&self.best_buf)?;
+                self.next_offset += self.best_buf.len() as u64;
+                WrittenHunk::Compressed {
+                    codec,
+                    offset,
+                    length: self.best_buf.len() as u32,
+                    crc,
+                }
+            }
+            None => {
+                // uncompressed maps address hunks in multiples of the hunk size.
+                let offset = if self.is_compressed() {
+                    self.next_offset
+                } else {
+                    self.next_offset.div_ceil(self.hunk_bytes as u64) * self.hunk_bytes as u64
+                };
+                self.output.seek(SeekFrom::Start(self.next_offset))?;
+                self.output
+                    .write_all(&vec![0u8; (offset - self.next_offset) as usize])?;
+                self.output.write_all(hunk)?;
+                self.next_offset = offset + self.hunk_bytes as u64;
+                WrittenHunk::Uncompressed { offset, crc }
+            }
+        };
+        self.hunks.push(written);
+        Ok(())
+    }
+
+    /// Writes the map and the header once every hunk has been written, and returns the output stream.
+    ///
+    /// If fewer hunks than the hunk count of the file have been written, returns
+    /// [`Error::InvalidState`](crate::Error::InvalidState).
+    pub fn finish(mut self) -> Result<W> {
+        if self.hunks_written() != self.hunk_count {
+            return Err(Error::InvalidState);
+        }
+
+        let (map_offset, map) = if self.is_compressed() {
+            (self.next_offset, self.compressed_map())
+        } else {
+            (V5_HEADER_SIZE, self.uncompressed_map())
+        };
+        self.output.seek(SeekFrom::Start(map_offset))?;
+        self.output.write_all(&map)?;
+
+        let raw_sha1: [u8; SHA1_BYTES] = self.raw_sha1.finalize_reset().into();
+        let header = Header::V5Header(HeaderV5 {
+            version: Version::ChdV5,
+            length: V5_HEADER_SIZE as u32,
+            compression: self.compression,
+            logical_bytes: self.logical_bytes,
+            map_offset,
+            meta_offset: self.meta_offset,
+            hunk_bytes: self.hunk_bytes,
+            unit_bytes: self.unit_bytes,
+            sha1: hash_with_metadata(&raw_sha1, &self.meta_hashes),
+            parent_sha1: self
+                .parent
+                .as_ref()
+                .map_or([0; SHA1_BYTES], ParentIndex::sha1),
+            raw_sha1,
+            unit_count: self.logical_bytes.div_ceil(self.unit_bytes as u64),
+            hunk_count: self.hunk_count,
+            map_entry_bytes: if self.is_compressed() {
+                V5_COMPRESSED_MAP_ENTRY_SIZE as u32
+            } else {
+                V5_UNCOMPRESSED_MAP_ENTRY_SIZE as u32
+            },
+        });
+        header.write_to(&mut self.output)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+
+    fn uncompressed_map(&self) -> Vec<u8> {
+        let mut map = Vec::with_capacity(self.hunks.len() * V5_UNCOMPRESSED_MAP_ENTRY_SIZE);
+        for hunk in &self.hunks {
+            let offset = match hunk {
+                WrittenHunk::Uncompressed { offset, .. } => *offset,
+                WrittenHunk::SelfHunk(earlier) => match self.hunks[*earlier as usize] {
+                    WrittenHunk::Uncompressed { offset, .. } => offset,
+                    _ => unreachable!("hunks of an uncompressed file are stored uncompressed"),
+                },
+                WrittenHunk::Compressed { .. } | WrittenHunk::Parent(_) => {
+                    unreachable!("hunks of an uncompressed file are stored uncompressed")
+                }
+            };
+            map.extend_from_slice(&((offset / self.hunk_bytes as u64) as u32).to_be_bytes());
+        }
+        map
+    }
+
+    fn compressed_map(&self) -> Vec<u8> {
+        let entries: Vec<_> = self
+            .hunks
+            .iter()
+            .map(|hunk| match *hunk {
+                WrittenHunk::Compressed {
+                    codec,
+                    offset,
+                    length,
+                    crc,
+                } => V5MapEntry {
+                    hunk_type: codec,
+                    length,
+                    offset,
+                    crc,
+                },
+                WrittenHunk::Uncompressed { offset, crc } => V5MapEntry {
+                    hunk_type: MAP_TYPE_NONE,
+                    length: self.hunk_bytes,
+                    offset,
+                    crc,
+                },
+                WrittenHunk::SelfHunk(earlier) => V5MapEntry {
+                    hunk_type: MAP_TYPE_SELF,
+                    length: 0,
+                    offset: earlier as u64,
+                    crc: 0,
+                },
+                WrittenHunk::Parent(unit) => V5MapEntry {
+                    hunk_type: MAP_TYPE_PARENT,
+                    length: 0,
+                    offset: unit,
+                    crc: 0,
+                },
+            })
+            .collect();
+        compress_v5_map(&entries, self.next_offset)
+    }
+}
+
+/// An entry of a V5 compressed map, as it is stored in the expanded map.
+#[derive(Copy, Clone)]
+pub(crate) struct V5MapEntry {
+    pub hunk_type: u8,
+    pub length: u32,
+    pub offset: u64,
+    pub crc: u16,
+}
+
+/// Encodes the entries of a V5 compressed map, including its 16 byte header.
+///
+/// The entries may only use the hunk types of an expanded map. If no entry stores hunk data,
+/// `data_end` is recorded as the offset of the first hunk.
+pub(crate) fn compress_v5_map(entries: &[V5MapEntry], data_end: u64) -> Vec<u8> {
+    let mut length_bits = 1;
+    let mut self_bits = 1;
+    let mut parent_bits = 1;
+    for entry in entries {
+        match entry.hunk_type {
+            MAP_TYPE_SELF => self_bits = self_bits.max(bits_for_value(entry.offset)),
+            MAP_TYPE_PARENT => parent_bits = parent_bits.max(bits_for_value(entry.offset)),
+            MAP_TYPE_NONE => {}
+            _ => length_bits = length_bits.max(bits_for_value(entry.length as u64)),
+        }
+    }
+
+    // every code of the map type tree is 4 bits long, so each type is written as itself.
+    let mut bits = BitWriter::default();
+    for _ in 0..16 {
+        bits.write(4, 4);
+    }
+    let mut raw_map = Vec::with_capacity(entries.len() * V5_COMPRESSED_MAP_ENTRY_SIZE);
+    let mut first_offs = None;
+    for entry in entries {
+        if entry.hunk_type != MAP_TYPE_SELF && entry.hunk_type != MAP_TYPE_PARENT {
+            first_offs.get_or_insert(entry.offset);
+        }
+        bits.write(4, entry.hunk_type as u64);
+        raw_map.push(entry.hunk_type);
+        raw_map.extend_from_slice(&entry.length.to_be_bytes()[1..]);
+        raw_map.extend_from_slice(&entry.offset.to_be_bytes()[2..]);
+        raw_map.extend_from_slice(&entry.crc.to_be_bytes());
+    }
+    for entry in entries {
+        match entry.hunk_type {
+            MAP_TYPE_SELF => bits.write(self_bits, entry.offset),
+            MAP_TYPE_PARENT => bits.write(parent_bits, entry.offset),
+            MAP_TYPE_NONE => bits.write(16, entry.crc as u64),
+            _ => {
+                bits.write(length_bits, entry.length as u64);
+                bits.write(16, entry.crc as u64);
+            }
+        }
+    }
+    // pad with enough zeroes for the decoder to peek past the end.
+    bits.bytes.extend_from_slice(&[0; 4]);
+
+    let mut map = Vec::with_capacity(16 + bits.bytes.len());
+    map.extend_from_slice(&(bits.bytes.len() as u32).to_be_bytes());
+    map.extend_from_slice(&first_offs.unwrap_or(data_end).to_be_bytes()[2..]);
+    map.extend_from_slice(&crc16(&raw_map).to_be_bytes());
+    map.extend_from_slice(&[length_bits, self_bits, parent_bits, 0]);
+    map.extend_from_slice(&bits.bytes);
+    map
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cdrom::CD_FRAME_SIZE;
+    use crate::header::CodecType;
+    use crate::make_tag;
+    use crate::map::{CompressionTypeV5, MapEntry};
+    use crate::metadata::{dump_all, Metadata};
+    use crate::write::{
+        can_compress, overall_sha1, AlwaysFirst, ChdWriter, CompressionAttempt,
+        CompressionStrategy, FastPreset, ParentIndex,
+    };
+    use crate::{Chd, Error};
+    use sha1::{Digest, Sha1};
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn round_trip(hunk_bytes: u32, unit_bytes: u32, codecs: &[CodecType], data: &[u8]) {
+        let metadata = vec![Metadata {
+            metatag: make_tag(b"GDDD"),
+            value: b"CYLS:1,HEADS:1,SECS:1,BPS:512\0".to_vec(),
+            flags: 0x01,
+            index: 0,
+            length: 30,
+        }];
+        let mut writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            hunk_bytes,
+            unit_bytes,
+            data.len() as u64,
+            codecs,
+            &metadata,
+        )
+        .expect("create");
+        for hunk in data.chunks(hunk_bytes as usize) {
+            let mut buf = hunk.to_vec();
+            buf.resize(hunk_bytes as usize, 0);
+            writer.write_hunk(&buf).expect("write hunk");
+        }
+        assert!(matches!(
+            writer.write_hunk(&vec![0u8; hunk_bytes as usize]),
+            Err(Error::HunkOutOfRange)
+        ));
+        let mut file = writer.finish().expect("finish");
+        if codecs.iter().any(|&codec| codec != CodecType::None) {
+            assert!(file.get_ref().len() < data.len());
+        }
+
+        file.set_position(0);
+        let mut chd = Chd::open(file, None).expect("open");
+        assert_eq!(data.len() as u64, chd.header().logical_bytes());
+        assert_eq!(
+            (data.len() as u64).div_ceil(unit_bytes as u64),
+            chd.header().unit_count()
+        );
+        let raw_sha1: [u8; 20] = Sha1::digest(data).into();
+        assert_eq!(Some(raw_sha1), chd.header().raw_sha1());
+        assert_eq!(
+            Some(overall_sha1(&raw_sha1, &metadata)),
+            chd.header().sha1()
+        );
+
+        let mut out = Vec::new();
+        for hunk in chd.hunk_bytes_iter() {
+            out.extend(hunk.expect("read hunk"));
+        }
+        assert_eq!(data, &out[..data.len()]);
+
+        let read = dump_all(&mut chd).expect("metadata");
+        assert_eq!(1, read.len());
+        assert_eq!(metadata[0].value, read[0].value);
+        assert_eq!(metadata[0].flags, read[0].flags);
+    }
+
+    #[test]
+    fn write_round_trip_test() {
+        let mut data = vec![0u8; 4096];
+        data.extend(noise(4096, 1));
+        data.extend(b"chd-rs ".iter().cycle().take(4096));
+        data.extend(noise(4096, 1));
+        data.extend(noise(1000, 2));
+
+        round_trip(4096, 512, &[CodecType::LzmaV5, CodecType::ZLibV5], &data);
+        round_trip(4096, 512, &[CodecType::None], &data);
+        round_trip(4096, 512, &[], &data);
+
+        let cd_hunk = CD_FRAME_SIZE * 8;
+        let mut data = vec![0u8; cd_hunk as usize];
+        data.extend(b"sector ".iter().cycle().take(cd_hunk as usize));
+        data.extend(noise(cd_hunk as usize, 3));
+        round_trip(
+            cd_hunk,
+            CD_FRAME_SIZE,
+            &[CodecType::LzmaCdV5, CodecType::ZLibCdV5],
+            &data,
+        );
+    }
+
+    fn hunk_types<S: CompressionStrategy + 'static>(
+        codecs: &[CodecType],
+        strategy: S,
+        data: &[u8],
+    ) -> Vec<CompressionTypeV5> {
+        let mut writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            4096,
+            512,
+            data.len() as u64,
+            codecs,
+            &[],
+        )
+        .expect("create")
+        .with_strategy(strategy);
+        for hunk in data.chunks(4096) {
+            writer.write_hunk(hunk).expect("write hunk");
+        }
+        let mut file = writer.finish().expect("finish");
+        file.set_position(0);
+        let chd = Chd::open(file, None).expect("open");
+        (0..chd.header().hunk_count() as usize)
+            .map(|hunk_num| match chd.map().get_entry(hunk_num) {
+                Some(MapEntry::V5Compressed(entry)) => entry.hunk_type().expect("hunk type"),
+                _ => panic!("expected a compressed map entry"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compression_strategy_test() {
+        struct Recorder(Rc<RefCell<Vec<CompressionAttempt>>>);
+        impl CompressionStrategy for Recorder {
+            fn should_try(&mut self, attempt: &CompressionAttempt) -> bool {
+                self.0.borrow_mut().push(*attempt);
+                true
+            }
+        }
+
+        let mut data: Vec<u8> = b"chd-rs ".iter().copied().cycle().take(4096).collect();
+        data.extend(noise(4096, 1));
+        let codecs = [CodecType::ZLibV5, CodecType::None, CodecType::LzmaV5];
+
+        let attempts = Rc::new(RefCell::new(Vec::new()));
+        hunk_types(&codecs, Recorder(Rc::clone(&attempts)), &data);
+        let attempts: Vec<_> = attempts
+            .borrow()
+            .iter()
+            .map(|a| (a.hunk_num, a.codec, a.tried, a.best_len))
+            .collect();
+        assert_eq!(4, attempts.len());
+        assert_eq!((0, CodecType::ZLibV5, 0, None), attempts[0]);
+        assert!(matches!(attempts[1], (0, CodecType::LzmaV5, 1, Some(len)) if len < 4096));
+        assert_eq!((1, CodecType::ZLibV5, 0, None), attempts[2]);
+        assert_eq!((1, CodecType::LzmaV5, 1, None), attempts[3]);
+
+        let types = hunk_types(&[CodecType::LzmaV5, CodecType::ZLibV5], AlwaysFirst, &data);
+        assert!(matches!(
+            types[..],
+            [
+                CompressionTypeV5::CompressionType0,
+                CompressionTypeV5::CompressionNone
+            ]
+        ));
+        let types = hunk_types(&[CodecType::ZLibV5, CodecType::LzmaV5], FastPreset, &data);
+        assert!(matches!(types[0], CompressionTypeV5::CompressionType0));
+    }
+
+    fn write_chd(data: &[u8]) -> Cursor<Vec<u8>> {
+        let mut writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            4096,
+            512,
+            data.len() as u64,
+            &[CodecType::ZLibV5],
+            &[],
+        )
+        .expect("create");
+        for hunk in data.chunks(4096) {
+            writer.write_hunk(hunk).expect("write hunk");
+        }
+        let mut file = writer.finish().expect("finish");
+        file.set_position(0);
+        file
+    }
+
+    #[test]
+    fn write_child_test() {
+        let parent_data = noise(4096 * 4, 5);
+        let parent_file = write_chd(&parent_data);
+        let mut parent = Chd::open(parent_file.clone(), None).expect("parent");
+        let index = ParentIndex::build(&mut parent).expect("index");
+        assert_eq!(Some(2), index.find(&parent_data[1024..5120]));
+        assert_eq!(None, index.find(&parent_data[1000..5096]));
+
+        // whole parent hunks, data of the parent beginning within a hunk, new data and a
+        // copy of new data, then the last hunk of the parent.
+        let mut data = parent_data[4096..8192].to_vec();
+        data.extend_from_slice(&parent_data[1536..5632]);
+        data.extend(noise(4096, 6));
+        data.extend(noise(4096, 6));
+        data.extend_from_slice(&parent_data[12288..]);
+
+        let mut writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            4096,
+            512,
+            data.len() as u64,
+            &[CodecType::ZLibV5],
+            &[],
+        )
+        .expect("create")
+        .with_parent(index)
+        .expect("parent");
+        for hunk in data.chunks(4096) {
+            writer.write_hunk(hunk).expect("write hunk");
+        }
+        assert_eq!(3, writer.parent_hunks());
+        let mut file = writer.finish().expect("finish");
+        file.set_position(0);
+        assert!(file.get_ref().len() < parent_file.get_ref().len());
+
+        let chd = Chd::open(file.clone(), None).expect("child");
+        assert_eq!(parent.header().sha1(), chd.header().parent_sha1());
+        let entries: Vec<_> = (0..5)
+            .map(|hunk_num| match chd.map().get_entry(hunk_num) {
+                Some(MapEntry::V5Compressed(entry)) => (
+                    entry.hunk_type().expect("hunk type") as u8,
+                    entry.block_offset().expect("offset"),
+                ),
+                _ => panic!("expected a compressed map entry"),
+            })
+            .collect();
+        let parent_type = CompressionTypeV5::CompressionParent as u8;
+        let self_type = CompressionTypeV5::CompressionSelf as u8;
+        assert_eq!((parent_type, 8), entries[0]);
+        assert_eq!((parent_type, 3), entries[1]);
+        assert_eq!((self_type, 2), entries[3]);
+        assert_eq!((parent_type, 24), entries[4]);
+
+        let mut chd = Chd::open(file, Some(Box::new(parent))).expect("child");
+        let mut out = Vec::new();
+        for hunk in chd.hunk_bytes_iter() {
+            out.extend(hunk.expect("read hunk"));
+        }
+        assert_eq!(data, out);
+
+        // the parent must have the same geometry, and the child must be compressed.
+        let mut parent = Chd::open(parent_file, None).expect("parent");
+        for (unit_bytes, codecs) in [(1024, &[CodecType::ZLibV5][..]), (512, &[])] {
+            let writer =
+                ChdWriter::create(Cursor::new(Vec::new()), 4096, unit_bytes, 4096, codecs, &[])
+                    .expect("create");
+            assert!(matches!(
+                writer.with_parent(ParentIndex::build(&mut parent).expect("index")),
+                Err(Error::InvalidParameter)
+            ));
+        }
+    }
+
+    #[test]
+    fn write_unsupported_test() {
+        assert!(matches!(
+            ChdWriter::create(
+                Cursor::new(Vec::new()),
+                4096,
+                512,
+                4096,
+                &[CodecType::FlacV5],
+                &[]
+            ),
+            Err(Error::UnsupportedFormat)
+        ));
+        assert!(!can_compress(CodecType::FlacV5));
+        assert!(can_compress(CodecType::LzmaCdV5));
+        assert!(matches!(
+            ChdWriter::create(
+                Cursor::new(Vec::new()),
+                4096,
+                512,
+                4096,
+                &[CodecType::ZLibCdV5],
+                &[]
+            ),
+            Err(Error::CodecError)
+        ));
+        let writer = ChdWriter::create(
+            Cursor::new(Vec::new()),
+            4096,
+            512,
+            8192,
+            &[CodecType::ZLibV5],
+            &[],
+        )
+        .expect("create");
+        assert!(matches!(writer.finish(), Err(Error::InvalidState)));
+    }
+}
@@ -1,9 +1,17 @@
 use anyhow::anyhow;
+use chd::cdrom::{ExtractOptions, TrackFileLayout};
+use chd::diff::DiffMode;
+use chd::dvd::DvdReader;
 use chd::header::{CodecType, Header};
 use chd::iter::LendingIterator;
-use chd::map::{CompressionTypeLegacy, CompressionTypeV5, MapEntry};
-use chd::metadata::Metadata;
-use chd::Chd;
+use chd::map::{CompressionTypeLegacy, CompressionTypeV5, MapEntry, MapExportFormat};
+use chd::metadata::parse::Creator;
+use chd::metadata::{dump_all, KnownMetadata, MetadataTag};
+use chd::read::ChdReader;
+use chd::repair::RepairOptions;
+use chd::split::SplitManifest;
+use chd::write::{can_compress, overall_sha1, ChdWriter, ParentIndex};
+use chd::{Chd, ChdOpenOptions, DecodeEvent, Tag};
 use clap::{Parser, Subcommand};
 use num_traits::cast::FromPrimitive;
 use sha1::{Digest, Sha1};
@@ -11,7 +19,8 @@ use std::ffi::OsStr;
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, BufWriter, Read, Seek, Write};
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thousands::Separable;
 
 fn validate_file_exists(s: &OsStr) -> Result<PathBuf, std::io::Error> {
@@ -25,20 +34,53 @@ fn validate_file_exists(s: &OsStr) -> Result<PathBuf, std::io::Error> {
     ))
 }
 
-fn try_fourcc_to_u32(s: &str) -> anyhow::Result<u32> {
-    const fn make_tag(a: &[u8; 4]) -> u32 {
-        ((a[0] as u32) << 24) | ((a[1] as u32) << 16) | ((a[2] as u32) << 8) | (a[3] as u32)
+fn parse_tag(s: &str) -> anyhow::Result<Tag> {
+    s.parse()
+        .map_err(|_| anyhow!("'{}' is not a tag of 1 to 4 ASCII characters", s))
+}
+
+fn parse_map_format(s: &str) -> anyhow::Result<MapExportFormat> {
+    match s {
+        "csv" => Ok(MapExportFormat::Csv),
+        "json" => Ok(MapExportFormat::Json),
+        _ => Err(anyhow!("unknown map format '{}', expected csv or json", s)),
     }
+}
 
-    let s = s.as_bytes();
-    let tag = [
-        s.get(0).map_or(b' ', |f| *f),
-        s.get(1).map_or(b' ', |f| *f),
-        s.get(2).map_or(b' ', |f| *f),
-        s.get(3).map_or(b' ', |f| *f),
-    ];
+fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| anyhow!("'{}' is not a size of at least 1 byte", s))
+}
+
+/// A list of up to 4 compression codecs.
+#[derive(Clone)]
+struct Compression(Vec<CodecType>);
 
-    Ok(make_tag(&tag))
+fn parse_compression(s: &str) -> anyhow::Result<Compression> {
+    if s == "none" {
+        return Ok(Compression(Vec::new()));
+    }
+    let codecs = s
+        .split(',')
+        .map(|name| {
+            CodecType::from_tag(parse_tag(name)?)
+                .ok_or_else(|| anyhow!("unknown compression codec '{}'", name))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if codecs.len() > 4 {
+        return Err(anyhow!("at most 4 compression codecs can be used"));
+    }
+    Ok(Compression(codecs))
 }
 
 #[derive(Parser)]
@@ -69,6 +111,12 @@ enum Commands {
         /// parent file name for input CHD
         #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
         inputparent: Option<PathBuf>,
+        /// number of times to read the CHD
+        #[clap(short = 'n', long, default_value = "1")]
+        passes: u32,
+        /// compute the SHA1 of the data while reading, and compare it against the header
+        #[clap(long)]
+        sha1: bool,
     },
     /// Verifies the integrity of a CHD
     Verify {
@@ -78,6 +126,27 @@ enum Commands {
         /// parent file name for input CHD
         #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
         inputparent: Option<PathBuf>,
+        /// rewrite the SHA1 hashes in the header if they do not match the data
+        #[clap(long)]
+        fix_sha1: bool,
+    },
+    /// Repairs recoverable corruption of a CHD in place
+    Repair {
+        /// input file name
+        #[clap(short, long, parse(try_from_os_str = validate_file_exists))]
+        input: PathBuf,
+        /// parent file name for input CHD
+        #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
+        inputparent: Option<PathBuf>,
+        /// rewrite the SHA1 hashes in the header if they do not match the data
+        #[clap(long)]
+        fix_sha1: bool,
+        /// re-encode the compressed map if it does not match its checksum
+        #[clap(long)]
+        rebuild_map: bool,
+        /// replace hunks that can not be read with zero-filled hunks
+        #[clap(long)]
+        zero_fill: bool,
     },
     /// Dump metadata from the CHD to stdout or to a file
     Dumpmeta {
@@ -91,11 +160,26 @@ enum Commands {
         #[clap(short, long)]
         force: bool,
         /// 4-character tag for metadata
-        #[clap(short, long, parse(try_from_str = try_fourcc_to_u32))]
-        tag: u32,
+        #[clap(short, long, parse(try_from_str = parse_tag))]
+        tag: Tag,
         #[clap(short = 'x', long, default_value = "0")]
         index: u32,
     },
+    /// Dump the hunk map of the CHD to stdout or to a file
+    Dumpmap {
+        /// input file name
+        #[clap(short, long, parse(try_from_os_str = validate_file_exists))]
+        input: PathBuf,
+        /// output file name
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// force overwriting an existing file
+        #[clap(short, long)]
+        force: bool,
+        /// output format, either csv or json
+        #[clap(long, default_value = "csv", parse(try_from_str = parse_map_format))]
+        format: MapExportFormat,
+    },
     /// Extract raw file from a CHD input file
     Extractraw {
         /// output file name
@@ -110,6 +194,123 @@ enum Commands {
         /// parent file name for input CHD
         #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
         inputparent: Option<PathBuf>,
+        /// starting byte offset within the input
+        #[clap(long, conflicts_with = "inputstarthunk")]
+        inputstartbyte: Option<u64>,
+        /// starting hunk offset within the input
+        #[clap(long)]
+        inputstarthunk: Option<u64>,
+        /// effective length of input in bytes
+        #[clap(long, conflicts_with = "inputhunks")]
+        inputbytes: Option<u64>,
+        /// effective length of input in hunks
+        #[clap(long)]
+        inputhunks: Option<u64>,
+    },
+    /// Extract CD file from a CHD input file
+    Extractcd {
+        /// output CUE file name
+        #[clap(short, long)]
+        output: PathBuf,
+        /// output BIN file name, or the base name of the BIN files if splitting tracks
+        #[clap(long = "outputbin", short = 'b')]
+        outputbin: Option<PathBuf>,
+        /// write each track to a separate BIN file
+        #[clap(long)]
+        splitbin: bool,
+        /// write only the 2048 bytes of user data of each sector of raw data tracks
+        #[clap(long)]
+        userdata: bool,
+        /// force overwriting an existing file
+        #[clap(short, long)]
+        force: bool,
+        /// input file name
+        #[clap(short, long, parse(try_from_os_str = validate_file_exists))]
+        input: PathBuf,
+        /// parent file name for input CHD
+        #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
+        inputparent: Option<PathBuf>,
+    },
+    /// Extract DVD file from a CHD input file
+    Extractdvd {
+        /// output file name
+        #[clap(short, long)]
+        output: PathBuf,
+        /// force overwriting an existing file
+        #[clap(short, long)]
+        force: bool,
+        /// input file name
+        #[clap(short, long, parse(try_from_os_str = validate_file_exists))]
+        input: PathBuf,
+        /// parent file name for input CHD
+        #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
+        inputparent: Option<PathBuf>,
+    },
+    /// Copy data from one CHD to another, recompressing it with different codecs
+    Copy {
+        /// output file name
+        #[clap(short, long)]
+        output: PathBuf,
+        /// force overwriting an existing file
+        #[clap(short, long)]
+        force: bool,
+        /// input file name
+        #[clap(short, long, parse(try_from_os_str = validate_file_exists))]
+        input: PathBuf,
+        /// parent file name for input CHD
+        #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
+        inputparent: Option<PathBuf>,
+        /// parent file name for output CHD, so that data found in it is not stored in the output
+        #[clap(long, parse(try_from_os_str = validate_file_exists))]
+        outputparent: Option<PathBuf>,
+        /// which compression codecs to use (up to 4, or none), defaulting to the codecs of the input
+        #[clap(short, long, parse(try_from_str = parse_compression))]
+        compression: Option<Compression>,
+    },
+    /// Split the raw data of a CHD into parts, with a manifest of their hashes
+    Split {
+        /// output base name, parts are written as <output>.000, <output>.001, ... and the manifest as <output>.manifest
+        #[clap(short, long)]
+        output: PathBuf,
+        /// force overwriting existing files
+        #[clap(short, long)]
+        force: bool,
+        /// input file name
+        #[clap(short, long, parse(try_from_os_str = validate_file_exists))]
+        input: PathBuf,
+        /// parent file name for input CHD
+        #[clap(short = 'p', long, parse(try_from_os_str = validate_file_exists))]
+        inputparent: Option<PathBuf>,
+        /// maximum size of each part in bytes, with an optional K, M or G suffix, defaulting to the largest FAT32 file
+        #[clap(short, long, parse(try_from_str = parse_size), conflicts_with = "parts")]
+        size: Option<u64>,
+        /// split into at most this many parts of equal size
+        #[clap(short = 'n', long)]
+        parts: Option<u64>,
+    },
+    /// Concatenate the parts of a split CHD, verifying them against the manifest
+    Concat {
+        /// input manifest file name
+        #[clap(short, long, parse(try_from_os_str = validate_file_exists))]
+        input: PathBuf,
+        /// output file name, if not given the parts are only verified
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// force overwriting an existing file
+        #[clap(short, long)]
+        force: bool,
+    },
+    /// Compares the contents of two CHDs hunk-by-hunk
+    Diff {
+        /// first input file name
+        #[clap(parse(try_from_os_str = validate_file_exists))]
+        a: PathBuf,
+        /// second input file name
+        #[clap(parse(try_from_os_str = validate_file_exists))]
+        b: PathBuf,
+        /// report hunks with differing map CRCs without decompressing them
+        #[clap(short, long)]
+        quick: bool,
     },
 }
 
@@ -178,25 +379,6 @@ fn info(input: &PathBuf, verbose: bool) -> anyhow::Result<()> {
     }
 
     fn print_compression(header: &Header) {
-        fn to_chdman_compression_name(ty: CodecType) -> &'static str {
-            match ty {
-                CodecType::None => "none",
-                CodecType::Zlib => "Legacy zlib (Deflate)",
-                CodecType::ZlibPlus => "Legacy zlib+ (Deflate)",
-                CodecType::AV => "Legacy av (AV)",
-                CodecType::ZLibV5 => "zlib (Deflate)",
-                CodecType::ZLibCdV5 => "cdzl (CD Deflate)",
-                CodecType::LzmaCdV5 => "cdlz (CD LZMA)",
-                CodecType::FlacCdV5 => "cdfl (CD FLAC)",
-                CodecType::FlacV5 => "flac (FLAC)",
-                CodecType::LzmaV5 => "lzma (LZMA)",
-                CodecType::AVHuffV5 => "avhu (A/V Huffman)",
-                CodecType::HuffV5 => "huff (Huffman)",
-                CodecType::ZstdV5 => "zstd (Zstandard)",
-                CodecType::ZstdCdV5 => "cdzs (CD Zstandard)",
-            }
-        }
-
         print!("Compression:\t");
         if !header.is_compressed() {
             println!("none");
@@ -205,52 +387,29 @@ fn info(input: &PathBuf, verbose: bool) -> anyhow::Result<()> {
 
         match header {
             Header::V1Header(h) | Header::V2Header(h) => {
-                println!(
-                    "{}",
-                    to_chdman_compression_name(CodecType::from_u32(h.compression).unwrap())
-                );
+                println!("{}", CodecType::from_u32(h.compression).unwrap());
             }
             Header::V3Header(h) => {
-                println!(
-                    "{}",
-                    to_chdman_compression_name(CodecType::from_u32(h.compression).unwrap())
-                );
+                println!("{}", CodecType::from_u32(h.compression).unwrap());
             }
             Header::V4Header(h) => {
-                println!(
-                    "{}",
-                    to_chdman_compression_name(CodecType::from_u32(h.compression).unwrap())
-                );
+                println!("{}", CodecType::from_u32(h.compression).unwrap());
             }
             Header::V5Header(h) => {
                 for compression in h.compression {
                     if compression == 0 {
                         break;
                     }
-                    print!(
-                        "{}, ",
-                        to_chdman_compression_name(CodecType::from_u32(compression).unwrap())
-                    );
+                    match CodecType::from_u32(compression) {
+                        Some(codec) => print!("{}, ", codec),
+                        None => print!("unknown ({}), ", Tag::from(compression)),
+                    }
                 }
                 println!();
             }
         }
     }
 
-    fn to_fourcc(fourcc: u32) -> anyhow::Result<[char; 4]> {
-        let parts = [
-            (fourcc >> 24) & 0xff,
-            (fourcc >> 16) & 0xff,
-            (fourcc >> 8) & 0xff,
-            fourcc & 0xff,
-        ];
-        let res = parts.map(char::from_u32);
-        if res.iter().any(|f| f.is_none()) {
-            return Err(anyhow!("unable to parse"));
-        }
-        Ok(res.map(Option::unwrap))
-    }
-
     fn print_verbose<F: Seek + Read>(chd: &Chd<F>) -> anyhow::Result<()> {
         // can only have 4 comptypes.
         // first four is for the four comp types.
@@ -289,11 +448,19 @@ fn info(input: &PathBuf, verbose: bool) -> anyhow::Result<()> {
                     CompressionTypeV5::CompressionParent
                     | CompressionTypeV5::CompressionParentSelf
                     | CompressionTypeV5::CompressionParent0
-                    | CompressionTypeV5::CompressionParent1 => {}
-                    _ => {
+                    | CompressionTypeV5::CompressionParent1 => {
                         hunk_count[6] += 1;
                     }
+                    _ => {
+                        hunk_count[8] += 1;
+                    }
                 },
+                // an uncompressed entry with no offset is read from the same hunk in the parent.
+                MapEntry::V5Uncompressed(c)
+                    if c.block_offset()? == 0 && chd.header().has_parent() =>
+                {
+                    hunk_count[6] += 1;
+                }
                 MapEntry::V5Uncompressed(_) => {
                     hunk_count[4] += 1;
                 }
@@ -335,16 +502,15 @@ fn info(input: &PathBuf, verbose: bool) -> anyhow::Result<()> {
                     6 => "Copy from parent",
                     7 => "Legacy 8-byte mini",
                     8 => "Unknown",
-                    i => codec_name(
-                        CodecType::from_u32(match chd.header() {
-                            Header::V1Header(h) => h.compression,
-                            Header::V2Header(h) => h.compression,
-                            Header::V3Header(h) => h.compression,
-                            Header::V4Header(h) => h.compression,
-                            Header::V5Header(h) => h.compression[i],
-                        })
-                        .unwrap(),
-                    ),
+                    // files may declare codecs that are not known.
+                    i => CodecType::from_u32(match chd.header() {
+                        Header::V1Header(h) => h.compression,
+                        Header::V2Header(h) => h.compression,
+                        Header::V3Header(h) => h.compression,
+                        Header::V4Header(h) => h.compression,
+                        Header::V5Header(h) => h.compression[i],
+                    })
+                    .map_or("Unknown", codec_name),
                 };
                 (*count, percent, name)
             })
@@ -416,22 +582,34 @@ fn info(input: &PathBuf, verbose: bool) -> anyhow::Result<()> {
     // hash
     print_hash(chd.header());
 
-    if let Ok(metadata) = Vec::<Metadata>::try_from(chd.metadata_refs()) {
+    if chd.header().has_parent() {
+        let hunk_count = chd.header().hunk_count();
+        let parent_refs = chd.map().parent_refs(chd.header()).count();
+        let parent_hunks = chd.map().required_parent_hunks(chd.header()).len();
+        println!(
+            "Parent Refs:\t{} of {} hunks ({:.1}%) are read from the parent",
+            parent_refs.separate_with_commas(),
+            hunk_count.separate_with_commas(),
+            100.0 * parent_refs as f64 / hunk_count.max(1) as f64
+        );
+        println!(
+            "Parent Hunks:\t{} hunks of the parent are needed",
+            parent_hunks.separate_with_commas()
+        );
+    }
+
+    if let Ok(Some(creator)) = chd.creator() {
+        println!("Creator:\t{}", creator);
+    }
+
+    if let Ok(metadata) = chd.metadata_refs().try_into_vec() {
         for meta in metadata {
-            let tag = to_fourcc(meta.metatag);
-            if let Ok(tag) = tag {
-                println!(
-                    "Metadata:\tTag='{}'  Index={}  Length={} bytes",
-                    tag.iter().collect::<String>(),
-                    meta.index,
-                    meta.length
-                );
-            } else {
-                println!(
-                    "Metadata:\tTag={:0x}  Index={}  Length={} bytes",
-                    meta.metatag, meta.index, meta.length
-                );
-            }
+            println!(
+                "Metadata:\tTag={:?}  Index={}  Length={} bytes",
+                meta.tag(),
+                meta.index,
+                meta.length
+            );
             print!("              \t");
             println!(
                 "{}",
@@ -459,7 +637,16 @@ fn info(input: &PathBuf, verbose: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn benchmark(p: impl AsRef<Path>, ip: Option<impl AsRef<Path>>) -> anyhow::Result<()> {
+fn benchmark(
+    p: impl AsRef<Path>,
+    ip: Option<impl AsRef<Path>>,
+    passes: u32,
+    sha1: bool,
+) -> anyhow::Result<()> {
+    fn rate(bytes: usize, time: Duration) -> f64 {
+        (bytes as f64 / (1024.0 * 1024.0)) / time.as_secs_f64()
+    }
+
     println!("\nchd-rs - rchdman benchmark");
     let f = BufReader::new(File::open(p)?);
     let ipf = ip.map(|ip| BufReader::new(File::open(ip).unwrap()));
@@ -472,48 +659,133 @@ fn benchmark(p: impl AsRef<Path>, ip: Option<impl AsRef<Path>>) -> anyhow::Resul
     } else {
         Chd::open(f, None)?
     };
+    println!(
+        "Opened in {} seconds",
+        Instant::now()
+            .saturating_duration_since(start)
+            .as_secs_f64()
+    );
 
     let mut hunk_buf = chd.get_hunksized_buffer();
     let mut cmp_buf = Vec::new();
-    let hunk_iter = chd.hunks();
-    let mut bytes = 0;
-    let mut hunk_num = 0;
-
-    hunk_iter.for_each(|mut hunk| {
-        bytes += hunk
-            .read_hunk_in(&mut cmp_buf, &mut hunk_buf)
-            .unwrap_or_else(|_| panic!("could not read_hunk {}", hunk_num));
-        hunk_num += 1;
+    let logical_bytes = chd.header().logical_bytes();
+
+    // compressed bytes, decompressed bytes and time spent per codec over all passes.
+    let codec_stats = Arc::new(Mutex::new(Vec::<(CodecType, usize, usize, Duration)>::new()));
+    chd.set_decode_observer({
+        let codec_stats = Arc::clone(&codec_stats);
+        move |event: &DecodeEvent| {
+            let mut codec_stats = codec_stats.lock().unwrap();
+            let idx = match codec_stats.iter().position(|(c, ..)| *c == event.codec) {
+                Some(idx) => idx,
+                None => {
+                    codec_stats.push((event.codec, 0, 0, Duration::ZERO));
+                    codec_stats.len() - 1
+                }
+            };
+            let stats = &mut codec_stats[idx];
+            stats.1 += event.compressed_len;
+            stats.2 += event.decompressed_len;
+            stats.3 += event.duration;
+        }
     });
+    let mut rates = Vec::with_capacity(passes as usize);
+    let mut raw_sha1 = None;
 
-    let time = Instant::now().saturating_duration_since(start);
+    for pass in 1..=passes.max(1) {
+        let start = Instant::now();
+        let mut hasher = sha1.then(Sha1::new);
+        let mut remaining = logical_bytes;
+        let mut bytes = 0;
+
+        for hunk_num in 0..chd.header().hunk_count() {
+            let read = chd
+                .hunk(hunk_num)?
+                .read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+                .unwrap_or_else(|_| panic!("could not read_hunk {}", hunk_num));
+            bytes += read;
+
+            if let Some(hasher) = hasher.as_mut() {
+                let len = remaining.min(read as u64);
+                hasher.update(&hunk_buf[..len as usize]);
+                remaining -= len;
+            }
+        }
+
+        let time = Instant::now().saturating_duration_since(start);
+        println!(
+            "Pass {}: read {} bytes ({} hunks) in {} seconds, {:.2} MB/s",
+            pass,
+            bytes.separate_with_commas(),
+            chd.header().hunk_count(),
+            time.as_secs_f64(),
+            rate(bytes, time)
+        );
+        rates.push(rate(bytes, time));
+        raw_sha1 = hasher.map(|hasher| <[u8; 20]>::from(hasher.finalize()));
+    }
+
+    let min = rates.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = rates.iter().copied().fold(0.0, f64::max);
+    let avg = rates.iter().sum::<f64>() / rates.len() as f64;
     println!(
-        "Read {} bytes ({} hunks) in {} seconds",
-        bytes,
-        hunk_num,
-        time.as_secs_f64()
+        "Rate is {:.2} MB/s average ({:.2} min, {:.2} max) over {} passes",
+        avg,
+        min,
+        max,
+        rates.len()
     );
+
+    if let Some(raw_sha1) = raw_sha1 {
+        let matches = match chd.header() {
+            Header::V3Header(h) => Some(h.sha1 == raw_sha1),
+            Header::V4Header(h) => Some(h.raw_sha1 == raw_sha1),
+            Header::V5Header(h) => Some(h.raw_sha1 == raw_sha1),
+            _ => None,
+        };
+        println!(
+            "Data SHA1:  {}{}",
+            hex::encode(raw_sha1),
+            match matches {
+                Some(true) => " (matches header)",
+                Some(false) => " (does not match header)",
+                None => "",
+            }
+        );
+    }
+
+    let codec_stats = codec_stats.lock().unwrap();
+    let total_time: Duration = codec_stats.iter().map(|(.., time)| *time).sum();
+    println!("Time spent per codec:");
+    for (codec, compressed, decompressed, time) in codec_stats.iter() {
+        let name = match codec {
+            CodecType::None => String::from("none"),
+            codec if codec.is_legacy() => String::from("legacy zlib"),
+            codec => String::from_utf8_lossy(&(*codec as u32).to_be_bytes()).into_owned(),
+        };
+        println!(
+            "  {}: {} bytes from {} bytes at {:.2} MB/s, {:.1}% of time",
+            name,
+            decompressed.separate_with_commas(),
+            compressed.separate_with_commas(),
+            rate(*decompressed, *time),
+            100.0 * time.as_secs_f64() / total_time.as_secs_f64().max(f64::EPSILON)
+        );
+    }
     println!(
-        "Rate is {} MB/s",
-        (bytes / (1024 * 1024)) as f64 / time.as_secs_f64()
+        "Hunks that copy another hunk are counted again, and hunks read from the parent are not counted."
     );
 
     Ok(())
 }
 
-fn verify(input: impl AsRef<Path>, inputparent: Option<impl AsRef<Path>>) -> anyhow::Result<()> {
+fn verify(
+    input: impl AsRef<Path>,
+    inputparent: Option<impl AsRef<Path>>,
+    fix_sha1: bool,
+) -> anyhow::Result<()> {
     println!("\nchd-rs - rchdman verify");
-    let f = BufReader::new(File::open(input)?);
-
-    let p = if let Some(parent) = inputparent {
-        let f = BufReader::new(File::open(parent)?);
-        let parent_chd = Chd::open(f, None)?;
-        Some(Box::new(parent_chd))
-    } else {
-        None
-    };
-
-    let mut chd = Chd::open(f, p)?;
+    let mut chd = Chd::open_path(input.as_ref(), inputparent.as_ref().map(AsRef::as_ref))?;
 
     let header = chd.header();
     if !header.is_compressed() {
@@ -529,13 +801,20 @@ fn verify(input: impl AsRef<Path>, inputparent: Option<impl AsRef<Path>>) -> any
 
     let mut hasher = Sha1::new();
     let mut out_buf = chd.get_hunksized_buffer();
+    // like chdman, the padding past the logical size in the last hunk is not hashed.
+    let mut remaining = header.logical_bytes();
     let mut hunk_iter = chd.hunks();
     let mut comp_buffer = Vec::new();
     while let Some(mut hunk) = hunk_iter.next() {
         hunk.read_hunk_in(&mut comp_buffer, &mut out_buf)?;
-        hasher.update(&out_buf);
+        let len = remaining.min(out_buf.len() as u64);
+        hasher.update(&out_buf[..len as usize]);
+        remaining -= len;
     }
-    let raw_result = hasher.finalize();
+    let raw_result: [u8; 20] = hasher.finalize().into();
+
+    let mut header = chd.header().clone();
+    let mut needs_fix = false;
 
     if raw_result[..] == raw_sha1[..] {
         println!("Raw SHA1 verification successful!");
@@ -545,28 +824,101 @@ fn verify(input: impl AsRef<Path>, inputparent: Option<impl AsRef<Path>>) -> any
             hex::encode(raw_sha1),
             hex::encode(raw_result)
         );
+        header.set_raw_sha1(raw_result)?;
+        needs_fix = true;
+    }
+
+    // V3 headers only contain the SHA1 of the raw data.
+    if let Some(sha1) = header.raw_sha1().and(header.sha1()) {
+        let metadatas = chd.metadata_refs().try_into_vec()?;
+        let overall_result = overall_sha1(&raw_result, &metadatas);
+        if overall_result == sha1 {
+            println!("Overall SHA1 verification successful!");
+        } else {
+            eprintln!(
+                "Error: Overall SHA1 in header = {}\n                  actual SHA1 = {}\n",
+                hex::encode(sha1),
+                hex::encode(overall_result)
+            );
+            header.set_sha1(overall_result)?;
+            needs_fix = true;
+        }
+    }
+
+    if needs_fix {
+        if fix_sha1 {
+            // release the read handle before reopening the file for writing.
+            drop(chd);
+            let mut f = OpenOptions::new().read(true).write(true).open(input)?;
+            header.write_to(&mut f)?;
+            println!("SHA1 hashes in header updated to match the data.");
+        } else {
+            println!("Run with --fix-sha1 to update the SHA1 hashes in the header.");
+        }
     }
 
     // todo: full verification
     Ok(())
 }
 
+fn repair(
+    input: impl AsRef<Path>,
+    inputparent: Option<impl AsRef<Path>>,
+    options: &RepairOptions,
+) -> anyhow::Result<()> {
+    println!("\nchd-rs - rchdman repair");
+    let file = OpenOptions::new().read(true).write(true).open(input)?;
+    // the map is read lazily so that a map that fails its checksum can still be rebuilt.
+    let open_options = ChdOpenOptions::new().lazy_map(true).lenient(true);
+    let chd = match inputparent {
+        Some(parent) => {
+            let parent = Chd::open(File::open(parent)?, None)?;
+            open_options.parent(Box::new(parent)).open(file)?
+        }
+        None => open_options.open(file)?,
+    };
+
+    let report = chd::repair::repair(chd, options)?;
+    for (hunk_num, err) in &report.unreadable_hunks {
+        eprintln!("Error: hunk {} could not be read: {}", hunk_num, err);
+    }
+    if report.unreadable_hunks.is_empty() {
+        println!("All hunks were read successfully.");
+    } else if report.zero_filled {
+        println!(
+            "{} unreadable hunks replaced with zero-filled hunks.",
+            report.unreadable_hunks.len()
+        );
+    } else {
+        println!("Run with --zero-fill to replace unreadable hunks with zero-filled hunks.");
+    }
+    if report.map_rebuilt {
+        println!("Compressed map rebuilt.");
+    }
+    if let Some(raw_sha1) = report.raw_sha1 {
+        println!("Raw SHA1:     {}", hex::encode(raw_sha1));
+    }
+    if report.hashes_fixed {
+        println!("SHA1 hashes in header updated to match the data.");
+    }
+    Ok(())
+}
+
 fn dumpmeta(
     input: impl AsRef<Path>,
     output: Option<&PathBuf>,
     force: bool,
-    tag: u32,
+    tag: Tag,
     index: u32,
 ) -> anyhow::Result<()> {
     println!("\nchd-rs - rchdman dumpmeta");
 
-    let mut f = BufReader::new(File::open(input)?);
-    let mut chd = Chd::open(&mut f, None)?;
+    let mut chd = Chd::open_path(input, None)?;
 
-    let metas: Vec<Metadata> = chd.metadata_refs().try_into()?;
+    let metas = chd.metadata_refs().try_into_vec()?;
     let tag = metas
         .iter()
-        .find(|p| p.metatag == tag && p.index == index)
+        .find(|p| p.tag() == tag && p.index == index)
         .ok_or_else(|| anyhow!("Error reading metadata: can't find metadata"))?;
 
     if let Some(output) = output {
@@ -576,19 +928,92 @@ fn dumpmeta(
             .create(true)
             .truncate(true)
             .open(output)?;
-        file.write_all(&*tag.value)?;
+        file.write_all(&tag.value)?;
         println!("File ({}) written, {} bytes", output.display(), tag.length)
     } else {
-        println!("{}", String::from_utf8_lossy(&*tag.value));
+        println!("{}", String::from_utf8_lossy(&tag.value));
+    }
+    Ok(())
+}
+
+fn dumpmap(
+    input: impl AsRef<Path>,
+    output: Option<&PathBuf>,
+    force: bool,
+    format: MapExportFormat,
+) -> anyhow::Result<()> {
+    // the map of a truncated CHD can still be listed.
+    let mut f = BufReader::new(File::open(input)?);
+    let chd = ChdOpenOptions::new().validate_length(false).open(&mut f)?;
+
+    if let Some(output) = output {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(!force)
+            .create(true)
+            .truncate(true)
+            .open(output)?;
+        let mut file = BufWriter::new(file);
+        chd.map().export(chd.header(), format, &mut file)?;
+        file.flush()?;
+        eprintln!(
+            "File ({}) written, {} hunks",
+            output.display(),
+            chd.map().len()
+        );
+    } else {
+        let stdout = std::io::stdout();
+        chd.map().export(chd.header(), format, stdout.lock())?;
     }
     Ok(())
 }
 
+const EXTRACT_CHUNK_BYTES: u32 = 8 * 1024 * 1024;
+
+/// A window of the input to extract, in bytes or hunks.
+struct InputWindow {
+    start_byte: Option<u64>,
+    start_hunk: Option<u64>,
+    bytes: Option<u64>,
+    hunks: Option<u64>,
+}
+
+impl InputWindow {
+    /// Returns the starting byte and length of the window for a CHD with the given hunk size and logical size.
+    fn resolve(&self, hunk_size: u64, logical_bytes: u64) -> anyhow::Result<(u64, Option<u64>)> {
+        let start = match (self.start_byte, self.start_hunk) {
+            (Some(byte), _) => byte,
+            (None, Some(hunk)) => hunk
+                .checked_mul(hunk_size)
+                .ok_or_else(|| anyhow!("input start hunk is out of range"))?,
+            (None, None) => 0,
+        };
+        if start > logical_bytes {
+            return Err(anyhow!(
+                "input start offset {} is past the end of the input ({} bytes)",
+                start,
+                logical_bytes
+            ));
+        }
+        let len = match (self.bytes, self.hunks) {
+            (Some(bytes), _) => Some(bytes),
+            (None, Some(hunks)) => Some(
+                hunks
+                    .checked_mul(hunk_size)
+                    .ok_or_else(|| anyhow!("input hunk count is out of range"))?,
+            ),
+            (None, None) => None,
+        };
+        Ok((start, len))
+    }
+}
+
 fn extractraw(
     input: &PathBuf,
     inputparent: Option<impl AsRef<Path>>,
     output: &PathBuf,
     force: bool,
+    window: InputWindow,
 ) -> anyhow::Result<()> {
     println!("\nchd-rs - rchdman extractraw");
     let mut output_file = BufWriter::new(
@@ -603,26 +1028,447 @@ fn extractraw(
     println!("Output File:  {}", output.display());
     println!("Input CHD:    {}", input.display());
 
-    let f = BufReader::new(File::open(input)?);
+    let chd = Chd::open_path(input, inputparent.as_ref().map(AsRef::as_ref))?;
+    let (start, len) = window.resolve(
+        chd.header().hunk_size() as u64,
+        chd.header().logical_bytes(),
+    )?;
+    if start != 0 || len.is_some() {
+        println!("Input offset: {}", start.separate_with_commas());
+    }
+
+    // read in large chunks, so that hunks stored uncompressed are copied with large sequential reads.
+    let read_ahead = (EXTRACT_CHUNK_BYTES / chd.header().hunk_size().max(1)).max(1);
+    let mut reader = ChdReader::with_read_ahead(chd, read_ahead);
+    let copied = reader.copy_range(start, len, &mut output_file)?;
+    println!(
+        "Extraction complete ({} bytes)",
+        copied.separate_with_commas()
+    );
+    output_file.flush()?;
+    Ok(())
+}
+
+fn extractcd(
+    input: &Path,
+    inputparent: Option<impl AsRef<Path>>,
+    output: &Path,
+    outputbin: Option<&Path>,
+    splitbin: bool,
+    userdata: bool,
+    force: bool,
+) -> anyhow::Result<()> {
+    println!("\nchd-rs - rchdman extractcd");
+    let create = |path: &Path| -> anyhow::Result<BufWriter<File>> {
+        Ok(BufWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .create_new(!force)
+                .create(true)
+                .truncate(true)
+                .open(path)?,
+        ))
+    };
 
-    let p = if let Some(parent) = inputparent {
-        let f = BufReader::new(File::open(parent)?);
-        let parent_chd = Chd::open(f, None)?;
-        Some(Box::new(parent_chd))
+    let bin_base = outputbin
+        .unwrap_or(output)
+        .with_extension("")
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!("Invalid output file name"))?;
+    let out_dir = output.parent().unwrap_or_else(|| Path::new(""));
+
+    println!("Output CUE:   {}", output.display());
+    println!("Input CHD:    {}", input.display());
+
+    let mut chd = Chd::open_path(input, inputparent.as_ref().map(AsRef::as_ref))?;
+    let tracks = chd.tracks()?;
+    if tracks.is_empty() {
+        return Err(anyhow!("Input CHD is not a CD-ROM"));
+    }
+
+    let layout = if splitbin {
+        TrackFileLayout::Split
     } else {
-        None
+        TrackFileLayout::Combined
     };
+    let options = ExtractOptions::new()
+        .layout(layout)
+        .user_data_only(userdata);
 
-    let mut chd = Chd::open(f, p)?;
-    let mut cmp_buf = Vec::new();
-    let mut out_buf = chd.get_hunksized_buffer();
-    let mut hunk_iter = chd.hunks();
-    while let Some(mut hunk) = hunk_iter.next() {
-        hunk.read_hunk_in(&mut cmp_buf, &mut out_buf)?;
-        output_file.write_all(&out_buf)?;
+    let mut cue = create(output)?;
+    options.write_cue(&tracks, &bin_base, &mut cue)?;
+    cue.flush()?;
+
+    let mut bin: Option<BufWriter<File>> = None;
+    for track in &tracks {
+        if bin.is_none() || splitbin {
+            if let Some(mut bin) = bin.take() {
+                bin.flush()?;
+            }
+            let bin_path = out_dir.join(options.bin_file_name(&bin_base, track));
+            println!("Output BIN:   {}", bin_path.display());
+            bin = Some(create(&bin_path)?);
+        }
+        if let Some(bin) = bin.as_mut() {
+            print!("Extracting track {:02}... ", track.number());
+            let extracted = options.write_track(&mut chd, track, bin)?;
+            println!("done");
+            for lba in extracted.edc_failures {
+                println!("Warning: EDC mismatch in sector {}", lba);
+            }
+        }
+    }
+    if let Some(mut bin) = bin {
+        bin.flush()?;
     }
     println!("Extraction complete");
+    Ok(())
+}
+
+fn extractdvd(
+    input: &Path,
+    inputparent: Option<impl AsRef<Path>>,
+    output: &Path,
+    force: bool,
+) -> anyhow::Result<()> {
+    println!("\nchd-rs - rchdman extractdvd");
+    let chd = Chd::open_path(input, inputparent.as_ref().map(AsRef::as_ref))?;
+    let mut dvd = match DvdReader::new(chd) {
+        Err(chd::Error::MetadataNotFound) => return Err(anyhow!("Input CHD is not a DVD")),
+        dvd => dvd?,
+    };
+
+    let mut output_file = BufWriter::new(
+        OpenOptions::new()
+            .write(true)
+            .create_new(!force)
+            .create(true)
+            .truncate(true)
+            .open(output)?,
+    );
+
+    println!("Output File:  {}", output.display());
+    println!("Input CHD:    {}", input.display());
+
+    let extracted = dvd.extract_iso(&mut output_file)?;
     output_file.flush()?;
+    println!(
+        "Extraction complete ({} bytes)",
+        extracted.separate_with_commas()
+    );
+    Ok(())
+}
+
+fn copy(
+    input: &Path,
+    inputparent: Option<impl AsRef<Path>>,
+    output: &Path,
+    outputparent: Option<&Path>,
+    force: bool,
+    compression: Option<&Compression>,
+) -> anyhow::Result<()> {
+    println!("\nchd-rs - rchdman copy");
+
+    let mut chd = Chd::open_path(input, inputparent.as_ref().map(AsRef::as_ref))?;
+
+    let codecs = match (compression, chd.header()) {
+        (Some(Compression(codecs)), _) => codecs.clone(),
+        (None, Header::V5Header(h)) => h
+            .compression
+            .iter()
+            .filter(|&&c| c != 0)
+            .filter_map(|&c| CodecType::from_u32(c))
+            .collect(),
+        // the legacy codecs are all zlib.
+        (None, header) if header.is_compressed() => vec![CodecType::ZLibV5],
+        (None, _) => Vec::new(),
+    };
+
+    println!("Output File:  {}", output.display());
+    if let Some(outputparent) = outputparent {
+        println!("Parent CHD:   {}", outputparent.display());
+    }
+    println!("Input CHD:    {}", input.display());
+    println!(
+        "Compression:  {}",
+        if codecs.is_empty() {
+            String::from("none")
+        } else {
+            codecs
+                .iter()
+                .map(|&c| String::from_utf8_lossy(&(c as u32).to_be_bytes()).into_owned())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    );
+
+    if let Some(&codec) = codecs.iter().find(|&&c| !can_compress(c)) {
+        return Err(anyhow!(
+            "compressing with {:?} is not supported, only zlib, lzma, cdzl and cdlz are",
+            codec
+        ));
+    }
+
+    let parent_index = match outputparent {
+        Some(_) if codecs.is_empty() => {
+            return Err(anyhow!(
+                "an output parent can only be used with compression"
+            ))
+        }
+        Some(outputparent) => {
+            let mut parent = Chd::open_path(outputparent, None)?;
+            if parent.header().hunk_size() != chd.header().hunk_size()
+                || parent.header().unit_bytes() != chd.header().unit_bytes()
+            {
+                return Err(anyhow!(
+                    "the output parent must have the same hunk size and unit size as the input"
+                ));
+            }
+            Some(ParentIndex::build(&mut parent)?)
+        }
+        None => None,
+    };
+
+    // the output is created by chd-rs, regardless of the creator of the input.
+    let mut metadata = dump_all(&mut chd)?;
+    metadata.retain(|m| m.metatag != KnownMetadata::Creator as u32);
+    metadata.push(Creator::chd_rs().to_metadata());
+    let header = chd.header().clone();
+    let output_file = BufWriter::new(
+        OpenOptions::new()
+            .write(true)
+            .create_new(!force)
+            .create(true)
+            .truncate(true)
+            .open(output)?,
+    );
+    let mut writer = ChdWriter::create(
+        output_file,
+        header.hunk_size(),
+        header.unit_bytes(),
+        header.logical_bytes(),
+        &codecs,
+        &metadata,
+    )?;
+    if let Some(parent_index) = parent_index {
+        writer = writer.with_parent(parent_index)?;
+    }
+
+    let mut hunk_buf = chd.get_hunksized_buffer();
+    let mut cmp_buf = Vec::new();
+    for hunk_num in 0..header.hunk_count() {
+        chd.hunk(hunk_num)?
+            .read_hunk_in(&mut cmp_buf, &mut hunk_buf)?;
+        writer.write_hunk(&hunk_buf)?;
+    }
+    if outputparent.is_some() {
+        println!(
+            "Parent hunks: {} of {} hunks are stored in the output parent",
+            writer.parent_hunks(),
+            header.hunk_count()
+        );
+    }
+    writer.finish()?.flush()?;
+
+    let output_len = std::fs::metadata(output)?.len();
+    println!(
+        "Compression complete ... final ratio = {:.1}%",
+        100.0 * output_len as f64 / header.logical_bytes().max(1) as f64
+    );
+    Ok(())
+}
+
+/// The size of the largest file that can be stored on a FAT32 file system.
+const FAT32_MAX_FILE_BYTES: u64 = 0xFFFF_FFFF;
+
+fn split(
+    input: &Path,
+    inputparent: Option<impl AsRef<Path>>,
+    output: &Path,
+    force: bool,
+    size: Option<u64>,
+    parts: Option<u64>,
+) -> anyhow::Result<()> {
+    println!("\nchd-rs - rchdman split");
+    let base_name = output
+        .file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| anyhow!("Output base name must be a valid UTF-8 file name"))?;
+    let output_dir = output.parent().unwrap_or_else(|| Path::new(""));
+    let mut manifest_path = output.as_os_str().to_owned();
+    manifest_path.push(".manifest");
+    let manifest_path = PathBuf::from(manifest_path);
+
+    let mut chd = Chd::open_path(input, inputparent.as_ref().map(AsRef::as_ref))?;
+    let logical_bytes = chd.header().readable_bytes();
+    let part_len = match (size, parts) {
+        (Some(size), _) => size,
+        (None, Some(0)) => return Err(anyhow!("Number of parts must be at least 1")),
+        (None, Some(parts)) => logical_bytes.div_ceil(parts).max(1),
+        (None, None) => FAT32_MAX_FILE_BYTES,
+    };
+
+    let mut manifest_file = BufWriter::new(
+        OpenOptions::new()
+            .write(true)
+            .create_new(!force)
+            .create(true)
+            .truncate(true)
+            .open(&manifest_path)?,
+    );
+
+    println!("Output File:  {}", manifest_path.display());
+    println!("Input CHD:    {}", input.display());
+    println!("Part Size:    {} bytes", part_len.separate_with_commas());
+
+    let manifest = chd::split::split(&mut chd, part_len, base_name, |name| {
+        OpenOptions::new()
+            .write(true)
+            .create_new(!force)
+            .create(true)
+            .truncate(true)
+            .open(output_dir.join(name))
+            .map(BufWriter::new)
+    })?;
+    manifest.write_to(&mut manifest_file)?;
+    manifest_file.flush()?;
+
+    for (index, part) in manifest.parts.iter().enumerate() {
+        println!(
+            "Part {}:\t{} ({} bytes)",
+            index,
+            part.name,
+            part.len.separate_with_commas()
+        );
+    }
+    if chd
+        .header()
+        .raw_sha1()
+        .is_some_and(|raw_sha1| raw_sha1 != manifest.sha1)
+    {
+        println!("Warning: the SHA1 of the data does not match the data SHA1 in the header");
+    }
+    println!(
+        "Split complete ({} parts, {} bytes)",
+        manifest.parts.len(),
+        manifest.len.separate_with_commas()
+    );
+    Ok(())
+}
+
+fn concat(input: &Path, output: Option<&Path>, force: bool) -> anyhow::Result<()> {
+    println!("\nchd-rs - rchdman concat");
+    let manifest = SplitManifest::read_from(BufReader::new(File::open(input)?))?;
+    let input_dir = input.parent().unwrap_or_else(|| Path::new(""));
+    let open_part =
+        |part: &chd::split::SplitPart| File::open(input_dir.join(&part.name)).map(BufReader::new);
+
+    println!("Input File:   {}", input.display());
+    let report = match output {
+        Some(output) => {
+            let mut output_file = BufWriter::new(
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(!force)
+                    .create(true)
+                    .truncate(true)
+                    .open(output)?,
+            );
+            println!("Output File:  {}", output.display());
+            let report = chd::split::concat(&manifest, open_part, &mut output_file)?;
+            output_file.flush()?;
+            report
+        }
+        None => chd::split::concat(&manifest, open_part, &mut std::io::sink())?,
+    };
+
+    for (index, part) in manifest.parts.iter().enumerate() {
+        let status = if report.mismatched_parts.contains(&index) {
+            "does not match the manifest"
+        } else {
+            "OK"
+        };
+        println!("Part {}:\t{} {}", index, part.name, status);
+    }
+    if !report.is_valid() {
+        return Err(anyhow!(
+            "{} of {} parts do not match the manifest{}",
+            report.mismatched_parts.len(),
+            manifest.parts.len(),
+            if report.sha1_matches {
+                ""
+            } else {
+                ", and the SHA1 of the data does not match"
+            }
+        ));
+    }
+    println!(
+        "{} complete ({} bytes, SHA1 {})",
+        if output.is_some() {
+            "Concatenation"
+        } else {
+            "Verification"
+        },
+        report.len.separate_with_commas(),
+        hex::encode(manifest.sha1)
+    );
+    Ok(())
+}
+
+fn diff(a: impl AsRef<Path>, b: impl AsRef<Path>, quick: bool) -> anyhow::Result<()> {
+    println!("\nchd-rs - rchdman diff");
+    println!("Input CHD A:  {}", a.as_ref().display());
+    println!("Input CHD B:  {}", b.as_ref().display());
+
+    let mut a = Chd::open_path(a, None)?;
+    let mut b = Chd::open_path(b, None)?;
+    if a.header().hunk_size() != b.header().hunk_size() {
+        return Err(anyhow!(
+            "Can not compare CHDs with different hunk sizes ({} and {} bytes)",
+            a.header().hunk_size(),
+            b.header().hunk_size()
+        ));
+    }
+
+    let mode = if quick {
+        DiffMode::Quick
+    } else {
+        DiffMode::Exact
+    };
+    let result = chd::diff::diff(&mut a, &mut b, mode)?;
+
+    if result.identical_by_hash {
+        println!("CHDs are identical (matching data SHA1)");
+        return Ok(());
+    }
+
+    for range in &result.ranges {
+        let end = range.start_hunk + range.hunk_count - 1;
+        match range.differing_bytes {
+            Some(bytes) => println!(
+                "Hunks {}-{} differ ({} bytes)",
+                range.start_hunk,
+                end,
+                bytes.separate_with_commas()
+            ),
+            None => println!("Hunks {}-{} differ", range.start_hunk, end),
+        }
+    }
+
+    println!(
+        "Compared {} hunks, {} hunks differ by CRC",
+        result.compared_hunks.separate_with_commas(),
+        result.crc_mismatched_hunks.separate_with_commas()
+    );
+    if result.is_identical() {
+        println!("CHDs are identical");
+    } else {
+        println!(
+            "CHDs differ in {} hunks",
+            result.differing_hunks().separate_with_commas()
+        );
+    }
     Ok(())
 }
 
@@ -630,8 +1476,31 @@ fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     match &cli.command {
         Commands::Info { input, verbose } => info(input, *verbose)?,
-        Commands::Benchmark { input, inputparent } => benchmark(input, inputparent.as_ref())?,
-        Commands::Verify { input, inputparent } => verify(input, inputparent.as_deref())?,
+        Commands::Benchmark {
+            input,
+            inputparent,
+            passes,
+            sha1,
+        } => benchmark(input, inputparent.as_ref(), *passes, *sha1)?,
+        Commands::Verify {
+            input,
+            inputparent,
+            fix_sha1,
+        } => verify(input, inputparent.as_deref(), *fix_sha1)?,
+        Commands::Repair {
+            input,
+            inputparent,
+            fix_sha1,
+            rebuild_map,
+            zero_fill,
+        } => repair(
+            input,
+            inputparent.as_deref(),
+            RepairOptions::new()
+                .fix_hashes(*fix_sha1)
+                .rebuild_map(*rebuild_map)
+                .zero_fill(*zero_fill),
+        )?,
         Commands::Dumpmeta {
             input,
             output,
@@ -639,12 +1508,85 @@ fn main() -> anyhow::Result<()> {
             tag,
             index,
         } => dumpmeta(input, output.as_ref(), *force, *tag, *index)?,
+        Commands::Dumpmap {
+            input,
+            output,
+            force,
+            format,
+        } => dumpmap(input, output.as_ref(), *force, *format)?,
         Commands::Extractraw {
             input,
             inputparent,
             force,
             output,
-        } => extractraw(input, inputparent.as_deref(), output, *force)?,
+            inputstartbyte,
+            inputstarthunk,
+            inputbytes,
+            inputhunks,
+        } => extractraw(
+            input,
+            inputparent.as_deref(),
+            output,
+            *force,
+            InputWindow {
+                start_byte: *inputstartbyte,
+                start_hunk: *inputstarthunk,
+                bytes: *inputbytes,
+                hunks: *inputhunks,
+            },
+        )?,
+        Commands::Extractcd {
+            input,
+            inputparent,
+            output,
+            outputbin,
+            splitbin,
+            userdata,
+            force,
+        } => extractcd(
+            input,
+            inputparent.as_deref(),
+            output,
+            outputbin.as_deref(),
+            *splitbin,
+            *userdata,
+            *force,
+        )?,
+        Commands::Extractdvd {
+            input,
+            inputparent,
+            output,
+            force,
+        } => extractdvd(input, inputparent.as_deref(), output, *force)?,
+        Commands::Copy {
+            input,
+            inputparent,
+            output,
+            outputparent,
+            force,
+            compression,
+        } => copy(
+            input,
+            inputparent.as_deref(),
+            output,
+            outputparent.as_deref(),
+            *force,
+            compression.as_ref(),
+        )?,
+        Commands::Split {
+            input,
+            inputparent,
+            output,
+            force,
+            size,
+            parts,
+        } => split(input, inputparent.as_deref(), output, *force, *size, *parts)?,
+        Commands::Concat {
+            input,
+            output,
+            force,
+        } => concat(input, output.as_deref(), *force)?,
+        Commands::Diff { a, b, quick } => diff(a, b, *quick)?,
     }
     Ok(())
 }